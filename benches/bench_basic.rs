@@ -259,6 +259,29 @@ fn bench_encode(c: &mut Criterion) {
 fn bench_decode_simple(b: &mut Bencher, input: &[u8]) {
     b.iter(|| redis::parse_redis_value(input).unwrap());
 }
+
+fn bench_decode_ref(b: &mut Bencher, input: &[u8]) {
+    b.iter(|| redis::parse_redis_value_ref(input).unwrap());
+}
+
+fn bench_decode_bytes(b: &mut Bencher, input: &bytes::Bytes) {
+    b.iter(|| redis::parse_redis_value_bytes(input).unwrap());
+}
+
+// Feeds the input in socket sized chunks like a framed connection does.
+fn bench_decode_chunked(b: &mut Bencher, input: &[u8]) {
+    use tokio_util::codec::Decoder;
+    b.iter(|| {
+        let mut codec = redis::BytesValueCodec::default();
+        let mut buffer = bytes::BytesMut::new();
+        for chunk in input.chunks(4096) {
+            buffer.extend_from_slice(chunk);
+            while let Some(reply) = codec.decode(&mut buffer).unwrap() {
+                reply.unwrap();
+            }
+        }
+    });
+}
 fn bench_decode(c: &mut Criterion) {
     let value = Value::Bulk(vec![
         Value::Okay,
@@ -276,6 +299,57 @@ fn bench_decode(c: &mut Criterion) {
         assert_eq!(redis::parse_redis_value(&input).unwrap(), value);
         group.bench_function("decode", move |b| bench_decode_simple(b, &input));
     }
+    {
+        let input = b"+QUEUED\r\n";
+        group.bench_function("status", move |b| bench_decode_simple(b, input));
+        group.bench_function("status_ref", move |b| bench_decode_ref(b, input));
+    }
+    {
+        let value = Value::Bulk(vec![Value::Data(vec![b'a'; 1024]); 1000]);
+        let mut input = Vec::new();
+        support::encode_value(&value, &mut input).unwrap();
+        let input = bytes::Bytes::from(input);
+        group.throughput(Throughput::Bytes(input.len() as u64));
+        let copied = input.clone();
+        group.bench_function("large_bulk", move |b| bench_decode_simple(b, &copied));
+        group.bench_function("large_bulk_bytes", move |b| bench_decode_bytes(b, &input));
+    }
+    {
+        let value = Value::Bulk(
+            (0..10_000)
+                .map(|i| Value::Data(format!("value:{}", i).into_bytes()))
+                .collect(),
+        );
+        let mut input = Vec::new();
+        support::encode_value(&value, &mut input).unwrap();
+        group.throughput(Throughput::Bytes(input.len() as u64));
+        group.bench_function("many_lines", move |b| bench_decode_ref(b, &input));
+    }
+    {
+        let value = Value::Bulk(vec![Value::Data(b"value".to_vec()); 10_000]);
+        let mut input = Vec::new();
+        support::encode_value(&value, &mut input).unwrap();
+        group.throughput(Throughput::Bytes(input.len() as u64));
+        group.bench_function("many_lines_chunked", move |b| {
+            bench_decode_chunked(b, &input)
+        });
+    }
+    {
+        let value = Value::Bulk(vec![
+            Value::Data(b"id".to_vec()),
+            Value::Int(42),
+            Value::Data(vec![b'a'; 1024 * 1024]),
+        ]);
+        let mut input = Vec::new();
+        support::encode_value(&value, &mut input).unwrap();
+        let input = bytes::Bytes::from(input);
+        group.throughput(Throughput::Bytes(input.len() as u64));
+        let copied = input.clone();
+        group.bench_function("huge_field", move |b| bench_decode_simple(b, &copied));
+        group.bench_function("huge_field_lazy", move |b| {
+            b.iter(|| redis::parse_redis_value_lazy(&input, 4096).unwrap())
+        });
+    }
     group.finish();
 }
 
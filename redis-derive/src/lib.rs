@@ -0,0 +1,274 @@
+//! Derive macros for the `ToRedisArgs` and `FromRedisValue` traits of the
+//! [redis](https://docs.rs/redis) crate.
+//!
+//! Use them through the `derive` feature of `redis`, which re-exports them
+//! next to the traits:
+//!
+//! * Structs with named fields are converted to field/value pairs, the
+//!   arguments of `HSET`, and from the map returned by `HGETALL`.
+//! * Enums with unit variants are converted to the name of the variant and
+//!   from a status or string reply holding it.
+//!
+//! Names can be changed with `#[redis(rename = "name")]` on fields and
+//! variants.
+#![deny(missing_docs)]
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::{
+    parse_macro_input, parse_quote, Attribute, Data, DeriveInput, Error, Fields, Generics, Ident,
+    LitByteStr, LitStr,
+};
+
+/// Derives `ToRedisArgs`.
+///
+/// A struct writes a name/value pair per field, fields which convert to no
+/// arguments, like `None`, are skipped.  Writing a field which converts to
+/// more than one argument, like a `Vec` of several items, panics since its
+/// arguments would not pair up with the names anymore.  An enum writes the
+/// name of the variant.
+#[proc_macro_derive(ToRedisArgs, attributes(redis))]
+pub fn derive_to_redis_args(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_to_redis_args(&input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+/// Derives `FromRedisValue`.
+///
+/// A struct is read from a map, or a bulk of alternating keys and values.
+/// Missing fields are converted from `nil`, so they are only allowed for
+/// types like `Option` which accept it, and unknown keys are ignored.  An
+/// enum is read from the name of a variant.
+#[proc_macro_derive(FromRedisValue, attributes(redis))]
+pub fn derive_from_redis_value(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_from_redis_value(&input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+enum Shape<'a> {
+    Struct(Vec<(&'a Ident, LitByteStr)>),
+    Enum(Vec<(&'a Ident, LitByteStr)>),
+}
+
+fn shape(input: &DeriveInput) -> syn::Result<Shape<'_>> {
+    match input.data {
+        Data::Struct(ref data) => match data.fields {
+            Fields::Named(ref fields) => fields
+                .named
+                .iter()
+                .map(|field| {
+                    let ident = field.ident.as_ref().unwrap();
+                    Ok((ident, redis_name(ident, &field.attrs)?))
+                })
+                .collect::<syn::Result<_>>()
+                .map(Shape::Struct),
+            _ => Err(Error::new_spanned(
+                &input.ident,
+                "only structs with named fields can be derived",
+            )),
+        },
+        Data::Enum(ref data) => data
+            .variants
+            .iter()
+            .map(|variant| match variant.fields {
+                Fields::Unit => Ok((&variant.ident, redis_name(&variant.ident, &variant.attrs)?)),
+                _ => Err(Error::new_spanned(
+                    variant,
+                    "only enums with unit variants can be derived",
+                )),
+            })
+            .collect::<syn::Result<_>>()
+            .map(Shape::Enum),
+        Data::Union(_) => Err(Error::new_spanned(
+            &input.ident,
+            "unions can not be derived",
+        )),
+    }
+}
+
+// The name of a field or variant, taken from `#[redis(rename = "...")]`
+// if there is one.
+fn redis_name(ident: &Ident, attrs: &[Attribute]) -> syn::Result<LitByteStr> {
+    let mut name = ident.to_string();
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("redis")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("rename") {
+                name = meta.value()?.parse::<LitStr>()?.value();
+                Ok(())
+            } else {
+                Err(meta.error("unsupported redis attribute"))
+            }
+        })?;
+    }
+    Ok(LitByteStr::new(name.as_bytes(), ident.span()))
+}
+
+// Adds `bound` to every type parameter.
+fn add_bounds(generics: &Generics, bound: syn::TypeParamBound) -> Generics {
+    let mut generics = generics.clone();
+    for param in generics.type_params_mut() {
+        param.bounds.push(bound.clone());
+    }
+    generics
+}
+
+fn expand_to_redis_args(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let ident = &input.ident;
+    let generics = add_bounds(&input.generics, parse_quote!(::redis::ToRedisArgs));
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    let body = match shape(input)? {
+        Shape::Struct(fields) => {
+            let writes = fields.iter().map(|(field, name)| {
+                let several = format!(
+                    "field `{}` of {} converts to more than one argument",
+                    field, ident
+                );
+                quote! {
+                    let args = ::redis::ToRedisArgs::to_redis_args(&self.#field);
+                    match args.len() {
+                        0 => {}
+                        1 => {
+                            out.write_arg(#name);
+                            out.write_arg(&args[0]);
+                        }
+                        _ => panic!(#several),
+                    }
+                }
+            });
+            quote! {
+                fn write_redis_args<W>(&self, out: &mut W)
+                where
+                    W: ?Sized + ::redis::RedisWrite,
+                {
+                    #(#writes)*
+                }
+
+                fn is_single_arg(&self) -> bool {
+                    false
+                }
+            }
+        }
+        Shape::Enum(variants) => {
+            let arms = variants.iter().map(|(variant, name)| {
+                quote! { #ident::#variant => out.write_arg(#name), }
+            });
+            quote! {
+                fn write_redis_args<W>(&self, out: &mut W)
+                where
+                    W: ?Sized + ::redis::RedisWrite,
+                {
+                    match *self {
+                        #(#arms)*
+                    }
+                }
+            }
+        }
+    };
+
+    Ok(quote! {
+        impl #impl_generics ::redis::ToRedisArgs for #ident #ty_generics #where_clause {
+            #body
+        }
+    })
+}
+
+fn expand_from_redis_value(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let ident = &input.ident;
+    let generics = add_bounds(&input.generics, parse_quote!(::redis::FromRedisValue));
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let incompatible = format!("(response type not {} compatible)", ident);
+
+    let body = match shape(input)? {
+        Shape::Struct(fields) => {
+            // the values found for the fields, named so they can not clash
+            // with the other variables
+            let slots: Vec<_> = fields
+                .iter()
+                .map(|(field, _)| format_ident!("__field_{}", field))
+                .collect();
+            let arms = fields.iter().zip(&slots).map(|((_, name), slot)| {
+                quote! { #name => #slot = ::std::option::Option::Some(value), }
+            });
+            let conversions = fields.iter().zip(&slots).map(|((field, name), slot)| {
+                let missing = format!(
+                    "missing field `{}` of {}",
+                    String::from_utf8_lossy(&name.value()),
+                    ident
+                );
+                quote! {
+                    #field: match #slot {
+                        ::std::option::Option::Some(value) => ::redis::from_redis_value(value)?,
+                        ::std::option::Option::None => {
+                            ::redis::from_redis_value(&::redis::Value::Nil).map_err(|_| {
+                                ::redis::RedisError::from((
+                                    ::redis::ErrorKind::TypeError,
+                                    "Response was of incompatible type",
+                                    #missing.to_string(),
+                                ))
+                            })?
+                        }
+                    },
+                }
+            });
+            quote! {
+                let items = v.as_map_iter().ok_or_else(|| {
+                    ::redis::RedisError::from((
+                        ::redis::ErrorKind::TypeError,
+                        "Response was of incompatible type",
+                        format!("{:?} {}", v, #incompatible),
+                    ))
+                })?;
+                #(let mut #slots = ::std::option::Option::None;)*
+                for (key, value) in items {
+                    let key: &[u8] = match *key {
+                        ::redis::Value::Data(ref key) => key,
+                        ::redis::Value::Status(ref key) => key.as_bytes(),
+                        _ => continue,
+                    };
+                    match key {
+                        #(#arms)*
+                        _ => {}
+                    }
+                }
+                ::std::result::Result::Ok(#ident {
+                    #(#conversions)*
+                })
+            }
+        }
+        Shape::Enum(variants) => {
+            let arms = variants.iter().map(|(variant, name)| {
+                quote! { #name => ::std::result::Result::Ok(#ident::#variant), }
+            });
+            quote! {
+                let name: &[u8] = match *v {
+                    ::redis::Value::Data(ref name) => name,
+                    ::redis::Value::Status(ref name) => name.as_bytes(),
+                    ::redis::Value::Okay => b"OK",
+                    _ => &[],
+                };
+                match name {
+                    #(#arms)*
+                    _ => ::std::result::Result::Err(::redis::RedisError::from((
+                        ::redis::ErrorKind::TypeError,
+                        "Response was of incompatible type",
+                        format!("{:?} {}", v, #incompatible),
+                    ))),
+                }
+            }
+        }
+    };
+
+    Ok(quote! {
+        impl #impl_generics ::redis::FromRedisValue for #ident #ty_generics #where_clause {
+            fn from_redis_value(v: &::redis::Value) -> ::redis::RedisResult<Self> {
+                #body
+            }
+        }
+    })
+}
@@ -1,5 +1,7 @@
 //! Defines types to use with the ACL commands.
 
+use std::collections::HashMap;
+
 use crate::types::{
     ErrorKind, FromRedisValue, RedisError, RedisResult, RedisWrite, ToRedisArgs, Value,
 };
@@ -16,7 +18,7 @@ macro_rules! not_convertible_error {
 
 /// ACL rules are used in order to activate or remove a flag, or to perform a
 /// given change to the user ACL, which under the hood are just single words.
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Clone, Eq, PartialEq)]
 pub enum Rule {
     /// Enable the user: it is possible to authenticate as this user.
     On,
@@ -180,27 +182,7 @@ impl FromRedisValue for AclInfo {
                     .map(|pass| Ok(Rule::AddHashedPass(String::from_redis_value(pass)?)))
                     .collect::<RedisResult<_>>()?;
 
-                let commands = match commands {
-                    Value::Data(cmd) => std::str::from_utf8(cmd)?,
-                    _ => {
-                        return Err(not_convertible_error!(
-                            commands,
-                            "Expect a valid UTF8 string"
-                        ))
-                    }
-                }
-                .split_terminator(' ')
-                .map(|cmd| match cmd {
-                    x if x.starts_with("+@") => Ok(Rule::AddCategory(x[2..].to_owned())),
-                    x if x.starts_with("-@") => Ok(Rule::RemoveCategory(x[2..].to_owned())),
-                    x if x.starts_with('+') => Ok(Rule::AddCommand(x[1..].to_owned())),
-                    x if x.starts_with('-') => Ok(Rule::RemoveCommand(x[1..].to_owned())),
-                    _ => Err(not_convertible_error!(
-                        cmd,
-                        "Expect a command addition/removal"
-                    )),
-                })
-                .collect::<RedisResult<_>>()?;
+                let commands = command_rules(commands)?;
 
                 let keys = keys
                     .as_sequence()
@@ -228,6 +210,90 @@ impl FromRedisValue for AclInfo {
     }
 }
 
+fn command_rules(commands: &Value) -> RedisResult<Vec<Rule>> {
+    match commands {
+        Value::Data(cmd) => std::str::from_utf8(cmd)?,
+        _ => {
+            return Err(not_convertible_error!(
+                commands,
+                "Expect a valid UTF8 string"
+            ))
+        }
+    }
+    .split_terminator(' ')
+    .map(|cmd| match cmd {
+        x if x.starts_with("+@") => Ok(Rule::AddCategory(x[2..].to_owned())),
+        x if x.starts_with("-@") => Ok(Rule::RemoveCategory(x[2..].to_owned())),
+        x if x.starts_with('+') => Ok(Rule::AddCommand(x[1..].to_owned())),
+        x if x.starts_with('-') => Ok(Rule::RemoveCommand(x[1..].to_owned())),
+        _ => Err(not_convertible_error!(
+            cmd,
+            "Expect a command addition/removal"
+        )),
+    })
+    .collect()
+}
+
+/// A user as described by the [`ACL GETUSER`][1] command.
+///
+/// Unlike [`AclInfo`] the fields may come in any order, missing fields are
+/// left empty and unknown fields are kept in `extra`, so replies of newer
+/// servers can be decoded as well.
+///
+/// [1]: https://redis.io/commands/acl-getuser
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+pub struct AclUser {
+    /// The flags of the user, such as `on`, `allkeys` or `nopass`.
+    pub flags: Vec<String>,
+    /// The SHA-256 hashes of the passwords of the user.
+    pub passwords: Vec<String>,
+    /// The command rules of the user.
+    pub commands: Vec<Rule>,
+    /// The key patterns of the user as sent by the server.  Older servers
+    /// send the bare patterns (`pat:*`) while newer ones send the rules
+    /// (`~pat:*`, `%R~pat:*`).
+    pub keys: Vec<String>,
+    /// The pub/sub channel patterns of the user as sent by the server.
+    pub channels: Vec<String>,
+    /// The fields which are not decoded into one of the fields above.
+    pub extra: HashMap<String, Value>,
+}
+
+// Reads a list of patterns which newer servers send as a single string.
+fn acl_patterns(v: &Value) -> RedisResult<Vec<String>> {
+    match v {
+        Value::Data(patterns) => Ok(std::str::from_utf8(patterns)?
+            .split_ascii_whitespace()
+            .map(str::to_owned)
+            .collect()),
+        _ => FromRedisValue::from_redis_value(v),
+    }
+}
+
+impl Value {
+    /// Decodes the reply of `ACL GETUSER` into an [`AclUser`].
+    pub fn into_acl_user(self) -> RedisResult<AclUser> {
+        let pairs = self
+            .as_map_iter()
+            .ok_or_else(|| not_convertible_error!(self, "Expect a response from `ACL GETUSER`"))?;
+        let mut user = AclUser::default();
+        for (key, value) in pairs {
+            let key = String::from_redis_value(key)?;
+            match key.as_str() {
+                "flags" => user.flags = FromRedisValue::from_redis_value(value)?,
+                "passwords" => user.passwords = FromRedisValue::from_redis_value(value)?,
+                "commands" => user.commands = command_rules(value)?,
+                "keys" => user.keys = acl_patterns(value)?,
+                "channels" => user.channels = acl_patterns(value)?,
+                _ => {
+                    user.extra.insert(key, value.clone());
+                }
+            }
+        }
+        Ok(user)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -298,4 +364,56 @@ mod tests {
             }
         );
     }
+
+    #[test]
+    fn test_into_acl_user() {
+        // Redis 6.0
+        let v6 = Value::Bulk(vec![
+            Value::Data("flags".into()),
+            Value::Bulk(vec![
+                Value::Data("on".into()),
+                Value::Data("allchannels".into()),
+            ]),
+            Value::Data("passwords".into()),
+            Value::Bulk(vec![Value::Data("2d9c75".into())]),
+            Value::Data("commands".into()),
+            Value::Data("+@all -debug".into()),
+            Value::Data("keys".into()),
+            Value::Bulk(vec![Value::Data("pat:*".into())]),
+        ]);
+        assert_eq!(
+            v6.into_acl_user().unwrap(),
+            AclUser {
+                flags: vec!["on".to_owned(), "allchannels".to_owned()],
+                passwords: vec!["2d9c75".to_owned()],
+                commands: vec![
+                    Rule::AddCategory("all".to_owned()),
+                    Rule::RemoveCommand("debug".to_owned()),
+                ],
+                keys: vec!["pat:*".to_owned()],
+                ..AclUser::default()
+            }
+        );
+
+        // Redis 7.0, fields reordered
+        let v7 = Value::Bulk(vec![
+            Value::Data("keys".into()),
+            Value::Data("~pat:* %R~ro:*".into()),
+            Value::Data("channels".into()),
+            Value::Data("&news".into()),
+            Value::Data("flags".into()),
+            Value::Bulk(vec![Value::Data("off".into())]),
+            Value::Data("selectors".into()),
+            Value::Bulk(vec![]),
+        ]);
+        let user = v7.into_acl_user().unwrap();
+        assert_eq!(user.flags, vec!["off".to_owned()]);
+        assert!(user.passwords.is_empty());
+        assert!(user.commands.is_empty());
+        assert_eq!(user.keys, vec!["~pat:*".to_owned(), "%R~ro:*".to_owned()]);
+        assert_eq!(user.channels, vec!["&news".to_owned()]);
+        assert_eq!(user.extra["selectors"], Value::Bulk(vec![]));
+
+        assert!(Value::Int(1).into_acl_user().is_err());
+    }
 }
@@ -1,30 +1,31 @@
 //! Adds experimental async IO support to redis.
 use async_trait::async_trait;
 use std::collections::VecDeque;
-use std::io;
+use std::io::{self, IoSlice};
 use std::mem;
 use std::net::SocketAddr;
-use std::net::ToSocketAddrs;
 #[cfg(unix)]
 use std::path::Path;
 use std::pin::Pin;
+use std::sync::{Arc, Mutex};
 use std::task::{self, Poll};
+use std::time::Duration;
 
 use combine::{parser::combinator::AnySendSyncPartialState, stream::PointerOffset};
 
 use ::tokio::{
     io::{AsyncRead, AsyncWrite, AsyncWriteExt},
-    sync::{mpsc, oneshot},
+    sync::{mpsc, oneshot, Notify},
 };
 
-#[cfg(feature = "tls")]
-use native_tls::TlsConnector;
+#[cfg(any(feature = "tls", feature = "tls-rustls"))]
+use crate::tls::TlsCertificates;
 
-#[cfg(any(feature = "tokio-comp", feature = "async-std-comp"))]
+#[cfg(feature = "aio")]
 use tokio_util::codec::Decoder;
 
 use futures_util::{
-    future::{Future, FutureExt},
+    future::{self, Future, FutureExt},
     ready,
     sink::Sink,
     stream::{self, Stream, StreamExt, TryStreamExt as _},
@@ -32,12 +33,22 @@ use futures_util::{
 
 use pin_project_lite::pin_project;
 
-use crate::cmd::{cmd, Cmd};
-use crate::connection::{ConnectionAddr, ConnectionInfo, Msg, RedisConnectionInfo};
+use crate::cmd::{advance_slices, cmd, Cmd};
+use crate::connection::{
+    client_setinfo_failed, client_setinfo_pipeline, hello_command, hello_failed, is_out_of_band,
+    subscription_reply, ConnectionAddr, ConnectionInfo, Msg, ProtocolVersion, RedisConnectionInfo,
+};
 
-#[cfg(any(feature = "tokio-comp", feature = "async-std-comp"))]
-use crate::parser::ValueCodec;
-use crate::types::{ErrorKind, FromRedisValue, RedisError, RedisFuture, RedisResult, Value};
+#[cfg(feature = "aio")]
+use crate::instrumentation::{observe_async, MetricsObserver, ObservedStream};
+use crate::parser::{BufferPolicy, ValueCodec};
+use crate::proxy::ProxyConfig;
+pub(crate) use crate::proxy::Tunnel;
+use crate::tcp::TcpSettings;
+use crate::types::{
+    ErrorKind, FromRedisValue, MonitorEntry, PushMessage, RedisError, RedisFuture, RedisResult,
+    Value,
+};
 use crate::{from_redis_value, ToRedisArgs};
 
 /// Enables the async_std compatibility
@@ -50,18 +61,44 @@ pub mod async_std;
 #[cfg_attr(docsrs, doc(cfg(feature = "tokio-comp")))]
 pub mod tokio;
 
+mod buffer;
+use buffer::BufferSender;
+pub use buffer::{BufferConfig, BufferStats, MessageStream, OverflowPolicy};
+
+mod credentials;
+pub(crate) use credentials::refresh_credentials;
+pub use credentials::{Credentials, CredentialsProvider};
+
+mod resolver;
+pub use resolver::Resolver;
+
+mod runtime;
+pub(crate) use runtime::Custom;
+pub use runtime::{set_runtime, AsyncRuntime, BoxFuture, BoxStream};
+
+#[cfg(feature = "tower")]
+mod service;
+
 /// Represents the ability of connecting via TCP or via Unix socket
 #[async_trait]
 pub(crate) trait RedisRuntime: AsyncStream + Send + Sync + Sized + 'static {
-    /// Performs a TCP connection
-    async fn connect_tcp(socket_addr: SocketAddr) -> RedisResult<Self>;
+    /// Performs a TCP connection to the first of the addresses accepting it,
+    /// opening the tunnel if the addresses are the ones of a proxy
+    async fn connect_tcp(
+        socket_addrs: Vec<SocketAddr>,
+        tunnel: Option<&Tunnel<'_>>,
+        tcp_settings: &TcpSettings,
+    ) -> RedisResult<Self>;
 
     // Performs a TCP TLS connection
-    #[cfg(feature = "tls")]
+    #[cfg(any(feature = "tls", feature = "tls-rustls"))]
     async fn connect_tcp_tls(
         hostname: &str,
-        socket_addr: SocketAddr,
+        socket_addrs: Vec<SocketAddr>,
+        tunnel: Option<&Tunnel<'_>>,
+        tcp_settings: &TcpSettings,
         insecure: bool,
+        certificates: Option<&TlsCertificates>,
     ) -> RedisResult<Self>;
 
     /// Performs a UNIX connection
@@ -81,10 +118,16 @@ pub(crate) enum Runtime {
     Tokio,
     #[cfg(feature = "async-std-comp")]
     AsyncStd,
+    /// The runtime registered with `set_runtime`.
+    Custom,
 }
 
 impl Runtime {
     pub(crate) fn locate() -> Self {
+        if runtime::is_registered() {
+            return Runtime::Custom;
+        }
+
         #[cfg(all(feature = "tokio-comp", not(feature = "async-std-comp")))]
         {
             Runtime::Tokio
@@ -106,21 +149,65 @@ impl Runtime {
 
         #[cfg(all(not(feature = "tokio-comp"), not(feature = "async-std-comp")))]
         {
-            compile_error!("tokio-comp or async-std-comp features required for aio feature")
+            panic!(
+                "no async runtime, enable the tokio-comp or async-std-comp feature \
+                 or register one with redis::aio::set_runtime"
+            )
         }
     }
 
-    #[allow(dead_code)]
-    fn spawn(&self, f: impl Future<Output = ()> + Send + 'static) {
+    pub(crate) fn spawn(&self, f: impl Future<Output = ()> + Send + 'static) {
         match self {
             #[cfg(feature = "tokio-comp")]
             Runtime::Tokio => tokio::Tokio::spawn(f),
             #[cfg(feature = "async-std-comp")]
             Runtime::AsyncStd => async_std::AsyncStd::spawn(f),
+            Runtime::Custom => runtime::registered().spawn(Box::pin(f)),
+        }
+    }
+
+    /// Waits until `duration` has elapsed.
+    pub(crate) async fn sleep(&self, duration: Duration) {
+        match self {
+            #[cfg(feature = "tokio-comp")]
+            Runtime::Tokio => ::tokio::time::sleep(duration).await,
+            #[cfg(feature = "async-std-comp")]
+            Runtime::AsyncStd => ::async_std::task::sleep(duration).await,
+            Runtime::Custom => runtime::registered().sleep(duration).await,
+        }
+    }
+
+    /// Awaits the future, failing with an `ErrorKind::Timeout` error if it
+    /// does not complete within `duration`.
+    pub(crate) async fn timeout<F: Future>(
+        &self,
+        duration: Duration,
+        future: F,
+    ) -> RedisResult<F::Output> {
+        match self {
+            #[cfg(feature = "tokio-comp")]
+            Runtime::Tokio => ::tokio::time::timeout(duration, future)
+                .await
+                .map_err(|_| timed_out()),
+            #[cfg(feature = "async-std-comp")]
+            Runtime::AsyncStd => ::async_std::future::timeout(duration, future)
+                .await
+                .map_err(|_| timed_out()),
+            Runtime::Custom => {
+                let sleep = runtime::registered().sleep(duration);
+                match future::select(Box::pin(future), sleep).await {
+                    future::Either::Left((output, _)) => Ok(output),
+                    future::Either::Right(_) => Err(timed_out()),
+                }
+            }
         }
     }
 }
 
+fn timed_out() -> RedisError {
+    RedisError::from((ErrorKind::Timeout, "Operation timed out"))
+}
+
 /// Trait for objects that implements `AsyncRead` and `AsyncWrite`
 pub trait AsyncStream: AsyncRead + AsyncWrite {}
 impl<S> AsyncStream for S where S: AsyncRead + AsyncWrite {}
@@ -176,9 +263,12 @@ where
     /// The message itself is still generic and can be converted into an appropriate type through
     /// the helper methods on it.
     pub fn on_message(&mut self) -> impl Stream<Item = Msg> + '_ {
-        ValueCodec::default()
-            .framed(&mut self.0.con)
-            .filter_map(|msg| Box::pin(async move { Msg::from_value(&msg.ok()?.ok()?) }))
+        let pushed = self.0.take_pushed_messages();
+        stream::iter(pushed).chain(
+            ValueCodec::default()
+                .framed(&mut self.0.con)
+                .filter_map(|msg| Box::pin(async move { Msg::from_value(&msg.ok()?.ok()?) })),
+        )
     }
 
     /// Returns [`Stream`] of [`Msg`]s from this [`PubSub`]s subscriptions consuming it.
@@ -187,10 +277,13 @@ where
     /// the helper methods on it.
     /// This can be useful in cases where the stream needs to be returned or held by something other
     //  than the [`PubSub`].
-    pub fn into_on_message(self) -> impl Stream<Item = Msg> {
-        ValueCodec::default()
-            .framed(self.0.con)
-            .filter_map(|msg| Box::pin(async move { Msg::from_value(&msg.ok()?.ok()?) }))
+    pub fn into_on_message(mut self) -> impl Stream<Item = Msg> {
+        let pushed = self.0.take_pushed_messages();
+        stream::iter(pushed).chain(
+            ValueCodec::default()
+                .framed(self.0.con)
+                .filter_map(|msg| Box::pin(async move { Msg::from_value(&msg.ok()?.ok()?) })),
+        )
     }
 
     /// Exits from `PubSub` mode and converts [`PubSub`] into [`Connection`].
@@ -240,12 +333,31 @@ pub struct Connection<C = Pin<Box<dyn AsyncStream + Send + Sync>>> {
     buf: Vec<u8>,
     decoder: combine::stream::Decoder<AnySendSyncPartialState, PointerOffset<[u8]>>,
     db: i64,
+    protocol: ProtocolVersion,
 
     // Flag indicating whether the connection was left in the PubSub state after dropping `PubSub`.
     //
     // This flag is checked when attempting to send a command, and if it's raised, we attempt to
     // exit the pubsub state before executing the new request.
     pubsub: bool,
+
+    // The pubsub messages pushed by the server over RESP3 while the reply
+    // of a command was read, returned first by the message streams.
+    pushes: VecDeque<Value>,
+
+    // The number of replies still to be read for the last request.  They are
+    // skipped before the next request when that request was cancelled, for
+    // instance by a timeout.
+    pending_replies: usize,
+
+    // Raised while a request is written.  A request cancelled before it was
+    // written completely leaves a partial frame, which the server would
+    // complete with the next request, so the connection can not be used
+    // anymore.
+    writing: bool,
+
+    // Whether server errors nested in aggregates are read as values.
+    error_values: bool,
 }
 
 fn assert_sync<T: Sync>() {}
@@ -256,20 +368,31 @@ fn test() {
 }
 
 impl<C> Connection<C> {
+    #[cfg(any(feature = "tokio-comp", feature = "async-std-comp"))]
     pub(crate) fn map<D>(self, f: impl FnOnce(C) -> D) -> Connection<D> {
         let Self {
             con,
             buf,
             decoder,
             db,
+            protocol,
             pubsub,
+            pushes,
+            pending_replies,
+            writing,
+            error_values,
         } = self;
         Connection {
             con: f(con),
             buf,
             decoder,
             db,
+            protocol,
             pubsub,
+            pushes,
+            pending_replies,
+            writing,
+            error_values,
         }
     }
 }
@@ -286,12 +409,30 @@ where
             buf: Vec::new(),
             decoder: combine::stream::Decoder::new(),
             db: connection_info.db,
+            protocol: ProtocolVersion::RESP2,
             pubsub: false,
+            pushes: VecDeque::new(),
+            pending_replies: 0,
+            writing: false,
+            error_values: false,
         };
-        authenticate(connection_info, &mut rv).await?;
+        rv.protocol = authenticate(connection_info, &mut rv).await?;
         Ok(rv)
     }
 
+    /// Returns the protocol negotiated with the server.
+    pub fn protocol(&self) -> ProtocolVersion {
+        self.protocol
+    }
+
+    /// Enables or disables reading server errors nested in aggregates into
+    /// `Value::ServerError` values.
+    ///
+    /// See `redis::Connection::set_error_values` for details.
+    pub fn set_error_values(&mut self, enabled: bool) {
+        self.error_values = enabled;
+    }
+
     /// Converts this [`Connection`] into [`PubSub`].
     pub fn into_pubsub(self) -> PubSub<C> {
         PubSub::new(self)
@@ -302,9 +443,71 @@ where
         Monitor::new(self)
     }
 
+    /// Switches the connection into monitor mode and returns the [`Stream`]
+    /// of the commands processed by the server, see
+    /// [`crate::Connection::monitor`].
+    ///
+    /// ```rust,no_run
+    /// # async fn do_something() -> redis::RedisResult<()> {
+    /// use futures::StreamExt;
+    ///
+    /// let client = redis::Client::open("redis://127.0.0.1/")?;
+    /// let mut entries = Box::pin(client.get_async_connection().await?.monitor().await?);
+    /// while let Some(entry) = entries.next().await {
+    ///     println!("[{} {}] {} {:?}", entry.db, entry.client, entry.command, entry.args);
+    /// }
+    /// # Ok(()) }
+    /// ```
+    pub async fn monitor(self) -> RedisResult<impl Stream<Item = MonitorEntry>> {
+        let mut monitor = self.into_monitor();
+        monitor.monitor().await?;
+        Ok(monitor.into_on_message())
+    }
+
     /// Fetches a single response from the connection.
     async fn read_response(&mut self) -> RedisResult<Value> {
-        crate::parser::parse_redis_value_async(&mut self.decoder, &mut self.con).await
+        crate::parser::parse_value_async(&mut self.decoder, &mut self.con, self.error_values).await
+    }
+
+    // Reads a reply of the current request.  Out of band push frames are
+    // not replies, pubsub messages are kept for the message streams and the
+    // other ones, like invalidations, are dropped.
+    async fn read_reply(&mut self) -> RedisResult<Value> {
+        let reply = loop {
+            match self.read_response().await {
+                Ok(value) if is_out_of_band(&value) => {
+                    if Msg::from_value(&value).is_some() {
+                        self.pushes.push_back(value);
+                    }
+                }
+                reply => break reply,
+            }
+        };
+        self.pending_replies = self.pending_replies.saturating_sub(1);
+        reply
+    }
+
+    fn take_pushed_messages(&mut self) -> Vec<Msg> {
+        self.pushes
+            .drain(..)
+            .filter_map(|value| Msg::from_value(&value))
+            .collect()
+    }
+
+    // Skips the replies of a request which was cancelled before they were
+    // read, so they are not taken for the replies of the next one.  Fails if
+    // a request was cancelled while it was written.
+    async fn skip_pending_replies(&mut self) -> RedisResult<()> {
+        if self.writing {
+            fail!(io::Error::from(io::ErrorKind::BrokenPipe));
+        }
+        while self.pending_replies > 0 {
+            match self.read_reply().await {
+                Err(err) if err.is_io_error() => return Err(err),
+                _ => {}
+            }
+        }
+        Ok(())
     }
 
     /// Brings [`Connection`] out of `PubSub` mode.
@@ -317,6 +520,7 @@ where
         let res = self.clear_active_subscriptions().await;
         if res.is_ok() {
             self.pubsub = false;
+            self.pushes.clear();
         } else {
             // Raise the pubsub flag to indicate the connection is "stuck" in that state.
             self.pubsub = true;
@@ -343,7 +547,9 @@ where
                 .get_packed_pipeline();
 
             // Execute commands
+            self.writing = true;
             self.con.write_all(&unsubscribe).await?;
+            self.writing = false;
         }
 
         // Receive responses
@@ -354,7 +560,11 @@ where
         let mut received_unsub = false;
         let mut received_punsub = false;
         loop {
-            let res: (Vec<u8>, (), isize) = from_redis_value(&self.read_response().await?)?;
+            let value = self.read_response().await?;
+            if Msg::from_value(&value).is_some() {
+                continue;
+            }
+            let res: (Vec<u8>, (), isize) = from_redis_value(&subscription_reply(value))?;
 
             match res.0.first() {
                 Some(&b'u') => received_unsub = true,
@@ -386,19 +596,31 @@ where
     }
 }
 
-pub(crate) async fn connect<C>(connection_info: &ConnectionInfo) -> RedisResult<Connection<C>>
-where
-    C: Unpin + RedisRuntime + AsyncRead + AsyncWrite + Send,
-{
-    let con = connect_simple::<C>(connection_info).await?;
-    Connection::new(&connection_info.redis, con).await
-}
-
-async fn authenticate<C>(connection_info: &RedisConnectionInfo, con: &mut C) -> RedisResult<()>
+// Authenticates the connection and selects its database.  Returns the
+// protocol negotiated with the server.
+async fn authenticate<C>(
+    connection_info: &RedisConnectionInfo,
+    con: &mut C,
+) -> RedisResult<ProtocolVersion>
 where
     C: ConnectionLike,
 {
-    if let Some(password) = &connection_info.password {
+    let mut protocol = ProtocolVersion::RESP2;
+    if connection_info.protocol == ProtocolVersion::RESP3 {
+        match hello_command(connection_info)
+            .query_async::<_, Value>(con)
+            .await
+        {
+            Ok(_) => protocol = ProtocolVersion::RESP3,
+            Err(err) => hello_failed(err)?,
+        }
+    }
+
+    let password = match protocol {
+        ProtocolVersion::RESP2 => connection_info.password.as_ref(),
+        ProtocolVersion::RESP3 => None,
+    };
+    if let Some(password) = password {
         let mut command = cmd("AUTH");
         if let Some(username) = &connection_info.username {
             command.arg(username);
@@ -448,29 +670,46 @@ where
         }
     }
 
-    Ok(())
+    if let Some(pipeline) = client_setinfo_pipeline(connection_info) {
+        if let Err(err) = pipeline.query_async::<_, ()>(con).await {
+            client_setinfo_failed(err)?;
+        }
+    }
+
+    Ok(protocol)
 }
 
 pub(crate) async fn connect_simple<T: RedisRuntime>(
     connection_info: &ConnectionInfo,
+    resolver: Option<&dyn Resolver>,
 ) -> RedisResult<T> {
+    let proxy = connection_info.proxy.as_ref();
     Ok(match connection_info.addr {
         ConnectionAddr::Tcp(ref host, port) => {
-            let socket_addr = get_socket_addrs(host, port)?;
-            <T>::connect_tcp(socket_addr).await?
+            let (socket_addrs, tunnel) = tcp_target(resolver, proxy, host, port).await?;
+            <T>::connect_tcp(socket_addrs, tunnel.as_ref(), &connection_info.tcp_settings).await?
         }
 
-        #[cfg(feature = "tls")]
+        #[cfg(any(feature = "tls", feature = "tls-rustls"))]
         ConnectionAddr::TcpTls {
             ref host,
             port,
             insecure,
+            ref certificates,
         } => {
-            let socket_addr = get_socket_addrs(host, port)?;
-            <T>::connect_tcp_tls(host, socket_addr, insecure).await?
+            let (socket_addrs, tunnel) = tcp_target(resolver, proxy, host, port).await?;
+            <T>::connect_tcp_tls(
+                host,
+                socket_addrs,
+                tunnel.as_ref(),
+                &connection_info.tcp_settings,
+                insecure,
+                certificates.as_ref(),
+            )
+            .await?
         }
 
-        #[cfg(not(feature = "tls"))]
+        #[cfg(not(any(feature = "tls", feature = "tls-rustls")))]
         ConnectionAddr::TcpTls { .. } => {
             fail!((
                 ErrorKind::InvalidClientConfig,
@@ -478,6 +717,13 @@ pub(crate) async fn connect_simple<T: RedisRuntime>(
             ));
         }
 
+        ConnectionAddr::Unix(_) if proxy.is_some() => {
+            fail!((
+                ErrorKind::InvalidClientConfig,
+                "Cannot connect to unix sockets through a proxy"
+            ));
+        }
+
         #[cfg(unix)]
         ConnectionAddr::Unix(ref path) => <T>::connect_unix(path).await?,
 
@@ -492,14 +738,20 @@ pub(crate) async fn connect_simple<T: RedisRuntime>(
     })
 }
 
-fn get_socket_addrs(host: &str, port: u16) -> RedisResult<SocketAddr> {
-    let mut socket_addrs = (host, port).to_socket_addrs()?;
-    match socket_addrs.next() {
-        Some(socket_addr) => Ok(socket_addr),
-        None => Err(RedisError::from((
-            ErrorKind::InvalidClientConfig,
-            "No address found for host",
-        ))),
+// The addresses to connect to for the server, and the tunnel to open if they
+// are the ones of a proxy.
+async fn tcp_target<'a>(
+    resolver: Option<&dyn Resolver>,
+    proxy: Option<&'a ProxyConfig>,
+    host: &'a str,
+    port: u16,
+) -> RedisResult<(Vec<SocketAddr>, Option<Tunnel<'a>>)> {
+    match proxy {
+        Some(proxy) => Ok((
+            resolver::resolve(resolver, &proxy.host, proxy.port).await?,
+            Some(Tunnel::new(proxy, host, port)?),
+        )),
+        None => Ok((resolver::resolve(resolver, host, port).await?, None)),
     }
 }
 
@@ -532,13 +784,17 @@ where
 {
     fn req_packed_command<'a>(&'a mut self, cmd: &'a Cmd) -> RedisFuture<'a, Value> {
         (async move {
+            self.skip_pending_replies().await?;
             if self.pubsub {
                 self.exit_pubsub().await?;
             }
             self.buf.clear();
             cmd.write_packed_command(&mut self.buf);
+            self.writing = true;
             self.con.write_all(&self.buf).await?;
-            self.read_response().await
+            self.writing = false;
+            self.pending_replies = 1;
+            self.read_reply().await
         })
         .boxed()
     }
@@ -550,18 +806,29 @@ where
         count: usize,
     ) -> RedisFuture<'a, Vec<Value>> {
         (async move {
+            self.skip_pending_replies().await?;
             if self.pubsub {
                 self.exit_pubsub().await?;
             }
 
-            self.buf.clear();
-            cmd.write_packed_pipeline(&mut self.buf);
-            self.con.write_all(&self.buf).await?;
+            let packed = cmd.packed_commands();
+            let mut slices = packed.slices();
+            let mut start = advance_slices(&mut slices, 0);
+            self.writing = true;
+            while start < slices.len() {
+                let io_slices: Vec<_> = slices[start..].iter().map(|s| IoSlice::new(s)).collect();
+                match self.con.write_vectored(&io_slices).await? {
+                    0 => return Err(io::Error::from(io::ErrorKind::WriteZero).into()),
+                    n => start += advance_slices(&mut slices[start..], n),
+                }
+            }
+            self.writing = false;
+            self.pending_replies = offset + count;
 
             let mut first_err = None;
 
             for _ in 0..offset {
-                let response = self.read_response().await;
+                let response = self.read_reply().await;
                 if let Err(err) = response {
                     if first_err.is_none() {
                         first_err = Some(err);
@@ -571,7 +838,7 @@ where
 
             let mut rv = Vec::with_capacity(count);
             for _ in 0..count {
-                let response = self.read_response().await;
+                let response = self.read_reply().await;
                 match response {
                     Ok(item) => {
                         rv.push(item);
@@ -598,6 +865,18 @@ where
     }
 }
 
+// Awaits the reply of a request, failing with `ErrorKind::Timeout` if it
+// does not arrive within `timeout`.
+pub(crate) async fn with_timeout<T>(
+    timeout: Duration,
+    request: impl Future<Output = RedisResult<T>>,
+) -> RedisResult<T> {
+    Runtime::locate()
+        .timeout(timeout, request)
+        .await
+        .map_err(|_| RedisError::from((ErrorKind::Timeout, "Request timed out")))?
+}
+
 // Senders which the result of a single request are sent through
 type PipelineOutput<O, E> = oneshot::Sender<Result<Vec<O>, E>>;
 
@@ -618,11 +897,15 @@ struct PipelineMessage<S, I, E> {
 /// items being output by the `Stream` (the number is specified at time of sending). With the
 /// interface provided by `Pipeline` an easy interface of request to response, hiding the `Stream`
 /// and `Sink`.
-struct Pipeline<SinkItem, I, E>(mpsc::Sender<PipelineMessage<SinkItem, I, E>>);
+struct Pipeline<SinkItem, I, E>(
+    mpsc::Sender<PipelineMessage<SinkItem, I, E>>,
+    // Stops the driver, which fails the requests in flight.
+    Arc<Notify>,
+);
 
 impl<SinkItem, I, E> Clone for Pipeline<SinkItem, I, E> {
     fn clone(&self) -> Self {
-        Pipeline(self.0.clone())
+        Pipeline(self.0.clone(), self.1.clone())
     }
 }
 
@@ -632,6 +915,8 @@ pin_project! {
         sink_stream: T,
         in_flight: VecDeque<InFlight<I, E>>,
         error: Option<E>,
+        read_idle: bool,
+        observer: Option<Arc<dyn MetricsObserver>>,
     }
 }
 
@@ -639,7 +924,11 @@ impl<T, I, E> PipelineSink<T, I, E>
 where
     T: Stream<Item = Result<I, E>> + 'static,
 {
-    fn new<SinkItem>(sink_stream: T) -> Self
+    fn new<SinkItem>(
+        sink_stream: T,
+        read_idle: bool,
+        observer: Option<Arc<dyn MetricsObserver>>,
+    ) -> Self
     where
         T: Sink<SinkItem, Error = E> + Stream<Item = Result<I, E>> + 'static,
     {
@@ -647,14 +936,23 @@ where
             sink_stream,
             in_flight: VecDeque::new(),
             error: None,
+            read_idle,
+            observer,
+        }
+    }
+
+    fn report_queue_depth(&self) {
+        if let Some(ref observer) = self.observer {
+            observer.queue_depth(self.in_flight.len());
         }
     }
 
     // Read messages from the stream and send them back to the caller
     fn poll_read(mut self: Pin<&mut Self>, cx: &mut task::Context) -> Poll<Result<(), ()>> {
         loop {
-            // No need to try reading a message if there is no message in flight
-            if self.in_flight.is_empty() {
+            // No need to try reading a message if there is no message in flight, unless
+            // the stream has to be drained of messages which are not replies
+            if self.in_flight.is_empty() && !self.read_idle {
                 return Poll::Ready(Ok(()));
             }
             let item = match ready!(self.as_mut().project().sink_stream.poll_next(cx)) {
@@ -667,8 +965,8 @@ where
         }
     }
 
-    fn send_result(self: Pin<&mut Self>, result: Result<I, E>) {
-        let self_ = self.project();
+    fn send_result(mut self: Pin<&mut Self>, result: Result<I, E>) {
+        let self_ = self.as_mut().project();
         let response = {
             let entry = match self_.in_flight.front_mut() {
                 Some(entry) => entry,
@@ -693,6 +991,7 @@ where
         // care about the output and we can continue by just dropping the value
         // and sender
         entry.output.send(response).ok();
+        self.report_queue_depth();
     }
 }
 
@@ -745,6 +1044,7 @@ where
                     response_count,
                     buffer: Vec::new(),
                 });
+                self.report_queue_depth();
                 Ok(())
             }
             Err(err) => {
@@ -791,7 +1091,11 @@ where
     I: Send + 'static,
     E: Send + 'static,
 {
-    fn new<T>(sink_stream: T) -> (Self, impl Future<Output = ()>)
+    fn new<T>(
+        sink_stream: T,
+        read_idle: bool,
+        observer: Option<Arc<dyn MetricsObserver>>,
+    ) -> (Self, impl Future<Output = ()>)
     where
         T: Sink<SinkItem, Error = E> + Stream<Item = Result<I, E>> + 'static,
         T: Send + 'static,
@@ -801,21 +1105,29 @@ where
     {
         const BUFFER_SIZE: usize = 50;
         let (sender, mut receiver) = mpsc::channel(BUFFER_SIZE);
-        let f = stream::poll_fn(move |cx| receiver.poll_recv(cx))
+        let close = Arc::new(Notify::new());
+        let forward = stream::poll_fn(move |cx| receiver.poll_recv(cx))
             .map(Ok)
-            .forward(PipelineSink::new::<SinkItem>(sink_stream))
-            .map(|_| ());
-        (Pipeline(sender), f)
+            .forward(PipelineSink::new::<SinkItem>(
+                sink_stream,
+                read_idle,
+                observer,
+            ));
+        let closed = close.clone();
+        let f = async move {
+            let closed = closed.notified();
+            futures_util::pin_mut!(forward, closed);
+            future::select(forward, closed).await;
+        };
+        (Pipeline(sender, close), f)
     }
 
-    // `None` means that the stream was out of items causing that poll loop to shut down.
-    async fn send(&mut self, item: SinkItem) -> Result<I, Option<E>> {
-        self.send_recv_multiple(item, 1)
-            .await
-            // We can unwrap since we do a request for `1` item
-            .map(|mut item| item.pop().unwrap())
+    // Closes the connection, the requests in flight and the next ones fail.
+    fn close(&self) {
+        self.1.notify_one();
     }
 
+    // `None` means that the stream was out of items causing that poll loop to shut down.
     async fn send_recv_multiple(
         &mut self,
         input: SinkItem,
@@ -842,12 +1154,39 @@ where
     }
 }
 
+// The receivers of push messages, `None` once the connection is closed.
+type PushSenders = Arc<Mutex<Option<Vec<BufferSender<PushMessage>>>>>;
+
+// Hands the push messages read by the connection driver to every receiver.
+struct PushRouter(PushSenders);
+
+impl PushRouter {
+    fn route(&self, msg: PushMessage) {
+        if let Some(senders) = self.0.lock().unwrap().as_mut() {
+            senders.retain(|sender| sender.send(msg.clone()));
+        }
+    }
+}
+
+impl Drop for PushRouter {
+    fn drop(&mut self) {
+        // ends the push message streams along with the driver
+        self.0.lock().unwrap().take();
+    }
+}
+
 /// A connection object which can be cloned, allowing requests to be be sent concurrently
 /// on the same underlying connection (tcp/unix socket).
 #[derive(Clone)]
 pub struct MultiplexedConnection {
     pipeline: Pipeline<Vec<u8>, Value, RedisError>,
     db: i64,
+    protocol: ProtocolVersion,
+    push_senders: PushSenders,
+    response_timeout: Option<Duration>,
+    #[cfg(feature = "tracing")]
+    traced: crate::instrument::TracedConnection,
+    metrics: Option<Arc<dyn MetricsObserver>>,
 }
 
 impl MultiplexedConnection {
@@ -857,6 +1196,49 @@ impl MultiplexedConnection {
         connection_info: &RedisConnectionInfo,
         stream: C,
     ) -> RedisResult<(Self, impl Future<Output = ()>)>
+    where
+        C: Unpin + AsyncRead + AsyncWrite + Send + 'static,
+    {
+        Self::new_with_buffer_policy(connection_info, stream, BufferPolicy::default()).await
+    }
+
+    /// Constructs a new `MultiplexedConnection` out of a connected stream,
+    /// spawning the future which drives it on the current runtime.
+    ///
+    /// Any `AsyncRead + AsyncWrite` transport works, like a stream tunnelled
+    /// over SSH or through a custom proxy, or an in-memory duplex stream in
+    /// tests.
+    pub async fn new_from_stream<C>(
+        connection_info: &RedisConnectionInfo,
+        stream: C,
+    ) -> RedisResult<Self>
+    where
+        C: Unpin + AsyncRead + AsyncWrite + Send + 'static,
+    {
+        let (con, driver) = Self::new(connection_info, stream).await?;
+        Runtime::locate().spawn(driver);
+        Ok(con)
+    }
+
+    /// Like `new` but the read buffer of the connection is managed with the
+    /// given policy, for instance to share large buffers between connections.
+    pub async fn new_with_buffer_policy<C>(
+        connection_info: &RedisConnectionInfo,
+        stream: C,
+        buffer_policy: BufferPolicy,
+    ) -> RedisResult<(Self, impl Future<Output = ()>)>
+    where
+        C: Unpin + AsyncRead + AsyncWrite + Send + 'static,
+    {
+        Self::new_with_observer(connection_info, stream, buffer_policy, None).await
+    }
+
+    pub(crate) async fn new_with_observer<C>(
+        connection_info: &RedisConnectionInfo,
+        stream: C,
+        buffer_policy: BufferPolicy,
+        observer: Option<Arc<dyn MetricsObserver>>,
+    ) -> RedisResult<(Self, impl Future<Output = ()>)>
     where
         C: Unpin + AsyncRead + AsyncWrite + Send + 'static,
     {
@@ -866,51 +1248,144 @@ impl MultiplexedConnection {
             Box::pin(f)
         }
 
-        #[cfg(all(not(feature = "tokio-comp"), not(feature = "async-std-comp")))]
-        compile_error!("tokio-comp or async-std-comp features required for aio feature");
-
+        let push_senders = Arc::new(Mutex::new(Some(Vec::new())));
+        let router = PushRouter(push_senders.clone());
         let codec = ValueCodec::default()
-            .framed(stream)
-            .and_then(|msg| async move { msg });
-        let (pipeline, driver) = Pipeline::new(codec);
+            .buffer_policy(buffer_policy)
+            .framed(ObservedStream::new(stream, observer.clone()))
+            .and_then(|msg| async move { msg })
+            .try_filter_map(move |value| {
+                let reply = match value {
+                    Value::Push { kind, data } => {
+                        router.route(PushMessage { kind, data });
+                        None
+                    }
+                    value => Some(value),
+                };
+                futures_util::future::ready(Ok(reply))
+            });
+        // Push messages may arrive while no request is in flight
+        let read_idle = connection_info.protocol == ProtocolVersion::RESP3;
+        let (pipeline, driver) = Pipeline::new(codec, read_idle, observer.clone());
         let driver = boxed(driver);
         let mut con = MultiplexedConnection {
             pipeline,
             db: connection_info.db,
+            protocol: ProtocolVersion::RESP2,
+            push_senders,
+            response_timeout: None,
+            #[cfg(feature = "tracing")]
+            traced: crate::instrument::TracedConnection::new(None),
+            metrics: observer,
         };
-        let driver = {
+        let (protocol, driver) = {
             let auth = authenticate(connection_info, &mut con);
             futures_util::pin_mut!(auth);
 
             match futures_util::future::select(auth, driver).await {
-                futures_util::future::Either::Left((result, driver)) => {
-                    result?;
-                    driver
-                }
+                futures_util::future::Either::Left((result, driver)) => (result?, driver),
                 futures_util::future::Either::Right(((), _)) => {
                     unreachable!("Multiplexed connection driver unexpectedly terminated")
                 }
             }
         };
+        con.protocol = protocol;
         Ok((con, driver))
     }
+
+    /// Returns the protocol negotiated with the server.
+    pub fn protocol(&self) -> ProtocolVersion {
+        self.protocol
+    }
+
+    /// Sets how long a request waits for its reply before it fails with an
+    /// `ErrorKind::Timeout` error, or `None` to wait forever, which is the
+    /// default.
+    ///
+    /// The connection is closed after a timeout, since a server which does
+    /// not reply would otherwise pile up the requests sent in the meantime:
+    /// the requests in flight and the next ones fail with an error for
+    /// which `is_connection_dropped` is true.  Clones of the connection made
+    /// afterwards share the timeout.
+    pub fn set_response_timeout(&mut self, timeout: Option<Duration>) {
+        self.response_timeout = timeout;
+    }
+
+    async fn send_recv(&mut self, input: Vec<u8>, count: usize) -> RedisResult<Vec<Value>> {
+        let request = self.pipeline.send_recv_multiple(input, count);
+        let result = match self.response_timeout {
+            Some(timeout) => match with_timeout(timeout, request.map(Ok)).await {
+                Ok(result) => result,
+                Err(err) => {
+                    self.pipeline.close();
+                    return Err(err);
+                }
+            },
+            None => request.await,
+        };
+        result.map_err(|err| {
+            err.unwrap_or_else(|| RedisError::from(io::Error::from(io::ErrorKind::BrokenPipe)))
+        })
+    }
+
+    /// Returns a stream of the push messages received from now on.
+    ///
+    /// The server only sends push messages, such as client side caching
+    /// invalidations, over RESP3 connections.  They are never returned as
+    /// the reply of a command.  Every stream receives every message and
+    /// ends when the connection is closed.
+    ///
+    /// The messages which are not consumed yet are buffered as configured
+    /// by the default `BufferConfig`: once 1024 messages are buffered, the
+    /// oldest one is dropped for every new one.  Use `push_messages_with`
+    /// to configure the buffer.
+    pub fn push_messages(&self) -> MessageStream<PushMessage> {
+        self.subscribe_pushes(BufferConfig::default())
+    }
+
+    /// Like `push_messages` but the messages are buffered as configured.
+    ///
+    /// `OverflowPolicy::Block` is rejected with an
+    /// `ErrorKind::InvalidClientConfig` error since a slow consumer would
+    /// hold up the replies of every request of the connection.
+    pub fn push_messages_with(
+        &self,
+        config: BufferConfig,
+    ) -> RedisResult<MessageStream<PushMessage>> {
+        Ok(self.subscribe_pushes(config.non_blocking()?))
+    }
+
+    // Records the server the connection is connected to in the spans of its
+    // commands, with the `otel` feature.
+    #[cfg_attr(not(feature = "otel"), allow(unused_mut, unused_variables))]
+    pub(crate) fn with_peer(mut self, addr: &crate::connection::ConnectionAddr) -> Self {
+        #[cfg(feature = "otel")]
+        self.traced.set_peer(addr);
+        self
+    }
+
+    fn subscribe_pushes(&self, config: BufferConfig) -> MessageStream<PushMessage> {
+        let (sender, receiver) = buffer::channel(config);
+        if let Some(senders) = self.push_senders.lock().unwrap().as_mut() {
+            senders.push(sender);
+        }
+        receiver
+    }
 }
 
 impl ConnectionLike for MultiplexedConnection {
     fn req_packed_command<'a>(&'a mut self, cmd: &'a Cmd) -> RedisFuture<'a, Value> {
-        (async move {
-            let value = self
-                .pipeline
-                .send(cmd.get_packed_command())
-                .await
-                .map_err(|err| {
-                    err.unwrap_or_else(|| {
-                        RedisError::from(io::Error::from(io::ErrorKind::BrokenPipe))
-                    })
-                })?;
-            Ok(value)
-        })
-        .boxed()
+        let observer = self.metrics.clone();
+        #[cfg(feature = "tracing")]
+        let span = self.traced.command_span(cmd, self.db);
+        let request = async move {
+            let mut value = self.send_recv(cmd.get_packed_command(), 1).await?;
+            // We can unwrap since we do a request for `1` item
+            Ok(value.pop().unwrap())
+        };
+        #[cfg(feature = "tracing")]
+        let request = crate::instrument::traced_async(span, request);
+        observe_async(observer, Some(cmd), request).boxed()
     }
 
     fn req_packed_commands<'a>(
@@ -919,21 +1394,20 @@ impl ConnectionLike for MultiplexedConnection {
         offset: usize,
         count: usize,
     ) -> RedisFuture<'a, Vec<Value>> {
-        (async move {
+        let observer = self.metrics.clone();
+        #[cfg(feature = "tracing")]
+        let span = self.traced.pipeline_span(offset + count, self.db);
+        let request = async move {
             let mut value = self
-                .pipeline
-                .send_recv_multiple(cmd.get_packed_pipeline(), offset + count)
-                .await
-                .map_err(|err| {
-                    err.unwrap_or_else(|| {
-                        RedisError::from(io::Error::from(io::ErrorKind::BrokenPipe))
-                    })
-                })?;
+                .send_recv(cmd.get_packed_pipeline(), offset + count)
+                .await?;
 
             value.drain(..offset);
             Ok(value)
-        })
-        .boxed()
+        };
+        #[cfg(feature = "tracing")]
+        let request = crate::instrument::traced_async(span, request);
+        observe_async(observer, None, request).boxed()
     }
 
     fn get_db(&self) -> i64 {
@@ -945,14 +1419,212 @@ impl ConnectionLike for MultiplexedConnection {
 mod connection_manager {
     use super::*;
 
+    use std::collections::HashSet;
+    use std::convert::TryFrom;
+    use std::fmt;
     use std::sync::Arc;
 
     use arc_swap::{self, ArcSwap};
-    use futures::future::{self, Shared};
+    use futures::future::{self, Either, Shared};
     use futures_util::future::BoxFuture;
+    use futures_util::sink::SinkExt;
+    use rand::Rng;
+    use tokio_util::codec::Framed;
 
+    use crate::connection::{report_connected, ConnectionEvent, ConnectionEventHandler};
     use crate::Client;
 
+    /// The reconnect behavior of a [`ConnectionManager`].
+    ///
+    /// By default a dropped connection is re-established with a single
+    /// attempt, commands are not retried and commands issued while
+    /// reconnecting wait for the new connection.  When more attempts are
+    /// allowed, the delay between them starts at `initial_delay` and is
+    /// multiplied by `multiplier` after every attempt, up to `max_delay`, with
+    /// a random delay of up to `jitter` added.
+    #[derive(Clone)]
+    pub struct ConnectionManagerConfig {
+        initial_delay: Duration,
+        multiplier: u32,
+        max_delay: Duration,
+        jitter: Duration,
+        max_attempts: Option<usize>,
+        command_retries: usize,
+        fail_fast: bool,
+        on_event: Option<ConnectionEventHandler>,
+        pubsub_buffer: BufferConfig,
+        response_timeout: Option<Duration>,
+    }
+
+    impl fmt::Debug for ConnectionManagerConfig {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.debug_struct("ConnectionManagerConfig")
+                .field("initial_delay", &self.initial_delay)
+                .field("multiplier", &self.multiplier)
+                .field("max_delay", &self.max_delay)
+                .field("jitter", &self.jitter)
+                .field("max_attempts", &self.max_attempts)
+                .field("command_retries", &self.command_retries)
+                .field("fail_fast", &self.fail_fast)
+                .field("on_event", &self.on_event.is_some())
+                .field("pubsub_buffer", &self.pubsub_buffer)
+                .field("response_timeout", &self.response_timeout)
+                .finish()
+        }
+    }
+
+    impl Default for ConnectionManagerConfig {
+        fn default() -> Self {
+            ConnectionManagerConfig {
+                initial_delay: Duration::from_millis(100),
+                multiplier: 2,
+                max_delay: Duration::from_secs(10),
+                jitter: Duration::from_millis(0),
+                max_attempts: Some(1),
+                command_retries: 0,
+                fail_fast: false,
+                on_event: None,
+                pubsub_buffer: BufferConfig::default(),
+                response_timeout: None,
+            }
+        }
+    }
+
+    impl ConnectionManagerConfig {
+        /// Sets the delay before the second attempt to reconnect.
+        pub fn initial_delay(mut self, delay: Duration) -> Self {
+            self.initial_delay = delay;
+            self
+        }
+
+        /// Sets the factor the delay grows by after every failed attempt.
+        pub fn multiplier(mut self, multiplier: u32) -> Self {
+            self.multiplier = multiplier;
+            self
+        }
+
+        /// Sets the longest delay between two attempts, before the jitter is
+        /// added.
+        pub fn max_delay(mut self, delay: Duration) -> Self {
+            self.max_delay = delay;
+            self
+        }
+
+        /// Sets the upper bound of the random delay added to every delay, so
+        /// clients which lost the connection together do not reconnect at
+        /// the same time.
+        pub fn jitter(mut self, jitter: Duration) -> Self {
+            self.jitter = jitter;
+            self
+        }
+
+        /// Sets how often a reconnect tries to connect before giving up, or
+        /// `None` to try until it succeeds.  Once it gave up, the next
+        /// command starts a new reconnect.
+        pub fn max_attempts(mut self, attempts: Option<usize>) -> Self {
+            self.max_attempts = attempts;
+            self
+        }
+
+        /// Sets how often a command which failed with an I/O error is sent
+        /// again on the new connection.  Note that a command may be executed
+        /// twice if the connection dropped after it reached the server.
+        pub fn command_retries(mut self, retries: usize) -> Self {
+            self.command_retries = retries;
+            self
+        }
+
+        /// Sets whether commands issued while reconnecting fail right away
+        /// instead of waiting for the new connection.
+        pub fn fail_fast(mut self, fail_fast: bool) -> Self {
+            self.fail_fast = fail_fast;
+            self
+        }
+
+        /// Sets a function which is called with the [`ConnectionEvent`]s of
+        /// the connection of the manager, for instance to log them or to
+        /// flush a local cache once it was re-established.
+        pub fn on_event<F>(mut self, handler: F) -> Self
+        where
+            F: Fn(&ConnectionEvent) + Send + Sync + 'static,
+        {
+            self.on_event = Some(Arc::new(handler));
+            self
+        }
+
+        /// Sets how the messages of the [`ManagedPubSub`] connections of the
+        /// manager are buffered until they are consumed.  Since those
+        /// connections only receive messages, `OverflowPolicy::Block` is
+        /// allowed: it stops reading from the connection until the consumer
+        /// made room.
+        pub fn pubsub_buffer(mut self, config: BufferConfig) -> Self {
+            self.pubsub_buffer = config;
+            self
+        }
+
+        /// Sets how long a command waits for its reply, see
+        /// `MultiplexedConnection::set_response_timeout`.  The manager
+        /// reconnects after a command timed out.
+        pub fn response_timeout(mut self, timeout: Option<Duration>) -> Self {
+            self.response_timeout = timeout;
+            self
+        }
+
+        fn report<F: FnOnce() -> ConnectionEvent>(&self, event: F) {
+            if let Some(ref handler) = self.on_event {
+                handler(&event());
+            }
+        }
+
+        // Opens a multiplexed connection and reports it.
+        async fn connect(&self, client: &Client) -> RedisResult<MultiplexedConnection> {
+            let mut connection = client.get_multiplexed_async_connection().await?;
+            connection.set_response_timeout(self.response_timeout);
+            if let Some(ref handler) = self.on_event {
+                let info = client.connection_info();
+                report_connected(handler, &info.addr, &info.redis);
+            }
+            Ok(connection)
+        }
+
+        // The delay after the given number of failed attempts.
+        fn delay(&self, attempts: usize) -> Duration {
+            let delay = u32::try_from(attempts - 1)
+                .ok()
+                .and_then(|exp| self.multiplier.checked_pow(exp))
+                .and_then(|factor| self.initial_delay.checked_mul(factor))
+                .map_or(self.max_delay, |delay| delay.min(self.max_delay));
+            delay + self.jitter.mul_f64(rand::thread_rng().gen())
+        }
+
+        // Runs `connect` until it succeeds, fails with an error other than an
+        // I/O error or runs out of attempts.
+        async fn retry_connect<T, F, Fut>(
+            &self,
+            runtime: &Runtime,
+            mut connect: F,
+        ) -> RedisResult<T>
+        where
+            F: FnMut() -> Fut,
+            Fut: Future<Output = RedisResult<T>>,
+        {
+            let mut attempts = 0;
+            loop {
+                attempts += 1;
+                match connect().await {
+                    Ok(connection) => return Ok(connection),
+                    Err(err)
+                        if !err.is_io_error()
+                            || self.max_attempts.is_some_and(|max| attempts >= max) =>
+                    {
+                        return Err(err)
+                    }
+                    Err(_) => runtime.sleep(self.delay(attempts)).await,
+                }
+            }
+        }
+    }
+
     /// A `ConnectionManager` is a proxy that wraps a [multiplexed
     /// connection][multiplexed-connection] and automatically reconnects to the
     /// server when necessary.
@@ -976,6 +1648,13 @@ mod connection_manager {
     ///   initiated, will have to await the connection future.
     /// - If reconnecting fails, all pending commands will be failed as well. A
     ///   new reconnection attempt will be triggered if the error is an I/O error.
+    /// - Subscriptions need a connection of their own, which is opened with
+    ///   [`pubsub`](ConnectionManager::pubsub) and reconnects the same way.
+    ///
+    /// The number of attempts, the delays between them, retrying of failed
+    /// commands and failing commands while reconnecting can be configured
+    /// with a [`ConnectionManagerConfig`], which also takes a handler for the
+    /// [`ConnectionEvent`]s of the connection.
     ///
     /// [multiplexed-connection]: struct.MultiplexedConnection.html
     #[derive(Clone)]
@@ -989,6 +1668,7 @@ mod connection_manager {
         connection: Arc<ArcSwap<SharedRedisFuture<MultiplexedConnection>>>,
 
         runtime: Runtime,
+        config: ConnectionManagerConfig,
     }
 
     /// A `RedisResult` that can be cloned because `RedisError` is behind an `Arc`.
@@ -1003,10 +1683,19 @@ mod connection_manager {
         /// This requires the `connection-manager` feature, which will also pull in
         /// the Tokio executor.
         pub async fn new(client: Client) -> RedisResult<Self> {
+            Self::new_with_config(client, ConnectionManagerConfig::default()).await
+        }
+
+        /// Connect to the server like `new`, reconnecting as configured by
+        /// `config`.
+        pub async fn new_with_config(
+            client: Client,
+            config: ConnectionManagerConfig,
+        ) -> RedisResult<Self> {
             // Create a MultiplexedConnection and wait for it to be established
 
             let runtime = Runtime::locate();
-            let connection = client.get_multiplexed_async_connection().await?;
+            let connection = config.connect(&client).await?;
 
             // Wrap the connection in an `ArcSwap` instance for fast atomic access
             Ok(Self {
@@ -1015,9 +1704,48 @@ mod connection_manager {
                     future::ok(connection).boxed().shared(),
                 )),
                 runtime,
+                config,
             })
         }
 
+        /// Opens a pub/sub connection to the server.
+        ///
+        /// The subscriptions made through it are remembered and issued again
+        /// when the connection is re-established, which happens as configured
+        /// for the manager.  The reconnect is reported on the message stream
+        /// since messages published in the meantime are lost.  The stream
+        /// ends when reconnecting gave up.  The messages which are not
+        /// consumed yet are buffered as configured with
+        /// `ConnectionManagerConfig::pubsub_buffer`.
+        pub async fn pubsub(&self) -> RedisResult<ManagedPubSub> {
+            let connection = self.client.get_async_connection().await?;
+            let (requests, requests_receiver) = mpsc::unbounded_channel();
+            let (events_sender, events) = buffer::channel(self.config.pubsub_buffer);
+            self.runtime.spawn(run_pubsub(
+                self.client.clone(),
+                self.config.clone(),
+                self.runtime.clone(),
+                ValueCodec::default().framed(connection.con),
+                requests_receiver,
+                events_sender,
+            ));
+            Ok(ManagedPubSub { requests, events })
+        }
+
+        /// The current connection, waiting for a reconnect in progress, or
+        /// `None` if it is still in progress and `fail_fast` is set.
+        async fn current_connection(
+            &self,
+            current: &SharedRedisFuture<MultiplexedConnection>,
+        ) -> Option<RedisResult<MultiplexedConnection>> {
+            let result = if self.config.fail_fast {
+                current.clone().now_or_never()?
+            } else {
+                current.clone().await
+            };
+            Some(result.map_err(|e| e.clone_mostly("Reconnecting failed")))
+        }
+
         /// Reconnect and overwrite the old connection.
         ///
         /// The `current` guard points to the shared future that was active
@@ -1027,10 +1755,30 @@ mod connection_manager {
             current: arc_swap::Guard<Arc<SharedRedisFuture<MultiplexedConnection>>>,
         ) {
             let client = self.client.clone();
-            let new_connection: SharedRedisFuture<MultiplexedConnection> =
-                async move { Ok(client.get_multiplexed_async_connection().await?) }
-                    .boxed()
-                    .shared();
+            let config = self.config.clone();
+            let runtime = self.runtime.clone();
+            let new_connection: SharedRedisFuture<MultiplexedConnection> = async move {
+                let addr = client.connection_info().addr.to_string();
+                config.report(|| ConnectionEvent::Reconnecting { addr: addr.clone() });
+                let result = config
+                    .retry_connect(&runtime, || config.connect(&client))
+                    .await;
+                match result {
+                    Ok(_) => {
+                        if let Some(observer) = client.metrics_observer() {
+                            observer.reconnected();
+                        }
+                        config.report(|| ConnectionEvent::Reconnected { addr })
+                    }
+                    Err(ref err) => config.report(|| ConnectionEvent::GaveUp {
+                        addr,
+                        error: err.clone_mostly("Reconnecting failed"),
+                    }),
+                }
+                Ok(result?)
+            }
+            .boxed()
+            .shared();
 
             // Update the connection in the connection manager
             let new_connection_arc = Arc::new(new_connection.clone());
@@ -1046,43 +1794,247 @@ mod connection_manager {
         }
     }
 
-    /// Handle a command result. If the connection was dropped, reconnect.
+    /// Handle a command result. If the connection was dropped, or closed
+    /// after a timeout, reconnect.
     macro_rules! reconnect_if_dropped {
         ($self:expr, $result:expr, $current:expr) => {
             if let Err(ref e) = $result {
-                if e.is_connection_dropped() {
+                if e.is_connection_dropped() || e.kind() == ErrorKind::Timeout {
                     $self.reconnect($current);
                 }
             }
         };
     }
 
-    /// Handle a connection result. If there's an I/O error, reconnect.
-    /// Propagate any error.
-    macro_rules! reconnect_if_io_error {
-        ($self:expr, $result:expr, $current:expr) => {
-            if let Err(e) = $result {
-                if e.is_io_error() {
-                    $self.reconnect($current);
+    /// An event of the message stream of a [`ManagedPubSub`].
+    #[derive(Debug)]
+    pub enum PubSubEvent {
+        /// A message published to one of the subscribed channels or patterns.
+        Message(Msg),
+        /// The connection was re-established and the subscriptions were
+        /// issued again.  Messages published while it was down are missed.
+        Reconnected,
+    }
+
+    /// A pub/sub connection of a [`ConnectionManager`] which survives
+    /// reconnects, see [`ConnectionManager::pubsub`].
+    pub struct ManagedPubSub {
+        requests: mpsc::UnboundedSender<SubscriptionRequest>,
+        events: MessageStream<PubSubEvent>,
+    }
+
+    impl ManagedPubSub {
+        /// Subscribes to a new channel.
+        pub async fn subscribe<T: ToRedisArgs>(&mut self, channel: T) -> RedisResult<()> {
+            self.request("SUBSCRIBE", channel).await
+        }
+
+        /// Subscribes to a new channel with a pattern.
+        pub async fn psubscribe<T: ToRedisArgs>(&mut self, pchannel: T) -> RedisResult<()> {
+            self.request("PSUBSCRIBE", pchannel).await
+        }
+
+        /// Unsubscribes from a channel.
+        pub async fn unsubscribe<T: ToRedisArgs>(&mut self, channel: T) -> RedisResult<()> {
+            self.request("UNSUBSCRIBE", channel).await
+        }
+
+        /// Unsubscribes from a channel with a pattern.
+        pub async fn punsubscribe<T: ToRedisArgs>(&mut self, pchannel: T) -> RedisResult<()> {
+            self.request("PUNSUBSCRIBE", pchannel).await
+        }
+
+        /// Returns the counters of the buffer of the messages, such as the
+        /// number of messages dropped because the consumer was too slow.
+        pub fn stats(&self) -> BufferStats {
+            self.events.stats()
+        }
+
+        /// Returns [`Stream`] of the messages and reconnects of this
+        /// [`ManagedPubSub`].
+        pub fn on_message(&mut self) -> impl Stream<Item = PubSubEvent> + '_ {
+            stream::poll_fn(move |cx| self.events.poll_recv(cx))
+        }
+
+        /// Returns [`Stream`] of the messages and reconnects of this
+        /// [`ManagedPubSub`] consuming it.
+        pub fn into_on_message(mut self) -> impl Stream<Item = PubSubEvent> {
+            stream::poll_fn(move |cx| self.events.poll_recv(cx))
+        }
+
+        async fn request<T: ToRedisArgs>(
+            &mut self,
+            command: &'static str,
+            names: T,
+        ) -> RedisResult<()> {
+            let (reply, result) = oneshot::channel();
+            let request = SubscriptionRequest {
+                command,
+                names: names.to_redis_args(),
+                reply,
+            };
+            if self.requests.send(request).is_err() {
+                return Err(RedisError::from(io::Error::from(io::ErrorKind::BrokenPipe)));
+            }
+            result.await.unwrap_or_else(|_| {
+                Err(RedisError::from(io::Error::from(io::ErrorKind::BrokenPipe)))
+            })
+        }
+    }
+
+    struct SubscriptionRequest {
+        command: &'static str,
+        names: Vec<Vec<u8>>,
+        reply: oneshot::Sender<RedisResult<()>>,
+    }
+
+    type PubSubFramed = Framed<Pin<Box<dyn AsyncStream + Send + Sync>>, ValueCodec>;
+
+    // The channels and patterns to subscribe to again after a reconnect.
+    #[derive(Default)]
+    struct Subscriptions {
+        channels: HashSet<Vec<u8>>,
+        patterns: HashSet<Vec<u8>>,
+    }
+
+    impl Subscriptions {
+        fn update(&mut self, command: &str, names: Vec<Vec<u8>>) {
+            let (names_set, subscribe) = match command {
+                "SUBSCRIBE" => (&mut self.channels, true),
+                "PSUBSCRIBE" => (&mut self.patterns, true),
+                "UNSUBSCRIBE" => (&mut self.channels, false),
+                _ => (&mut self.patterns, false),
+            };
+            if subscribe {
+                names_set.extend(names);
+            } else if names.is_empty() {
+                // without arguments the server drops all of them
+                names_set.clear();
+            } else {
+                for name in &names {
+                    names_set.remove(name);
                 }
-                return Err(e);
             }
-        };
+        }
+
+        async fn connect(&self, client: &Client) -> RedisResult<PubSubFramed> {
+            let connection = client.get_async_connection().await?;
+            let mut framed = ValueCodec::default().framed(connection.con);
+            for (command, names) in [
+                ("SUBSCRIBE", &self.channels),
+                ("PSUBSCRIBE", &self.patterns),
+            ] {
+                if !names.is_empty() {
+                    let mut command = cmd(command);
+                    for name in names {
+                        command.arg(name.as_slice());
+                    }
+                    framed.send(command.get_packed_command()).await?;
+                }
+            }
+            Ok(framed)
+        }
+    }
+
+    // Serves the subscription requests and forwards the messages of a
+    // `ManagedPubSub` until it is dropped or reconnecting gave up.
+    async fn run_pubsub(
+        client: Client,
+        config: ConnectionManagerConfig,
+        runtime: Runtime,
+        mut framed: PubSubFramed,
+        mut requests: mpsc::UnboundedReceiver<SubscriptionRequest>,
+        events: BufferSender<PubSubEvent>,
+    ) {
+        let mut subscriptions = Subscriptions::default();
+        loop {
+            loop {
+                let event = match future::select(Box::pin(requests.recv()), framed.next()).await {
+                    Either::Left((request, _)) => Either::Left(request),
+                    Either::Right((value, _)) => Either::Right(value),
+                };
+                match event {
+                    Either::Left(None) => return,
+                    Either::Left(Some(request)) => {
+                        let mut command = cmd(request.command);
+                        for name in &request.names {
+                            command.arg(name.as_slice());
+                        }
+                        let result = framed.send(command.get_packed_command()).await;
+                        let dropped = result.is_err();
+                        if !dropped {
+                            subscriptions.update(request.command, request.names);
+                        }
+                        let _ = request.reply.send(result);
+                        if dropped {
+                            break;
+                        }
+                    }
+                    Either::Right(Some(Ok(Ok(value)))) => {
+                        if let Some(msg) = Msg::from_value(&value) {
+                            if !events.send_or_wait(PubSubEvent::Message(msg)).await {
+                                return;
+                            }
+                        }
+                    }
+                    // error replies, like to a bad pattern, are not messages
+                    Either::Right(Some(Ok(Err(_)))) => {}
+                    Either::Right(Some(Err(_))) | Either::Right(None) => break,
+                }
+            }
+
+            framed = match config
+                .retry_connect(&runtime, || subscriptions.connect(&client))
+                .await
+            {
+                Ok(framed) => framed,
+                Err(_) => return,
+            };
+            if !events.send_or_wait(PubSubEvent::Reconnected).await {
+                return;
+            }
+        }
+    }
+
+    /// Send a request, retrying it as configured when it failed with an I/O
+    /// error.
+    macro_rules! request_with_retries {
+        ($self:expr, |$connection:ident| $request:expr) => {{
+            let mut retries = $self.config.command_retries;
+            loop {
+                // Clone connection to avoid having to lock the ArcSwap in write mode
+                let guard = $self.connection.load();
+                let result = match $self.current_connection(&guard).await {
+                    Some(Ok(mut $connection)) => {
+                        let result = $request.await;
+                        reconnect_if_dropped!($self, &result, guard);
+                        result
+                    }
+                    Some(Err(e)) => {
+                        if e.is_io_error() {
+                            $self.reconnect(guard);
+                        }
+                        Err(e)
+                    }
+                    None => {
+                        break Err(RedisError::from(io::Error::new(
+                            io::ErrorKind::NotConnected,
+                            "Reconnecting",
+                        )))
+                    }
+                };
+                match result {
+                    Err(ref e) if retries > 0 && e.is_io_error() => retries -= 1,
+                    result => break result,
+                }
+            }
+        }};
     }
 
     impl ConnectionLike for ConnectionManager {
         fn req_packed_command<'a>(&'a mut self, cmd: &'a Cmd) -> RedisFuture<'a, Value> {
             (async move {
-                // Clone connection to avoid having to lock the ArcSwap in write mode
-                let guard = self.connection.load();
-                let connection_result = (**guard)
-                    .clone()
-                    .await
-                    .map_err(|e| e.clone_mostly("Reconnecting failed"));
-                reconnect_if_io_error!(self, connection_result, guard);
-                let result = connection_result?.req_packed_command(cmd).await;
-                reconnect_if_dropped!(self, &result, guard);
-                result
+                request_with_retries!(self, |connection| connection.req_packed_command(cmd))
             })
             .boxed()
         }
@@ -1094,18 +2046,8 @@ mod connection_manager {
             count: usize,
         ) -> RedisFuture<'a, Vec<Value>> {
             (async move {
-                // Clone shared connection future to avoid having to lock the ArcSwap in write mode
-                let guard = self.connection.load();
-                let connection_result = (**guard)
-                    .clone()
-                    .await
-                    .map_err(|e| e.clone_mostly("Reconnecting failed"));
-                reconnect_if_io_error!(self, connection_result, guard);
-                let result = connection_result?
-                    .req_packed_commands(cmd, offset, count)
-                    .await;
-                reconnect_if_dropped!(self, &result, guard);
-                result
+                request_with_retries!(self, |connection| connection
+                    .req_packed_commands(cmd, offset, count))
             })
             .boxed()
         }
@@ -1118,4 +2060,430 @@ mod connection_manager {
 
 #[cfg(feature = "connection-manager")]
 #[cfg_attr(docsrs, doc(cfg(feature = "connection-manager")))]
-pub use connection_manager::ConnectionManager;
+pub use connection_manager::{
+    ConnectionManager, ConnectionManagerConfig, ManagedPubSub, PubSubEvent,
+};
+
+#[cfg(feature = "connection-pool")]
+mod connection_pool {
+    use super::*;
+
+    use std::ops::Deref;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::Instant;
+
+    use ::tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+    use crate::cmd::Arg;
+    use crate::Client;
+
+    /// The configuration of a [`ConnectionPool`].
+    ///
+    /// By default the pool opens up to 10 connections, keeps none open
+    /// when idle, closes connections idle for 10 minutes, waits up to 30
+    /// seconds for a connection and `PING`s idle connections before handing
+    /// them out, waiting up to a second for the reply.
+    ///
+    /// Idle connections are reaped lazily: there is no background task, the
+    /// connections idle for too long are only closed when the pool is used
+    /// next, by `get` or `state`.
+    #[derive(Clone, Debug)]
+    pub struct PoolConfig {
+        min_size: usize,
+        max_size: usize,
+        checkout_timeout: Option<Duration>,
+        idle_timeout: Option<Duration>,
+        test_on_checkout: bool,
+        test_timeout: Duration,
+    }
+
+    impl Default for PoolConfig {
+        fn default() -> Self {
+            PoolConfig {
+                min_size: 0,
+                max_size: 10,
+                checkout_timeout: Some(Duration::from_secs(30)),
+                idle_timeout: Some(Duration::from_secs(10 * 60)),
+                test_on_checkout: true,
+                test_timeout: Duration::from_secs(1),
+            }
+        }
+    }
+
+    impl PoolConfig {
+        /// Sets the number of connections opened with the pool and kept
+        /// open even when they are idle.  Connections which broke or were
+        /// discarded are replaced in the background to keep this many.
+        pub fn min_size(mut self, min_size: usize) -> Self {
+            self.min_size = min_size;
+            self
+        }
+
+        /// Sets the maximum number of connections of the pool.
+        pub fn max_size(mut self, max_size: usize) -> Self {
+            self.max_size = max_size;
+            self
+        }
+
+        /// Sets how long `get` takes at most, or `None` to wait forever.
+        /// This includes waiting for a connection to be returned when all
+        /// of them are in use, checking idle connections and opening a new
+        /// one.
+        pub fn checkout_timeout(mut self, timeout: Option<Duration>) -> Self {
+            self.checkout_timeout = timeout;
+            self
+        }
+
+        /// Sets how long a connection may stay idle before it is closed, or
+        /// `None` to never close idle connections.  Connections are only
+        /// closed when the pool is used, see the [type docs](Self).
+        pub fn idle_timeout(mut self, timeout: Option<Duration>) -> Self {
+            self.idle_timeout = timeout;
+            self
+        }
+
+        /// Sets whether idle connections are checked with `PING` before
+        /// they are handed out.
+        pub fn test_on_checkout(mut self, test: bool) -> Self {
+            self.test_on_checkout = test;
+            self
+        }
+
+        /// Sets how long the `PING` of an idle connection may take before
+        /// the connection is considered broken and closed.
+        pub fn test_timeout(mut self, timeout: Duration) -> Self {
+            self.test_timeout = timeout;
+            self
+        }
+    }
+
+    /// The number of connections of a [`ConnectionPool`].
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub struct PoolState {
+        /// The number of open connections.
+        pub connections: usize,
+        /// The number of open connections which are not in use.
+        pub idle_connections: usize,
+    }
+
+    struct IdleConnection {
+        con: Connection,
+        since: Instant,
+    }
+
+    struct PoolInner {
+        client: Client,
+        config: PoolConfig,
+        idle: Mutex<VecDeque<IdleConnection>>,
+        permits: Arc<Semaphore>,
+        refilling: AtomicBool,
+    }
+
+    impl PoolInner {
+        fn connections(&self, idle: &VecDeque<IdleConnection>) -> usize {
+            idle.len() + self.config.max_size - self.permits.available_permits()
+        }
+
+        // Opens connections in the background until the pool has `min_size`
+        // of them again, after a connection was discarded.  A connection
+        // being opened holds a permit like one in use, so the pool never
+        // exceeds `max_size`.
+        fn refill(self: &Arc<Self>) {
+            if self.refilling.swap(true, Ordering::AcqRel) {
+                return;
+            }
+            let pool = self.clone();
+            Runtime::locate().spawn(async move {
+                while pool.connections(&pool.idle.lock().unwrap()) < pool.config.min_size {
+                    let permit = match pool.permits.clone().try_acquire_owned() {
+                        Ok(permit) => permit,
+                        Err(_) => break,
+                    };
+                    match pool.client.get_async_connection().await {
+                        Ok(con) => {
+                            let mut idle = pool.idle.lock().unwrap();
+                            idle.push_back(IdleConnection {
+                                con,
+                                since: Instant::now(),
+                            });
+                            drop(permit);
+                        }
+                        Err(_) => break,
+                    }
+                }
+                pool.refilling.store(false, Ordering::Release);
+            });
+        }
+
+        // Closes the connections idle for too long, keeping `min_size` of them.
+        fn reap(&self, idle: &mut VecDeque<IdleConnection>) {
+            let idle_timeout = match self.config.idle_timeout {
+                Some(timeout) => timeout,
+                None => return,
+            };
+            // the most recently returned connections are at the back
+            while self.connections(idle) > self.config.min_size
+                && idle
+                    .front()
+                    .is_some_and(|c| c.since.elapsed() >= idle_timeout)
+            {
+                idle.pop_front();
+            }
+        }
+    }
+
+    /// A pool of async connections to a single server.
+    ///
+    /// Connections are opened when needed, up to the maximum size of the
+    /// pool, and returned to it when the [`PooledConnection`] is dropped.
+    /// Idle connections are reaped lazily whenever the pool is used, see
+    /// [`PoolConfig`].  The pool can be cloned to share it between tasks.
+    #[derive(Clone)]
+    pub struct ConnectionPool {
+        inner: Arc<PoolInner>,
+    }
+
+    impl ConnectionPool {
+        /// Creates a pool and opens its `min_size` connections.
+        pub async fn new(client: Client, config: PoolConfig) -> RedisResult<Self> {
+            if config.max_size == 0 || config.min_size > config.max_size {
+                fail!((
+                    ErrorKind::InvalidClientConfig,
+                    "Pool sizes must satisfy 0 <= min_size <= max_size and max_size > 0"
+                ));
+            }
+            let mut idle = VecDeque::with_capacity(config.min_size);
+            for _ in 0..config.min_size {
+                idle.push_back(IdleConnection {
+                    con: client.get_async_connection().await?,
+                    since: Instant::now(),
+                });
+            }
+            Ok(ConnectionPool {
+                inner: Arc::new(PoolInner {
+                    permits: Arc::new(Semaphore::new(config.max_size)),
+                    client,
+                    config,
+                    idle: Mutex::new(idle),
+                    refilling: AtomicBool::new(false),
+                }),
+            })
+        }
+
+        /// Checks out a connection, opening one if none is idle.
+        ///
+        /// Fails with an `ErrorKind::Timeout` error if the checkout takes
+        /// longer than the checkout timeout, because every connection stays
+        /// in use or because the server does not answer.
+        pub async fn get(&self) -> RedisResult<PooledConnection> {
+            let timeout = match self.inner.config.checkout_timeout {
+                Some(timeout) => timeout,
+                None => return self.checkout().await,
+            };
+            match Runtime::locate().timeout(timeout, self.checkout()).await {
+                Ok(result) => result,
+                Err(_) => {
+                    // an idle connection may have been closed while it was
+                    // checked
+                    self.inner.refill();
+                    fail!((ErrorKind::Timeout, "Connection checkout timed out"))
+                }
+            }
+        }
+
+        async fn checkout(&self) -> RedisResult<PooledConnection> {
+            let permit = self
+                .inner
+                .permits
+                .clone()
+                .acquire_owned()
+                .await
+                .expect("the pool semaphore is never closed");
+
+            loop {
+                let idle = {
+                    let mut idle = self.inner.idle.lock().unwrap();
+                    self.inner.reap(&mut idle);
+                    idle.pop_back()
+                };
+                let mut con = match idle {
+                    Some(idle) => idle.con,
+                    None => break,
+                };
+                if !self.inner.config.test_on_checkout || self.test(&mut con).await {
+                    return Ok(PooledConnection::new(self.inner.clone(), con, permit));
+                }
+                self.inner.refill();
+            }
+            let con = self.inner.client.get_async_connection().await?;
+            Ok(PooledConnection::new(self.inner.clone(), con, permit))
+        }
+
+        // Whether the idle connection answers a `PING` in time.
+        async fn test(&self, con: &mut Connection) -> bool {
+            let ping = cmd("PING");
+            let reply = Runtime::locate()
+                .timeout(
+                    self.inner.config.test_timeout,
+                    ping.query_async::<_, ()>(con),
+                )
+                .await;
+            matches!(reply, Ok(Ok(())))
+        }
+
+        /// Returns the number of connections of the pool.
+        pub fn state(&self) -> PoolState {
+            let mut idle = self.inner.idle.lock().unwrap();
+            self.inner.reap(&mut idle);
+            PoolState {
+                connections: self.inner.connections(&idle),
+                idle_connections: idle.len(),
+            }
+        }
+    }
+
+    // The state of the session which commands leave on a connection, and
+    // which must not leak to the next user of the connection.
+    #[derive(Default)]
+    struct Session {
+        transaction: bool,
+        watching: bool,
+        changed: bool,
+    }
+
+    impl Session {
+        fn track(&mut self, cmd: &Cmd) {
+            let mut args = cmd.args_iter().map(|arg| match arg {
+                Arg::Simple(arg) => arg.to_ascii_uppercase(),
+                Arg::Cursor => Vec::new(),
+            });
+            let name = match args.next() {
+                Some(name) => name,
+                None => return,
+            };
+            match &name[..] {
+                b"MULTI" => self.transaction = true,
+                b"WATCH" => self.watching = true,
+                b"UNWATCH" => self.watching = false,
+                b"EXEC" | b"DISCARD" => {
+                    self.transaction = false;
+                    self.watching = false;
+                }
+                b"SELECT" | b"SUBSCRIBE" | b"PSUBSCRIBE" | b"SSUBSCRIBE" | b"MONITOR" | b"AUTH"
+                | b"HELLO" | b"RESET" | b"READONLY" | b"READWRITE" => self.changed = true,
+                b"CLIENT" => {
+                    if matches!(args.next().as_deref(), Some(b"REPLY") | Some(b"TRACKING")) {
+                        self.changed = true;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        fn is_clean(&self) -> bool {
+            !(self.transaction || self.watching || self.changed)
+        }
+    }
+
+    /// A connection checked out of a [`ConnectionPool`].
+    ///
+    /// It is returned to the pool when dropped, unless a command failed
+    /// because the connection broke or a command changed the state of the
+    /// session: a transaction or `WATCH` left open, `SELECT`, a
+    /// subscription, `AUTH`, `HELLO`, `CLIENT REPLY` or `CLIENT TRACKING`.
+    /// Such connections are closed and replaced instead.
+    pub struct PooledConnection {
+        pool: Arc<PoolInner>,
+        con: Option<Connection>,
+        broken: bool,
+        session: Session,
+        permit: Option<OwnedSemaphorePermit>,
+    }
+
+    impl PooledConnection {
+        fn new(pool: Arc<PoolInner>, con: Connection, permit: OwnedSemaphorePermit) -> Self {
+            PooledConnection {
+                pool,
+                con: Some(con),
+                broken: false,
+                session: Session::default(),
+                permit: Some(permit),
+            }
+        }
+
+        fn con(&mut self) -> &mut Connection {
+            self.con.as_mut().unwrap()
+        }
+
+        fn check<T>(&mut self, result: RedisResult<T>) -> RedisResult<T> {
+            if let Err(ref err) = result {
+                if err.is_connection_dropped() || err.is_io_error() {
+                    self.broken = true;
+                }
+            }
+            result
+        }
+    }
+
+    impl Deref for PooledConnection {
+        type Target = Connection;
+
+        fn deref(&self) -> &Connection {
+            self.con.as_ref().unwrap()
+        }
+    }
+
+    impl Drop for PooledConnection {
+        fn drop(&mut self) {
+            let con = self.con.take();
+            // release the slot before a replacement is opened
+            self.permit.take();
+            match con {
+                Some(con) if !self.broken && self.session.is_clean() => {
+                    let mut idle = self.pool.idle.lock().unwrap();
+                    idle.push_back(IdleConnection {
+                        con,
+                        since: Instant::now(),
+                    });
+                }
+                _ => self.pool.refill(),
+            }
+        }
+    }
+
+    impl ConnectionLike for PooledConnection {
+        fn req_packed_command<'a>(&'a mut self, cmd: &'a Cmd) -> RedisFuture<'a, Value> {
+            (async move {
+                self.session.track(cmd);
+                let result = self.con().req_packed_command(cmd).await;
+                self.check(result)
+            })
+            .boxed()
+        }
+
+        fn req_packed_commands<'a>(
+            &'a mut self,
+            cmd: &'a crate::Pipeline,
+            offset: usize,
+            count: usize,
+        ) -> RedisFuture<'a, Vec<Value>> {
+            (async move {
+                for cmd in cmd.cmd_iter() {
+                    self.session.track(cmd);
+                }
+                let result = self.con().req_packed_commands(cmd, offset, count).await;
+                self.check(result)
+            })
+            .boxed()
+        }
+
+        fn get_db(&self) -> i64 {
+            self.deref().get_db()
+        }
+    }
+}
+
+#[cfg(feature = "connection-pool")]
+#[cfg_attr(docsrs, doc(cfg(feature = "connection-pool")))]
+pub use connection_pool::{ConnectionPool, PoolConfig, PoolState, PooledConnection};
@@ -8,15 +8,27 @@ use std::{
     task::{self, Poll},
 };
 
-use crate::aio::{AsyncStream, RedisRuntime};
+use crate::aio::{AsyncStream, RedisRuntime, Tunnel};
+use crate::happy_eyeballs;
+use crate::tcp::TcpSettings;
+#[cfg(all(feature = "tls", not(feature = "tls-rustls")))]
+use crate::tls::pem_certificates;
+#[cfg(any(feature = "tls", feature = "tls-rustls"))]
+use crate::tls::TlsCertificates;
+#[cfg(feature = "async-std-rustls-comp")]
+use crate::tls::{create_rustls_config, server_name};
 use crate::types::RedisResult;
-#[cfg(feature = "tls")]
+#[cfg(all(feature = "tls", not(feature = "tls-rustls")))]
 use async_native_tls::{TlsConnector, TlsStream};
 use async_std::net::TcpStream;
 #[cfg(unix)]
 use async_std::os::unix::net::UnixStream;
 use async_trait::async_trait;
+#[cfg(feature = "async-std-rustls-comp")]
+use futures_rustls::{client::TlsStream, TlsConnector};
 use futures_util::ready;
+#[cfg(feature = "async-std-rustls-comp")]
+use std::sync::Arc;
 use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
 
 pin_project_lite::pin_project! {
@@ -82,8 +94,11 @@ pub enum AsyncStd {
     /// Represents an Async_std TCP connection.
     Tcp(AsyncStdWrapped<TcpStream>),
     /// Represents an Async_std TLS encrypted TCP connection.
-    #[cfg(feature = "async-std-tls-comp")]
+    #[cfg(all(feature = "async-std-tls-comp", not(feature = "async-std-rustls-comp")))]
     TcpTls(AsyncStdWrapped<TlsStream<TcpStream>>),
+    /// Represents an Async_std TLS encrypted TCP connection backed by rustls.
+    #[cfg(feature = "async-std-rustls-comp")]
+    TcpTls(AsyncStdWrapped<Box<TlsStream<TcpStream>>>),
     /// Represents an Async_std Unix connection.
     #[cfg(unix)]
     Unix(AsyncStdWrapped<UnixStream>),
@@ -97,7 +112,7 @@ impl AsyncWrite for AsyncStd {
     ) -> Poll<io::Result<usize>> {
         match &mut *self {
             AsyncStd::Tcp(r) => Pin::new(r).poll_write(cx, buf),
-            #[cfg(feature = "async-std-tls-comp")]
+            #[cfg(any(feature = "async-std-tls-comp", feature = "async-std-rustls-comp"))]
             AsyncStd::TcpTls(r) => Pin::new(r).poll_write(cx, buf),
             #[cfg(unix)]
             AsyncStd::Unix(r) => Pin::new(r).poll_write(cx, buf),
@@ -107,7 +122,7 @@ impl AsyncWrite for AsyncStd {
     fn poll_flush(mut self: Pin<&mut Self>, cx: &mut task::Context) -> Poll<io::Result<()>> {
         match &mut *self {
             AsyncStd::Tcp(r) => Pin::new(r).poll_flush(cx),
-            #[cfg(feature = "async-std-tls-comp")]
+            #[cfg(any(feature = "async-std-tls-comp", feature = "async-std-rustls-comp"))]
             AsyncStd::TcpTls(r) => Pin::new(r).poll_flush(cx),
             #[cfg(unix)]
             AsyncStd::Unix(r) => Pin::new(r).poll_flush(cx),
@@ -117,7 +132,7 @@ impl AsyncWrite for AsyncStd {
     fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut task::Context) -> Poll<io::Result<()>> {
         match &mut *self {
             AsyncStd::Tcp(r) => Pin::new(r).poll_shutdown(cx),
-            #[cfg(feature = "async-std-tls-comp")]
+            #[cfg(any(feature = "async-std-tls-comp", feature = "async-std-rustls-comp"))]
             AsyncStd::TcpTls(r) => Pin::new(r).poll_shutdown(cx),
             #[cfg(unix)]
             AsyncStd::Unix(r) => Pin::new(r).poll_shutdown(cx),
@@ -133,7 +148,7 @@ impl AsyncRead for AsyncStd {
     ) -> Poll<io::Result<()>> {
         match &mut *self {
             AsyncStd::Tcp(r) => Pin::new(r).poll_read(cx, buf),
-            #[cfg(feature = "async-std-tls-comp")]
+            #[cfg(any(feature = "async-std-tls-comp", feature = "async-std-rustls-comp"))]
             AsyncStd::TcpTls(r) => Pin::new(r).poll_read(cx, buf),
             #[cfg(unix)]
             AsyncStd::Unix(r) => Pin::new(r).poll_read(cx, buf),
@@ -141,22 +156,54 @@ impl AsyncRead for AsyncStd {
     }
 }
 
+// Connects to the address with the TCP settings, opening the tunnel if there
+// is one.
+async fn connect_tcp_stream(
+    socket_addrs: Vec<SocketAddr>,
+    tunnel: Option<&Tunnel<'_>>,
+    tcp_settings: &TcpSettings,
+) -> RedisResult<TcpStream> {
+    let mut tcp = happy_eyeballs::connect_async(socket_addrs, TcpStream::connect).await?;
+    if *tcp_settings != TcpSettings::default() {
+        // The options are set on the socket as a std stream, which the
+        // async_std one does not lend out.
+        let std_tcp: std::net::TcpStream = std::convert::TryFrom::try_from(tcp)?;
+        tcp_settings.apply(&std_tcp)?;
+        tcp = TcpStream::from(std_tcp);
+    }
+    match tunnel {
+        Some(tunnel) => {
+            let mut tcp = AsyncStdWrapped::new(tcp);
+            tunnel.open_async(&mut tcp).await?;
+            Ok(tcp.inner)
+        }
+        None => Ok(tcp),
+    }
+}
+
 #[async_trait]
 impl RedisRuntime for AsyncStd {
-    async fn connect_tcp(socket_addr: SocketAddr) -> RedisResult<Self> {
-        Ok(TcpStream::connect(&socket_addr)
+    async fn connect_tcp(
+        socket_addrs: Vec<SocketAddr>,
+        tunnel: Option<&Tunnel<'_>>,
+        tcp_settings: &TcpSettings,
+    ) -> RedisResult<Self> {
+        Ok(connect_tcp_stream(socket_addrs, tunnel, tcp_settings)
             .await
             .map(|con| Self::Tcp(AsyncStdWrapped::new(con)))?)
     }
 
-    #[cfg(feature = "tls")]
+    #[cfg(all(feature = "tls", not(feature = "tls-rustls")))]
     async fn connect_tcp_tls(
         hostname: &str,
-        socket_addr: SocketAddr,
+        socket_addrs: Vec<SocketAddr>,
+        tunnel: Option<&Tunnel<'_>>,
+        tcp_settings: &TcpSettings,
         insecure: bool,
+        certificates: Option<&TlsCertificates>,
     ) -> RedisResult<Self> {
-        let tcp_stream = TcpStream::connect(&socket_addr).await?;
-        let tls_connector = if insecure {
+        let tcp_stream = connect_tcp_stream(socket_addrs, tunnel, tcp_settings).await?;
+        let mut tls_connector = if insecure {
             TlsConnector::new()
                 .danger_accept_invalid_certs(true)
                 .danger_accept_invalid_hostnames(true)
@@ -164,12 +211,44 @@ impl RedisRuntime for AsyncStd {
         } else {
             TlsConnector::new()
         };
+        if let Some(certificates) = certificates {
+            if let Some(ref client_tls) = certificates.client_tls {
+                tls_connector = tls_connector.identity(native_tls::Identity::from_pkcs8(
+                    &client_tls.client_cert,
+                    &client_tls.client_key,
+                )?);
+            }
+            if let Some(ref root_cert) = certificates.root_cert {
+                for cert in pem_certificates(root_cert) {
+                    tls_connector = tls_connector
+                        .add_root_certificate(native_tls::Certificate::from_pem(cert)?);
+                }
+            }
+        }
         Ok(tls_connector
             .connect(hostname, tcp_stream)
             .await
             .map(|con| Self::TcpTls(AsyncStdWrapped::new(con)))?)
     }
 
+    #[cfg(feature = "tls-rustls")]
+    async fn connect_tcp_tls(
+        hostname: &str,
+        socket_addrs: Vec<SocketAddr>,
+        tunnel: Option<&Tunnel<'_>>,
+        tcp_settings: &TcpSettings,
+        insecure: bool,
+        certificates: Option<&TlsCertificates>,
+    ) -> RedisResult<Self> {
+        let tcp_stream = connect_tcp_stream(socket_addrs, tunnel, tcp_settings).await?;
+        let tls_connector =
+            TlsConnector::from(Arc::new(create_rustls_config(insecure, certificates)?));
+        Ok(tls_connector
+            .connect(server_name(hostname)?, tcp_stream)
+            .await
+            .map(|con| Self::TcpTls(AsyncStdWrapped::new(Box::new(con))))?)
+    }
+
     #[cfg(unix)]
     async fn connect_unix(path: &Path) -> RedisResult<Self> {
         Ok(UnixStream::connect(path)
@@ -184,7 +263,7 @@ impl RedisRuntime for AsyncStd {
     fn boxed(self) -> Pin<Box<dyn AsyncStream + Send + Sync>> {
         match self {
             AsyncStd::Tcp(x) => Box::pin(x),
-            #[cfg(feature = "async-std-tls-comp")]
+            #[cfg(any(feature = "async-std-tls-comp", feature = "async-std-rustls-comp"))]
             AsyncStd::TcpTls(x) => Box::pin(x),
             #[cfg(unix)]
             AsyncStd::Unix(x) => Box::pin(x),
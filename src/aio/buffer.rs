@@ -0,0 +1,334 @@
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+use futures_util::stream::Stream;
+
+use crate::types::{ErrorKind, RedisResult};
+
+/// What a message buffer does with a message which arrives while it is
+/// full.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Waits for the consumer to make room, which stops reading from the
+    /// connection meanwhile.  This is only allowed for dedicated pub/sub
+    /// connections, where the replies to other commands cannot be held up.
+    Block,
+    /// Drops the oldest buffered message to make room for the new one.
+    DropOldest,
+    /// Drops the new message.
+    DropNewest,
+    /// Drops the new message and closes the buffer: the stream ends once
+    /// the buffered messages were read and [`BufferStats::overflowed`]
+    /// reports why.
+    CloseWithError,
+}
+
+/// The capacity and overflow policy of the buffer of messages a
+/// connection receives while they are not consumed.
+///
+/// By default up to 1024 messages are buffered and the oldest message is
+/// dropped when another one arrives.
+#[derive(Clone, Copy, Debug)]
+pub struct BufferConfig {
+    capacity: usize,
+    overflow: OverflowPolicy,
+}
+
+impl Default for BufferConfig {
+    fn default() -> Self {
+        BufferConfig {
+            capacity: 1024,
+            overflow: OverflowPolicy::DropOldest,
+        }
+    }
+}
+
+impl BufferConfig {
+    /// Sets how many messages are buffered at most, at least one.
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity.max(1);
+        self
+    }
+
+    /// Sets what happens to a message which arrives while the buffer is
+    /// full.
+    pub fn overflow(mut self, policy: OverflowPolicy) -> Self {
+        self.overflow = policy;
+        self
+    }
+
+    // Fails for `OverflowPolicy::Block`, for buffers filled by a connection
+    // which must keep reading replies.
+    pub(crate) fn non_blocking(self) -> RedisResult<Self> {
+        if self.overflow == OverflowPolicy::Block {
+            fail!((
+                ErrorKind::InvalidClientConfig,
+                "Buffers of multiplexed connections cannot block when full"
+            ));
+        }
+        Ok(self)
+    }
+}
+
+/// The counters of a message buffer.
+#[derive(Clone, Debug, Default)]
+pub struct BufferStats(Arc<Counters>);
+
+#[derive(Debug, Default)]
+struct Counters {
+    dropped: AtomicU64,
+    overflowed: AtomicBool,
+}
+
+impl BufferStats {
+    /// Returns the number of messages dropped because the buffer was full.
+    pub fn dropped_messages(&self) -> u64 {
+        self.0.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Returns whether the buffer was closed because it overflowed with
+    /// `OverflowPolicy::CloseWithError`.
+    pub fn overflowed(&self) -> bool {
+        self.0.overflowed.load(Ordering::Relaxed)
+    }
+}
+
+struct State<T> {
+    messages: VecDeque<T>,
+    closed: bool,
+    receiver: Option<Waker>,
+    sender: Option<Waker>,
+}
+
+struct Shared<T> {
+    state: Mutex<State<T>>,
+    config: BufferConfig,
+    stats: BufferStats,
+}
+
+impl<T> Shared<T> {
+    fn close(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.closed = true;
+        for waker in state.receiver.take().into_iter().chain(state.sender.take()) {
+            waker.wake();
+        }
+    }
+}
+
+// Returns the two ends of a buffer with the given config.
+pub(crate) fn channel<T>(config: BufferConfig) -> (BufferSender<T>, MessageStream<T>) {
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            messages: VecDeque::new(),
+            closed: false,
+            receiver: None,
+            sender: None,
+        }),
+        config,
+        stats: BufferStats::default(),
+    });
+    (BufferSender(shared.clone()), MessageStream(shared))
+}
+
+// The end of a buffer the connection hands its messages to.
+pub(crate) struct BufferSender<T>(Arc<Shared<T>>);
+
+impl<T> BufferSender<T> {
+    // Waits for room in the buffer if it blocks when full, then buffers the
+    // message.  Returns false once the buffer is closed.
+    #[cfg(feature = "connection-manager")]
+    pub(crate) async fn send_or_wait(&self, msg: T) -> bool {
+        futures_util::future::poll_fn(|cx| self.poll_room(cx)).await;
+        self.send(msg)
+    }
+
+    #[cfg(feature = "connection-manager")]
+    fn poll_room(&self, cx: &mut Context<'_>) -> Poll<()> {
+        let shared = &self.0;
+        let mut state = shared.state.lock().unwrap();
+        if shared.config.overflow == OverflowPolicy::Block
+            && !state.closed
+            && state.messages.len() >= shared.config.capacity
+        {
+            state.sender = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+        Poll::Ready(())
+    }
+
+    // Buffers the message, applying the overflow policy if the buffer is
+    // full.  Returns false once the buffer is closed.
+    pub(crate) fn send(&self, msg: T) -> bool {
+        let shared = &self.0;
+        let mut state = shared.state.lock().unwrap();
+        if state.closed {
+            return false;
+        }
+        if state.messages.len() >= shared.config.capacity {
+            shared.stats.0.dropped.fetch_add(1, Ordering::Relaxed);
+            match shared.config.overflow {
+                OverflowPolicy::DropOldest => {
+                    state.messages.pop_front();
+                }
+                // only reached if the caller did not wait for room
+                OverflowPolicy::Block | OverflowPolicy::DropNewest => return true,
+                OverflowPolicy::CloseWithError => {
+                    shared.stats.0.overflowed.store(true, Ordering::Relaxed);
+                    state.closed = true;
+                    if let Some(waker) = state.receiver.take() {
+                        waker.wake();
+                    }
+                    return false;
+                }
+            }
+        }
+        state.messages.push_back(msg);
+        if let Some(waker) = state.receiver.take() {
+            waker.wake();
+        }
+        true
+    }
+}
+
+impl<T> Drop for BufferSender<T> {
+    fn drop(&mut self) {
+        self.0.close();
+    }
+}
+
+/// A stream of the messages of a bounded buffer.  It ends when the
+/// connection is closed, or when the buffer overflowed with
+/// `OverflowPolicy::CloseWithError`.
+pub struct MessageStream<T>(Arc<Shared<T>>);
+
+impl<T> MessageStream<T> {
+    /// Returns the counters of the buffer.
+    pub fn stats(&self) -> BufferStats {
+        self.0.stats.clone()
+    }
+
+    pub(crate) fn poll_recv(&mut self, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        let mut state = self.0.state.lock().unwrap();
+        match state.messages.pop_front() {
+            Some(msg) => {
+                if let Some(waker) = state.sender.take() {
+                    waker.wake();
+                }
+                Poll::Ready(Some(msg))
+            }
+            None if state.closed => Poll::Ready(None),
+            None => {
+                state.receiver = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl<T> Stream for MessageStream<T> {
+    type Item = T;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        self.poll_recv(cx)
+    }
+}
+
+impl<T> Drop for MessageStream<T> {
+    fn drop(&mut self) {
+        self.0.close();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use futures::executor::block_on;
+    use futures_util::{FutureExt, StreamExt};
+
+    fn fill(config: BufferConfig) -> (MessageStream<u32>, Vec<bool>) {
+        let (sender, receiver) = channel(config.capacity(2));
+        let sent = (1..=4).map(|msg| sender.send(msg)).collect();
+        (receiver, sent)
+    }
+
+    #[test]
+    fn drop_oldest_keeps_the_newest_messages() {
+        let (receiver, sent) = fill(BufferConfig::default());
+        assert_eq!(sent, vec![true; 4]);
+        let stats = receiver.stats();
+        assert_eq!(block_on(receiver.collect::<Vec<_>>()), vec![3, 4]);
+        assert_eq!(stats.dropped_messages(), 2);
+        assert!(!stats.overflowed());
+    }
+
+    #[test]
+    fn drop_newest_keeps_the_oldest_messages() {
+        let (receiver, sent) = fill(BufferConfig::default().overflow(OverflowPolicy::DropNewest));
+        assert_eq!(sent, vec![true; 4]);
+        let stats = receiver.stats();
+        assert_eq!(block_on(receiver.collect::<Vec<_>>()), vec![1, 2]);
+        assert_eq!(stats.dropped_messages(), 2);
+    }
+
+    #[test]
+    fn close_with_error_ends_the_stream() {
+        let (receiver, sent) =
+            fill(BufferConfig::default().overflow(OverflowPolicy::CloseWithError));
+        assert_eq!(sent, vec![true, true, false, false]);
+        let stats = receiver.stats();
+        assert!(stats.overflowed());
+        assert_eq!(block_on(receiver.collect::<Vec<_>>()), vec![1, 2]);
+        assert_eq!(stats.dropped_messages(), 1);
+    }
+
+    #[test]
+    #[cfg(feature = "connection-manager")]
+    fn block_waits_for_the_consumer() {
+        let (sender, mut receiver) = channel(
+            BufferConfig::default()
+                .capacity(1)
+                .overflow(OverflowPolicy::Block),
+        );
+        assert!(block_on(sender.send_or_wait(1)));
+
+        let mut blocked = Box::pin(sender.send_or_wait(2));
+        assert_eq!(blocked.as_mut().now_or_never(), None);
+        assert_eq!(block_on(receiver.next()), Some(1));
+        assert_eq!(blocked.now_or_never(), Some(true));
+        assert_eq!(block_on(receiver.next()), Some(2));
+        assert_eq!(receiver.stats().dropped_messages(), 0);
+
+        // a waiting sender gives up once the consumer is gone
+        assert!(block_on(sender.send_or_wait(3)));
+        let mut blocked = Box::pin(sender.send_or_wait(4));
+        assert_eq!(blocked.as_mut().now_or_never(), None);
+        drop(receiver);
+        assert!(!block_on(blocked));
+    }
+
+    #[test]
+    fn multiplexed_buffers_cannot_block() {
+        assert!(BufferConfig::default()
+            .overflow(OverflowPolicy::Block)
+            .non_blocking()
+            .is_err());
+        assert!(BufferConfig::default().non_blocking().is_ok());
+    }
+
+    #[test]
+    fn consumed_messages_make_room() {
+        let (sender, mut receiver) = channel(BufferConfig::default().capacity(1));
+        for msg in 0..3 {
+            assert!(sender.send(msg));
+            assert_eq!(block_on(receiver.next()), Some(msg));
+        }
+        assert_eq!(receiver.stats().dropped_messages(), 0);
+        drop(receiver);
+        assert!(!sender.send(3));
+    }
+}
@@ -0,0 +1,208 @@
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::{
+    async_trait, mpsc, Future, MultiplexedConnection, Notify, Pipeline, PushSenders, Runtime,
+};
+use crate::cmd::cmd;
+use crate::connection::{ProtocolVersion, RedisConnectionInfo};
+use crate::instrumentation::MetricsObserver;
+use crate::types::{RedisError, RedisResult, Value};
+
+// How long to wait before asking for new credentials again after sending
+// them failed, and the shortest time between two refreshes.
+const REFRESH_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// The credentials a connection authenticates with, returned by a
+/// [`CredentialsProvider`].
+#[derive(Clone)]
+pub struct Credentials {
+    /// The username, or `None` to authenticate the default user.
+    pub username: Option<String>,
+    /// The password, or the token standing in for it.
+    pub password: String,
+    /// How long the credentials are accepted by the server, or `None` if
+    /// they do not expire.
+    pub valid_for: Option<Duration>,
+}
+
+impl fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Credentials")
+            .field("username", &self.username)
+            .field("password", &"<redacted>")
+            .field("valid_for", &self.valid_for)
+            .finish()
+    }
+}
+
+impl Credentials {
+    pub(crate) fn apply(&self, info: &mut RedisConnectionInfo) {
+        info.username = self.username.clone();
+        info.password = Some(self.password.clone());
+    }
+}
+
+/// Provides the credentials of the async connections of a `Client`, set with
+/// `Client::with_credentials_provider`.
+///
+/// The provider is asked for credentials whenever a connection is opened,
+/// which includes the reconnects of a `ConnectionManager`, so it can hand out
+/// short-lived tokens like the IAM tokens of AWS ElastiCache or the Entra ID
+/// tokens of Azure Cache for Redis.  When the credentials of a multiplexed
+/// connection expire, it asks for new ones after three quarters of their
+/// lifetime, but at most once a second, and sends them with `AUTH` in the
+/// background.
+///
+/// ```rust,no_run
+/// use std::time::Duration;
+/// use redis::aio::{Credentials, CredentialsProvider};
+///
+/// struct TokenProvider;
+///
+/// #[async_trait::async_trait]
+/// impl CredentialsProvider for TokenProvider {
+///     async fn credentials(&self) -> redis::RedisResult<Credentials> {
+///         Ok(Credentials {
+///             username: Some("app".to_string()),
+///             password: "token fetched from the identity provider".to_string(),
+///             valid_for: Some(Duration::from_secs(15 * 60)),
+///         })
+///     }
+/// }
+///
+/// # async fn do_something() -> redis::RedisResult<()> {
+/// let client = redis::Client::open("rediss://cache.example.com/")?
+///     .with_credentials_provider(TokenProvider);
+/// let con = client.get_multiplexed_async_connection().await?;
+/// # Ok(()) }
+/// ```
+#[async_trait]
+pub trait CredentialsProvider: Send + Sync {
+    /// Returns the credentials for a new connection, or for a connection
+    /// whose credentials are about to expire.
+    async fn credentials(&self) -> RedisResult<Credentials>;
+}
+
+// A handle to a multiplexed connection which does not keep it open.
+struct WeakMultiplexedConnection {
+    sender: mpsc::WeakSender<super::PipelineMessage<Vec<u8>, Value, RedisError>>,
+    close: Arc<Notify>,
+    db: i64,
+    protocol: ProtocolVersion,
+    push_senders: PushSenders,
+    #[cfg(feature = "tracing")]
+    traced: crate::instrument::TracedConnection,
+    metrics: Option<Arc<dyn MetricsObserver>>,
+}
+
+impl WeakMultiplexedConnection {
+    fn new(connection: &MultiplexedConnection) -> Self {
+        WeakMultiplexedConnection {
+            sender: connection.pipeline.0.downgrade(),
+            close: connection.pipeline.1.clone(),
+            db: connection.db,
+            protocol: connection.protocol,
+            push_senders: connection.push_senders.clone(),
+            #[cfg(feature = "tracing")]
+            traced: connection.traced.clone(),
+            metrics: connection.metrics.clone(),
+        }
+    }
+
+    fn upgrade(&self) -> Option<MultiplexedConnection> {
+        Some(MultiplexedConnection {
+            pipeline: Pipeline(self.sender.upgrade()?, self.close.clone()),
+            db: self.db,
+            protocol: self.protocol,
+            push_senders: self.push_senders.clone(),
+            response_timeout: None,
+            #[cfg(feature = "tracing")]
+            traced: self.traced.clone(),
+            metrics: self.metrics.clone(),
+        })
+    }
+}
+
+// Sends new credentials with `AUTH` before the current ones expire, until
+// the connection is closed.
+pub(crate) fn refresh_credentials(
+    connection: &MultiplexedConnection,
+    provider: Arc<dyn CredentialsProvider>,
+    valid_for: Duration,
+) -> impl Future<Output = ()> {
+    let connection = WeakMultiplexedConnection::new(connection);
+    async move {
+        let runtime = Runtime::locate();
+        let mut delay = refresh_delay(valid_for);
+        loop {
+            runtime.sleep(delay).await;
+            let mut con = match connection.upgrade() {
+                Some(con) => con,
+                None => return,
+            };
+            let result = async {
+                let credentials = provider.credentials().await?;
+                let mut command = cmd("AUTH");
+                if let Some(ref username) = credentials.username {
+                    command.arg(username);
+                }
+                command
+                    .arg(&credentials.password)
+                    .query_async::<_, ()>(&mut con)
+                    .await?;
+                Ok::<_, RedisError>(credentials.valid_for)
+            }
+            .await;
+            delay = match result {
+                Ok(Some(valid_for)) => refresh_delay(valid_for),
+                Ok(None) => return,
+                Err(ref err) if err.is_connection_dropped() => return,
+                Err(_) => REFRESH_RETRY_DELAY,
+            };
+        }
+    }
+}
+
+// Refreshes after three quarters of the lifetime of the credentials, without
+// flooding the provider and the server when they are short-lived.
+fn refresh_delay(valid_for: Duration) -> Duration {
+    (valid_for * 3 / 4).max(REFRESH_RETRY_DELAY)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_credentials_apply() {
+        let credentials = Credentials {
+            username: Some("app".to_string()),
+            password: "secret token".to_string(),
+            valid_for: None,
+        };
+        assert!(!format!("{:?}", credentials).contains("secret"));
+
+        let mut info = RedisConnectionInfo {
+            password: Some("old".to_string()),
+            ..RedisConnectionInfo::default()
+        };
+        credentials.apply(&mut info);
+        assert_eq!(info.username.as_deref(), Some("app"));
+        assert_eq!(info.password.as_deref(), Some("secret token"));
+    }
+
+    #[test]
+    fn test_refresh_delay() {
+        assert_eq!(
+            refresh_delay(Duration::from_secs(60)),
+            Duration::from_secs(45)
+        );
+        assert_eq!(refresh_delay(Duration::ZERO), REFRESH_RETRY_DELAY);
+        assert_eq!(
+            refresh_delay(Duration::from_millis(10)),
+            REFRESH_RETRY_DELAY
+        );
+    }
+}
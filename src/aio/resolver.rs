@@ -0,0 +1,71 @@
+use std::net::{SocketAddr, ToSocketAddrs};
+
+use super::async_trait;
+use crate::types::{ErrorKind, RedisError, RedisResult};
+
+/// Resolves the hostnames of the async connections of a `Client`, set with
+/// `Client::with_resolver`.
+///
+/// Without a resolver the hostnames are looked up with the blocking
+/// `ToSocketAddrs`.  A resolver can use an async DNS client like hickory, or
+/// map the hostnames of a split-horizon setup itself.  It is used for the
+/// hostname of the proxy too, if the connection goes through one.
+///
+/// When the client discovers the server with `Client::with_srv_discovery`,
+/// the resolver also looks up the SRV records.
+///
+/// ```rust,no_run
+/// use std::net::SocketAddr;
+/// use redis::aio::Resolver;
+///
+/// struct StaticResolver(SocketAddr);
+///
+/// #[async_trait::async_trait]
+/// impl Resolver for StaticResolver {
+///     async fn resolve(&self, _host: &str, _port: u16) -> redis::RedisResult<Vec<SocketAddr>> {
+///         Ok(vec![self.0])
+///     }
+/// }
+///
+/// # async fn do_something() -> redis::RedisResult<()> {
+/// let client = redis::Client::open("redis://cache.internal/")?
+///     .with_resolver(StaticResolver("10.0.0.7:6379".parse().unwrap()));
+/// let con = client.get_multiplexed_async_connection().await?;
+/// # Ok(()) }
+/// ```
+#[async_trait]
+pub trait Resolver: Send + Sync {
+    /// Returns the addresses of the host, in the order they are tried.
+    async fn resolve(&self, host: &str, port: u16) -> RedisResult<Vec<SocketAddr>>;
+
+    /// Returns the hosts and ports of the SRV records of the name, in the
+    /// order they are tried, so sorted by priority and weight.
+    ///
+    /// The default implementation fails, as SRV records can not be looked up
+    /// with the standard library.
+    async fn resolve_srv(&self, _name: &str) -> RedisResult<Vec<(String, u16)>> {
+        Err(RedisError::from((
+            ErrorKind::InvalidClientConfig,
+            "The resolver does not look up SRV records",
+        )))
+    }
+}
+
+// Returns the addresses of the host, failing if there are none.
+pub(crate) async fn resolve(
+    resolver: Option<&dyn Resolver>,
+    host: &str,
+    port: u16,
+) -> RedisResult<Vec<SocketAddr>> {
+    let addrs: Vec<_> = match resolver {
+        Some(resolver) => resolver.resolve(host, port).await?,
+        None => (host, port).to_socket_addrs()?.collect(),
+    };
+    if addrs.is_empty() {
+        return Err(RedisError::from((
+            ErrorKind::InvalidClientConfig,
+            "No address found for host",
+        )));
+    }
+    Ok(addrs)
+}
@@ -0,0 +1,193 @@
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::OnceLock;
+use std::task::{self, Poll};
+use std::time::Duration;
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use super::{async_trait, AsyncStream, RedisRuntime, Tunnel};
+use crate::happy_eyeballs;
+use crate::tcp::TcpSettings;
+#[cfg(any(feature = "tls", feature = "tls-rustls"))]
+use crate::tls::TlsCertificates;
+use crate::types::{ErrorKind, RedisError, RedisResult};
+
+/// A boxed future returned by an [`AsyncRuntime`].
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// A connection opened by an [`AsyncRuntime`].
+pub type BoxStream = Pin<Box<dyn AsyncStream + Send + Sync>>;
+
+static RUNTIME: OnceLock<Box<dyn AsyncRuntime>> = OnceLock::new();
+
+/// An async runtime for the async connections, registered with
+/// [`set_runtime`].
+///
+/// Besides tokio and async-std, which are built in with the `tokio-comp` and
+/// `async-std-comp` features, the connections can run on any runtime which
+/// spawns tasks, provides timers and opens TCP connections whose streams
+/// implement the `AsyncRead` and `AsyncWrite` traits of tokio.  Only the
+/// `aio` feature is needed then.
+///
+/// The connections opened through a registered runtime do not support TLS
+/// and do not apply the [`TcpSettings`](crate::TcpSettings), as the runtime
+/// owns the sockets.
+///
+/// ```rust,no_run
+/// use std::net::SocketAddr;
+/// use std::time::Duration;
+/// use redis::aio::{AsyncRuntime, BoxFuture, BoxStream};
+///
+/// // Runs the connections on a tokio runtime of their own.
+/// struct Dedicated(tokio::runtime::Handle);
+///
+/// impl AsyncRuntime for Dedicated {
+///     fn spawn(&self, future: BoxFuture<'static, ()>) {
+///         self.0.spawn(future);
+///     }
+///
+///     fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+///         Box::pin(tokio::time::sleep(duration))
+///     }
+///
+///     fn connect_tcp(&self, addr: SocketAddr) -> BoxFuture<'static, std::io::Result<BoxStream>> {
+///         let handle = self.0.clone();
+///         Box::pin(async move {
+///             let stream = handle.spawn(tokio::net::TcpStream::connect(addr)).await??;
+///             Ok(Box::pin(stream) as BoxStream)
+///         })
+///     }
+/// }
+///
+/// let runtime = tokio::runtime::Runtime::new().unwrap();
+/// redis::aio::set_runtime(Dedicated(runtime.handle().clone())).unwrap();
+/// ```
+pub trait AsyncRuntime: Send + Sync + 'static {
+    /// Runs the future in the background.
+    fn spawn(&self, future: BoxFuture<'static, ()>);
+
+    /// Returns a future which completes after `duration`.
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()>;
+
+    /// Opens a TCP connection to the address.
+    fn connect_tcp(&self, addr: SocketAddr) -> BoxFuture<'static, io::Result<BoxStream>>;
+
+    /// Opens a connection to the unix socket at the path.
+    ///
+    /// The default implementation fails, as unix sockets are optional.
+    fn connect_unix(&self, _path: &Path) -> BoxFuture<'static, io::Result<BoxStream>> {
+        Box::pin(async {
+            Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "the async runtime does not support unix sockets",
+            ))
+        })
+    }
+}
+
+/// Registers the runtime on which all async connections run from now on,
+/// instead of tokio or async-std.
+///
+/// A runtime can only be registered once, this fails if there is one
+/// already.
+pub fn set_runtime<R: AsyncRuntime>(runtime: R) -> RedisResult<()> {
+    RUNTIME.set(Box::new(runtime)).map_err(|_| {
+        RedisError::from((
+            ErrorKind::ClientError,
+            "An async runtime is registered already",
+        ))
+    })
+}
+
+pub(crate) fn is_registered() -> bool {
+    RUNTIME.get().is_some()
+}
+
+// The registered runtime, which is only asked for once it was found to be
+// registered.
+pub(crate) fn registered() -> &'static dyn AsyncRuntime {
+    match RUNTIME.get() {
+        Some(runtime) => &**runtime,
+        None => unreachable!("no async runtime is registered"),
+    }
+}
+
+/// A connection opened by the registered runtime.
+pub(crate) struct Custom(BoxStream);
+
+impl AsyncWrite for Custom {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut task::Context,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.0.as_mut().poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut task::Context) -> Poll<io::Result<()>> {
+        self.0.as_mut().poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut task::Context) -> Poll<io::Result<()>> {
+        self.0.as_mut().poll_shutdown(cx)
+    }
+}
+
+impl AsyncRead for Custom {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut task::Context,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        self.0.as_mut().poll_read(cx, buf)
+    }
+}
+
+#[async_trait]
+impl RedisRuntime for Custom {
+    async fn connect_tcp(
+        socket_addrs: Vec<SocketAddr>,
+        tunnel: Option<&Tunnel<'_>>,
+        _tcp_settings: &TcpSettings,
+    ) -> RedisResult<Self> {
+        let runtime = registered();
+        let mut stream =
+            happy_eyeballs::connect_async(socket_addrs, |addr| runtime.connect_tcp(addr)).await?;
+        if let Some(tunnel) = tunnel {
+            tunnel.open_async(&mut stream).await?;
+        }
+        Ok(Custom(stream))
+    }
+
+    #[cfg(any(feature = "tls", feature = "tls-rustls"))]
+    async fn connect_tcp_tls(
+        _hostname: &str,
+        _socket_addrs: Vec<SocketAddr>,
+        _tunnel: Option<&Tunnel<'_>>,
+        _tcp_settings: &TcpSettings,
+        _insecure: bool,
+        _certificates: Option<&TlsCertificates>,
+    ) -> RedisResult<Self> {
+        Err(RedisError::from((
+            ErrorKind::InvalidClientConfig,
+            "Cannot connect to TCP with TLS on a registered async runtime",
+        )))
+    }
+
+    #[cfg(unix)]
+    async fn connect_unix(path: &Path) -> RedisResult<Self> {
+        Ok(Custom(registered().connect_unix(path).await?))
+    }
+
+    fn spawn(f: impl Future<Output = ()> + Send + 'static) {
+        registered().spawn(Box::pin(f));
+    }
+
+    fn boxed(self) -> BoxStream {
+        self.0
+    }
+}
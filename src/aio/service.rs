@@ -0,0 +1,57 @@
+use std::task::{self, Poll};
+
+use tower_service::Service;
+
+use super::MultiplexedConnection;
+use crate::cmd::Cmd;
+use crate::types::{RedisError, RedisFuture, Value};
+
+/// Sends a command, so that tower middleware like timeouts, rate limits or
+/// load shedding can be put in front of the connection.
+///
+/// The connection is always ready, requests are queued on the connection
+/// until it sends them.  Every call sends the command on a clone of the
+/// connection, so the returned future does not borrow the service.
+///
+/// ```rust,no_run
+/// use tower_service::Service;
+///
+/// # async fn do_something() -> redis::RedisResult<()> {
+/// let client = redis::Client::open("redis://127.0.0.1/")?;
+/// let mut con = client.get_multiplexed_async_connection().await?;
+/// let value = con.call(redis::cmd("GET").arg("my_key").clone()).await?;
+/// # Ok(()) }
+/// ```
+#[cfg_attr(docsrs, doc(cfg(feature = "tower")))]
+impl Service<Cmd> for MultiplexedConnection {
+    type Response = Value;
+    type Error = RedisError;
+    type Future = RedisFuture<'static, Value>;
+
+    fn poll_ready(&mut self, _cx: &mut task::Context<'_>) -> Poll<Result<(), RedisError>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, cmd: Cmd) -> Self::Future {
+        let mut con = self.clone();
+        Box::pin(async move { cmd.query_async(&mut con).await })
+    }
+}
+
+/// Sends a pipeline, replying with the values of the commands which are not
+/// ignored, or with the result of the transaction of an atomic pipeline.
+#[cfg_attr(docsrs, doc(cfg(feature = "tower")))]
+impl Service<crate::Pipeline> for MultiplexedConnection {
+    type Response = Value;
+    type Error = RedisError;
+    type Future = RedisFuture<'static, Value>;
+
+    fn poll_ready(&mut self, _cx: &mut task::Context<'_>) -> Poll<Result<(), RedisError>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, pipeline: crate::Pipeline) -> Self::Future {
+        let mut con = self.clone();
+        Box::pin(async move { pipeline.query_async(&mut con).await })
+    }
+}
@@ -1,4 +1,9 @@
-use super::{async_trait, AsyncStream, RedisResult, RedisRuntime, SocketAddr};
+use super::{async_trait, AsyncStream, RedisResult, RedisRuntime, SocketAddr, Tunnel};
+use crate::happy_eyeballs;
+use crate::tcp::TcpSettings;
+
+#[cfg(any(feature = "tls", feature = "tls-rustls"))]
+use super::TlsCertificates;
 
 use std::{
     future::Future,
@@ -15,12 +20,19 @@ use tokio::{
     net::TcpStream as TcpStreamTokio,
 };
 
-#[cfg(feature = "tls")]
-use super::TlsConnector;
+#[cfg(all(feature = "tls", not(feature = "tls-rustls")))]
+use crate::tls::create_native_tls_connector;
 
-#[cfg(feature = "tokio-native-tls-comp")]
+#[cfg(all(feature = "tokio-native-tls-comp", not(feature = "tokio-rustls-comp")))]
 use tokio_native_tls::TlsStream;
 
+#[cfg(feature = "tokio-rustls-comp")]
+use crate::tls::{create_rustls_config, server_name};
+#[cfg(feature = "tokio-rustls-comp")]
+use std::sync::Arc;
+#[cfg(feature = "tokio-rustls-comp")]
+use tokio_rustls::{client::TlsStream, TlsConnector};
+
 #[cfg(unix)]
 use super::Path;
 
@@ -28,8 +40,11 @@ pub(crate) enum Tokio {
     /// Represents a Tokio TCP connection.
     Tcp(TcpStreamTokio),
     /// Represents a Tokio TLS encrypted TCP connection
-    #[cfg(feature = "tokio-native-tls-comp")]
+    #[cfg(all(feature = "tokio-native-tls-comp", not(feature = "tokio-rustls-comp")))]
     TcpTls(TlsStream<TcpStreamTokio>),
+    /// Represents a Tokio TLS encrypted TCP connection backed by rustls
+    #[cfg(feature = "tokio-rustls-comp")]
+    TcpTls(Box<TlsStream<TcpStreamTokio>>),
     /// Represents a Tokio Unix connection.
     #[cfg(unix)]
     Unix(UnixStreamTokio),
@@ -43,7 +58,7 @@ impl AsyncWrite for Tokio {
     ) -> Poll<io::Result<usize>> {
         match &mut *self {
             Tokio::Tcp(r) => Pin::new(r).poll_write(cx, buf),
-            #[cfg(feature = "tokio-native-tls-comp")]
+            #[cfg(any(feature = "tokio-native-tls-comp", feature = "tokio-rustls-comp"))]
             Tokio::TcpTls(r) => Pin::new(r).poll_write(cx, buf),
             #[cfg(unix)]
             Tokio::Unix(r) => Pin::new(r).poll_write(cx, buf),
@@ -53,7 +68,7 @@ impl AsyncWrite for Tokio {
     fn poll_flush(mut self: Pin<&mut Self>, cx: &mut task::Context) -> Poll<io::Result<()>> {
         match &mut *self {
             Tokio::Tcp(r) => Pin::new(r).poll_flush(cx),
-            #[cfg(feature = "tokio-native-tls-comp")]
+            #[cfg(any(feature = "tokio-native-tls-comp", feature = "tokio-rustls-comp"))]
             Tokio::TcpTls(r) => Pin::new(r).poll_flush(cx),
             #[cfg(unix)]
             Tokio::Unix(r) => Pin::new(r).poll_flush(cx),
@@ -63,7 +78,7 @@ impl AsyncWrite for Tokio {
     fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut task::Context) -> Poll<io::Result<()>> {
         match &mut *self {
             Tokio::Tcp(r) => Pin::new(r).poll_shutdown(cx),
-            #[cfg(feature = "tokio-native-tls-comp")]
+            #[cfg(any(feature = "tokio-native-tls-comp", feature = "tokio-rustls-comp"))]
             Tokio::TcpTls(r) => Pin::new(r).poll_shutdown(cx),
             #[cfg(unix)]
             Tokio::Unix(r) => Pin::new(r).poll_shutdown(cx),
@@ -79,7 +94,7 @@ impl AsyncRead for Tokio {
     ) -> Poll<io::Result<()>> {
         match &mut *self {
             Tokio::Tcp(r) => Pin::new(r).poll_read(cx, buf),
-            #[cfg(feature = "tokio-native-tls-comp")]
+            #[cfg(any(feature = "tokio-native-tls-comp", feature = "tokio-rustls-comp"))]
             Tokio::TcpTls(r) => Pin::new(r).poll_read(cx, buf),
             #[cfg(unix)]
             Tokio::Unix(r) => Pin::new(r).poll_read(cx, buf),
@@ -87,36 +102,73 @@ impl AsyncRead for Tokio {
     }
 }
 
+// Connects to the address with the TCP settings, opening the tunnel if there
+// is one.
+async fn connect_tcp_stream(
+    socket_addrs: Vec<SocketAddr>,
+    tunnel: Option<&Tunnel<'_>>,
+    tcp_settings: &TcpSettings,
+) -> RedisResult<TcpStreamTokio> {
+    let mut tcp = happy_eyeballs::connect_async(socket_addrs, TcpStreamTokio::connect).await?;
+    tcp_settings.apply(&tcp)?;
+    if let Some(tunnel) = tunnel {
+        tunnel.open_async(&mut tcp).await?;
+    }
+    Ok(tcp)
+}
+
 #[async_trait]
 impl RedisRuntime for Tokio {
-    async fn connect_tcp(socket_addr: SocketAddr) -> RedisResult<Self> {
-        Ok(TcpStreamTokio::connect(&socket_addr)
+    async fn connect_tcp(
+        socket_addrs: Vec<SocketAddr>,
+        tunnel: Option<&Tunnel<'_>>,
+        tcp_settings: &TcpSettings,
+    ) -> RedisResult<Self> {
+        Ok(connect_tcp_stream(socket_addrs, tunnel, tcp_settings)
             .await
             .map(Tokio::Tcp)?)
     }
 
-    #[cfg(feature = "tls")]
+    #[cfg(all(feature = "tls", not(feature = "tls-rustls")))]
     async fn connect_tcp_tls(
         hostname: &str,
-        socket_addr: SocketAddr,
+        socket_addrs: Vec<SocketAddr>,
+        tunnel: Option<&Tunnel<'_>>,
+        tcp_settings: &TcpSettings,
         insecure: bool,
+        certificates: Option<&TlsCertificates>,
     ) -> RedisResult<Self> {
-        let tls_connector: tokio_native_tls::TlsConnector = if insecure {
-            TlsConnector::builder()
-                .danger_accept_invalid_certs(true)
-                .danger_accept_invalid_hostnames(true)
-                .use_sni(false)
-                .build()?
-        } else {
-            TlsConnector::new()?
-        }
-        .into();
+        let tls_connector: tokio_native_tls::TlsConnector =
+            create_native_tls_connector(insecure, certificates)?.into();
         Ok(tls_connector
-            .connect(hostname, TcpStreamTokio::connect(&socket_addr).await?)
+            .connect(
+                hostname,
+                connect_tcp_stream(socket_addrs, tunnel, tcp_settings).await?,
+            )
             .await
             .map(Tokio::TcpTls)?)
     }
 
+    #[cfg(feature = "tls-rustls")]
+    async fn connect_tcp_tls(
+        hostname: &str,
+        socket_addrs: Vec<SocketAddr>,
+        tunnel: Option<&Tunnel<'_>>,
+        tcp_settings: &TcpSettings,
+        insecure: bool,
+        certificates: Option<&TlsCertificates>,
+    ) -> RedisResult<Self> {
+        let tls_connector =
+            TlsConnector::from(Arc::new(create_rustls_config(insecure, certificates)?));
+        Ok(tls_connector
+            .connect(
+                server_name(hostname)?,
+                connect_tcp_stream(socket_addrs, tunnel, tcp_settings).await?,
+            )
+            .await
+            .map(|con| Tokio::TcpTls(Box::new(con)))?)
+    }
+
     #[cfg(unix)]
     async fn connect_unix(path: &Path) -> RedisResult<Self> {
         Ok(UnixStreamTokio::connect(path).await.map(Tokio::Unix)?)
@@ -135,7 +187,7 @@ impl RedisRuntime for Tokio {
     fn boxed(self) -> Pin<Box<dyn AsyncStream + Send + Sync>> {
         match self {
             Tokio::Tcp(x) => Box::pin(x),
-            #[cfg(feature = "tokio-native-tls-comp")]
+            #[cfg(any(feature = "tokio-native-tls-comp", feature = "tokio-rustls-comp"))]
             Tokio::TcpTls(x) => Box::pin(x),
             #[cfg(unix)]
             Tokio::Unix(x) => Box::pin(x),
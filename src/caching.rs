@@ -0,0 +1,235 @@
+//! Client side caching on top of RESP3 multiplexed connections.
+//!
+//! The server keeps track of the keys a connection reads after
+//! `CLIENT TRACKING` was enabled and pushes an invalidation message when
+//! one of them is modified.  [`invalidations`] turns those messages into a
+//! stream while [`CachedConnection`] keeps an in process cache of `GET`
+//! replies which is invalidated automatically.
+//!
+//! ```rust,no_run
+//! # async fn do_something() -> redis::RedisResult<()> {
+//! use redis::caching::{CachedConnection, TrackingOptions};
+//!
+//! let client = redis::Client::open("redis://127.0.0.1/?protocol=3")?;
+//! let con = client.get_multiplexed_async_connection().await?;
+//! let mut cached = CachedConnection::new(con, TrackingOptions::default()).await?;
+//! let name: Option<String> = cached.get("user:1:name").await?;
+//! # Ok(()) }
+//! ```
+use std::collections::HashMap;
+
+use futures_util::{
+    future::{self, FutureExt},
+    stream::{Stream, StreamExt},
+};
+
+use crate::aio::{BufferConfig, MessageStream, MultiplexedConnection, OverflowPolicy};
+use crate::cmd::cmd;
+use crate::connection::ProtocolVersion;
+use crate::types::{
+    from_redis_value, ErrorKind, FromRedisValue, Invalidation, PushMessage, RedisResult,
+    RedisWrite, ToRedisArgs, Value,
+};
+
+/// Builder options for `CLIENT TRACKING ON`.
+///
+/// By default the server remembers the keys read by the connection.  In
+/// broadcasting mode it instead sends invalidations for every modified key
+/// starting with one of the prefixes, or for every key if there are none.
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct TrackingOptions {
+    broadcast: bool,
+    prefixes: Vec<String>,
+    noloop: bool,
+}
+
+impl TrackingOptions {
+    /// Switches to broadcasting mode (`BCAST`).
+    pub fn broadcast(mut self) -> Self {
+        self.broadcast = true;
+        self
+    }
+
+    /// Adds a key prefix to track in broadcasting mode (`PREFIX`).
+    pub fn prefix<P: Into<String>>(mut self, prefix: P) -> Self {
+        self.prefixes.push(prefix.into());
+        self
+    }
+
+    /// Skips the invalidations of keys modified by this connection
+    /// (`NOLOOP`).
+    pub fn noloop(mut self, noloop: bool) -> Self {
+        self.noloop = noloop;
+        self
+    }
+}
+
+impl ToRedisArgs for TrackingOptions {
+    fn write_redis_args<W>(&self, out: &mut W)
+    where
+        W: ?Sized + RedisWrite,
+    {
+        out.write_arg(b"ON");
+        if self.broadcast {
+            out.write_arg(b"BCAST");
+        }
+        for prefix in &self.prefixes {
+            out.write_arg(b"PREFIX");
+            out.write_arg(prefix.as_bytes());
+        }
+        if self.noloop {
+            out.write_arg(b"NOLOOP");
+        }
+    }
+}
+
+/// Enables `CLIENT TRACKING` on the connection.
+///
+/// The invalidations are pushed on the connection itself, so it must have
+/// negotiated RESP3.
+pub async fn enable_tracking(
+    con: &mut MultiplexedConnection,
+    options: &TrackingOptions,
+) -> RedisResult<()> {
+    if con.protocol() != ProtocolVersion::RESP3 {
+        fail!((
+            ErrorKind::InvalidClientConfig,
+            "Client side caching requires a RESP3 connection"
+        ));
+    }
+    cmd("CLIENT")
+        .arg("TRACKING")
+        .arg(options)
+        .query_async(con)
+        .await
+}
+
+/// Returns a stream of the invalidations received by the connection from
+/// now on.  Other push messages are skipped.
+pub fn invalidations(con: &MultiplexedConnection) -> impl Stream<Item = Invalidation> {
+    con.push_messages()
+        .filter_map(|msg| future::ready(Value::from(msg).into_invalidation().ok()))
+}
+
+/// A multiplexed connection which caches the replies of `GET` until the
+/// server invalidates them.
+///
+/// Invalidations are applied when the cache is accessed, and the whole
+/// cache is dropped if the connection is closed or more invalidations
+/// arrived than could be buffered in the meantime.
+pub struct CachedConnection {
+    con: MultiplexedConnection,
+    pushes: MessageStream<PushMessage>,
+    cache: HashMap<Vec<u8>, Value>,
+}
+
+impl CachedConnection {
+    /// Enables tracking with the given options and wraps the connection.
+    pub async fn new(
+        mut con: MultiplexedConnection,
+        options: TrackingOptions,
+    ) -> RedisResult<CachedConnection> {
+        // A dropped invalidation would leave a stale value in the cache, so
+        // the cache is dropped instead when the buffer overflows.
+        let pushes = con
+            .push_messages_with(BufferConfig::default().overflow(OverflowPolicy::CloseWithError))?;
+        enable_tracking(&mut con, &options).await?;
+        Ok(CachedConnection {
+            con,
+            pushes,
+            cache: HashMap::new(),
+        })
+    }
+
+    /// Gets the value of a key, from the cache if it was not invalidated
+    /// since it was last read.
+    pub async fn get<K: ToRedisArgs, RV: FromRedisValue>(&mut self, key: K) -> RedisResult<RV> {
+        let mut args = key.to_redis_args();
+        if args.len() != 1 {
+            fail!((
+                ErrorKind::ClientError,
+                "Cached keys must be a single argument"
+            ));
+        }
+        let key = args.pop().unwrap();
+
+        self.apply_invalidations();
+        if let Some(value) = self.cache.get(&key) {
+            return from_redis_value(value);
+        }
+        let value: Value = cmd("GET").arg(&key).query_async(&mut self.con).await?;
+        let rv = from_redis_value(&value);
+        self.cache.insert(key, value);
+        rv
+    }
+
+    /// Returns the number of cached keys.
+    pub fn cached_keys(&mut self) -> usize {
+        self.apply_invalidations();
+        self.cache.len()
+    }
+
+    /// Drops every cached value.
+    pub fn clear(&mut self) {
+        self.cache.clear();
+    }
+
+    /// Returns the underlying connection to send commands which are not
+    /// cached.
+    pub fn connection(&mut self) -> &mut MultiplexedConnection {
+        &mut self.con
+    }
+
+    fn apply_invalidations(&mut self) {
+        while let Some(msg) = self.pushes.next().now_or_never() {
+            let msg = match msg {
+                Some(msg) => msg,
+                // the connection is gone or invalidations were dropped, so
+                // the cache cannot be trusted anymore
+                None => {
+                    self.cache.clear();
+                    return;
+                }
+            };
+            match Value::from(msg).into_invalidation() {
+                Ok(Invalidation::Keys(keys)) => {
+                    for key in keys {
+                        self.cache.remove(&key);
+                    }
+                }
+                Ok(Invalidation::FlushAll) => self.cache.clear(),
+                Err(_) => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tracking_options_args() {
+        assert_eq!(
+            TrackingOptions::default().to_redis_args(),
+            vec![b"ON".to_vec()]
+        );
+        assert_eq!(
+            TrackingOptions::default()
+                .broadcast()
+                .prefix("user:")
+                .prefix("item:")
+                .noloop(true)
+                .to_redis_args(),
+            vec![
+                b"ON".to_vec(),
+                b"BCAST".to_vec(),
+                b"PREFIX".to_vec(),
+                b"user:".to_vec(),
+                b"PREFIX".to_vec(),
+                b"item:".to_vec(),
+                b"NOLOOP".to_vec(),
+            ]
+        );
+    }
+}
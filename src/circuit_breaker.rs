@@ -0,0 +1,472 @@
+//! A circuit breaker which sheds the load of an unavailable server.
+//!
+//! [`CircuitBreaker`] wraps a connection and counts the requests which fail
+//! because the server cannot be reached or cannot serve them.  After
+//! `failure_threshold` failures in a row the circuit opens and every request
+//! fails right away with an `ErrorKind::ClientError`.  Once `open_duration`
+//! passed the circuit is half open: the next request first sends a probe
+//! command, `PING` by default, and closes the circuit again if it succeeds
+//! or keeps it open for another `open_duration` if it fails.  The failures
+//! which opened the circuit usually broke the connection too, so the probe
+//! is sent on a new one when a function opening connections was set with
+//! [`CircuitBreaker::reconnect_with`].
+//!
+//! ```rust,no_run
+//! # fn do_something() -> redis::RedisResult<()> {
+//! use std::time::Duration;
+//! use redis::Commands;
+//! use redis::circuit_breaker::{CircuitBreaker, CircuitBreakerConfig};
+//!
+//! let client = redis::Client::open("redis://127.0.0.1/")?;
+//! let config = CircuitBreakerConfig::default()
+//!     .failure_threshold(3)
+//!     .open_duration(Duration::from_secs(5));
+//! let mut con = CircuitBreaker::new(client.get_connection()?, config)
+//!     .reconnect_with(move || client.get_connection());
+//! let value: Option<String> = con.get("my_key")?;
+//! # Ok(()) }
+//! ```
+use std::time::{Duration, Instant};
+
+use crate::cmd::{cmd, Cmd};
+use crate::connection::ConnectionLike;
+use crate::retry::Reconnect;
+#[cfg(feature = "aio")]
+use crate::types::RedisFuture;
+use crate::types::{ErrorKind, RedisError, RedisResult, Value};
+
+/// The configuration of a [`CircuitBreaker`].
+///
+/// By default the circuit opens after 5 failures in a row, stays open for
+/// 10 seconds and is probed with `PING`.
+#[derive(Clone)]
+pub struct CircuitBreakerConfig {
+    failure_threshold: usize,
+    open_duration: Duration,
+    probe: Cmd,
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        CircuitBreakerConfig {
+            failure_threshold: 5,
+            open_duration: Duration::from_secs(10),
+            probe: cmd("PING"),
+        }
+    }
+}
+
+impl CircuitBreakerConfig {
+    /// Sets the number of failures in a row which open the circuit.
+    pub fn failure_threshold(mut self, failures: usize) -> Self {
+        self.failure_threshold = failures;
+        self
+    }
+
+    /// Sets how long the circuit stays open before it is probed.
+    pub fn open_duration(mut self, duration: Duration) -> Self {
+        self.open_duration = duration;
+        self
+    }
+
+    /// Sets the command sent to check whether the server is back.
+    pub fn probe(mut self, probe: Cmd) -> Self {
+        self.probe = probe;
+        self
+    }
+}
+
+/// The state of the circuit of a [`CircuitBreaker`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CircuitState {
+    /// Requests are sent to the server.
+    Closed,
+    /// Requests fail without being sent.
+    Open,
+    /// The next request probes whether the server is back.
+    HalfOpen,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum State {
+    Closed { failures: usize },
+    Open { since: Instant },
+}
+
+/// A connection which stops sending requests to a failing server for a
+/// while, see the [module docs](self).
+pub struct CircuitBreaker<C> {
+    con: C,
+    config: CircuitBreakerConfig,
+    state: State,
+    reconnect: Option<Reconnect<C>>,
+}
+
+// Whether the error means that the server is unavailable, rather than that
+// the request was wrong.
+fn is_failure(err: &RedisError) -> bool {
+    err.is_io_error()
+        || err.is_timeout()
+        || matches!(
+            err.kind(),
+            ErrorKind::BusyLoadingError
+                | ErrorKind::TryAgain
+                | ErrorKind::ClusterDown
+                | ErrorKind::MasterDown
+        )
+}
+
+impl<C> CircuitBreaker<C> {
+    /// Wraps the connection, starting with a closed circuit.
+    pub fn new(con: C, config: CircuitBreakerConfig) -> Self {
+        CircuitBreaker {
+            con,
+            config,
+            state: State::Closed { failures: 0 },
+            reconnect: None,
+        }
+    }
+
+    /// Sets the function opening a new connection, which replaces the
+    /// wrapped one before the probe of a half open circuit.  A failure to
+    /// connect keeps the circuit open like a failed probe.  The async
+    /// connection calls the function as well, but blocks while doing so,
+    /// see [`reconnect_with_async`](Self::reconnect_with_async).
+    pub fn reconnect_with<F>(mut self, connect: F) -> Self
+    where
+        F: FnMut() -> RedisResult<C> + Send + 'static,
+    {
+        self.reconnect = Some(Reconnect::Blocking(Box::new(connect)));
+        self
+    }
+
+    /// Sets the function opening a new connection for the async
+    /// connection, see [`reconnect_with`](Self::reconnect_with).
+    ///
+    /// The blocking connection does not reconnect with it.
+    #[cfg(feature = "aio")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "aio")))]
+    pub fn reconnect_with_async<F>(mut self, connect: F) -> Self
+    where
+        F: FnMut() -> RedisFuture<'static, C> + Send + 'static,
+    {
+        self.reconnect = Some(Reconnect::Async(Box::new(connect)));
+        self
+    }
+
+    /// Returns the current state of the circuit.
+    pub fn state(&self) -> CircuitState {
+        match self.state {
+            State::Closed { .. } => CircuitState::Closed,
+            State::Open { since } if since.elapsed() < self.config.open_duration => {
+                CircuitState::Open
+            }
+            State::Open { .. } => CircuitState::HalfOpen,
+        }
+    }
+
+    /// Returns a reference to the wrapped connection.
+    pub fn get_ref(&self) -> &C {
+        &self.con
+    }
+
+    /// Returns a mutable reference to the wrapped connection.
+    pub fn get_mut(&mut self) -> &mut C {
+        &mut self.con
+    }
+
+    /// Returns the wrapped connection.
+    pub fn into_inner(self) -> C {
+        self.con
+    }
+
+    // Fails if the circuit is open, returns whether a probe is due.
+    fn before_request(&self) -> RedisResult<bool> {
+        match self.state() {
+            CircuitState::Closed => Ok(false),
+            CircuitState::HalfOpen => Ok(true),
+            CircuitState::Open => fail!((ErrorKind::ClientError, "Circuit breaker is open")),
+        }
+    }
+
+    fn record<T>(&mut self, result: &RedisResult<T>) {
+        self.state = match (self.state, result) {
+            (State::Closed { failures }, Err(err)) if is_failure(err) => {
+                if failures + 1 >= self.config.failure_threshold {
+                    State::Open {
+                        since: Instant::now(),
+                    }
+                } else {
+                    State::Closed {
+                        failures: failures + 1,
+                    }
+                }
+            }
+            // a failed probe keeps the circuit open for another period
+            (State::Open { .. }, Err(err)) if is_failure(err) => State::Open {
+                since: Instant::now(),
+            },
+            _ => State::Closed { failures: 0 },
+        };
+    }
+}
+
+impl<C: ConnectionLike> CircuitBreaker<C> {
+    fn call<T, F>(&mut self, send: F) -> RedisResult<T>
+    where
+        F: FnOnce(&mut C) -> RedisResult<T>,
+    {
+        if self.before_request()? {
+            let probe = self
+                .reconnect()
+                .and_then(|()| self.con.req_command(&self.config.probe));
+            self.record(&probe);
+            probe?;
+        }
+        let result = send(&mut self.con);
+        self.record(&result);
+        result
+    }
+
+    fn reconnect(&mut self) -> RedisResult<()> {
+        if let Some(Reconnect::Blocking(connect)) = &mut self.reconnect {
+            self.con = connect()?;
+        }
+        Ok(())
+    }
+}
+
+impl<C: ConnectionLike> ConnectionLike for CircuitBreaker<C> {
+    fn req_packed_command(&mut self, cmd: &[u8]) -> RedisResult<Value> {
+        self.call(|con| con.req_packed_command(cmd))
+    }
+
+    fn req_packed_commands(
+        &mut self,
+        cmd: &[u8],
+        offset: usize,
+        count: usize,
+    ) -> RedisResult<Vec<Value>> {
+        self.call(|con| con.req_packed_commands(cmd, offset, count))
+    }
+
+    fn req_packed_commands_vectored(
+        &mut self,
+        cmds: &mut [&[u8]],
+        offset: usize,
+        count: usize,
+    ) -> RedisResult<Vec<Value>> {
+        self.call(|con| con.req_packed_commands_vectored(cmds, offset, count))
+    }
+
+    fn req_command(&mut self, cmd: &Cmd) -> RedisResult<Value> {
+        self.call(|con| con.req_command(cmd))
+    }
+
+    fn get_db(&self) -> i64 {
+        self.con.get_db()
+    }
+
+    fn supports_pipelining(&self) -> bool {
+        self.con.supports_pipelining()
+    }
+
+    fn check_connection(&mut self) -> bool {
+        self.con.check_connection()
+    }
+
+    fn is_open(&self) -> bool {
+        self.con.is_open()
+    }
+}
+
+#[cfg(feature = "aio")]
+impl<C: crate::aio::ConnectionLike + Send> CircuitBreaker<C> {
+    async fn probe_async(&mut self) -> RedisResult<()> {
+        if self.before_request()? {
+            let probe = match self.reconnect_async().await {
+                Ok(()) => self.con.req_packed_command(&self.config.probe).await,
+                Err(err) => Err(err),
+            };
+            self.record(&probe);
+            probe?;
+        }
+        Ok(())
+    }
+
+    async fn reconnect_async(&mut self) -> RedisResult<()> {
+        match &mut self.reconnect {
+            Some(Reconnect::Blocking(connect)) => self.con = connect()?,
+            Some(Reconnect::Async(connect)) => self.con = connect().await?,
+            None => {}
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "aio")]
+impl<C: crate::aio::ConnectionLike + Send> crate::aio::ConnectionLike for CircuitBreaker<C> {
+    fn req_packed_command<'a>(&'a mut self, cmd: &'a Cmd) -> crate::RedisFuture<'a, Value> {
+        Box::pin(async move {
+            self.probe_async().await?;
+            let result = self.con.req_packed_command(cmd).await;
+            self.record(&result);
+            result
+        })
+    }
+
+    fn req_packed_commands<'a>(
+        &'a mut self,
+        cmd: &'a crate::Pipeline,
+        offset: usize,
+        count: usize,
+    ) -> crate::RedisFuture<'a, Vec<Value>> {
+        Box::pin(async move {
+            self.probe_async().await?;
+            let result = self.con.req_packed_commands(cmd, offset, count).await;
+            self.record(&result);
+            result
+        })
+    }
+
+    fn get_db(&self) -> i64 {
+        self.con.get_db()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+    use std::thread;
+
+    // Fails every request while `down` is set.
+    struct Server {
+        down: bool,
+        requests: Vec<Vec<u8>>,
+    }
+
+    impl ConnectionLike for Server {
+        fn req_packed_command(&mut self, cmd: &[u8]) -> RedisResult<Value> {
+            self.requests.push(cmd.to_vec());
+            if self.down {
+                Err(io::Error::from(io::ErrorKind::ConnectionRefused).into())
+            } else {
+                Ok(Value::Okay)
+            }
+        }
+
+        fn req_packed_commands(
+            &mut self,
+            _cmd: &[u8],
+            _offset: usize,
+            _count: usize,
+        ) -> RedisResult<Vec<Value>> {
+            unimplemented!()
+        }
+
+        fn get_db(&self) -> i64 {
+            0
+        }
+
+        fn check_connection(&mut self) -> bool {
+            !self.down
+        }
+
+        fn is_open(&self) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn test_circuit_breaker() {
+        let server = Server {
+            down: true,
+            requests: vec![],
+        };
+        let config = CircuitBreakerConfig::default()
+            .failure_threshold(2)
+            .open_duration(Duration::from_millis(20));
+        let mut con = CircuitBreaker::new(server, config);
+        let get = cmd("GET").arg("key").clone();
+
+        assert!(get.query::<()>(&mut con).unwrap_err().is_io_error());
+        assert_eq!(con.state(), CircuitState::Closed);
+        assert!(get.query::<()>(&mut con).unwrap_err().is_io_error());
+        assert_eq!(con.state(), CircuitState::Open);
+
+        // fails without reaching the server
+        let err = get.query::<()>(&mut con).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ClientError);
+        assert_eq!(con.get_ref().requests.len(), 2);
+
+        // the probe fails and keeps the circuit open
+        thread::sleep(Duration::from_millis(30));
+        assert_eq!(con.state(), CircuitState::HalfOpen);
+        assert!(get.query::<()>(&mut con).unwrap_err().is_io_error());
+        assert_eq!(con.get_ref().requests.len(), 3);
+        assert_eq!(con.get_ref().requests[2], cmd("PING").get_packed_command());
+        assert_eq!(con.state(), CircuitState::Open);
+
+        // the probe succeeds and closes it
+        con.get_mut().down = false;
+        thread::sleep(Duration::from_millis(30));
+        assert_eq!(get.query::<()>(&mut con), Ok(()));
+        assert_eq!(con.get_ref().requests.len(), 5);
+        assert_eq!(con.state(), CircuitState::Closed);
+    }
+
+    #[test]
+    fn test_reconnect_before_probe() {
+        let server = Server {
+            down: true,
+            requests: vec![],
+        };
+        let config = CircuitBreakerConfig::default()
+            .failure_threshold(1)
+            .open_duration(Duration::from_millis(20));
+        let mut available = false;
+        let mut con = CircuitBreaker::new(server, config).reconnect_with(move || {
+            if !available {
+                available = true;
+                return Err(io::Error::from(io::ErrorKind::ConnectionRefused).into());
+            }
+            Ok(Server {
+                down: false,
+                requests: vec![],
+            })
+        });
+        let get = cmd("GET").arg("key").clone();
+        assert!(get.query::<()>(&mut con).unwrap_err().is_io_error());
+        assert_eq!(con.state(), CircuitState::Open);
+
+        // connecting fails and keeps the circuit open
+        thread::sleep(Duration::from_millis(30));
+        assert!(get.query::<()>(&mut con).unwrap_err().is_io_error());
+        assert_eq!(con.get_ref().requests.len(), 1);
+        assert_eq!(con.state(), CircuitState::Open);
+
+        // the probe is sent on the new connection
+        thread::sleep(Duration::from_millis(30));
+        assert_eq!(get.query::<()>(&mut con), Ok(()));
+        assert_eq!(
+            con.get_ref().requests,
+            [cmd("PING").get_packed_command(), get.get_packed_command()]
+        );
+        assert_eq!(con.state(), CircuitState::Closed);
+    }
+
+    #[test]
+    fn test_request_errors_do_not_open() {
+        let server = Server {
+            down: false,
+            requests: vec![],
+        };
+        let config = CircuitBreakerConfig::default().failure_threshold(1);
+        let mut con = CircuitBreaker::new(server, config);
+        con.record::<()>(&Err(RedisError::from((ErrorKind::TypeError, "Wrong"))));
+        assert_eq!(con.state(), CircuitState::Closed);
+        con.record::<()>(&Err(RedisError::from((ErrorKind::MasterDown, "Down"))));
+        assert_eq!(con.state(), CircuitState::Open);
+    }
+}
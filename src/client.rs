@@ -1,17 +1,57 @@
+use std::fmt;
+use std::net::TcpStream;
 use std::time::Duration;
 
+#[cfg(feature = "aio")]
+use std::borrow::Cow;
 #[cfg(feature = "aio")]
 use std::pin::Pin;
+use std::sync::Arc;
 
 use crate::{
-    connection::{connect, Connection, ConnectionInfo, ConnectionLike, IntoConnectionInfo},
-    types::{RedisResult, Value},
+    cmd::Cmd,
+    connection::{
+        connect, connect_with_tcp_stream, Connection, ConnectionInfo, ConnectionLike,
+        IntoConnectionInfo,
+    },
+    instrumentation::MetricsObserver,
+    tls::TlsCertificates,
+    types::{FromRedisValue, RedisResult, Value},
 };
 
+#[cfg(feature = "script")]
+use crate::types::ToRedisArgs;
+
+/// The timeout applied to connecting and to awaiting the response by the
+/// one-shot helpers.
+pub const ONE_SHOT_TIMEOUT: Duration = Duration::from_secs(10);
+
 /// The client type.
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct Client {
     connection_info: ConnectionInfo,
+    #[cfg(feature = "aio")]
+    credentials_provider: Option<Arc<dyn crate::aio::CredentialsProvider>>,
+    #[cfg(feature = "aio")]
+    resolver: Option<Arc<dyn crate::aio::Resolver>>,
+    #[cfg(feature = "aio")]
+    srv_name: Option<String>,
+    metrics_observer: Option<Arc<dyn MetricsObserver>>,
+}
+
+impl fmt::Debug for Client {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut client = f.debug_struct("Client");
+        client.field("connection_info", &self.connection_info);
+        #[cfg(feature = "aio")]
+        client.field("credentials_provider", &self.credentials_provider.is_some());
+        #[cfg(feature = "aio")]
+        client.field("resolver", &self.resolver.is_some());
+        #[cfg(feature = "aio")]
+        client.field("srv_name", &self.srv_name);
+        client.field("metrics_observer", &self.metrics_observer.is_some());
+        client.finish()
+    }
 }
 
 /// The client acts as connector to the redis server.  By itself it does not
@@ -35,9 +75,55 @@ impl Client {
     /// actually open a connection yet but it does perform some basic
     /// checks on the URL that might make the operation fail.
     pub fn open<T: IntoConnectionInfo>(params: T) -> RedisResult<Client> {
-        Ok(Client {
-            connection_info: params.into_connection_info()?,
-        })
+        Ok(Client::new(params.into_connection_info()?))
+    }
+
+    /// Like `open` but connects with the given TLS certificates, to present
+    /// a client certificate or to trust a custom certificate authority.
+    ///
+    /// The parameters must describe a `rediss://` address.
+    ///
+    /// ```rust,no_run
+    /// # fn do_something() -> redis::RedisResult<()> {
+    /// let certificates = redis::TlsCertificates::new().root_cert(std::fs::read("ca.crt")?);
+    /// let client = redis::Client::build_with_tls("rediss://127.0.0.1/", certificates)?;
+    /// let con = client.get_connection()?;
+    /// # Ok(()) }
+    /// ```
+    pub fn build_with_tls<T: IntoConnectionInfo>(
+        params: T,
+        certificates: TlsCertificates,
+    ) -> RedisResult<Client> {
+        let mut connection_info = params.into_connection_info()?;
+        certificates.apply(&mut connection_info)?;
+        Ok(Client::new(connection_info))
+    }
+
+    fn new(connection_info: ConnectionInfo) -> Client {
+        Client {
+            connection_info,
+            #[cfg(feature = "aio")]
+            credentials_provider: None,
+            #[cfg(feature = "aio")]
+            resolver: None,
+            #[cfg(feature = "aio")]
+            srv_name: None,
+            metrics_observer: None,
+        }
+    }
+
+    /// Sets the observer which is told about the commands and the traffic of
+    /// the connections of the client, see the
+    /// [`instrumentation`](crate::instrumentation) module.
+    ///
+    /// It is used by `Connection`, `MultiplexedConnection` and
+    /// `ConnectionManager`, the latter also reporting its reconnects.
+    pub fn with_metrics_observer<O>(mut self, observer: O) -> Client
+    where
+        O: MetricsObserver + 'static,
+    {
+        self.metrics_observer = Some(Arc::new(observer));
+        self
     }
 
     /// Instructs the client to actually connect to redis and returns a
@@ -46,7 +132,9 @@ impl Client {
     /// (like unreachable host) so it's important that you handle those
     /// errors.
     pub fn get_connection(&self) -> RedisResult<Connection> {
-        connect(&self.connection_info, None)
+        let mut con = connect(&self.connection_info, None)?;
+        con.set_metrics_observer(self.metrics_observer.clone());
+        Ok(con)
     }
 
     /// Instructs the client to actually connect to redis with specified
@@ -55,13 +143,139 @@ impl Client {
     /// a variety of errors (like unreachable host) so it's important
     /// that you handle those errors.
     pub fn get_connection_with_timeout(&self, timeout: Duration) -> RedisResult<Connection> {
-        connect(&self.connection_info, Some(timeout))
+        let mut con = connect(&self.connection_info, Some(timeout))?;
+        con.set_metrics_observer(self.metrics_observer.clone());
+        Ok(con)
+    }
+
+    /// Sets up a connection over an already connected TCP stream instead of
+    /// connecting to the address of the connection info, whose other
+    /// parameters like the database and the credentials still apply.
+    ///
+    /// This is how connections are made on WASI runtimes which hand out
+    /// preopened sockets rather than letting the module connect itself.
+    ///
+    /// ```rust,no_run
+    /// # fn do_something() -> redis::RedisResult<()> {
+    /// let client = redis::Client::open("redis://127.0.0.1/")?;
+    /// let stream = std::net::TcpStream::connect("127.0.0.1:6379")?;
+    /// let mut con = client.get_connection_with_stream(stream)?;
+    /// # Ok(()) }
+    /// ```
+    pub fn get_connection_with_stream(&self, stream: TcpStream) -> RedisResult<Connection> {
+        let mut con = connect_with_tcp_stream(stream, &self.connection_info)?;
+        con.set_metrics_observer(self.metrics_observer.clone());
+        Ok(con)
     }
 
     /// Returns a reference of client connection info object.
     pub fn get_connection_info(&self) -> &ConnectionInfo {
         &self.connection_info
     }
+
+    /// Connects, evaluates a lua script once and disconnects again.
+    ///
+    /// The script is invoked through `EVALSHA` and uploaded if the server
+    /// does not know it yet, just like `Script::invoke`.  Connecting and
+    /// awaiting the response are bounded by `ONE_SHOT_TIMEOUT`.
+    ///
+    /// ```rust,no_run
+    /// # fn do_something() -> redis::RedisResult<()> {
+    /// let client = redis::Client::open("redis://127.0.0.1/")?;
+    /// let counter: i64 = client.eval_once(
+    ///     "return redis.call('INCRBY', KEYS[1], ARGV[1])",
+    ///     "my_counter",
+    ///     5,
+    /// )?;
+    /// # Ok(()) }
+    /// ```
+    #[cfg(feature = "script")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "script")))]
+    pub fn eval_once<T, K, A>(&self, script: &str, keys: K, args: A) -> RedisResult<T>
+    where
+        T: FromRedisValue,
+        K: ToRedisArgs,
+        A: ToRedisArgs,
+    {
+        let mut con = self.get_one_shot_connection(ONE_SHOT_TIMEOUT)?;
+        crate::Script::new(script)
+            .prepare_invoke()
+            .key(keys)
+            .arg(args)
+            .invoke(&mut con)
+    }
+
+    fn get_one_shot_connection(&self, timeout: Duration) -> RedisResult<Connection> {
+        let con = self.get_connection_with_timeout(timeout)?;
+        con.set_read_timeout(Some(timeout))?;
+        con.set_write_timeout(Some(timeout))?;
+        Ok(con)
+    }
+}
+
+/// Connects to a redis server, runs a single command and disconnects again.
+///
+/// This is the quickest way to talk to redis from small tools and scripts
+/// which do not need to keep a connection around.  Connecting and awaiting
+/// the response are bounded by `ONE_SHOT_TIMEOUT`.
+///
+/// ```rust,no_run
+/// # fn do_something() -> redis::RedisResult<()> {
+/// let value: Option<String> = redis::one_shot(
+///     "redis://127.0.0.1/",
+///     redis::cmd("GET").arg("my_key"),
+/// )?;
+/// # Ok(()) }
+/// ```
+pub fn one_shot<T, I>(params: I, cmd: &Cmd) -> RedisResult<T>
+where
+    T: FromRedisValue,
+    I: IntoConnectionInfo,
+{
+    one_shot_with_timeout(params, cmd, ONE_SHOT_TIMEOUT)
+}
+
+/// Like `one_shot` but with a custom timeout for connecting and for
+/// awaiting the response.
+pub fn one_shot_with_timeout<T, I>(params: I, cmd: &Cmd, timeout: Duration) -> RedisResult<T>
+where
+    T: FromRedisValue,
+    I: IntoConnectionInfo,
+{
+    let mut con = Client::open(params)?.get_one_shot_connection(timeout)?;
+    cmd.query(&mut con)
+}
+
+/// Async version of `one_shot`.
+#[cfg(feature = "aio")]
+#[cfg_attr(docsrs, doc(cfg(feature = "aio")))]
+pub async fn one_shot_async<T, I>(params: I, cmd: &Cmd) -> RedisResult<T>
+where
+    T: FromRedisValue,
+    I: IntoConnectionInfo,
+{
+    one_shot_with_timeout_async(params, cmd, ONE_SHOT_TIMEOUT).await
+}
+
+/// Async version of `one_shot_with_timeout`.
+#[cfg(feature = "aio")]
+#[cfg_attr(docsrs, doc(cfg(feature = "aio")))]
+pub async fn one_shot_with_timeout_async<T, I>(
+    params: I,
+    cmd: &Cmd,
+    timeout: Duration,
+) -> RedisResult<T>
+where
+    T: FromRedisValue,
+    I: IntoConnectionInfo,
+{
+    let client = Client::open(params)?;
+    Runtime::locate()
+        .timeout(timeout, async {
+            let mut con = client.get_async_connection().await?;
+            cmd.query_async(&mut con).await
+        })
+        .await?
 }
 
 /// To enable async support you need to chose one of the supported runtimes and active its
@@ -69,9 +283,43 @@ impl Client {
 #[cfg(feature = "aio")]
 #[cfg_attr(docsrs, doc(cfg(feature = "aio")))]
 impl Client {
+    /// Sets the provider which is asked for the credentials of every new
+    /// async connection, instead of using the ones of the connection info.
+    /// See [`CredentialsProvider`](crate::aio::CredentialsProvider) for
+    /// credentials which expire.
+    pub fn with_credentials_provider<P>(mut self, provider: P) -> Client
+    where
+        P: crate::aio::CredentialsProvider + 'static,
+    {
+        self.credentials_provider = Some(Arc::new(provider));
+        self
+    }
+
+    /// Sets the resolver which looks up the hostnames of the async
+    /// connections, instead of the blocking `ToSocketAddrs`.
+    pub fn with_resolver<R>(mut self, resolver: R) -> Client
+    where
+        R: crate::aio::Resolver + 'static,
+    {
+        self.resolver = Some(Arc::new(resolver));
+        self
+    }
+
+    /// Discovers the server of the async connections with the SRV records of
+    /// `name`, like `_redis._tcp.example.com`, looked up by the resolver set
+    /// with `with_resolver`.
+    ///
+    /// The targets of the records are tried in the order the resolver returns
+    /// them until one accepts the connection.  They replace the host and the
+    /// port of the connection info, its TLS settings are kept.
+    pub fn with_srv_discovery<T: Into<String>>(mut self, name: T) -> Client {
+        self.srv_name = Some(name.into());
+        self
+    }
+
     /// Returns an async connection from the client.
-    #[cfg(any(feature = "tokio-comp", feature = "async-std-comp"))]
     pub async fn get_async_connection(&self) -> RedisResult<crate::aio::Connection> {
+        let (info, _) = self.async_connection_info().await?;
         let con = match Runtime::locate() {
             #[cfg(feature = "tokio-comp")]
             Runtime::Tokio => {
@@ -83,9 +331,13 @@ impl Client {
                 self.get_simple_async_connection::<crate::aio::async_std::AsyncStd>()
                     .await?
             }
+            Runtime::Custom => {
+                self.get_simple_async_connection::<crate::aio::Custom>()
+                    .await?
+            }
         };
 
-        crate::aio::Connection::new(&self.connection_info.redis, con).await
+        crate::aio::Connection::new(&info.redis, con).await
     }
 
     /// Returns an async connection from the client.
@@ -93,11 +345,11 @@ impl Client {
     #[cfg_attr(docsrs, doc(cfg(feature = "tokio-comp")))]
     pub async fn get_tokio_connection(&self) -> RedisResult<crate::aio::Connection> {
         use crate::aio::RedisRuntime;
-        Ok(
-            crate::aio::connect::<crate::aio::tokio::Tokio>(&self.connection_info)
-                .await?
-                .map(RedisRuntime::boxed),
-        )
+        let (info, _) = self.async_connection_info().await?;
+        let con = self.connect_stream::<crate::aio::tokio::Tokio>().await?;
+        Ok(crate::aio::Connection::new(&info.redis, con)
+            .await?
+            .map(RedisRuntime::boxed))
     }
 
     /// Returns an async connection from the client.
@@ -105,19 +357,16 @@ impl Client {
     #[cfg_attr(docsrs, doc(cfg(feature = "async-std-comp")))]
     pub async fn get_async_std_connection(&self) -> RedisResult<crate::aio::Connection> {
         use crate::aio::RedisRuntime;
-        Ok(
-            crate::aio::connect::<crate::aio::async_std::AsyncStd>(&self.connection_info)
-                .await?
-                .map(RedisRuntime::boxed),
-        )
+        let (info, _) = self.async_connection_info().await?;
+        let con = self
+            .connect_stream::<crate::aio::async_std::AsyncStd>()
+            .await?;
+        Ok(crate::aio::Connection::new(&info.redis, con)
+            .await?
+            .map(RedisRuntime::boxed))
     }
 
     /// Returns an async connection from the client.
-    #[cfg(any(feature = "tokio-comp", feature = "async-std-comp"))]
-    #[cfg_attr(
-        docsrs,
-        doc(cfg(any(feature = "tokio-comp", feature = "async-std-comp")))
-    )]
     pub async fn get_multiplexed_async_connection(
         &self,
     ) -> RedisResult<crate::aio::MultiplexedConnection> {
@@ -126,6 +375,10 @@ impl Client {
             Runtime::Tokio => self.get_multiplexed_tokio_connection().await,
             #[cfg(feature = "async-std-comp")]
             Runtime::AsyncStd => self.get_multiplexed_async_std_connection().await,
+            Runtime::Custom => {
+                self.get_multiplexed_async_connection_inner::<crate::aio::Custom>()
+                    .await
+            }
         }
     }
 
@@ -189,6 +442,46 @@ impl Client {
             .await
     }
 
+    /// Returns an async multiplexed connection over the given stream instead
+    /// of connecting to the address of the connection info.
+    ///
+    /// This allows to tunnel the connection over SSH or a custom proxy, or to
+    /// use an in-memory duplex stream in tests.  The stream has to be
+    /// connected already, including a TLS handshake if one is needed, while
+    /// the database, the credentials and the protocol of the connection info
+    /// are applied as usual.
+    ///
+    /// ```rust,no_run
+    /// # async fn do_something() -> redis::RedisResult<()> {
+    /// let client = redis::Client::open("redis://127.0.0.1/")?;
+    /// let stream = tokio::net::TcpStream::connect("127.0.0.1:6379").await?;
+    /// let mut con = client.connect_with_stream(stream).await?;
+    /// # Ok(()) }
+    /// ```
+    pub async fn connect_with_stream<S>(
+        &self,
+        stream: S,
+    ) -> RedisResult<crate::aio::MultiplexedConnection>
+    where
+        S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send + 'static,
+    {
+        let (info, valid_for) = self.async_connection_info().await?;
+        let (connection, driver) = crate::aio::MultiplexedConnection::new_with_observer(
+            &info.redis,
+            stream,
+            BufferPolicy::default(),
+            self.metrics_observer.clone(),
+        )
+        .await?;
+        let connection = connection.with_peer(&info.addr);
+        let runtime = Runtime::locate();
+        runtime.spawn(driver);
+        if let Some(refresh) = self.credentials_refresh(&connection, valid_for) {
+            runtime.spawn(refresh);
+        }
+        Ok(connection)
+    }
+
     /// Returns an async [`ConnectionManager`][connection-manager] from the client.
     ///
     /// The connection manager wraps a
@@ -212,19 +505,60 @@ impl Client {
         Ok(crate::aio::ConnectionManager::new(self.clone()).await?)
     }
 
+    /// Returns an async [`ConnectionManager`][connection-manager] from the
+    /// client which reconnects as configured by `config`.
+    ///
+    /// [connection-manager]: aio/struct.ConnectionManager.html
+    #[cfg(feature = "connection-manager")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "connection-manager")))]
+    pub async fn get_tokio_connection_manager_with_config(
+        &self,
+        config: crate::aio::ConnectionManagerConfig,
+    ) -> RedisResult<crate::aio::ConnectionManager> {
+        crate::aio::ConnectionManager::new_with_config(self.clone(), config).await
+    }
+
     async fn get_multiplexed_async_connection_inner<T>(
         &self,
     ) -> RedisResult<crate::aio::MultiplexedConnection>
     where
         T: crate::aio::RedisRuntime,
     {
-        let (connection, driver) = self
-            .create_multiplexed_async_connection_inner::<T>()
-            .await?;
+        let (info, valid_for) = self.async_connection_info().await?;
+        let con = self.connect_stream::<T>().await?.boxed();
+        let (connection, driver) = crate::aio::MultiplexedConnection::new_with_observer(
+            &info.redis,
+            con,
+            BufferPolicy::default(),
+            self.metrics_observer.clone(),
+        )
+        .await?;
+        let connection = connection.with_peer(&info.addr);
         T::spawn(driver);
+        if let Some(refresh) = self.credentials_refresh(&connection, valid_for) {
+            T::spawn(refresh);
+        }
         Ok(connection)
     }
 
+    // The task which refreshes the credentials of the connection before they
+    // expire, if they do.
+    fn credentials_refresh(
+        &self,
+        connection: &crate::aio::MultiplexedConnection,
+        valid_for: Option<Duration>,
+    ) -> Option<impl std::future::Future<Output = ()>> {
+        match (&self.credentials_provider, valid_for) {
+            (Some(provider), Some(valid_for)) => Some(crate::aio::refresh_credentials(
+                connection,
+                provider.clone(),
+                valid_for,
+            )),
+            _ => None,
+        }
+    }
+
+    #[cfg(any(feature = "tokio-comp", feature = "async-std-comp"))]
     async fn create_multiplexed_async_connection_inner<T>(
         &self,
     ) -> RedisResult<(
@@ -234,8 +568,17 @@ impl Client {
     where
         T: crate::aio::RedisRuntime,
     {
+        let (info, _) = self.async_connection_info().await?;
         let con = self.get_simple_async_connection::<T>().await?;
-        crate::aio::MultiplexedConnection::new(&self.connection_info.redis, con).await
+        let (connection, driver) = crate::aio::MultiplexedConnection::new_with_observer(
+            &info.redis,
+            con,
+            BufferPolicy::default(),
+            self.metrics_observer.clone(),
+        )
+        .await?;
+        let connection = connection.with_peer(&info.addr);
+        Ok((connection, driver))
     }
 
     async fn get_simple_async_connection<T>(
@@ -244,19 +587,90 @@ impl Client {
     where
         T: crate::aio::RedisRuntime,
     {
-        Ok(crate::aio::connect_simple::<T>(&self.connection_info)
-            .await?
-            .boxed())
+        Ok(self.connect_stream::<T>().await?.boxed())
+    }
+
+    // Connects to the address of the connection info, or to the first target
+    // of the SRV records which accepts the connection.
+    async fn connect_stream<T>(&self) -> RedisResult<T>
+    where
+        T: crate::aio::RedisRuntime,
+    {
+        let resolver = self.resolver.as_deref();
+        let (name, resolver) = match (&self.srv_name, resolver) {
+            (None, _) => {
+                return crate::aio::connect_simple::<T>(&self.connection_info, resolver).await
+            }
+            (Some(name), Some(resolver)) => (name, resolver),
+            (Some(_), None) => fail!((
+                ErrorKind::InvalidClientConfig,
+                "SRV discovery requires a resolver"
+            )),
+        };
+        let mut last_error = None;
+        for (host, port) in resolver.resolve_srv(name).await? {
+            let mut info = self.connection_info.clone();
+            info.addr = match info.addr {
+                ConnectionAddr::Tcp(..) => ConnectionAddr::Tcp(host, port),
+                ConnectionAddr::TcpTls {
+                    insecure,
+                    certificates,
+                    ..
+                } => ConnectionAddr::TcpTls {
+                    host,
+                    port,
+                    insecure,
+                    certificates,
+                },
+                ConnectionAddr::Unix(_) => fail!((
+                    ErrorKind::InvalidClientConfig,
+                    "Cannot discover unix sockets with SRV records"
+                )),
+            };
+            match crate::aio::connect_simple::<T>(&info, Some(resolver)).await {
+                Ok(con) => return Ok(con),
+                Err(err) => last_error = Some(err),
+            }
+        }
+        Err(last_error.unwrap_or_else(|| {
+            RedisError::from((ErrorKind::InvalidClientConfig, "No SRV records found"))
+        }))
+    }
+
+    // The connection info with the credentials of the provider, if there is
+    // one, and how long they are valid.
+    async fn async_connection_info(
+        &self,
+    ) -> RedisResult<(Cow<'_, ConnectionInfo>, Option<Duration>)> {
+        match self.credentials_provider {
+            Some(ref provider) => {
+                let credentials = provider.credentials().await?;
+                let mut info = self.connection_info.clone();
+                credentials.apply(&mut info.redis);
+                Ok((Cow::Owned(info), credentials.valid_for))
+            }
+            None => Ok((Cow::Borrowed(&self.connection_info), None)),
+        }
     }
 
     #[cfg(feature = "connection-manager")]
     pub(crate) fn connection_info(&self) -> &ConnectionInfo {
         &self.connection_info
     }
+
+    #[cfg(feature = "connection-manager")]
+    pub(crate) fn metrics_observer(&self) -> Option<&dyn MetricsObserver> {
+        self.metrics_observer.as_deref()
+    }
 }
 
 #[cfg(feature = "aio")]
-use crate::aio::Runtime;
+use crate::{
+    aio::Runtime,
+    connection::ConnectionAddr,
+    parser::BufferPolicy,
+    types::{ErrorKind, RedisError},
+};
 
 impl ConnectionLike for Client {
     fn req_packed_command(&mut self, cmd: &[u8]) -> RedisResult<Value> {
@@ -21,6 +21,9 @@
 //! assert_eq!(rv, "test_data");
 //! ```
 //!
+//! `MGET`, `MSET`, `DEL`, `UNLINK`, `EXISTS` and `TOUCH` may be given keys
+//! of different slots, they are split by slot and their replies are merged.
+//!
 //! # Pipelining
 //! ```rust,no_run
 //! use redis::Commands;
@@ -38,11 +41,59 @@
 //!     .expire(key, 60).ignore()
 //!     .query(&mut connection).unwrap();
 //! ```
-use std::cell::RefCell;
-use std::collections::{BTreeMap, HashMap, HashSet};
-use std::iter::Iterator;
+//!
+//! A regular [`Pipeline`](crate::Pipeline) works as well.  Its commands are
+//! split by the nodes serving them like those of a `ClusterPipeline`, and
+//! an atomic pipeline is sent to the node serving its first key.
+//!
+//! # Pub/Sub
+//! `ClusterClient::get_pubsub` returns a [`ClusterPubSub`], which also
+//! supports the shard channels of Redis 7 and keeps the subscriptions when
+//! nodes fail over.
+//!
+//! # Reading from replicas
+//! Read-only commands like `GET` can be spread over the replicas, see
+//! [`ReadRouting`].  The connections to the replicas are put into `READONLY`
+//! mode, and all other commands keep going to the masters.
+//! ```rust,no_run
+//! use redis::cluster::{ClusterClientBuilder, ReadRouting};
+//!
+//! let nodes = vec!["redis://127.0.0.1:6379/", "redis://127.0.0.1:6378/", "redis://127.0.0.1:6377/"];
+//! let client = ClusterClientBuilder::new(nodes)
+//!     .read_routing(ReadRouting::RoundRobinReplicas)
+//!     .open()
+//!     .unwrap();
+//! let mut connection = client.get_connection().unwrap();
+//! ```
+//!
+//! # Inspecting the topology
+//! The replies of `CLUSTER SHARDS`, `CLUSTER SLOTS` and `CLUSTER NODES` can
+//! be read as [`ClusterShard`]s, [`ClusterSlots`] and a
+//! [`ClusterNodesReply`], for instance to build operator tooling.
+//!
+//! # Scanning
+//! `SCAN` only sees the keys of the node it is sent to, use
+//! `ClusterConnection::scan_cluster` to iterate the keys of every master.
+//! ```rust,no_run
+//! use redis::ScanOptions;
+//! use redis::cluster::ClusterClient;
+//!
+//! let nodes = vec!["redis://127.0.0.1:6379/", "redis://127.0.0.1:6378/", "redis://127.0.0.1:6377/"];
+//! let client = ClusterClient::open(nodes).unwrap();
+//! let mut connection = client.get_connection().unwrap();
+//!
+//! let options = ScanOptions::default().pattern("session:*");
+//! for key in connection.scan_cluster::<String>(options).unwrap() {
+//!     println!("{}", key.unwrap());
+//! }
+//! ```
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::iter::{self, Iterator};
+use std::sync::Arc;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
+use std::vec;
 
 use rand::{
     seq::{IteratorRandom, SliceRandom},
@@ -50,16 +101,31 @@ use rand::{
 };
 
 use super::{
-    cmd, parse_redis_value, Cmd, Connection, ConnectionAddr, ConnectionInfo, ConnectionLike,
-    ErrorKind, IntoConnectionInfo, RedisError, RedisResult, Value,
+    cmd, from_redis_value, parse_redis_value, Cmd, Connection, ConnectionAddr, ConnectionEvent,
+    ConnectionInfo, ConnectionLike, ErrorKind, FromRedisValue, IntoConnectionInfo, RedisError,
+    RedisResult, ScanOptions, Value,
 };
 
 pub use crate::cluster_client::{ClusterClient, ClusterClientBuilder};
+use crate::cluster_client::{NodeAddressMapper, NodeSettings, TopologyRefresh};
 use crate::cluster_pipeline::UNROUTABLE_ERROR;
 pub use crate::cluster_pipeline::{cluster_pipe, ClusterPipeline};
-use crate::cluster_routing::{Routable, RoutingInfo, Slot, SLOT_SIZE};
+pub use crate::cluster_pubsub::ClusterPubSub;
+pub use crate::cluster_routing::{hash_tag, key_slot, ReadRouting};
+use crate::cluster_routing::{MultiKeySplit, Routable, RoutingInfo, Slot, SLOT_SIZE};
+pub use crate::cluster_topology::{
+    ClusterNode, ClusterNodesReply, ClusterShard, ClusterSlots, NodeHealth, NodeRole, ShardNode,
+    SlotNode, SlotRange,
+};
+use crate::connection::{report_connected, ConnectionEventHandler};
+use crate::instrumentation::MetricsObserver;
+use crate::parser::unpack_commands;
+use crate::tls::TlsCertificates;
+
+type SlotMap = BTreeMap<u16, Slot>;
 
-type SlotMap = BTreeMap<u16, String>;
+// How often the scan of a node is retried after it became unreachable.
+const SCAN_RETRIES: usize = 5;
 
 /// This is a connection of Redis cluster.
 pub struct ClusterConnection {
@@ -67,11 +133,20 @@ pub struct ClusterConnection {
     connections: RefCell<HashMap<String, Connection>>,
     slots: RefCell<SlotMap>,
     auto_reconnect: RefCell<bool>,
+    read_routing: ReadRouting,
+    // whether the connections are put into READONLY mode for the replicas
     readonly: bool,
-    password: Option<String>,
-    read_timeout: RefCell<Option<Duration>>,
-    write_timeout: RefCell<Option<Duration>>,
+    // how long the nodes took to answer PING at the last slot refresh
+    latencies: RefCell<HashMap<String, Duration>>,
+    next_replica: Cell<usize>,
+    topology_refresh: TopologyRefresh,
+    last_refresh: Cell<Instant>,
+    settings: RefCell<NodeSettings>,
     tls: Option<TlsMode>,
+    tls_certificates: Option<TlsCertificates>,
+    node_address_mapper: Option<NodeAddressMapper>,
+    on_event: Option<ConnectionEventHandler>,
+    metrics: Option<Arc<dyn MetricsObserver>>,
 }
 
 #[derive(Clone, Copy)]
@@ -93,21 +168,34 @@ impl TlsMode {
 impl ClusterConnection {
     pub(crate) fn new(
         initial_nodes: Vec<ConnectionInfo>,
-        readonly: bool,
-        password: Option<String>,
+        read_routing: ReadRouting,
+        topology_refresh: TopologyRefresh,
+        settings: NodeSettings,
+        node_address_mapper: Option<NodeAddressMapper>,
+        on_event: Option<ConnectionEventHandler>,
+        metrics: Option<Arc<dyn MetricsObserver>>,
     ) -> RedisResult<ClusterConnection> {
-        let connections =
-            Self::create_initial_connections(&initial_nodes, readonly, password.clone())?;
+        let readonly = read_routing != ReadRouting::PreferMaster;
+        let connections = Self::create_initial_connections(
+            &initial_nodes,
+            readonly,
+            &settings,
+            on_event.as_ref(),
+            metrics.as_ref(),
+        )?;
 
         let connection = ClusterConnection {
             connections: RefCell::new(connections),
             slots: RefCell::new(SlotMap::new()),
             auto_reconnect: RefCell::new(true),
+            read_routing,
             readonly,
-            password,
-            read_timeout: RefCell::new(None),
-            write_timeout: RefCell::new(None),
-            #[cfg(feature = "tls")]
+            latencies: RefCell::new(HashMap::new()),
+            next_replica: Cell::new(0),
+            topology_refresh,
+            last_refresh: Cell::new(Instant::now()),
+            settings: RefCell::new(settings),
+            #[cfg(any(feature = "tls", feature = "tls-rustls"))]
             tls: {
                 if initial_nodes.is_empty() {
                     None
@@ -115,17 +203,22 @@ impl ClusterConnection {
                     // TODO: Maybe should run through whole list and make sure they're all matching?
                     match &initial_nodes.get(0).unwrap().addr {
                         ConnectionAddr::Tcp(_, _) => None,
-                        ConnectionAddr::TcpTls {
-                            host: _,
-                            port: _,
-                            insecure,
-                        } => Some(TlsMode::from_insecure_flag(*insecure)),
+                        ConnectionAddr::TcpTls { insecure, .. } => {
+                            Some(TlsMode::from_insecure_flag(*insecure))
+                        }
                         _ => None,
                     }
                 }
             },
-            #[cfg(not(feature = "tls"))]
+            #[cfg(not(any(feature = "tls", feature = "tls-rustls")))]
             tls: None,
+            tls_certificates: match initial_nodes.first().map(|info| &info.addr) {
+                Some(ConnectionAddr::TcpTls { certificates, .. }) => certificates.clone(),
+                _ => None,
+            },
+            node_address_mapper,
+            on_event,
+            metrics,
             initial_nodes,
         };
         connection.refresh_slots()?;
@@ -146,8 +239,7 @@ impl ClusterConnection {
     /// block indefinitely. It is an error to pass the zero `Duration` to this
     /// method.
     pub fn set_write_timeout(&self, dur: Option<Duration>) -> RedisResult<()> {
-        let mut t = self.write_timeout.borrow_mut();
-        *t = dur;
+        self.settings.borrow_mut().write_timeout = dur;
         let connections = self.connections.borrow();
         for conn in connections.values() {
             conn.set_write_timeout(dur)?;
@@ -161,8 +253,7 @@ impl ClusterConnection {
     /// block indefinitely. It is an error to pass the zero `Duration` to this
     /// method.
     pub fn set_read_timeout(&self, dur: Option<Duration>) -> RedisResult<()> {
-        let mut t = self.read_timeout.borrow_mut();
-        *t = dur;
+        self.settings.borrow_mut().read_timeout = dur;
         let connections = self.connections.borrow();
         for conn in connections.values() {
             conn.set_read_timeout(dur)?;
@@ -181,6 +272,38 @@ impl ClusterConnection {
         true
     }
 
+    /// Incrementally iterates the keys of all master nodes with `SCAN`.
+    ///
+    /// The nodes are scanned one after the other, each with its own cursor.
+    /// When a node becomes unreachable the slots are refreshed and its scan
+    /// is resumed, or dropped if it is no longer a master, in which case the
+    /// masters which took over its slots are scanned as well.  Like `SCAN`
+    /// itself, keys may be returned more than once, and keys moving to a node
+    /// which was already scanned completely can be missed.
+    pub fn scan_cluster<T: FromRedisValue>(
+        &mut self,
+        options: ScanOptions,
+    ) -> RedisResult<ClusterScanIter<'_, T>> {
+        self.scan_shared(options)
+    }
+
+    // `scan_cluster` leaving the connection usable for other commands while
+    // the scan goes on.
+    pub(crate) fn scan_shared<T: FromRedisValue>(
+        &self,
+        options: ScanOptions,
+    ) -> RedisResult<ClusterScanIter<'_, T>> {
+        let masters = self.master_addresses()?;
+        Ok(ClusterScanIter {
+            con: self,
+            options,
+            pending: masters.iter().map(|addr| (addr.clone(), 0)).collect(),
+            seen: masters,
+            batch: Vec::new().into_iter(),
+            retries: 0,
+        })
+    }
+
     pub(crate) fn execute_pipeline(&mut self, pipe: &ClusterPipeline) -> RedisResult<Vec<Value>> {
         self.send_recv_and_retry_cmds(pipe.commands())
     }
@@ -195,25 +318,29 @@ impl ClusterConnection {
     fn create_initial_connections(
         initial_nodes: &[ConnectionInfo],
         readonly: bool,
-        password: Option<String>,
+        settings: &NodeSettings,
+        on_event: Option<&ConnectionEventHandler>,
+        metrics: Option<&Arc<dyn MetricsObserver>>,
     ) -> RedisResult<HashMap<String, Connection>> {
         let mut connections = HashMap::with_capacity(initial_nodes.len());
 
         for info in initial_nodes.iter() {
             let addr = match info.addr {
-                ConnectionAddr::Tcp(ref host, port) => format!("redis://{}:{}", host, port),
+                ConnectionAddr::Tcp(ref host, port) => build_connection_string(host, port, None),
                 ConnectionAddr::TcpTls {
                     ref host,
                     port,
                     insecure,
+                    ..
                 } => {
                     let tls_mode = TlsMode::from_insecure_flag(insecure);
-                    build_connection_string(host, Some(port), Some(tls_mode))
+                    build_connection_string(host, port, Some(tls_mode))
                 }
                 _ => panic!("No reach."),
             };
 
-            if let Ok(mut conn) = connect(info.clone(), readonly, password.clone()) {
+            if let Ok(mut conn) = connect(info.clone(), readonly, settings, None, on_event, metrics)
+            {
                 if conn.check_connection() {
                     connections.insert(addr, conn);
                     break;
@@ -230,44 +357,70 @@ impl ClusterConnection {
         Ok(connections)
     }
 
+    // Refreshes the slots on a redirect, unless they were refreshed very
+    // recently, so a storm of MOVED errors during a resharding does not
+    // refresh them for every command.
+    fn refresh_slots_debounced(&self) -> RedisResult<()> {
+        if self.last_refresh.get().elapsed() < self.topology_refresh.min_interval {
+            return Ok(());
+        }
+        self.refresh_slots()
+    }
+
+    // Refreshes the slots if the refresh interval has passed.  A failure is
+    // left to the command to run into.
+    fn refresh_slots_if_due(&self) {
+        if let Some(interval) = self.topology_refresh.interval {
+            if self.last_refresh.get().elapsed() >= interval {
+                let _ = self.refresh_slots();
+            }
+        }
+    }
+
     // Query a node to discover slot-> master mappings.
-    fn refresh_slots(&self) -> RedisResult<()> {
+    pub(crate) fn refresh_slots(&self) -> RedisResult<()> {
+        self.last_refresh.set(Instant::now());
         let mut slots = self.slots.borrow_mut();
-        *slots = if self.readonly {
-            let mut rng = thread_rng();
-            self.create_new_slots(|slot_data| {
-                let replicas = slot_data.replicas();
-                if replicas.is_empty() {
-                    slot_data.master().to_string()
-                } else {
-                    replicas.choose(&mut rng).unwrap().to_string()
-                }
-            })?
-        } else {
-            self.create_new_slots(|slot_data| slot_data.master().to_string())?
-        };
+        *slots = self.create_new_slots()?;
+
+        // The replicas are only needed if reads may be sent to them.
+        let addrs = slots.values().flat_map(|slot| {
+            let replicas = if self.readonly {
+                &slot.replicas()[..]
+            } else {
+                &[]
+            };
+            iter::once(slot.master()).chain(replicas.iter().map(String::as_str))
+        });
 
+        let mut latencies = self.latencies.borrow_mut();
+        latencies.clear();
         let mut connections = self.connections.borrow_mut();
         *connections = {
             // Remove dead connections and connect to new nodes if necessary
             let mut new_connections = HashMap::with_capacity(connections.len());
 
-            for addr in slots.values() {
+            for addr in addrs {
                 if !new_connections.contains_key(addr) {
                     if connections.contains_key(addr) {
                         let mut conn = connections.remove(addr).unwrap();
-                        if conn.check_connection() {
+                        if let Some(latency) = ping(&mut conn) {
+                            latencies.insert(addr.to_string(), latency);
                             new_connections.insert(addr.to_string(), conn);
                             continue;
                         }
                     }
 
-                    if let Ok(mut conn) =
-                        connect(addr.as_ref(), self.readonly, self.password.clone())
-                    {
-                        if conn.check_connection() {
-                            conn.set_read_timeout(*self.read_timeout.borrow())?;
-                            conn.set_write_timeout(*self.write_timeout.borrow())?;
+                    if let Ok(mut conn) = connect(
+                        addr,
+                        self.readonly,
+                        &self.settings.borrow(),
+                        self.tls_certificates.as_ref(),
+                        self.on_event.as_ref(),
+                        self.metrics.as_ref(),
+                    ) {
+                        if let Some(latency) = ping(&mut conn) {
+                            latencies.insert(addr.to_string(), latency);
                             new_connections.insert(addr.to_string(), conn);
                         }
                     }
@@ -279,10 +432,16 @@ impl ClusterConnection {
         Ok(())
     }
 
-    fn create_new_slots<F>(&self, mut get_addr: F) -> RedisResult<SlotMap>
-    where
-        F: FnMut(&Slot) -> String,
-    {
+    // The addresses of the masters serving the slots.
+    fn master_addresses(&self) -> RedisResult<HashSet<String>> {
+        let slots = self.create_new_slots()?;
+        Ok(slots
+            .into_values()
+            .map(|slot| slot.master().to_string())
+            .collect())
+    }
+
+    fn create_new_slots(&self) -> RedisResult<SlotMap> {
         let mut connections = self.connections.borrow_mut();
         let mut new_slots = None;
         let mut rng = thread_rng();
@@ -290,7 +449,9 @@ impl ClusterConnection {
         let mut samples = connections.values_mut().choose_multiple(&mut rng, len);
 
         for mut conn in samples.iter_mut() {
-            if let Ok(mut slots_data) = get_slots(&mut conn, self.tls) {
+            if let Ok(mut slots_data) =
+                get_slots(&mut conn, self.tls, self.node_address_mapper.as_ref())
+            {
                 slots_data.sort_by_key(|s| s.start());
                 let last_slot = slots_data.iter().try_fold(0, |prev_end, slot_data| {
                     if prev_end != slot_data.start() {
@@ -318,8 +479,8 @@ impl ClusterConnection {
 
                 new_slots = Some(
                     slots_data
-                        .iter()
-                        .map(|slot_data| (slot_data.end(), get_addr(slot_data)))
+                        .into_iter()
+                        .map(|slot_data| (slot_data.end(), slot_data))
                         .collect(),
                 );
                 break;
@@ -336,17 +497,75 @@ impl ClusterConnection {
         }
     }
 
+    // The address of the node serving the slot which a command is sent to,
+    // a replica for reads depending on the read routing.
+    fn node_for_slot(&self, slot: u16, read: bool) -> Option<String> {
+        let slots = self.slots.borrow();
+        let (_, slot) = slots.range(&slot..).next()?;
+        let replicas = slot.replicas();
+        let addr = match self.read_routing {
+            _ if !read || replicas.is_empty() => slot.master(),
+            ReadRouting::PreferMaster => slot.master(),
+            ReadRouting::PreferReplica => replicas.choose(&mut thread_rng()).unwrap(),
+            ReadRouting::RoundRobinReplicas => {
+                let next = self.next_replica.get();
+                self.next_replica.set(next.wrapping_add(1));
+                &replicas[next % replicas.len()]
+            }
+            ReadRouting::LowestLatency => {
+                let latencies = self.latencies.borrow();
+                iter::once(slot.master())
+                    .chain(replicas.iter().map(String::as_str))
+                    .min_by_key(|addr| latencies.get(*addr).copied().unwrap_or(Duration::MAX))
+                    .unwrap()
+            }
+        };
+        Some(addr.to_string())
+    }
+
+    // The address of the master serving the slot of the key.
+    pub(crate) fn master_for_key(&self, key: &[u8]) -> Option<String> {
+        match RoutingInfo::for_key(key)? {
+            RoutingInfo::Slot(slot) => self.node_for_slot(slot, false),
+            _ => None,
+        }
+    }
+
+    // The address of a random master.
+    pub(crate) fn random_master(&self) -> Option<String> {
+        let slots = self.slots.borrow();
+        slots
+            .values()
+            .choose(&mut thread_rng())
+            .map(|slot| slot.master().to_string())
+    }
+
+    // Opens a separate connection to the node.
+    pub(crate) fn connect_node(&self, addr: &str) -> RedisResult<Connection> {
+        let conn = connect(
+            addr,
+            false,
+            &self.settings.borrow(),
+            self.tls_certificates.as_ref(),
+            self.on_event.as_ref(),
+            self.metrics.as_ref(),
+        )?;
+        Ok(conn)
+    }
+
     fn get_connection<'a>(
         &self,
         connections: &'a mut HashMap<String, Connection>,
         slot: u16,
+        read: bool,
     ) -> RedisResult<(String, &'a mut Connection)> {
-        let slots = self.slots.borrow();
-        if let Some((_, addr)) = slots.range(&slot..).next() {
-            Ok((
-                addr.to_string(),
-                self.get_connection_by_addr(connections, addr)?,
-            ))
+        if let Some(mut addr) = self.node_for_slot(slot, read) {
+            // Reads fall back to the master if the replica is unreachable.
+            if read && self.get_connection_by_addr(connections, &addr).is_err() {
+                addr = self.node_for_slot(slot, false).unwrap();
+            }
+            let conn = self.get_connection_by_addr(connections, &addr)?;
+            Ok((addr, conn))
         } else {
             // try a random node next.  This is safe if slots are involved
             // as a wrong node would reject the request.
@@ -364,27 +583,54 @@ impl ClusterConnection {
         } else {
             // Create new connection.
             // TODO: error handling
-            let conn = connect(addr, self.readonly, self.password.clone())?;
+            let conn = connect(
+                addr,
+                self.readonly,
+                &self.settings.borrow(),
+                self.tls_certificates.as_ref(),
+                self.on_event.as_ref(),
+                self.metrics.as_ref(),
+            )?;
             Ok(connections.entry(addr.to_string()).or_insert(conn))
         }
     }
 
-    fn execute_on_all_nodes<T, F>(&self, mut func: F) -> RedisResult<T>
+    fn execute_on_all_nodes<T, F>(&self, mut func: F, masters_only: bool) -> RedisResult<T>
     where
         T: MergeResults,
         F: FnMut(&mut Connection) -> RedisResult<T>,
     {
+        let masters: HashSet<String> = self
+            .slots
+            .borrow()
+            .values()
+            .map(|slot| slot.master().to_string())
+            .collect();
         let mut connections = self.connections.borrow_mut();
         let mut results = HashMap::new();
 
         // TODO: reconnect and shit
         for (addr, connection) in connections.iter_mut() {
+            if masters_only && !masters.contains(addr) {
+                continue;
+            }
             results.insert(addr.as_str(), func(connection)?);
         }
 
         Ok(T::merge_results(results))
     }
 
+    // Builds the connection string for a node learned from a MOVED/ASK redirect.
+    fn redirect_connection_string(&self, node: &str) -> Option<String> {
+        let (host, port) = split_node_address(node)?;
+        Some(node_connection_string(
+            host,
+            port,
+            self.node_address_mapper.as_ref(),
+            self.tls,
+        ))
+    }
+
     #[allow(clippy::unnecessary_unwrap)]
     fn request<R, T, F>(&self, cmd: &R, mut func: F) -> RedisResult<T>
     where
@@ -395,11 +641,12 @@ impl ClusterConnection {
         let slot = match RoutingInfo::for_routable(cmd) {
             Some(RoutingInfo::Random) => None,
             Some(RoutingInfo::Slot(slot)) => Some(slot),
-            Some(RoutingInfo::AllNodes) | Some(RoutingInfo::AllMasters) => {
-                return self.execute_on_all_nodes(func);
-            }
+            Some(RoutingInfo::AllNodes) => return self.execute_on_all_nodes(func, false),
+            Some(RoutingInfo::AllMasters) => return self.execute_on_all_nodes(func, true),
             None => fail!(UNROUTABLE_ERROR),
         };
+        let read = cmd.is_readonly();
+        self.refresh_slots_if_due();
 
         let mut retries = 16;
         let mut excludes = HashSet::new();
@@ -422,7 +669,7 @@ impl ClusterConnection {
                 } else if !excludes.is_empty() || slot.is_none() {
                     get_random_connection(&mut *connections, Some(&excludes))
                 } else {
-                    self.get_connection(&mut *connections, slot.unwrap())?
+                    self.get_connection(&mut *connections, slot.unwrap(), read)?
                 };
                 (addr, func(conn))
             };
@@ -441,37 +688,52 @@ impl ClusterConnection {
                         if kind == ErrorKind::Ask {
                             redirected = err
                                 .redirect_node()
-                                .map(|(node, _slot)| build_connection_string(node, None, self.tls));
-                            is_asking = true;
+                                .and_then(|(node, _slot)| self.redirect_connection_string(node));
+                            // The slot is being migrated, only this command is
+                            // sent to the node the key moved to, the slot map
+                            // stays as it is.  A redirect without a usable node
+                            // falls back to the slot routing like any other
+                            // retry.
+                            if redirected.is_some() {
+                                is_asking = true;
+                                continue;
+                            }
                         } else if kind == ErrorKind::Moved {
                             // Refresh slots.
-                            self.refresh_slots()?;
+                            self.refresh_slots_debounced()?;
                             excludes.clear();
 
                             // Request again.
                             redirected = err
                                 .redirect_node()
-                                .map(|(node, _slot)| build_connection_string(node, None, self.tls));
+                                .and_then(|(node, _slot)| self.redirect_connection_string(node));
                             is_asking = false;
                             continue;
                         } else if kind == ErrorKind::TryAgain || kind == ErrorKind::ClusterDown {
                             // Sleep and retry.
                             let sleep_time = 2u64.pow(16 - retries.max(9)) * 10;
                             thread::sleep(Duration::from_millis(sleep_time));
+                            if kind == ErrorKind::ClusterDown {
+                                // a failover may have moved the slots
+                                self.refresh_slots_debounced()?;
+                            }
                             excludes.clear();
                             continue;
                         }
                     } else if *self.auto_reconnect.borrow() && err.is_io_error() {
-                        let new_connections = Self::create_initial_connections(
-                            &self.initial_nodes,
-                            self.readonly,
-                            self.password.clone(),
-                        )?;
-                        {
-                            let mut connections = self.connections.borrow_mut();
-                            *connections = new_connections;
+                        let addr = event_addr(&addr);
+                        self.report(|| ConnectionEvent::Reconnecting { addr: addr.clone() });
+                        if let Err(err) = self.reconnect() {
+                            self.report(|| ConnectionEvent::GaveUp {
+                                addr,
+                                error: err.clone_mostly("Reconnecting failed"),
+                            });
+                            return Err(err);
+                        }
+                        if let Some(ref observer) = self.metrics {
+                            observer.reconnected();
                         }
-                        self.refresh_slots()?;
+                        self.report(|| ConnectionEvent::Reconnected { addr });
                         excludes.clear();
                         continue;
                     } else {
@@ -489,13 +751,50 @@ impl ClusterConnection {
         }
     }
 
+    // Connects to the cluster from the initial nodes again.
+    fn reconnect(&self) -> RedisResult<()> {
+        let new_connections = Self::create_initial_connections(
+            &self.initial_nodes,
+            self.readonly,
+            &self.settings.borrow(),
+            self.on_event.as_ref(),
+            self.metrics.as_ref(),
+        )?;
+        {
+            let mut connections = self.connections.borrow_mut();
+            *connections = new_connections;
+        }
+        self.refresh_slots()
+    }
+
+    fn report<F: FnOnce() -> ConnectionEvent>(&self, event: F) {
+        if let Some(ref handler) = self.on_event {
+            handler(&event());
+        }
+    }
+
+    // Runs the command.  The multi key commands `MGET`, `MSET`, `DEL`,
+    // `UNLINK`, `EXISTS` and `TOUCH` with keys of different slots are split
+    // into a command per slot, which run as one pipeline per node, so they
+    // do not fail with CROSSSLOT.  Split that way, `MSET` is not atomic.
+    pub(crate) fn execute_command(&self, cmd: &Cmd) -> RedisResult<Value> {
+        match MultiKeySplit::new(cmd) {
+            Some(split) => {
+                let replies = self.send_recv_and_retry_cmds(&split.cmds)?;
+                split.merge(replies)
+            }
+            None => self.request(cmd, move |conn| conn.req_command(cmd)),
+        }
+    }
+
     fn send_recv_and_retry_cmds(&self, cmds: &[Cmd]) -> RedisResult<Vec<Value>> {
         // Vector to hold the results, pre-populated with `Nil` values. This allows the original
         // cmd ordering to be re-established by inserting the response directly into the result
         // vector (e.g., results[10] = response).
         let mut results = vec![Value::Nil; cmds.len()];
+        self.refresh_slots_if_due();
 
-        let to_retry = self
+        let (to_retry, moved) = self
             .send_all_commands(cmds)
             .and_then(|node_cmds| self.recv_all_commands(&mut results, &node_cmds))?;
 
@@ -503,8 +802,11 @@ impl ClusterConnection {
             return Ok(results);
         }
 
-        // Refresh the slots to ensure that we have a clean slate for the retry attempts.
-        self.refresh_slots()?;
+        // Refresh the slots to ensure that we have a clean slate for the retry attempts,
+        // unless the commands were only redirected by ASK for the slots being migrated.
+        if moved {
+            self.refresh_slots_debounced()?;
+        }
 
         // Given that there are commands that need to be retried, it means something in the cluster
         // topology changed. Execute each command seperately to take advantage of the existing
@@ -529,14 +831,12 @@ impl ClusterConnection {
     }
 
     fn get_addr_for_cmd(&self, cmd: &Cmd) -> RedisResult<String> {
-        let slots = self.slots.borrow();
-
+        let read = cmd.is_readonly();
         let addr_for_slot = |slot: u16| -> RedisResult<String> {
-            let (_, addr) = slots
-                .range(&slot..)
-                .next()
-                .ok_or((ErrorKind::ClusterDown, "Missing slot coverage"))?;
-            Ok(addr.to_string())
+            match self.node_for_slot(slot, read) {
+                Some(addr) => Ok(addr),
+                None => fail!((ErrorKind::ClusterDown, "Missing slot coverage")),
+            }
         };
 
         match RoutingInfo::for_routable(cmd) {
@@ -568,13 +868,15 @@ impl ClusterConnection {
         Ok(result)
     }
 
-    // Receive from each node, keeping track of which commands need to be retried.
+    // Receive from each node, keeping track of which commands need to be retried
+    // and whether any other error than ASK asks for them.
     fn recv_all_commands(
         &self,
         results: &mut Vec<Value>,
         node_cmds: &[NodeCmd],
-    ) -> RedisResult<Vec<usize>> {
+    ) -> RedisResult<(Vec<usize>, bool)> {
         let mut to_retry = Vec::new();
+        let mut moved = false;
         let mut connections = self.connections.borrow_mut();
         let mut first_err = None;
 
@@ -585,14 +887,17 @@ impl ClusterConnection {
                     .recv_response()
                 {
                     Ok(item) => results[*cmd_idx] = item,
-                    Err(err) if err.is_cluster_error() => to_retry.push(*cmd_idx),
+                    Err(err) if err.is_cluster_error() => {
+                        moved |= err.kind() != ErrorKind::Ask;
+                        to_retry.push(*cmd_idx);
+                    }
                     Err(err) => first_err = first_err.or(Some(err)),
                 }
             }
         }
         match first_err {
             Some(err) => Err(err),
-            None => Ok(to_retry),
+            None => Ok((to_retry, moved)),
         }
     }
 }
@@ -640,13 +945,95 @@ impl NodeCmd {
     }
 }
 
+/// Iterator over the keys of all masters of a cluster, returned by
+/// `ClusterConnection::scan_cluster`.
+pub struct ClusterScanIter<'a, T> {
+    con: &'a ClusterConnection,
+    options: ScanOptions,
+    // the nodes left to scan with the cursor to continue from
+    pending: VecDeque<(String, u64)>,
+    // every master scheduled so far, so a refresh only adds new ones
+    seen: HashSet<String>,
+    batch: vec::IntoIter<T>,
+    retries: usize,
+}
+
+impl<'a, T: FromRedisValue> ClusterScanIter<'a, T> {
+    fn scan_node(&self, addr: &str, cursor: u64) -> RedisResult<Value> {
+        let mut connections = self.con.connections.borrow_mut();
+        let conn = self.con.get_connection_by_addr(&mut connections, addr)?;
+        conn.req_command(cmd("SCAN").arg(cursor).arg(&self.options))
+    }
+
+    // Picks up the new topology after a node failed, so the scan can go on.
+    fn recover(&mut self, err: RedisError) -> RedisResult<()> {
+        self.retries += 1;
+        if !err.is_io_error() || !*self.con.auto_reconnect.borrow() || self.retries > SCAN_RETRIES {
+            return Err(err);
+        }
+        thread::sleep(Duration::from_millis(100 * self.retries as u64));
+
+        self.con.refresh_slots()?;
+        let masters = self.con.master_addresses()?;
+        // The slots of a node which is no longer a master were taken over by
+        // one of the others, which are scanned instead.
+        self.pending.retain(|(addr, _)| masters.contains(addr));
+        for addr in masters {
+            if self.seen.insert(addr.clone()) {
+                self.pending.push_back((addr, 0));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'a, T: FromRedisValue> Iterator for ClusterScanIter<'a, T> {
+    type Item = RedisResult<T>;
+
+    fn next(&mut self) -> Option<RedisResult<T>> {
+        loop {
+            if let Some(item) = self.batch.next() {
+                return Some(Ok(item));
+            }
+
+            let (addr, cursor) = self.pending.pop_front()?;
+            match self.scan_node(&addr, cursor) {
+                Ok(value) => {
+                    self.retries = 0;
+                    let (cursor, batch): (u64, Vec<T>) = match from_redis_value(&value) {
+                        Ok(rv) => rv,
+                        Err(err) => {
+                            self.pending.clear();
+                            return Some(Err(err));
+                        }
+                    };
+                    if cursor != 0 {
+                        self.pending.push_front((addr, cursor));
+                    }
+                    self.batch = batch.into_iter();
+                }
+                Err(err) => {
+                    self.pending.push_front((addr, cursor));
+                    if let Err(err) = self.recover(err) {
+                        self.pending.clear();
+                        return Some(Err(err));
+                    }
+                }
+            }
+        }
+    }
+}
+
 impl ConnectionLike for ClusterConnection {
-    fn supports_pipelining(&self) -> bool {
-        false
+    #[cfg(not(feature = "tracing"))]
+    fn req_command(&mut self, cmd: &Cmd) -> RedisResult<Value> {
+        self.execute_command(cmd)
     }
 
+    #[cfg(feature = "tracing")]
     fn req_command(&mut self, cmd: &Cmd) -> RedisResult<Value> {
-        self.request(cmd, move |conn| conn.req_command(cmd))
+        let span = crate::instrument::cluster_command_span(cmd);
+        crate::instrument::traced(span, || self.execute_command(cmd))
     }
 
     fn req_packed_command(&mut self, cmd: &[u8]) -> RedisResult<Value> {
@@ -660,10 +1047,35 @@ impl ConnectionLike for ClusterConnection {
         offset: usize,
         count: usize,
     ) -> RedisResult<Vec<Value>> {
-        let value = parse_redis_value(cmd)?;
-        self.request(&value, move |conn| {
-            conn.req_packed_commands(cmd, offset, count)
-        })
+        let cmds: Vec<Cmd> = unpack_commands(cmd)?
+            .into_iter()
+            .map(|args| {
+                let mut cmd = Cmd::new();
+                for arg in args {
+                    cmd.arg(arg);
+                }
+                cmd
+            })
+            .collect();
+        if offset == 0 {
+            // A plain pipeline is split into a pipeline for each node, and
+            // the results are put back into the order of the commands.
+            let mut results = self.send_recv_and_retry_cmds(&cmds)?;
+            results.truncate(count);
+            return Ok(results);
+        }
+
+        // A transaction has to run on the node serving the slot of its keys.
+        let routable = cmds
+            .iter()
+            .find(|cmd| matches!(RoutingInfo::for_routable(*cmd), Some(RoutingInfo::Slot(_))))
+            .or_else(|| cmds.first());
+        match routable {
+            Some(routable) => self.request(routable, move |conn| {
+                conn.req_packed_commands(cmd, offset, count)
+            }),
+            None => fail!((ErrorKind::ClientError, "Empty pipeline")),
+        }
     }
 
     fn get_db(&self) -> i64 {
@@ -694,22 +1106,63 @@ impl ConnectionLike for ClusterConnection {
 fn connect<T: IntoConnectionInfo>(
     info: T,
     readonly: bool,
-    password: Option<String>,
+    settings: &NodeSettings,
+    tls_certificates: Option<&TlsCertificates>,
+    on_event: Option<&ConnectionEventHandler>,
+    metrics: Option<&Arc<dyn MetricsObserver>>,
 ) -> RedisResult<Connection>
 where
     T: std::fmt::Debug,
 {
     let mut connection_info = info.into_connection_info()?;
-    connection_info.redis.password = password;
+    connection_info.redis.username = settings.username.clone();
+    connection_info.redis.password = settings.password.clone();
+    connection_info.tcp_settings = settings.tcp_settings;
+    // node addresses are learned as URLs which cannot carry the certificates
+    if let ConnectionAddr::TcpTls {
+        ref mut certificates,
+        ..
+    } = connection_info.addr
+    {
+        if certificates.is_none() {
+            *certificates = tls_certificates.cloned();
+        }
+    }
     let client = super::Client::open(connection_info)?;
 
-    let mut con = client.get_connection()?;
+    let mut con = match settings.connection_timeout {
+        Some(timeout) => client.get_connection_with_timeout(timeout)?,
+        None => client.get_connection()?,
+    };
+    con.set_read_timeout(settings.read_timeout)?;
+    con.set_write_timeout(settings.write_timeout)?;
+    con.set_metrics_observer(metrics.cloned());
+    if let Some(handler) = on_event {
+        let info = client.get_connection_info();
+        report_connected(handler, &info.addr, &info.redis);
+    }
     if readonly {
         cmd("READONLY").query(&mut con)?;
     }
     Ok(con)
 }
 
+// PINGs the node, returning how long the answer took.
+fn ping(conn: &mut Connection) -> Option<Duration> {
+    let start = Instant::now();
+    if conn.check_connection() {
+        Some(start.elapsed())
+    } else {
+        None
+    }
+}
+
+// The `host:port` of the node a connection is stored under.
+fn event_addr(node: &str) -> String {
+    node.into_connection_info()
+        .map_or_else(|_| node.to_string(), |info| info.addr.to_string())
+}
+
 fn get_random_connection<'a>(
     connections: &'a mut HashMap<String, Connection>,
     excludes: Option<&'a HashSet<String>>,
@@ -730,7 +1183,11 @@ fn get_random_connection<'a>(
 }
 
 // Get slot data from connection.
-fn get_slots(connection: &mut Connection, tls_mode: Option<TlsMode>) -> RedisResult<Vec<Slot>> {
+fn get_slots(
+    connection: &mut Connection,
+    tls_mode: Option<TlsMode>,
+    node_address_mapper: Option<&NodeAddressMapper>,
+) -> RedisResult<Vec<Slot>> {
     let mut cmd = Cmd::new();
     cmd.arg("CLUSTER").arg("SLOTS");
     let value = connection.req_command(&cmd)?;
@@ -766,13 +1223,15 @@ fn get_slots(connection: &mut Connection, tls_mode: Option<TlsMode>) -> RedisRes
                             return None;
                         }
 
-                        let ip = if let Value::Data(ref ip) = node[0] {
-                            String::from_utf8_lossy(ip)
+                        let mut ip = if let Value::Data(ref ip) = node[0] {
+                            String::from_utf8_lossy(ip).into_owned()
                         } else {
                             return None;
                         };
-                        if ip.is_empty() {
-                            return None;
+                        // An unknown endpoint is reported as "?" (or empty), in
+                        // which case fall back to the announced hostname if any.
+                        if ip.is_empty() || ip == "?" {
+                            ip = announced_hostname(node.get(3))?;
                         }
 
                         let port = if let Value::Int(port) = node[1] {
@@ -780,7 +1239,12 @@ fn get_slots(connection: &mut Connection, tls_mode: Option<TlsMode>) -> RedisRes
                         } else {
                             return None;
                         };
-                        Some(build_connection_string(&ip, Some(port), tls_mode))
+                        Some(node_connection_string(
+                            &ip,
+                            port,
+                            node_address_mapper,
+                            tls_mode,
+                        ))
                     } else {
                         None
                     }
@@ -799,10 +1263,50 @@ fn get_slots(connection: &mut Connection, tls_mode: Option<TlsMode>) -> RedisRes
     Ok(result)
 }
 
-fn build_connection_string(host: &str, port: Option<u16>, tls_mode: Option<TlsMode>) -> String {
-    let host_port = match port {
-        Some(port) => format!("{}:{}", host, port),
-        None => host.to_string(),
+// Extracts the `hostname` entry of the node metadata returned by CLUSTER SLOTS.
+fn announced_hostname(metadata: Option<&Value>) -> Option<String> {
+    let mut pairs = metadata?.as_map_iter()?;
+    pairs.find_map(|(key, value)| match (key, value) {
+        (Value::Data(key), Value::Data(hostname)) if key == b"hostname" && !hostname.is_empty() => {
+            Some(String::from_utf8_lossy(hostname).into_owned())
+        }
+        _ => None,
+    })
+}
+
+// Splits the `host:port` of a node in a redirect, where IPv6 hosts are not
+// enclosed in brackets.  Nodes with an unknown endpoint have no host.
+fn split_node_address(node: &str) -> Option<(&str, u16)> {
+    let (host, port) = node.rsplit_once(':')?;
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    if host.is_empty() {
+        return None;
+    }
+    Some((host, port.parse().ok()?))
+}
+
+// Builds the connection string of a node announced by the cluster, with the
+// host and port given by the node address mapper if there is one.
+fn node_connection_string(
+    host: &str,
+    port: u16,
+    node_address_mapper: Option<&NodeAddressMapper>,
+    tls_mode: Option<TlsMode>,
+) -> String {
+    match node_address_mapper {
+        Some(mapper) => {
+            let (host, port) = mapper(host, port);
+            build_connection_string(&host, port, tls_mode)
+        }
+        None => build_connection_string(host, port, tls_mode),
+    }
+}
+
+fn build_connection_string(host: &str, port: u16, tls_mode: Option<TlsMode>) -> String {
+    let host_port = if host.contains(':') {
+        format!("[{}]:{}", host, port)
+    } else {
+        format!("{}:{}", host, port)
     };
     match tls_mode {
         None => format!("redis://{}", host_port),
@@ -812,3 +1316,35 @@ fn build_connection_string(host: &str, port: Option<u16>, tls_mode: Option<TlsMo
         Some(TlsMode::Secure) => format!("rediss://{}", host_port),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redirect_node_addresses() {
+        assert_eq!(
+            split_node_address("node-1.example:7000"),
+            Some(("node-1.example", 7000))
+        );
+        assert_eq!(split_node_address("::1:7000"), Some(("::1", 7000)));
+        assert_eq!(split_node_address("[::1]:7000"), Some(("::1", 7000)));
+        assert_eq!(split_node_address(":7000"), None);
+        assert_eq!(split_node_address("node-1.example"), None);
+
+        assert_eq!(
+            build_connection_string("::1", 7000, None),
+            "redis://[::1]:7000"
+        );
+        let mapper: NodeAddressMapper =
+            Arc::new(|host, port| (format!("{}.local", host), port + 1));
+        assert_eq!(
+            node_connection_string("node-1", 7000, Some(&mapper), Some(TlsMode::Secure)),
+            "rediss://node-1.local:7001"
+        );
+        assert_eq!(
+            "redis://[::1]:7000".into_connection_info().unwrap().addr,
+            ConnectionAddr::Tcp("::1".into(), 7000)
+        );
+    }
+}
@@ -0,0 +1,312 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use crate::cluster::ClusterConnection;
+use crate::cmd::cmd;
+use crate::connection::{Connection, Msg};
+use crate::types::{from_redis_value, ErrorKind, RedisError, RedisResult, ToRedisArgs, Value};
+
+// How long each node is waited for while the nodes are polled in turn.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+// How often a subscription is retried when the slot map was outdated.
+const SUBSCRIBE_RETRIES: usize = 3;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    Channel,
+    Pattern,
+    ShardChannel,
+}
+
+impl Kind {
+    fn subscribe(self) -> &'static str {
+        match self {
+            Kind::Channel => "SUBSCRIBE",
+            Kind::Pattern => "PSUBSCRIBE",
+            Kind::ShardChannel => "SSUBSCRIBE",
+        }
+    }
+
+    fn unsubscribe(self) -> &'static str {
+        match self {
+            Kind::Channel => "UNSUBSCRIBE",
+            Kind::Pattern => "PUNSUBSCRIBE",
+            Kind::ShardChannel => "SUNSUBSCRIBE",
+        }
+    }
+}
+
+/// Pub/sub on a redis cluster, created by
+/// [`ClusterClient::get_pubsub`](crate::cluster::ClusterClient::get_pubsub).
+///
+/// Channels and patterns are subscribed on a single node, as the cluster
+/// forwards their messages to every node.  Shard channels (`SSUBSCRIBE`,
+/// Redis 7) are subscribed on the master serving the slot of the channel
+/// name.  The messages of all nodes are returned by `get_message` in the
+/// order they are read.
+///
+/// When a node fails or the slot of a shard channel moves, the slots are
+/// refreshed and the subscriptions are made again on the nodes which took
+/// over, messages published in between are lost.
+///
+/// ```rust,no_run
+/// # fn do_something() -> redis::RedisResult<()> {
+/// use redis::cluster::ClusterClient;
+///
+/// let nodes = vec!["redis://127.0.0.1:6379/", "redis://127.0.0.1:6378/"];
+/// let client = ClusterClient::open(nodes)?;
+/// let mut pubsub = client.get_pubsub()?;
+/// pubsub.subscribe("news")?;
+/// pubsub.ssubscribe("orders:{eu}")?;
+/// loop {
+///     let msg = pubsub.get_message()?;
+///     let payload: String = msg.get_payload()?;
+///     println!("channel '{}': {}", msg.get_channel_name(), payload);
+/// }
+/// # }
+/// ```
+pub struct ClusterPubSub {
+    // used for the slot map and to connect to the nodes
+    con: ClusterConnection,
+    nodes: HashMap<String, Connection>,
+    // the node of every subscription
+    subscriptions: Vec<(Kind, Vec<u8>, String)>,
+    pending: VecDeque<Msg>,
+    read_timeout: Option<Duration>,
+    next_node: usize,
+}
+
+impl ClusterPubSub {
+    pub(crate) fn new(con: ClusterConnection) -> ClusterPubSub {
+        ClusterPubSub {
+            con,
+            nodes: HashMap::new(),
+            subscriptions: Vec::new(),
+            pending: VecDeque::new(),
+            read_timeout: None,
+            next_node: 0,
+        }
+    }
+
+    /// Subscribes to channels.
+    pub fn subscribe<T: ToRedisArgs>(&mut self, channel: T) -> RedisResult<()> {
+        self.subscribe_all(Kind::Channel, channel)
+    }
+
+    /// Subscribes to channels with a pattern.
+    pub fn psubscribe<T: ToRedisArgs>(&mut self, pchannel: T) -> RedisResult<()> {
+        self.subscribe_all(Kind::Pattern, pchannel)
+    }
+
+    /// Subscribes to shard channels, on the nodes serving their slots.
+    pub fn ssubscribe<T: ToRedisArgs>(&mut self, schannel: T) -> RedisResult<()> {
+        self.subscribe_all(Kind::ShardChannel, schannel)
+    }
+
+    /// Unsubscribes from channels.
+    pub fn unsubscribe<T: ToRedisArgs>(&mut self, channel: T) -> RedisResult<()> {
+        self.unsubscribe_all(Kind::Channel, channel)
+    }
+
+    /// Unsubscribes from channels with a pattern.
+    pub fn punsubscribe<T: ToRedisArgs>(&mut self, pchannel: T) -> RedisResult<()> {
+        self.unsubscribe_all(Kind::Pattern, pchannel)
+    }
+
+    /// Unsubscribes from shard channels.
+    pub fn sunsubscribe<T: ToRedisArgs>(&mut self, schannel: T) -> RedisResult<()> {
+        self.unsubscribe_all(Kind::ShardChannel, schannel)
+    }
+
+    /// Fetches the next message of any node.  Blocks until a message
+    /// becomes available or the read timeout passed.
+    pub fn get_message(&mut self) -> RedisResult<Msg> {
+        let start = Instant::now();
+        loop {
+            if let Some(msg) = self.pending.pop_front() {
+                return Ok(msg);
+            }
+            if let Some(timeout) = self.read_timeout {
+                if start.elapsed() >= timeout {
+                    fail!(RedisError::from(std::io::Error::from(
+                        std::io::ErrorKind::TimedOut
+                    )));
+                }
+            }
+            self.poll()?;
+        }
+    }
+
+    /// Sets the read timeout of `get_message`.
+    ///
+    /// If the provided value is `None`, then `get_message` call will
+    /// block indefinitely.
+    pub fn set_read_timeout(&mut self, dur: Option<Duration>) {
+        self.read_timeout = dur;
+    }
+
+    fn subscribe_all<T: ToRedisArgs>(&mut self, kind: Kind, names: T) -> RedisResult<()> {
+        for name in names.to_redis_args() {
+            if !self
+                .subscriptions
+                .iter()
+                .any(|(k, n, _)| *k == kind && *n == name)
+            {
+                self.subscribe_one(kind, name)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn unsubscribe_all<T: ToRedisArgs>(&mut self, kind: Kind, names: T) -> RedisResult<()> {
+        for name in names.to_redis_args() {
+            let index = self
+                .subscriptions
+                .iter()
+                .position(|(k, n, _)| *k == kind && *n == name);
+            if let Some(index) = index {
+                let (_, name, addr) = self.subscriptions.remove(index);
+                let command = cmd(kind.unsubscribe()).arg(&name[..]).get_packed_command();
+                // A failed node dropped the subscription anyway.
+                let _ = self.request(&addr, &command);
+            }
+        }
+        Ok(())
+    }
+
+    // The node a subscription is made on.
+    fn node_for(&self, kind: Kind, name: &[u8]) -> RedisResult<String> {
+        let addr = match kind {
+            Kind::ShardChannel => self.con.master_for_key(name),
+            // All channels share one node, preferably one already connected.
+            _ => match self
+                .subscriptions
+                .iter()
+                .find(|(k, _, _)| *k != Kind::ShardChannel)
+            {
+                Some((_, _, addr)) => Some(addr.clone()),
+                None => self.con.random_master(),
+            },
+        };
+        match addr {
+            Some(addr) => Ok(addr),
+            None => fail!((ErrorKind::ClusterDown, "Missing slot coverage")),
+        }
+    }
+
+    fn subscribe_one(&mut self, kind: Kind, name: Vec<u8>) -> RedisResult<()> {
+        let command = cmd(kind.subscribe()).arg(&name[..]).get_packed_command();
+        let mut retries = 0;
+        loop {
+            let addr = self.node_for(kind, &name)?;
+            match self.request(&addr, &command) {
+                Ok(()) => {
+                    self.subscriptions.push((kind, name, addr));
+                    return Ok(());
+                }
+                Err(err) if retries < SUBSCRIBE_RETRIES && is_topology_error(&err) => {
+                    retries += 1;
+                    self.nodes.remove(&addr);
+                    self.con.refresh_slots()?;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    // Sends a (un)subscription for a single channel to the node and waits
+    // for its confirmation, keeping the messages arriving before it.
+    fn request(&mut self, addr: &str, command: &[u8]) -> RedisResult<()> {
+        if !self.nodes.contains_key(addr) {
+            let node = self.con.connect_node(addr)?;
+            self.nodes.insert(addr.to_string(), node);
+        }
+        let node = self.nodes.get_mut(addr).unwrap();
+        node.set_read_timeout(None)?;
+        node.send_packed_command(command)?;
+        loop {
+            let value = node.recv_response()?;
+            match Msg::from_value(&value) {
+                Some(msg) => self.pending.push_back(msg),
+                None => return Ok(()),
+            }
+        }
+    }
+
+    // Reads from the nodes in turn until a message arrived or every node
+    // was waited for once.
+    fn poll(&mut self) -> RedisResult<()> {
+        let mut addrs: Vec<String> = self.nodes.keys().cloned().collect();
+        if addrs.is_empty() {
+            fail!((ErrorKind::ClientError, "There are no subscriptions"));
+        }
+        addrs.sort();
+        self.next_node = (self.next_node + 1) % addrs.len();
+        addrs.rotate_left(self.next_node);
+
+        // A single node can be waited for as long as the read timeout.
+        let timeout = match addrs.len() {
+            1 => self.read_timeout,
+            _ => Some(POLL_INTERVAL),
+        };
+        for addr in addrs {
+            let node = self.nodes.get_mut(&addr).unwrap();
+            let value = node
+                .set_read_timeout(timeout)
+                .and_then(|_| node.recv_response());
+            match value {
+                Ok(value) => {
+                    if let Some(msg) = Msg::from_value(&value) {
+                        self.pending.push_back(msg);
+                    } else if is_moved_away(&value) {
+                        // The slot of a shard channel moved to another node.
+                        let name = from_redis_value::<(String, Vec<u8>)>(&value)?.1;
+                        self.resubscribe(|kind, n, _| kind == Kind::ShardChannel && *n == name)?;
+                    }
+                    return Ok(());
+                }
+                Err(err) if err.is_timeout() => {}
+                Err(err) if err.is_io_error() || err.is_connection_dropped() => {
+                    self.nodes.remove(&addr);
+                    self.resubscribe(|_, _, a| *a == addr)?;
+                    return Ok(());
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(())
+    }
+
+    // Makes the matching subscriptions again after a topology change.
+    fn resubscribe<F>(&mut self, mut matches: F) -> RedisResult<()>
+    where
+        F: FnMut(Kind, &Vec<u8>, &str) -> bool,
+    {
+        self.con.refresh_slots()?;
+        let (lost, kept) = self
+            .subscriptions
+            .drain(..)
+            .partition(|(kind, name, addr)| matches(*kind, name, addr));
+        self.subscriptions = kept;
+        for (kind, name, _) in lost {
+            self.subscribe_one(kind, name)?;
+        }
+        Ok(())
+    }
+}
+
+// Whether the server unsubscribed from a shard channel on its own, which it
+// does when the slot of the channel moves.
+fn is_moved_away(value: &Value) -> bool {
+    match value {
+        Value::Bulk(items) => {
+            matches!(items.first(), Some(Value::Data(kind)) if kind == b"sunsubscribe")
+        }
+        _ => false,
+    }
+}
+
+fn is_topology_error(err: &RedisError) -> bool {
+    err.is_cluster_error() || err.is_io_error() || err.is_connection_dropped()
+}
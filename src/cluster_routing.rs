@@ -1,10 +1,30 @@
 use std::iter::Iterator;
 
 use crate::cmd::{Arg, Cmd};
-use crate::types::Value;
+use crate::types::{from_redis_value, ErrorKind, RedisResult, Value};
 
 pub(crate) const SLOT_SIZE: usize = 16384;
 
+/// Where a cluster connection sends the read-only commands, see
+/// [`ClusterClientBuilder::read_routing`](crate::cluster::ClusterClientBuilder::read_routing).
+///
+/// All other commands are sent to the master serving the slot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReadRouting {
+    /// Reads are sent to the master, like writes.
+    #[default]
+    PreferMaster,
+    /// Reads are sent to a random replica of the slot, or to the master if
+    /// the slot has no replicas.
+    PreferReplica,
+    /// Reads are sent to the replicas of the slot in turn, or to the master
+    /// if the slot has no replicas.
+    RoundRobinReplicas,
+    /// Reads are sent to the node serving the slot, master or replica,
+    /// which answered `PING` the fastest when the slots were last refreshed.
+    LowestLatency,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub(crate) enum RoutingInfo {
     AllNodes,
@@ -25,7 +45,7 @@ impl RoutingInfo {
             | b"KEYS" => Some(RoutingInfo::AllNodes),
             b"SCAN" | b"CLIENT SETNAME" | b"SHUTDOWN" | b"SLAVEOF" | b"REPLICAOF"
             | b"SCRIPT KILL" | b"MOVE" | b"BITOP" => None,
-            b"EVALSHA" | b"EVAL" => {
+            b"EVALSHA" | b"EVAL" | b"EVALSHA_RO" | b"EVAL_RO" => {
                 let key_count = r
                     .arg_idx(2)
                     .and_then(|x| std::str::from_utf8(x).ok())
@@ -50,13 +70,107 @@ impl RoutingInfo {
     }
 
     pub fn for_key(key: &[u8]) -> Option<RoutingInfo> {
-        let key = match get_hashtag(key) {
-            Some(tag) => tag,
-            None => key,
+        Some(RoutingInfo::Slot(key_slot(key)))
+    }
+}
+
+// How the replies of a multi key command split by slot are merged.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Merge {
+    // the values of the keys, in the order of the keys
+    Values,
+    // `OK` if every part succeeded
+    Okay,
+    // the sum of the integer replies
+    Sum,
+}
+
+// A multi key command split into a command per slot of its keys.
+pub(crate) struct MultiKeySplit {
+    pub(crate) merge: Merge,
+    pub(crate) cmds: Vec<Cmd>,
+    // the positions of the keys of each command among all keys
+    pub(crate) keys: Vec<Vec<usize>>,
+}
+
+impl MultiKeySplit {
+    // Splits the command if it is one of the multi key commands which can be
+    // run per slot and its keys belong to more than one slot.
+    pub(crate) fn new(cmd: &Cmd) -> Option<MultiKeySplit> {
+        let (merge, step) = match &cmd.command()?[..] {
+            b"MGET" => (Merge::Values, 1),
+            b"MSET" => (Merge::Okay, 2),
+            b"DEL" | b"UNLINK" | b"EXISTS" | b"TOUCH" => (Merge::Sum, 1),
+            _ => return None,
+        };
+        let name = cmd.arg_idx(0)?;
+        let args: Vec<&[u8]> = (1..).map_while(|idx| cmd.arg_idx(idx)).collect();
+        if !args.chunks_exact(step).remainder().is_empty() {
+            return None;
+        }
+
+        let mut slots: Vec<u16> = Vec::new();
+        let mut split = MultiKeySplit {
+            merge,
+            cmds: Vec::new(),
+            keys: Vec::new(),
         };
-        Some(RoutingInfo::Slot(
-            crc16::State::<crc16::XMODEM>::calculate(key) % SLOT_SIZE as u16,
-        ))
+        for (idx, key_args) in args.chunks(step).enumerate() {
+            let slot = match RoutingInfo::for_key(key_args[0])? {
+                RoutingInfo::Slot(slot) => slot,
+                _ => return None,
+            };
+            let part = match slots.iter().position(|s| *s == slot) {
+                Some(part) => part,
+                None => {
+                    slots.push(slot);
+                    split.cmds.push(Cmd::new());
+                    split.cmds.last_mut().unwrap().arg(name);
+                    split.keys.push(Vec::new());
+                    slots.len() - 1
+                }
+            };
+            for arg in key_args {
+                split.cmds[part].arg(*arg);
+            }
+            split.keys[part].push(idx);
+        }
+        if split.cmds.len() < 2 {
+            return None;
+        }
+        Some(split)
+    }
+
+    // Merges the replies of the commands into the reply of the command
+    // which was split.
+    pub(crate) fn merge(&self, replies: Vec<Value>) -> RedisResult<Value> {
+        match self.merge {
+            Merge::Okay => Ok(Value::Okay),
+            Merge::Sum => {
+                let mut sum = 0;
+                for reply in replies {
+                    sum += from_redis_value::<i64>(&reply)?;
+                }
+                Ok(Value::Int(sum))
+            }
+            Merge::Values => {
+                let count = self.keys.iter().map(Vec::len).sum();
+                let mut values = vec![Value::Nil; count];
+                for (keys, reply) in self.keys.iter().zip(replies) {
+                    let items = match reply {
+                        Value::Bulk(items) if items.len() == keys.len() => items,
+                        _ => fail!((
+                            ErrorKind::ResponseError,
+                            "Invalid response when merging the replies of the slots"
+                        )),
+                    };
+                    for (idx, item) in keys.iter().zip(items) {
+                        values[*idx] = item;
+                    }
+                }
+                Ok(Value::Bulk(values))
+            }
+        }
     }
 }
 
@@ -72,6 +186,96 @@ pub(crate) trait Routable {
 
     // Returns index of argument that matches `candidate`, if it exists
     fn position(&self, candidate: &[u8]) -> Option<usize>;
+
+    // Whether the command only reads, so it may be served by a replica.
+    fn is_readonly(&self) -> bool {
+        match self.command() {
+            Some(cmd) => is_readonly_command(&cmd),
+            None => false,
+        }
+    }
+}
+
+fn is_readonly_command(cmd: &[u8]) -> bool {
+    matches!(
+        cmd,
+        b"BITCOUNT"
+            | b"BITFIELD_RO"
+            | b"BITPOS"
+            | b"DUMP"
+            | b"EVALSHA_RO"
+            | b"EVAL_RO"
+            | b"EXISTS"
+            | b"EXPIRETIME"
+            | b"GEODIST"
+            | b"GEOHASH"
+            | b"GEOPOS"
+            | b"GEORADIUSBYMEMBER_RO"
+            | b"GEORADIUS_RO"
+            | b"GEOSEARCH"
+            | b"GET"
+            | b"GETBIT"
+            | b"GETRANGE"
+            | b"HEXISTS"
+            | b"HGET"
+            | b"HGETALL"
+            | b"HKEYS"
+            | b"HLEN"
+            | b"HMGET"
+            | b"HRANDFIELD"
+            | b"HSCAN"
+            | b"HSTRLEN"
+            | b"HVALS"
+            | b"LCS"
+            | b"LINDEX"
+            | b"LLEN"
+            | b"LPOS"
+            | b"LRANGE"
+            | b"MGET"
+            | b"PEXPIRETIME"
+            | b"PFCOUNT"
+            | b"PTTL"
+            | b"SCARD"
+            | b"SDIFF"
+            | b"SINTER"
+            | b"SINTERCARD"
+            | b"SISMEMBER"
+            | b"SMEMBERS"
+            | b"SMISMEMBER"
+            | b"SORT_RO"
+            | b"SRANDMEMBER"
+            | b"SSCAN"
+            | b"STRLEN"
+            | b"SUBSTR"
+            | b"SUNION"
+            | b"TTL"
+            | b"TYPE"
+            | b"XINFO"
+            | b"XLEN"
+            | b"XPENDING"
+            | b"XRANGE"
+            | b"XREAD"
+            | b"XREVRANGE"
+            | b"ZCARD"
+            | b"ZCOUNT"
+            | b"ZDIFF"
+            | b"ZINTER"
+            | b"ZINTERCARD"
+            | b"ZLEXCOUNT"
+            | b"ZMSCORE"
+            | b"ZRANDMEMBER"
+            | b"ZRANGE"
+            | b"ZRANGEBYLEX"
+            | b"ZRANGEBYSCORE"
+            | b"ZRANK"
+            | b"ZREVRANGE"
+            | b"ZREVRANGEBYLEX"
+            | b"ZREVRANGEBYSCORE"
+            | b"ZREVRANK"
+            | b"ZSCAN"
+            | b"ZSCORE"
+            | b"ZUNION"
+    )
 }
 
 impl Routable for Cmd {
@@ -145,7 +349,32 @@ impl Slot {
     }
 }
 
-fn get_hashtag(key: &[u8]) -> Option<&[u8]> {
+/// Returns the hash slot of the key, which decides the node of the cluster
+/// serving the key.  If the key has a [hash tag](hash_tag), only the tag is
+/// hashed.
+///
+/// ```rust
+/// use redis::cluster::key_slot;
+///
+/// assert_eq!(key_slot(b"foo"), 12182);
+/// assert_eq!(key_slot(b"{user1000}.following"), key_slot(b"user1000"));
+/// ```
+pub fn key_slot(key: &[u8]) -> u16 {
+    let key = hash_tag(key).unwrap_or(key);
+    crc16::State::<crc16::XMODEM>::calculate(key) % SLOT_SIZE as u16
+}
+
+/// Returns the hash tag of the key, the part between the first `{` and the
+/// following `}` if it is not empty.  Keys with the same hash tag always
+/// belong to the same slot.
+///
+/// ```rust
+/// use redis::cluster::hash_tag;
+///
+/// assert_eq!(hash_tag(b"{user1000}.following"), Some(&b"user1000"[..]));
+/// assert_eq!(hash_tag(b"foo{}{bar}"), None);
+/// ```
+pub fn hash_tag(key: &[u8]) -> Option<&[u8]> {
     let open = key.iter().position(|v| *v == b'{');
     let open = match open {
         Some(open) => open,
@@ -168,14 +397,22 @@ fn get_hashtag(key: &[u8]) -> Option<&[u8]> {
 
 #[cfg(test)]
 mod tests {
-    use super::{get_hashtag, RoutingInfo};
+    use super::{hash_tag, key_slot, MultiKeySplit, Routable, RoutingInfo};
+    use crate::types::Value;
     use crate::{cmd, parser::parse_redis_value};
 
     #[test]
     fn test_get_hashtag() {
-        assert_eq!(get_hashtag(&b"foo{bar}baz"[..]), Some(&b"bar"[..]));
-        assert_eq!(get_hashtag(&b"foo{}{baz}"[..]), None);
-        assert_eq!(get_hashtag(&b"foo{{bar}}zap"[..]), Some(&b"{bar"[..]));
+        assert_eq!(hash_tag(&b"foo{bar}baz"[..]), Some(&b"bar"[..]));
+        assert_eq!(hash_tag(&b"foo{}{baz}"[..]), None);
+        assert_eq!(hash_tag(&b"foo{{bar}}zap"[..]), Some(&b"{bar"[..]));
+    }
+
+    #[test]
+    fn test_key_slot() {
+        assert_eq!(key_slot(b"123456789"), 0x31c3);
+        assert_eq!(key_slot(b""), 0);
+        assert_eq!(key_slot(b"foo{bar}"), key_slot(b"bar"));
     }
 
     #[test]
@@ -200,6 +437,52 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_multi_key_split() {
+        assert!(MultiKeySplit::new(cmd("MGET").arg("{a}1").arg("{a}2")).is_none());
+        assert!(MultiKeySplit::new(cmd("GET").arg("a")).is_none());
+
+        let split = MultiKeySplit::new(cmd("MGET").arg("{a}1").arg("{b}1").arg("{a}2")).unwrap();
+        assert_eq!(split.cmds.len(), 2);
+        assert_eq!(
+            split.cmds[0].get_packed_command(),
+            cmd("MGET").arg("{a}1").arg("{a}2").get_packed_command()
+        );
+        assert_eq!(split.keys, vec![vec![0, 2], vec![1]]);
+        let merged = split.merge(vec![
+            Value::Bulk(vec![Value::Int(1), Value::Int(3)]),
+            Value::Bulk(vec![Value::Nil]),
+        ]);
+        assert_eq!(
+            merged,
+            Ok(Value::Bulk(vec![Value::Int(1), Value::Nil, Value::Int(3)]))
+        );
+
+        let split = MultiKeySplit::new(cmd("MSET").arg("{a}1").arg(1).arg("{b}1").arg(2)).unwrap();
+        assert_eq!(
+            split.cmds[1].get_packed_command(),
+            cmd("MSET").arg("{b}1").arg(2).get_packed_command()
+        );
+        assert_eq!(split.merge(vec![Value::Okay, Value::Okay]), Ok(Value::Okay));
+
+        let split = MultiKeySplit::new(cmd("DEL").arg("{a}1").arg("{b}1")).unwrap();
+        assert_eq!(
+            split.merge(vec![Value::Int(1), Value::Int(0)]),
+            Ok(Value::Int(1))
+        );
+    }
+
+    #[test]
+    fn test_is_readonly() {
+        assert!(cmd("GET").arg("foo").is_readonly());
+        assert!(cmd("zrangebyscore").arg("foo").arg(0).arg(1).is_readonly());
+        assert!(!cmd("SET").arg("foo").arg(1).is_readonly());
+        assert!(!cmd("XREADGROUP").arg("GROUP").is_readonly());
+
+        let value = parse_redis_value(&cmd("HGETALL").arg("foo").get_packed_command()).unwrap();
+        assert!(value.is_readonly());
+    }
+
     #[test]
     fn test_routing_info() {
         let mut test_cmds = vec![];
@@ -0,0 +1,540 @@
+use crate::types::{from_redis_value, ErrorKind, FromRedisValue, RedisResult, Value};
+
+macro_rules! invalid_type_error {
+    ($v:expr, $det:expr) => {{
+        fail!((
+            ErrorKind::TypeError,
+            "Response was of incompatible type",
+            format!("{:?} (response was {:?})", $det, $v)
+        ));
+    }};
+}
+
+/// An inclusive range of hash slots.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SlotRange {
+    /// The first slot of the range.
+    pub start: u16,
+    /// The last slot of the range.
+    pub end: u16,
+}
+
+/// The role of a cluster node.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NodeRole {
+    /// The node serves the slots of its shard.
+    Master,
+    /// The node replicates a master.
+    Replica,
+}
+
+impl NodeRole {
+    fn parse(role: &str) -> Option<NodeRole> {
+        match role {
+            "master" => Some(NodeRole::Master),
+            "replica" | "slave" => Some(NodeRole::Replica),
+            _ => None,
+        }
+    }
+}
+
+/// The health of a cluster node.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NodeHealth {
+    /// The node is reachable and serving.
+    Online,
+    /// The node is loading its data set, for instance after a restart.
+    Loading,
+    /// The cluster agreed that the node failed.
+    Failed,
+    /// The node is unreachable from the node which was asked, but the
+    /// cluster did not agree on its failure yet (`fail?` in `CLUSTER NODES`).
+    Suspected,
+}
+
+/// A shard of a `CLUSTER SHARDS` reply (Redis 7), which can be read as a
+/// `Vec<ClusterShard>`.
+///
+/// ```rust,no_run
+/// # fn do_something() -> redis::RedisResult<()> {
+/// use redis::cluster::{ClusterShard, NodeRole};
+///
+/// let client = redis::Client::open("redis://127.0.0.1:6379/")?;
+/// let mut con = client.get_connection()?;
+/// let shards: Vec<ClusterShard> = redis::cmd("CLUSTER").arg("SHARDS").query(&mut con)?;
+/// for shard in shards {
+///     let master = shard.nodes.iter().find(|node| node.role == NodeRole::Master);
+///     println!("{:?} served by {:?}", shard.slots, master.map(|node| &node.id));
+/// }
+/// # Ok(()) }
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ClusterShard {
+    /// The slots served by the shard.
+    pub slots: Vec<SlotRange>,
+    /// The master and the replicas of the shard.
+    pub nodes: Vec<ShardNode>,
+}
+
+/// A node of a [`ClusterShard`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ShardNode {
+    /// The node id.
+    pub id: String,
+    /// The endpoint clients should connect to, as configured by
+    /// `cluster-preferred-endpoint-type`.
+    pub endpoint: String,
+    /// The IP address of the node.
+    pub ip: String,
+    /// The hostname the node announces, if any.
+    pub hostname: Option<String>,
+    /// The plain text port, if the node listens on one.
+    pub port: Option<u16>,
+    /// The TLS port, if the node listens on one.
+    pub tls_port: Option<u16>,
+    /// Whether the node is a master or a replica.
+    pub role: NodeRole,
+    /// The replication offset of the node.
+    pub replication_offset: i64,
+    /// The health of the node.
+    pub health: NodeHealth,
+}
+
+impl FromRedisValue for ClusterShard {
+    fn from_redis_value(v: &Value) -> RedisResult<ClusterShard> {
+        let pairs = match v.as_map_iter() {
+            Some(pairs) => pairs,
+            None => invalid_type_error!(v, "CLUSTER SHARDS entry is not a map"),
+        };
+        let mut slots = None;
+        let mut nodes = None;
+        for (key, value) in pairs {
+            match &from_redis_value::<String>(key)?[..] {
+                "slots" => slots = Some(slot_ranges(value)?),
+                "nodes" => nodes = Some(from_redis_value(value)?),
+                _ => {}
+            }
+        }
+        match (slots, nodes) {
+            (Some(slots), Some(nodes)) => Ok(ClusterShard { slots, nodes }),
+            _ => invalid_type_error!(v, "CLUSTER SHARDS entry lacks slots or nodes"),
+        }
+    }
+}
+
+impl FromRedisValue for ShardNode {
+    fn from_redis_value(v: &Value) -> RedisResult<ShardNode> {
+        let pairs = match v.as_map_iter() {
+            Some(pairs) => pairs,
+            None => invalid_type_error!(v, "CLUSTER SHARDS node is not a map"),
+        };
+        let mut id = None;
+        let mut endpoint = None;
+        let mut ip = None;
+        let mut hostname = None;
+        let mut port = None;
+        let mut tls_port = None;
+        let mut role = None;
+        let mut replication_offset = 0;
+        let mut health = None;
+        for (key, value) in pairs {
+            match &from_redis_value::<String>(key)?[..] {
+                "id" => id = Some(from_redis_value(value)?),
+                "endpoint" => endpoint = Some(from_redis_value(value)?),
+                "ip" => ip = Some(from_redis_value(value)?),
+                "hostname" => hostname = Some(from_redis_value(value)?),
+                "port" => port = Some(from_redis_value(value)?),
+                "tls-port" => tls_port = Some(from_redis_value(value)?),
+                "role" => {
+                    let text: String = from_redis_value(value)?;
+                    match NodeRole::parse(&text) {
+                        Some(parsed) => role = Some(parsed),
+                        None => invalid_type_error!(value, "Unknown CLUSTER SHARDS node role"),
+                    }
+                }
+                "replication-offset" => replication_offset = from_redis_value(value)?,
+                "health" => {
+                    health = match &from_redis_value::<String>(value)?[..] {
+                        "online" => Some(NodeHealth::Online),
+                        "loading" => Some(NodeHealth::Loading),
+                        "failed" => Some(NodeHealth::Failed),
+                        _ => invalid_type_error!(value, "Unknown CLUSTER SHARDS node health"),
+                    }
+                }
+                _ => {}
+            }
+        }
+        match (id, role, health) {
+            (Some(id), Some(role), Some(health)) => Ok(ShardNode {
+                id,
+                endpoint: endpoint.unwrap_or_default(),
+                ip: ip.unwrap_or_default(),
+                hostname,
+                port,
+                tls_port,
+                role,
+                replication_offset,
+                health,
+            }),
+            _ => invalid_type_error!(v, "CLUSTER SHARDS node lacks id, role or health"),
+        }
+    }
+}
+
+// The slots of a shard, a flat list of start and end pairs.
+fn slot_ranges(v: &Value) -> RedisResult<Vec<SlotRange>> {
+    let bounds: Vec<u16> = from_redis_value(v)?;
+    let pairs = bounds.chunks_exact(2);
+    if !pairs.remainder().is_empty() {
+        invalid_type_error!(v, "CLUSTER SHARDS slots are not pairs");
+    }
+    Ok(pairs
+        .map(|pair| SlotRange {
+            start: pair[0],
+            end: pair[1],
+        })
+        .collect())
+}
+
+/// A slot range of a `CLUSTER SLOTS` reply, which can be read as a
+/// `Vec<ClusterSlots>`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ClusterSlots {
+    /// The slots of the range.
+    pub range: SlotRange,
+    /// The master serving the slots.
+    pub master: SlotNode,
+    /// The replicas of the master.
+    pub replicas: Vec<SlotNode>,
+}
+
+/// A node of [`ClusterSlots`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SlotNode {
+    /// The preferred endpoint of the node, `?` if it is unknown.
+    pub endpoint: String,
+    /// The port of the node.
+    pub port: u16,
+    /// The node id, sent by Redis 4 and later.
+    pub id: Option<String>,
+    /// The hostname the node announces, if any (Redis 7).
+    pub hostname: Option<String>,
+}
+
+impl FromRedisValue for ClusterSlots {
+    fn from_redis_value(v: &Value) -> RedisResult<ClusterSlots> {
+        match v.as_sequence() {
+            Some([start, end, master, replicas @ ..]) => Ok(ClusterSlots {
+                range: SlotRange {
+                    start: from_redis_value(start)?,
+                    end: from_redis_value(end)?,
+                },
+                master: from_redis_value(master)?,
+                replicas: replicas
+                    .iter()
+                    .map(from_redis_value)
+                    .collect::<RedisResult<_>>()?,
+            }),
+            _ => invalid_type_error!(v, "CLUSTER SLOTS entry must have at least three elements"),
+        }
+    }
+}
+
+impl FromRedisValue for SlotNode {
+    fn from_redis_value(v: &Value) -> RedisResult<SlotNode> {
+        let (endpoint, port, id, metadata) = match v.as_sequence() {
+            Some([endpoint, port]) => (endpoint, port, None, None),
+            Some([endpoint, port, id]) => (endpoint, port, Some(id), None),
+            Some([endpoint, port, id, metadata, ..]) => (endpoint, port, Some(id), Some(metadata)),
+            _ => invalid_type_error!(v, "CLUSTER SLOTS node must have at least two elements"),
+        };
+        let mut hostname = None;
+        if let Some(metadata) = metadata {
+            let pairs = match metadata.as_map_iter() {
+                Some(pairs) => pairs,
+                None => invalid_type_error!(metadata, "CLUSTER SLOTS node metadata is not a map"),
+            };
+            for (key, value) in pairs {
+                if from_redis_value::<String>(key)? == "hostname" {
+                    hostname = Some(from_redis_value(value)?);
+                }
+            }
+        }
+        Ok(SlotNode {
+            endpoint: from_redis_value(endpoint)?,
+            port: from_redis_value(port)?,
+            id: id.map(from_redis_value).transpose()?,
+            hostname,
+        })
+    }
+}
+
+/// The reply of `CLUSTER NODES`, parsed from its text.
+///
+/// ```rust,no_run
+/// # fn do_something() -> redis::RedisResult<()> {
+/// use redis::cluster::{ClusterNodesReply, NodeHealth};
+///
+/// let client = redis::Client::open("redis://127.0.0.1:6379/")?;
+/// let mut con = client.get_connection()?;
+/// let reply: ClusterNodesReply = redis::cmd("CLUSTER").arg("NODES").query(&mut con)?;
+/// for node in reply.nodes.iter().filter(|node| node.health != NodeHealth::Online) {
+///     println!("{} at {}:{} is {:?}", node.id, node.ip, node.port, node.health);
+/// }
+/// # Ok(()) }
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ClusterNodesReply {
+    /// The nodes, one per line of the reply.
+    pub nodes: Vec<ClusterNode>,
+}
+
+/// A node of [`ClusterNodesReply`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ClusterNode {
+    /// The node id.
+    pub id: String,
+    /// The IP address of the node, empty if it is unknown.
+    pub ip: String,
+    /// The client port of the node.
+    pub port: u16,
+    /// The cluster bus port of the node, which Redis 3 does not report.
+    pub bus_port: Option<u16>,
+    /// The hostname the node announces, if any (Redis 7).
+    pub hostname: Option<String>,
+    /// The flags of the node, such as `myself`, `master` or `fail?`.
+    pub flags: Vec<String>,
+    /// Whether the node is a master or a replica.
+    pub role: NodeRole,
+    /// The health of the node derived from its flags, as seen by the node
+    /// which was asked.
+    pub health: NodeHealth,
+    /// The id of the master, if the node is a replica.
+    pub master_id: Option<String>,
+    /// When the pending ping was sent in milliseconds since the epoch, zero
+    /// if there is none.
+    pub ping_sent: u64,
+    /// When the last pong was received in milliseconds since the epoch.
+    pub pong_received: u64,
+    /// The configuration epoch of the node.
+    pub config_epoch: u64,
+    /// Whether the cluster bus link to the node is connected.
+    pub connected: bool,
+    /// The slots served by the node.  Slots being imported or migrated are
+    /// left out.
+    pub slots: Vec<SlotRange>,
+}
+
+impl ClusterNode {
+    /// Returns whether this is the node which was asked.
+    pub fn is_myself(&self) -> bool {
+        self.flags.iter().any(|flag| flag == "myself")
+    }
+}
+
+impl FromRedisValue for ClusterNodesReply {
+    fn from_redis_value(v: &Value) -> RedisResult<ClusterNodesReply> {
+        let text: String = from_redis_value(v)?;
+        let nodes = text
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| match parse_node(line) {
+                Some(node) => Ok(node),
+                None => invalid_type_error!(line, "Invalid CLUSTER NODES line"),
+            })
+            .collect::<RedisResult<_>>()?;
+        Ok(ClusterNodesReply { nodes })
+    }
+}
+
+// Parses a line `<id> <ip:port@cport[,hostname]> <flags> <master> <ping-sent>
+// <pong-recv> <config-epoch> <link-state> <slot> ... <slot>`.
+fn parse_node(line: &str) -> Option<ClusterNode> {
+    let mut fields = line.split_whitespace();
+    let id = fields.next()?.to_string();
+    let addr = fields.next()?;
+    let (addr, hostname) = match addr.split_once(',') {
+        Some((addr, hostname)) if !hostname.is_empty() => (addr, Some(hostname.to_string())),
+        Some((addr, _)) => (addr, None),
+        None => (addr, None),
+    };
+    let (addr, bus_port) = match addr.split_once('@') {
+        Some((addr, bus_port)) => (addr, Some(bus_port.parse().ok()?)),
+        None => (addr, None),
+    };
+    let (ip, port) = addr.rsplit_once(':')?;
+    let flags: Vec<String> = fields.next()?.split(',').map(str::to_string).collect();
+    let has_flag = |name: &str| flags.iter().any(|flag| flag == name);
+    let role = if has_flag("slave") {
+        NodeRole::Replica
+    } else {
+        NodeRole::Master
+    };
+    let health = if has_flag("fail") {
+        NodeHealth::Failed
+    } else if has_flag("fail?") {
+        NodeHealth::Suspected
+    } else {
+        NodeHealth::Online
+    };
+    let master_id = match fields.next()? {
+        "-" => None,
+        master_id => Some(master_id.to_string()),
+    };
+    let ping_sent = fields.next()?.parse().ok()?;
+    let pong_received = fields.next()?.parse().ok()?;
+    let config_epoch = fields.next()?.parse().ok()?;
+    let connected = fields.next()? == "connected";
+    let mut slots = Vec::new();
+    for slot in fields.filter(|slot| !slot.starts_with('[')) {
+        let (start, end) = slot.split_once('-').unwrap_or((slot, slot));
+        slots.push(SlotRange {
+            start: start.parse().ok()?,
+            end: end.parse().ok()?,
+        });
+    }
+    Some(ClusterNode {
+        id,
+        ip: ip.to_string(),
+        port: port.parse().ok()?,
+        bus_port,
+        hostname,
+        flags,
+        role,
+        health,
+        master_id,
+        ping_sent,
+        pong_received,
+        config_epoch,
+        connected,
+        slots,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data(text: &str) -> Value {
+        Value::Data(text.as_bytes().to_vec())
+    }
+
+    #[test]
+    fn test_cluster_shards() {
+        let node = Value::Bulk(vec![
+            data("id"),
+            data("e5d4"),
+            data("port"),
+            Value::Int(30001),
+            data("ip"),
+            data("127.0.0.1"),
+            data("endpoint"),
+            data("127.0.0.1"),
+            data("role"),
+            data("master"),
+            data("replication-offset"),
+            Value::Int(72156),
+            data("health"),
+            data("online"),
+        ]);
+        let shard = Value::Bulk(vec![
+            data("slots"),
+            Value::Bulk(vec![
+                Value::Int(0),
+                Value::Int(5460),
+                Value::Int(6000),
+                Value::Int(6000),
+            ]),
+            data("nodes"),
+            Value::Bulk(vec![node]),
+        ]);
+        let shards: Vec<ClusterShard> = from_redis_value(&Value::Bulk(vec![shard])).unwrap();
+        assert_eq!(
+            shards,
+            vec![ClusterShard {
+                slots: vec![
+                    SlotRange {
+                        start: 0,
+                        end: 5460
+                    },
+                    SlotRange {
+                        start: 6000,
+                        end: 6000
+                    },
+                ],
+                nodes: vec![ShardNode {
+                    id: "e5d4".to_string(),
+                    endpoint: "127.0.0.1".to_string(),
+                    ip: "127.0.0.1".to_string(),
+                    hostname: None,
+                    port: Some(30001),
+                    tls_port: None,
+                    role: NodeRole::Master,
+                    replication_offset: 72156,
+                    health: NodeHealth::Online,
+                }],
+            }]
+        );
+    }
+
+    #[test]
+    fn test_cluster_slots() {
+        let reply = Value::Bulk(vec![Value::Bulk(vec![
+            Value::Int(0),
+            Value::Int(5460),
+            Value::Bulk(vec![data("127.0.0.1"), Value::Int(30001), data("e5d4")]),
+            Value::Bulk(vec![
+                data("127.0.0.1"),
+                Value::Int(30004),
+                data("f6a1"),
+                Value::Bulk(vec![data("hostname"), data("replica.example.com")]),
+            ]),
+        ])]);
+        let slots: Vec<ClusterSlots> = from_redis_value(&reply).unwrap();
+        assert_eq!(slots.len(), 1);
+        assert_eq!(
+            slots[0].range,
+            SlotRange {
+                start: 0,
+                end: 5460
+            }
+        );
+        assert_eq!(slots[0].master.port, 30001);
+        assert_eq!(slots[0].master.id.as_deref(), Some("e5d4"));
+        assert_eq!(
+            slots[0].replicas[0].hostname.as_deref(),
+            Some("replica.example.com")
+        );
+    }
+
+    #[test]
+    fn test_cluster_nodes() {
+        let text = "\
+e5d4 127.0.0.1:30001@31001,node1.example.com myself,master - 0 1426238316232 1 connected 0-5460 [5461->-f6a1]
+f6a1 127.0.0.1:30004@31004, slave,fail? e5d4 1426238317239 1426238316232 1 disconnected
+";
+        let reply: ClusterNodesReply = from_redis_value(&data(text)).unwrap();
+        let (master, replica) = (&reply.nodes[0], &reply.nodes[1]);
+        assert!(master.is_myself());
+        assert_eq!(master.hostname.as_deref(), Some("node1.example.com"));
+        assert_eq!(master.bus_port, Some(31001));
+        assert_eq!(master.role, NodeRole::Master);
+        assert_eq!(master.health, NodeHealth::Online);
+        assert_eq!(
+            master.slots,
+            vec![SlotRange {
+                start: 0,
+                end: 5460
+            }]
+        );
+        assert!(master.connected);
+        assert_eq!(replica.hostname, None);
+        assert_eq!(replica.role, NodeRole::Replica);
+        assert_eq!(replica.health, NodeHealth::Suspected);
+        assert_eq!(replica.master_id.as_deref(), Some("e5d4"));
+        assert!(!replica.connected);
+        assert!(replica.slots.is_empty());
+
+        assert!(from_redis_value::<ClusterNodesReply>(&data("e5d4 garbage")).is_err());
+    }
+}
@@ -5,6 +5,7 @@ use futures_util::{
 };
 #[cfg(feature = "aio")]
 use std::pin::Pin;
+use std::time::Duration;
 use std::{fmt, io};
 
 use crate::connection::ConnectionLike;
@@ -27,6 +28,9 @@ pub struct Cmd {
     // Arg::Simple contains the offset that marks the end of the argument
     args: Vec<Arg<usize>>,
     cursor: Option<u64>,
+    timeout: Option<Duration>,
+    #[cfg(feature = "otel")]
+    span: Option<tracing::Span>,
 }
 
 /// Represents a redis iterator.
@@ -124,6 +128,53 @@ impl<'a, T: FromRedisValue + 'a> AsyncIter<'a, T> {
     }
 }
 
+/// A stream of the items of a `SCAN` like command, see
+/// [`Cmd::into_scan_stream`].
+#[cfg(feature = "aio")]
+pub type ScanStream<'a, T> = Pin<Box<dyn Stream<Item = RedisResult<T>> + Send + 'a>>;
+
+#[cfg(feature = "aio")]
+struct ScanState<'a, T> {
+    batch: std::vec::IntoIter<T>,
+    con: &'a mut (dyn AsyncConnection + Send + 'a),
+    cmd: Cmd,
+    done: bool,
+}
+
+#[cfg(feature = "aio")]
+impl<'a, T: FromRedisValue> ScanState<'a, T> {
+    async fn next_item(&mut self) -> Option<RedisResult<T>> {
+        loop {
+            if let Some(v) = self.batch.next() {
+                return Some(Ok(v));
+            }
+            if self.done {
+                return None;
+            }
+
+            let batch = self.con.req_packed_command(&self.cmd).await.and_then(|rv| {
+                if rv.looks_like_cursor() {
+                    from_redis_value::<(u64, Vec<T>)>(&rv)
+                } else {
+                    Ok((0, from_redis_value(&rv)?))
+                }
+            });
+            match batch {
+                Ok((cursor, batch)) => {
+                    self.done = cursor == 0;
+                    self.cmd.cursor = Some(cursor);
+                    self.batch = batch.into_iter();
+                }
+                Err(err) => {
+                    // the cursor is lost with the reply
+                    self.done = true;
+                    return Some(Err(err));
+                }
+            }
+        }
+    }
+}
+
 #[cfg(feature = "aio")]
 impl<'a, T: FromRedisValue + Unpin + 'a> Stream for AsyncIter<'a, T> {
     type Item = T;
@@ -228,6 +279,113 @@ where
     Ok(())
 }
 
+// Arguments of at least this size are referenced instead of copied by
+// `PackedCommands`.
+const LARGE_ARG_LEN: usize = 4 * 1024;
+
+/// Packed commands which reference their large arguments instead of
+/// copying them, to be sent with vectored writes.
+pub(crate) struct PackedCommands<'a> {
+    buf: Vec<u8>,
+    // the large arguments and where they go in `buf`
+    args: Vec<(usize, &'a [u8])>,
+}
+
+impl<'a> PackedCommands<'a> {
+    pub(crate) fn new() -> PackedCommands<'a> {
+        PackedCommands {
+            buf: Vec::new(),
+            args: Vec::new(),
+        }
+    }
+
+    /// Appends an already packed command.
+    pub(crate) fn push_packed(&mut self, packed: &[u8]) {
+        self.buf.extend_from_slice(packed);
+    }
+
+    pub(crate) fn push(&mut self, cmd: &'a Cmd) {
+        self.push_len(b'*', cmd.args.len());
+        for item in cmd.args_iter() {
+            match item {
+                Arg::Simple(val) if val.len() >= LARGE_ARG_LEN => {
+                    self.push_len(b'$', val.len());
+                    self.args.push((self.buf.len(), val));
+                    self.buf.extend_from_slice(b"\r\n");
+                }
+                Arg::Simple(val) => self.push_arg(val),
+                Arg::Cursor => {
+                    let mut cursor_bytes = itoa::Buffer::new();
+                    self.push_arg(cursor_bytes.format(cmd.cursor.unwrap_or(0)).as_bytes());
+                }
+            }
+        }
+    }
+
+    fn push_arg(&mut self, arg: &[u8]) {
+        self.push_len(b'$', arg.len());
+        self.buf.extend_from_slice(arg);
+        self.buf.extend_from_slice(b"\r\n");
+    }
+
+    fn push_len(&mut self, prefix: u8, len: usize) {
+        self.buf.push(prefix);
+        self.buf
+            .extend_from_slice(itoa::Buffer::new().format(len).as_bytes());
+        self.buf.extend_from_slice(b"\r\n");
+    }
+
+    /// Returns the slices which make up the packed commands, in order.
+    pub(crate) fn slices(&self) -> Vec<&[u8]> {
+        let mut slices = Vec::with_capacity(2 * self.args.len() + 1);
+        let mut start = 0;
+        for &(pos, arg) in &self.args {
+            slices.push(&self.buf[start..pos]);
+            slices.push(arg);
+            start = pos;
+        }
+        slices.push(&self.buf[start..]);
+        slices
+    }
+}
+
+/// Writes the slices with vectored writes, retrying until all of them are
+/// written like `write_all` does.
+pub(crate) fn write_all_vectored<W>(w: &mut W, slices: &mut [&[u8]]) -> io::Result<()>
+where
+    W: ?Sized + io::Write,
+{
+    let mut start = advance_slices(slices, 0);
+    while start < slices.len() {
+        let io_slices: Vec<_> = slices[start..]
+            .iter()
+            .map(|s| io::IoSlice::new(s))
+            .collect();
+        match w.write_vectored(&io_slices) {
+            Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+            Ok(n) => start += advance_slices(&mut slices[start..], n),
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+/// Removes `n` written bytes from the front of the slices and returns the
+/// number of slices which were written completely.
+pub(crate) fn advance_slices(slices: &mut [&[u8]], mut n: usize) -> usize {
+    let mut written = 0;
+    for slice in slices.iter_mut() {
+        if n < slice.len() {
+            *slice = &slice[n..];
+            break;
+        }
+        n -= slice.len();
+        written += 1;
+    }
+    written
+}
+
 impl RedisWrite for Cmd {
     fn write_arg(&mut self, arg: &[u8]) {
         self.data.extend_from_slice(arg);
@@ -281,6 +439,9 @@ impl Cmd {
             data: vec![],
             args: vec![],
             cursor: None,
+            timeout: None,
+            #[cfg(feature = "otel")]
+            span: None,
         }
     }
 
@@ -326,6 +487,61 @@ impl Cmd {
         self
     }
 
+    /// Sets how long `query_async` waits for the reply before it fails with
+    /// `ErrorKind::Timeout`.  The request is cancelled and the connection
+    /// stays usable, the late reply is discarded.  Synchronous queries are
+    /// not affected, they use the timeouts of the connection.  Neither are
+    /// pipelines, which ignore the timeouts of their commands; set a
+    /// response timeout on the connection to bound them, e.g. with
+    /// `MultiplexedConnection::set_response_timeout`.
+    ///
+    /// ```rust,no_run
+    /// # async fn do_something(con: &mut redis::aio::MultiplexedConnection) -> redis::RedisResult<()> {
+    /// use std::time::Duration;
+    ///
+    /// let value: Option<String> = redis::cmd("GET")
+    ///     .arg("my_key")
+    ///     .timeout(Duration::from_millis(50))
+    ///     .query_async(con)
+    ///     .await?;
+    /// # Ok(()) }
+    /// ```
+    #[inline]
+    pub fn timeout(&mut self, timeout: Duration) -> &mut Cmd {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Attaches the current `tracing` span to the command, which becomes
+    /// the parent of the span of the command wherever it is sent from.
+    ///
+    /// This keeps the logs of a request correlated with its commands when
+    /// they are built in one task and sent from another one, like a batch
+    /// writer or a job queue.
+    ///
+    /// ```rust,no_run
+    /// # fn do_something(con: &mut redis::Connection) -> redis::RedisResult<()> {
+    /// let span = tracing::info_span!("checkout", order = 42);
+    /// let mut cmd = span.in_scope(|| {
+    ///     let mut cmd = redis::cmd("INCR");
+    ///     cmd.arg("orders").attach_current_span();
+    ///     cmd
+    /// });
+    /// cmd.query::<i64>(con)?;
+    /// # Ok(()) }
+    /// ```
+    #[cfg(feature = "otel")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "otel")))]
+    pub fn attach_current_span(&mut self) -> &mut Cmd {
+        self.span = Some(tracing::Span::current());
+        self
+    }
+
+    #[cfg(feature = "otel")]
+    pub(crate) fn attached_span(&self) -> Option<&tracing::Span> {
+        self.span.as_ref()
+    }
+
     /// Returns the packed command as a byte vector.
     #[inline]
     pub fn get_packed_command(&self) -> Vec<u8> {
@@ -338,7 +554,13 @@ impl Cmd {
         write_command_to_vec(cmd, self.args_iter(), self.cursor.unwrap_or(0))
     }
 
-    pub(crate) fn write_packed_command_preallocated(&self, cmd: &mut Vec<u8>) {
+    #[cfg(feature = "aio")]
+    pub(crate) fn write_packed_command_to_bytes(&self, out: &mut bytes::BytesMut) {
+        out.reserve(cmd_len(self));
+        self.write_packed_command_preallocated(&mut bytes::BufMut::writer(out))
+    }
+
+    pub(crate) fn write_packed_command_preallocated(&self, cmd: &mut (impl ?Sized + io::Write)) {
         write_command(cmd, self.args_iter(), self.cursor.unwrap_or(0)).unwrap()
     }
 
@@ -372,13 +594,38 @@ impl Cmd {
     }
 
     /// Async version of `query`.
+    ///
+    /// Fails with `ErrorKind::Timeout` if a timeout was set with `timeout`
+    /// and the reply did not arrive in time.
     #[inline]
     #[cfg(feature = "aio")]
     pub async fn query_async<C, T: FromRedisValue>(&self, con: &mut C) -> RedisResult<T>
     where
         C: crate::aio::ConnectionLike,
     {
-        let val = con.req_packed_command(self).await?;
+        let val = match self.timeout {
+            Some(timeout) => {
+                crate::aio::with_timeout(timeout, con.req_packed_command(self)).await?
+            }
+            None => con.req_packed_command(self).await?,
+        };
+        from_redis_value(&val)
+    }
+
+    /// Like `query_async` but fails with `ErrorKind::Timeout` if the reply
+    /// did not arrive within `timeout`, which takes precedence over the one
+    /// set with `timeout`.
+    #[inline]
+    #[cfg(feature = "aio")]
+    pub async fn query_async_with_timeout<C, T: FromRedisValue>(
+        &self,
+        con: &mut C,
+        timeout: Duration,
+    ) -> RedisResult<T>
+    where
+        C: crate::aio::ConnectionLike,
+    {
+        let val = crate::aio::with_timeout(timeout, con.req_packed_command(self)).await?;
         from_redis_value(&val)
     }
 
@@ -455,6 +702,51 @@ impl Cmd {
         })
     }
 
+    /// Turns a command using `cursor_arg` into a stream of the items of all
+    /// its batches, sending the command again with the returned cursor
+    /// until the server side cursor is exhausted.
+    ///
+    /// Unlike `iter_async`, nothing is sent until the stream is polled, and
+    /// errors are yielded as items, after which the stream ends.  The
+    /// stream can be used with the combinators of `StreamExt` and
+    /// `TryStreamExt`:
+    ///
+    /// ```rust,no_run
+    /// use futures::prelude::*;
+    /// # async fn do_something() -> redis::RedisResult<()> {
+    /// # let client = redis::Client::open("redis://127.0.0.1/")?;
+    /// # let mut con = client.get_async_connection().await?;
+    /// let keys: Vec<String> = redis::cmd("SCAN")
+    ///     .cursor_arg(0)
+    ///     .clone()
+    ///     .into_scan_stream(&mut con)
+    ///     .try_collect()
+    ///     .await?;
+    /// # Ok(()) }
+    /// ```
+    #[cfg(feature = "aio")]
+    pub fn into_scan_stream<'a, T>(
+        self,
+        con: &'a mut (dyn AsyncConnection + Send + 'a),
+    ) -> ScanStream<'a, T>
+    where
+        T: FromRedisValue + Send + 'a,
+    {
+        let state = ScanState {
+            batch: Vec::new().into_iter(),
+            con,
+            cmd: self,
+            done: false,
+        };
+        Box::pin(futures_util::stream::unfold(
+            state,
+            |mut state| async move {
+                let item = state.next_item().await?;
+                Some((item, state))
+            },
+        ))
+    }
+
     /// This is a shortcut to `query()` that does not return a value and
     /// will fail the task if the query fails because of an error.  This is
     /// mainly useful in examples and for simple commands like setting
@@ -553,10 +845,63 @@ pub fn pipe() -> Pipeline {
 }
 
 #[cfg(test)]
-#[cfg(feature = "cluster")]
 mod tests {
-    use super::Cmd;
+    use super::*;
+
+    #[test]
+    fn test_packed_commands_reference_large_args() {
+        let large = vec![b'x'; LARGE_ARG_LEN];
+        let mut set = cmd("SET");
+        set.arg("key").arg(&large[..]);
+        let mut scan = cmd("SCAN");
+        scan.cursor_arg(7);
+
+        let mut packed = PackedCommands::new();
+        packed.push_packed(b"*1\r\n$5\r\nMULTI\r\n");
+        packed.push(&set);
+        packed.push(&scan);
+        let slices = packed.slices();
+        assert_eq!(slices.len(), 3);
+        assert_eq!(slices[1].len(), LARGE_ARG_LEN);
+
+        let mut expected = b"*1\r\n$5\r\nMULTI\r\n".to_vec();
+        expected.extend(set.get_packed_command());
+        expected.extend(scan.get_packed_command());
+        assert_eq!(slices.concat(), expected);
+    }
+
+    #[test]
+    fn test_write_all_vectored() {
+        // accepts at most a few bytes of the first two slices per write
+        struct Trickle(Vec<u8>);
+        impl io::Write for Trickle {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.write_vectored(&[io::IoSlice::new(buf)])
+            }
+            fn write_vectored(&mut self, bufs: &[io::IoSlice<'_>]) -> io::Result<usize> {
+                let mut n = 0;
+                for buf in bufs.iter().take(2) {
+                    let len = buf.len().min(3);
+                    self.0.extend_from_slice(&buf[..len]);
+                    n += len;
+                    if len < buf.len() {
+                        break;
+                    }
+                }
+                Ok(n)
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mut out = Trickle(Vec::new());
+        let mut slices: Vec<&[u8]> = vec![b"hello", b"", b" ", b"world", b"!"];
+        write_all_vectored(&mut out, &mut slices).unwrap();
+        assert_eq!(out.0, b"hello world!");
+    }
 
+    #[cfg(feature = "cluster")]
     #[test]
     fn test_cmd_arg_idx() {
         let mut c = Cmd::new();
@@ -572,4 +917,74 @@ mod tests {
         assert_eq!(c.arg_idx(3), None);
         assert_eq!(c.arg_idx(4), None);
     }
+
+    #[cfg(feature = "aio")]
+    #[test]
+    fn test_scan_stream() {
+        use crate::types::{ErrorKind, RedisError, RedisFuture, Value};
+        use futures::{executor::block_on, StreamExt};
+        use std::collections::VecDeque;
+
+        // replies to the commands in order and records them
+        struct Replies {
+            replies: VecDeque<RedisResult<Value>>,
+            sent: Vec<Vec<u8>>,
+        }
+
+        impl AsyncConnection for Replies {
+            fn req_packed_command<'a>(&'a mut self, cmd: &'a Cmd) -> RedisFuture<'a, Value> {
+                self.sent.push(cmd.get_packed_command());
+                let reply = self.replies.pop_front().unwrap();
+                Box::pin(async move { reply })
+            }
+
+            fn req_packed_commands<'a>(
+                &'a mut self,
+                _cmd: &'a Pipeline,
+                _offset: usize,
+                _count: usize,
+            ) -> RedisFuture<'a, Vec<Value>> {
+                unimplemented!()
+            }
+
+            fn get_db(&self) -> i64 {
+                0
+            }
+        }
+
+        let batch = |cursor: &str, items: &[i64]| {
+            Ok(Value::Bulk(vec![
+                Value::Data(cursor.as_bytes().to_vec()),
+                Value::Bulk(items.iter().map(|&i| Value::Int(i)).collect()),
+            ]))
+        };
+        let mut con = Replies {
+            replies: vec![batch("5", &[1, 2]), batch("9", &[]), batch("0", &[3])].into(),
+            sent: vec![],
+        };
+        let mut scan = cmd("SSCAN");
+        scan.arg("set").cursor_arg(0);
+        let items: Vec<RedisResult<i64>> = block_on(scan.into_scan_stream(&mut con).collect());
+        assert_eq!(items, vec![Ok(1), Ok(2), Ok(3)]);
+        let expected: Vec<_> = [0, 5, 9]
+            .iter()
+            .map(|cursor| cmd("SSCAN").arg("set").arg(*cursor).get_packed_command())
+            .collect();
+        assert_eq!(con.sent, expected);
+
+        let mut con = Replies {
+            replies: vec![
+                batch("5", &[1]),
+                Err(RedisError::from((ErrorKind::IoError, "broken"))),
+            ]
+            .into(),
+            sent: vec![],
+        };
+        let mut scan = cmd("SCAN");
+        scan.cursor_arg(0);
+        let items: Vec<RedisResult<i64>> = block_on(scan.into_scan_stream(&mut con).collect());
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0], Ok(1));
+        assert_eq!(items[1].as_ref().unwrap_err().kind(), ErrorKind::IoError);
+    }
 }
@@ -132,6 +132,43 @@ macro_rules! implement_commands {
                 c.arg(key).cursor_arg(0).arg("MATCH").arg(pattern);
                 c.iter(self)
             }
+
+            /// Incrementally iterate the keys space with the given options.
+            #[inline]
+            fn scan_options<RV: FromRedisValue>(&mut self, opts: ScanOptions) -> RedisResult<Iter<'_, RV>> {
+                let mut c = cmd("SCAN");
+                c.cursor_arg(0).arg(opts);
+                c.iter(self)
+            }
+
+            /// Incrementally iterate the fields and values of a hash with the
+            /// given options.
+            #[inline]
+            fn hscan_options<K: ToRedisArgs, F: FromRedisValue, V: FromRedisValue>
+                    (&mut self, key: K, opts: ScanOptions) -> RedisResult<Iter<'_, (F, V)>> {
+                let mut c = cmd("HSCAN");
+                c.arg(key).cursor_arg(0).arg(opts);
+                c.iter(self)
+            }
+
+            /// Incrementally iterate set elements with the given options.
+            #[inline]
+            fn sscan_options<K: ToRedisArgs, RV: FromRedisValue>
+                    (&mut self, key: K, opts: ScanOptions) -> RedisResult<Iter<'_, RV>> {
+                let mut c = cmd("SSCAN");
+                c.arg(key).cursor_arg(0).arg(opts);
+                c.iter(self)
+            }
+
+            /// Incrementally iterate the members and scores of a sorted set with
+            /// the given options.
+            #[inline]
+            fn zscan_options<K: ToRedisArgs, M: FromRedisValue>
+                    (&mut self, key: K, opts: ScanOptions) -> RedisResult<Iter<'_, (M, f64)>> {
+                let mut c = cmd("ZSCAN");
+                c.arg(key).cursor_arg(0).arg(opts);
+                c.iter(self)
+            }
         }
 
         impl Cmd {
@@ -256,6 +293,75 @@ macro_rules! implement_commands {
                 c.arg(key).cursor_arg(0).arg("MATCH").arg(pattern);
                 Box::pin(async move {c.iter_async(self).await })
             }
+
+            /// Incrementally iterate the keys space with the given options.
+            #[inline]
+            fn scan_options<RV: FromRedisValue>(&mut self, opts: ScanOptions) -> crate::types::RedisFuture<'_, crate::cmd::AsyncIter<'_, RV>> {
+                let mut c = cmd("SCAN");
+                c.cursor_arg(0).arg(opts);
+                Box::pin(async move { c.iter_async(self).await })
+            }
+
+            /// Incrementally iterate the fields and values of a hash with the
+            /// given options.
+            #[inline]
+            fn hscan_options<K: ToRedisArgs, F: FromRedisValue, V: FromRedisValue>
+                    (&mut self, key: K, opts: ScanOptions) -> crate::types::RedisFuture<'_, crate::cmd::AsyncIter<'_, (F, V)>> {
+                let mut c = cmd("HSCAN");
+                c.arg(key).cursor_arg(0).arg(opts);
+                Box::pin(async move { c.iter_async(self).await })
+            }
+
+            /// Incrementally iterate set elements with the given options.
+            #[inline]
+            fn sscan_options<K: ToRedisArgs, RV: FromRedisValue>
+                    (&mut self, key: K, opts: ScanOptions) -> crate::types::RedisFuture<'_, crate::cmd::AsyncIter<'_, RV>> {
+                let mut c = cmd("SSCAN");
+                c.arg(key).cursor_arg(0).arg(opts);
+                Box::pin(async move { c.iter_async(self).await })
+            }
+
+            /// Incrementally iterate the members and scores of a sorted set with
+            /// the given options.
+            #[inline]
+            fn zscan_options<K: ToRedisArgs, M: FromRedisValue>
+                    (&mut self, key: K, opts: ScanOptions) -> crate::types::RedisFuture<'_, crate::cmd::AsyncIter<'_, (M, f64)>> {
+                let mut c = cmd("ZSCAN");
+                c.arg(key).cursor_arg(0).arg(opts);
+                Box::pin(async move { c.iter_async(self).await })
+            }
+
+            /// Incrementally iterate the keys space as a stream.
+            #[inline]
+            fn scan_stream<'a, RV: FromRedisValue + Send + 'a>(&'a mut self) -> crate::cmd::ScanStream<'a, RV> {
+                let mut c = cmd("SCAN");
+                c.cursor_arg(0);
+                c.into_scan_stream(self)
+            }
+
+            /// Incrementally iterate hash fields and associated values as a stream.
+            #[inline]
+            fn hscan_stream<'a, K: ToRedisArgs, RV: FromRedisValue + Send + 'a>(&'a mut self, key: K) -> crate::cmd::ScanStream<'a, RV> {
+                let mut c = cmd("HSCAN");
+                c.arg(key).cursor_arg(0);
+                c.into_scan_stream(self)
+            }
+
+            /// Incrementally iterate set elements as a stream.
+            #[inline]
+            fn sscan_stream<'a, K: ToRedisArgs, RV: FromRedisValue + Send + 'a>(&'a mut self, key: K) -> crate::cmd::ScanStream<'a, RV> {
+                let mut c = cmd("SSCAN");
+                c.arg(key).cursor_arg(0);
+                c.into_scan_stream(self)
+            }
+
+            /// Incrementally iterate sorted set elements as a stream.
+            #[inline]
+            fn zscan_stream<'a, K: ToRedisArgs, RV: FromRedisValue + Send + 'a>(&'a mut self, key: K) -> crate::cmd::ScanStream<'a, RV> {
+                let mut c = cmd("ZSCAN");
+                c.arg(key).cursor_arg(0);
+                c.into_scan_stream(self)
+            }
         }
 
         /// Implements common redis commands for pipelines.  Unlike the regular
@@ -382,6 +488,17 @@ implement_commands! {
         cmd("PEXPIREAT").arg(key).arg(ts)
     }
 
+    /// Get the UNIX timestamp in seconds at which a key will expire.
+    fn expire_time<K: ToRedisArgs>(key: K) {
+        cmd("EXPIRETIME").arg(key)
+    }
+
+    /// Get the UNIX timestamp in milliseconds at which a key will expire,
+    /// which converts into a `SystemTime`.
+    fn pexpire_time<K: ToRedisArgs>(key: K) {
+        cmd("PEXPIRETIME").arg(key)
+    }
+
     /// Remove the expiration from a key.
     fn persist<K: ToRedisArgs>(key: K) {
         cmd("PERSIST").arg(key)
@@ -2164,6 +2281,72 @@ impl ToRedisArgs for LposOptions {
     }
 }
 
+/// Options for the `SCAN`, `HSCAN`, `SSCAN` and `ZSCAN` commands.
+///
+/// ```rust,no_run
+/// use redis::{Commands, RedisResult, ScanOptions};
+/// fn fetch_sessions(con: &mut redis::Connection) -> RedisResult<Vec<String>> {
+///     let opts = ScanOptions::default()
+///         .pattern("session:*")
+///         .count(1000)
+///         .scan_type("hash");
+///     Ok(con.scan_options(opts)?.collect())
+/// }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct ScanOptions {
+    pattern: Option<Vec<u8>>,
+    count: Option<usize>,
+    scan_type: Option<String>,
+}
+
+impl ScanOptions {
+    /// Only return the elements matching the glob-style pattern.
+    pub fn pattern<P: ToRedisArgs>(mut self, pattern: P) -> Self {
+        self.pattern = Some(pattern.to_redis_args().concat());
+        self
+    }
+
+    /// Hint how many elements the server should look at per call.
+    pub fn count(mut self, n: usize) -> Self {
+        self.count = Some(n);
+        self
+    }
+
+    /// Only return keys of the given type, like `string` or `hash`.  This
+    /// is only supported by `SCAN`.
+    pub fn scan_type<T: Into<String>>(mut self, scan_type: T) -> Self {
+        self.scan_type = Some(scan_type.into());
+        self
+    }
+}
+
+impl ToRedisArgs for ScanOptions {
+    fn write_redis_args<W>(&self, out: &mut W)
+    where
+        W: ?Sized + RedisWrite,
+    {
+        if let Some(ref pattern) = self.pattern {
+            out.write_arg(b"MATCH");
+            out.write_arg(pattern);
+        }
+
+        if let Some(n) = self.count {
+            out.write_arg(b"COUNT");
+            out.write_arg_fmt(n);
+        }
+
+        if let Some(ref scan_type) = self.scan_type {
+            out.write_arg(b"TYPE");
+            out.write_arg(scan_type.as_bytes());
+        }
+    }
+
+    fn is_single_arg(&self) -> bool {
+        false
+    }
+}
+
 /// Enum for the LEFT | RIGHT args used by some commands
 pub enum Direction {
     Left,
@@ -1,22 +1,39 @@
+use std::collections::VecDeque;
 use std::fmt;
 use std::io::{self, Write};
+use std::marker::PhantomData;
 use std::net::{self, TcpStream, ToSocketAddrs};
 use std::path::PathBuf;
 use std::str::{from_utf8, FromStr};
+use std::sync::Arc;
 use std::time::Duration;
 
-use crate::cmd::{cmd, pipe, Cmd};
-use crate::parser::Parser;
+use crate::cmd::{cmd, pipe, write_all_vectored, Cmd};
+use crate::happy_eyeballs;
+use crate::instrumentation::{observe, CountingReader, MetricsObserver};
+use crate::parser::{ParseLimits, Parser, ReplyHead};
 use crate::pipeline::Pipeline;
+use crate::proxy::{ProxyConfig, Tunnel};
+use crate::tcp::TcpSettings;
 use crate::types::{
-    from_redis_value, ErrorKind, FromRedisValue, RedisError, RedisResult, ToRedisArgs, Value,
+    from_redis_value, ErrorKind, FromRedisValue, MonitorEntry, RedisError, RedisResult,
+    ToRedisArgs, Value,
 };
 
 #[cfg(unix)]
 use std::os::unix::net::UnixStream;
 
-#[cfg(feature = "tls")]
-use native_tls::{TlsConnector, TlsStream};
+use crate::tls::TlsCertificates;
+
+#[cfg(all(feature = "tls", not(feature = "tls-rustls")))]
+use crate::tls::create_native_tls_connector;
+#[cfg(all(feature = "tls", not(feature = "tls-rustls")))]
+use native_tls::TlsStream;
+
+#[cfg(feature = "tls-rustls")]
+use crate::tls::{create_rustls_config, server_name};
+#[cfg(feature = "tls-rustls")]
+use rustls::{ClientConnection, StreamOwned};
 
 static DEFAULT_PORT: u16 = 6379;
 
@@ -42,7 +59,9 @@ pub fn parse_redis_url(input: &str) -> Option<url::Url> {
 pub enum ConnectionAddr {
     /// Format for this is `(host, port)`.
     Tcp(String, u16),
-    /// Format for this is `(host, port)`.
+    /// Format for this is `(host, port)`.  Fields may be added to the
+    /// variant, it is created with [`ConnectionAddr::tcp_tls`].
+    #[non_exhaustive]
     TcpTls {
         /// Hostname
         host: String,
@@ -57,12 +76,26 @@ pub enum ConnectionAddr {
         /// trusted for use from any other. This introduces a significant
         /// vulnerability to man-in-the-middle attacks.
         insecure: bool,
+        /// Client certificate and custom root certificates, see
+        /// [`Client::build_with_tls`](crate::Client::build_with_tls).
+        certificates: Option<TlsCertificates>,
     },
     /// Format for this is the path to the unix socket.
     Unix(PathBuf),
 }
 
 impl ConnectionAddr {
+    /// Creates the address of a TLS connection.  Certificates are attached
+    /// with [`Client::build_with_tls`](crate::Client::build_with_tls).
+    pub fn tcp_tls<H: Into<String>>(host: H, port: u16, insecure: bool) -> ConnectionAddr {
+        ConnectionAddr::TcpTls {
+            host: host.into(),
+            port,
+            insecure,
+            certificates: None,
+        }
+    }
+
     /// Checks if this address is supported.
     ///
     /// Because not all platforms support all connection addresses this is a
@@ -73,7 +106,7 @@ impl ConnectionAddr {
     pub fn is_supported(&self) -> bool {
         match *self {
             ConnectionAddr::Tcp(_, _) => true,
-            ConnectionAddr::TcpTls { .. } => cfg!(feature = "tls"),
+            ConnectionAddr::TcpTls { .. } => cfg!(any(feature = "tls", feature = "tls-rustls")),
             ConnectionAddr::Unix(_) => cfg!(unix),
         }
     }
@@ -89,6 +122,64 @@ impl fmt::Display for ConnectionAddr {
     }
 }
 
+/// An event in the life of a connection which is re-established when it is
+/// lost, reported to the handler set with `ConnectionManagerConfig::on_event`
+/// or `ClusterClientBuilder::on_event`.
+///
+/// The addresses are given as `host:port`, or as the path of a unix socket.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ConnectionEvent {
+    /// A connection to a server was established.
+    Connected {
+        /// The address of the server.
+        addr: String,
+    },
+    /// A new connection sent its credentials and they were accepted.  Only
+    /// reported, right after `Connected`, when credentials are configured.
+    Authenticated {
+        /// The address of the server.
+        addr: String,
+    },
+    /// A connection was lost and is being re-established.
+    Reconnecting {
+        /// The address of the server the connection was lost to.
+        addr: String,
+    },
+    /// A lost connection was re-established.
+    Reconnected {
+        /// The address of the server the connection was lost to.
+        addr: String,
+    },
+    /// Re-establishing a lost connection failed and was given up.
+    GaveUp {
+        /// The address of the server the connection was lost to.
+        addr: String,
+        /// The error of the last attempt.
+        error: RedisError,
+    },
+}
+
+/// Receives the [`ConnectionEvent`]s of a connection.
+#[cfg(any(feature = "cluster", feature = "connection-manager"))]
+pub(crate) type ConnectionEventHandler = std::sync::Arc<dyn Fn(&ConnectionEvent) + Send + Sync>;
+
+// Reports a new connection to `addr` which was set up with `info`.
+#[cfg(any(feature = "cluster", feature = "connection-manager"))]
+pub(crate) fn report_connected(
+    handler: &ConnectionEventHandler,
+    addr: &ConnectionAddr,
+    info: &RedisConnectionInfo,
+) {
+    let addr = addr.to_string();
+    if info.password.is_some() {
+        handler(&ConnectionEvent::Connected { addr: addr.clone() });
+        handler(&ConnectionEvent::Authenticated { addr });
+    } else {
+        handler(&ConnectionEvent::Connected { addr });
+    }
+}
+
 /// Holds the connection information that redis should use for connecting.
 #[derive(Clone, Debug)]
 pub struct ConnectionInfo {
@@ -97,6 +188,48 @@ pub struct ConnectionInfo {
 
     /// A boxed connection address for where to connect to.
     pub redis: RedisConnectionInfo,
+
+    /// A proxy to connect through, `None` to connect directly.
+    pub proxy: Option<ProxyConfig>,
+
+    /// The options of the TCP socket.
+    pub tcp_settings: TcpSettings,
+}
+
+/// The version of the redis protocol spoken with the server.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ProtocolVersion {
+    /// The RESP2 protocol understood by all servers.  This is the default.
+    #[default]
+    RESP2,
+    /// The RESP3 protocol introduced with redis 6.
+    RESP3,
+}
+
+/// The library name and version a connection announces with
+/// `CLIENT SETINFO`, which operators see in the output of `CLIENT LIST`.
+///
+/// Servers older than redis 7.2 do not know the command, its errors are
+/// ignored.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum LibraryInfo {
+    /// `lib-name` is `redis-rs` and `lib-ver` the version of this crate.
+    /// This is the default.
+    #[default]
+    RedisRs,
+    /// Like `RedisRs` with the suffix appended to `lib-name` in parentheses,
+    /// like `redis-rs(my-app_v1.2)`, to identify the application.
+    WithSuffix(String),
+    /// The given `lib-name` and `lib-ver`, for libraries built on top of
+    /// this one.
+    Custom {
+        /// The announced `lib-name`.
+        name: String,
+        /// The announced `lib-ver`.
+        version: String,
+    },
+    /// Nothing is announced.
+    Disabled,
 }
 
 /// Redis specific/connection independent information used to establish a connection to redis.
@@ -108,6 +241,14 @@ pub struct RedisConnectionInfo {
     pub username: Option<String>,
     /// Optionally a password that should be used for connection.
     pub password: Option<String>,
+    /// The protocol to request from the server.  Requesting `RESP3` makes
+    /// the connection start with a `HELLO` handshake which also
+    /// authenticates it, and falls back to `RESP2` if the server does not
+    /// support it.
+    pub protocol: ProtocolVersion,
+    /// What the connection announces about the library with
+    /// `CLIENT SETINFO`.
+    pub library_info: LibraryInfo,
 }
 
 impl FromStr for ConnectionInfo {
@@ -149,6 +290,8 @@ where
         Ok(ConnectionInfo {
             addr: ConnectionAddr::Tcp(self.0.into(), self.1),
             redis: RedisConnectionInfo::default(),
+            proxy: None,
+            tcp_settings: TcpSettings::default(),
         })
     }
 }
@@ -164,18 +307,21 @@ impl IntoConnectionInfo for String {
 
 fn url_to_tcp_connection_info(url: url::Url) -> RedisResult<ConnectionInfo> {
     let host = match url.host() {
+        // without the brackets of the URL, so the address can be resolved
+        Some(url::Host::Ipv6(addr)) => addr.to_string(),
         Some(host) => host.to_string(),
         None => fail!((ErrorKind::InvalidClientConfig, "Missing hostname")),
     };
     let port = url.port().unwrap_or(DEFAULT_PORT);
     let addr = if url.scheme() == "rediss" {
-        #[cfg(feature = "tls")]
+        #[cfg(any(feature = "tls", feature = "tls-rustls"))]
         {
             match url.fragment() {
                 Some("insecure") => ConnectionAddr::TcpTls {
                     host,
                     port,
                     insecure: true,
+                    certificates: None,
                 },
                 Some(_) => fail!((
                     ErrorKind::InvalidClientConfig,
@@ -185,11 +331,12 @@ fn url_to_tcp_connection_info(url: url::Url) -> RedisResult<ConnectionInfo> {
                     host,
                     port,
                     insecure: false,
+                    certificates: None,
                 },
             }
         }
 
-        #[cfg(not(feature = "tls"))]
+        #[cfg(not(any(feature = "tls", feature = "tls-rustls")))]
         fail!((
             ErrorKind::InvalidClientConfig,
             "can't connect with TLS, the feature is not enabled"
@@ -228,10 +375,26 @@ fn url_to_tcp_connection_info(url: url::Url) -> RedisResult<ConnectionInfo> {
                 },
                 None => None,
             },
+            protocol: url_protocol(&url)?,
+            library_info: LibraryInfo::default(),
         },
+        proxy: None,
+        tcp_settings: TcpSettings::default(),
     })
 }
 
+// Reads the `protocol` query parameter of a connection url.
+fn url_protocol(url: &url::Url) -> RedisResult<ProtocolVersion> {
+    match url.query_pairs().find(|(key, _)| key == "protocol") {
+        None => Ok(ProtocolVersion::RESP2),
+        Some((_, value)) => match &*value {
+            "2" | "resp2" => Ok(ProtocolVersion::RESP2),
+            "3" | "resp3" => Ok(ProtocolVersion::RESP3),
+            _ => fail!((ErrorKind::InvalidClientConfig, "Invalid protocol version")),
+        },
+    }
+}
+
 #[cfg(unix)]
 fn url_to_unix_connection_info(url: url::Url) -> RedisResult<ConnectionInfo> {
     let query: std::collections::HashMap<_, _> = url.query_pairs().collect();
@@ -250,7 +413,11 @@ fn url_to_unix_connection_info(url: url::Url) -> RedisResult<ConnectionInfo> {
             },
             username: query.get("user").map(|username| username.to_string()),
             password: query.get("pass").map(|password| password.to_string()),
+            protocol: url_protocol(&url)?,
+            library_info: LibraryInfo::default(),
         },
+        proxy: None,
+        tcp_settings: TcpSettings::default(),
     })
 }
 
@@ -280,21 +447,34 @@ struct TcpConnection {
     open: bool,
 }
 
-#[cfg(feature = "tls")]
+#[cfg(all(feature = "tls", not(feature = "tls-rustls")))]
 struct TcpTlsConnection {
     reader: TlsStream<TcpStream>,
     open: bool,
 }
 
+#[cfg(feature = "tls-rustls")]
+struct TcpTlsConnection {
+    reader: Box<StreamOwned<ClientConnection, TcpStream>>,
+    open: bool,
+}
+
 #[cfg(unix)]
 struct UnixConnection {
     sock: UnixStream,
     open: bool,
 }
 
+// The stream of a connection taken out of it, see `Connection::into_stream`.
+#[cfg(feature = "replication")]
+pub(crate) trait RawStream: io::Read + io::Write + Send {}
+
+#[cfg(feature = "replication")]
+impl<T: io::Read + io::Write + Send> RawStream for T {}
+
 enum ActualConnection {
     Tcp(TcpConnection),
-    #[cfg(feature = "tls")]
+    #[cfg(any(feature = "tls", feature = "tls-rustls"))]
     TcpTls(TcpTlsConnection),
     #[cfg(unix)]
     Unix(UnixConnection),
@@ -305,12 +485,23 @@ pub struct Connection {
     con: ActualConnection,
     parser: Parser,
     db: i64,
+    protocol: ProtocolVersion,
 
     /// Flag indicating whether the connection was left in the PubSub state after dropping `PubSub`.
     ///
     /// This flag is checked when attempting to send a command, and if it's raised, we attempt to
     /// exit the pubsub state before executing the new request.
     pubsub: bool,
+
+    // The pubsub messages pushed by the server over RESP3 while the reply
+    // of a command was read, returned first by `recv_response`.
+    pushes: VecDeque<Value>,
+
+    /// What the spans of the commands record about the connection.
+    #[cfg(feature = "tracing")]
+    traced: crate::instrument::TracedConnection,
+
+    metrics: Option<Arc<dyn MetricsObserver>>,
 }
 
 /// Represents a pubsub connection.
@@ -318,6 +509,11 @@ pub struct PubSub<'a> {
     con: &'a mut Connection,
 }
 
+/// Represents a connection in monitor mode, see [`Connection::monitor`].
+pub struct Monitor {
+    con: Connection,
+}
+
 /// Represents a pubsub message.
 #[derive(Debug)]
 pub struct Msg {
@@ -327,97 +523,29 @@ pub struct Msg {
 }
 
 impl ActualConnection {
-    pub fn new(addr: &ConnectionAddr, timeout: Option<Duration>) -> RedisResult<ActualConnection> {
-        Ok(match *addr {
-            ConnectionAddr::Tcp(ref host, ref port) => {
-                let host: &str = &*host;
-                let tcp = match timeout {
-                    None => TcpStream::connect((host, *port))?,
-                    Some(timeout) => {
-                        let mut tcp = None;
-                        let mut last_error = None;
-                        for addr in (host, *port).to_socket_addrs()? {
-                            match TcpStream::connect_timeout(&addr, timeout) {
-                                Ok(l) => {
-                                    tcp = Some(l);
-                                    break;
-                                }
-                                Err(e) => {
-                                    last_error = Some(e);
-                                }
-                            };
-                        }
-                        match (tcp, last_error) {
-                            (Some(tcp), _) => tcp,
-                            (None, Some(e)) => {
-                                fail!(e);
-                            }
-                            (None, None) => {
-                                fail!((
-                                    ErrorKind::InvalidClientConfig,
-                                    "could not resolve to any addresses"
-                                ));
-                            }
-                        }
-                    }
-                };
+    pub fn new(info: &ConnectionInfo, timeout: Option<Duration>) -> RedisResult<ActualConnection> {
+        Ok(match info.addr {
+            ConnectionAddr::Tcp(ref host, port) => {
+                let tcp = connect_tcp((host, port), info, timeout)?;
                 ActualConnection::Tcp(TcpConnection {
                     reader: tcp,
                     open: true,
                 })
             }
-            #[cfg(feature = "tls")]
+            #[cfg(all(feature = "tls", not(feature = "tls-rustls")))]
             ConnectionAddr::TcpTls {
                 ref host,
                 port,
                 insecure,
+                ref certificates,
             } => {
-                let tls_connector = if insecure {
-                    TlsConnector::builder()
-                        .danger_accept_invalid_certs(true)
-                        .danger_accept_invalid_hostnames(true)
-                        .use_sni(false)
-                        .build()?
-                } else {
-                    TlsConnector::new()?
-                };
+                let tls_connector = create_native_tls_connector(insecure, certificates.as_ref())?;
                 let host: &str = &*host;
-                let tls = match timeout {
-                    None => {
-                        let tcp = TcpStream::connect((host, port))?;
-                        match tls_connector.connect(host, tcp) {
-                            Ok(res) => res,
-                            Err(e) => {
-                                fail!((ErrorKind::IoError, "SSL Handshake error", e.to_string()));
-                            }
-                        }
-                    }
-                    Some(timeout) => {
-                        let mut tcp = None;
-                        let mut last_error = None;
-                        for addr in (host, port).to_socket_addrs()? {
-                            match TcpStream::connect_timeout(&addr, timeout) {
-                                Ok(l) => {
-                                    tcp = Some(l);
-                                    break;
-                                }
-                                Err(e) => {
-                                    last_error = Some(e);
-                                }
-                            };
-                        }
-                        match (tcp, last_error) {
-                            (Some(tcp), _) => tls_connector.connect(host, tcp).unwrap(),
-                            (None, Some(e)) => {
-                                fail!(e);
-                            }
-                            (None, None) => {
-                                fail!((
-                                    ErrorKind::InvalidClientConfig,
-                                    "could not resolve to any addresses"
-                                ));
-                            }
-                        }
+                let tcp = connect_tcp((host, port), info, timeout)?;
+                let tls = match tls_connector.connect(host, tcp) {
+                    Ok(res) => res,
+                    Err(e) => {
+                        fail!((ErrorKind::IoError, "SSL Handshake error", e.to_string()));
                     }
                 };
                 ActualConnection::TcpTls(TcpTlsConnection {
@@ -425,13 +553,34 @@ impl ActualConnection {
                     open: true,
                 })
             }
-            #[cfg(not(feature = "tls"))]
+            #[cfg(feature = "tls-rustls")]
+            ConnectionAddr::TcpTls {
+                ref host,
+                port,
+                insecure,
+                ref certificates,
+            } => {
+                let config = create_rustls_config(insecure, certificates.as_ref())?;
+                let conn = ClientConnection::new(Arc::new(config), server_name(host)?)?;
+                let tcp = connect_tcp((host, port), info, timeout)?;
+                ActualConnection::TcpTls(TcpTlsConnection {
+                    reader: Box::new(StreamOwned::new(conn, tcp)),
+                    open: true,
+                })
+            }
+            #[cfg(not(any(feature = "tls", feature = "tls-rustls")))]
             ConnectionAddr::TcpTls { .. } => {
                 fail!((
                     ErrorKind::InvalidClientConfig,
                     "Cannot connect to TCP with TLS without the tls feature"
                 ));
             }
+            ConnectionAddr::Unix(_) if info.proxy.is_some() => {
+                fail!((
+                    ErrorKind::InvalidClientConfig,
+                    "Cannot connect to unix sockets through a proxy"
+                ));
+            }
             #[cfg(unix)]
             ConnectionAddr::Unix(ref path) => ActualConnection::Unix(UnixConnection {
                 sock: UnixStream::connect(path)?,
@@ -449,9 +598,20 @@ impl ActualConnection {
     }
 
     pub fn send_bytes(&mut self, bytes: &[u8]) -> RedisResult<Value> {
+        self.send_with(|w| w.write_all(bytes))
+    }
+
+    pub fn send_slices(&mut self, slices: &mut [&[u8]]) -> RedisResult<Value> {
+        self.send_with(|w| write_all_vectored(w, slices))
+    }
+
+    fn send_with<F>(&mut self, write: F) -> RedisResult<Value>
+    where
+        F: FnOnce(&mut dyn Write) -> io::Result<()>,
+    {
         match *self {
             ActualConnection::Tcp(ref mut connection) => {
-                let res = connection.reader.write_all(bytes).map_err(RedisError::from);
+                let res = write(&mut connection.reader).map_err(RedisError::from);
                 match res {
                     Err(e) => {
                         if e.is_connection_dropped() {
@@ -462,9 +622,9 @@ impl ActualConnection {
                     Ok(_) => Ok(Value::Okay),
                 }
             }
-            #[cfg(feature = "tls")]
+            #[cfg(any(feature = "tls", feature = "tls-rustls"))]
             ActualConnection::TcpTls(ref mut connection) => {
-                let res = connection.reader.write_all(bytes).map_err(RedisError::from);
+                let res = write(&mut connection.reader).map_err(RedisError::from);
                 match res {
                     Err(e) => {
                         if e.is_connection_dropped() {
@@ -477,7 +637,7 @@ impl ActualConnection {
             }
             #[cfg(unix)]
             ActualConnection::Unix(ref mut connection) => {
-                let result = connection.sock.write_all(bytes).map_err(RedisError::from);
+                let result = write(&mut connection.sock).map_err(RedisError::from);
                 match result {
                     Err(e) => {
                         if e.is_connection_dropped() {
@@ -496,7 +656,7 @@ impl ActualConnection {
             ActualConnection::Tcp(TcpConnection { ref reader, .. }) => {
                 reader.set_write_timeout(dur)?;
             }
-            #[cfg(feature = "tls")]
+            #[cfg(any(feature = "tls", feature = "tls-rustls"))]
             ActualConnection::TcpTls(TcpTlsConnection { ref reader, .. }) => {
                 reader.get_ref().set_write_timeout(dur)?;
             }
@@ -513,7 +673,7 @@ impl ActualConnection {
             ActualConnection::Tcp(TcpConnection { ref reader, .. }) => {
                 reader.set_read_timeout(dur)?;
             }
-            #[cfg(feature = "tls")]
+            #[cfg(any(feature = "tls", feature = "tls-rustls"))]
             ActualConnection::TcpTls(TcpTlsConnection { ref reader, .. }) => {
                 reader.get_ref().set_read_timeout(dur)?;
             }
@@ -528,7 +688,7 @@ impl ActualConnection {
     pub fn is_open(&self) -> bool {
         match *self {
             ActualConnection::Tcp(TcpConnection { open, .. }) => open,
-            #[cfg(feature = "tls")]
+            #[cfg(any(feature = "tls", feature = "tls-rustls"))]
             ActualConnection::TcpTls(TcpTlsConnection { open, .. }) => open,
             #[cfg(unix)]
             ActualConnection::Unix(UnixConnection { open, .. }) => open,
@@ -536,6 +696,48 @@ impl ActualConnection {
     }
 }
 
+// Connects to the address, or through the proxy of the connection info to
+// it if there is one, and applies the TCP settings.
+fn connect_tcp(
+    addr: (&str, u16),
+    info: &ConnectionInfo,
+    timeout: Option<Duration>,
+) -> RedisResult<TcpStream> {
+    let tcp = match info.proxy {
+        Some(ref proxy) => connect_tcp_tunnel(addr, proxy, timeout)?,
+        None => connect_tcp_direct(addr, timeout)?,
+    };
+    info.tcp_settings.apply(&tcp)?;
+    Ok(tcp)
+}
+
+fn connect_tcp_tunnel(
+    addr: (&str, u16),
+    proxy: &ProxyConfig,
+    timeout: Option<Duration>,
+) -> RedisResult<TcpStream> {
+    let tunnel = Tunnel::new(proxy, addr.0, addr.1)?;
+    let mut tcp = connect_tcp_direct((&proxy.host, proxy.port), timeout)?;
+    // The timeout bounds the handshake with the proxy as well.
+    tcp.set_read_timeout(timeout)?;
+    tcp.set_write_timeout(timeout)?;
+    tunnel.open(&mut tcp)?;
+    tcp.set_read_timeout(None)?;
+    tcp.set_write_timeout(None)?;
+    Ok(tcp)
+}
+
+fn connect_tcp_direct(addr: (&str, u16), timeout: Option<Duration>) -> RedisResult<TcpStream> {
+    let addrs: Vec<_> = addr.to_socket_addrs()?.collect();
+    if addrs.is_empty() {
+        fail!((
+            ErrorKind::InvalidClientConfig,
+            "could not resolve to any addresses"
+        ));
+    }
+    Ok(happy_eyeballs::connect(addrs, timeout)?)
+}
+
 fn connect_auth(con: &mut Connection, connection_info: &RedisConnectionInfo) -> RedisResult<()> {
     let mut command = cmd("AUTH");
     if let Some(username) = &connection_info.username {
@@ -574,26 +776,119 @@ fn connect_auth(con: &mut Connection, connection_info: &RedisConnectionInfo) ->
     }
 }
 
+// Builds the `HELLO` command which switches to RESP3 and authenticates.
+pub(crate) fn hello_command(connection_info: &RedisConnectionInfo) -> Cmd {
+    let mut command = cmd("HELLO");
+    command.arg(3);
+    if let Some(password) = &connection_info.password {
+        let username = connection_info.username.as_deref().unwrap_or("default");
+        command.arg("AUTH").arg(username).arg(password);
+    }
+    command
+}
+
+// Builds the `CLIENT SETINFO` commands announcing the library, if any.
+pub(crate) fn client_setinfo_pipeline(connection_info: &RedisConnectionInfo) -> Option<Pipeline> {
+    let (name, version) = match connection_info.library_info {
+        LibraryInfo::RedisRs => ("redis-rs".to_string(), env!("CARGO_PKG_VERSION")),
+        LibraryInfo::WithSuffix(ref suffix) => {
+            (format!("redis-rs({})", suffix), env!("CARGO_PKG_VERSION"))
+        }
+        LibraryInfo::Custom {
+            ref name,
+            ref version,
+        } => (name.clone(), version.as_str()),
+        LibraryInfo::Disabled => return None,
+    };
+    let mut pipeline = Pipeline::new();
+    pipeline
+        .cmd("CLIENT")
+        .arg("SETINFO")
+        .arg("LIB-NAME")
+        .arg(name)
+        .ignore()
+        .cmd("CLIENT")
+        .arg("SETINFO")
+        .arg("LIB-VER")
+        .arg(version)
+        .ignore();
+    Some(pipeline)
+}
+
+// Handles the failure of `CLIENT SETINFO`, which is only fatal if the
+// connection broke: older servers do not know the command and servers
+// reject names with spaces.
+pub(crate) fn client_setinfo_failed(err: RedisError) -> RedisResult<()> {
+    if err.is_io_error() {
+        Err(err)
+    } else {
+        Ok(())
+    }
+}
+
+// Handles the failure of a `HELLO` handshake.  Returns `Ok` if the server
+// does not support RESP3 and the connection should continue with RESP2.
+pub(crate) fn hello_failed(err: RedisError) -> RedisResult<()> {
+    match err.code() {
+        Some("NOPROTO") => Ok(()),
+        Some("ERR")
+            if err
+                .detail()
+                .is_some_and(|d| d.starts_with("unknown command")) =>
+        {
+            Ok(())
+        }
+        Some("WRONGPASS") | Some("NOPERM") => fail!((
+            ErrorKind::AuthenticationFailed,
+            "Password authentication failed",
+        )),
+        _ => Err(err),
+    }
+}
+
 pub fn connect(
     connection_info: &ConnectionInfo,
     timeout: Option<Duration>,
 ) -> RedisResult<Connection> {
-    let con = ActualConnection::new(&connection_info.addr, timeout)?;
-    setup_connection(con, &connection_info.redis)
+    let con = ActualConnection::new(connection_info, timeout)?;
+    setup_connection(con, connection_info)
 }
 
-fn setup_connection(
-    con: ActualConnection,
-    connection_info: &RedisConnectionInfo,
+// Sets up a connection over the already connected TCP stream.
+pub(crate) fn connect_with_tcp_stream(
+    tcp: TcpStream,
+    connection_info: &ConnectionInfo,
 ) -> RedisResult<Connection> {
+    connection_info.tcp_settings.apply(&tcp)?;
+    let con = ActualConnection::Tcp(TcpConnection {
+        reader: tcp,
+        open: true,
+    });
+    setup_connection(con, connection_info)
+}
+
+fn setup_connection(con: ActualConnection, info: &ConnectionInfo) -> RedisResult<Connection> {
+    let connection_info = &info.redis;
     let mut rv = Connection {
         con,
         parser: Parser::new(),
         db: connection_info.db,
+        protocol: ProtocolVersion::RESP2,
         pubsub: false,
+        pushes: VecDeque::new(),
+        #[cfg(feature = "tracing")]
+        traced: crate::instrument::TracedConnection::new(Some(&info.addr)),
+        metrics: None,
     };
 
-    if connection_info.password.is_some() {
+    if connection_info.protocol == ProtocolVersion::RESP3 {
+        match hello_command(connection_info).query::<Value>(&mut rv) {
+            Ok(_) => rv.protocol = ProtocolVersion::RESP3,
+            Err(err) => hello_failed(err)?,
+        }
+    }
+
+    if rv.protocol == ProtocolVersion::RESP2 && connection_info.password.is_some() {
         connect_auth(&mut rv, connection_info)?;
     }
 
@@ -610,6 +905,12 @@ fn setup_connection(
         }
     }
 
+    if let Some(pipeline) = client_setinfo_pipeline(connection_info) {
+        if let Err(err) = pipeline.query::<()>(&mut rv) {
+            client_setinfo_failed(err)?;
+        }
+    }
+
     Ok(rv)
 }
 
@@ -638,6 +939,18 @@ pub trait ConnectionLike {
         count: usize,
     ) -> RedisResult<Vec<Value>>;
 
+    /// Like `req_packed_commands` but the packed commands are split into
+    /// slices, which connections can send with vectored writes instead of
+    /// concatenating them.  This is used to send pipelines.
+    fn req_packed_commands_vectored(
+        &mut self,
+        cmds: &mut [&[u8]],
+        offset: usize,
+        count: usize,
+    ) -> RedisResult<Vec<Value>> {
+        self.req_packed_commands(&cmds.concat(), offset, count)
+    }
+
     /// Sends a [Cmd](Cmd) into the TCP socket and reads a single response from it.
     fn req_command(&mut self, cmd: &Cmd) -> RedisResult<Value> {
         let pcmd = cmd.get_packed_command();
@@ -682,14 +995,28 @@ impl Connection {
     /// `MONITOR` which yield multiple items.  This needs to be used with
     /// care because it changes the state of the connection.
     pub fn send_packed_command(&mut self, cmd: &[u8]) -> RedisResult<()> {
-        self.con.send_bytes(cmd)?;
-        Ok(())
+        self.send_bytes(cmd)
     }
 
     /// Fetches a single response from the connection.  This is useful
     /// if used in combination with `send_packed_command`.
+    ///
+    /// Over RESP3 this also returns the pubsub messages which were pushed
+    /// while the reply of a command was read.
     pub fn recv_response(&mut self) -> RedisResult<Value> {
-        self.read_response()
+        match self.pushes.pop_front() {
+            Some(push) => Ok(push),
+            None => self.read_with(|parser, reader| parser.parse_value(reader)),
+        }
+    }
+
+    /// Returns the protocol negotiated with the server.
+    pub fn protocol(&self) -> ProtocolVersion {
+        self.protocol
+    }
+
+    pub(crate) fn set_metrics_observer(&mut self, observer: Option<Arc<dyn MetricsObserver>>) {
+        self.metrics = observer;
     }
 
     /// Sets the write timeout for the connection.
@@ -710,6 +1037,126 @@ impl Connection {
         self.con.set_read_timeout(dur)
     }
 
+    /// Sets the limits on the size of the replies read from the connection.
+    ///
+    /// A reply exceeding a limit fails with `ErrorKind::ParseLimitExceeded`
+    /// and closes the connection.
+    pub fn set_parse_limits(&mut self, limits: ParseLimits) {
+        self.parser.set_limits(limits);
+    }
+
+    /// Enables or disables reading server errors nested in aggregates, for
+    /// instance in the reply of `EXEC`, into `Value::ServerError` values
+    /// instead of failing the whole reply.  This is disabled by default.
+    pub fn set_error_values(&mut self, enabled: bool) {
+        self.parser.set_error_values(enabled);
+    }
+
+    /// Sends a command and returns an iterator which reads and converts the
+    /// elements of its array reply one at a time.
+    ///
+    /// Unlike querying a `Vec<T>`, this never holds the whole reply in
+    /// memory, which matters for commands like `LRANGE` or `SMEMBERS` on
+    /// very large collections.  The connection is borrowed until the
+    /// iterator is dropped, which reads the elements left over.  The keys
+    /// and values of RESP3 map replies are read in turn, like the flat
+    /// arrays RESP2 sends for them.
+    ///
+    /// ```rust,no_run
+    /// # fn do_something() -> redis::RedisResult<()> {
+    /// # let client = redis::Client::open("redis://127.0.0.1/")?;
+    /// # let mut con = client.get_connection()?;
+    /// let mut total = 0;
+    /// for item in con.iter_reply::<i64>(redis::cmd("LRANGE").arg("numbers").arg(0).arg(-1))? {
+    ///     total += item?;
+    /// }
+    /// # Ok(()) }
+    /// ```
+    pub fn iter_reply<T: FromRedisValue>(&mut self, cmd: &Cmd) -> RedisResult<ReplyIter<'_, T>> {
+        if self.pubsub {
+            self.exit_pubsub()?;
+        }
+
+        self.send_bytes(&cmd.get_packed_command())?;
+        let state = match self.read_with(|parser, reader| parser.parse_head(reader))? {
+            ReplyHead::Aggregate(remaining) => ReplyState::Streamed(remaining),
+            ReplyHead::Value(Value::Bulk(items)) | ReplyHead::Value(Value::Set(items)) => {
+                ReplyState::Parsed(items.into_iter())
+            }
+            // only streamed maps are parsed as a whole
+            ReplyHead::Value(Value::Map(pairs)) => ReplyState::Parsed(
+                pairs
+                    .into_iter()
+                    .flat_map(|(key, value)| [key, value])
+                    .collect::<Vec<_>>()
+                    .into_iter(),
+            ),
+            ReplyHead::Value(Value::Nil) => ReplyState::Parsed(Vec::new().into_iter()),
+            ReplyHead::Value(v) => fail!((
+                ErrorKind::TypeError,
+                "Response was of incompatible type",
+                format!("{:?} (response was {:?})", "Response type not iterable", v)
+            )),
+        };
+        Ok(ReplyIter {
+            con: self,
+            state,
+            marker: PhantomData,
+        })
+    }
+
+    /// Sends `CONFIG GET` with the given glob pattern and returns an
+    /// iterator over the matching parameters and their values, which are
+    /// read one at a time like `iter_reply` does.
+    ///
+    /// ```rust,no_run
+    /// # fn do_something() -> redis::RedisResult<()> {
+    /// # let client = redis::Client::open("redis://127.0.0.1/")?;
+    /// # let mut con = client.get_connection()?;
+    /// for param in con.iter_config("*")? {
+    ///     let (name, value) = param?;
+    ///     println!("{} = {}", name, value);
+    /// }
+    /// # Ok(()) }
+    /// ```
+    pub fn iter_config(&mut self, pattern: &str) -> RedisResult<ConfigIter<'_>> {
+        let iter = self.iter_reply(cmd("CONFIG").arg("GET").arg(pattern))?;
+        Ok(ConfigIter(iter))
+    }
+
+    // Takes the stream out of the connection, to speak a protocol which is
+    // not made of replies, such as the replication stream.  The last reply
+    // must have been read completely.
+    #[cfg(feature = "replication")]
+    pub(crate) fn into_stream(self) -> Box<dyn RawStream> {
+        match self.con {
+            ActualConnection::Tcp(connection) => Box::new(connection.reader),
+            #[cfg(any(feature = "tls", feature = "tls-rustls"))]
+            ActualConnection::TcpTls(connection) => Box::new(connection.reader),
+            #[cfg(unix)]
+            ActualConnection::Unix(connection) => Box::new(connection.sock),
+        }
+    }
+
+    /// Switches the connection into monitor mode, in which the server
+    /// reports every command it processes.  Commands can not be sent over
+    /// the connection anymore.
+    ///
+    /// ```rust,no_run
+    /// # fn do_something() -> redis::RedisResult<()> {
+    /// let client = redis::Client::open("redis://127.0.0.1/")?;
+    /// let mut monitor = client.get_connection()?.monitor()?;
+    /// for entry in monitor.iter() {
+    ///     let entry = entry?;
+    ///     println!("[{} {}] {} {:?}", entry.db, entry.client, entry.command, entry.args);
+    /// }
+    /// # Ok(()) }
+    /// ```
+    pub fn monitor(mut self) -> RedisResult<Monitor> {
+        cmd("MONITOR").query::<()>(&mut self)?;
+        Ok(Monitor { con: self })
+    }
+
     /// Creates a [`PubSub`] instance for this connection.
     pub fn as_pubsub(&mut self) -> PubSub<'_> {
         // NOTE: The pubsub flag is intentionally not raised at this time since
@@ -722,6 +1169,7 @@ impl Connection {
         let res = self.clear_active_subscriptions();
         if res.is_ok() {
             self.pubsub = false;
+            self.pushes.clear();
         } else {
             // Raise the pubsub flag to indicate the connection is "stuck" in that state.
             self.pubsub = true;
@@ -745,13 +1193,9 @@ impl Connection {
             let unsubscribe = cmd("UNSUBSCRIBE").get_packed_command();
             let punsubscribe = cmd("PUNSUBSCRIBE").get_packed_command();
 
-            // Grab a reference to the underlying connection so that we may send
-            // the commands without immediately blocking for a response.
-            let con = &mut self.con;
-
-            // Execute commands
-            con.send_bytes(&unsubscribe)?;
-            con.send_bytes(&punsubscribe)?;
+            // Send the commands without immediately blocking for a response.
+            self.send_bytes(&unsubscribe)?;
+            self.send_bytes(&punsubscribe)?;
         }
 
         // Receive responses
@@ -762,7 +1206,11 @@ impl Connection {
         let mut received_unsub = false;
         let mut received_punsub = false;
         loop {
-            let res: (Vec<u8>, (), isize) = from_redis_value(&self.recv_response()?)?;
+            let value = self.recv_response()?;
+            if Msg::from_value(&value).is_some() {
+                continue;
+            }
+            let res: (Vec<u8>, (), isize) = from_redis_value(&subscription_reply(value))?;
 
             match res.0.first() {
                 Some(&b'u') => received_unsub = true,
@@ -780,26 +1228,90 @@ impl Connection {
         Ok(())
     }
 
+    fn send_bytes(&mut self, bytes: &[u8]) -> RedisResult<()> {
+        self.con.send_bytes(bytes)?;
+        if let Some(ref observer) = self.metrics {
+            observer.bytes_sent(bytes.len());
+        }
+        Ok(())
+    }
+
+    // Reads the responses of pipelined commands, skipping the first `offset`.
+    fn read_responses(&mut self, offset: usize, count: usize) -> RedisResult<Vec<Value>> {
+        let mut rv = vec![];
+        let mut first_err = None;
+        for idx in 0..(offset + count) {
+            // When processing a transaction, some responses may be errors.
+            // We need to keep processing the rest of the responses in that case,
+            // so bailing early with `?` would not be correct.
+            // See: https://github.com/mitsuhiko/redis-rs/issues/436
+            let response = self.read_response();
+            match response {
+                Ok(item) => {
+                    if idx >= offset {
+                        rv.push(item);
+                    }
+                }
+                Err(err) => {
+                    if first_err.is_none() {
+                        first_err = Some(err);
+                    }
+                }
+            }
+        }
+
+        if let Some(err) = first_err {
+            Err(err)
+        } else {
+            Ok(rv)
+        }
+    }
+
     /// Fetches a single response from the connection.
+    ///
+    /// Out of band push frames are not the reply of a command, pubsub
+    /// messages are kept for `recv_response` and the other ones, like
+    /// invalidations, are dropped.
     fn read_response(&mut self) -> RedisResult<Value> {
-        let result = match self.con {
-            ActualConnection::Tcp(TcpConnection { ref mut reader, .. }) => {
-                self.parser.parse_value(reader)
+        loop {
+            let value = self.read_with(|parser, reader| parser.parse_value(reader))?;
+            if !is_out_of_band(&value) {
+                return Ok(value);
             }
-            #[cfg(feature = "tls")]
-            ActualConnection::TcpTls(TcpTlsConnection { ref mut reader, .. }) => {
-                self.parser.parse_value(reader)
+            if Msg::from_value(&value).is_some() {
+                self.pushes.push_back(value);
             }
+        }
+    }
+
+    // Reads from the connection with the parser, shutting the connection
+    // down if the stream can not be continued after an error.
+    fn read_with<R, F>(&mut self, read: F) -> RedisResult<R>
+    where
+        F: FnOnce(&mut Parser, &mut dyn io::Read) -> RedisResult<R>,
+    {
+        let reader: &mut dyn io::Read = match self.con {
+            ActualConnection::Tcp(TcpConnection { ref mut reader, .. }) => reader,
+            #[cfg(any(feature = "tls", feature = "tls-rustls"))]
+            ActualConnection::TcpTls(TcpTlsConnection { ref mut reader, .. }) => reader,
             #[cfg(unix)]
-            ActualConnection::Unix(UnixConnection { ref mut sock, .. }) => {
-                self.parser.parse_value(sock)
+            ActualConnection::Unix(UnixConnection { ref mut sock, .. }) => sock,
+        };
+        let result = match self.metrics {
+            Some(ref observer) => {
+                let mut reader = CountingReader::new(reader);
+                let result = read(&mut self.parser, &mut reader);
+                observer.bytes_received(reader.count());
+                result
             }
+            None => read(&mut self.parser, reader),
         };
         // shutdown connection on protocol error
         if let Err(e) = &result {
+            // the rest of a reply exceeding a limit is still in the stream
             let shutdown = match e.as_io_error() {
                 Some(e) => e.kind() == io::ErrorKind::UnexpectedEof,
-                None => false,
+                None => e.kind() == ErrorKind::ParseLimitExceeded,
             };
             if shutdown {
                 match self.con {
@@ -807,11 +1319,18 @@ impl Connection {
                         let _ = connection.reader.shutdown(net::Shutdown::Both);
                         connection.open = false;
                     }
-                    #[cfg(feature = "tls")]
+                    #[cfg(all(feature = "tls", not(feature = "tls-rustls")))]
                     ActualConnection::TcpTls(ref mut connection) => {
                         let _ = connection.reader.shutdown();
                         connection.open = false;
                     }
+                    #[cfg(feature = "tls-rustls")]
+                    ActualConnection::TcpTls(ref mut connection) => {
+                        connection.reader.conn.send_close_notify();
+                        let _ = connection.reader.flush();
+                        let _ = connection.reader.sock.shutdown(net::Shutdown::Both);
+                        connection.open = false;
+                    }
                     #[cfg(unix)]
                     ActualConnection::Unix(ref mut connection) => {
                         let _ = connection.sock.shutdown(net::Shutdown::Both);
@@ -824,53 +1343,158 @@ impl Connection {
     }
 }
 
+/// An iterator over the elements of an array reply, see
+/// [`Connection::iter_reply`].
+pub struct ReplyIter<'a, T> {
+    con: &'a mut Connection,
+    state: ReplyState,
+    marker: PhantomData<fn() -> T>,
+}
+
+enum ReplyState {
+    // The number of elements still to be read from the connection.
+    Streamed(usize),
+    // A reply which was read as a whole, like a streamed RESP3 array.
+    Parsed(std::vec::IntoIter<Value>),
+}
+
+impl<'a, T> ReplyIter<'a, T> {
+    // Reads the next element from the connection.
+    fn read_element(&mut self) -> Option<RedisResult<Value>> {
+        match self.state {
+            ReplyState::Streamed(0) => None,
+            ReplyState::Streamed(ref mut remaining) => {
+                *remaining -= 1;
+                let result = self.con.read_response();
+                if let Err(ref err) = result {
+                    // a broken stream has no more elements
+                    if !self.con.is_open() || err.is_io_error() {
+                        *remaining = 0;
+                    }
+                }
+                Some(result)
+            }
+            ReplyState::Parsed(ref mut items) => items.next().map(Ok),
+        }
+    }
+}
+
+impl<'a, T: FromRedisValue> Iterator for ReplyIter<'a, T> {
+    type Item = RedisResult<T>;
+
+    fn next(&mut self) -> Option<RedisResult<T>> {
+        self.read_element()
+            .map(|result| result.and_then(|v| from_redis_value(&v)))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = match self.state {
+            ReplyState::Streamed(remaining) => remaining,
+            ReplyState::Parsed(ref items) => items.len(),
+        };
+        (len, Some(len))
+    }
+}
+
+impl<'a, T> Drop for ReplyIter<'a, T> {
+    fn drop(&mut self) {
+        // keeps the connection in sync with the server
+        while self.read_element().is_some() {}
+    }
+}
+
+/// An iterator over the parameters of a `CONFIG GET` reply and their
+/// values, see [`Connection::iter_config`].
+pub struct ConfigIter<'a>(ReplyIter<'a, String>);
+
+impl<'a> Iterator for ConfigIter<'a> {
+    type Item = RedisResult<(String, String)>;
+
+    fn next(&mut self) -> Option<RedisResult<(String, String)>> {
+        let name = self.0.next()?;
+        let value = match self.0.next() {
+            Some(value) => value,
+            None => {
+                return Some(Err((
+                    ErrorKind::TypeError,
+                    "Response was of incompatible type",
+                    "CONFIG GET reply without a value for the last parameter".to_string(),
+                )
+                    .into()))
+            }
+        };
+        Some(name.and_then(|name| Ok((name, value?))))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.0.size_hint().0.div_ceil(2);
+        (len, Some(len))
+    }
+}
+
 impl ConnectionLike for Connection {
     fn req_packed_command(&mut self, cmd: &[u8]) -> RedisResult<Value> {
         if self.pubsub {
             self.exit_pubsub()?;
         }
 
-        self.con.send_bytes(cmd)?;
+        self.send_bytes(cmd)?;
         self.read_response()
     }
 
+    fn req_command(&mut self, cmd: &Cmd) -> RedisResult<Value> {
+        let observer = self.metrics.clone();
+        #[cfg(feature = "tracing")]
+        let span = self.traced.command_span(cmd, self.db);
+        let request = || self.req_packed_command(&cmd.get_packed_command());
+        #[cfg(feature = "tracing")]
+        let request = || crate::instrument::traced(span, request);
+        observe(observer.as_deref(), Some(cmd), request)
+    }
+
     fn req_packed_commands(
         &mut self,
         cmd: &[u8],
         offset: usize,
         count: usize,
     ) -> RedisResult<Vec<Value>> {
-        if self.pubsub {
-            self.exit_pubsub()?;
-        }
-        self.con.send_bytes(cmd)?;
-        let mut rv = vec![];
-        let mut first_err = None;
-        for idx in 0..(offset + count) {
-            // When processing a transaction, some responses may be errors.
-            // We need to keep processing the rest of the responses in that case,
-            // so bailing early with `?` would not be correct.
-            // See: https://github.com/mitsuhiko/redis-rs/issues/436
-            let response = self.read_response();
-            match response {
-                Ok(item) => {
-                    if idx >= offset {
-                        rv.push(item);
-                    }
-                }
-                Err(err) => {
-                    if first_err.is_none() {
-                        first_err = Some(err);
-                    }
-                }
+        let observer = self.metrics.clone();
+        #[cfg(feature = "tracing")]
+        let span = self.traced.pipeline_span(offset + count, self.db);
+        let request = || {
+            if self.pubsub {
+                self.exit_pubsub()?;
             }
-        }
+            self.send_bytes(cmd)?;
+            self.read_responses(offset, count)
+        };
+        #[cfg(feature = "tracing")]
+        let request = || crate::instrument::traced(span, request);
+        observe(observer.as_deref(), None, request)
+    }
 
-        if let Some(err) = first_err {
-            Err(err)
-        } else {
-            Ok(rv)
-        }
+    fn req_packed_commands_vectored(
+        &mut self,
+        cmds: &mut [&[u8]],
+        offset: usize,
+        count: usize,
+    ) -> RedisResult<Vec<Value>> {
+        let observer = self.metrics.clone();
+        #[cfg(feature = "tracing")]
+        let span = self.traced.pipeline_span(offset + count, self.db);
+        let request = || {
+            if self.pubsub {
+                self.exit_pubsub()?;
+            }
+            self.con.send_slices(cmds)?;
+            if let Some(ref observer) = self.metrics {
+                observer.bytes_sent(cmds.iter().map(|cmd| cmd.len()).sum());
+            }
+            self.read_responses(offset, count)
+        };
+        #[cfg(feature = "tracing")]
+        let request = || crate::instrument::traced(span, request);
+        observe(observer.as_deref(), None, request)
     }
 
     fn get_db(&self) -> i64 {
@@ -958,6 +1582,67 @@ impl<'a> PubSub<'a> {
     }
 }
 
+impl Monitor {
+    /// Fetches the next command processed by the server.  Blocks until one
+    /// is available or the read timeout passed.
+    pub fn get_entry(&mut self) -> RedisResult<MonitorEntry> {
+        from_redis_value(&self.con.recv_response()?)
+    }
+
+    /// Returns an iterator of the commands processed by the server, which
+    /// ends after the first error.
+    pub fn iter(&mut self) -> impl Iterator<Item = RedisResult<MonitorEntry>> + '_ {
+        let mut failed = false;
+        std::iter::from_fn(move || {
+            if failed {
+                return None;
+            }
+            let entry = self.get_entry();
+            failed = entry.is_err();
+            Some(entry)
+        })
+    }
+
+    /// Sets the read timeout for the connection.
+    ///
+    /// If the provided value is `None`, then `get_entry` call will block
+    /// indefinitely. It is an error to pass the zero `Duration` to this
+    /// method.
+    pub fn set_read_timeout(&self, dur: Option<Duration>) -> RedisResult<()> {
+        self.con.set_read_timeout(dur)
+    }
+}
+
+// Whether the value is a push frame sent by the server on its own rather
+// than the reply of a command.  The confirmations of (un)subscriptions are
+// pushed too but are the replies of the commands.
+pub(crate) fn is_out_of_band(value: &Value) -> bool {
+    match value {
+        Value::Push { kind, .. } => !matches!(
+            kind.as_str(),
+            "subscribe"
+                | "psubscribe"
+                | "ssubscribe"
+                | "unsubscribe"
+                | "punsubscribe"
+                | "sunsubscribe"
+        ),
+        _ => false,
+    }
+}
+
+// Turns a pushed (un)subscription confirmation into the array RESP2 replies
+// with, `[kind, channel, count]`.
+pub(crate) fn subscription_reply(value: Value) -> Value {
+    match value {
+        Value::Push { kind, mut data } => {
+            data.insert(0, Value::Data(kind.into_bytes()));
+            Value::Bulk(data)
+        }
+        value => value,
+    }
+}
+
 impl<'a> Drop for PubSub<'a> {
     fn drop(&mut self) {
         let _ = self.con.exit_pubsub();
@@ -969,14 +1654,21 @@ impl<'a> Drop for PubSub<'a> {
 impl Msg {
     /// Tries to convert provided [`Value`] into [`Msg`].
     pub fn from_value(value: &Value) -> Option<Self> {
-        let raw_msg: Vec<Value> = from_redis_value(value).ok()?;
-        let mut iter = raw_msg.into_iter();
-        let msg_type: String = from_redis_value(&iter.next()?).ok()?;
+        let (msg_type, mut iter) = match value {
+            // RESP3 pushes the messages out of band
+            Value::Push { kind, data } => (kind.clone(), data.clone().into_iter()),
+            _ => {
+                let raw_msg: Vec<Value> = from_redis_value(value).ok()?;
+                let mut iter = raw_msg.into_iter();
+                let msg_type: String = from_redis_value(&iter.next()?).ok()?;
+                (msg_type, iter)
+            }
+        };
         let mut pattern = None;
         let payload;
         let channel;
 
-        if msg_type == "message" {
+        if msg_type == "message" || msg_type == "smessage" {
             channel = iter.next()?;
             payload = iter.next()?;
         } else if msg_type == "pmessage" {
@@ -1109,6 +1801,172 @@ pub fn transaction<
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_resp3_pushes_are_not_replies() {
+        use std::io::Read;
+
+        let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = std::thread::spawn(move || {
+            let (mut sock, _) = listener.accept().unwrap();
+            let mut buf = [0; 64];
+            let _ = sock.read(&mut buf).unwrap();
+            // a message and an invalidation arrive before the reply
+            sock.write_all(b">3\r\n$7\r\nmessage\r\n$2\r\nch\r\n$2\r\nhi\r\n")
+                .unwrap();
+            sock.write_all(b">2\r\n$10\r\ninvalidate\r\n*1\r\n$1\r\nk\r\n")
+                .unwrap();
+            sock.write_all(b"+PONG\r\n").unwrap();
+        });
+
+        let info = ConnectionInfo {
+            addr: ConnectionAddr::Tcp("127.0.0.1".into(), port),
+            redis: RedisConnectionInfo {
+                library_info: LibraryInfo::Disabled,
+                ..Default::default()
+            },
+            proxy: None,
+            tcp_settings: Default::default(),
+        };
+        let mut con = connect(&info, None).unwrap();
+        assert_eq!(cmd("PING").query::<String>(&mut con).unwrap(), "PONG");
+        server.join().unwrap();
+
+        let msg = Msg::from_value(&con.recv_response().unwrap()).unwrap();
+        assert_eq!(msg.get_channel_name(), "ch");
+        assert_eq!(msg.get_payload_bytes(), b"hi");
+    }
+
+    #[test]
+    fn test_error_values() {
+        use std::io::Read;
+
+        let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = std::thread::spawn(move || {
+            let (mut sock, _) = listener.accept().unwrap();
+            let mut buf = [0; 64];
+            for _ in 0..2 {
+                let _ = sock.read(&mut buf).unwrap();
+                sock.write_all(b"*2\r\n:1\r\n-ERR no\r\n").unwrap();
+            }
+        });
+
+        let info = ConnectionInfo {
+            addr: ConnectionAddr::Tcp("127.0.0.1".into(), port),
+            redis: RedisConnectionInfo {
+                library_info: LibraryInfo::Disabled,
+                ..Default::default()
+            },
+            proxy: None,
+            tcp_settings: Default::default(),
+        };
+        let mut con = connect(&info, None).unwrap();
+        let err = cmd("EXEC").query::<Value>(&mut con).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ResponseError);
+
+        con.set_error_values(true);
+        assert_eq!(
+            cmd("EXEC").query::<Value>(&mut con).unwrap(),
+            Value::Bulk(vec![Value::Int(1), Value::ServerError("ERR no".into())])
+        );
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_iter_config() {
+        use std::io::Read;
+
+        let params = (0..500)
+            .map(|i| (format!("param-{}", i), format!("value-{}", i)))
+            .collect::<Vec<_>>();
+        let mut array = format!("*{}\r\n", params.len() * 2).into_bytes();
+        let mut map = format!("%{}\r\n", params.len()).into_bytes();
+        let mut streamed = b"%?\r\n".to_vec();
+        for (name, value) in &params {
+            for reply in [&mut array, &mut map, &mut streamed] {
+                write!(reply, "${}\r\n{}\r\n", name.len(), name).unwrap();
+                write!(reply, "${}\r\n{}\r\n", value.len(), value).unwrap();
+            }
+        }
+        streamed.extend_from_slice(b".\r\n");
+
+        let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = std::thread::spawn(move || {
+            let (mut sock, _) = listener.accept().unwrap();
+            let mut buf = [0; 64];
+            for reply in [array, map, streamed] {
+                let _ = sock.read(&mut buf).unwrap();
+                sock.write_all(&reply).unwrap();
+            }
+        });
+
+        let info = ConnectionInfo {
+            addr: ConnectionAddr::Tcp("127.0.0.1".into(), port),
+            redis: RedisConnectionInfo {
+                library_info: LibraryInfo::Disabled,
+                ..Default::default()
+            },
+            proxy: None,
+            tcp_settings: Default::default(),
+        };
+        let mut con = connect(&info, None).unwrap();
+
+        // the pairs of the array are read as they are consumed
+        let mut iter = con.iter_config("*").unwrap();
+        assert_eq!(iter.size_hint(), (500, Some(500)));
+        assert_eq!(iter.next().unwrap().unwrap(), params[0]);
+        assert_eq!(iter.size_hint(), (499, Some(499)));
+        let rest = iter.collect::<RedisResult<Vec<_>>>().unwrap();
+        assert_eq!(rest, params[1..]);
+
+        // so are the entries of a map
+        let mut iter = con.iter_config("*").unwrap();
+        assert_eq!(iter.size_hint(), (500, Some(500)));
+        assert_eq!(iter.next().unwrap().unwrap(), params[0]);
+        assert_eq!(iter.size_hint(), (499, Some(499)));
+        let rest = iter.collect::<RedisResult<Vec<_>>>().unwrap();
+        assert_eq!(rest, params[1..]);
+
+        // a streamed map is read as a whole
+        let pairs = con
+            .iter_config("*")
+            .unwrap()
+            .collect::<RedisResult<Vec<_>>>()
+            .unwrap();
+        assert_eq!(pairs, params);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_msg_from_push() {
+        let push = Value::Push {
+            kind: "pmessage".into(),
+            data: vec![
+                Value::Data(b"c*".to_vec()),
+                Value::Data(b"ch".to_vec()),
+                Value::Data(b"hi".to_vec()),
+            ],
+        };
+        let msg = Msg::from_value(&push).unwrap();
+        assert_eq!(msg.get_pattern(), Ok("c*".to_string()));
+        assert_eq!(msg.get_channel_name(), "ch");
+        assert_eq!(msg.get_payload_bytes(), b"hi");
+        assert!(is_out_of_band(&push));
+
+        let confirmation = Value::Push {
+            kind: "subscribe".into(),
+            data: vec![Value::Data(b"ch".to_vec()), Value::Int(1)],
+        };
+        assert!(Msg::from_value(&confirmation).is_none());
+        assert!(!is_out_of_band(&confirmation));
+        assert_eq!(
+            from_redis_value::<(String, String, i64)>(&subscription_reply(confirmation)),
+            Ok(("subscribe".into(), "ch".into(), 1))
+        );
+    }
+
     #[test]
     fn test_parse_redis_url() {
         let cases = vec![
@@ -1137,6 +1995,17 @@ mod tests {
                 ConnectionInfo {
                     addr: ConnectionAddr::Tcp("127.0.0.1".to_string(), 6379),
                     redis: Default::default(),
+                    proxy: None,
+                    tcp_settings: Default::default(),
+                },
+            ),
+            (
+                url::Url::parse("redis://[::1]:7000").unwrap(),
+                ConnectionInfo {
+                    addr: ConnectionAddr::Tcp("::1".to_string(), 7000),
+                    redis: Default::default(),
+                    proxy: None,
+                    tcp_settings: Default::default(),
                 },
             ),
             (
@@ -1147,7 +2016,11 @@ mod tests {
                         db: 2,
                         username: Some("%johndoe%".to_string()),
                         password: Some("#@<>$".to_string()),
+                        protocol: ProtocolVersion::RESP2,
+                        library_info: LibraryInfo::default(),
                     },
+                    proxy: None,
+                    tcp_settings: Default::default(),
                 },
             ),
         ];
@@ -1172,6 +2045,121 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_url_protocol() {
+        for (url, expected) in &[
+            ("redis://127.0.0.1", ProtocolVersion::RESP2),
+            ("redis://127.0.0.1/?protocol=resp2", ProtocolVersion::RESP2),
+            ("redis://127.0.0.1/2?protocol=3", ProtocolVersion::RESP3),
+            ("redis://127.0.0.1/?protocol=resp3", ProtocolVersion::RESP3),
+        ] {
+            let info = url::Url::parse(url)
+                .unwrap()
+                .into_connection_info()
+                .unwrap();
+            assert_eq!(info.redis.protocol, *expected, "{}", url);
+        }
+        let err =
+            url_to_tcp_connection_info(url::Url::parse("redis://127.0.0.1/?protocol=4").unwrap())
+                .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidClientConfig);
+    }
+
+    #[test]
+    fn test_hello_command() {
+        let mut info = RedisConnectionInfo {
+            protocol: ProtocolVersion::RESP3,
+            ..Default::default()
+        };
+        assert_eq!(
+            hello_command(&info).get_packed_command(),
+            cmd("HELLO").arg(3).get_packed_command()
+        );
+        info.password = Some("secret".to_string());
+        assert_eq!(
+            hello_command(&info).get_packed_command(),
+            cmd("HELLO")
+                .arg(3)
+                .arg("AUTH")
+                .arg("default")
+                .arg("secret")
+                .get_packed_command()
+        );
+        info.username = Some("alice".to_string());
+        assert_eq!(
+            hello_command(&info).get_packed_command(),
+            cmd("HELLO")
+                .arg(3)
+                .arg("AUTH")
+                .arg("alice")
+                .arg("secret")
+                .get_packed_command()
+        );
+    }
+
+    #[test]
+    fn test_client_setinfo_pipeline() {
+        let setinfo = |name: &str, version: &str| {
+            pipe()
+                .cmd("CLIENT")
+                .arg("SETINFO")
+                .arg("LIB-NAME")
+                .arg(name)
+                .cmd("CLIENT")
+                .arg("SETINFO")
+                .arg("LIB-VER")
+                .arg(version)
+                .get_packed_pipeline()
+        };
+        let mut info = RedisConnectionInfo::default();
+        assert_eq!(
+            client_setinfo_pipeline(&info)
+                .unwrap()
+                .get_packed_pipeline(),
+            setinfo("redis-rs", env!("CARGO_PKG_VERSION"))
+        );
+        info.library_info = LibraryInfo::WithSuffix("app_v1".to_string());
+        assert_eq!(
+            client_setinfo_pipeline(&info)
+                .unwrap()
+                .get_packed_pipeline(),
+            setinfo("redis-rs(app_v1)", env!("CARGO_PKG_VERSION"))
+        );
+        info.library_info = LibraryInfo::Custom {
+            name: "wrapper".to_string(),
+            version: "2.0".to_string(),
+        };
+        assert_eq!(
+            client_setinfo_pipeline(&info)
+                .unwrap()
+                .get_packed_pipeline(),
+            setinfo("wrapper", "2.0")
+        );
+        info.library_info = LibraryInfo::Disabled;
+        assert!(client_setinfo_pipeline(&info).is_none());
+    }
+
+    #[test]
+    fn test_hello_failed() {
+        use crate::parse_redis_value;
+
+        let fallback = |reply: &[u8]| hello_failed(parse_redis_value(reply).unwrap_err());
+        assert!(
+            fallback(b"-ERR unknown command `HELLO`, with args beginning with: `3`, \r\n").is_ok()
+        );
+        assert!(fallback(b"-NOPROTO unsupported protocol version\r\n").is_ok());
+        assert_eq!(
+            fallback(b"-WRONGPASS invalid username-password pair\r\n")
+                .unwrap_err()
+                .kind(),
+            ErrorKind::AuthenticationFailed
+        );
+        assert_eq!(
+            fallback(b"-ERR Protocol error\r\n").unwrap_err().kind(),
+            ErrorKind::ResponseError
+        );
+    }
+
     #[test]
     fn test_url_to_tcp_connection_info_failed() {
         let cases = vec![
@@ -1218,7 +2206,11 @@ mod tests {
                         db: 0,
                         username: None,
                         password: None,
+                        protocol: ProtocolVersion::RESP2,
+                        library_info: LibraryInfo::default(),
                     },
+                    proxy: None,
+                    tcp_settings: Default::default(),
                 },
             ),
             (
@@ -1229,7 +2221,11 @@ mod tests {
                         db: 1,
                         username: None,
                         password: None,
+                        protocol: ProtocolVersion::RESP2,
+                        library_info: LibraryInfo::default(),
                     },
+                    proxy: None,
+                    tcp_settings: Default::default(),
                 },
             ),
             (
@@ -1243,7 +2239,11 @@ mod tests {
                         db: 2,
                         username: Some("%johndoe%".to_string()),
                         password: Some("#@<>$".to_string()),
+                        protocol: ProtocolVersion::RESP2,
+                        library_info: LibraryInfo::default(),
                     },
+                    proxy: None,
+                    tcp_settings: Default::default(),
                 },
             ),
             (
@@ -1257,7 +2257,11 @@ mod tests {
                         db: 2,
                         username: Some("%johndoe%".to_string()),
                         password: Some("&?= *+".to_string()),
+                        protocol: ProtocolVersion::RESP2,
+                        library_info: LibraryInfo::default(),
                     },
+                    proxy: None,
+                    tcp_settings: Default::default(),
                 },
             ),
         ];
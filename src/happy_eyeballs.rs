@@ -0,0 +1,182 @@
+// Connecting to the addresses of a hostname as described by RFC 8305, "Happy
+// Eyeballs".
+//
+// The addresses are tried alternating between IPv6 and IPv4, starting with
+// the family of the first address.  The next address is tried as soon as an
+// attempt fails or after `CONNECTION_ATTEMPT_DELAY` if it did not complete,
+// without cancelling the attempts in flight.  The first connection wins and
+// the others are dropped.
+use std::io;
+use std::net::{SocketAddr, TcpStream};
+#[cfg(not(target_os = "wasi"))]
+use std::sync::mpsc;
+#[cfg(not(target_os = "wasi"))]
+use std::thread;
+use std::time::Duration;
+
+// How long an attempt is waited for before the next address is tried too.
+#[cfg(any(not(target_os = "wasi"), feature = "aio"))]
+const CONNECTION_ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+// Orders the addresses alternating between the address families, keeping
+// the order of the addresses of each family.
+fn interleave(addrs: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let first_v6 = match addrs.first() {
+        Some(addr) => addr.is_ipv6(),
+        None => return addrs,
+    };
+    let (mut preferred, mut other): (Vec<_>, Vec<_>) = addrs
+        .into_iter()
+        .partition(|addr| addr.is_ipv6() == first_v6);
+    let mut preferred = preferred.drain(..);
+    let mut other = other.drain(..);
+    let mut interleaved = Vec::with_capacity(preferred.len() + other.len());
+    loop {
+        match (preferred.next(), other.next()) {
+            (None, None) => return interleaved,
+            (first, second) => interleaved.extend(first.into_iter().chain(second)),
+        }
+    }
+}
+
+fn no_addresses() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        "could not resolve to any addresses",
+    )
+}
+
+fn connect_one(addr: SocketAddr, timeout: Option<Duration>) -> io::Result<TcpStream> {
+    match timeout {
+        Some(timeout) => TcpStream::connect_timeout(&addr, timeout),
+        None => TcpStream::connect(addr),
+    }
+}
+
+// Connects to the first of the addresses which accepts the connection, each
+// attempt bounded by the timeout.
+#[cfg(not(target_os = "wasi"))]
+pub(crate) fn connect(addrs: Vec<SocketAddr>, timeout: Option<Duration>) -> io::Result<TcpStream> {
+    let mut addrs = interleave(addrs).into_iter();
+    let first = addrs.next().ok_or_else(no_addresses)?;
+    if addrs.len() == 0 {
+        return connect_one(first, timeout);
+    }
+
+    // The attempts run on threads of their own, the ones still in flight
+    // when a connection was established finish in the background.
+    let (sender, receiver) = mpsc::channel();
+    let attempt = |addr| {
+        let sender = sender.clone();
+        thread::spawn(move || {
+            let _ = sender.send(connect_one(addr, timeout));
+        });
+    };
+    attempt(first);
+    let mut pending = 1;
+    let mut last_error = None;
+    loop {
+        match receiver.recv_timeout(CONNECTION_ATTEMPT_DELAY) {
+            Ok(Ok(tcp)) => return Ok(tcp),
+            Ok(Err(err)) => {
+                pending -= 1;
+                last_error = Some(err);
+            }
+            Err(_) => {}
+        }
+        match addrs.next() {
+            Some(addr) => {
+                attempt(addr);
+                pending += 1;
+            }
+            None if pending == 0 => return Err(last_error.unwrap_or_else(no_addresses)),
+            None => {}
+        }
+    }
+}
+
+// WASI has no threads, so there the addresses are tried one after the other.
+#[cfg(target_os = "wasi")]
+pub(crate) fn connect(addrs: Vec<SocketAddr>, timeout: Option<Duration>) -> io::Result<TcpStream> {
+    let mut last_error = None;
+    for addr in interleave(addrs) {
+        match connect_one(addr, timeout) {
+            Ok(tcp) => return Ok(tcp),
+            Err(err) => last_error = Some(err),
+        }
+    }
+    Err(last_error.unwrap_or_else(no_addresses))
+}
+
+// Async version of `connect`, establishing the connections with `connect`.
+#[cfg(feature = "aio")]
+pub(crate) async fn connect_async<S, F, Fut>(addrs: Vec<SocketAddr>, connect: F) -> io::Result<S>
+where
+    F: Fn(SocketAddr) -> Fut,
+    Fut: std::future::Future<Output = io::Result<S>>,
+{
+    use futures_util::future::{self, Either};
+    use futures_util::stream::{FuturesUnordered, StreamExt};
+
+    let runtime = crate::aio::Runtime::locate();
+    let mut addrs = interleave(addrs).into_iter();
+    let first = addrs.next().ok_or_else(no_addresses)?;
+    if addrs.len() == 0 {
+        return connect(first).await;
+    }
+
+    let mut attempts = FuturesUnordered::new();
+    attempts.push(connect(first));
+    let mut last_error = None;
+    loop {
+        let delay = Box::pin(runtime.sleep(CONNECTION_ATTEMPT_DELAY));
+        let finished = match future::select(attempts.next(), delay).await {
+            Either::Left((finished, _)) => finished,
+            Either::Right(_) => None,
+        };
+        match finished {
+            Some(Ok(con)) => return Ok(con),
+            Some(Err(err)) => last_error = Some(err),
+            None => {}
+        }
+        match addrs.next() {
+            Some(addr) => attempts.push(connect(addr)),
+            None if attempts.is_empty() => return Err(last_error.unwrap_or_else(no_addresses)),
+            None => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    #[test]
+    fn test_interleave() {
+        let addrs: Vec<SocketAddr> = vec![
+            "[::1]:1".parse().unwrap(),
+            "[::2]:1".parse().unwrap(),
+            "[::3]:1".parse().unwrap(),
+            "10.0.0.1:1".parse().unwrap(),
+        ];
+        assert_eq!(
+            interleave(addrs.clone()),
+            vec![addrs[0], addrs[3], addrs[1], addrs[2]]
+        );
+        assert_eq!(interleave(vec![]), vec![]);
+    }
+
+    #[test]
+    fn test_connect_skips_failed_address() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let closed = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let addr = listener.local_addr().unwrap();
+        let tcp = connect(vec![closed, addr], Some(Duration::from_secs(5))).unwrap();
+        assert_eq!(tcp.peer_addr().unwrap(), addr);
+        assert!(connect(vec![closed, closed], None).is_err());
+    }
+}
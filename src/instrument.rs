@@ -0,0 +1,328 @@
+// Spans for the commands sent with the `tracing` feature.
+//
+// A span is named `redis.command` and records the name of the command, its
+// key, the connection it was sent on, how long it took and its outcome.  The
+// spans of a cluster connection record the slot of the key instead of the
+// connection, the commands sent to the nodes get spans of their own.  Other
+// arguments are never recorded, so the credentials sent with `AUTH` or
+// `HELLO` and the values written do not end up in traces.  Pipelines get a
+// single `redis.pipeline` span recording the number of commands sent,
+// including the `MULTI` and `EXEC` of transactions.
+//
+// With the `otel` feature the spans also follow the OpenTelemetry database
+// conventions: `otel.name` and `db.operation` hold the command name, or
+// `PIPELINE`, and `db.system`, `db.redis.database_index`,
+// `db.redis.pipeline_length`, `net.peer.name` and `net.peer.port` are
+// recorded.  Failed requests set `otel.status_code` to `ERROR` and
+// `otel.status_message` to the kind of the error.
+use std::sync::atomic::{AtomicU64, Ordering};
+#[cfg(feature = "otel")]
+use std::sync::Arc;
+use std::time::Instant;
+
+use tracing::{field, Span};
+
+use crate::cmd::{Arg, Cmd};
+use crate::connection::ConnectionAddr;
+use crate::types::RedisResult;
+
+// Commands whose first argument is not a key, or whose arguments hold
+// credentials.
+const KEYLESS_COMMANDS: &[&[u8]] = &[
+    b"ACL",
+    b"AUTH",
+    b"BGREWRITEAOF",
+    b"BGSAVE",
+    b"CLIENT",
+    b"CLUSTER",
+    b"COMMAND",
+    b"CONFIG",
+    b"DBSIZE",
+    b"DEBUG",
+    b"DISCARD",
+    b"ECHO",
+    b"EXEC",
+    b"FLUSHALL",
+    b"FLUSHDB",
+    b"FUNCTION",
+    b"HELLO",
+    b"INFO",
+    b"LASTSAVE",
+    b"MIGRATE",
+    b"MODULE",
+    b"MONITOR",
+    b"MULTI",
+    b"PING",
+    b"PSUBSCRIBE",
+    b"PUBLISH",
+    b"PUNSUBSCRIBE",
+    b"QUIT",
+    b"READONLY",
+    b"READWRITE",
+    b"REPLICAOF",
+    b"SAVE",
+    b"SCAN",
+    b"SCRIPT",
+    b"SELECT",
+    b"SHUTDOWN",
+    b"SLAVEOF",
+    b"SLOWLOG",
+    b"SUBSCRIBE",
+    b"SWAPDB",
+    b"TIME",
+    b"UNSUBSCRIBE",
+    b"UNWATCH",
+    b"WAIT",
+];
+
+// Declares the fields of every span, the ones of the OpenTelemetry
+// conventions are only recorded with the `otel` feature.
+macro_rules! redis_span {
+    ($name:literal, $operation:expr) => {{
+        let operation: &str = $operation;
+        let span = tracing::debug_span!(
+            $name,
+            command = operation,
+            key = field::Empty,
+            slot = field::Empty,
+            commands = field::Empty,
+            connection_id = field::Empty,
+            duration_us = field::Empty,
+            outcome = field::Empty,
+            otel.name = field::Empty,
+            otel.kind = field::Empty,
+            otel.status_code = field::Empty,
+            otel.status_message = field::Empty,
+            db.system = field::Empty,
+            db.operation = field::Empty,
+            db.redis.database_index = field::Empty,
+            db.redis.pipeline_length = field::Empty,
+            net.peer.name = field::Empty,
+            net.peer.port = field::Empty,
+        );
+        #[cfg(feature = "otel")]
+        {
+            span.record("otel.name", operation);
+            span.record("otel.kind", "client");
+            span.record("db.system", "redis");
+            span.record("db.operation", operation);
+        }
+        span
+    }};
+}
+
+static NEXT_CONNECTION_ID: AtomicU64 = AtomicU64::new(1);
+
+// What the spans of the commands sent on a connection record about it.
+#[derive(Clone, Debug)]
+pub(crate) struct TracedConnection {
+    id: u64,
+    #[cfg(feature = "otel")]
+    peer: Option<Arc<Peer>>,
+}
+
+#[cfg(feature = "otel")]
+#[derive(Debug)]
+struct Peer {
+    name: String,
+    port: Option<u16>,
+}
+
+impl TracedConnection {
+    // Gives a new connection to the given server, if known, the next id.
+    #[cfg_attr(not(feature = "otel"), allow(unused_variables))]
+    pub(crate) fn new(addr: Option<&ConnectionAddr>) -> TracedConnection {
+        #[cfg_attr(not(feature = "otel"), allow(unused_mut))]
+        let mut traced = TracedConnection {
+            id: NEXT_CONNECTION_ID.fetch_add(1, Ordering::Relaxed),
+            #[cfg(feature = "otel")]
+            peer: None,
+        };
+        #[cfg(feature = "otel")]
+        if let Some(addr) = addr {
+            traced.set_peer(addr);
+        }
+        traced
+    }
+
+    // Records the server the connection is connected to.
+    #[cfg(feature = "otel")]
+    pub(crate) fn set_peer(&mut self, addr: &ConnectionAddr) {
+        let peer = match *addr {
+            ConnectionAddr::Tcp(ref host, port) | ConnectionAddr::TcpTls { ref host, port, .. } => {
+                Peer {
+                    name: host.clone(),
+                    port: Some(port),
+                }
+            }
+            ConnectionAddr::Unix(ref path) => Peer {
+                name: path.display().to_string(),
+                port: None,
+            },
+        };
+        self.peer = Some(Arc::new(peer));
+    }
+
+    // The span of a command sent on the connection, which uses the given
+    // database.
+    pub(crate) fn command_span(&self, cmd: &Cmd, db: i64) -> Span {
+        let span = span(cmd);
+        self.record(&span, db);
+        span
+    }
+
+    // The span of a pipeline of `commands` commands sent on the connection.
+    pub(crate) fn pipeline_span(&self, commands: usize, db: i64) -> Span {
+        let span = redis_span!("redis.pipeline", "PIPELINE");
+        span.record("commands", commands);
+        #[cfg(feature = "otel")]
+        span.record("db.redis.pipeline_length", commands);
+        self.record(&span, db);
+        span
+    }
+
+    #[cfg_attr(not(feature = "otel"), allow(unused_variables))]
+    fn record(&self, span: &Span, db: i64) {
+        span.record("connection_id", self.id);
+        #[cfg(feature = "otel")]
+        {
+            span.record("db.redis.database_index", db);
+            if let Some(ref peer) = self.peer {
+                span.record("net.peer.name", peer.name.as_str());
+                if let Some(port) = peer.port {
+                    span.record("net.peer.port", port);
+                }
+            }
+        }
+    }
+}
+
+fn arg(cmd: &Cmd, idx: usize) -> Option<&[u8]> {
+    match cmd.args_iter().nth(idx)? {
+        Arg::Simple(arg) => Some(arg),
+        Arg::Cursor => None,
+    }
+}
+
+// The key the command operates on, if it has one.
+fn command_key(cmd: &Cmd) -> Option<&[u8]> {
+    let name = arg(cmd, 0)?.to_ascii_uppercase();
+    match &name[..] {
+        name if KEYLESS_COMMANDS.contains(&name) => None,
+        b"EVAL" | b"EVALSHA" | b"EVAL_RO" | b"EVALSHA_RO" | b"FCALL" | b"FCALL_RO" => {
+            match arg(cmd, 2)? {
+                b"0" => None,
+                _ => arg(cmd, 3),
+            }
+        }
+        b"XGROUP" | b"XINFO" => arg(cmd, 2),
+        b"XREAD" | b"XREADGROUP" => {
+            let streams = cmd.args_iter().position(|arg| match arg {
+                Arg::Simple(arg) => arg.eq_ignore_ascii_case(b"STREAMS"),
+                Arg::Cursor => false,
+            })?;
+            arg(cmd, streams + 1)
+        }
+        _ => arg(cmd, 1),
+    }
+}
+
+fn span(cmd: &Cmd) -> Span {
+    let name = arg(cmd, 0).map(|name| String::from_utf8_lossy(name).to_ascii_uppercase());
+    // the span of a command is a child of the span attached to it, if any
+    #[cfg(feature = "otel")]
+    let _parent = cmd.attached_span().map(Span::enter);
+    let span = redis_span!("redis.command", name.as_deref().unwrap_or(""));
+    if !span.is_disabled() {
+        if let Some(key) = command_key(cmd) {
+            span.record("key", field::display(String::from_utf8_lossy(key)));
+        }
+    }
+    span
+}
+
+// The span of a command sent on a cluster connection.
+#[cfg(feature = "cluster")]
+pub(crate) fn cluster_command_span(cmd: &Cmd) -> Span {
+    use crate::cluster_routing::RoutingInfo;
+
+    let span = span(cmd);
+    if !span.is_disabled() {
+        if let Some(RoutingInfo::Slot(slot)) = RoutingInfo::for_routable(cmd) {
+            span.record("slot", slot);
+        }
+    }
+    span
+}
+
+fn record_outcome<T>(span: &Span, start: Instant, result: &RedisResult<T>) {
+    span.record("duration_us", start.elapsed().as_micros() as u64);
+    match result {
+        Ok(_) => span.record("outcome", "ok"),
+        Err(err) => {
+            #[cfg(feature = "otel")]
+            {
+                span.record("otel.status_code", "ERROR");
+                span.record("otel.status_message", field::debug(err.kind()));
+            }
+            span.record("outcome", err.category())
+        }
+    };
+}
+
+// Sends a request inside the span.
+pub(crate) fn traced<T, F>(span: Span, request: F) -> RedisResult<T>
+where
+    F: FnOnce() -> RedisResult<T>,
+{
+    let _entered = span.enter();
+    let start = Instant::now();
+    let result = request();
+    record_outcome(&span, start, &result);
+    result
+}
+
+// Sends an async request inside the span.
+#[cfg(feature = "aio")]
+pub(crate) async fn traced_async<T, F>(span: Span, request: F) -> RedisResult<T>
+where
+    F: std::future::Future<Output = RedisResult<T>>,
+{
+    use tracing::Instrument;
+
+    let start = Instant::now();
+    let result = request.instrument(span.clone()).await;
+    record_outcome(&span, start, &result);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cmd::cmd;
+
+    #[test]
+    fn test_command_key() {
+        assert_eq!(command_key(cmd("GET").arg("user:1")), Some(&b"user:1"[..]));
+        assert_eq!(command_key(cmd("get").arg("user:1")), Some(&b"user:1"[..]));
+        assert_eq!(command_key(&cmd("PING")), None);
+        assert_eq!(command_key(cmd("AUTH").arg("user").arg("secret")), None);
+        assert_eq!(command_key(cmd("HELLO").arg(3).arg("AUTH")), None);
+        assert_eq!(
+            command_key(cmd("EVALSHA").arg("abc").arg(1).arg("script-key")),
+            Some(&b"script-key"[..])
+        );
+        assert_eq!(command_key(cmd("EVAL").arg("return 1").arg(0)), None);
+        assert_eq!(
+            command_key(
+                cmd("XREAD")
+                    .arg("COUNT")
+                    .arg(2)
+                    .arg("STREAMS")
+                    .arg("events")
+                    .arg(0)
+            ),
+            Some(&b"events"[..])
+        );
+    }
+}
@@ -0,0 +1,280 @@
+//! Hooks to collect metrics about the connections.
+//!
+//! Implement [`MetricsObserver`] to feed the commands, the traffic and the
+//! reconnects of the connections into a metrics system like Prometheus or
+//! StatsD, and set it with `Client::with_metrics_observer` or
+//! `ClusterClientBuilder::metrics_observer`.  The observer is called on the
+//! thread or task sending the commands, so its methods should be as cheap as
+//! updating a counter.
+//!
+//! ```rust,no_run
+//! # fn do_something() -> redis::RedisResult<()> {
+//! use std::sync::atomic::{AtomicU64, Ordering};
+//! use std::time::Duration;
+//! use redis::instrumentation::MetricsObserver;
+//!
+//! #[derive(Default)]
+//! struct Metrics {
+//!     commands: AtomicU64,
+//!     errors: AtomicU64,
+//! }
+//!
+//! impl MetricsObserver for Metrics {
+//!     fn command_finished(&self, _: &str, _: Duration, error: Option<&redis::RedisError>) {
+//!         self.commands.fetch_add(1, Ordering::Relaxed);
+//!         if error.is_some() {
+//!             self.errors.fetch_add(1, Ordering::Relaxed);
+//!         }
+//!     }
+//! }
+//!
+//! let client = redis::Client::open("redis://127.0.0.1/")?
+//!     .with_metrics_observer(Metrics::default());
+//! let mut con = client.get_connection()?;
+//! # Ok(()) }
+//! ```
+use std::io;
+use std::time::{Duration, Instant};
+
+use crate::cmd::{Arg, Cmd};
+use crate::types::{RedisError, RedisResult};
+
+/// Observes the commands and the traffic of connections.
+///
+/// Every method does nothing by default, so an implementation only needs to
+/// provide the ones it records.
+pub trait MetricsObserver: Send + Sync {
+    /// Called before a command is sent.  Pipelines and transactions are
+    /// reported as a single command named `PIPELINE`.
+    fn command_started(&self, _command: &str) {}
+
+    /// Called once the reply of a command was received, with how long it
+    /// took and the error if the command failed.
+    fn command_finished(&self, _command: &str, _duration: Duration, _error: Option<&RedisError>) {}
+
+    /// Called with the number of bytes written to the server.
+    fn bytes_sent(&self, _bytes: usize) {}
+
+    /// Called with the number of bytes read from the server.
+    fn bytes_received(&self, _bytes: usize) {}
+
+    /// Called with the number of requests a multiplexed connection awaits
+    /// the replies of whenever it changes.
+    fn queue_depth(&self, _depth: usize) {}
+
+    /// Called after a `ConnectionManager` or a cluster connection
+    /// reconnected.
+    fn reconnected(&self) {}
+}
+
+// The name reported for the command, or for a pipeline if it is `None`.
+fn command_name(cmd: Option<&Cmd>) -> String {
+    match cmd.and_then(|cmd| cmd.args_iter().next()) {
+        Some(Arg::Simple(name)) => String::from_utf8_lossy(name).to_ascii_uppercase(),
+        Some(Arg::Cursor) => String::new(),
+        None => "PIPELINE".to_string(),
+    }
+}
+
+// Sends a request, reporting it to the observer if there is one.
+pub(crate) fn observe<T, F>(
+    observer: Option<&dyn MetricsObserver>,
+    cmd: Option<&Cmd>,
+    request: F,
+) -> RedisResult<T>
+where
+    F: FnOnce() -> RedisResult<T>,
+{
+    let observer = match observer {
+        Some(observer) => observer,
+        None => return request(),
+    };
+    let name = command_name(cmd);
+    observer.command_started(&name);
+    let start = Instant::now();
+    let result = request();
+    observer.command_finished(&name, start.elapsed(), result.as_ref().err());
+    result
+}
+
+// Sends an async request, reporting it to the observer if there is one.
+#[cfg(feature = "aio")]
+pub(crate) async fn observe_async<T, F>(
+    observer: Option<std::sync::Arc<dyn MetricsObserver>>,
+    cmd: Option<&Cmd>,
+    request: F,
+) -> RedisResult<T>
+where
+    F: std::future::Future<Output = RedisResult<T>>,
+{
+    let observer = match observer {
+        Some(observer) => observer,
+        None => return request.await,
+    };
+    let name = command_name(cmd);
+    observer.command_started(&name);
+    let start = Instant::now();
+    let result = request.await;
+    observer.command_finished(&name, start.elapsed(), result.as_ref().err());
+    result
+}
+
+// Counts the bytes read through it.
+pub(crate) struct CountingReader<'a> {
+    inner: &'a mut dyn io::Read,
+    count: usize,
+}
+
+impl<'a> CountingReader<'a> {
+    pub(crate) fn new(inner: &'a mut dyn io::Read) -> Self {
+        CountingReader { inner, count: 0 }
+    }
+
+    pub(crate) fn count(&self) -> usize {
+        self.count
+    }
+}
+
+impl<'a> io::Read for CountingReader<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.count += read;
+        Ok(read)
+    }
+}
+
+#[cfg(feature = "aio")]
+pub(crate) use self::aio::ObservedStream;
+
+#[cfg(feature = "aio")]
+mod aio {
+    use std::io::{self, IoSlice};
+    use std::pin::Pin;
+    use std::sync::Arc;
+    use std::task::{Context, Poll};
+
+    use pin_project_lite::pin_project;
+    use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+    use super::MetricsObserver;
+
+    pin_project! {
+        // A stream reporting the bytes transferred through it to the
+        // observer, if there is one.
+        pub(crate) struct ObservedStream<S> {
+            #[pin]
+            inner: S,
+            observer: Option<Arc<dyn MetricsObserver>>,
+        }
+    }
+
+    impl<S> ObservedStream<S> {
+        pub(crate) fn new(inner: S, observer: Option<Arc<dyn MetricsObserver>>) -> Self {
+            ObservedStream { inner, observer }
+        }
+    }
+
+    impl<S: AsyncRead> AsyncRead for ObservedStream<S> {
+        fn poll_read(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<io::Result<()>> {
+            let this = self.project();
+            let filled = buf.filled().len();
+            let result = this.inner.poll_read(cx, buf);
+            if let Some(observer) = this.observer {
+                let read = buf.filled().len() - filled;
+                if read > 0 {
+                    observer.bytes_received(read);
+                }
+            }
+            result
+        }
+    }
+
+    impl<S: AsyncWrite> AsyncWrite for ObservedStream<S> {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            let this = self.project();
+            let result = this.inner.poll_write(cx, buf);
+            if let (Some(observer), Poll::Ready(Ok(written))) = (this.observer, &result) {
+                observer.bytes_sent(*written);
+            }
+            result
+        }
+
+        fn poll_write_vectored(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            bufs: &[IoSlice<'_>],
+        ) -> Poll<io::Result<usize>> {
+            let this = self.project();
+            let result = this.inner.poll_write_vectored(cx, bufs);
+            if let (Some(observer), Poll::Ready(Ok(written))) = (this.observer, &result) {
+                observer.bytes_sent(*written);
+            }
+            result
+        }
+
+        fn is_write_vectored(&self) -> bool {
+            self.inner.is_write_vectored()
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            self.project().inner.poll_flush(cx)
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            self.project().inner.poll_shutdown(cx)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cmd::cmd;
+    use crate::types::ErrorKind;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<String>>);
+
+    impl MetricsObserver for Recorder {
+        fn command_started(&self, command: &str) {
+            self.0.lock().unwrap().push(format!("started {}", command));
+        }
+
+        fn command_finished(&self, command: &str, _: Duration, error: Option<&RedisError>) {
+            let outcome = error.map_or("ok", |err| err.category());
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("finished {} {}", command, outcome));
+        }
+    }
+
+    #[test]
+    fn test_observe() {
+        let recorder = Recorder::default();
+        let get = cmd("get").arg("key").clone();
+        assert_eq!(observe(Some(&recorder), Some(&get), || Ok(1)), Ok(1));
+        let failed: RedisResult<()> = observe(Some(&recorder), None, || {
+            Err((ErrorKind::TypeError, "Wrong").into())
+        });
+        assert!(failed.is_err());
+        assert_eq!(
+            *recorder.0.lock().unwrap(),
+            vec![
+                "started GET",
+                "finished GET ok",
+                "started PIPELINE",
+                "finished PIPELINE type error",
+            ]
+        );
+    }
+}
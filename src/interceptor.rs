@@ -0,0 +1,374 @@
+//! Interceptors which observe and change the commands sent on a connection.
+//!
+//! An [`Interceptor`] is handed every command before it is sent and every
+//! reply after it was received.  It can rewrite a command, for instance to
+//! prefix its keys, reject it, for instance to enforce a deny-list, or
+//! change the reply.  [`Intercepted`] wraps any connection, sync or async,
+//! including cluster connections and connection managers, and applies an
+//! interceptor to it.  Wrapping an `Intercepted` connection again applies
+//! several interceptors, the outermost one first.
+//!
+//! ```rust,no_run
+//! # fn do_something() -> redis::RedisResult<()> {
+//! use redis::interceptor::{Intercepted, Interceptor};
+//! use redis::{Commands, Cmd, ErrorKind, RedisResult};
+//!
+//! struct DenyFlush;
+//!
+//! impl Interceptor for DenyFlush {
+//!     fn before_command(&self, cmd: &mut Cmd) -> RedisResult<()> {
+//!         match cmd.args_iter().next() {
+//!             Some(redis::Arg::Simple(name))
+//!                 if name.eq_ignore_ascii_case(b"FLUSHALL")
+//!                     || name.eq_ignore_ascii_case(b"FLUSHDB") =>
+//!             {
+//!                 Err((ErrorKind::ClientError, "Command is not allowed").into())
+//!             }
+//!             _ => Ok(()),
+//!         }
+//!     }
+//! }
+//!
+//! let client = redis::Client::open("redis://127.0.0.1/")?;
+//! let mut con = Intercepted::new(client.get_connection()?, DenyFlush);
+//! let value: Option<String> = con.get("my_key")?;
+//! # Ok(()) }
+//! ```
+use std::sync::Arc;
+
+use crate::cmd::{Arg, Cmd};
+use crate::connection::ConnectionLike;
+use crate::types::{ErrorKind, RedisResult, Value};
+
+/// Observes and changes the commands sent on an [`Intercepted`] connection
+/// and their replies.
+///
+/// The `MULTI` and `EXEC` wrapping the commands of an atomic pipeline are not
+/// passed to `before_command`, the reply of the transaction is passed to
+/// `after_reply` along with the `EXEC` command.
+pub trait Interceptor: Send + Sync {
+    /// Called with every command before it is sent.  The command may be
+    /// changed, or rejected by returning an error, which is returned instead
+    /// of sending the command.
+    fn before_command(&self, _cmd: &mut Cmd) -> RedisResult<()> {
+        Ok(())
+    }
+
+    /// Called with the reply of every command the server did not fail,
+    /// returns the reply handed to the caller.
+    fn after_reply(&self, _cmd: &Cmd, reply: Value) -> RedisResult<Value> {
+        Ok(reply)
+    }
+}
+
+/// A connection whose commands and replies pass through an
+/// [`Interceptor`], see the [module docs](self).
+#[derive(Clone)]
+pub struct Intercepted<C> {
+    con: C,
+    interceptor: Arc<dyn Interceptor>,
+}
+
+impl<C> Intercepted<C> {
+    /// Wraps the connection.
+    pub fn new<I: Interceptor + 'static>(con: C, interceptor: I) -> Self {
+        Intercepted {
+            con,
+            interceptor: Arc::new(interceptor),
+        }
+    }
+
+    /// Returns a reference to the wrapped connection.
+    pub fn get_ref(&self) -> &C {
+        &self.con
+    }
+
+    /// Returns a mutable reference to the wrapped connection.
+    pub fn get_mut(&mut self) -> &mut C {
+        &mut self.con
+    }
+
+    /// Returns the wrapped connection.
+    pub fn into_inner(self) -> C {
+        self.con
+    }
+
+    fn before(&self, cmd: &Cmd) -> RedisResult<Cmd> {
+        let mut cmd = cmd.clone();
+        self.interceptor.before_command(&mut cmd)?;
+        Ok(cmd)
+    }
+
+    // Applies `after_reply` to the replies of the commands from `offset` on.
+    fn after_replies(
+        &self,
+        cmds: &[Cmd],
+        offset: usize,
+        replies: Vec<Value>,
+    ) -> RedisResult<Vec<Value>> {
+        replies
+            .into_iter()
+            .zip(&cmds[offset..])
+            .map(|(reply, cmd)| self.interceptor.after_reply(cmd, reply))
+            .collect()
+    }
+}
+
+fn is_command(cmd: &Cmd, name: &[u8]) -> bool {
+    matches!(cmd.args_iter().next(), Some(Arg::Simple(arg)) if arg == name)
+}
+
+// Reads one argument of a packed command, `*` for the length of the
+// command or `$` for a bulk string.
+fn unpack_length(bytes: &mut &[u8], prefix: u8) -> Option<usize> {
+    let end = bytes.windows(2).position(|w| w == b"\r\n")?;
+    let (line, rest) = bytes.split_at(end);
+    if line.first() != Some(&prefix) {
+        return None;
+    }
+    let len = std::str::from_utf8(&line[1..]).ok()?.parse().ok()?;
+    *bytes = &rest[2..];
+    Some(len)
+}
+
+fn unpack_command(bytes: &mut &[u8]) -> Option<Cmd> {
+    let mut cmd = Cmd::new();
+    for _ in 0..unpack_length(bytes, b'*')? {
+        let len = unpack_length(bytes, b'$')?;
+        if bytes.len() < len + 2 || &bytes[len..len + 2] != b"\r\n" {
+            return None;
+        }
+        cmd.arg(&bytes[..len]);
+        *bytes = &bytes[len + 2..];
+    }
+    Some(cmd)
+}
+
+// Turns the commands packed by `Cmd` and `Pipeline` back into commands.
+fn unpack_commands(mut bytes: &[u8]) -> RedisResult<Vec<Cmd>> {
+    let mut cmds = vec![];
+    while !bytes.is_empty() {
+        match unpack_command(&mut bytes) {
+            Some(cmd) => cmds.push(cmd),
+            None => fail!((ErrorKind::ClientError, "Invalid packed command")),
+        }
+    }
+    Ok(cmds)
+}
+
+impl<C: ConnectionLike> ConnectionLike for Intercepted<C> {
+    fn req_packed_command(&mut self, cmd: &[u8]) -> RedisResult<Value> {
+        match &unpack_commands(cmd)?[..] {
+            [cmd] => self.req_command(cmd),
+            _ => fail!((ErrorKind::ClientError, "Expected a single packed command")),
+        }
+    }
+
+    fn req_packed_commands(
+        &mut self,
+        cmd: &[u8],
+        offset: usize,
+        count: usize,
+    ) -> RedisResult<Vec<Value>> {
+        let mut cmds = unpack_commands(cmd)?;
+        let mut commands = &mut cmds[..];
+        // the transaction of an atomic pipeline is not intercepted
+        if let [first, .., last] = commands {
+            if is_command(first, b"MULTI") && is_command(last, b"EXEC") {
+                let len = commands.len();
+                commands = &mut commands[1..len - 1];
+            }
+        }
+        for cmd in commands {
+            self.interceptor.before_command(cmd)?;
+        }
+        let packed: Vec<u8> = cmds.iter().flat_map(Cmd::get_packed_command).collect();
+        let replies = self.con.req_packed_commands(&packed, offset, count)?;
+        self.after_replies(&cmds, offset, replies)
+    }
+
+    fn req_command(&mut self, cmd: &Cmd) -> RedisResult<Value> {
+        let cmd = self.before(cmd)?;
+        let reply = self.con.req_command(&cmd)?;
+        self.interceptor.after_reply(&cmd, reply)
+    }
+
+    fn get_db(&self) -> i64 {
+        self.con.get_db()
+    }
+
+    fn supports_pipelining(&self) -> bool {
+        self.con.supports_pipelining()
+    }
+
+    fn check_connection(&mut self) -> bool {
+        self.con.check_connection()
+    }
+
+    fn is_open(&self) -> bool {
+        self.con.is_open()
+    }
+}
+
+#[cfg(feature = "aio")]
+impl<C: crate::aio::ConnectionLike + Send> crate::aio::ConnectionLike for Intercepted<C> {
+    fn req_packed_command<'a>(&'a mut self, cmd: &'a Cmd) -> crate::RedisFuture<'a, Value> {
+        Box::pin(async move {
+            let cmd = self.before(cmd)?;
+            let reply = self.con.req_packed_command(&cmd).await?;
+            self.interceptor.after_reply(&cmd, reply)
+        })
+    }
+
+    fn req_packed_commands<'a>(
+        &'a mut self,
+        pipeline: &'a crate::Pipeline,
+        offset: usize,
+        count: usize,
+    ) -> crate::RedisFuture<'a, Vec<Value>> {
+        Box::pin(async move {
+            let mut intercepted = pipeline.clone();
+            for cmd in intercepted.commands_mut() {
+                self.interceptor.before_command(cmd)?;
+            }
+            let replies = self
+                .con
+                .req_packed_commands(&intercepted, offset, count)
+                .await?;
+            if intercepted.is_transaction() {
+                self.after_replies(&[crate::cmd::cmd("EXEC")], 0, replies)
+            } else {
+                self.after_replies(intercepted.commands_mut(), offset, replies)
+            }
+        })
+    }
+
+    fn get_db(&self) -> i64 {
+        self.con.get_db()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cmd::{cmd, pipe};
+
+    // Replies to every command with its arguments.
+    struct Echo {
+        requests: Vec<Vec<u8>>,
+    }
+
+    impl ConnectionLike for Echo {
+        fn req_packed_command(&mut self, cmd: &[u8]) -> RedisResult<Value> {
+            self.req_packed_commands(cmd, 0, 1)
+                .map(|mut replies| replies.remove(0))
+        }
+
+        fn req_packed_commands(
+            &mut self,
+            cmd: &[u8],
+            offset: usize,
+            count: usize,
+        ) -> RedisResult<Vec<Value>> {
+            self.requests.push(cmd.to_vec());
+            let replies = unpack_commands(cmd)?.into_iter().map(|cmd| {
+                Value::Bulk(
+                    cmd.args_iter()
+                        .map(|arg| match arg {
+                            Arg::Simple(arg) => Value::Data(arg.to_vec()),
+                            Arg::Cursor => Value::Nil,
+                        })
+                        .collect(),
+                )
+            });
+            Ok(replies.skip(offset).take(count).collect())
+        }
+
+        fn get_db(&self) -> i64 {
+            0
+        }
+
+        fn check_connection(&mut self) -> bool {
+            true
+        }
+
+        fn is_open(&self) -> bool {
+            true
+        }
+    }
+
+    // Prefixes the first key and counts the replies.
+    struct Prefix;
+
+    impl Interceptor for Prefix {
+        fn before_command(&self, cmd: &mut Cmd) -> RedisResult<()> {
+            if is_command(cmd, b"DEL") {
+                fail!((ErrorKind::ClientError, "DEL is denied"));
+            }
+            let mut args = cmd.args_iter().map(|arg| match arg {
+                Arg::Simple(arg) => arg.to_vec(),
+                Arg::Cursor => unreachable!(),
+            });
+            let mut prefixed = Cmd::new();
+            prefixed.arg(args.next());
+            if let Some(key) = args.next() {
+                prefixed.arg([&b"app:"[..], &key].concat());
+            }
+            for arg in args {
+                prefixed.arg(arg);
+            }
+            *cmd = prefixed;
+            Ok(())
+        }
+
+        fn after_reply(&self, cmd: &Cmd, reply: Value) -> RedisResult<Value> {
+            match reply {
+                Value::Bulk(items) if is_command(cmd, b"EXEC") => {
+                    Ok(Value::Int(items.len() as i64))
+                }
+                reply => Ok(reply),
+            }
+        }
+    }
+
+    #[test]
+    fn test_unpack_commands() {
+        let cmds = [
+            cmd("SET").arg("key").arg(&b"a\r\nb"[..]).clone(),
+            cmd("PING"),
+        ];
+        let packed: Vec<u8> = cmds.iter().flat_map(Cmd::get_packed_command).collect();
+        let unpacked: Vec<Vec<u8>> = unpack_commands(&packed)
+            .unwrap()
+            .iter()
+            .map(Cmd::get_packed_command)
+            .collect();
+        assert_eq!(unpacked.concat(), packed);
+        assert!(unpack_commands(b"*1\r\n$4\r\nPING").is_err());
+    }
+
+    #[test]
+    fn test_intercepted() {
+        let mut con = Intercepted::new(Echo { requests: vec![] }, Prefix);
+
+        let reply: Vec<String> = cmd("GET").arg("key").query(&mut con).unwrap();
+        assert_eq!(reply, ["GET", "app:key"]);
+        let err = cmd("DEL").arg("key").query::<()>(&mut con).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ClientError);
+        assert_eq!(con.get_ref().requests.len(), 1);
+
+        let (reply,): (Vec<String>,) = pipe().get("key").query(&mut con).unwrap();
+        assert_eq!(reply, ["GET", "app:key"]);
+
+        // the transaction reply is passed along with `EXEC`
+        let reply: Value = con
+            .req_packed_commands(&pipe().atomic().get("key").get_packed_pipeline(), 2, 1)
+            .unwrap()
+            .remove(0);
+        assert_eq!(reply, Value::Int(1));
+        assert_eq!(
+            con.get_ref().requests[2],
+            pipe().atomic().get("app:key").get_packed_pipeline()
+        );
+    }
+}
@@ -0,0 +1,458 @@
+//! Helpers which operate on all keys matching a pattern.
+
+use std::collections::HashSet;
+use std::thread;
+use std::time::Duration;
+
+#[cfg(feature = "cluster")]
+use crate::cluster::{ClusterConnection, ClusterScanIter};
+use crate::cmd::cmd;
+#[cfg(feature = "cluster")]
+use crate::commands::ScanOptions;
+use crate::connection::ConnectionLike;
+#[cfg(feature = "cluster")]
+use crate::types::from_redis_value;
+use crate::types::{ErrorKind, RedisError, RedisResult};
+
+/// Builder options for [`delete_matching`].
+///
+/// [`delete_matching`]: ./fn.delete_matching.html
+#[derive(Clone, Debug)]
+pub struct DeleteMatchingOptions {
+    /// Set the COUNT hint passed to SCAN.
+    count: Option<usize>,
+    /// Number of keys removed per UNLINK.
+    batch_size: usize,
+    /// Only count the matching keys.
+    dry_run: bool,
+    /// Abort once more keys than this match.
+    max_keys: Option<u64>,
+    /// Pause between two UNLINK batches.
+    batch_delay: Option<Duration>,
+}
+
+impl Default for DeleteMatchingOptions {
+    fn default() -> Self {
+        DeleteMatchingOptions {
+            count: None,
+            batch_size: 500,
+            dry_run: false,
+            max_keys: None,
+            batch_delay: None,
+        }
+    }
+}
+
+impl DeleteMatchingOptions {
+    /// Set the COUNT hint passed to every SCAN call.
+    pub fn count(mut self, count: usize) -> Self {
+        self.count = Some(count);
+        self
+    }
+
+    /// Set the number of keys removed by a single UNLINK (default is 500).
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Only count the matching keys without removing any of them.
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Abort with an error as soon as more than `max_keys` keys match.
+    ///
+    /// The matching keys are all found before any of them is removed, so
+    /// nothing is removed when the limit is exceeded.
+    pub fn max_keys(mut self, max_keys: u64) -> Self {
+        self.max_keys = Some(max_keys);
+        self
+    }
+
+    /// Pause for the given duration between two UNLINK batches to limit
+    /// the load put on the server.
+    pub fn batch_delay(mut self, delay: Duration) -> Self {
+        self.batch_delay = Some(delay);
+        self
+    }
+
+    fn scan_cmd(&self, cursor: u64, pattern: &str) -> crate::Cmd {
+        let mut c = cmd("SCAN");
+        c.arg(cursor).arg("MATCH").arg(pattern);
+        if let Some(count) = self.count {
+            c.arg("COUNT").arg(count);
+        }
+        c
+    }
+
+    // Whether the matching keys are collected before any is removed, to
+    // count them or to check the limit.
+    fn collects_keys(&self) -> bool {
+        self.dry_run || self.max_keys.is_some()
+    }
+
+    fn check_limit(&self, matched: u64) -> RedisResult<()> {
+        match self.max_keys {
+            Some(max_keys) if matched > max_keys => Err(RedisError::from((
+                ErrorKind::ClientError,
+                "Too many keys match the pattern",
+                format!("more than {} keys matched", max_keys),
+            ))),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Removes all keys matching `pattern` and returns how many were removed.
+///
+/// Unlike `KEYS` followed by `DEL` this does not block the server: the
+/// keys are found with `SCAN` and removed in batches with `UNLINK`.  In
+/// dry-run mode the number of matching keys is returned instead.
+///
+/// Keys returned more than once by `SCAN` are counted once.  In dry-run
+/// mode and with `max_keys` the matching keys are kept in memory until
+/// all of them were found.
+///
+/// This only considers the keys of a single server, use
+/// [`delete_matching_cluster`] for the keys of a whole cluster.
+///
+/// ```rust,no_run
+/// # fn do_something() -> redis::RedisResult<()> {
+/// # let client = redis::Client::open("redis://127.0.0.1/").unwrap();
+/// # let mut con = client.get_connection().unwrap();
+/// let opts = redis::DeleteMatchingOptions::default()
+///     .count(1000)
+///     .max_keys(100_000);
+/// let removed = redis::delete_matching(&mut con, "session:*", opts)?;
+/// # Ok(()) }
+/// ```
+pub fn delete_matching(
+    con: &mut dyn ConnectionLike,
+    pattern: &str,
+    opts: DeleteMatchingOptions,
+) -> RedisResult<u64> {
+    let mut keys = NodeKeys {
+        con,
+        pattern,
+        opts: &opts,
+        cursor: Some(0),
+    };
+    delete_keys(&mut keys, &opts)
+}
+
+/// Removes all keys matching `pattern` from every master of a cluster, see
+/// [`delete_matching`].
+///
+/// The keys are found with
+/// [`ClusterConnection::scan_cluster`](crate::cluster::ClusterConnection::scan_cluster)
+/// and each batch is removed with one `UNLINK` per slot.
+#[cfg(feature = "cluster")]
+#[cfg_attr(docsrs, doc(cfg(feature = "cluster")))]
+pub fn delete_matching_cluster(
+    con: &mut ClusterConnection,
+    pattern: &str,
+    opts: DeleteMatchingOptions,
+) -> RedisResult<u64> {
+    let mut scan_options = ScanOptions::default().pattern(pattern);
+    if let Some(count) = opts.count {
+        scan_options = scan_options.count(count);
+    }
+    let con = &*con;
+    let mut keys = ClusterKeys {
+        con,
+        iter: con.scan_shared(scan_options)?,
+        batch_size: opts.batch_size,
+    };
+    delete_keys(&mut keys, &opts)
+}
+
+// The keys matching a pattern, found in batches, and how to remove them.
+trait MatchingKeys {
+    // Returns the next batch of keys, `None` once all were returned.
+    fn next_keys(&mut self) -> RedisResult<Option<Vec<Vec<u8>>>>;
+
+    // Removes the keys, returning how many existed.
+    fn unlink(&mut self, keys: &[Vec<u8>]) -> RedisResult<u64>;
+}
+
+struct NodeKeys<'a> {
+    con: &'a mut dyn ConnectionLike,
+    pattern: &'a str,
+    opts: &'a DeleteMatchingOptions,
+    cursor: Option<u64>,
+}
+
+impl MatchingKeys for NodeKeys<'_> {
+    fn next_keys(&mut self) -> RedisResult<Option<Vec<Vec<u8>>>> {
+        let cursor = match self.cursor {
+            Some(cursor) => cursor,
+            None => return Ok(None),
+        };
+        let (next, keys): (u64, Vec<Vec<u8>>) =
+            self.opts.scan_cmd(cursor, self.pattern).query(self.con)?;
+        self.cursor = if next == 0 { None } else { Some(next) };
+        Ok(Some(keys))
+    }
+
+    fn unlink(&mut self, keys: &[Vec<u8>]) -> RedisResult<u64> {
+        cmd("UNLINK").arg(keys).query(self.con)
+    }
+}
+
+#[cfg(feature = "cluster")]
+struct ClusterKeys<'a> {
+    con: &'a ClusterConnection,
+    iter: ClusterScanIter<'a, Vec<u8>>,
+    batch_size: usize,
+}
+
+#[cfg(feature = "cluster")]
+impl MatchingKeys for ClusterKeys<'_> {
+    fn next_keys(&mut self) -> RedisResult<Option<Vec<Vec<u8>>>> {
+        let keys = self
+            .iter
+            .by_ref()
+            .take(self.batch_size)
+            .collect::<RedisResult<Vec<_>>>()?;
+        Ok(if keys.is_empty() { None } else { Some(keys) })
+    }
+
+    fn unlink(&mut self, keys: &[Vec<u8>]) -> RedisResult<u64> {
+        // split into one command per slot by the connection
+        from_redis_value(&self.con.execute_command(cmd("UNLINK").arg(keys))?)
+    }
+}
+
+// What a deletion does next.
+enum Step {
+    // Scan for the next batch of matching keys.
+    Scan,
+    // Remove the keys, after waiting for the delay if any.
+    Unlink {
+        keys: Vec<Vec<u8>>,
+        delay: Option<Duration>,
+    },
+    // All keys were handled, with the number of removed or matching keys.
+    Done(u64),
+}
+
+// The batching of a deletion, shared by the blocking and the async
+// versions which only send the commands.
+struct Deletion<'a> {
+    opts: &'a DeleteMatchingOptions,
+    batch: Vec<Vec<u8>>,
+    // The keys found so far while they are collected.
+    seen: Option<HashSet<Vec<u8>>>,
+    // Whether all keys were found.
+    scanned: bool,
+    removed: u64,
+    first: bool,
+}
+
+impl<'a> Deletion<'a> {
+    fn new(opts: &'a DeleteMatchingOptions) -> Self {
+        Deletion {
+            opts,
+            batch: Vec::with_capacity(opts.batch_size),
+            seen: if opts.collects_keys() {
+                Some(HashSet::new())
+            } else {
+                None
+            },
+            scanned: false,
+            removed: 0,
+            first: true,
+        }
+    }
+
+    fn next_step(&mut self) -> Step {
+        if self.seen.is_some() && !self.scanned {
+            return Step::Scan;
+        }
+        if self.opts.dry_run {
+            return Step::Done(self.batch.len() as u64);
+        }
+        if self.batch.len() >= self.opts.batch_size || (self.scanned && !self.batch.is_empty()) {
+            let delay = self.opts.batch_delay.filter(|_| !self.first);
+            self.first = false;
+            let end = self.batch.len().min(self.opts.batch_size);
+            return Step::Unlink {
+                keys: self.batch.drain(..end).collect(),
+                delay,
+            };
+        }
+        if self.scanned {
+            Step::Done(self.removed)
+        } else {
+            Step::Scan
+        }
+    }
+
+    // Takes the keys found by a scan, `None` once all were found.
+    fn found(&mut self, keys: Option<Vec<Vec<u8>>>) -> RedisResult<()> {
+        let keys = match keys {
+            Some(keys) => keys,
+            None => {
+                self.scanned = true;
+                return Ok(());
+            }
+        };
+        match self.seen {
+            Some(ref mut seen) => {
+                for key in keys {
+                    if seen.insert(key.clone()) {
+                        self.batch.push(key);
+                    }
+                }
+                self.opts.check_limit(self.batch.len() as u64)
+            }
+            None => {
+                self.batch.extend(keys);
+                Ok(())
+            }
+        }
+    }
+
+    fn unlinked(&mut self, removed: u64) {
+        // duplicates are not counted twice as only existing keys are
+        self.removed += removed;
+    }
+}
+
+fn delete_keys<K: MatchingKeys>(keys: &mut K, opts: &DeleteMatchingOptions) -> RedisResult<u64> {
+    let mut deletion = Deletion::new(opts);
+    loop {
+        match deletion.next_step() {
+            Step::Scan => deletion.found(keys.next_keys()?)?,
+            Step::Unlink { keys: batch, delay } => {
+                if let Some(delay) = delay {
+                    thread::sleep(delay);
+                }
+                deletion.unlinked(keys.unlink(&batch)?);
+            }
+            Step::Done(count) => return Ok(count),
+        }
+    }
+}
+
+/// Async version of [`delete_matching`].
+///
+/// [`delete_matching`]: ./fn.delete_matching.html
+#[cfg(feature = "aio")]
+#[cfg_attr(docsrs, doc(cfg(feature = "aio")))]
+pub async fn delete_matching_async<C>(
+    con: &mut C,
+    pattern: &str,
+    opts: DeleteMatchingOptions,
+) -> RedisResult<u64>
+where
+    C: crate::aio::ConnectionLike,
+{
+    let mut deletion = Deletion::new(&opts);
+    let mut cursor = Some(0);
+    loop {
+        match deletion.next_step() {
+            Step::Scan => {
+                let found = match cursor {
+                    Some(current) => {
+                        let (next, found): (u64, Vec<Vec<u8>>) =
+                            opts.scan_cmd(current, pattern).query_async(con).await?;
+                        cursor = if next == 0 { None } else { Some(next) };
+                        Some(found)
+                    }
+                    None => None,
+                };
+                deletion.found(found)?;
+            }
+            Step::Unlink { keys, delay } => {
+                if let Some(delay) = delay {
+                    crate::aio::Runtime::locate().sleep(delay).await;
+                }
+                let removed = cmd("UNLINK").arg(&keys).query_async(con).await?;
+                deletion.unlinked(removed);
+            }
+            Step::Done(count) => return Ok(count),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockConnection;
+    use crate::types::Value;
+
+    fn scan_reply(cursor: &str, keys: &[&str]) -> RedisResult<Value> {
+        Ok(Value::Bulk(vec![
+            Value::Data(cursor.as_bytes().to_vec()),
+            Value::Bulk(
+                keys.iter()
+                    .map(|key| Value::Data(key.as_bytes().to_vec()))
+                    .collect(),
+            ),
+        ]))
+    }
+
+    fn scans(con: MockConnection) -> MockConnection {
+        // SCAN may return a key more than once
+        con.expect(
+            cmd("SCAN").arg(0).arg("MATCH").arg("k*"),
+            scan_reply("7", &["k1", "k2"]),
+        )
+        .expect(
+            cmd("SCAN").arg(7).arg("MATCH").arg("k*"),
+            scan_reply("0", &["k2", "k3"]),
+        )
+    }
+
+    #[test]
+    fn test_duplicates_are_counted_once() {
+        let opts = DeleteMatchingOptions::default();
+        let mut con = scans(MockConnection::new());
+        assert_eq!(
+            delete_matching(&mut con, "k*", opts.clone().dry_run(true)),
+            Ok(3)
+        );
+
+        let mut con = scans(MockConnection::new()).expect(
+            cmd("UNLINK").arg("k1").arg("k2").arg("k3"),
+            Ok(Value::Int(3)),
+        );
+        assert_eq!(delete_matching(&mut con, "k*", opts.max_keys(3)), Ok(3));
+    }
+
+    #[test]
+    fn test_keys_are_removed_in_batches() {
+        let opts = DeleteMatchingOptions::default().batch_size(2);
+        let mut con = scans(MockConnection::new())
+            .expect(cmd("UNLINK").arg("k1").arg("k2"), Ok(Value::Int(2)))
+            .expect(cmd("UNLINK").arg("k2").arg("k3"), Ok(Value::Int(1)));
+        assert_eq!(delete_matching(&mut con, "k*", opts), Ok(3));
+    }
+
+    #[cfg(feature = "aio")]
+    #[test]
+    fn test_async_deletion_matches_blocking() {
+        use futures::executor::block_on;
+
+        let opts = DeleteMatchingOptions::default().batch_size(2);
+        let mut con = scans(MockConnection::new());
+        let dry_run = delete_matching_async(&mut con, "k*", opts.clone().dry_run(true));
+        assert_eq!(block_on(dry_run), Ok(3));
+
+        let mut con = scans(MockConnection::new())
+            .expect(cmd("UNLINK").arg("k1").arg("k2"), Ok(Value::Int(2)))
+            .expect(cmd("UNLINK").arg("k2").arg("k3"), Ok(Value::Int(1)));
+        assert_eq!(block_on(delete_matching_async(&mut con, "k*", opts)), Ok(3));
+    }
+
+    #[test]
+    fn test_nothing_is_removed_over_max_keys() {
+        let opts = DeleteMatchingOptions::default().batch_size(1).max_keys(2);
+        let mut con = scans(MockConnection::new());
+        let err = delete_matching(&mut con, "k*", opts).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ClientError);
+    }
+}
@@ -58,8 +58,34 @@
 //! * `script`: enables script support (enabled by default)
 //! * `r2d2`: enables r2d2 connection pool support (optional)
 //! * `cluster`: enables redis cluster support (optional)
+//! * `caching`: enables client side caching support (optional)
+//! * `sentinel`: enables redis sentinel support (optional)
+//! * `replication`: enables following the replication stream of a master, see the `replication` module (optional)
+//! * `tls`: enables TLS support with native-tls (optional)
+//! * `tls-rustls`: enables TLS support with rustls, preferred over `tls` (optional)
 //! * `tokio-comp`: enables support for tokio (optional)
 //! * `connection-manager`: enables support for automatic reconnection (optional)
+//! * `connection-pool`: enables the async connection pool (optional)
+//! * `derive`: enables the `ToRedisArgs` and `FromRedisValue` derive macros (optional)
+//! * `serde`: enables storing serde serializable types, see the `serde` module (optional)
+//! * `chrono`: enables conversions of `chrono::DateTime<Utc>` (optional)
+//! * `time`: enables conversions of `time::OffsetDateTime` (optional)
+//! * `uuid`, `rust_decimal`, `ipnet`: enable conversions of the types of these crates (optional)
+//! * `tracing`: emits a `tracing` span for every command (optional)
+//! * `otel`: makes the `tracing` spans follow the OpenTelemetry database conventions (optional)
+//! * `tower`: implements `tower::Service` for `aio::MultiplexedConnection` (optional)
+//! * `sharding`: enables spreading keys over independent servers, see the `sharding` module (optional)
+//! * `test-support`: enables starting throwaway servers for tests, see the `testing` module (optional)
+//!
+//! ## WASI
+//!
+//! The crate builds for the `wasm32-wasip1` and `wasm32-wasip2` targets.
+//! Connecting works where the runtime supports outgoing sockets, otherwise a
+//! socket preopened by the runtime is passed to
+//! `Client::get_connection_with_stream`.  Unix sockets and the TCP settings
+//! are not available there, and neither are the `tokio-comp` and
+//! `async-std-comp` features; the async connections run on a runtime
+//! registered with `aio::set_runtime` instead.
 //!
 //! ## Connection Parameters
 //!
@@ -82,6 +108,9 @@
 //!
 //! `unix:///<path>[?db=<db>][&pass=<password>][&user=<username>]]`
 //!
+//! Unix sockets work for the async connections as well, including the
+//! multiplexed connection and the `ConnectionManager`.
+//!
 //! ## Executing Low-Level Commands
 //!
 //! To execute low-level commands you can use the `cmd` function which allows
@@ -164,6 +193,20 @@
 //! # }
 //! ```
 //!
+//! With the `derive` feature both traits can also be derived: structs are
+//! converted to the field/value pairs of a hash and enums with unit variants
+//! to the names of their variants.
+//! The `serde` feature stores any serde serializable type instead, either
+//! as JSON or as the fields of a hash, and `from_redis_value_serde`
+//! deserializes any reply into a type implementing `Deserialize`.
+//!
+//! `Duration` and `SystemTime` are converted to and from milliseconds,
+//! the unit of `PEXPIRE` and `PEXPIRETIME`, and so are the date times of
+//! `chrono` and `time` with the features of the same name.
+//! IP addresses are converted to and from their text, and with their
+//! features so are the UUIDs of `uuid`, the decimals of `rust_decimal` and
+//! the networks of `ipnet`.
+//!
 //! # Iteration Protocol
 //!
 //! In addition to sending a single query, iterators are also supported.  When
@@ -358,20 +401,47 @@ assert_eq!(result, Ok(("foo".to_string(), b"bar".to_vec())));
 #![cfg_attr(docsrs, feature(doc_cfg))]
 
 // public api
-pub use crate::client::Client;
+pub use crate::client::{one_shot, one_shot_with_timeout, Client, ONE_SHOT_TIMEOUT};
 pub use crate::cmd::{cmd, pack_command, pipe, Arg, Cmd, Iter};
-pub use crate::commands::{Commands, ControlFlow, LposOptions, PubSubCommands};
+pub use crate::commands::{Commands, ControlFlow, LposOptions, PubSubCommands, ScanOptions};
 pub use crate::connection::{
-    parse_redis_url, transaction, Connection, ConnectionAddr, ConnectionInfo, ConnectionLike,
-    IntoConnectionInfo, Msg, PubSub, RedisConnectionInfo,
+    parse_redis_url, transaction, ConfigIter, Connection, ConnectionAddr, ConnectionEvent,
+    ConnectionInfo, ConnectionLike, IntoConnectionInfo, LibraryInfo, Monitor, Msg, ProtocolVersion,
+    PubSub, RedisConnectionInfo, ReplyIter,
+};
+#[cfg(feature = "cluster")]
+#[cfg_attr(docsrs, doc(cfg(feature = "cluster")))]
+pub use crate::keys::delete_matching_cluster;
+pub use crate::keys::{delete_matching, DeleteMatchingOptions};
+pub use crate::parser::{
+    parse_inline_command, parse_redis_value, parse_redis_value_partial, parse_redis_value_ref,
+    parse_with_sink, parse_with_sink_limited, write_reply, IntegerParsing, ParseLimits, Parser,
+    ParserSink, Token, Tokens, TypeHistogram,
 };
-pub use crate::parser::{parse_redis_value, Parser};
 pub use crate::pipeline::Pipeline;
+pub use crate::proxy::{ProxyConfig, ProxyKind};
+pub use crate::tcp::TcpSettings;
+pub use crate::tls::{ClientTlsConfig, TlsCertificates};
+
+#[cfg(feature = "bytes")]
+#[cfg_attr(docsrs, doc(cfg(feature = "bytes")))]
+pub use crate::{
+    parser::{parse_redis_value_bytes, parse_redis_value_lazy},
+    types::{BytesValue, LazyData, LazyValue},
+};
 
 #[cfg(feature = "script")]
 #[cfg_attr(docsrs, doc(cfg(feature = "script")))]
 pub use crate::script::{Script, ScriptInvocation};
 
+#[cfg(feature = "derive")]
+#[cfg_attr(docsrs, doc(cfg(feature = "derive")))]
+pub use redis_derive::{FromRedisValue, ToRedisArgs};
+
+#[cfg(feature = "serde")]
+#[cfg_attr(docsrs, doc(cfg(feature = "serde")))]
+pub use crate::serde::from_redis_value_serde;
+
 pub use crate::types::{
     // utility functions
     from_redis_value,
@@ -384,24 +454,53 @@ pub use crate::types::{
 
     // utility types
     InfoDict,
+    Invalidation,
+    LatencyLatest,
+    LatencySample,
+    MonitorEntry,
     NumericBehavior,
 
+    // low level values
+    PushMessage,
     // error and result types
     RedisError,
     RedisResult,
     RedisWrite,
+    StringDecoding,
     ToRedisArgs,
+    TrackingInfo,
 
-    // low level values
     Value,
+    ValueRef,
+};
+
+#[cfg(feature = "aio")]
+#[cfg_attr(docsrs, doc(cfg(feature = "aio")))]
+pub use crate::{
+    cmd::{AsyncIter, ScanStream},
+    commands::AsyncCommands,
+    parser::{
+        fuzz_decode, parse_redis_value_async, write_reply_async, BufferPolicy, BufferPool,
+        BytesValueCodec, CommandCodec, ValueCodec,
+    },
+    types::RedisFuture,
 };
 
 #[cfg(feature = "aio")]
 #[cfg_attr(docsrs, doc(cfg(feature = "aio")))]
 pub use crate::{
-    cmd::AsyncIter, commands::AsyncCommands, parser::parse_redis_value_async, types::RedisFuture,
+    client::{one_shot_async, one_shot_with_timeout_async},
+    keys::delete_matching_async,
 };
 
+// Neither runtime connects on WASI, the async connections need a runtime
+// registered with `aio::set_runtime` there.
+#[cfg(all(
+    target_os = "wasi",
+    any(feature = "tokio-comp", feature = "async-std-comp")
+))]
+compile_error!("the tokio-comp and async-std-comp features are not supported on WASI");
+
 mod macros;
 mod pipeline;
 
@@ -417,6 +516,10 @@ pub mod aio;
 #[cfg_attr(docsrs, doc(cfg(feature = "geospatial")))]
 pub mod geo;
 
+#[cfg(feature = "caching")]
+#[cfg_attr(docsrs, doc(cfg(feature = "caching")))]
+pub mod caching;
+
 #[cfg(feature = "cluster")]
 #[cfg_attr(docsrs, doc(cfg(feature = "cluster")))]
 pub mod cluster;
@@ -427,21 +530,61 @@ mod cluster_client;
 #[cfg(feature = "cluster")]
 mod cluster_pipeline;
 
+#[cfg(feature = "cluster")]
+mod cluster_pubsub;
+
 #[cfg(feature = "cluster")]
 mod cluster_routing;
 
+#[cfg(feature = "cluster")]
+mod cluster_topology;
+
 #[cfg(feature = "r2d2")]
 #[cfg_attr(docsrs, doc(cfg(feature = "r2d2")))]
 mod r2d2;
 
+#[cfg(feature = "replication")]
+#[cfg_attr(docsrs, doc(cfg(feature = "replication")))]
+pub mod replication;
+
+#[cfg(feature = "sentinel")]
+#[cfg_attr(docsrs, doc(cfg(feature = "sentinel")))]
+pub mod sentinel;
+
+#[cfg(feature = "serde")]
+#[cfg_attr(docsrs, doc(cfg(feature = "serde")))]
+pub mod serde;
+
+#[cfg(feature = "sharding")]
+#[cfg_attr(docsrs, doc(cfg(feature = "sharding")))]
+pub mod sharding;
+
 #[cfg(feature = "streams")]
 #[cfg_attr(docsrs, doc(cfg(feature = "streams")))]
 pub mod streams;
 
+pub mod retry;
+
+pub mod circuit_breaker;
+
+pub mod instrumentation;
+
+pub mod interceptor;
+
+pub mod testing;
+
+#[cfg(feature = "tracing")]
+mod instrument;
+
 mod client;
 mod cmd;
 mod commands;
 mod connection;
+mod happy_eyeballs;
+mod keys;
 mod parser;
+mod proxy;
 mod script;
+mod tcp;
+mod tls;
 mod types;
@@ -1,21 +1,31 @@
 use std::{
+    cell::Cell,
+    collections::VecDeque,
+    error, fmt,
     io::{self, Read},
-    str,
+    marker::PhantomData,
+    mem, str,
 };
 
-use crate::types::{make_extension_error, ErrorKind, RedisError, RedisResult, Value};
+use crate::types::{
+    from_redis_value, make_extension_error, ErrorKind, RedisError, RedisResult, StringDecoding,
+    Value, ValueRef,
+};
+#[cfg(feature = "bytes")]
+use crate::types::{BytesValue, LazyData, LazyValue};
 
 use combine::{
     any,
-    error::StreamError,
+    error::{Commit, ParseResult, StreamError},
     opaque,
     parser::{
         byte::{crlf, take_until_bytes},
         combinator::{any_send_sync_partial_state, AnySendSyncPartialState},
         range::{recognize, take},
+        FirstMode, ParseMode, PartialMode,
     },
     stream::{PointerOffset, RangeStream, StreamErrorFor},
-    ParseError, Parser as _,
+    ParseError, Parser as _, Stream,
 };
 
 struct ResultExtend<T, E>(Result<T, E>);
@@ -53,7 +63,524 @@ where
     }
 }
 
+// The chunks of a streamed string, appended as they are parsed.
+#[derive(Default)]
+struct Chunks(Vec<u8>);
+
+impl Extend<Vec<u8>> for Chunks {
+    fn extend<I>(&mut self, iter: I)
+    where
+        I: IntoIterator<Item = Vec<u8>>,
+    {
+        for chunk in iter {
+            self.0.extend_from_slice(&chunk);
+        }
+    }
+}
+
+// The values of an aggregate with the number of `Data` bytes they hold.
+#[derive(Default)]
+struct Items {
+    values: ResultExtend<Vec<Value>, RedisError>,
+    payload: usize,
+}
+
+impl Extend<RedisResult<Value>> for Items {
+    fn extend<I>(&mut self, iter: I)
+    where
+        I: IntoIterator<Item = RedisResult<Value>>,
+    {
+        for item in iter {
+            if let Ok(ref value) = item {
+                self.payload = self.payload.saturating_add(data_len(value));
+            }
+            self.values.extend(Some(item));
+        }
+    }
+}
+
+// Like `repeat_until` but runs `check` on the elements collected so far
+// after every element, so that a streamed reply fails as soon as it exceeds
+// a limit instead of once all of it was buffered.  The parser of every
+// element is built by `parser` from the elements collected before it.
+struct CheckedRepeatUntil<F, P, E, C> {
+    parser: P,
+    end: E,
+    check: C,
+    _marker: PhantomData<fn() -> F>,
+}
+
+fn checked_repeat_until<F, P, E, C>(parser: P, end: E, check: C) -> CheckedRepeatUntil<F, P, E, C> {
+    CheckedRepeatUntil {
+        parser,
+        end,
+        check,
+        _marker: PhantomData,
+    }
+}
+
+impl<F, P, E, C> CheckedRepeatUntil<F, P, E, C> {
+    fn parse_checked<I, M, Q>(
+        &mut self,
+        mut mode: M,
+        input: &mut I,
+        state: &mut (F, bool, Q::PartialState, E::PartialState),
+    ) -> ParseResult<F, I::Error>
+    where
+        I: Stream,
+        M: ParseMode,
+        F: Extend<Q::Output> + Default,
+        P: FnMut(&F) -> Q,
+        Q: combine::Parser<I>,
+        E: combine::Parser<I>,
+        C: FnMut(&F) -> Result<(), LimitExceeded>,
+    {
+        let (output, is_parse, parse_state, end_state) = state;
+        let mut committed = Commit::Peek(());
+        loop {
+            if *is_parse {
+                match (self.parser)(output).parse_mode(mode, input, parse_state) {
+                    ParseResult::CommitOk(item) => {
+                        output.extend(Some(item));
+                        committed = Commit::Commit(());
+                    }
+                    ParseResult::PeekOk(item) => output.extend(Some(item)),
+                    ParseResult::CommitErr(err) => return ParseResult::CommitErr(err),
+                    ParseResult::PeekErr(err) => return ParseResult::PeekErr(err),
+                }
+                *is_parse = false;
+                if let Err(err) = (self.check)(output) {
+                    let err =
+                        I::Error::from_error(input.position(), StreamErrorFor::<I>::other(err));
+                    return ParseResult::CommitErr(err);
+                }
+            } else {
+                let before = input.checkpoint();
+                let result = self.end.parse_mode(mode, input, end_state);
+                if let Err(err) = input.reset(before) {
+                    return ParseResult::CommitErr(err);
+                }
+                match result {
+                    ParseResult::CommitOk(_) => return ParseResult::CommitOk(mem::take(output)),
+                    ParseResult::PeekOk(_) => {
+                        return match committed {
+                            Commit::Commit(()) => ParseResult::CommitOk(mem::take(output)),
+                            Commit::Peek(()) => ParseResult::PeekOk(mem::take(output)),
+                        }
+                    }
+                    ParseResult::PeekErr(_) => {
+                        mode.set_first();
+                        *is_parse = true;
+                    }
+                    ParseResult::CommitErr(err) => return ParseResult::CommitErr(err),
+                }
+            }
+        }
+    }
+}
+
+impl<F, I, P, Q, E, C> combine::Parser<I> for CheckedRepeatUntil<F, P, E, C>
+where
+    I: Stream,
+    F: Extend<Q::Output> + Default,
+    P: FnMut(&F) -> Q,
+    Q: combine::Parser<I>,
+    E: combine::Parser<I>,
+    C: FnMut(&F) -> Result<(), LimitExceeded>,
+{
+    type Output = F;
+    type PartialState = (F, bool, Q::PartialState, E::PartialState);
+
+    fn parse_partial(
+        &mut self,
+        input: &mut I,
+        state: &mut Self::PartialState,
+    ) -> ParseResult<F, I::Error> {
+        self.parse_checked(PartialMode::default(), input, state)
+    }
+
+    fn parse_first(
+        &mut self,
+        input: &mut I,
+        state: &mut Self::PartialState,
+    ) -> ParseResult<F, I::Error> {
+        self.parse_checked(FirstMode, input, state)
+    }
+}
+
+// Like `count_min_max(count, count, ..)` but the parser of every element is
+// built by `parser` from the elements collected before it.
+struct CountWith<F, P> {
+    count: usize,
+    parser: P,
+    _marker: PhantomData<fn() -> F>,
+}
+
+fn count_with<F, P>(count: usize, parser: P) -> CountWith<F, P> {
+    CountWith {
+        count,
+        parser,
+        _marker: PhantomData,
+    }
+}
+
+impl<F, P> CountWith<F, P> {
+    fn parse_count<I, M, Q>(
+        &mut self,
+        mut mode: M,
+        input: &mut I,
+        state: &mut (F, usize, Q::PartialState),
+    ) -> ParseResult<F, I::Error>
+    where
+        I: Stream,
+        M: ParseMode,
+        F: Extend<Q::Output> + Default,
+        P: FnMut(&F) -> Q,
+        Q: combine::Parser<I>,
+    {
+        let (output, parsed, parse_state) = state;
+        let mut committed = false;
+        while *parsed < self.count {
+            match (self.parser)(output).parse_mode(mode, input, parse_state) {
+                ParseResult::CommitOk(item) => {
+                    output.extend(Some(item));
+                    committed = true;
+                }
+                ParseResult::PeekOk(item) => output.extend(Some(item)),
+                ParseResult::CommitErr(err) => return ParseResult::CommitErr(err),
+                ParseResult::PeekErr(err) if committed => return ParseResult::CommitErr(err.error),
+                ParseResult::PeekErr(err) => return ParseResult::PeekErr(err),
+            }
+            *parsed += 1;
+            mode.set_first();
+        }
+        *parsed = 0;
+        if committed {
+            ParseResult::CommitOk(mem::take(output))
+        } else {
+            ParseResult::PeekOk(mem::take(output))
+        }
+    }
+}
+
+impl<F, I, P, Q> combine::Parser<I> for CountWith<F, P>
+where
+    I: Stream,
+    F: Extend<Q::Output> + Default,
+    P: FnMut(&F) -> Q,
+    Q: combine::Parser<I>,
+{
+    type Output = F;
+    type PartialState = (F, usize, Q::PartialState);
+
+    fn parse_partial(
+        &mut self,
+        input: &mut I,
+        state: &mut Self::PartialState,
+    ) -> ParseResult<F, I::Error> {
+        self.parse_count(PartialMode::default(), input, state)
+    }
+
+    fn parse_first(
+        &mut self,
+        input: &mut I,
+        state: &mut Self::PartialState,
+    ) -> ParseResult<F, I::Error> {
+        self.parse_count(FirstMode, input, state)
+    }
+}
+
+/// The policy for parsing integer replies.
+#[derive(PartialEq, Eq, Clone, Debug, Copy, Default)]
+pub enum IntegerParsing {
+    /// The whole line must be an integer.  This is the default.
+    #[default]
+    Strict,
+    /// Only the leading integer of the line is parsed and anything after
+    /// the first whitespace is ignored, so `:5 ignored` is read as `5`.
+    /// This is meant for talking to noncompliant servers and emulators.
+    Lenient,
+    /// Only the canonical encoding is accepted: no surrounding whitespace,
+    /// no `+` sign, no leading zeros and no `-0`.  This is meant for
+    /// checking that a server emits conforming replies.
+    Canonical,
+}
+
+fn is_canonical_integer(line: &str) -> bool {
+    let digits = line.strip_prefix('-').unwrap_or(line);
+    match digits.as_bytes() {
+        [] => false,
+        [b'0'] => digits.len() == line.len(),
+        [first, rest @ ..] => {
+            (b'1'..=b'9').contains(first) && rest.iter().all(|b| b.is_ascii_digit())
+        }
+    }
+}
+
+/// Limits on the size of the replies a parser accepts.
+///
+/// Without limits a misbehaving server can make the client allocate
+/// arbitrary amounts of memory by announcing huge bulk strings or arrays.
+/// A reply exceeding a limit fails with `ErrorKind::ParseLimitExceeded` as
+/// soon as the offending header is read.  There are no limits by default.
+///
+/// ```rust
+/// let limits = redis::ParseLimits::new()
+///     .max_bulk_length(16 * 1024 * 1024)
+///     .max_aggregate_length(1024 * 1024)
+///     .max_map_entries(64 * 1024)
+///     .max_total_payload(64 * 1024 * 1024)
+///     .max_depth(32);
+/// let mut parser = redis::Parser::new();
+/// parser.set_limits(limits);
+/// ```
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct ParseLimits {
+    max_bulk_length: usize,
+    max_aggregate_length: usize,
+    max_map_entries: usize,
+    max_total_payload: usize,
+    max_depth: usize,
+}
+
+impl Default for ParseLimits {
+    fn default() -> Self {
+        ParseLimits {
+            max_bulk_length: usize::MAX,
+            max_aggregate_length: usize::MAX,
+            max_map_entries: usize::MAX,
+            max_total_payload: usize::MAX,
+            max_depth: usize::MAX,
+        }
+    }
+}
+
+impl ParseLimits {
+    /// Creates limits which accept replies of any size.
+    pub fn new() -> ParseLimits {
+        ParseLimits::default()
+    }
+
+    /// Sets the maximum length in bytes of bulk strings, verbatim strings
+    /// and blob errors.
+    pub fn max_bulk_length(mut self, len: usize) -> ParseLimits {
+        self.max_bulk_length = len;
+        self
+    }
+
+    /// Sets the maximum number of elements of arrays, sets and pushes, and
+    /// of entries of maps.
+    pub fn max_aggregate_length(mut self, len: usize) -> ParseLimits {
+        self.max_aggregate_length = len;
+        self
+    }
+
+    /// Sets the maximum number of entries of maps and attributes, which
+    /// also have to stay within `max_aggregate_length`.  Every entry holds
+    /// two values, so maps can be limited more strictly than arrays.
+    pub fn max_map_entries(mut self, entries: usize) -> ParseLimits {
+        self.max_map_entries = entries;
+        self
+    }
+
+    /// Sets the maximum number of bulk string bytes of a reply, summed over
+    /// all the bulk strings nested in it.  This bounds replies made of many
+    /// bulk strings which each stay within `max_bulk_length`.  The elements
+    /// read one at a time by `Connection::iter_reply` are counted
+    /// separately.
+    pub fn max_total_payload(mut self, len: usize) -> ParseLimits {
+        self.max_total_payload = len;
+        self
+    }
+
+    /// Sets the maximum nesting depth of aggregates, counted like
+    /// `Parser::last_max_depth` does.  A limit of `0` only accepts scalars.
+    pub fn max_depth(mut self, depth: usize) -> ParseLimits {
+        self.max_depth = depth;
+        self
+    }
+
+    fn check_bulk(&self, len: usize) -> Result<(), LimitExceeded> {
+        if len > self.max_bulk_length {
+            return Err(LimitExceeded(format!(
+                "bulk string of {} bytes is longer than {}",
+                len, self.max_bulk_length
+            )));
+        }
+        Ok(())
+    }
+
+    // `total` is the number of bulk string bytes of the reply so far.
+    fn check_payload(&self, total: usize) -> Result<(), LimitExceeded> {
+        if total > self.max_total_payload {
+            return Err(LimitExceeded(format!(
+                "reply of {} bulk string bytes is longer than {}",
+                total, self.max_total_payload
+            )));
+        }
+        Ok(())
+    }
+
+    // `depth` is the number of aggregates the new one is nested in.
+    fn check_aggregate(&self, len: usize, depth: usize) -> Result<(), LimitExceeded> {
+        if len > self.max_aggregate_length {
+            return Err(LimitExceeded(format!(
+                "aggregate of {} elements is longer than {}",
+                len, self.max_aggregate_length
+            )));
+        }
+        if depth >= self.max_depth {
+            return Err(LimitExceeded(format!(
+                "aggregates are nested deeper than {}",
+                self.max_depth
+            )));
+        }
+        Ok(())
+    }
+
+    // Checks a map or attribute with `entries` entries.
+    fn check_map(&self, entries: usize, depth: usize) -> Result<(), LimitExceeded> {
+        if entries > self.max_map_entries {
+            return Err(LimitExceeded(format!(
+                "map of {} entries is longer than {}",
+                entries, self.max_map_entries
+            )));
+        }
+        self.check_aggregate(entries, depth)
+    }
+
+    // Checks an aggregate with `len` elements, or entries if it holds
+    // `values_per_entry` values per entry like maps do.
+    fn check_entries(
+        &self,
+        len: usize,
+        values_per_entry: usize,
+        depth: usize,
+    ) -> Result<(), LimitExceeded> {
+        match values_per_entry {
+            2 => self.check_map(len, depth),
+            _ => self.check_aggregate(len, depth),
+        }
+    }
+}
+
+// A reply exceeding a `ParseLimits`, carried through combine as the source
+// of its parse error.
+#[derive(Debug, Clone)]
+struct LimitExceeded(String);
+
+impl fmt::Display for LimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl error::Error for LimitExceeded {}
+
+impl From<LimitExceeded> for RedisError {
+    fn from(err: LimitExceeded) -> RedisError {
+        RedisError::from((
+            ErrorKind::ParseLimitExceeded,
+            "Reply exceeds the parser limits",
+            err.0,
+        ))
+    }
+}
+
+// Returns the limit violation behind a combine parse error, which is
+// reported with its own kind instead of as a generic parse error.
+fn limit_error<T, R, P>(err: &combine::easy::Errors<T, R, P>) -> Option<RedisError> {
+    err.errors.iter().find_map(|err| match err {
+        combine::easy::Error::Other(err) => err
+            .downcast_ref::<LimitExceeded>()
+            .map(|err| err.clone().into()),
+        _ => None,
+    })
+}
+
+thread_local! {
+    // The deepest nesting of aggregates of the reply being parsed by a
+    // `Parser` tracking it, `None` while no parser tracks it.
+    static MAX_DEPTH: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+// Records that the parsed reply has aggregates nested `depth` levels deep.
+fn record_depth(depth: usize) {
+    MAX_DEPTH.with(|max| {
+        if let Some(max_depth) = max.get() {
+            max.set(Some(max_depth.max(depth)));
+        }
+    });
+}
+
+// Settings shared by every level of the value parser.
+#[derive(Clone, Copy, Default)]
+struct ParseOptions {
+    decoding: StringDecoding,
+    integers: IntegerParsing,
+    limits: ParseLimits,
+    // The number of aggregates around the value being parsed.
+    depth: usize,
+    // The number of bulk string bytes of the reply before the value.
+    payload: usize,
+    // Whether errors nested in aggregates are parsed as values.
+    error_values: bool,
+}
+
+// Converts an error line sent by the server into the matching error.
+pub(crate) fn server_error(line: &str) -> RedisError {
+    let desc = "An error was signalled by the server";
+    let mut pieces = line.splitn(2, ' ');
+    let kind = match pieces.next().unwrap() {
+        "ERR" => ErrorKind::ResponseError,
+        "EXECABORT" => ErrorKind::ExecAbortError,
+        "LOADING" => ErrorKind::BusyLoadingError,
+        "NOSCRIPT" => ErrorKind::NoScriptError,
+        "MOVED" => ErrorKind::Moved,
+        "ASK" => ErrorKind::Ask,
+        "TRYAGAIN" => ErrorKind::TryAgain,
+        "CLUSTERDOWN" => ErrorKind::ClusterDown,
+        "CROSSSLOT" => ErrorKind::CrossSlot,
+        "MASTERDOWN" => ErrorKind::MasterDown,
+        "READONLY" => ErrorKind::ReadOnly,
+        code => return make_extension_error(code, pieces.next()),
+    };
+    match pieces.next() {
+        Some(detail) => RedisError::from((kind, desc, detail.to_string())),
+        None => RedisError::from((kind, desc)),
+    }
+}
+
+// Errors nested in aggregates become values if enabled, errors at the top
+// fail the reply.
+fn error_reply(line: String, options: ParseOptions) -> RedisResult<Value> {
+    if options.error_values && options.depth > 0 {
+        Ok(Value::ServerError(line))
+    } else {
+        Err(server_error(&line))
+    }
+}
+
+fn map_from_pairs(values: Vec<Value>) -> RedisResult<Value> {
+    let mut values = values.into_iter();
+    let mut pairs = Vec::with_capacity(values.len() / 2);
+    while let (Some(key), Some(value)) = (values.next(), values.next()) {
+        pairs.push((key, value));
+    }
+    Ok(Value::Map(pairs))
+}
+
+// The first element of a push frame names its kind.
+fn push_from_items(mut items: Vec<Value>) -> RedisResult<Value> {
+    if items.is_empty() {
+        fail!((ErrorKind::ResponseError, "Push frame without a kind"));
+    }
+    let kind = from_redis_value(&items.remove(0))?;
+    Ok(Value::Push { kind, data: items })
+}
+
 fn value<'a, I>(
+    options: ParseOptions,
 ) -> impl combine::Parser<I, Output = RedisResult<Value>, PartialState = AnySendSyncPartialState>
 where
     I: RangeStream<Token = u8, Range = &'a [u8]>,
@@ -61,25 +588,35 @@ where
 {
     opaque!(any_send_sync_partial_state(any().then_partial(
         move |&mut b| {
-            let line = || {
-                recognize(take_until_bytes(&b"\r\n"[..]).with(take(2).map(|_| ()))).and_then(
-                    |line: &[u8]| {
-                        str::from_utf8(&line[..line.len() - 2]).map_err(StreamErrorFor::<I>::other)
-                    },
-                )
+            let decoding = options.decoding;
+            let raw_line = || {
+                recognize(take_until_bytes(&b"\r\n"[..]).with(take(2).map(|_| ())))
+                    .map(|line: &[u8]| &line[..line.len() - 2])
+            };
+
+            let line = move || {
+                raw_line().and_then(|line: &[u8]| {
+                    str::from_utf8(line).map_err(StreamErrorFor::<I>::other)
+                })
+            };
+
+            let text_line = move || {
+                raw_line().and_then(move |line: &[u8]| {
+                    decoding.decode(line).map_err(StreamErrorFor::<I>::other)
+                })
             };
 
             let status = || {
-                line().map(|line| {
+                text_line().map(|line| {
                     if line == "OK" {
                         Value::Okay
                     } else {
-                        Value::Status(line.into())
+                        Value::Status(line)
                     }
                 })
             };
 
-            let int = || {
+            let int = move || {
                 line().and_then(|line| match line.trim().parse::<i64>() {
                     Err(_) => Err(StreamErrorFor::<I>::message_static_message(
                         "Expected integer, got garbage",
@@ -88,64 +625,347 @@ where
                 })
             };
 
-            let data = || {
-                int().then_partial(move |size| {
-                    if *size < 0 {
-                        combine::value(Value::Nil).left()
+            let int_reply = move || {
+                line().and_then(move |line| {
+                    let line = match options.integers {
+                        IntegerParsing::Strict => line.trim(),
+                        IntegerParsing::Lenient => line.split_whitespace().next().unwrap_or(""),
+                        IntegerParsing::Canonical if !is_canonical_integer(line) => {
+                            return Err(StreamErrorFor::<I>::message_static_message(
+                                "Expected canonical integer",
+                            ))
+                        }
+                        IntegerParsing::Canonical => line,
+                    };
+                    line.parse::<i64>().map_err(|_| {
+                        StreamErrorFor::<I>::message_static_message("Expected integer, got garbage")
+                    })
+                })
+            };
+
+            // The length of strings and aggregates, `None` for the `?` of RESP3
+            // streamed replies.
+            let length = move || {
+                line().and_then(|line: &str| {
+                    if line == "?" {
+                        return Ok(None);
+                    }
+                    match line.trim().parse::<i64>() {
+                        Err(_) => Err(StreamErrorFor::<I>::message_static_message(
+                            "Expected integer, got garbage",
+                        )),
+                        Ok(value) => Ok(Some(value)),
+                    }
+                })
+            };
+
+            // Succeeds without consuming anything if the input starts with the
+            // terminator of a streamed reply.  A partial terminator at the end of
+            // the input is reported as incomplete input, so that parsing resumes
+            // with the terminator instead of with another element.
+            let stream_end = |terminator: &'static [u8]| {
+                combine::parser(move |input: &mut I| {
+                    let position = input.position();
+                    let rest = input.range();
+                    if rest.starts_with(terminator) {
+                        Ok(((), combine::error::Commit::Peek(())))
+                    } else if terminator.starts_with(rest) {
+                        let err =
+                            I::Error::from_error(position, StreamErrorFor::<I>::end_of_input());
+                        Err(combine::error::Commit::Commit(err.into()))
                     } else {
-                        take(*size as usize)
-                            .map(|bs: &[u8]| Value::Data(bs.to_vec()))
-                            .skip(crlf())
-                            .right()
+                        Err(combine::error::Commit::Peek(
+                            I::Error::empty(position).into(),
+                        ))
+                    }
+                })
+            };
+
+            let check_bulk = move |size: i64| -> Result<_, StreamErrorFor<I>> {
+                if size >= 0 {
+                    options
+                        .limits
+                        .check_bulk(size as usize)
+                        .map_err(StreamErrorFor::<I>::other)?;
+                }
+                Ok(size)
+            };
+
+            let blob_body = |size: i64| {
+                if size < 0 {
+                    combine::value(None).left()
+                } else {
+                    take(size as usize)
+                        .map(|bs: &[u8]| Some(bs.to_vec()))
+                        .skip(crlf())
+                        .right()
+                }
+            };
+
+            let check_payload = move |size: i64| -> Result<_, StreamErrorFor<I>> {
+                options
+                    .limits
+                    .check_payload(options.payload.saturating_add(size.max(0) as usize))
+                    .map_err(StreamErrorFor::<I>::other)?;
+                Ok(size)
+            };
+
+            let blob = move || {
+                int()
+                    .and_then(check_bulk)
+                    .then_partial(move |&mut size| blob_body(size))
+            };
+
+            // A streamed string is sent as `;<length>` chunks ended by an empty
+            // one.
+            let streamed_string = move || {
+                let chunk = move || {
+                    combine::token(b';')
+                        .with(int())
+                        .and_then(move |size| {
+                            if size <= 0 {
+                                return Err(StreamErrorFor::<I>::message_static_message(
+                                    "Expected chunk length",
+                                ));
+                            }
+                            check_bulk(size)
+                        })
+                        .then_partial(|&mut size| {
+                            take(size as usize)
+                                .map(|bs: &[u8]| bs.to_vec())
+                                .skip(crlf())
+                        })
+                };
+                checked_repeat_until(
+                    move |_: &Chunks| chunk(),
+                    stream_end(b";0\r\n"),
+                    move |chunks: &Chunks| {
+                        options.limits.check_bulk(chunks.0.len())?;
+                        options
+                            .limits
+                            .check_payload(options.payload.saturating_add(chunks.0.len()))
+                    },
+                )
+                .skip(take(4))
+                .map(|chunks: Chunks| Value::Data(chunks.0))
+            };
+
+            let data = move || {
+                length()
+                    .and_then(move |size| match size {
+                        Some(size) => check_bulk(size).and_then(check_payload).map(Some),
+                        None => Ok(None),
+                    })
+                    .then_partial(move |&mut size| match size {
+                        None => streamed_string().left(),
+                        Some(size) => blob_body(size)
+                            .map(|bs: Option<Vec<u8>>| bs.map_or(Value::Nil, Value::Data))
+                            .right(),
+                    })
+            };
+
+            // Verbatim strings start with a three letter format such as `txt:`.
+            let verbatim = move || {
+                blob().and_then(move |bs: Option<Vec<u8>>| match bs {
+                    None => Ok(Value::Nil),
+                    Some(bs) if bs.len() >= 4 && bs[3] == b':' => {
+                        let format = str::from_utf8(&bs[..3])
+                            .map_err(StreamErrorFor::<I>::other)?
+                            .to_string();
+                        let text = decoding
+                            .decode(&bs[4..])
+                            .map_err(StreamErrorFor::<I>::other)?;
+                        Ok(Value::VerbatimString { format, text })
                     }
+                    Some(_) => Err(StreamErrorFor::<I>::message_static_message(
+                        "Expected verbatim string format",
+                    )),
                 })
             };
 
-            let bulk = || {
-                int().then_partial(|&mut length| {
-                    if length < 0 {
-                        combine::value(Value::Nil).map(Ok).left()
+            // Maps have two values per entry which `build` receives flattened.
+            // Streamed aggregates have no length and end with a `.` line.  The
+            // bulk strings of every element count towards the payload of the
+            // ones after it.
+            let aggregate =
+                move |values_per_entry: usize, build: fn(Vec<Value>) -> RedisResult<Value>| {
+                    length()
+                        .and_then(move |length| -> Result<_, StreamErrorFor<I>> {
+                            match length {
+                                Some(length) if length < 0 => {}
+                                _ => {
+                                    options
+                                        .limits
+                                        .check_entries(
+                                            length.unwrap_or(0) as usize,
+                                            values_per_entry,
+                                            options.depth,
+                                        )
+                                        .map_err(StreamErrorFor::<I>::other)?;
+                                    record_depth(options.depth + 1);
+                                }
+                            }
+                            Ok(length)
+                        })
+                        .then_partial(move |&mut length| {
+                            let options = ParseOptions {
+                                depth: options.depth + 1,
+                                ..options
+                            };
+                            let element = move |items: &Items| {
+                                value(ParseOptions {
+                                    payload: options.payload.saturating_add(items.payload),
+                                    ..options
+                                })
+                            };
+                            match length {
+                                None => checked_repeat_until(
+                                    element,
+                                    stream_end(b".\r\n"),
+                                    move |items: &Items| match items.values.0 {
+                                        Ok(ref items) => options.limits.check_entries(
+                                            items.len().div_ceil(values_per_entry),
+                                            values_per_entry,
+                                            0,
+                                        ),
+                                        Err(_) => Ok(()),
+                                    },
+                                )
+                                .skip(take(3))
+                                .and_then(move |items: Items| {
+                                    let items = match items.values.0 {
+                                        Ok(items) => items,
+                                        Err(err) => return Ok(Err(err)),
+                                    };
+                                    if items.len() % values_per_entry != 0 {
+                                        return Err(StreamErrorFor::<I>::message_static_message(
+                                            "Expected a value for every key",
+                                        ));
+                                    }
+                                    Ok(build(items))
+                                })
+                                .left(),
+                                Some(length) if length < 0 => {
+                                    combine::value(Value::Nil).map(Ok).left().right()
+                                }
+                                Some(length) => {
+                                    let length = (length as usize).saturating_mul(values_per_entry);
+                                    count_with(length, element)
+                                        .map(move |items: Items| items.values.0.and_then(build))
+                                        .right()
+                                        .right()
+                                }
+                            }
+                        })
+                };
+
+            // Attributes are a map sent before the value they belong to.
+            let attribute = move || {
+                aggregate(2, map_from_pairs).and(value(options)).and_then(
+                    move |(attributes, data): (RedisResult<Value>, RedisResult<Value>)|
+                          -> Result<_, StreamErrorFor<I>> {
+                        let payload = [&attributes, &data]
+                            .iter()
+                            .filter_map(|value| value.as_ref().ok())
+                            .map(data_len)
+                            .fold(options.payload, usize::saturating_add);
+                        options
+                            .limits
+                            .check_payload(payload)
+                            .map_err(StreamErrorFor::<I>::other)?;
+                        let attributes = match attributes {
+                            Ok(Value::Map(pairs)) => pairs,
+                            Ok(_) => Vec::new(),
+                            Err(err) => return Ok(Err(err)),
+                        };
+                        Ok(data.map(|data| Value::Attribute {
+                            data: Box::new(data),
+                            attributes,
+                        }))
+                    },
+                )
+            };
+
+            let error = move || text_line().map(move |line| error_reply(line, options));
+
+            let blob_error = move || {
+                blob()
+                    .and_then(move |bs: Option<Vec<u8>>| {
+                        decoding
+                            .decode(&bs.unwrap_or_default())
+                            .map_err(StreamErrorFor::<I>::other)
+                    })
+                    .map(move |line| error_reply(line, options))
+            };
+
+            let null = || {
+                line().and_then(|line: &str| {
+                    if line.is_empty() {
+                        Ok(Value::Nil)
                     } else {
-                        let length = length as usize;
-                        combine::count_min_max(length, length, value())
-                            .map(|result: ResultExtend<_, _>| result.0.map(Value::Bulk))
-                            .right()
+                        Err(StreamErrorFor::<I>::message_static_message("Expected null"))
                     }
                 })
             };
 
-            let error = || {
-                line().map(|line: &str| {
-                    let desc = "An error was signalled by the server";
-                    let mut pieces = line.splitn(2, ' ');
-                    let kind = match pieces.next().unwrap() {
-                        "ERR" => ErrorKind::ResponseError,
-                        "EXECABORT" => ErrorKind::ExecAbortError,
-                        "LOADING" => ErrorKind::BusyLoadingError,
-                        "NOSCRIPT" => ErrorKind::NoScriptError,
-                        "MOVED" => ErrorKind::Moved,
-                        "ASK" => ErrorKind::Ask,
-                        "TRYAGAIN" => ErrorKind::TryAgain,
-                        "CLUSTERDOWN" => ErrorKind::ClusterDown,
-                        "CROSSSLOT" => ErrorKind::CrossSlot,
-                        "MASTERDOWN" => ErrorKind::MasterDown,
-                        "READONLY" => ErrorKind::ReadOnly,
-                        code => return make_extension_error(code, pieces.next()),
-                    };
-                    match pieces.next() {
-                        Some(detail) => RedisError::from((kind, desc, detail.to_string())),
-                        None => RedisError::from((kind, desc)),
+            let boolean = || {
+                line().and_then(|line: &str| match line {
+                    "t" => Ok(Value::Boolean(true)),
+                    "f" => Ok(Value::Boolean(false)),
+                    _ => Err(StreamErrorFor::<I>::message_static_message(
+                        "Expected boolean, got garbage",
+                    )),
+                })
+            };
+
+            let double = || {
+                line().and_then(|line: &str| match line.parse::<f64>() {
+                    Ok(value) => Ok(Value::Double(value)),
+                    Err(_) => Err(StreamErrorFor::<I>::message_static_message(
+                        "Expected double, got garbage",
+                    )),
+                })
+            };
+
+            let big_number = || {
+                line().and_then(|line: &str| {
+                    let digits = line.strip_prefix('-').unwrap_or(line);
+                    if !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit()) {
+                        Ok(Value::BigNumber(line.to_string()))
+                    } else {
+                        Err(StreamErrorFor::<I>::message_static_message(
+                            "Expected big number, got garbage",
+                        ))
                     }
                 })
             };
 
             combine::dispatch!(b;
                 b'+' => status().map(Ok),
-                b':' => int().map(|i| Ok(Value::Int(i))),
+                b':' => int_reply().map(|i| Ok(Value::Int(i))),
                 b'$' => data().map(Ok),
-                b'*' => bulk(),
-                b'-' => error().map(Err),
-                b => combine::unexpected_any(combine::error::Token(b))
+                b'*' => aggregate(1, |items| Ok(Value::Bulk(items))),
+                b'-' => error(),
+                b'%' => aggregate(2, map_from_pairs),
+                b'~' => aggregate(1, |items| Ok(Value::Set(items))),
+                b'>' => aggregate(1, push_from_items),
+                b'_' => null().map(Ok),
+                b'#' => boolean().map(Ok),
+                b',' => double().map(Ok),
+                b'(' => big_number().map(Ok),
+                b'=' => verbatim().map(Ok),
+                b'!' => blob_error(),
+                b'|' => attribute(),
+                // Fail without consulting the following input so that an unknown
+                // type byte at the end of a partial buffer is reported as an error
+                // instead of being treated as incomplete input.
+                b => combine::parser(move |input: &mut I| {
+                    let err = I::Error::from_error(
+                        input.position(),
+                        StreamErrorFor::<I>::unexpected_token(b),
+                    );
+                    Err(combine::error::Commit::Commit(err.into()))
+                })
             )
         }
     )))
@@ -155,28 +975,243 @@ where
 mod aio_support {
     use super::*;
 
-    use bytes::{Buf, BytesMut};
-    use tokio::io::AsyncRead;
+    use crate::cmd::Cmd;
+    use crate::pipeline::Pipeline;
+    use bytes::{Buf, BufMut, BytesMut};
+    use std::{
+        mem,
+        sync::{Arc, Mutex},
+    };
+    use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
     use tokio_util::codec::{Decoder, Encoder};
 
+    // Capacity of the buffers which replace shrunk read buffers, the
+    // initial capacity of tokio-util's framed streams.
+    const INITIAL_BUFFER_CAPACITY: usize = 8 * 1024;
+
+    /// How a codec manages its read buffer between replies.
+    ///
+    /// Decoding a large reply grows the read buffer, which would otherwise
+    /// keep its peak capacity for the lifetime of the connection.  Once a
+    /// reply was decoded and the buffer has grown past the idle capacity,
+    /// it is replaced by a small one.  By default the idle capacity is
+    /// 64 KiB and there is no pool.
+    #[derive(Clone, Debug)]
+    pub struct BufferPolicy {
+        max_idle_capacity: usize,
+        pool: Option<BufferPool>,
+    }
+
+    impl Default for BufferPolicy {
+        fn default() -> BufferPolicy {
+            BufferPolicy {
+                max_idle_capacity: 64 * 1024,
+                pool: None,
+            }
+        }
+    }
+
+    impl BufferPolicy {
+        /// Creates the default policy.
+        pub fn new() -> BufferPolicy {
+            BufferPolicy::default()
+        }
+
+        /// Sets the capacity past which the buffer is shrunk after a reply.
+        /// `usize::MAX` keeps buffers at their peak capacity.
+        pub fn max_idle_capacity(mut self, capacity: usize) -> BufferPolicy {
+            self.max_idle_capacity = capacity;
+            self
+        }
+
+        /// Hands the large buffers of the codec to a pool instead of freeing
+        /// them, and takes large buffers from it when a reply outgrows the
+        /// read buffer.
+        pub fn pool(mut self, pool: BufferPool) -> BufferPolicy {
+            self.pool = Some(pool);
+            self
+        }
+    }
+
+    /// Large read buffers shared by the codecs of several connections.
+    ///
+    /// Connections which only occasionally receive large replies can share
+    /// a few large buffers this way instead of each one allocating its own.
+    /// Buffers still referenced by decoded values are not pooled.
+    ///
+    /// ```rust
+    /// let pool = redis::BufferPool::new(16 * 1024 * 1024);
+    /// let policy = redis::BufferPolicy::new().pool(pool.clone());
+    /// let codec = redis::ValueCodec::default().buffer_policy(policy);
+    /// ```
+    #[derive(Clone, Debug)]
+    pub struct BufferPool {
+        state: Arc<Mutex<PoolState>>,
+    }
+
+    #[derive(Debug)]
+    struct PoolState {
+        buffers: Vec<BytesMut>,
+        pooled_bytes: usize,
+        max_pooled_bytes: usize,
+    }
+
+    impl BufferPool {
+        /// Creates a pool which keeps buffers up to a total capacity of
+        /// `max_pooled_bytes`.
+        pub fn new(max_pooled_bytes: usize) -> BufferPool {
+            BufferPool {
+                state: Arc::new(Mutex::new(PoolState {
+                    buffers: Vec::new(),
+                    pooled_bytes: 0,
+                    max_pooled_bytes,
+                })),
+            }
+        }
+
+        /// Returns the total capacity of the buffers in the pool.
+        pub fn pooled_bytes(&self) -> usize {
+            self.state.lock().unwrap().pooled_bytes
+        }
+
+        // Takes the smallest buffer with room for `capacity` bytes.
+        fn take(&self, capacity: usize) -> Option<BytesMut> {
+            let mut state = self.state.lock().unwrap();
+            let (idx, _) = state
+                .buffers
+                .iter()
+                .enumerate()
+                .filter(|(_, buf)| buf.capacity() >= capacity)
+                .min_by_key(|(_, buf)| buf.capacity())?;
+            let buf = state.buffers.swap_remove(idx);
+            state.pooled_bytes -= buf.capacity();
+            Some(buf)
+        }
+
+        // Pools the buffer if it can be reused without allocating.
+        fn put(&self, mut buf: BytesMut, capacity: usize) {
+            buf.clear();
+            if !buf.try_reclaim(capacity) {
+                return;
+            }
+            let mut state = self.state.lock().unwrap();
+            if state.pooled_bytes + buf.capacity() <= state.max_pooled_bytes {
+                state.pooled_bytes += buf.capacity();
+                state.buffers.push(buf);
+            }
+        }
+    }
+
+    // Applies a `BufferPolicy` to the read buffer of a codec.
+    #[derive(Debug, Default)]
+    struct ReadBuffer {
+        policy: BufferPolicy,
+        // the largest capacity the buffer was seen with
+        peak: usize,
+    }
+
+    impl ReadBuffer {
+        fn new(policy: BufferPolicy) -> ReadBuffer {
+            ReadBuffer { policy, peak: 0 }
+        }
+
+        fn observe(&mut self, bytes: &BytesMut) {
+            self.peak = self.peak.max(bytes.capacity());
+        }
+
+        // Called after a decode attempt which produced an item or, if
+        // `decoded` is false, needs more bytes.
+        fn update(&mut self, bytes: &mut BytesMut, decoded: bool) {
+            let max_idle = self.policy.max_idle_capacity;
+            if decoded {
+                if self.peak > max_idle && bytes.len() <= max_idle {
+                    let mut buf = BytesMut::with_capacity(INITIAL_BUFFER_CAPACITY.max(bytes.len()));
+                    buf.extend_from_slice(bytes);
+                    self.replace(bytes, buf);
+                }
+            } else if bytes.len() >= max_idle && bytes.capacity() - bytes.len() < bytes.len() {
+                // the buffer is about to grow, prefer a pooled one
+                let pooled = match self.policy.pool {
+                    Some(ref pool) => pool.take(2 * bytes.len()),
+                    None => None,
+                };
+                if let Some(mut buf) = pooled {
+                    buf.extend_from_slice(bytes);
+                    self.replace(bytes, buf);
+                }
+            }
+        }
+
+        fn replace(&mut self, bytes: &mut BytesMut, buf: BytesMut) {
+            let old = mem::replace(bytes, buf);
+            if let Some(ref pool) = self.policy.pool {
+                pool.put(old, self.peak);
+            }
+            self.peak = bytes.capacity();
+        }
+    }
+
+    /// A codec for the client side of the protocol, which encodes commands
+    /// and decodes the replies into `Value`s.
+    ///
+    /// Commands and pipelines are serialized straight into the write buffer,
+    /// already packed commands can be sent as a `Vec<u8>`.  Errors returned
+    /// by the server are items of the stream, while malformed replies fail
+    /// the decoding.
+    ///
+    /// ```rust,no_run
+    /// # async fn do_something(stream: tokio::net::TcpStream) -> redis::RedisResult<()> {
+    /// use futures_util::{SinkExt, StreamExt};
+    /// use tokio_util::codec::Framed;
+    ///
+    /// let mut framed = Framed::new(stream, redis::ValueCodec::default());
+    /// framed.send(&redis::cmd("PING")).await?;
+    /// let reply = framed.next().await.unwrap()??;
+    /// # Ok(()) }
+    /// ```
     #[derive(Default)]
     pub struct ValueCodec {
         state: AnySendSyncPartialState,
+        buffer: ReadBuffer,
+        options: ParseOptions,
     }
 
     impl ValueCodec {
+        /// Sets how the read buffer is managed between replies.
+        pub fn buffer_policy(mut self, policy: BufferPolicy) -> ValueCodec {
+            self.buffer = ReadBuffer::new(policy);
+            self
+        }
+
+        /// Enables or disables decoding server errors nested in aggregates
+        /// into `Value::ServerError` values.
+        ///
+        /// See `Parser::set_error_values` for details.
+        pub fn error_values(mut self, enabled: bool) -> ValueCodec {
+            self.options.error_values = enabled;
+            self
+        }
+
         fn decode_stream(
             &mut self,
             bytes: &mut BytesMut,
             eof: bool,
         ) -> RedisResult<Option<RedisResult<Value>>> {
+            self.buffer.observe(bytes);
             let (opt, removed_len) = {
                 let buffer = &bytes[..];
                 let mut stream =
                     combine::easy::Stream(combine::stream::MaybePartialStream(buffer, !eof));
-                match combine::stream::decode_tokio(value(), &mut stream, &mut self.state) {
+                match combine::stream::decode_tokio(
+                    value(self.options),
+                    &mut stream,
+                    &mut self.state,
+                ) {
                     Ok(x) => x,
                     Err(err) => {
+                        if let Some(err) = limit_error(&err) {
+                            return Err(err);
+                        }
                         let err = err
                             .map_position(|pos| pos.translate_position(buffer))
                             .map_range(|range| format!("{:?}", range))
@@ -191,6 +1226,7 @@ mod aio_support {
             };
 
             bytes.advance(removed_len);
+            self.buffer.update(bytes, opt.is_some());
             match opt {
                 Some(result) => Ok(Some(result)),
                 None => Ok(None),
@@ -198,15 +1234,288 @@ mod aio_support {
         }
     }
 
-    impl Encoder<Vec<u8>> for ValueCodec {
-        type Error = RedisError;
-        fn encode(&mut self, item: Vec<u8>, dst: &mut BytesMut) -> Result<(), Self::Error> {
-            dst.extend_from_slice(item.as_ref());
-            Ok(())
-        }
+    /// A codec which decodes replies into `BytesValue`s without copying
+    /// their binary data.
+    ///
+    /// Each reply is split off the decode buffer as a whole, so the `Data`
+    /// of the value are slices sharing the memory the reply was read into.
+    /// This avoids an allocation and a copy per bulk string, which matters
+    /// for large `GET` and `MGET` replies.  Like `parse_redis_value_bytes`
+    /// this only accepts RESP2 data; a RESP3 reply fails the decoding with
+    /// an `ErrorKind::ResponseError`.
+    ///
+    /// ```rust,no_run
+    /// # async fn do_something(stream: tokio::net::TcpStream) -> redis::RedisResult<()> {
+    /// use futures_util::StreamExt;
+    /// use tokio_util::codec::FramedRead;
+    ///
+    /// let mut replies = FramedRead::new(stream, redis::BytesValueCodec::default());
+    /// while let Some(reply) = replies.next().await {
+    ///     let value: redis::BytesValue = reply??;
+    /// }
+    /// # Ok(()) }
+    /// ```
+    #[derive(Default, Debug)]
+    pub struct BytesValueCodec {
+        limits: ParseLimits,
+        scanned: ScanState,
+        buffer: ReadBuffer,
     }
 
-    impl Decoder for ValueCodec {
+    impl BytesValueCodec {
+        /// Creates a codec which rejects replies exceeding the limits.
+        ///
+        /// The whole reply is buffered before it is decoded, so the limits
+        /// are what bounds the size of the buffer.
+        pub fn with_limits(limits: ParseLimits) -> BytesValueCodec {
+            BytesValueCodec {
+                limits,
+                ..BytesValueCodec::default()
+            }
+        }
+
+        /// Sets how the read buffer is managed between replies.
+        pub fn buffer_policy(mut self, policy: BufferPolicy) -> BytesValueCodec {
+            self.buffer = ReadBuffer::new(policy);
+            self
+        }
+
+        // Splits the next reply off the buffer, if it is complete.
+        fn split_reply(&mut self, bytes: &mut BytesMut) -> RedisResult<Option<bytes::Bytes>> {
+            // Only the headers are scanned to find the end of the reply, the
+            // payloads are skipped over.
+            self.buffer.observe(bytes);
+            let len = match scan_reply(&bytes[..], &mut NoopSink, &self.limits, &mut self.scanned) {
+                Ok(len) => len,
+                Err(err) if err.is_io_error() => {
+                    self.buffer.update(bytes, false);
+                    return Ok(None);
+                }
+                Err(err) => {
+                    self.scanned = ScanState::default();
+                    return Err(err);
+                }
+            };
+            let frame = bytes.split_to(len).freeze();
+            self.buffer.update(bytes, true);
+            Ok(Some(frame))
+        }
+    }
+
+    impl Decoder for BytesValueCodec {
+        type Item = RedisResult<BytesValue>;
+        type Error = RedisError;
+
+        fn decode(&mut self, bytes: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+            let frame = match self.split_reply(bytes)? {
+                Some(frame) => frame,
+                None => return Ok(None),
+            };
+            let mut sink = TreeSink::new(&frame, BytesBuilder(&frame));
+            sink.scan(true)?;
+            sink.into_reply().map(Some)
+        }
+
+        fn decode_eof(&mut self, bytes: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+            match self.decode(bytes)? {
+                None if !bytes.is_empty() => {
+                    Err(io::Error::from(io::ErrorKind::UnexpectedEof).into())
+                }
+                item => Ok(item),
+            }
+        }
+    }
+
+    /// A codec for the server side of the protocol, which decodes the
+    /// commands sent by clients and encodes the replies to them.
+    ///
+    /// A command is an array of bulk strings and is decoded into its
+    /// arguments, which share the memory the command was read into.  Lines
+    /// which do not start with `*` are decoded as inline commands, see
+    /// `parse_inline_command`, and empty lines are skipped.  Other frames
+    /// fail the decoding.  Replies are encoded with `write_reply`.
+    ///
+    /// ```rust,no_run
+    /// # async fn do_something(stream: tokio::net::TcpStream) -> redis::RedisResult<()> {
+    /// use futures_util::{SinkExt, StreamExt};
+    /// use tokio_util::codec::Framed;
+    ///
+    /// let mut framed = Framed::new(stream, redis::CommandCodec::default());
+    /// while let Some(args) = framed.next().await {
+    ///     let reply = match args?.first() {
+    ///         Some(name) if name.eq_ignore_ascii_case(b"PING") => redis::Value::Status("PONG".into()),
+    ///         _ => redis::Value::Nil,
+    ///     };
+    ///     framed.send(&reply).await?;
+    /// }
+    /// # Ok(()) }
+    /// ```
+    #[derive(Default, Debug)]
+    pub struct CommandCodec {
+        limits: ParseLimits,
+        scanned: ScanState,
+    }
+
+    impl CommandCodec {
+        /// Creates a codec which rejects commands exceeding the limits.
+        pub fn with_limits(limits: ParseLimits) -> CommandCodec {
+            CommandCodec {
+                limits,
+                scanned: ScanState::default(),
+            }
+        }
+    }
+
+    impl Decoder for CommandCodec {
+        type Item = Vec<bytes::Bytes>;
+        type Error = RedisError;
+
+        fn decode(&mut self, bytes: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+            while matches!(bytes.first(), Some(&b) if b != b'*') {
+                let end = match memchr::memchr(b'\n', &bytes[..]) {
+                    Some(end) => end,
+                    None => {
+                        self.limits.check_bulk(bytes.len())?;
+                        return Ok(None);
+                    }
+                };
+                // the line is consumed even if it is malformed
+                let line = bytes.split_to(end + 1);
+                let line = &line[..end];
+                let args = split_inline_args(line.strip_suffix(b"\r").unwrap_or(line))?;
+                if !args.is_empty() {
+                    return Ok(Some(args.into_iter().map(bytes::Bytes::from).collect()));
+                }
+            }
+
+            let len = match scan_reply(&bytes[..], &mut NoopSink, &self.limits, &mut self.scanned) {
+                Ok(len) => len,
+                Err(err) if err.is_io_error() => return Ok(None),
+                Err(err) => {
+                    self.scanned = ScanState::default();
+                    return Err(err);
+                }
+            };
+            let frame = bytes.split_to(len).freeze();
+            let mut sink = CommandSink {
+                frame: &frame,
+                args: Vec::new(),
+                depth: 0,
+                valid: true,
+            };
+            scan_reply(
+                &frame[..],
+                &mut sink,
+                &self.limits,
+                &mut ScanState::default(),
+            )?;
+            if !sink.valid || frame[0] != b'*' {
+                fail!((
+                    ErrorKind::ResponseError,
+                    "parse error",
+                    "Expected a command array of bulk strings".to_string()
+                ));
+            }
+            Ok(Some(sink.args))
+        }
+    }
+
+    impl<'a> Encoder<&'a Value> for CommandCodec {
+        type Error = RedisError;
+
+        fn encode(&mut self, item: &'a Value, dst: &mut BytesMut) -> Result<(), Self::Error> {
+            write_reply(item, &mut dst.writer())?;
+            Ok(())
+        }
+    }
+
+    // Collects the arguments of a command, `valid` is cleared by any event
+    // other than the ones of a flat array of bulk strings.
+    struct CommandSink<'a> {
+        frame: &'a bytes::Bytes,
+        args: Vec<bytes::Bytes>,
+        depth: usize,
+        valid: bool,
+    }
+
+    impl ParserSink for CommandSink<'_> {
+        fn on_nil(&mut self) {
+            self.valid = false;
+        }
+
+        fn on_int(&mut self, _value: i64) {
+            self.valid = false;
+        }
+
+        fn on_data(&mut self, data: &[u8]) {
+            self.valid &= self.depth == 1;
+            if self.valid {
+                self.args.push(self.frame.slice_ref(data));
+            }
+        }
+
+        fn on_status(&mut self, _status: &[u8]) {
+            self.valid = false;
+        }
+
+        fn on_error(&mut self, _error: &[u8]) {
+            self.valid = false;
+        }
+
+        fn on_array_begin(&mut self, len: usize) {
+            self.valid &= self.depth == 0;
+            self.depth += 1;
+            if self.valid {
+                self.args.reserve(len.min(1024));
+            }
+        }
+
+        fn on_array_end(&mut self) {
+            self.depth -= 1;
+        }
+
+        fn on_double(&mut self, _value: f64) {
+            self.valid = false;
+        }
+
+        fn on_big_number(&mut self, _digits: &[u8]) {
+            self.valid = false;
+        }
+
+        fn on_verbatim(&mut self, _format: &[u8], _text: &[u8]) {
+            self.valid = false;
+        }
+
+        fn on_data_chunks(&mut self, _chunks: &[&[u8]]) {
+            self.valid = false;
+        }
+    }
+
+    impl Encoder<Vec<u8>> for ValueCodec {
+        type Error = RedisError;
+        fn encode(&mut self, item: Vec<u8>, dst: &mut BytesMut) -> Result<(), Self::Error> {
+            dst.extend_from_slice(item.as_ref());
+            Ok(())
+        }
+    }
+
+    impl<'a> Encoder<&'a Cmd> for ValueCodec {
+        type Error = RedisError;
+        fn encode(&mut self, item: &'a Cmd, dst: &mut BytesMut) -> Result<(), Self::Error> {
+            item.write_packed_command_to_bytes(dst);
+            Ok(())
+        }
+    }
+
+    impl<'a> Encoder<&'a Pipeline> for ValueCodec {
+        type Error = RedisError;
+        fn encode(&mut self, item: &'a Pipeline, dst: &mut BytesMut) -> Result<(), Self::Error> {
+            item.write_packed_pipeline_to_bytes(dst);
+            Ok(())
+        }
+    }
+
+    impl Decoder for ValueCodec {
         type Item = RedisResult<Value>;
         type Error = RedisError;
 
@@ -219,6 +1528,79 @@ mod aio_support {
         }
     }
 
+    /// Fuzzing entry point which checks that incremental parsing agrees with
+    /// one-shot parsing.
+    ///
+    /// The input is parsed once with `parse_redis_value` and once with a
+    /// `ValueCodec` which is fed the same bytes in chunks of varying size.
+    /// Both must produce the same value or the same server error, and both
+    /// must reject input the other one rejects.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the two parse paths disagree.
+    pub fn fuzz_decode(data: &[u8]) {
+        use tokio_util::codec::Decoder;
+
+        let one_shot = parse_redis_value(data);
+
+        let mut codec = ValueCodec::default();
+        let mut buffer = BytesMut::new();
+        let mut seed = data.iter().fold(data.len() as u64, |seed, &b| {
+            seed.wrapping_mul(31).wrapping_add(u64::from(b))
+        });
+        let mut rest = data;
+        let mut incremental = None;
+        while incremental.is_none() {
+            let decoded = if rest.is_empty() {
+                let decoded = codec.decode_eof(&mut buffer);
+                if let Ok(None) = decoded {
+                    break;
+                }
+                decoded
+            } else {
+                seed = seed
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                let chunk = 1 + (seed >> 33) as usize % rest.len();
+                buffer.extend_from_slice(&rest[..chunk]);
+                rest = &rest[chunk..];
+                codec.decode(&mut buffer)
+            };
+            match decoded {
+                Ok(None) => {}
+                Ok(Some(result)) => incremental = Some(Ok(result)),
+                Err(err) => incremental = Some(Err(err)),
+            }
+        }
+
+        match (one_shot, incremental) {
+            (Ok(expected), Some(Ok(Ok(value)))) => assert_eq!(expected, value),
+            (Err(expected), Some(Ok(Err(err)))) => assert_eq!(expected, err),
+            (Err(expected), Some(Err(_))) | (Err(expected), None) => assert!(
+                expected.is_io_error() || expected.to_string().starts_with("parse error"),
+                "one-shot parse failed with {:?} but incremental parse rejected the input",
+                expected
+            ),
+            (one_shot, incremental) => panic!(
+                "one-shot parse returned {:?} but incremental parse returned {:?}",
+                one_shot, incremental
+            ),
+        }
+    }
+
+    /// Serializes a redis value in the wire format to an async writer.
+    ///
+    /// See `write_reply` for details.
+    pub async fn write_reply_async<W>(value: &Value, w: &mut W) -> io::Result<()>
+    where
+        W: AsyncWrite + std::marker::Unpin,
+    {
+        let mut buf = Vec::new();
+        write_reply(value, &mut buf)?;
+        w.write_all(&buf).await
+    }
+
     /// Parses a redis value asynchronously.
     pub async fn parse_redis_value_async<R>(
         decoder: &mut combine::stream::Decoder<AnySendSyncPartialState, PointerOffset<[u8]>>,
@@ -227,7 +1609,24 @@ mod aio_support {
     where
         R: AsyncRead + std::marker::Unpin,
     {
-        let result = combine::decode_tokio!(*decoder, *read, value(), |input, _| {
+        parse_value_async(decoder, read, false).await
+    }
+
+    // Parses a redis value asynchronously, keeping the server errors nested
+    // in aggregates as `Value::ServerError` if `error_values` is set.
+    pub(crate) async fn parse_value_async<R>(
+        decoder: &mut combine::stream::Decoder<AnySendSyncPartialState, PointerOffset<[u8]>>,
+        read: &mut R,
+        error_values: bool,
+    ) -> RedisResult<Value>
+    where
+        R: AsyncRead + std::marker::Unpin,
+    {
+        let options = ParseOptions {
+            error_values,
+            ..ParseOptions::default()
+        };
+        let result = combine::decode_tokio!(*decoder, *read, value(options), |input, _| {
             combine::stream::easy::Stream::from(input)
         });
         match result {
@@ -236,6 +1635,8 @@ mod aio_support {
                 combine::stream::decoder::Error::Parse(err) => {
                     if err.is_unexpected_end_of_input() {
                         RedisError::from(io::Error::from(io::ErrorKind::UnexpectedEof))
+                    } else if let Some(err) = limit_error(&err) {
+                        err
                     } else {
                         let err = err
                             .map_range(|range| format!("{:?}", range))
@@ -254,9 +1655,98 @@ mod aio_support {
 #[cfg_attr(docsrs, doc(cfg(feature = "aio")))]
 pub use self::aio_support::*;
 
+/// The number of parsed values of each type, as returned by
+/// `Parser::type_histogram`.
+///
+/// Every element of an array is counted as well as the array itself.  A
+/// server error aborts the parsing of the value it is part of, so it is
+/// counted once and the elements parsed before it are not counted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TypeHistogram {
+    /// The number of nil values.
+    pub nils: u64,
+    /// The number of integers.
+    pub integers: u64,
+    /// The number of bulk strings.
+    pub bulk_strings: u64,
+    /// The number of arrays.
+    pub arrays: u64,
+    /// The number of statuses, including `OK`.
+    pub statuses: u64,
+    /// The number of server errors.
+    pub errors: u64,
+    /// The number of RESP3 maps.
+    pub maps: u64,
+    /// The number of RESP3 sets.
+    pub sets: u64,
+    /// The number of RESP3 doubles.
+    pub doubles: u64,
+    /// The number of RESP3 booleans.
+    pub booleans: u64,
+    /// The number of RESP3 big numbers.
+    pub big_numbers: u64,
+    /// The number of RESP3 verbatim strings.
+    pub verbatim_strings: u64,
+    /// The number of RESP3 push frames.
+    pub pushes: u64,
+    /// The number of values sent with attributes.
+    pub attributes: u64,
+}
+
+impl TypeHistogram {
+    fn count(&mut self, value: &Value) {
+        match *value {
+            Value::Nil => self.nils += 1,
+            Value::Int(_) => self.integers += 1,
+            Value::Data(_) => self.bulk_strings += 1,
+            Value::Bulk(ref items) => {
+                self.arrays += 1;
+                for item in items {
+                    self.count(item);
+                }
+            }
+            Value::Status(_) | Value::Okay => self.statuses += 1,
+            Value::Map(ref pairs) => {
+                self.maps += 1;
+                for (key, val) in pairs {
+                    self.count(key);
+                    self.count(val);
+                }
+            }
+            Value::Set(ref items) => {
+                self.sets += 1;
+                for item in items {
+                    self.count(item);
+                }
+            }
+            Value::Double(_) => self.doubles += 1,
+            Value::Boolean(_) => self.booleans += 1,
+            Value::BigNumber(_) => self.big_numbers += 1,
+            Value::VerbatimString { .. } => self.verbatim_strings += 1,
+            Value::Push { ref data, .. } => {
+                self.pushes += 1;
+                for item in data {
+                    self.count(item);
+                }
+            }
+            Value::Attribute { ref data, .. } => {
+                self.attributes += 1;
+                self.count(data);
+            }
+            Value::ServerError(_) => self.errors += 1,
+        }
+    }
+}
+
 /// The internal redis response parser.
 pub struct Parser {
     decoder: combine::stream::decoder::Decoder<AnySendSyncPartialState, PointerOffset<[u8]>>,
+    options: ParseOptions,
+    track_depth: bool,
+    // The depth reached by the reply parsed so far, while it is incomplete.
+    partial_depth: usize,
+    last_max_depth: Option<usize>,
+    type_histogram: Option<TypeHistogram>,
 }
 
 impl Default for Parser {
@@ -277,23 +1767,112 @@ impl Parser {
     pub fn new() -> Parser {
         Parser {
             decoder: combine::stream::decoder::Decoder::new(),
+            options: ParseOptions::default(),
+            track_depth: false,
+            partial_depth: 0,
+            last_max_depth: None,
+            type_histogram: None,
         }
     }
 
+    /// Sets the policy for decoding status and error lines.  They are
+    /// decoded as strict UTF-8 by default.
+    pub fn set_string_decoding(&mut self, decoding: StringDecoding) {
+        self.options.decoding = decoding;
+    }
+
+    /// Returns the string decoding policy of this parser.
+    pub fn string_decoding(&self) -> StringDecoding {
+        self.options.decoding
+    }
+
+    /// Sets the policy for parsing integer replies.  Integers are parsed
+    /// strictly by default.
+    pub fn set_integer_parsing(&mut self, integers: IntegerParsing) {
+        self.options.integers = integers;
+    }
+
+    /// Returns the policy for parsing integer replies of this parser.
+    pub fn integer_parsing(&self) -> IntegerParsing {
+        self.options.integers
+    }
+
+    /// Enables or disables parsing server errors nested in aggregates into
+    /// `Value::ServerError` values instead of failing the whole reply, so
+    /// that the other elements are kept.  Errors which are a reply of their
+    /// own still fail.  This is disabled by default.
+    pub fn set_error_values(&mut self, enabled: bool) {
+        self.options.error_values = enabled;
+    }
+
+    /// Sets the limits on the size of the parsed replies.  There are no
+    /// limits by default.
+    pub fn set_limits(&mut self, limits: ParseLimits) {
+        self.options.limits = limits;
+    }
+
+    /// Returns the limits on the size of the replies of this parser.
+    pub fn limits(&self) -> ParseLimits {
+        self.options.limits
+    }
+
+    /// Enables or disables recording the nesting depth of parsed values.
+    /// This is disabled by default.
+    pub fn set_track_depth(&mut self, enabled: bool) {
+        self.track_depth = enabled;
+        self.partial_depth = 0;
+        self.last_max_depth = None;
+    }
+
+    /// Returns the maximum nesting depth of the last successfully parsed
+    /// value if depth tracking is enabled.
+    ///
+    /// Scalars have a depth of `0` and every level of arrays, sets, maps
+    /// and attributes adds one, so `*1\r\n*0\r\n` has a depth of `2`.  The
+    /// depth is recorded while parsing, also across the parts of a reply
+    /// read with `parse_value_from_deque`.  Returns `None` if
+    /// tracking is disabled or if the last parse failed.
+    pub fn last_max_depth(&self) -> Option<usize> {
+        self.last_max_depth
+    }
+
+    /// Enables or disables counting the types of the parsed values.
+    /// Enabling it resets the counters.  This is disabled by default.
+    pub fn set_count_types(&mut self, enabled: bool) {
+        self.type_histogram = if enabled {
+            Some(TypeHistogram::default())
+        } else {
+            None
+        };
+    }
+
+    /// Returns how many values of each type were parsed since counting was
+    /// enabled, or `None` if counting is disabled.
+    pub fn type_histogram(&self) -> Option<&TypeHistogram> {
+        self.type_histogram.as_ref()
+    }
+
     // public api
 
     /// Parses synchronously into a single value from the reader.
     pub fn parse_value<T: Read>(&mut self, mut reader: T) -> RedisResult<Value> {
+        let tracked = if self.track_depth {
+            MAX_DEPTH.with(|max| max.replace(Some(self.partial_depth)))
+        } else {
+            None
+        };
         let mut decoder = &mut self.decoder;
-        let result = combine::decode!(decoder, reader, value(), |input, _| {
+        let result = combine::decode!(decoder, reader, value(self.options), |input, _| {
             combine::stream::easy::Stream::from(input)
         });
-        match result {
+        let result = match result {
             Err(err) => Err(match err {
                 combine::stream::decoder::Error::Io { error, .. } => error.into(),
                 combine::stream::decoder::Error::Parse(err) => {
                     if err.is_unexpected_end_of_input() {
                         RedisError::from(io::Error::from(io::ErrorKind::UnexpectedEof))
+                    } else if let Some(err) = limit_error(&err) {
+                        err
                     } else {
                         let err = err
                             .map_range(|range| format!("{:?}", range))
@@ -303,37 +1882,2835 @@ impl Parser {
                     }
                 }
             }),
-            Ok(result) => result,
+            Ok(result) => {
+                if let Some(ref mut histogram) = self.type_histogram {
+                    match result {
+                        Ok(ref value) => histogram.count(value),
+                        Err(_) => histogram.errors += 1,
+                    }
+                }
+                result
+            }
+        };
+        if self.track_depth {
+            let depth = MAX_DEPTH.with(|max| max.replace(tracked)).unwrap_or(0);
+            match result {
+                Err(ref err) if err.is_io_error() => self.partial_depth = depth,
+                _ => {
+                    self.partial_depth = 0;
+                    self.last_max_depth = result.as_ref().ok().map(|_| depth);
+                }
+            }
+        }
+        result
+    }
+
+    /// Parses synchronously into a single value from the bytes of a
+    /// `VecDeque`, reading both of its slices without making it contiguous.
+    ///
+    /// The bytes read by the parser are removed from the front of the
+    /// deque.  If the deque does not hold a complete value, an IO error of
+    /// kind `WouldBlock` is returned and the parser keeps the incomplete
+    /// bytes, so parsing can be resumed once more bytes were pushed.
+    pub fn parse_value_from_deque(&mut self, deque: &mut VecDeque<u8>) -> RedisResult<Value> {
+        let (front, back) = deque.as_slices();
+        let mut reader = DequeReader {
+            inner: front.chain(back),
+            count: 0,
+        };
+        let result = self.parse_value(&mut reader);
+        let count = reader.count;
+        deque.drain(..count);
+        result
+    }
+
+    /// Parses synchronously into a single value from the reader, returning
+    /// `Ok(None)` if the reader is exhausted exactly at a frame boundary.
+    ///
+    /// This allows reading values in a loop until the stream ends without
+    /// treating the end of the stream as an error.  A reader that ends in
+    /// the middle of a frame still results in an `UnexpectedEof` error.
+    pub fn try_parse_value<T: Read>(&mut self, mut reader: T) -> RedisResult<Option<Value>> {
+        if !self.decoder.buffer().is_empty() {
+            return self.parse_value(reader).map(Some);
+        }
+
+        let mut first = [0; 1];
+        loop {
+            match reader.read(&mut first) {
+                Ok(0) => return Ok(None),
+                Ok(_) => break,
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err.into()),
+            }
+        }
+        self.parse_value(io::Cursor::new(first).chain(reader))
+            .map(Some)
+    }
+
+    /// Parses the start of a reply.  The elements of an array or set, and
+    /// the keys and values of a map, are left in the reader, so they can be
+    /// parsed one by one with `parse_value`, any other reply is parsed as a
+    /// whole.
+    pub(crate) fn parse_head<T: Read>(&mut self, mut reader: T) -> RedisResult<ReplyHead> {
+        loop {
+            match self.decoder.buffer().first().copied() {
+                Some(b'*') | Some(b'~') | Some(b'%') => {}
+                Some(_) => break,
+                None if self.fill_buffer(&mut reader)? => continue,
+                None => break,
+            }
+            let end = match memchr::memchr(b'\n', self.decoder.buffer()) {
+                Some(end) => end,
+                None if self.fill_buffer(&mut reader)? => continue,
+                None => fail!(io::Error::from(io::ErrorKind::UnexpectedEof)),
+            };
+            // null and streamed aggregates are parsed as a whole
+            let len = match str::from_utf8(&self.decoder.buffer()[1..end])
+                .ok()
+                .and_then(|line| line.strip_suffix('\r'))
+                .and_then(|line| line.parse::<usize>().ok())
+            {
+                Some(len) => len,
+                None => break,
+            };
+            let len = match self.decoder.buffer()[0] {
+                b'%' => {
+                    self.options.limits.check_map(len, 0)?;
+                    len.saturating_mul(2)
+                }
+                _ => {
+                    self.options.limits.check_aggregate(len, 0)?;
+                    len
+                }
+            };
+            self.decoder.advance(&mut reader, end + 1);
+            return Ok(ReplyHead::Aggregate(len));
         }
+        self.parse_value(reader).map(ReplyHead::Value)
+    }
+
+    // Reads more bytes into the buffer of the decoder, returns false at the
+    // end of the reader.
+    fn fill_buffer<T: Read>(&mut self, reader: T) -> RedisResult<bool> {
+        let len = self.decoder.buffer().len();
+        self.decoder.__before_parse(reader)?;
+        Ok(self.decoder.buffer().len() > len)
     }
 }
 
-/// Parses bytes into a redis value.
+/// The start of a reply read by `Parser::parse_head`.
+#[derive(Debug)]
+pub(crate) enum ReplyHead {
+    /// An array, set or map of which the given number of elements are
+    /// still to be parsed, counting the keys and values of maps.
+    Aggregate(usize),
+    /// Any other reply.
+    Value(Value),
+}
+
+/// A low-level token of the redis protocol as produced by [`Tokens`].
 ///
-/// This is the most straightforward way to parse something into a low
-/// level redis value instead of having to use a whole parser.
-pub fn parse_redis_value(bytes: &[u8]) -> RedisResult<Value> {
-    let mut parser = Parser::new();
-    parser.parse_value(bytes)
+/// Tokens follow the order in which they appear on the wire:
+///
+/// * an `ArrayHeader(n)` is followed by the tokens of exactly `n` elements,
+///   each of which may itself be an array;
+/// * a `BulkHeader(n)` is always immediately followed by a `BulkData` of
+///   exactly `n` bytes;
+/// * null bulk strings and null arrays are both reported as `Null` and
+///   have no further tokens.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Token<'a> {
+    /// The header of an array with the given number of elements.
+    ArrayHeader(usize),
+    /// The header of a bulk string with the given length in bytes.
+    BulkHeader(usize),
+    /// The payload of a bulk string.
+    BulkData(&'a [u8]),
+    /// An integer.
+    Integer(i64),
+    /// A simple string (status) line.
+    SimpleString(&'a str),
+    /// An error line, including the error code (e.g. `ERR unknown command`).
+    Error(&'a str),
+    /// A null bulk string or null array.
+    Null,
 }
 
-#[cfg(test)]
-mod tests {
-    #[cfg(feature = "aio")]
-    use super::*;
+/// An iterator over the [`Token`]s of a buffer of redis protocol data.
+///
+/// Unlike `parse_redis_value` this does not build a `Value` tree, which
+/// makes it useful for tooling that only needs to inspect the structure of
+/// the data.  The tokens are reported by scanning one reply at a time
+/// with [`parse_with_sink`].  If the buffer ends in the middle of a token
+/// an `UnexpectedEof` io error is returned after the tokens before it and
+/// [`Tokens::position`] points to the start of that token, or to the header
+/// of a bulk string whose data is incomplete, so scanning can be resumed
+/// from there once more data is available.
+///
+/// Only the RESP2 protocol is tokenized.  The RESP3 types, including
+/// streamed strings and aggregates, fail with an `ErrorKind::ResponseError`
+/// naming the unsupported type; use [`parse_with_sink`] to scan RESP3 data.
+///
+/// ```rust
+/// use redis::{Token, Tokens};
+///
+/// let tokens: Vec<_> = Tokens::new(b"*2\r\n$3\r\nfoo\r\n:42\r\n")
+///     .collect::<redis::RedisResult<_>>()
+///     .unwrap();
+/// assert_eq!(
+///     tokens,
+///     vec![
+///         Token::ArrayHeader(2),
+///         Token::BulkHeader(3),
+///         Token::BulkData(b"foo"),
+///         Token::Integer(42),
+///     ]
+/// );
+/// ```
+#[derive(Debug)]
+pub struct Tokens<'a> {
+    buf: &'a [u8],
+    // Where the reply whose tokens are pending starts, and where scanning
+    // resumes once they were returned.
+    start: usize,
+    resume: usize,
+    pending: VecDeque<Token<'a>>,
+    // The error to return after the pending tokens.
+    error: Option<RedisError>,
+    failed: bool,
+}
 
-    #[cfg(feature = "aio")]
-    #[test]
-    fn decode_eof_returns_none_at_eof() {
-        use tokio_util::codec::Decoder;
-        let mut codec = ValueCodec::default();
+impl<'a> Tokens<'a> {
+    /// Creates a token iterator over the given buffer.
+    pub fn new(buf: &'a [u8]) -> Tokens<'a> {
+        Tokens {
+            buf,
+            start: 0,
+            resume: 0,
+            pending: VecDeque::new(),
+            error: None,
+            failed: false,
+        }
+    }
 
-        let mut bytes = bytes::BytesMut::from(&b"+GET 123\r\n"[..]);
-        assert_eq!(
-            codec.decode_eof(&mut bytes),
-            Ok(Some(Ok(parse_redis_value(b"+GET 123\r\n").unwrap())))
-        );
-        assert_eq!(codec.decode_eof(&mut bytes), Ok(None));
-        assert_eq!(codec.decode_eof(&mut bytes), Ok(None));
+    /// Returns the position in the buffer scanning can be resumed from:
+    /// the number of bytes of the tokens returned so far.  While the tokens
+    /// of a reply are being returned this is the start of the reply, as a
+    /// new `Tokens` has to start with the reply again.
+    pub fn position(&self) -> usize {
+        if self.pending.is_empty() {
+            self.resume
+        } else {
+            self.start
+        }
+    }
+
+    fn text(line: &'a [u8]) -> RedisResult<&'a str> {
+        str::from_utf8(line).map_err(|err| {
+            RedisError::from((ErrorKind::ResponseError, "parse error", err.to_string()))
+        })
+    }
+
+    fn length(line: &'a [u8]) -> RedisResult<Option<usize>> {
+        match Self::text(line)?.trim().parse::<i64>() {
+            Ok(len) if len < 0 => Ok(None),
+            Ok(len) => Ok(Some(len as usize)),
+            Err(_) => fail!((
+                ErrorKind::ResponseError,
+                "parse error",
+                "Expected integer, got garbage".to_string()
+            )),
+        }
+    }
+
+    // Scans the next reply into the pending tokens.  After an error the
+    // tokens before it are still returned.
+    fn scan(&mut self) {
+        self.start = self.resume;
+        let mut sink = TokenSink {
+            buf: &self.buf[self.start..],
+            tokens: VecDeque::new(),
+            error: None,
+        };
+        let mut state = ScanState {
+            resp2_only: true,
+            ..ScanState::default()
+        };
+        let result = scan_reply(sink.buf, &mut sink, &ParseLimits::default(), &mut state);
+        self.pending = sink.tokens;
+        match (result, sink.error) {
+            (_, Some(err)) => self.error = Some(err),
+            (Ok(len), None) => self.resume += len,
+            (Err(err), None) => {
+                self.resume += state.pos;
+                self.error = Some(err);
+            }
+        }
+    }
+}
+
+impl Clone for Tokens<'_> {
+    fn clone(&self) -> Self {
+        Tokens {
+            pending: self.pending.clone(),
+            error: self
+                .error
+                .as_ref()
+                .map(|err| err.clone_mostly("Scanning failed")),
+            ..*self
+        }
+    }
+}
+
+impl<'a> Iterator for Tokens<'a> {
+    type Item = RedisResult<Token<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.pending.is_empty() && self.error.is_none() {
+            if self.failed || self.resume == self.buf.len() {
+                return None;
+            }
+            self.scan();
+        }
+        if let Some(token) = self.pending.pop_front() {
+            return Some(Ok(token));
+        }
+        self.failed = true;
+        self.error.take().map(Err)
+    }
+}
+
+// Records the tokens of a reply scanned out of `buf`, up to the first
+// error.
+struct TokenSink<'a> {
+    buf: &'a [u8],
+    tokens: VecDeque<Token<'a>>,
+    error: Option<RedisError>,
+}
+
+impl<'a> TokenSink<'a> {
+    fn push(&mut self, token: RedisResult<Token<'a>>) {
+        if self.error.is_some() {
+            return;
+        }
+        match token {
+            Ok(token) => self.tokens.push_back(token),
+            Err(err) => self.error = Some(err),
+        }
+    }
+}
+
+impl ParserSink for TokenSink<'_> {
+    fn on_nil(&mut self) {
+        self.push(Ok(Token::Null));
+    }
+
+    fn on_int(&mut self, value: i64) {
+        self.push(Ok(Token::Integer(value)));
+    }
+
+    fn on_data(&mut self, data: &[u8]) {
+        let data = reborrow(self.buf, data);
+        self.push(Ok(Token::BulkHeader(data.len())));
+        self.push(Ok(Token::BulkData(data)));
+    }
+
+    fn on_status(&mut self, status: &[u8]) {
+        let status = Tokens::text(reborrow(self.buf, status));
+        self.push(status.map(Token::SimpleString));
+    }
+
+    fn on_error(&mut self, error: &[u8]) {
+        let error = Tokens::text(reborrow(self.buf, error));
+        self.push(error.map(Token::Error));
+    }
+
+    fn on_array_begin(&mut self, len: usize) {
+        self.push(Ok(Token::ArrayHeader(len)));
+    }
+}
+
+// The type bytes which only appear in RESP3 data.
+const RESP3_TYPE_BYTES: &[u8] = b"_#,(!=%~>|;.";
+
+// The error of the RESP2 only parsers when they meet RESP3 data.
+fn resp3_error(type_byte: u8) -> RedisError {
+    RedisError::from((
+        ErrorKind::ResponseError,
+        "RESP3 data is not supported",
+        format!(
+            "Unexpected RESP3 type {:?}, only RESP2 is supported",
+            type_byte as char
+        ),
+    ))
+}
+
+// The events of `scan_reply` report slices of the scanned buffer, which
+// are borrowed again for the lifetime of the buffer.
+fn reborrow<'a>(buf: &'a [u8], part: &[u8]) -> &'a [u8] {
+    let start = part.as_ptr() as usize - buf.as_ptr() as usize;
+    &buf[start..start + part.len()]
+}
+
+// Returns the line starting at `start` without its CRLF and the position
+// after it.
+fn find_line(buf: &[u8], start: usize) -> RedisResult<(&[u8], usize)> {
+    let rest = &buf[start..];
+    for len in memchr::memchr_iter(b'\r', rest) {
+        if rest.get(len + 1) == Some(&b'\n') {
+            return Ok((&rest[..len], start + len + 2));
+        }
+    }
+    Err(io::Error::from(io::ErrorKind::UnexpectedEof).into())
+}
+
+/// Receives the events of [`parse_with_sink`].
+///
+/// Every method has a default implementation, so a sink only implements
+/// the events it is interested in.  An aggregate is reported as a
+/// `*_begin` event with its number of elements, followed by the events of
+/// the elements and the matching `*_end` event.
+///
+/// The RESP3 types default to the events of their RESP2 counterparts: maps,
+/// sets and pushes are reported as arrays (a map of `n` entries as an array
+/// of `2 * n` elements), booleans as integers, doubles, big numbers and
+/// verbatim strings as data.  Streamed aggregates are reported like the
+/// other aggregates, with the number of elements that were streamed.
+/// Attributes are skipped.
+pub trait ParserSink {
+    /// A null bulk string, null array or RESP3 null.
+    fn on_nil(&mut self) {}
+
+    /// An integer.
+    fn on_int(&mut self, _value: i64) {}
+
+    /// The payload of a bulk string.
+    fn on_data(&mut self, _data: &[u8]) {}
+
+    /// A status line, such as `OK`.
+    fn on_status(&mut self, _status: &[u8]) {}
+
+    /// An error line or RESP3 blob error, including the error code.
+    fn on_error(&mut self, _error: &[u8]) {}
+
+    /// The start of an array of `len` elements.
+    fn on_array_begin(&mut self, _len: usize) {}
+
+    /// The end of the innermost array.
+    fn on_array_end(&mut self) {}
+
+    /// The start of a RESP3 map of `len` entries.
+    fn on_map_begin(&mut self, len: usize) {
+        self.on_array_begin(len.saturating_mul(2))
+    }
+
+    /// The end of the innermost map.
+    fn on_map_end(&mut self) {
+        self.on_array_end()
+    }
+
+    /// The start of a RESP3 set of `len` elements.
+    fn on_set_begin(&mut self, len: usize) {
+        self.on_array_begin(len)
+    }
+
+    /// The end of the innermost set.
+    fn on_set_end(&mut self) {
+        self.on_array_end()
+    }
+
+    /// The start of a RESP3 push of `len` elements, the first of which is
+    /// the kind of the push.
+    fn on_push_begin(&mut self, len: usize) {
+        self.on_array_begin(len)
+    }
+
+    /// The end of the innermost push.
+    fn on_push_end(&mut self) {
+        self.on_array_end()
+    }
+
+    /// A RESP3 boolean.
+    fn on_boolean(&mut self, value: bool) {
+        self.on_int(value as i64)
+    }
+
+    /// A RESP3 double.
+    fn on_double(&mut self, value: f64) {
+        self.on_data(value.to_string().as_bytes())
+    }
+
+    /// A RESP3 big number, as its decimal digits.
+    fn on_big_number(&mut self, digits: &[u8]) {
+        self.on_data(digits)
+    }
+
+    /// A RESP3 verbatim string with its three letter format.
+    fn on_verbatim(&mut self, _format: &[u8], text: &[u8]) {
+        self.on_data(text)
+    }
+
+    /// A RESP3 streamed string, as the chunks its payload was sent in.
+    fn on_data_chunks(&mut self, chunks: &[&[u8]]) {
+        self.on_data(&chunks.concat())
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum SinkAggregate {
+    Array,
+    Map,
+    Set,
+    Push,
+}
+
+/// Parses a single reply from the start of `bytes`, reporting it to the
+/// sink instead of building a `Value`, and returns the number of bytes it
+/// took.
+///
+/// If the buffer ends before the reply does an `UnexpectedEof` io error is
+/// returned; the sink has then already received the events of the complete
+/// part, so the reply should be parsed again with a fresh sink once more
+/// data is available.
+///
+/// ```rust
+/// use redis::{parse_with_sink, ParserSink};
+///
+/// #[derive(Default)]
+/// struct Sizes(usize);
+///
+/// impl ParserSink for Sizes {
+///     fn on_data(&mut self, data: &[u8]) {
+///         self.0 += data.len();
+///     }
+/// }
+///
+/// let mut sizes = Sizes::default();
+/// let consumed = parse_with_sink(b"*2\r\n$3\r\nfoo\r\n$2\r\nhi\r\n", &mut sizes).unwrap();
+/// assert_eq!((consumed, sizes.0), (21, 5));
+/// ```
+pub fn parse_with_sink<S: ParserSink + ?Sized>(bytes: &[u8], sink: &mut S) -> RedisResult<usize> {
+    parse_with_sink_limited(bytes, sink, &ParseLimits::default())
+}
+
+/// Like `parse_with_sink` but fails with `ErrorKind::ParseLimitExceeded`
+/// once a header of the reply exceeds the limits, before the events of the
+/// offending value are reported.
+pub fn parse_with_sink_limited<S: ParserSink + ?Sized>(
+    bytes: &[u8],
+    sink: &mut S,
+    limits: &ParseLimits,
+) -> RedisResult<usize> {
+    scan_reply(bytes, sink, limits, &mut ScanState::default())
+}
+
+// Parses a reply arriving in parts into the same sink, resuming after its
+// last complete element whenever more of it was read.  The events of the
+// elements parsed before are not reported again.
+#[cfg(feature = "replication")]
+#[derive(Default, Debug)]
+pub(crate) struct ResumableParse(ScanState);
+
+#[cfg(feature = "replication")]
+impl ResumableParse {
+    pub(crate) fn parse<S: ParserSink + ?Sized>(
+        &mut self,
+        bytes: &[u8],
+        sink: &mut S,
+    ) -> RedisResult<usize> {
+        let rv = scan_reply(bytes, sink, &ParseLimits::default(), &mut self.0);
+        if matches!(rv, Err(ref err) if !err.is_io_error()) {
+            self.0 = ScanState::default();
+        }
+        rv
+    }
+}
+
+// How far an incomplete reply was scanned.  Scanning it again resumes after
+// its last complete element instead of starting over.
+#[derive(Default, Debug)]
+struct ScanState {
+    // The open aggregates with the number of elements they still wait for
+    // and whether they are streamed.
+    open: Vec<(SinkAggregate, usize, bool)>,
+    pos: usize,
+    // The number of bulk string bytes before `pos`.  A reply starting at
+    // `0` has none.
+    payload: usize,
+    // Whether RESP3 data fails the scan, for the parsers of RESP2 values.
+    resp2_only: bool,
+}
+
+fn scan_reply<S: ParserSink + ?Sized>(
+    bytes: &[u8],
+    sink: &mut S,
+    limits: &ParseLimits,
+    state: &mut ScanState,
+) -> RedisResult<usize> {
+    if state.pos == 0 {
+        state.payload = 0;
+    }
+    let open = &mut state.open;
+    let mut pos = state.pos;
+    loop {
+        // Every element before `pos` is complete.
+        state.pos = pos;
+        if pos >= bytes.len() {
+            fail!(io::Error::from(io::ErrorKind::UnexpectedEof));
+        }
+        let (line, next) = find_line(bytes, pos + 1)?;
+        let type_byte = bytes[pos];
+        if state.resp2_only && (RESP3_TYPE_BYTES.contains(&type_byte) || line == b"?") {
+            fail!(resp3_error(type_byte));
+        }
+        pos = next;
+        match type_byte {
+            b'+' => sink.on_status(line),
+            b'-' => sink.on_error(line),
+            b':' => match Tokens::text(line)?.trim().parse::<i64>() {
+                Ok(value) => sink.on_int(value),
+                Err(_) => fail!((
+                    ErrorKind::ResponseError,
+                    "parse error",
+                    "Expected integer, got garbage".to_string()
+                )),
+            },
+            b'_' if line.is_empty() => sink.on_nil(),
+            b'_' => fail!((
+                ErrorKind::ResponseError,
+                "parse error",
+                "Expected null".to_string()
+            )),
+            b'#' => match line {
+                b"t" => sink.on_boolean(true),
+                b"f" => sink.on_boolean(false),
+                _ => fail!((
+                    ErrorKind::ResponseError,
+                    "parse error",
+                    "Expected boolean, got garbage".to_string()
+                )),
+            },
+            b',' => match Tokens::text(line)?.parse::<f64>() {
+                Ok(value) => sink.on_double(value),
+                Err(_) => fail!((
+                    ErrorKind::ResponseError,
+                    "parse error",
+                    "Expected double, got garbage".to_string()
+                )),
+            },
+            b'(' => sink.on_big_number(line),
+            b'$' if line == b"?" => {
+                let (chunks, next) = streamed_chunks(bytes, pos, limits, state.payload)?;
+                pos = next;
+                state.payload = chunks
+                    .iter()
+                    .map(|chunk| chunk.len())
+                    .fold(state.payload, usize::saturating_add);
+                sink.on_data_chunks(&chunks);
+            }
+            b'$' | b'=' | b'!' => match Tokens::length(line)? {
+                None => sink.on_nil(),
+                Some(len) => {
+                    limits.check_bulk(len)?;
+                    if type_byte == b'$' {
+                        limits.check_payload(state.payload.saturating_add(len))?;
+                    }
+                    let (data, next) = bulk_data(bytes, pos, len)?;
+                    pos = next;
+                    match type_byte {
+                        b'$' => {
+                            state.payload += len;
+                            sink.on_data(data)
+                        }
+                        b'!' => sink.on_error(data),
+                        _ if data.len() >= 4 && data[3] == b':' => {
+                            sink.on_verbatim(&data[..3], &data[4..])
+                        }
+                        _ => fail!((
+                            ErrorKind::ResponseError,
+                            "parse error",
+                            "Expected verbatim string format".to_string()
+                        )),
+                    }
+                }
+            },
+            b'*' | b'%' | b'~' | b'>' => {
+                match aggregate_length(bytes, pos, line, type_byte, limits, state.payload)? {
+                    None => sink.on_nil(),
+                    Some(len) => {
+                        let streamed = line == b"?";
+                        match type_byte {
+                            b'%' => limits.check_map(len, open.len())?,
+                            _ => limits.check_aggregate(len, open.len())?,
+                        }
+                        let (kind, elements) = match type_byte {
+                            b'*' => (SinkAggregate::Array, len),
+                            b'%' => (SinkAggregate::Map, len.saturating_mul(2)),
+                            b'~' => (SinkAggregate::Set, len),
+                            _ => (SinkAggregate::Push, len),
+                        };
+                        match kind {
+                            SinkAggregate::Array => sink.on_array_begin(len),
+                            SinkAggregate::Map => sink.on_map_begin(len),
+                            SinkAggregate::Set => sink.on_set_begin(len),
+                            SinkAggregate::Push => sink.on_push_begin(len),
+                        }
+                        if elements > 0 {
+                            open.push((kind, elements, streamed));
+                            continue;
+                        }
+                        if streamed {
+                            pos = streamed_end(bytes, pos)?;
+                        }
+                        end_aggregate(sink, kind);
+                    }
+                }
+            }
+            // The value the attributes were sent with follows them.
+            b'|' => {
+                let entries = Tokens::length(line)?.unwrap_or(0);
+                limits.check_map(entries, open.len())?;
+                let mut payload = state.payload;
+                for _ in 0..entries.saturating_mul(2) {
+                    let mut skipped = ScanState {
+                        open: Vec::new(),
+                        pos,
+                        payload,
+                        resp2_only: false,
+                    };
+                    pos = scan_reply(bytes, &mut NoopSink, limits, &mut skipped)?;
+                    payload = skipped.payload;
+                }
+                state.payload = payload;
+                continue;
+            }
+            b => fail!((
+                ErrorKind::ResponseError,
+                "parse error",
+                format!("Unexpected type byte {:?}", b as char)
+            )),
+        }
+
+        // An element is complete, which may complete the aggregates around it.
+        loop {
+            match open.last_mut() {
+                None => {
+                    state.pos = 0;
+                    return Ok(pos);
+                }
+                Some((kind, remaining, streamed)) => {
+                    *remaining -= 1;
+                    if *remaining > 0 {
+                        break;
+                    }
+                    let (kind, streamed) = (*kind, *streamed);
+                    open.pop();
+                    if streamed {
+                        pos = streamed_end(bytes, pos)?;
+                    }
+                    end_aggregate(sink, kind);
+                }
+            }
+        }
+    }
+}
+
+// Returns the `len` bytes of bulk data starting at `pos` and the position
+// after their CRLF.
+fn bulk_data(bytes: &[u8], pos: usize, len: usize) -> RedisResult<(&[u8], usize)> {
+    let end = pos.saturating_add(len);
+    if bytes.len() < end.saturating_add(2) {
+        fail!(io::Error::from(io::ErrorKind::UnexpectedEof));
+    }
+    if &bytes[end..end + 2] != b"\r\n" {
+        fail!((
+            ErrorKind::ResponseError,
+            "parse error",
+            "Expected CRLF after bulk data".to_string()
+        ));
+    }
+    Ok((&bytes[pos..end], end + 2))
+}
+
+// Reads the `;<length>` chunks of the streamed string starting at `pos`, up
+// to the empty chunk which ends it.  `payload` bulk string bytes of the reply
+// came before it.
+fn streamed_chunks<'a>(
+    bytes: &'a [u8],
+    mut pos: usize,
+    limits: &ParseLimits,
+    payload: usize,
+) -> RedisResult<(Vec<&'a [u8]>, usize)> {
+    let mut chunks = Vec::new();
+    let mut total = 0usize;
+    loop {
+        match bytes.get(pos) {
+            None => fail!(io::Error::from(io::ErrorKind::UnexpectedEof)),
+            Some(b';') => {}
+            Some(_) => fail!((
+                ErrorKind::ResponseError,
+                "parse error",
+                "Expected a string chunk".to_string()
+            )),
+        }
+        let (line, next) = find_line(bytes, pos + 1)?;
+        let len = match Tokens::length(line)? {
+            Some(0) => return Ok((chunks, next)),
+            Some(len) => len,
+            None => fail!((
+                ErrorKind::ResponseError,
+                "parse error",
+                "Expected chunk length".to_string()
+            )),
+        };
+        total = total.saturating_add(len);
+        limits.check_bulk(total)?;
+        limits.check_payload(payload.saturating_add(total))?;
+        let (chunk, next) = bulk_data(bytes, next, len)?;
+        chunks.push(chunk);
+        pos = next;
+    }
+}
+
+// Returns the number of elements, or of entries for maps, of the aggregate
+// whose header `line` ends at `pos`.  The elements of streamed aggregates
+// are counted by scanning ahead to their end, after `payload` bulk string
+// bytes of the reply.
+fn aggregate_length(
+    bytes: &[u8],
+    mut pos: usize,
+    line: &[u8],
+    type_byte: u8,
+    limits: &ParseLimits,
+    mut payload: usize,
+) -> RedisResult<Option<usize>> {
+    if line != b"?" {
+        return Tokens::length(line);
+    }
+    let values_per_entry = if type_byte == b'%' { 2 } else { 1 };
+    let mut elements = 0usize;
+    while bytes.get(pos) != Some(&b'.') {
+        let mut state = ScanState {
+            open: Vec::new(),
+            pos,
+            payload,
+            resp2_only: false,
+        };
+        pos = scan_reply(bytes, &mut NoopSink, limits, &mut state)?;
+        payload = state.payload;
+        elements += 1;
+        limits.check_entries(elements.div_ceil(values_per_entry), values_per_entry, 0)?;
+    }
+    streamed_end(bytes, pos)?;
+    match type_byte {
+        b'%' if elements % 2 == 1 => fail!((
+            ErrorKind::ResponseError,
+            "parse error",
+            "Expected a value for every key".to_string()
+        )),
+        b'%' => Ok(Some(elements / 2)),
+        _ => Ok(Some(elements)),
+    }
+}
+
+// Returns the position after the `.` line ending a streamed aggregate.
+fn streamed_end(bytes: &[u8], pos: usize) -> RedisResult<usize> {
+    match bytes.get(pos) {
+        None => fail!(io::Error::from(io::ErrorKind::UnexpectedEof)),
+        Some(b'.') => match find_line(bytes, pos + 1)? {
+            (b"", next) => Ok(next),
+            _ => fail!((
+                ErrorKind::ResponseError,
+                "parse error",
+                "Expected end of streamed aggregate".to_string()
+            )),
+        },
+        Some(_) => fail!((
+            ErrorKind::ResponseError,
+            "parse error",
+            "Expected end of streamed aggregate".to_string()
+        )),
+    }
+}
+
+// Ignores every event, to find where a reply ends.
+struct NoopSink;
+
+impl ParserSink for NoopSink {
+    fn on_data_chunks(&mut self, _chunks: &[&[u8]]) {}
+}
+
+fn end_aggregate<S: ParserSink + ?Sized>(sink: &mut S, kind: SinkAggregate) {
+    match kind {
+        SinkAggregate::Array => sink.on_array_end(),
+        SinkAggregate::Map => sink.on_map_end(),
+        SinkAggregate::Set => sink.on_set_end(),
+        SinkAggregate::Push => sink.on_push_end(),
+    }
+}
+
+// The deepest nesting of aggregates the parsers building trees of borrowed
+// or shared values accept.  The trees are built without recursion, but
+// converting and dropping them recurses into every level.
+const MAX_TREE_DEPTH: usize = 512;
+
+// A scalar of a reply, borrowing from the parsed buffer.
+enum Leaf<'a> {
+    Nil,
+    Int(i64),
+    Data(&'a [u8]),
+    Status(&'a str),
+    // The payload of a streamed string sent in more than one chunk.
+    Chunks(Vec<u8>),
+    Boolean(bool),
+    Double(f64),
+    BigNumber(&'a str),
+    Verbatim { format: &'a str, text: &'a str },
+}
+
+// Builds the values of a `TreeSink`.
+trait TreeBuilder<'a> {
+    type Value;
+
+    fn leaf(&mut self, leaf: Leaf<'a>) -> RedisResult<Self::Value>;
+
+    fn aggregate(
+        &mut self,
+        kind: SinkAggregate,
+        items: Vec<Self::Value>,
+    ) -> RedisResult<Self::Value>;
+}
+
+// Builds a tree of values out of the events of a reply read from `buf`.
+// The aggregates being filled are kept on a stack, so that deeply nested
+// replies do not recurse.
+struct TreeSink<'a, B: TreeBuilder<'a>> {
+    buf: &'a [u8],
+    builder: B,
+    open: Vec<(SinkAggregate, Vec<B::Value>)>,
+    value: Option<B::Value>,
+    server_error: Option<RedisError>,
+    parse_error: Option<RedisError>,
+}
+
+impl<'a, B: TreeBuilder<'a>> TreeSink<'a, B> {
+    fn new(buf: &'a [u8], builder: B) -> TreeSink<'a, B> {
+        TreeSink {
+            buf,
+            builder,
+            open: Vec::new(),
+            value: None,
+            server_error: None,
+            parse_error: None,
+        }
+    }
+
+    // Scans the reply at the start of the buffer into the tree, rejecting
+    // replies nested deeper than `MAX_TREE_DEPTH`.
+    fn scan(&mut self, resp2_only: bool) -> RedisResult<usize> {
+        let limits = ParseLimits::default().max_depth(MAX_TREE_DEPTH);
+        let mut state = ScanState {
+            resp2_only,
+            ..ScanState::default()
+        };
+        scan_reply(self.buf, self, &limits, &mut state)
+    }
+
+    // Parses the RESP2 reply at the start of the buffer, rejecting RESP3
+    // data.
+    fn parse_resp2(mut self) -> RedisResult<B::Value> {
+        self.scan(true)?;
+        self.finish()
+    }
+
+    fn finish(self) -> RedisResult<B::Value> {
+        self.into_reply()?
+    }
+
+    // Parse errors fail the parse while server errors, like with
+    // `ValueCodec`, are the result of the reply.
+    fn into_reply(self) -> RedisResult<RedisResult<B::Value>> {
+        if let Some(err) = self.parse_error {
+            return Err(err);
+        }
+        if let Some(err) = self.server_error {
+            return Ok(Err(err));
+        }
+        match self.value {
+            Some(value) => Ok(Ok(value)),
+            None => fail!(io::Error::from(io::ErrorKind::UnexpectedEof)),
+        }
+    }
+
+    fn push(&mut self, value: RedisResult<B::Value>) {
+        let value = match value {
+            Ok(value) => value,
+            Err(err) => {
+                self.parse_error.get_or_insert(err);
+                return;
+            }
+        };
+        match self.open.last_mut() {
+            Some((_, items)) => items.push(value),
+            None => self.value = Some(value),
+        }
+    }
+
+    fn push_leaf(&mut self, leaf: Leaf<'a>) {
+        let value = self.builder.leaf(leaf);
+        self.push(value);
+    }
+
+    fn text(&mut self, line: &[u8]) -> Option<&'a str> {
+        match str::from_utf8(reborrow(self.buf, line)) {
+            Ok(line) => Some(line),
+            Err(err) => {
+                self.parse_error.get_or_insert_with(|| {
+                    RedisError::from((ErrorKind::ResponseError, "parse error", err.to_string()))
+                });
+                None
+            }
+        }
+    }
+
+    // Closes the aggregates left open by a truncated reply with the
+    // elements they received so far.
+    fn finish_truncated(mut self) -> RedisResult<B::Value> {
+        while !self.open.is_empty() {
+            self.end();
+        }
+        self.finish()
+    }
+
+    fn begin(&mut self, kind: SinkAggregate, len: usize) {
+        self.open.push((kind, Vec::with_capacity(len.min(1024))));
+    }
+
+    fn end(&mut self) {
+        if let Some((kind, items)) = self.open.pop() {
+            let value = self.builder.aggregate(kind, items);
+            self.push(value);
+        }
+    }
+}
+
+impl<'a, B: TreeBuilder<'a>> ParserSink for TreeSink<'a, B> {
+    fn on_nil(&mut self) {
+        self.push_leaf(Leaf::Nil);
+    }
+
+    fn on_int(&mut self, value: i64) {
+        self.push_leaf(Leaf::Int(value));
+    }
+
+    fn on_data(&mut self, data: &[u8]) {
+        let data = reborrow(self.buf, data);
+        self.push_leaf(Leaf::Data(data));
+    }
+
+    fn on_status(&mut self, status: &[u8]) {
+        if let Some(status) = self.text(status) {
+            self.push_leaf(Leaf::Status(status));
+        }
+    }
+
+    fn on_error(&mut self, error: &[u8]) {
+        if let Some(error) = self.text(error) {
+            self.server_error.get_or_insert_with(|| server_error(error));
+        }
+        self.push_leaf(Leaf::Nil);
+    }
+
+    fn on_array_begin(&mut self, len: usize) {
+        self.begin(SinkAggregate::Array, len);
+    }
+
+    fn on_array_end(&mut self) {
+        self.end();
+    }
+
+    fn on_map_begin(&mut self, len: usize) {
+        self.begin(SinkAggregate::Map, len.saturating_mul(2));
+    }
+
+    fn on_map_end(&mut self) {
+        self.end();
+    }
+
+    fn on_set_begin(&mut self, len: usize) {
+        self.begin(SinkAggregate::Set, len);
+    }
+
+    fn on_set_end(&mut self) {
+        self.end();
+    }
+
+    fn on_push_begin(&mut self, len: usize) {
+        self.begin(SinkAggregate::Push, len);
+    }
+
+    fn on_push_end(&mut self) {
+        self.end();
+    }
+
+    fn on_boolean(&mut self, value: bool) {
+        self.push_leaf(Leaf::Boolean(value));
+    }
+
+    fn on_double(&mut self, value: f64) {
+        self.push_leaf(Leaf::Double(value));
+    }
+
+    fn on_big_number(&mut self, digits: &[u8]) {
+        let line = match self.text(digits) {
+            Some(line) => line,
+            None => return,
+        };
+        let digits = line.strip_prefix('-').unwrap_or(line);
+        if !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit()) {
+            self.push_leaf(Leaf::BigNumber(line));
+        } else {
+            self.push(Err(RedisError::from((
+                ErrorKind::ResponseError,
+                "parse error",
+                "Expected big number, got garbage".to_string(),
+            ))));
+        }
+    }
+
+    fn on_verbatim(&mut self, format: &[u8], text: &[u8]) {
+        if let (Some(format), Some(text)) = (self.text(format), self.text(text)) {
+            self.push_leaf(Leaf::Verbatim { format, text });
+        }
+    }
+
+    fn on_data_chunks(&mut self, chunks: &[&[u8]]) {
+        match *chunks {
+            [chunk] => self.on_data(chunk),
+            _ => self.push_leaf(Leaf::Chunks(chunks.concat())),
+        }
+    }
+}
+
+// Builds the values of `parse_redis_value_ref`.
+struct RefBuilder;
+
+impl<'a> TreeBuilder<'a> for RefBuilder {
+    type Value = ValueRef<'a>;
+
+    fn leaf(&mut self, leaf: Leaf<'a>) -> RedisResult<ValueRef<'a>> {
+        Ok(match leaf {
+            Leaf::Nil => ValueRef::Nil,
+            Leaf::Int(value) => ValueRef::Int(value),
+            Leaf::Data(data) => ValueRef::Data(data),
+            Leaf::Status("OK") => ValueRef::Okay,
+            Leaf::Status(status) => ValueRef::Status(status),
+            _ => unreachable!("RESP3 data is rejected while scanning"),
+        })
+    }
+
+    fn aggregate(
+        &mut self,
+        _kind: SinkAggregate,
+        items: Vec<ValueRef<'a>>,
+    ) -> RedisResult<ValueRef<'a>> {
+        Ok(ValueRef::Bulk(items))
+    }
+}
+
+// Builds the values of `parse_redis_value_bytes` out of the buffer they
+// share.
+#[cfg(feature = "bytes")]
+struct BytesBuilder<'a>(&'a bytes::Bytes);
+
+#[cfg(feature = "bytes")]
+impl<'a> TreeBuilder<'a> for BytesBuilder<'_> {
+    type Value = BytesValue;
+
+    fn leaf(&mut self, leaf: Leaf<'a>) -> RedisResult<BytesValue> {
+        Ok(match leaf {
+            Leaf::Nil => BytesValue::Nil,
+            Leaf::Int(value) => BytesValue::Int(value),
+            Leaf::Data(data) => BytesValue::Data(self.0.slice_ref(data)),
+            Leaf::Status("OK") => BytesValue::Okay,
+            Leaf::Status(status) => BytesValue::Status(status.to_string()),
+            _ => unreachable!("RESP3 data is rejected while scanning"),
+        })
+    }
+
+    fn aggregate(
+        &mut self,
+        _kind: SinkAggregate,
+        items: Vec<BytesValue>,
+    ) -> RedisResult<BytesValue> {
+        Ok(BytesValue::Bulk(items))
+    }
+}
+
+// Builds the values of `parse_redis_value_lazy`, sharing the data longer
+// than the threshold with the buffer.
+#[cfg(feature = "bytes")]
+struct LazyBuilder<'a>(&'a bytes::Bytes, usize);
+
+#[cfg(feature = "bytes")]
+impl<'a> TreeBuilder<'a> for LazyBuilder<'_> {
+    type Value = LazyValue;
+
+    fn leaf(&mut self, leaf: Leaf<'a>) -> RedisResult<LazyValue> {
+        Ok(match leaf {
+            Leaf::Nil => LazyValue::Nil,
+            Leaf::Int(value) => LazyValue::Int(value),
+            Leaf::Data(data) if data.len() > self.1 => {
+                LazyValue::Data(LazyData::Shared(self.0.slice_ref(data)))
+            }
+            Leaf::Data(data) => LazyValue::Data(LazyData::Owned(data.to_vec())),
+            Leaf::Status("OK") => LazyValue::Okay,
+            Leaf::Status(status) => LazyValue::Status(status.to_string()),
+            _ => unreachable!("RESP3 data is rejected while scanning"),
+        })
+    }
+
+    fn aggregate(&mut self, _kind: SinkAggregate, items: Vec<LazyValue>) -> RedisResult<LazyValue> {
+        Ok(LazyValue::Bulk(items))
+    }
+}
+
+// Builds the values of `parse_redis_value_partial`.
+struct ValueBuilder;
+
+impl<'a> TreeBuilder<'a> for ValueBuilder {
+    type Value = Value;
+
+    fn leaf(&mut self, leaf: Leaf<'a>) -> RedisResult<Value> {
+        Ok(match leaf {
+            Leaf::Nil => Value::Nil,
+            Leaf::Int(value) => Value::Int(value),
+            Leaf::Data(data) => Value::Data(data.to_vec()),
+            Leaf::Chunks(data) => Value::Data(data),
+            Leaf::Status("OK") => Value::Okay,
+            Leaf::Status(status) => Value::Status(status.to_string()),
+            Leaf::Boolean(value) => Value::Boolean(value),
+            Leaf::Double(value) => Value::Double(value),
+            Leaf::BigNumber(digits) => Value::BigNumber(digits.to_string()),
+            Leaf::Verbatim { format, text } => Value::VerbatimString {
+                format: format.to_string(),
+                text: text.to_string(),
+            },
+        })
+    }
+
+    fn aggregate(&mut self, kind: SinkAggregate, items: Vec<Value>) -> RedisResult<Value> {
+        match kind {
+            SinkAggregate::Array => Ok(Value::Bulk(items)),
+            SinkAggregate::Map => map_from_pairs(items),
+            SinkAggregate::Set => Ok(Value::Set(items)),
+            SinkAggregate::Push => push_from_items(items),
+        }
+    }
+}
+
+// Counts the bytes read from a deque and reports running out of bytes as
+// `WouldBlock` instead of the end of the stream, which would end parsing
+// for good.
+struct DequeReader<R> {
+    inner: R,
+    count: usize,
+}
+
+impl<R: Read> Read for DequeReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.inner.read(buf)? {
+            0 if !buf.is_empty() => Err(io::ErrorKind::WouldBlock.into()),
+            read => {
+                self.count += read;
+                Ok(read)
+            }
+        }
+    }
+}
+
+// Returns the number of bulk string bytes in the value.
+fn data_len(value: &Value) -> usize {
+    match *value {
+        Value::Data(ref bytes) => bytes.len(),
+        Value::Bulk(ref items)
+        | Value::Set(ref items)
+        | Value::Push {
+            data: ref items, ..
+        } => items.iter().map(data_len).sum(),
+        Value::Map(ref pairs) => pairs
+            .iter()
+            .map(|(key, val)| data_len(key) + data_len(val))
+            .sum(),
+        Value::Attribute {
+            ref data,
+            ref attributes,
+        } => {
+            data_len(data)
+                + attributes
+                    .iter()
+                    .map(|(key, val)| data_len(key) + data_len(val))
+                    .sum::<usize>()
+        }
+        _ => 0,
+    }
+}
+
+/// Parses an inline command, a line of space separated arguments as typed
+/// by telnet style clients, from the start of `bytes`.  Returns the
+/// arguments and the number of bytes the line took.
+///
+/// The line ends with LF, optionally preceded by CR, and if the buffer
+/// ends before it an `UnexpectedEof` io error is returned.  Arguments are
+/// split like the server does it: they can be quoted with double quotes,
+/// which support the `\n`, `\r`, `\t`, `\b`, `\a` and `\xHH` escapes, or
+/// with single quotes, which only support `\'`.
+///
+/// ```rust
+/// let (args, len) = redis::parse_inline_command(b"SET key \"hello world\"\r\n").unwrap();
+/// assert_eq!(args, vec![&b"SET"[..], &b"key"[..], &b"hello world"[..]]);
+/// assert_eq!(len, 23);
+/// ```
+pub fn parse_inline_command(bytes: &[u8]) -> RedisResult<(Vec<Vec<u8>>, usize)> {
+    let end = match memchr::memchr(b'\n', bytes) {
+        Some(end) => end,
+        None => fail!(io::Error::from(io::ErrorKind::UnexpectedEof)),
+    };
+    let line = &bytes[..end];
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    Ok((split_inline_args(line)?, end + 1))
+}
+
+pub(crate) fn split_inline_args(line: &[u8]) -> RedisResult<Vec<Vec<u8>>> {
+    fn unbalanced() -> RedisError {
+        RedisError::from((
+            ErrorKind::ResponseError,
+            "parse error",
+            "Unbalanced quotes in inline command".to_string(),
+        ))
+    }
+
+    let mut args = Vec::new();
+    let mut rest = line;
+    loop {
+        while let [b, tail @ ..] = rest {
+            if !b.is_ascii_whitespace() {
+                break;
+            }
+            rest = tail;
+        }
+        if rest.is_empty() {
+            return Ok(args);
+        }
+
+        let mut arg = Vec::new();
+        let (mut in_double, mut in_single) = (false, false);
+        loop {
+            let (b, tail) = match rest.split_first() {
+                Some((&b, tail)) => (b, tail),
+                None if in_double || in_single => return Err(unbalanced()),
+                None => break,
+            };
+            rest = tail;
+            if in_double {
+                match (b, rest) {
+                    (b'\\', [b'x', hi, lo, tail @ ..])
+                        if hi.is_ascii_hexdigit() && lo.is_ascii_hexdigit() =>
+                    {
+                        let hex = [*hi, *lo];
+                        let hex = str::from_utf8(&hex).unwrap();
+                        arg.push(u8::from_str_radix(hex, 16).unwrap());
+                        rest = tail;
+                    }
+                    (b'\\', [escaped, tail @ ..]) => {
+                        arg.push(match *escaped {
+                            b'n' => b'\n',
+                            b'r' => b'\r',
+                            b't' => b'\t',
+                            b'b' => 0x08,
+                            b'a' => 0x07,
+                            other => other,
+                        });
+                        rest = tail;
+                    }
+                    // the closing quote must end the argument
+                    (b'"', [next, ..]) if !next.is_ascii_whitespace() => return Err(unbalanced()),
+                    (b'"', _) => break,
+                    _ => arg.push(b),
+                }
+            } else if in_single {
+                match (b, rest) {
+                    (b'\\', [b'\'', tail @ ..]) => {
+                        arg.push(b'\'');
+                        rest = tail;
+                    }
+                    (b'\'', [next, ..]) if !next.is_ascii_whitespace() => return Err(unbalanced()),
+                    (b'\'', _) => break,
+                    _ => arg.push(b),
+                }
+            } else {
+                match b {
+                    b if b.is_ascii_whitespace() => break,
+                    b'"' => in_double = true,
+                    b'\'' => in_single = true,
+                    _ => arg.push(b),
+                }
+            }
+        }
+        args.push(arg);
+    }
+}
+
+/// Parses bytes into a redis value.
+///
+/// This is the most straightforward way to parse something into a low
+/// level redis value instead of having to use a whole parser.
+pub fn parse_redis_value(bytes: &[u8]) -> RedisResult<Value> {
+    let mut parser = Parser::new();
+    parser.parse_value(bytes)
+}
+
+// Statuses, errors and big numbers are sent as single lines, so they cannot
+// contain line breaks.
+fn check_line(line: &str) -> io::Result<&str> {
+    if line.contains(['\r', '\n']) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "line replies cannot contain CR or LF",
+        ));
+    }
+    Ok(line)
+}
+
+/// Serializes a redis value in the wire format to the writer.
+///
+/// This is the counterpart of `parse_redis_value` and is mostly useful to
+/// write canned replies from a fake server or a proxy.  RESP3 values are
+/// written with their RESP3 types, so they should only be sent to clients
+/// which negotiated RESP3.
+///
+/// Values which have no valid encoding fail with an `InvalidInput` error:
+/// statuses and big numbers containing line breaks, and verbatim strings
+/// whose format is not three bytes long.
+pub fn write_reply<W: io::Write>(value: &Value, w: &mut W) -> io::Result<()> {
+    match *value {
+        Value::Nil => w.write_all(b"$-1\r\n"),
+        Value::Int(val) => write!(w, ":{}\r\n", val),
+        Value::Data(ref val) => {
+            write!(w, "${}\r\n", val.len())?;
+            w.write_all(val)?;
+            w.write_all(b"\r\n")
+        }
+        Value::Bulk(ref values) => {
+            write!(w, "*{}\r\n", values.len())?;
+            for val in values {
+                write_reply(val, w)?;
+            }
+            Ok(())
+        }
+        Value::Okay => w.write_all(b"+OK\r\n"),
+        Value::Status(ref s) => write!(w, "+{}\r\n", check_line(s)?),
+        Value::ServerError(ref line) => write!(w, "-{}\r\n", check_line(line)?),
+        Value::Map(ref pairs) => {
+            write!(w, "%{}\r\n", pairs.len())?;
+            for (key, val) in pairs {
+                write_reply(key, w)?;
+                write_reply(val, w)?;
+            }
+            Ok(())
+        }
+        Value::Set(ref values) => {
+            write!(w, "~{}\r\n", values.len())?;
+            for val in values {
+                write_reply(val, w)?;
+            }
+            Ok(())
+        }
+        Value::Double(val) if val.is_nan() => w.write_all(b",nan\r\n"),
+        Value::Double(val) => write!(w, ",{}\r\n", val),
+        Value::Boolean(true) => w.write_all(b"#t\r\n"),
+        Value::Boolean(false) => w.write_all(b"#f\r\n"),
+        Value::BigNumber(ref val) => write!(w, "({}\r\n", check_line(val)?),
+        Value::VerbatimString { ref format, .. } if format.len() != 3 => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "verbatim string formats must be three bytes long",
+        )),
+        Value::VerbatimString {
+            ref format,
+            ref text,
+        } => {
+            write!(w, "={}\r\n{}:", format.len() + 1 + text.len(), format)?;
+            w.write_all(text.as_bytes())?;
+            w.write_all(b"\r\n")
+        }
+        Value::Push { ref kind, ref data } => {
+            write!(w, ">{}\r\n${}\r\n{}\r\n", data.len() + 1, kind.len(), kind)?;
+            for val in data {
+                write_reply(val, w)?;
+            }
+            Ok(())
+        }
+        Value::Attribute {
+            ref data,
+            ref attributes,
+        } => {
+            write!(w, "|{}\r\n", attributes.len())?;
+            for (key, val) in attributes {
+                write_reply(key, w)?;
+                write_reply(val, w)?;
+            }
+            write_reply(data, w)
+        }
+    }
+}
+
+// Splits packed commands into the arguments of each command, including the
+// command name.
+pub(crate) fn unpack_commands(packed: &[u8]) -> RedisResult<Vec<Vec<&[u8]>>> {
+    let mut commands: Vec<Vec<&[u8]>> = Vec::new();
+    for token in Tokens::new(packed) {
+        match (token?, commands.last_mut()) {
+            (Token::ArrayHeader(len), _) => commands.push(Vec::with_capacity(len)),
+            (Token::BulkHeader(_), Some(_)) => {}
+            (Token::BulkData(arg), Some(args)) => args.push(arg),
+            _ => fail!((ErrorKind::ClientError, "Invalid packed command")),
+        }
+    }
+    Ok(commands)
+}
+
+/// Parses bytes into a redis value which borrows its binary data and
+/// statuses from `bytes` instead of copying them.
+///
+/// Only a complete value is accepted; incomplete input results in an
+/// `UnexpectedEof` error.  Like [`Tokens`] this only accepts RESP2 data;
+/// RESP3 data fails with an `ErrorKind::ResponseError`.  Arrays nested
+/// deeper than 512 levels fail with `ErrorKind::ParseLimitExceeded`.
+pub fn parse_redis_value_ref(bytes: &[u8]) -> RedisResult<ValueRef<'_>> {
+    TreeSink::new(bytes, RefBuilder).parse_resp2()
+}
+
+/// Parses bytes into a redis value, recovering what can be recovered if
+/// the input is truncated.
+///
+/// This is meant for tools inspecting cut-off captures.  If the input
+/// ends in the middle of an aggregate, the elements parsed so far are
+/// returned in their aggregate and the returned flag is `true`.  Elements
+/// which were themselves cut off are dropped, except for aggregates which
+/// are kept with their own recovered elements, and maps drop a key whose
+/// value was cut off.  Streamed aggregates are only kept once complete
+/// and attributes are skipped.  Input that is truncated before any value could be
+/// recovered still results in an `UnexpectedEof` error.  Arrays nested
+/// deeper than 512 levels fail with `ErrorKind::ParseLimitExceeded`.
+pub fn parse_redis_value_partial(bytes: &[u8]) -> RedisResult<(Value, bool)> {
+    let mut sink = TreeSink::new(bytes, ValueBuilder);
+    match sink.scan(false) {
+        Ok(_) => sink.finish().map(|value| (value, false)),
+        Err(err) if err.is_io_error() => sink.finish_truncated().map(|value| (value, true)),
+        Err(err) => Err(err),
+    }
+}
+
+/// Parses a `Bytes` buffer into a redis value without copying any
+/// binary data.
+///
+/// The `Data` of the returned value are slices of `bytes` which keep the
+/// buffer alive through its reference count, so the value can outlive the
+/// call while the payloads are never copied.  Only RESP2 data nested at
+/// most 512 levels deep is accepted, as for [`parse_redis_value_ref`].
+#[cfg(feature = "bytes")]
+#[cfg_attr(docsrs, doc(cfg(feature = "bytes")))]
+pub fn parse_redis_value_bytes(bytes: &bytes::Bytes) -> RedisResult<BytesValue> {
+    TreeSink::new(bytes, BytesBuilder(bytes)).parse_resp2()
+}
+
+/// Parses a `Bytes` buffer into a redis value which only copies small
+/// binary data.
+///
+/// Bulk strings longer than `threshold` bytes are kept as slices of
+/// `bytes` and only copied when they are converted into owned data, so
+/// large payloads which are never looked at are never copied.  Only RESP2
+/// data nested at most 512 levels deep is accepted, as for
+/// [`parse_redis_value_ref`].
+#[cfg(feature = "bytes")]
+#[cfg_attr(docsrs, doc(cfg(feature = "bytes")))]
+pub fn parse_redis_value_lazy(bytes: &bytes::Bytes, threshold: usize) -> RedisResult<LazyValue> {
+    TreeSink::new(bytes, LazyBuilder(bytes, threshold)).parse_resp2()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "aio")]
+    #[test]
+    fn value_codec_encodes_commands() {
+        use tokio_util::codec::Encoder;
+        let mut codec = ValueCodec::default();
+
+        let mut get = crate::cmd("GET");
+        get.arg("key");
+        let mut scan = crate::cmd("SCAN");
+        scan.cursor_arg(42);
+        let mut pipe = crate::pipe();
+        pipe.add_command(get.clone()).add_command(scan.clone());
+
+        let mut out = bytes::BytesMut::new();
+        codec.encode(&get, &mut out).unwrap();
+        codec.encode(&scan, &mut out).unwrap();
+        codec.encode(&pipe, &mut out).unwrap();
+        pipe.atomic();
+        codec.encode(&pipe, &mut out).unwrap();
+
+        let mut expected = get.get_packed_command();
+        expected.extend(scan.get_packed_command());
+        expected.extend(
+            crate::pipe()
+                .add_command(get)
+                .add_command(scan)
+                .get_packed_pipeline(),
+        );
+        expected.extend(pipe.get_packed_pipeline());
+        assert_eq!(&out[..], &expected[..]);
+        assert!(out.ends_with(b"SCAN\r\n$2\r\n42\r\n*1\r\n$4\r\nEXEC\r\n"));
+    }
+
+    #[cfg(feature = "aio")]
+    #[test]
+    fn decode_eof_returns_none_at_eof() {
+        use tokio_util::codec::Decoder;
+        let mut codec = ValueCodec::default();
+
+        let mut bytes = bytes::BytesMut::from(&b"+GET 123\r\n"[..]);
+        assert_eq!(
+            codec.decode_eof(&mut bytes),
+            Ok(Some(Ok(parse_redis_value(b"+GET 123\r\n").unwrap())))
+        );
+        assert_eq!(codec.decode_eof(&mut bytes), Ok(None));
+        assert_eq!(codec.decode_eof(&mut bytes), Ok(None));
+    }
+
+    #[cfg(feature = "aio")]
+    #[test]
+    fn fuzz_decode_unknown_type_byte_split_across_chunks() {
+        // The incremental path used to drop an unknown type byte that ended a
+        // chunk and continue parsing after it.
+        fuzz_decode(b"*2\r\n$-1\r\nj-1\r\n");
+        fuzz_decode(b"j-1\r\n");
+
+        use tokio_util::codec::Decoder;
+        let mut codec = ValueCodec::default();
+        let mut bytes = bytes::BytesMut::from(&b"j"[..]);
+        assert!(codec.decode(&mut bytes).is_err());
+    }
+
+    #[cfg(feature = "aio")]
+    #[test]
+    fn fuzz_decode_corpus() {
+        let corpus = concat!(env!("CARGO_MANIFEST_DIR"), "/fuzz/corpus/parser");
+        for entry in std::fs::read_dir(corpus).unwrap() {
+            fuzz_decode(&std::fs::read(entry.unwrap().path()).unwrap());
+        }
+    }
+
+    #[cfg(feature = "bytes")]
+    #[test]
+    fn parse_bytes_shares_buffer() {
+        let buffer = bytes::Bytes::from_static(b"*3\r\n$3\r\nfoo\r\n:7\r\n*1\r\n$-1\r\n");
+        let value = parse_redis_value_bytes(&buffer).unwrap();
+        match value {
+            BytesValue::Bulk(ref items) => match items[0] {
+                BytesValue::Data(ref data) => {
+                    assert_eq!(&data[..], b"foo");
+                    assert_eq!(data.as_ptr(), buffer[8..].as_ptr());
+                }
+                _ => panic!("expected data"),
+            },
+            _ => panic!("expected bulk"),
+        }
+        assert_eq!(Value::from(value), parse_redis_value(&buffer[..]).unwrap());
+
+        let err = parse_redis_value_bytes(&bytes::Bytes::from_static(b"*1\r\n-MOVED 1 a:1\r\n"))
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Moved);
+        assert!(
+            parse_redis_value_bytes(&bytes::Bytes::from_static(b"*2\r\n:1\r\n"))
+                .unwrap_err()
+                .is_io_error()
+        );
+        let nested = [&b"*1\r\n".repeat(200_000)[..], b":1\r\n"].concat();
+        let err = parse_redis_value_bytes(&nested.into()).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ParseLimitExceeded);
+    }
+
+    #[cfg(feature = "aio")]
+    #[test]
+    fn bytes_value_codec_decodes_chunks() {
+        use tokio_util::codec::Decoder;
+        let mut codec = BytesValueCodec::default();
+
+        let mut bytes = bytes::BytesMut::from(&b"*3\r\n$3\r\nfoo\r\n+OK\r\n*1\r\n:1"[..]);
+        assert_eq!(codec.decode(&mut bytes), Ok(None));
+        bytes.extend_from_slice(b"\r\n-ERR boom\r\n");
+        let value = codec.decode(&mut bytes).unwrap().unwrap().unwrap();
+        assert_eq!(
+            Value::from(value),
+            Value::Bulk(vec![
+                Value::Data(b"foo".to_vec()),
+                Value::Okay,
+                Value::Bulk(vec![Value::Int(1)]),
+            ])
+        );
+
+        let err = codec.decode(&mut bytes).unwrap().unwrap().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ResponseError);
+        assert!(bytes.is_empty());
+
+        let mut bytes = bytes::BytesMut::from(&b"%1\r\n#t\r\n,1.5\r\n"[..]);
+        let err = codec.decode(&mut bytes).unwrap_err();
+        assert!(err.to_string().contains("RESP3 data is not supported"));
+
+        let mut bytes = bytes::BytesMut::from(&b"$3\r\nfo"[..]);
+        assert!(codec.decode_eof(&mut bytes).is_err());
+        let mut bytes = bytes::BytesMut::from(&b"j1\r\n"[..]);
+        assert!(codec.decode(&mut bytes).is_err());
+    }
+
+    #[cfg(feature = "aio")]
+    #[test]
+    fn command_codec() {
+        use tokio_util::codec::{Decoder, Encoder};
+        let mut codec = CommandCodec::default();
+
+        let mut bytes =
+            bytes::BytesMut::from(&b"*2\r\n$3\r\nGET\r\n$3\r\nfoo\r\n*1\r\n$4\r\nPI"[..]);
+        let args = codec.decode(&mut bytes).unwrap().unwrap();
+        assert_eq!(args, vec![&b"GET"[..], &b"foo"[..]]);
+        assert_eq!(codec.decode(&mut bytes).unwrap(), None);
+        bytes.extend_from_slice(b"NG\r\n");
+        assert_eq!(
+            codec.decode(&mut bytes).unwrap().unwrap(),
+            vec![&b"PING"[..]]
+        );
+
+        let invalid: &[&[u8]] = &[b"*1\r\n:1\r\n", b"*1\r\n*0\r\n", b"*-1\r\n"];
+        for frame in invalid {
+            let mut bytes = bytes::BytesMut::from(&frame[..]);
+            assert!(codec.decode(&mut bytes).is_err(), "{:?}", frame);
+        }
+
+        let mut out = bytes::BytesMut::new();
+        codec.encode(&Value::Okay, &mut out).unwrap();
+        codec
+            .encode(&Value::Bulk(vec![Value::Int(1)]), &mut out)
+            .unwrap();
+        assert_eq!(&out[..], b"+OK\r\n*1\r\n:1\r\n");
+    }
+
+    #[cfg(feature = "aio")]
+    #[test]
+    fn command_codec_inline() {
+        use tokio_util::codec::Decoder;
+        let mut codec = CommandCodec::with_limits(ParseLimits::new().max_bulk_length(16));
+
+        // like the server, anything which is not an array is an inline command
+        let mut bytes =
+            bytes::BytesMut::from(&b"\r\n  \r\n+PING\r\nSET k 'a b'\n*1\r\n$4\r\nPI"[..]);
+        assert_eq!(
+            codec.decode(&mut bytes).unwrap().unwrap(),
+            vec![&b"+PING"[..]]
+        );
+        assert_eq!(
+            codec.decode(&mut bytes).unwrap().unwrap(),
+            vec![&b"SET"[..], &b"k"[..], &b"a b"[..]]
+        );
+        assert_eq!(codec.decode(&mut bytes).unwrap(), None);
+        bytes.extend_from_slice(b"NG\r\n");
+        assert_eq!(
+            codec.decode(&mut bytes).unwrap().unwrap(),
+            vec![&b"PING"[..]]
+        );
+
+        // a malformed line is consumed with the error
+        let mut bytes = bytes::BytesMut::from(&b"GET \"k\r\nPING\r\n"[..]);
+        assert!(codec.decode(&mut bytes).is_err());
+        assert_eq!(
+            codec.decode(&mut bytes).unwrap().unwrap(),
+            vec![&b"PING"[..]]
+        );
+
+        let mut bytes = bytes::BytesMut::from(&b"GET a-very-long-key"[..]);
+        let err = codec.decode(&mut bytes).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ParseLimitExceeded);
+    }
+
+    #[test]
+    fn parse_inline_commands() {
+        let cases: &[(&[u8], &[&[u8]])] = &[
+            (b"\r\n", &[]),
+            (b"PING\r\n", &[b"PING"]),
+            (b"  SET   k  v \n", &[b"SET", b"k", b"v"]),
+            (
+                b"SET k \"a \\\"b\\\" \\x41\\x7a\\n\"\r\n",
+                &[b"SET", b"k", b"a \"b\" Az\n"],
+            ),
+            (b"SET k 'it\\'s \\n'\r\n", &[b"SET", b"k", b"it's \\n"]),
+            (b"SET k \"\"\r\n", &[b"SET", b"k", b""]),
+            (b"SET k\"ey\" v\r\n", &[b"SET", b"key", b"v"]),
+        ];
+        for (input, expected) in cases {
+            let (args, len) = parse_inline_command(input).unwrap();
+            assert_eq!(&args, expected, "{:?}", input);
+            assert_eq!(len, input.len());
+        }
+
+        let (args, len) = parse_inline_command(b"PING\r\nPING\r\n").unwrap();
+        assert_eq!((args.len(), len), (1, 6));
+        assert!(parse_inline_command(b"PING").unwrap_err().is_io_error());
+        for input in [&b"GET \"k\r\n"[..], b"GET 'k\r\n", b"GET \"k\"x\r\n"] {
+            let err = parse_inline_command(input).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::ResponseError);
+        }
+    }
+
+    #[test]
+    fn write_reply_round_trip() {
+        let value = Value::Bulk(vec![
+            Value::Nil,
+            Value::Int(-42),
+            Value::Data(b"bin\r\nary".to_vec()),
+            Value::Bulk(vec![]),
+            Value::Bulk(vec![Value::Okay, Value::Status("PONG".into())]),
+        ]);
+        let mut buf = Vec::new();
+        write_reply(&value, &mut buf).unwrap();
+        assert_eq!(parse_redis_value(&buf).unwrap(), value);
+    }
+
+    #[test]
+    fn write_reply_rejects_unencodable_values() {
+        let values = [
+            Value::Status("a\r\n:1".into()),
+            Value::BigNumber("1\n".into()),
+            Value::VerbatimString {
+                format: "text".into(),
+                text: "hi".into(),
+            },
+            Value::Bulk(vec![Value::Okay, Value::Status("\r".into())]),
+        ];
+        for value in &values {
+            let err = write_reply(value, &mut Vec::new()).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        }
+    }
+
+    #[test]
+    fn write_reply_resp3_round_trip() {
+        let value = Value::Map(vec![
+            (
+                Value::Data(b"set".to_vec()),
+                Value::Set(vec![Value::Boolean(true), Value::Boolean(false)]),
+            ),
+            (Value::Double(-1.5), Value::Double(f64::INFINITY)),
+            (
+                Value::Double(f64::NAN),
+                Value::BigNumber("-12345678901234567890".into()),
+            ),
+            (
+                Value::Int(1),
+                Value::VerbatimString {
+                    format: "mkd".into(),
+                    text: "# title".into(),
+                },
+            ),
+        ]);
+        let mut buf = Vec::new();
+        write_reply(&value, &mut buf).unwrap();
+        assert_eq!(parse_redis_value(&buf).unwrap(), value);
+    }
+
+    #[cfg(feature = "aio")]
+    #[test]
+    fn write_reply_async_round_trip() {
+        let value = Value::Bulk(vec![Value::Int(1), Value::Data(b"foo".to_vec())]);
+        let mut buf = Vec::new();
+        futures::executor::block_on(write_reply_async(&value, &mut buf)).unwrap();
+        assert_eq!(parse_redis_value(&buf).unwrap(), value);
+    }
+
+    #[test]
+    fn parse_ref_borrows_input() {
+        let bytes = b"*3\r\n+PONG\r\n$3\r\nfoo\r\n+OK\r\n";
+        let value = parse_redis_value_ref(bytes).unwrap();
+        match value {
+            ValueRef::Bulk(ref items) => match (&items[0], &items[1]) {
+                (ValueRef::Status(status), ValueRef::Data(data)) => {
+                    assert_eq!(*status, "PONG");
+                    assert_eq!(status.as_ptr(), bytes[5..].as_ptr());
+                    assert_eq!(data.as_ptr(), bytes[15..].as_ptr());
+                }
+                _ => panic!("expected a status and data"),
+            },
+            _ => panic!("expected bulk"),
+        }
+        assert_eq!(value.to_value(), parse_redis_value(bytes).unwrap());
+
+        assert!(parse_redis_value_ref(b"+\xff\r\n").is_err());
+        assert!(parse_redis_value_ref(b"+PON").unwrap_err().is_io_error());
+    }
+
+    #[test]
+    fn parse_ref_limits_depth() {
+        let nested = |depth: usize| [&b"*1\r\n".repeat(depth)[..], b":1\r\n"].concat();
+        assert!(parse_redis_value_ref(&nested(512)).is_ok());
+        let err = parse_redis_value_ref(&nested(200_000)).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ParseLimitExceeded);
+    }
+
+    #[test]
+    fn resp2_only_parsers_reject_resp3() {
+        let inputs: &[&[u8]] = &[
+            b"_\r\n",
+            b"#t\r\n",
+            b",1.5\r\n",
+            b"(12345678901234567890\r\n",
+            b"!3\r\nERR\r\n",
+            b"=7\r\ntxt:foo\r\n",
+            b"%1\r\n:1\r\n:2\r\n",
+            b"~1\r\n:1\r\n",
+            b">2\r\n+message\r\n:1\r\n",
+            b"|1\r\n:1\r\n:2\r\n:3\r\n",
+            b"$?\r\n;3\r\nfoo\r\n;0\r\n",
+            b"*?\r\n:1\r\n.\r\n",
+            b"*2\r\n:1\r\n#f\r\n",
+        ];
+        for input in inputs {
+            let err = parse_redis_value_ref(input).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::ResponseError, "{:?}", input);
+            assert!(err.to_string().contains("RESP3 data is not supported"));
+            assert_eq!(parse_with_sink(input, &mut NoopSink).unwrap(), input.len());
+        }
+    }
+
+    #[test]
+    fn parse_partial_recovers_truncated_arrays() {
+        let bytes = b"*4\r\n:1\r\n*3\r\n$3\r\nfoo\r\n$3\r\nbar\r\n$10\r\nbaz";
+        for end in 0..bytes.len() {
+            let complete = parse_redis_value(&bytes[..end]);
+            let partial = parse_redis_value_partial(&bytes[..end]);
+            assert!(complete.is_err());
+            if end < 4 {
+                assert!(partial.unwrap_err().is_io_error());
+            } else {
+                assert!(partial.unwrap().1);
+            }
+        }
+
+        assert_eq!(
+            parse_redis_value_partial(bytes).unwrap(),
+            (
+                Value::Bulk(vec![
+                    Value::Int(1),
+                    Value::Bulk(vec![
+                        Value::Data(b"foo".to_vec()),
+                        Value::Data(b"bar".to_vec()),
+                    ]),
+                ]),
+                true
+            )
+        );
+
+        assert_eq!(
+            parse_redis_value_partial(b"*1\r\n:1\r\n").unwrap(),
+            (Value::Bulk(vec![Value::Int(1)]), false)
+        );
+        assert_eq!(
+            parse_redis_value_partial(b"*2\r\n-ERR no\r\n")
+                .unwrap_err()
+                .kind(),
+            ErrorKind::ResponseError
+        );
+
+        assert_eq!(
+            parse_redis_value_partial(b"%2\r\n+a\r\n#t\r\n+b\r\n~2\r\n,1.5\r\n").unwrap(),
+            (
+                Value::Map(vec![
+                    (Value::Status("a".into()), Value::Boolean(true)),
+                    (
+                        Value::Status("b".into()),
+                        Value::Set(vec![Value::Double(1.5)])
+                    ),
+                ]),
+                true
+            )
+        );
+        assert_eq!(
+            parse_redis_value_partial(b"%2\r\n+a\r\n#t\r\n+b\r\n").unwrap(),
+            (
+                Value::Map(vec![(Value::Status("a".into()), Value::Boolean(true))]),
+                true
+            )
+        );
+
+        let nested = [&b"*1\r\n".repeat(200_000)[..], b":1"].concat();
+        let err = parse_redis_value_partial(&nested).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ParseLimitExceeded);
+    }
+
+    #[test]
+    fn track_max_depth() {
+        let mut parser = Parser::new();
+        assert_eq!(
+            parser.parse_value(&b"*1\r\n:1\r\n"[..]).unwrap(),
+            Value::Bulk(vec![Value::Int(1)])
+        );
+        assert_eq!(parser.last_max_depth(), None);
+
+        parser.set_track_depth(true);
+        let bytes = b"*3\r\n:1\r\n*1\r\n*2\r\n$1\r\na\r\n*0\r\n*0\r\n:2\r\n";
+        let mut reader = &bytes[..];
+        parser.parse_value(&mut reader).unwrap();
+        assert_eq!(parser.last_max_depth(), Some(4));
+        parser.parse_value(&mut reader).unwrap();
+        assert_eq!(parser.last_max_depth(), Some(0));
+
+        assert!(parser.parse_value(&b"-ERR no\r\n"[..]).is_err());
+        assert_eq!(parser.last_max_depth(), None);
+
+        // attributes count like any other aggregate
+        let bytes = b"|1\r\n+key\r\n*1\r\n*1\r\n:1\r\n:2\r\n";
+        let value = parser.parse_value(&bytes[..]).unwrap();
+        assert!(matches!(value, Value::Attribute { .. }));
+        assert_eq!(parser.last_max_depth(), Some(3));
+
+        // the depth of a reply read in parts is tracked across the parts
+        let mut deque = VecDeque::from(b"*1\r\n*2\r\n".to_vec());
+        assert!(parser.parse_value_from_deque(&mut deque).is_err());
+        deque.extend(b"~0\r\n:1\r\n");
+        parser.parse_value_from_deque(&mut deque).unwrap();
+        assert_eq!(parser.last_max_depth(), Some(3));
+    }
+
+    #[test]
+    fn parse_limits() {
+        let limits = ParseLimits::new()
+            .max_bulk_length(3)
+            .max_aggregate_length(2)
+            .max_depth(2);
+        let parse = |bytes: &[u8]| {
+            let mut parser = Parser::new();
+            parser.set_limits(limits);
+            parser.parse_value(bytes)
+        };
+        let sink =
+            |bytes: &[u8]| parse_with_sink_limited(bytes, &mut RecordingSink::default(), &limits);
+
+        let accepted: &[&[u8]] = &[
+            b"*2\r\n$3\r\nfoo\r\n*1\r\n$-1\r\n",
+            b"%2\r\n:1\r\n:2\r\n:3\r\n:4\r\n",
+        ];
+        for bytes in accepted {
+            assert!(parse(bytes).is_ok());
+            assert_eq!(sink(bytes).unwrap(), bytes.len());
+        }
+
+        // The headers are rejected without waiting for the payload.
+        let rejected: &[&[u8]] = &[
+            b"$4\r\n",
+            b"=10\r\n",
+            b"*3\r\n",
+            b"%3\r\n",
+            b"*1\r\n*1\r\n*1\r\n",
+            // streamed replies are rejected before their end
+            b"$?\r\n;2\r\nab\r\n;2\r\ncd\r\n",
+            b"*?\r\n:1\r\n:2\r\n:3\r\n",
+            b"%?\r\n:1\r\n:2\r\n:3\r\n:4\r\n:5\r\n",
+        ];
+        for bytes in rejected {
+            assert_eq!(
+                parse(bytes).unwrap_err().kind(),
+                ErrorKind::ParseLimitExceeded
+            );
+            assert_eq!(
+                sink(bytes).unwrap_err().kind(),
+                ErrorKind::ParseLimitExceeded
+            );
+        }
+    }
+
+    #[test]
+    fn error_values() {
+        let bytes = b"*3\r\n-ERR no\r\n*1\r\n!9\r\nMOVED 1 a\r\n:1\r\n";
+        assert_eq!(
+            Parser::new().parse_value(&bytes[..]).unwrap_err().kind(),
+            ErrorKind::ResponseError
+        );
+
+        let mut parser = Parser::new();
+        parser.set_error_values(true);
+        parser.set_count_types(true);
+        assert_eq!(
+            parser.parse_value(&bytes[..]).unwrap(),
+            Value::Bulk(vec![
+                Value::ServerError("ERR no".into()),
+                Value::Bulk(vec![Value::ServerError("MOVED 1 a".into())]),
+                Value::Int(1),
+            ])
+        );
+        assert_eq!(parser.type_histogram().unwrap().errors, 2);
+        // an error reply of its own still fails
+        assert_eq!(
+            parser.parse_value(&b"-ERR no\r\n"[..]).unwrap_err().kind(),
+            ErrorKind::ResponseError
+        );
+    }
+
+    #[cfg(feature = "aio")]
+    #[test]
+    fn value_codec_error_values() {
+        use tokio_util::codec::Decoder;
+
+        let reply = &b"*2\r\n:1\r\n-ERR no\r\n"[..];
+        let mut bytes = bytes::BytesMut::from(reply);
+        let decoded = ValueCodec::default().decode(&mut bytes).unwrap().unwrap();
+        assert_eq!(decoded.unwrap_err().kind(), ErrorKind::ResponseError);
+
+        let mut bytes = bytes::BytesMut::from(reply);
+        let mut codec = ValueCodec::default().error_values(true);
+        assert_eq!(
+            codec.decode(&mut bytes),
+            Ok(Some(Ok(Value::Bulk(vec![
+                Value::Int(1),
+                Value::ServerError("ERR no".into()),
+            ]))))
+        );
+    }
+
+    #[test]
+    fn parse_limits_maps() {
+        let limits = ParseLimits::new()
+            .max_aggregate_length(4)
+            .max_map_entries(2);
+        let parse = |bytes: &[u8]| {
+            let mut parser = Parser::new();
+            parser.set_limits(limits);
+            parser.parse_value(bytes)
+        };
+        let sink =
+            |bytes: &[u8]| parse_with_sink_limited(bytes, &mut RecordingSink::default(), &limits);
+
+        let array = b"*3\r\n:1\r\n:2\r\n:3\r\n";
+        assert!(parse(array).is_ok());
+        assert_eq!(sink(array).unwrap(), array.len());
+
+        let rejected: &[&[u8]] = &[
+            b"%3\r\n",
+            b"|3\r\n",
+            b"%?\r\n:1\r\n:2\r\n:3\r\n:4\r\n:5\r\n",
+        ];
+        for bytes in rejected {
+            assert_eq!(
+                parse(bytes).unwrap_err().kind(),
+                ErrorKind::ParseLimitExceeded
+            );
+            assert_eq!(
+                sink(bytes).unwrap_err().kind(),
+                ErrorKind::ParseLimitExceeded
+            );
+        }
+    }
+
+    #[test]
+    fn parse_limits_total_payload() {
+        let bulks = |count: usize| {
+            let mut bytes = format!("*{}\r\n", count).into_bytes();
+            for _ in 0..count {
+                bytes.extend_from_slice(b"$4\r\naaaa\r\n");
+            }
+            bytes
+        };
+        let nested = b"*2\r\n*2\r\n$4\r\naaaa\r\n$4\r\naaaa\r\n~2\r\n$4\r\naaaa\r\n$?\r\n;2\r\naa\r\n;2\r\naa\r\n;0\r\n";
+        let streamed = b"*?\r\n$4\r\naaaa\r\n$4\r\naaaa\r\n$4\r\naaaa\r\n$4\r\naaaa\r\n.\r\n";
+        let attribute = b"|1\r\n$4\r\naaaa\r\n$4\r\naaaa\r\n*2\r\n$4\r\naaaa\r\n$4\r\naaaa\r\n";
+        let replies: &[&[u8]] = &[&bulks(4), nested, streamed, attribute];
+
+        let limits = |max| ParseLimits::new().max_bulk_length(4).max_total_payload(max);
+        for bytes in replies {
+            let mut parser = Parser::new();
+            parser.set_limits(limits(16));
+            // the payload starts over with every reply
+            assert!(parser.parse_value(&bytes[..]).is_ok());
+            assert!(parser.parse_value(&bytes[..]).is_ok());
+            let mut sink = RecordingSink::default();
+            assert_eq!(
+                parse_with_sink_limited(bytes, &mut sink, &limits(16)).unwrap(),
+                bytes.len()
+            );
+
+            parser.set_limits(limits(15));
+            assert_eq!(
+                parser.parse_value(&bytes[..]).unwrap_err().kind(),
+                ErrorKind::ParseLimitExceeded
+            );
+            assert_eq!(
+                parse_with_sink_limited(bytes, &mut sink, &limits(15))
+                    .unwrap_err()
+                    .kind(),
+                ErrorKind::ParseLimitExceeded
+            );
+        }
+
+        // the limit is hit as soon as the header of the excess bulk is read
+        let mut parser = Parser::new();
+        parser.set_limits(limits(15));
+        let truncated = &bulks(100)[..60];
+        assert_eq!(
+            parser.parse_value(truncated).unwrap_err().kind(),
+            ErrorKind::ParseLimitExceeded
+        );
+    }
+
+    #[cfg(feature = "aio")]
+    #[test]
+    fn bytes_value_codec_limits() {
+        use tokio_util::codec::Decoder;
+        let limits = ParseLimits::new().max_bulk_length(4);
+        let mut codec = BytesValueCodec::with_limits(limits);
+        let mut bytes = bytes::BytesMut::from(&b"$1000000\r\nabc"[..]);
+        let err = codec.decode(&mut bytes).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ParseLimitExceeded);
+    }
+
+    #[cfg(feature = "aio")]
+    #[test]
+    fn codecs_shrink_read_buffers() {
+        use tokio_util::codec::Decoder;
+        let mut reply = b"$1048576\r\n".to_vec();
+        reply.extend(vec![b'x'; 1 << 20]);
+        reply.extend(b"\r\n+O");
+
+        let mut codec = ValueCodec::default();
+        let mut bytes = bytes::BytesMut::from(&reply[..]);
+        assert!(codec.decode(&mut bytes).unwrap().is_some());
+        assert_eq!(&bytes[..], b"+O");
+        assert!(bytes.capacity() <= 64 * 1024);
+
+        let mut codec = BytesValueCodec::default();
+        let mut bytes = bytes::BytesMut::from(&reply[..]);
+        assert!(codec.decode(&mut bytes).unwrap().is_some());
+        assert_eq!(&bytes[..], b"+O");
+        assert!(bytes.capacity() <= 64 * 1024);
+
+        let policy = BufferPolicy::new().max_idle_capacity(usize::MAX);
+        let mut codec = ValueCodec::default().buffer_policy(policy);
+        let mut bytes = bytes::BytesMut::from(&reply[..]);
+        let capacity = bytes.capacity();
+        assert!(codec.decode(&mut bytes).unwrap().is_some());
+        assert_eq!(bytes.capacity() + reply.len() - 2, capacity);
+    }
+
+    #[cfg(feature = "aio")]
+    #[test]
+    fn codecs_share_buffer_pool() {
+        use tokio_util::codec::Decoder;
+        let pool = BufferPool::new(4 << 20);
+        let policy = BufferPolicy::new().pool(pool.clone());
+        let mut reply = b"$1048576\r\n".to_vec();
+        reply.extend(vec![b'x'; 1 << 20]);
+        reply.extend(b"\r\n");
+
+        // the large buffer of the first connection goes to the pool
+        let mut codec = ValueCodec::default().buffer_policy(policy.clone());
+        let mut bytes = bytes::BytesMut::from(&reply[..]);
+        assert!(codec.decode(&mut bytes).unwrap().is_some());
+        assert!(pool.pooled_bytes() >= 1 << 20);
+
+        // and is taken by the second one once its buffer is about to grow
+        let mut codec = ValueCodec::default().buffer_policy(policy);
+        let mut bytes = bytes::BytesMut::from(&reply[..128 * 1024]);
+        assert_eq!(codec.decode(&mut bytes).unwrap(), None);
+        assert!(bytes.capacity() >= 1 << 20);
+        assert!(pool.pooled_bytes() < 1 << 20);
+        bytes.extend_from_slice(&reply[128 * 1024..]);
+        assert_eq!(
+            codec.decode(&mut bytes).unwrap().unwrap().unwrap(),
+            Value::Data(vec![b'x'; 1 << 20])
+        );
+    }
+
+    #[test]
+    fn count_parsed_types() {
+        let mut parser = Parser::new();
+        assert_eq!(parser.type_histogram(), None);
+        parser.set_count_types(true);
+
+        let bytes = b"+OK\r\n:1\r\n*3\r\n$1\r\na\r\n$-1\r\n*1\r\n+PONG\r\n-ERR no\r\n";
+        let mut reader = &bytes[..];
+        for _ in 0..4 {
+            let _ = parser.parse_value(&mut reader);
+        }
+        assert_eq!(
+            parser.type_histogram(),
+            Some(&TypeHistogram {
+                nils: 1,
+                integers: 1,
+                bulk_strings: 1,
+                arrays: 2,
+                statuses: 2,
+                errors: 1,
+                ..TypeHistogram::default()
+            })
+        );
+
+        // protocol errors are not server errors
+        let mut parser = Parser::new();
+        parser.set_count_types(true);
+        assert!(parser.parse_value(&b"?\r\n"[..]).is_err());
+        assert_eq!(parser.type_histogram(), Some(&TypeHistogram::default()));
+
+        parser.set_count_types(false);
+        assert_eq!(parser.type_histogram(), None);
+    }
+
+    #[test]
+    fn parse_resp3_frames() {
+        let cases: &[(&[u8], Value)] = &[
+            (
+                b"%2\r\n+first\r\n:1\r\n$6\r\nsecond\r\n_\r\n",
+                Value::Map(vec![
+                    (Value::Status("first".into()), Value::Int(1)),
+                    (Value::Data(b"second".to_vec()), Value::Nil),
+                ]),
+            ),
+            (
+                b"~2\r\n$1\r\na\r\n$1\r\nb\r\n",
+                Value::Set(vec![Value::Data(b"a".to_vec()), Value::Data(b"b".to_vec())]),
+            ),
+            (
+                b">2\r\n$10\r\ninvalidate\r\n*1\r\n$3\r\nkey\r\n",
+                Value::Push {
+                    kind: "invalidate".into(),
+                    data: vec![Value::Bulk(vec![Value::Data(b"key".to_vec())])],
+                },
+            ),
+            (b"_\r\n", Value::Nil),
+            (b"#t\r\n", Value::Boolean(true)),
+            (b"#f\r\n", Value::Boolean(false)),
+            (b",2.5\r\n", Value::Double(2.5)),
+            (b",-inf\r\n", Value::Double(f64::NEG_INFINITY)),
+            (
+                b"(3492890328409238509324850943850943825024385\r\n",
+                Value::BigNumber("3492890328409238509324850943850943825024385".into()),
+            ),
+            (
+                b"=15\r\ntxt:Some string\r\n",
+                Value::VerbatimString {
+                    format: "txt".into(),
+                    text: "Some string".into(),
+                },
+            ),
+        ];
+        for (bytes, expected) in cases {
+            assert_eq!(
+                parse_redis_value(bytes).as_ref(),
+                Ok(expected),
+                "{:?}",
+                bytes
+            );
+        }
+
+        let err = parse_redis_value(b"!21\r\nSYNTAX invalid syntax\r\n").unwrap_err();
+        assert_eq!(err.code(), Some("SYNTAX"));
+        assert_eq!(err.detail(), Some("invalid syntax"));
+
+        for garbage in &[
+            &b"#x\r\n"[..],
+            b",pi\r\n",
+            b"(12a\r\n",
+            b"_x\r\n",
+            b"=3\r\ntxt\r\n",
+        ] {
+            let err = parse_redis_value(garbage).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::ResponseError, "{:?}", garbage);
+        }
+    }
+
+    #[test]
+    fn parse_lenient_integers() {
+        let bytes = b":5 ignored\r\n*2\r\n:-3\tx y\r\n:7\r\n";
+
+        let err = parse_redis_value(&bytes[..]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ResponseError);
+
+        let mut parser = Parser::new();
+        parser.set_integer_parsing(IntegerParsing::Lenient);
+        assert_eq!(parser.integer_parsing(), IntegerParsing::Lenient);
+        let mut reader = &bytes[..];
+        assert_eq!(parser.parse_value(&mut reader).unwrap(), Value::Int(5));
+        assert_eq!(
+            parser.parse_value(&mut reader).unwrap(),
+            Value::Bulk(vec![Value::Int(-3), Value::Int(7)])
+        );
+
+        // lengths are never parsed leniently
+        assert!(parser.parse_value(&b"$3 x\r\nfoo\r\n"[..]).is_err());
+    }
+
+    #[cfg(feature = "bytes")]
+    #[test]
+    fn parse_lazy_copies_small_data_only() {
+        let buffer = bytes::Bytes::from_static(b"*2\r\n$2\r\nid\r\n$8\r\npayload!\r\n");
+        let value = parse_redis_value_lazy(&buffer, 4).unwrap();
+        let mut items = match value.clone() {
+            LazyValue::Bulk(items) => items,
+            _ => panic!("expected bulk"),
+        };
+        match items[0] {
+            LazyValue::Data(ref data) => assert!(!data.is_shared()),
+            _ => panic!("expected data"),
+        }
+        match items[1] {
+            LazyValue::Data(ref mut data) => {
+                assert!(data.is_shared());
+                assert_eq!(data.as_bytes().as_ptr(), buffer[16..].as_ptr());
+                data.to_mut().push(b'!');
+                assert!(!data.is_shared());
+                assert_eq!(data.as_bytes(), b"payload!!");
+            }
+            _ => panic!("expected data"),
+        }
+        assert_eq!(Value::from(value), parse_redis_value(&buffer).unwrap());
+
+        let nested = [&b"*1\r\n".repeat(200_000)[..], b":1\r\n"].concat();
+        let err = parse_redis_value_lazy(&nested.into(), 4).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ParseLimitExceeded);
+        assert!(parse_redis_value_lazy(&bytes::Bytes::from_static(b"%0\r\n"), 4).is_err());
+    }
+
+    #[test]
+    fn parse_canonical_integers() {
+        let mut parser = Parser::new();
+        parser.set_integer_parsing(IntegerParsing::Canonical);
+        for (bytes, expected) in &[
+            (&b":0\r\n"[..], 0),
+            (b":-1\r\n", -1),
+            (b":7512182390\r\n", 7512182390),
+        ] {
+            assert_eq!(parser.parse_value(*bytes).unwrap(), Value::Int(*expected));
+        }
+        for bytes in &[&b":007\r\n"[..], b":+5\r\n", b":-0\r\n", b": 5\r\n"] {
+            assert!(parse_redis_value(bytes).is_ok());
+            let mut parser = Parser::new();
+            parser.set_integer_parsing(IntegerParsing::Canonical);
+            assert!(parser.parse_value(*bytes).is_err(), "{:?}", bytes);
+        }
+        assert!(!is_canonical_integer("-"));
+        assert!(!is_canonical_integer(""));
+
+        // lengths are not affected
+        let mut parser = Parser::new();
+        parser.set_integer_parsing(IntegerParsing::Canonical);
+        assert_eq!(
+            parser.parse_value(&b"$03\r\nfoo\r\n"[..]).unwrap(),
+            Value::Data(b"foo".to_vec())
+        );
+    }
+
+    #[test]
+    fn parse_reply_heads() {
+        // hands out a byte at a time so lines are split across reads
+        struct Trickle<'a>(&'a [u8]);
+
+        impl io::Read for Trickle<'_> {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                self.0.read(&mut buf[..1])
+            }
+        }
+
+        let mut reader = Trickle(b"*3\r\n:1\r\n$1\r\na\r\n*0\r\n~1\r\n+OK\r\n");
+        let mut parser = Parser::new();
+        assert!(matches!(
+            parser.parse_head(&mut reader).unwrap(),
+            ReplyHead::Aggregate(3)
+        ));
+        assert_eq!(parser.parse_value(&mut reader).unwrap(), Value::Int(1));
+        assert_eq!(
+            parser.parse_value(&mut reader).unwrap(),
+            Value::Data(b"a".to_vec())
+        );
+        assert_eq!(
+            parser.parse_value(&mut reader).unwrap(),
+            Value::Bulk(vec![])
+        );
+        assert!(matches!(
+            parser.parse_head(&mut reader).unwrap(),
+            ReplyHead::Aggregate(1)
+        ));
+        assert_eq!(parser.parse_value(&mut reader).unwrap(), Value::Okay);
+
+        let mut reader = io::Cursor::new(&b"*-1\r\n*?\r\n:1\r\n.\r\n:2\r\n*1"[..]);
+        assert!(matches!(
+            parser.parse_head(&mut reader).unwrap(),
+            ReplyHead::Value(Value::Nil)
+        ));
+        match parser.parse_head(&mut reader).unwrap() {
+            ReplyHead::Value(v) => assert_eq!(v, Value::Bulk(vec![Value::Int(1)])),
+            ReplyHead::Aggregate(_) => panic!("streamed aggregate was not parsed"),
+        }
+        assert!(matches!(
+            parser.parse_head(&mut reader).unwrap(),
+            ReplyHead::Value(Value::Int(2))
+        ));
+        assert!(parser.parse_head(&mut reader).unwrap_err().is_io_error());
+
+        // maps count their keys and values
+        let mut parser = Parser::new();
+        let mut reader = io::Cursor::new(&b"%2\r\n+a\r\n:1\r\n+b\r\n:2\r\n"[..]);
+        assert!(matches!(
+            parser.parse_head(&mut reader).unwrap(),
+            ReplyHead::Aggregate(4)
+        ));
+        assert_eq!(
+            parser.parse_value(&mut reader).unwrap(),
+            Value::Status("a".into())
+        );
+
+        let mut parser = Parser::new();
+        parser.set_limits(ParseLimits::new().max_aggregate_length(2));
+        let err = parser
+            .parse_head(io::Cursor::new(&b"*3\r\n"[..]))
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ParseLimitExceeded);
+        let mut parser = Parser::new();
+        parser.set_limits(ParseLimits::new().max_map_entries(1));
+        let err = parser
+            .parse_head(io::Cursor::new(&b"%2\r\n"[..]))
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ParseLimitExceeded);
+    }
+
+    #[test]
+    fn parse_value_from_wrapped_deque() {
+        let frame = b"*2\r\n$5\r\nhello\r\n:42\r\n";
+        let mut deque = VecDeque::with_capacity(64);
+        // move the start of the deque close to the end of its buffer so
+        // that the frame wraps around
+        let start = deque.capacity() - 8;
+        deque.extend(vec![0; start]);
+        deque.drain(..start - 1);
+        deque.extend(&frame[..]);
+        deque.extend(b"+OK\r\n");
+        deque.pop_front();
+        assert!(!deque.as_slices().1.is_empty());
+
+        let mut parser = Parser::new();
+        assert_eq!(
+            parser.parse_value_from_deque(&mut deque).unwrap(),
+            Value::Bulk(vec![Value::Data(b"hello".to_vec()), Value::Int(42)])
+        );
+        assert_eq!(
+            parser.parse_value_from_deque(&mut deque).unwrap(),
+            Value::Okay
+        );
+        assert!(deque.is_empty());
+
+        deque.extend(&frame[..7]);
+        assert!(parser
+            .parse_value_from_deque(&mut deque)
+            .unwrap_err()
+            .is_io_error());
+        assert!(parser
+            .parse_value_from_deque(&mut deque)
+            .unwrap_err()
+            .is_io_error());
+        assert!(deque.is_empty());
+        deque.extend(&frame[7..]);
+        assert_eq!(
+            parser.parse_value_from_deque(&mut deque).unwrap(),
+            Value::Bulk(vec![Value::Data(b"hello".to_vec()), Value::Int(42)])
+        );
+    }
+
+    #[test]
+    fn try_parse_value_returns_none_at_clean_eof() {
+        let mut parser = Parser::new();
+        let mut reader = &b"+OK\r\n:1\r\n*2\r\n$1\r\na\r\n$-1\r\n"[..];
+
+        assert_eq!(parser.try_parse_value(&mut reader), Ok(Some(Value::Okay)));
+        assert_eq!(parser.try_parse_value(&mut reader), Ok(Some(Value::Int(1))));
+        assert_eq!(
+            parser.try_parse_value(&mut reader),
+            Ok(Some(Value::Bulk(vec![
+                Value::Data(b"a".to_vec()),
+                Value::Nil
+            ])))
+        );
+        assert_eq!(parser.try_parse_value(&mut reader), Ok(None));
+        assert_eq!(parser.try_parse_value(&mut reader), Ok(None));
+    }
+
+    #[test]
+    fn parse_latin1_status_with_latin1_policy() {
+        let input = &b"+caf\xe9\r\n"[..];
+        assert!(parse_redis_value(input).is_err());
+
+        let mut parser = Parser::new();
+        parser.set_string_decoding(StringDecoding::Latin1);
+        assert_eq!(
+            parser.parse_value(input),
+            Ok(Value::Status("caf\u{e9}".to_string()))
+        );
+
+        let data = Value::Data(b"caf\xe9".to_vec());
+        assert!(data.decode_string(StringDecoding::Utf8Strict).is_err());
+        assert_eq!(
+            data.decode_string(parser.string_decoding()),
+            Ok("caf\u{e9}".to_string())
+        );
+    }
+
+    #[test]
+    fn tokens_of_nested_reply() {
+        let input = b"*3\r\n*2\r\n+OK\r\n$-1\r\n$0\r\n\r\n-ERR oops\r\n";
+        let tokens: Vec<_> = Tokens::new(input).collect::<RedisResult<_>>().unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::ArrayHeader(3),
+                Token::ArrayHeader(2),
+                Token::SimpleString("OK"),
+                Token::Null,
+                Token::BulkHeader(0),
+                Token::BulkData(b""),
+                Token::Error("ERR oops"),
+            ]
+        );
+    }
+
+    #[test]
+    fn tokens_resume_after_incomplete_input() {
+        let input = b":1\r\n$5\r\nhello\r\n";
+        let mut tokens = Tokens::new(&input[..12]);
+        assert_eq!(tokens.next(), Some(Ok(Token::Integer(1))));
+        assert!(tokens.next().unwrap().unwrap_err().is_io_error());
+        assert_eq!(tokens.next(), None);
+        let resume = tokens.position();
+        assert_eq!(resume, 4);
+
+        let mut tokens = Tokens::new(&input[resume..]);
+        assert_eq!(tokens.next(), Some(Ok(Token::BulkHeader(5))));
+        assert_eq!(tokens.next(), Some(Ok(Token::BulkData(b"hello"))));
+        assert_eq!(tokens.next(), None);
+    }
+
+    #[test]
+    fn try_parse_value_errors_at_mid_frame_eof() {
+        let mut parser = Parser::new();
+        let err = parser.try_parse_value(&b"$5\r\nab"[..]).unwrap_err();
+        assert!(err.is_io_error());
+    }
+
+    #[derive(Default)]
+    struct RecordingSink(Vec<String>);
+
+    impl ParserSink for RecordingSink {
+        fn on_nil(&mut self) {
+            self.0.push("nil".into());
+        }
+        fn on_int(&mut self, value: i64) {
+            self.0.push(format!("int {}", value));
+        }
+        fn on_data(&mut self, data: &[u8]) {
+            self.0
+                .push(format!("data {}", String::from_utf8_lossy(data)));
+        }
+        fn on_status(&mut self, status: &[u8]) {
+            self.0
+                .push(format!("status {}", String::from_utf8_lossy(status)));
+        }
+        fn on_error(&mut self, error: &[u8]) {
+            self.0
+                .push(format!("error {}", String::from_utf8_lossy(error)));
+        }
+        fn on_array_begin(&mut self, len: usize) {
+            self.0.push(format!("array {}", len));
+        }
+        fn on_array_end(&mut self) {
+            self.0.push("end".into());
+        }
+        fn on_map_begin(&mut self, len: usize) {
+            self.0.push(format!("map {}", len));
+        }
+    }
+
+    fn sink_events(bytes: &[u8]) -> (usize, Vec<String>) {
+        let mut sink = RecordingSink::default();
+        let consumed = parse_with_sink(bytes, &mut sink).unwrap();
+        (consumed, sink.0)
+    }
+
+    #[test]
+    fn parse_with_sink_events() {
+        let bytes = b"*3\r\n:1\r\n*0\r\n*2\r\n$-1\r\n+OK\r\n-ERR x\r\n";
+        let (consumed, events) = sink_events(bytes);
+        assert_eq!(consumed, bytes.len() - b"-ERR x\r\n".len());
+        assert_eq!(
+            events,
+            vec![
+                "array 3",
+                "int 1",
+                "array 0",
+                "end",
+                "array 2",
+                "nil",
+                "status OK",
+                "end",
+                "end",
+            ]
+        );
+
+        // the RESP3 types fall back to the array and data events
+        let (_, events) = sink_events(b"%1\r\n~1\r\n#t\r\n=8\r\ntxt:text\r\n");
+        assert_eq!(
+            events,
+            vec!["map 1", "array 1", "int 1", "end", "data text", "end"]
+        );
+        let (_, events) = sink_events(b"!3\r\nERR\r\n");
+        assert_eq!(events, vec!["error ERR"]);
+    }
+
+    #[test]
+    fn parse_with_sink_incomplete() {
+        let mut sink = RecordingSink::default();
+        let err = parse_with_sink(b"*2\r\n$3\r\nfo", &mut sink).unwrap_err();
+        assert!(err.is_io_error());
+        assert_eq!(sink.0, vec!["array 2"]);
+
+        let err = parse_with_sink(b"?\r\n", &mut RecordingSink::default()).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ResponseError);
+    }
+
+    #[test]
+    fn parse_attributes() {
+        let bytes = b"*2\r\n|1\r\n+key-popularity\r\n%1\r\n$1\r\na\r\n,0.19\r\n:1\r\n:2\r\n";
+        let value = parse_redis_value(bytes).unwrap();
+        let attributed = Value::Attribute {
+            data: Box::new(Value::Int(1)),
+            attributes: vec![(
+                Value::Status("key-popularity".into()),
+                Value::Map(vec![(Value::Data(b"a".to_vec()), Value::Double(0.19))]),
+            )],
+        };
+        assert_eq!(value, Value::Bulk(vec![attributed.clone(), Value::Int(2)]));
+        assert_eq!(attributed.without_attributes(), &Value::Int(1));
+        assert_eq!(attributed.attributes().unwrap().len(), 1);
+        assert_eq!(from_redis_value::<i64>(&attributed).unwrap(), 1);
+        assert_eq!(from_redis_value::<Value>(&attributed).unwrap(), attributed);
+
+        let mut buf = Vec::new();
+        write_reply(&value, &mut buf).unwrap();
+        assert_eq!(parse_redis_value(&buf).unwrap(), value);
+
+        let (consumed, events) = sink_events(bytes);
+        assert_eq!(consumed, bytes.len());
+        assert_eq!(events, vec!["array 2", "int 1", "int 2", "end"]);
+    }
+
+    const STREAMED: &[u8] = b"*?\r\n:1\r\n$?\r\n;4\r\nHell\r\n;1\r\no\r\n;0\r\n\
+        %?\r\n+a\r\n~?\r\n.\r\n.\r\n*?\r\n.\r\n.\r\n";
+
+    fn streamed_value() -> Value {
+        Value::Bulk(vec![
+            Value::Int(1),
+            Value::Data(b"Hello".to_vec()),
+            Value::Map(vec![(Value::Status("a".into()), Value::Set(vec![]))]),
+            Value::Bulk(vec![]),
+        ])
+    }
+
+    #[test]
+    fn parse_streamed_replies() {
+        assert_eq!(parse_redis_value(STREAMED).unwrap(), streamed_value());
+        assert_eq!(
+            parse_redis_value(b"$?\r\n;0\r\n").unwrap(),
+            Value::Data(vec![])
+        );
+
+        let invalid: &[&[u8]] = &[
+            b"$?\r\n;-1\r\n",
+            b"$?\r\n:1\r\n",
+            b"%?\r\n:1\r\n.\r\n",
+            b"*?\r\n.x\r\n",
+        ];
+        for bytes in invalid {
+            let err = parse_redis_value(bytes).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::ResponseError, "{:?}", bytes);
+            let err = parse_with_sink(bytes, &mut RecordingSink::default()).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::ResponseError, "{:?}", bytes);
+        }
+
+        let limits = ParseLimits::new().max_bulk_length(4);
+        let mut parser = Parser::new();
+        parser.set_limits(limits);
+        let err = parser.parse_value(STREAMED).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ParseLimitExceeded);
+    }
+
+    #[cfg(feature = "aio")]
+    #[test]
+    fn decode_streamed_replies_incrementally() {
+        use tokio_util::codec::Decoder;
+
+        // Split the input at every byte, including inside the terminators.
+        let mut codec = ValueCodec::default();
+        let mut bytes_codec = BytesValueCodec::default();
+        let mut buffer = bytes::BytesMut::new();
+        let mut bytes_buffer = bytes::BytesMut::new();
+        for (i, &b) in STREAMED.iter().enumerate() {
+            buffer.extend_from_slice(&[b]);
+            bytes_buffer.extend_from_slice(&[b]);
+            let value = codec.decode(&mut buffer).unwrap();
+            let bytes_value = bytes_codec.decode(&mut bytes_buffer);
+            if i + 1 < STREAMED.len() {
+                assert_eq!(value, None);
+                assert!(bytes_value.unwrap().is_none());
+            } else {
+                assert_eq!(value, Some(Ok(streamed_value())));
+                // only RESP2 replies are decoded into `BytesValue`s
+                let err = bytes_value.unwrap_err();
+                assert!(err.to_string().contains("RESP3 data is not supported"));
+            }
+        }
+        fuzz_decode(STREAMED);
+    }
+
+    #[test]
+    fn parse_with_sink_streamed() {
+        let (consumed, events) = sink_events(STREAMED);
+        assert_eq!(consumed, STREAMED.len());
+        assert_eq!(
+            events,
+            vec![
+                "array 4",
+                "int 1",
+                "data Hello",
+                "map 1",
+                "status a",
+                "array 0",
+                "end",
+                "end",
+                "array 0",
+                "end",
+                "end",
+            ]
+        );
+
+        let err = parse_with_sink(
+            &STREAMED[..STREAMED.len() - 3],
+            &mut RecordingSink::default(),
+        )
+        .unwrap_err();
+        assert!(err.is_io_error());
     }
 }
@@ -1,9 +1,10 @@
 #![macro_use]
 
-use crate::cmd::{cmd, cmd_len, Cmd};
+use crate::cmd::{cmd, cmd_len, Cmd, PackedCommands};
 use crate::connection::ConnectionLike;
 use crate::types::{from_redis_value, ErrorKind, FromRedisValue, RedisResult, ToRedisArgs, Value};
 use std::collections::HashSet;
+use std::io;
 
 /// Represents a redis command pipeline.
 #[derive(Clone)]
@@ -69,30 +70,59 @@ impl Pipeline {
         self
     }
 
+    #[cfg(feature = "aio")]
+    pub(crate) fn is_transaction(&self) -> bool {
+        self.transaction_mode
+    }
+
+    #[cfg(feature = "aio")]
+    pub(crate) fn commands_mut(&mut self) -> &mut [Cmd] {
+        &mut self.commands
+    }
+
     /// Returns the encoded pipeline commands.
     pub fn get_packed_pipeline(&self) -> Vec<u8> {
         encode_pipeline(&self.commands, self.transaction_mode)
     }
 
+    // Packs the commands without copying their large arguments.
+    pub(crate) fn packed_commands(&self) -> PackedCommands<'_> {
+        let mut packed = PackedCommands::new();
+        if self.transaction_mode {
+            packed.push_packed(PACKED_MULTI);
+        }
+        for cmd in &self.commands {
+            packed.push(cmd);
+        }
+        if self.transaction_mode {
+            packed.push_packed(PACKED_EXEC);
+        }
+        packed
+    }
+
     #[cfg(feature = "aio")]
-    pub(crate) fn write_packed_pipeline(&self, out: &mut Vec<u8>) {
-        write_pipeline(out, &self.commands, self.transaction_mode)
+    pub(crate) fn write_packed_pipeline_to_bytes(&self, out: &mut bytes::BytesMut) {
+        out.reserve(pipeline_len(&self.commands, self.transaction_mode));
+        write_pipeline(
+            &mut bytes::BufMut::writer(out),
+            &self.commands,
+            self.transaction_mode,
+        )
     }
 
     fn execute_pipelined(&self, con: &mut dyn ConnectionLike) -> RedisResult<Value> {
-        Ok(self.make_pipeline_results(con.req_packed_commands(
-            &encode_pipeline(&self.commands, false),
+        let packed = self.packed_commands();
+        Ok(self.make_pipeline_results(con.req_packed_commands_vectored(
+            &mut packed.slices(),
             0,
             self.commands.len(),
         )?))
     }
 
     fn execute_transaction(&self, con: &mut dyn ConnectionLike) -> RedisResult<Value> {
-        let mut resp = con.req_packed_commands(
-            &encode_pipeline(&self.commands, true),
-            self.commands.len() + 1,
-            1,
-        )?;
+        let packed = self.packed_commands();
+        let mut resp =
+            con.req_packed_commands_vectored(&mut packed.slices(), self.commands.len() + 1, 1)?;
         match resp.pop() {
             Some(Value::Nil) => Ok(Value::Nil),
             Some(Value::Bulk(items)) => Ok(self.make_pipeline_results(items)),
@@ -206,31 +236,34 @@ impl Pipeline {
     }
 }
 
+const PACKED_MULTI: &[u8] = b"*1\r\n$5\r\nMULTI\r\n";
+const PACKED_EXEC: &[u8] = b"*1\r\n$4\r\nEXEC\r\n";
+
 fn encode_pipeline(cmds: &[Cmd], atomic: bool) -> Vec<u8> {
-    let mut rv = vec![];
+    let mut rv = Vec::with_capacity(pipeline_len(cmds, atomic));
     write_pipeline(&mut rv, cmds, atomic);
     rv
 }
 
-fn write_pipeline(rv: &mut Vec<u8>, cmds: &[Cmd], atomic: bool) {
-    let cmds_len = cmds.iter().map(cmd_len).sum();
-
+fn pipeline_len(cmds: &[Cmd], atomic: bool) -> usize {
+    let cmds_len: usize = cmds.iter().map(cmd_len).sum();
     if atomic {
-        let multi = cmd("MULTI");
-        let exec = cmd("EXEC");
-        rv.reserve(cmd_len(&multi) + cmd_len(&exec) + cmds_len);
-
-        multi.write_packed_command_preallocated(rv);
-        for cmd in cmds {
-            cmd.write_packed_command_preallocated(rv);
-        }
-        exec.write_packed_command_preallocated(rv);
+        PACKED_MULTI.len() + cmds_len + PACKED_EXEC.len()
     } else {
-        rv.reserve(cmds_len);
+        cmds_len
+    }
+}
 
-        for cmd in cmds {
-            cmd.write_packed_command_preallocated(rv);
-        }
+// The output is expected to have room for `pipeline_len` bytes already.
+fn write_pipeline(rv: &mut (impl ?Sized + io::Write), cmds: &[Cmd], atomic: bool) {
+    if atomic {
+        rv.write_all(PACKED_MULTI).unwrap();
+    }
+    for cmd in cmds {
+        cmd.write_packed_command_preallocated(rv);
+    }
+    if atomic {
+        rv.write_all(PACKED_EXEC).unwrap();
     }
 }
 
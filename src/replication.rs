@@ -0,0 +1,499 @@
+//! Follows the replication stream of a master like a replica does.
+//!
+//! [`ReplicationStream`] attaches to a master with `REPLCONF` and `PSYNC`,
+//! receives the RDB snapshot the master sends on a full resynchronization
+//! and then returns the write commands the master propagates to its
+//! replicas, the building block for change data capture pipelines and cache
+//! warmers.
+//!
+//! ```rust,no_run
+//! use redis::replication::{ReplicationConfig, ReplicationStream};
+//!
+//! # fn do_something() -> redis::RedisResult<()> {
+//! let client = redis::Client::open("redis://127.0.0.1/")?;
+//! let mut stream = ReplicationStream::open(&client, ReplicationConfig::default())?;
+//! let mut snapshot = std::fs::File::create("dump.rdb")?;
+//! stream.read_rdb(&mut snapshot)?;
+//! loop {
+//!     let command = stream.next_command()?;
+//!     println!("db {}: {}", command.db, command.name());
+//! }
+//! # }
+//! ```
+//!
+//! The master lists the stream as one of its replicas and counts it for
+//! `WAIT`.  The stream acknowledges the offset it has read every second
+//! while commands are read, and whenever the master asks for it.
+use std::io::{self, Read, Write};
+use std::time::{Duration, Instant};
+
+use crate::client::Client;
+use crate::cmd::cmd;
+use crate::connection::RawStream;
+use crate::parser::{ParserSink, ResumableParse};
+use crate::types::{ErrorKind, RedisError, RedisResult};
+
+// How often the read offset is acknowledged to the master.
+const ACK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How a [`ReplicationStream`] attaches to the master.
+#[derive(Clone, Debug, Default)]
+pub struct ReplicationConfig {
+    listening_port: u16,
+    resume: Option<(String, u64)>,
+}
+
+impl ReplicationConfig {
+    /// Sets the port the master reports for the replica in `INFO
+    /// replication` (default is 0).  Nothing has to listen on it.
+    pub fn listening_port(mut self, port: u16) -> Self {
+        self.listening_port = port;
+        self
+    }
+
+    /// Asks the master to continue after the offset of the replication id,
+    /// as returned by an earlier stream, instead of sending a snapshot.  The
+    /// master falls back to a full resynchronization if its backlog does
+    /// not reach back to the offset.
+    pub fn resume<S: Into<String>>(mut self, replication_id: S, offset: u64) -> Self {
+        self.resume = Some((replication_id.into(), offset));
+        self
+    }
+}
+
+/// How the master answered `PSYNC`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SyncMode {
+    /// The master sends an RDB snapshot followed by the commands after it.
+    Full,
+    /// The master continues after the offset to resume from, without a
+    /// snapshot.
+    Partial,
+}
+
+/// A command propagated by the master.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReplicatedCommand {
+    /// The database the command applies to, as selected by the `SELECT`s
+    /// of the stream.
+    pub db: i64,
+    /// The name and the arguments of the command.
+    pub args: Vec<Vec<u8>>,
+    /// The replication offset after the command.
+    pub offset: u64,
+}
+
+impl ReplicatedCommand {
+    /// Returns the name of the command in upper case.
+    pub fn name(&self) -> String {
+        match self.args.first() {
+            Some(name) => String::from_utf8_lossy(name).to_ascii_uppercase(),
+            None => String::new(),
+        }
+    }
+}
+
+// The snapshot still to be read.
+enum Rdb {
+    // announced with its length
+    Length(u64),
+    // streamed by a diskless master, ending with the mark
+    Delimited(Vec<u8>),
+}
+
+/// The replication stream of a master, see the [module](self) docs.
+pub struct ReplicationStream {
+    stream: Box<dyn RawStream>,
+    buf: Vec<u8>,
+    // the command read so far, kept while the rest of it is read
+    parse: ResumableParse,
+    command: CommandSink,
+    mode: SyncMode,
+    rdb: Option<Rdb>,
+    replication_id: String,
+    offset: u64,
+    db: i64,
+    last_ack: Instant,
+}
+
+impl ReplicationStream {
+    /// Connects to the master as a replica and starts the synchronization.
+    /// On a full resynchronization this waits until the master has started
+    /// to send the snapshot.
+    pub fn open(client: &Client, config: ReplicationConfig) -> RedisResult<ReplicationStream> {
+        let mut con = client.get_connection()?;
+        cmd("REPLCONF")
+            .arg("listening-port")
+            .arg(config.listening_port)
+            .query::<()>(&mut con)?;
+        cmd("REPLCONF")
+            .arg("capa")
+            .arg("eof")
+            .arg("capa")
+            .arg("psync2")
+            .query::<()>(&mut con)?;
+        let mut psync = cmd("PSYNC");
+        match config.resume {
+            Some((ref replication_id, offset)) => psync.arg(replication_id).arg(offset + 1),
+            None => psync.arg("?").arg(-1),
+        };
+        con.send_packed_command(&psync.get_packed_command())?;
+        ReplicationStream::start(con.into_stream(), config.resume)
+    }
+
+    // Reads the answer to `PSYNC` and the header of the snapshot.
+    fn start(
+        stream: Box<dyn RawStream>,
+        resume: Option<(String, u64)>,
+    ) -> RedisResult<ReplicationStream> {
+        let mut rv = ReplicationStream {
+            stream,
+            buf: Vec::new(),
+            parse: ResumableParse::default(),
+            command: CommandSink::default(),
+            mode: SyncMode::Full,
+            rdb: None,
+            replication_id: String::new(),
+            offset: 0,
+            db: 0,
+            last_ack: Instant::now(),
+        };
+        let line = rv.read_line()?;
+        let mut words = line.split(' ');
+        match words.next() {
+            Some("+FULLRESYNC") => {
+                match (words.next(), words.next().map(str::parse)) {
+                    (Some(replication_id), Some(Ok(offset))) => {
+                        rv.replication_id = replication_id.to_string();
+                        rv.offset = offset;
+                    }
+                    _ => fail!((ErrorKind::ResponseError, "Invalid FULLRESYNC reply", line)),
+                }
+                rv.rdb = Some(rv.read_rdb_header()?);
+            }
+            Some("+CONTINUE") => {
+                let (replication_id, offset) = match resume {
+                    Some(resume) => resume,
+                    None => fail!((ErrorKind::ResponseError, "Unexpected CONTINUE reply")),
+                };
+                rv.mode = SyncMode::Partial;
+                rv.replication_id = words.next().map_or(replication_id, str::to_string);
+                rv.offset = offset;
+            }
+            _ => fail!((ErrorKind::ResponseError, "PSYNC was refused", line)),
+        }
+        Ok(rv)
+    }
+
+    /// Returns whether the master sends a snapshot.
+    pub fn mode(&self) -> SyncMode {
+        self.mode
+    }
+
+    /// Returns the replication id of the master, to resume the stream later.
+    pub fn replication_id(&self) -> &str {
+        &self.replication_id
+    }
+
+    /// Returns the replication offset of the last command read, to resume
+    /// the stream later.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Copies the RDB snapshot of a full resynchronization to `out` and
+    /// returns its size.  There is nothing to copy on a partial
+    /// resynchronization or once the snapshot was read.
+    pub fn read_rdb<W: Write + ?Sized>(&mut self, out: &mut W) -> RedisResult<u64> {
+        let size = match self.rdb.take() {
+            None => return Ok(0),
+            Some(Rdb::Length(len)) => {
+                let mut left = len;
+                while left > 0 {
+                    if self.buf.is_empty() {
+                        self.fill()?;
+                    }
+                    let n = self.buf.len().min(left as usize);
+                    out.write_all(&self.buf[..n])?;
+                    self.buf.drain(..n);
+                    left -= n as u64;
+                }
+                len
+            }
+            Some(Rdb::Delimited(mark)) => {
+                let mut size = 0;
+                loop {
+                    if let Some(end) = memchr::memmem::find(&self.buf, &mark) {
+                        out.write_all(&self.buf[..end])?;
+                        self.buf.drain(..end + mark.len());
+                        break size + end as u64;
+                    }
+                    // The end of the buffer may be the start of the mark.
+                    let n = self.buf.len().saturating_sub(mark.len() - 1);
+                    out.write_all(&self.buf[..n])?;
+                    self.buf.drain(..n);
+                    size += n as u64;
+                    self.fill()?;
+                }
+            }
+        };
+        // A diskless master waits for an acknowledgement before it sends
+        // the commands.
+        self.ack(self.offset)?;
+        Ok(size)
+    }
+
+    /// Reads the next command of the stream.  An unread snapshot is skipped.
+    ///
+    /// `SELECT` is reported through the database of the commands, and the
+    /// `REPLCONF` requests of the master are answered without being
+    /// returned.
+    pub fn next_command(&mut self) -> RedisResult<ReplicatedCommand> {
+        if self.rdb.is_some() {
+            self.read_rdb(&mut io::sink())?;
+        }
+        loop {
+            if self.last_ack.elapsed() >= ACK_INTERVAL {
+                self.ack(self.offset)?;
+            }
+            let len = match self.parse.parse(&self.buf, &mut self.command) {
+                Ok(len) => len,
+                Err(err) if is_incomplete(&err) => {
+                    self.fill()?;
+                    continue;
+                }
+                Err(err) => {
+                    self.command = CommandSink::default();
+                    return Err(err);
+                }
+            };
+            self.buf.drain(..len);
+            let before = self.offset;
+            self.offset += len as u64;
+            let args = std::mem::take(&mut self.command).args;
+            match args.first() {
+                Some(name) if name.eq_ignore_ascii_case(b"REPLCONF") => {
+                    let getack = args
+                        .get(1)
+                        .is_some_and(|arg| arg.eq_ignore_ascii_case(b"GETACK"));
+                    // The request itself is not part of the offset yet.
+                    if getack {
+                        self.ack(before)?;
+                    }
+                }
+                Some(name) if name.eq_ignore_ascii_case(b"SELECT") => {
+                    self.db = match args.get(1).map(|db| String::from_utf8_lossy(db).parse()) {
+                        Some(Ok(db)) => db,
+                        _ => fail!((ErrorKind::ResponseError, "Invalid SELECT in the stream")),
+                    };
+                }
+                Some(_) => {
+                    return Ok(ReplicatedCommand {
+                        db: self.db,
+                        args,
+                        offset: self.offset,
+                    })
+                }
+                None => {}
+            }
+        }
+    }
+
+    // Reads `$<length>` or `$EOF:<mark>`, after the newlines the master
+    // sends while it prepares the snapshot.
+    fn read_rdb_header(&mut self) -> RedisResult<Rdb> {
+        let line = self.read_line()?;
+        if let Some(mark) = line.strip_prefix("$EOF:") {
+            return Ok(Rdb::Delimited(mark.as_bytes().to_vec()));
+        }
+        match line.strip_prefix('$').map(str::parse) {
+            Some(Ok(len)) => Ok(Rdb::Length(len)),
+            _ => fail!((ErrorKind::ResponseError, "Invalid RDB header", line)),
+        }
+    }
+
+    fn read_line(&mut self) -> RedisResult<String> {
+        loop {
+            let newlines = self.buf.iter().take_while(|&&b| b == b'\n').count();
+            self.buf.drain(..newlines);
+            if let Some(end) = memchr::memmem::find(&self.buf, b"\r\n") {
+                let line = String::from_utf8_lossy(&self.buf[..end]).into_owned();
+                self.buf.drain(..end + 2);
+                return Ok(line);
+            }
+            self.fill()?;
+        }
+    }
+
+    fn fill(&mut self) -> RedisResult<()> {
+        let mut chunk = [0; 16 * 1024];
+        let n = self.stream.read(&mut chunk)?;
+        if n == 0 {
+            fail!(RedisError::from(io::Error::from(
+                io::ErrorKind::UnexpectedEof
+            )));
+        }
+        self.buf.extend_from_slice(&chunk[..n]);
+        Ok(())
+    }
+
+    fn ack(&mut self, offset: u64) -> RedisResult<()> {
+        let ack = cmd("REPLCONF").arg("ACK").arg(offset).get_packed_command();
+        self.stream.write_all(&ack)?;
+        self.last_ack = Instant::now();
+        Ok(())
+    }
+}
+
+fn is_incomplete(err: &RedisError) -> bool {
+    err.as_io_error()
+        .is_some_and(|err| err.kind() == io::ErrorKind::UnexpectedEof)
+}
+
+// Collects the arguments of a command.
+#[derive(Default)]
+struct CommandSink {
+    args: Vec<Vec<u8>>,
+}
+
+impl ParserSink for CommandSink {
+    fn on_data(&mut self, data: &[u8]) {
+        self.args.push(data.to_vec());
+    }
+
+    fn on_int(&mut self, value: i64) {
+        self.args.push(value.to_string().into_bytes());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use std::sync::{Arc, Mutex};
+
+    struct MockStream {
+        input: Cursor<Vec<u8>>,
+        output: Arc<Mutex<Vec<u8>>>,
+        // the most bytes a read returns
+        chunk: usize,
+    }
+
+    impl Read for MockStream {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = buf.len().min(self.chunk);
+            self.input.read(&mut buf[..n])
+        }
+    }
+
+    impl Write for MockStream {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.output.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn open_mock(
+        input: &[u8],
+        resume: Option<(String, u64)>,
+    ) -> (RedisResult<ReplicationStream>, Arc<Mutex<Vec<u8>>>) {
+        open_chunked_mock(input, resume, usize::MAX)
+    }
+
+    fn open_chunked_mock(
+        input: &[u8],
+        resume: Option<(String, u64)>,
+        chunk: usize,
+    ) -> (RedisResult<ReplicationStream>, Arc<Mutex<Vec<u8>>>) {
+        let output = Arc::new(Mutex::new(Vec::new()));
+        let mock = MockStream {
+            input: Cursor::new(input.to_vec()),
+            output: output.clone(),
+            chunk,
+        };
+        (ReplicationStream::start(Box::new(mock), resume), output)
+    }
+
+    #[test]
+    fn test_full_resync() {
+        let set = b"*3\r\n$3\r\nSET\r\n$1\r\nk\r\n$1\r\nv\r\n";
+        let mut input = b"+FULLRESYNC 8de1 100\r\n\n\n$5\r\nREDIS".to_vec();
+        input.extend_from_slice(b"*2\r\n$6\r\nSELECT\r\n$1\r\n1\r\n");
+        input.extend_from_slice(set);
+        input.extend_from_slice(b"*3\r\n$8\r\nREPLCONF\r\n$6\r\nGETACK\r\n$1\r\n*\r\n");
+        let (stream, output) = open_mock(&input, None);
+        let mut stream = stream.unwrap();
+        assert_eq!(stream.mode(), SyncMode::Full);
+        assert_eq!(stream.replication_id(), "8de1");
+
+        let mut rdb = Vec::new();
+        assert_eq!(stream.read_rdb(&mut rdb).unwrap(), 5);
+        assert_eq!(rdb, b"REDIS");
+
+        let command = stream.next_command().unwrap();
+        let offset = 100 + 23 + set.len() as u64;
+        assert_eq!(command.name(), "SET");
+        assert_eq!(command.db, 1);
+        assert_eq!(
+            command.args,
+            vec![b"SET".to_vec(), b"k".to_vec(), b"v".to_vec()]
+        );
+        assert_eq!(command.offset, offset);
+
+        // The stream ends after the GETACK, which is answered.
+        assert!(stream.next_command().is_err());
+        let acks = String::from_utf8(output.lock().unwrap().clone()).unwrap();
+        assert!(acks.ends_with(&format!("$3\r\nACK\r\n$3\r\n{}\r\n", offset)));
+    }
+
+    #[test]
+    fn test_diskless_snapshot() {
+        let mark = "a".repeat(40);
+        let input = format!(
+            "+FULLRESYNC 8de1 0\r\n$EOF:{0}\r\nREDIS{0}*1\r\n$4\r\nPING\r\n",
+            mark
+        );
+        let (stream, _) = open_mock(input.as_bytes(), None);
+        let mut stream = stream.unwrap();
+        let command = stream.next_command().unwrap();
+        assert_eq!(command.name(), "PING");
+        assert_eq!(command.offset, 14);
+    }
+
+    #[test]
+    fn test_partial_resync() {
+        let (stream, _) = open_mock(b"+CONTINUE 9f2c\r\n", Some(("8de1".to_string(), 42)));
+        let mut stream = stream.unwrap();
+        assert_eq!(stream.mode(), SyncMode::Partial);
+        assert_eq!(stream.replication_id(), "9f2c");
+        assert_eq!(stream.offset(), 42);
+        assert_eq!(stream.read_rdb(&mut io::sink()).unwrap(), 0);
+
+        let (stream, _) = open_mock(b"-NOMASTERLINK Can't SYNC\r\n", None);
+        assert_eq!(stream.err().unwrap().kind(), ErrorKind::ResponseError);
+    }
+
+    #[test]
+    fn test_commands_read_in_parts() {
+        let mut input = b"+CONTINUE 9f2c\r\n".to_vec();
+        input.extend_from_slice(b"*3\r\n$3\r\nSET\r\n$1\r\nk\r\n$5\r\nvalue\r\n");
+        input.extend_from_slice(b"*2\r\n$4\r\nINCR\r\n$1\r\nn\r\n");
+        let (stream, _) = open_chunked_mock(&input, Some(("8de1".to_string(), 0)), 3);
+        let mut stream = stream.unwrap();
+
+        let command = stream.next_command().unwrap();
+        assert_eq!(
+            command.args,
+            vec![b"SET".to_vec(), b"k".to_vec(), b"value".to_vec()]
+        );
+        assert_eq!(command.offset, 31);
+        let command = stream.next_command().unwrap();
+        assert_eq!(command.args, vec![b"INCR".to_vec(), b"n".to_vec()]);
+        assert_eq!(command.offset, 52);
+        assert!(stream.next_command().is_err());
+    }
+}
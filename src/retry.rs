@@ -0,0 +1,644 @@
+//! Retrying of commands which failed with a transient error.
+//!
+//! [`RetryConnection`] wraps any connection and sends a command again when
+//! it failed with an error its [`RetryPolicy`] considers retryable, waiting
+//! between the attempts.  Errors the server raises instead of executing the
+//! command, like `LOADING` or `TRYAGAIN`, are retried for every command.
+//! After an I/O error or a timeout the command may have been executed
+//! already and its reply may still arrive, so it is only retried if it is
+//! idempotent and the connection was replaced with a new one, see
+//! [`RetryConnection::reconnect_with`].  Pipelines are never retried since
+//! some of their commands may have been executed.
+//!
+//! ```rust,no_run
+//! # fn do_something() -> redis::RedisResult<()> {
+//! use redis::Commands;
+//! use redis::retry::{ExponentialBackoff, RetryConnection};
+//!
+//! let client = redis::Client::open("redis://127.0.0.1/")?;
+//! let policy = ExponentialBackoff::default().max_attempts(5);
+//! let mut con = RetryConnection::new(client.get_connection()?, policy)
+//!     .reconnect_with(move || client.get_connection());
+//! let value: Option<String> = con.get("my_key")?;
+//! # Ok(()) }
+//! ```
+use std::thread;
+use std::time::Duration;
+
+use crate::cmd::{Arg, Cmd};
+use crate::connection::ConnectionLike;
+use crate::parser::parse_redis_value;
+#[cfg(feature = "aio")]
+use crate::types::RedisFuture;
+use crate::types::{ErrorKind, RedisError, RedisResult, Value};
+
+// Commands which only read, so sending them twice does no harm.
+const READ_ONLY_COMMANDS: &[&[u8]] = &[
+    b"BITCOUNT",
+    b"BITPOS",
+    b"DBSIZE",
+    b"DUMP",
+    b"ECHO",
+    b"EXISTS",
+    b"GEODIST",
+    b"GEOHASH",
+    b"GEOPOS",
+    b"GET",
+    b"GETBIT",
+    b"GETRANGE",
+    b"HEXISTS",
+    b"HGET",
+    b"HGETALL",
+    b"HKEYS",
+    b"HLEN",
+    b"HMGET",
+    b"HSCAN",
+    b"HSTRLEN",
+    b"HVALS",
+    b"INFO",
+    b"KEYS",
+    b"LINDEX",
+    b"LLEN",
+    b"LPOS",
+    b"LRANGE",
+    b"MGET",
+    b"PFCOUNT",
+    b"PING",
+    b"PTTL",
+    b"SCAN",
+    b"SCARD",
+    b"SDIFF",
+    b"SINTER",
+    b"SISMEMBER",
+    b"SMEMBERS",
+    b"SMISMEMBER",
+    b"SRANDMEMBER",
+    b"SSCAN",
+    b"STRLEN",
+    b"SUNION",
+    b"TTL",
+    b"TYPE",
+    b"XLEN",
+    b"XRANGE",
+    b"XREVRANGE",
+    b"ZCARD",
+    b"ZCOUNT",
+    b"ZLEXCOUNT",
+    b"ZMSCORE",
+    b"ZRANGE",
+    b"ZRANGEBYLEX",
+    b"ZRANGEBYSCORE",
+    b"ZRANK",
+    b"ZREVRANGE",
+    b"ZREVRANGEBYSCORE",
+    b"ZREVRANK",
+    b"ZSCAN",
+    b"ZSCORE",
+];
+
+/// Decides which failed commands a [`RetryConnection`] sends again and how
+/// long it waits before.
+pub trait RetryPolicy {
+    /// The number of times a command is sent at most, including the first
+    /// attempt.
+    fn max_attempts(&self) -> usize;
+
+    /// The delay before the given retry, starting at 1.
+    fn delay(&self, retry: usize) -> Duration;
+
+    /// Whether errors of the given kind are retried.
+    ///
+    /// By default these are the errors of a server which is loading its
+    /// data or in the middle of a failover, I/O errors and timeouts.
+    fn is_retryable(&self, kind: ErrorKind) -> bool {
+        matches!(
+            kind,
+            ErrorKind::BusyLoadingError
+                | ErrorKind::TryAgain
+                | ErrorKind::ClusterDown
+                | ErrorKind::MasterDown
+                | ErrorKind::IoError
+                | ErrorKind::Timeout
+        )
+    }
+
+    /// Whether sending the command twice has the same effect as sending it
+    /// once, which is required to retry it after an I/O error or a timeout.
+    /// `command`
+    /// is the upper case name of the command.
+    ///
+    /// By default only commands which do not modify the data are idempotent.
+    fn is_idempotent(&self, command: &[u8]) -> bool {
+        READ_ONLY_COMMANDS.contains(&command)
+    }
+}
+
+/// A [`RetryPolicy`] which doubles the delay after every attempt.
+///
+/// By default a command is sent up to 3 times, waiting 50 milliseconds
+/// before the first retry, and at most 2 seconds.
+#[derive(Clone, Debug)]
+pub struct ExponentialBackoff {
+    max_attempts: usize,
+    initial_delay: Duration,
+    max_delay: Duration,
+}
+
+impl Default for ExponentialBackoff {
+    fn default() -> Self {
+        ExponentialBackoff {
+            max_attempts: 3,
+            initial_delay: Duration::from_millis(50),
+            max_delay: Duration::from_secs(2),
+        }
+    }
+}
+
+impl ExponentialBackoff {
+    /// Sets how often a command is sent at most, including the first
+    /// attempt.
+    pub fn max_attempts(mut self, attempts: usize) -> Self {
+        self.max_attempts = attempts;
+        self
+    }
+
+    /// Sets the delay before the first retry.
+    pub fn initial_delay(mut self, delay: Duration) -> Self {
+        self.initial_delay = delay;
+        self
+    }
+
+    /// Sets the longest delay between two attempts.
+    pub fn max_delay(mut self, delay: Duration) -> Self {
+        self.max_delay = delay;
+        self
+    }
+}
+
+impl RetryPolicy for ExponentialBackoff {
+    fn max_attempts(&self) -> usize {
+        self.max_attempts
+    }
+
+    fn delay(&self, retry: usize) -> Duration {
+        let factor = 1u32
+            .checked_shl(retry.saturating_sub(1) as u32)
+            .unwrap_or(u32::MAX);
+        self.initial_delay
+            .checked_mul(factor)
+            .map_or(self.max_delay, |delay| delay.min(self.max_delay))
+    }
+}
+
+/// A connection which retries the commands failing with a transient error
+/// as decided by its [`RetryPolicy`], see the [module docs](self).
+pub struct RetryConnection<C, P = ExponentialBackoff> {
+    con: C,
+    policy: P,
+    reconnect: Option<Reconnect<C>>,
+}
+
+// Opens the connection replacing one which failed, for instance with an
+// I/O error.
+pub(crate) enum Reconnect<C> {
+    Blocking(Box<dyn FnMut() -> RedisResult<C> + Send>),
+    #[cfg(feature = "aio")]
+    Async(Box<dyn FnMut() -> RedisFuture<'static, C> + Send>),
+}
+
+impl<C, P: RetryPolicy> RetryConnection<C, P> {
+    /// Wraps the connection.
+    pub fn new(con: C, policy: P) -> Self {
+        RetryConnection {
+            con,
+            policy,
+            reconnect: None,
+        }
+    }
+
+    /// Sets the function opening a new connection.
+    ///
+    /// After an I/O error or a timeout the reply of the failed command may
+    /// still be on its way, so it is only retried on a new connection.
+    /// Without this function these errors are never retried.  The async
+    /// connection calls the function as well, but blocks while doing so,
+    /// see [`reconnect_with_async`](Self::reconnect_with_async).
+    pub fn reconnect_with<F>(mut self, connect: F) -> Self
+    where
+        F: FnMut() -> RedisResult<C> + Send + 'static,
+    {
+        self.reconnect = Some(Reconnect::Blocking(Box::new(connect)));
+        self
+    }
+
+    /// Sets the function opening a new connection for the async
+    /// connection, see [`reconnect_with`](Self::reconnect_with).
+    ///
+    /// The blocking connection does not retry I/O errors with it.
+    #[cfg(feature = "aio")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "aio")))]
+    pub fn reconnect_with_async<F>(mut self, connect: F) -> Self
+    where
+        F: FnMut() -> RedisFuture<'static, C> + Send + 'static,
+    {
+        self.reconnect = Some(Reconnect::Async(Box::new(connect)));
+        self
+    }
+
+    /// Returns a reference to the wrapped connection.
+    pub fn get_ref(&self) -> &C {
+        &self.con
+    }
+
+    /// Returns a mutable reference to the wrapped connection.
+    pub fn get_mut(&mut self) -> &mut C {
+        &mut self.con
+    }
+
+    /// Returns the wrapped connection.
+    pub fn into_inner(self) -> C {
+        self.con
+    }
+
+    // The delay before sending the command again after the given number of
+    // failed attempts, `None` if it is not retried.
+    fn retry_delay(
+        &self,
+        attempts: usize,
+        err: &RedisError,
+        command: Option<&[u8]>,
+        can_reconnect: bool,
+    ) -> Option<Duration> {
+        if attempts >= self.policy.max_attempts() || !self.policy.is_retryable(err.kind()) {
+            return None;
+        }
+        if needs_reconnect(err)
+            && (!can_reconnect
+                || !command.is_some_and(|command| self.policy.is_idempotent(command)))
+        {
+            return None;
+        }
+        Some(self.policy.delay(attempts))
+    }
+}
+
+// Whether the connection may be out of sync after the error, because the
+// reply of the command may still arrive.
+fn needs_reconnect(err: &RedisError) -> bool {
+    err.kind() == ErrorKind::IoError || err.is_timeout()
+}
+
+fn command_name(cmd: &Cmd) -> Option<Vec<u8>> {
+    match cmd.args_iter().next() {
+        Some(Arg::Simple(name)) => Some(name.to_ascii_uppercase()),
+        _ => None,
+    }
+}
+
+fn packed_command_name(cmd: &[u8]) -> Option<Vec<u8>> {
+    match parse_redis_value(cmd) {
+        Ok(Value::Bulk(items)) => match items.first() {
+            Some(Value::Data(name)) => Some(name.to_ascii_uppercase()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+impl<C: ConnectionLike, P: RetryPolicy> RetryConnection<C, P> {
+    fn request<F>(&mut self, command: Option<&[u8]>, mut send: F) -> RedisResult<Value>
+    where
+        F: FnMut(&mut C) -> RedisResult<Value>,
+    {
+        let can_reconnect = matches!(self.reconnect, Some(Reconnect::Blocking(_)));
+        let mut attempts = 0;
+        let mut reconnect = false;
+        loop {
+            attempts += 1;
+            let result = if reconnect {
+                self.reconnect().map(|()| reconnect = false)
+            } else {
+                Ok(())
+            };
+            match result.and_then(|()| send(&mut self.con)) {
+                Err(err) => match self.retry_delay(attempts, &err, command, can_reconnect) {
+                    Some(delay) => {
+                        reconnect |= needs_reconnect(&err);
+                        thread::sleep(delay)
+                    }
+                    None => return Err(err),
+                },
+                result => return result,
+            }
+        }
+    }
+
+    fn reconnect(&mut self) -> RedisResult<()> {
+        match &mut self.reconnect {
+            Some(Reconnect::Blocking(connect)) => {
+                self.con = connect()?;
+                Ok(())
+            }
+            _ => unreachable!("I/O errors are only retried after reconnecting"),
+        }
+    }
+}
+
+impl<C: ConnectionLike, P: RetryPolicy> ConnectionLike for RetryConnection<C, P> {
+    fn req_packed_command(&mut self, cmd: &[u8]) -> RedisResult<Value> {
+        let command = packed_command_name(cmd);
+        self.request(command.as_deref(), |con| con.req_packed_command(cmd))
+    }
+
+    fn req_packed_commands(
+        &mut self,
+        cmd: &[u8],
+        offset: usize,
+        count: usize,
+    ) -> RedisResult<Vec<Value>> {
+        self.con.req_packed_commands(cmd, offset, count)
+    }
+
+    fn req_packed_commands_vectored(
+        &mut self,
+        cmds: &mut [&[u8]],
+        offset: usize,
+        count: usize,
+    ) -> RedisResult<Vec<Value>> {
+        self.con.req_packed_commands_vectored(cmds, offset, count)
+    }
+
+    fn req_command(&mut self, cmd: &Cmd) -> RedisResult<Value> {
+        let command = command_name(cmd);
+        self.request(command.as_deref(), |con| con.req_command(cmd))
+    }
+
+    fn get_db(&self) -> i64 {
+        self.con.get_db()
+    }
+
+    fn supports_pipelining(&self) -> bool {
+        self.con.supports_pipelining()
+    }
+
+    fn check_connection(&mut self) -> bool {
+        self.con.check_connection()
+    }
+
+    fn is_open(&self) -> bool {
+        self.con.is_open()
+    }
+}
+
+#[cfg(feature = "aio")]
+impl<C, P> crate::aio::ConnectionLike for RetryConnection<C, P>
+where
+    C: crate::aio::ConnectionLike + Send,
+    P: RetryPolicy + Send,
+{
+    fn req_packed_command<'a>(&'a mut self, cmd: &'a Cmd) -> crate::RedisFuture<'a, Value> {
+        Box::pin(async move {
+            let command = command_name(cmd);
+            let can_reconnect = self.reconnect.is_some();
+            let mut attempts = 0;
+            let mut reconnect = false;
+            loop {
+                attempts += 1;
+                let result = if reconnect {
+                    match &mut self.reconnect {
+                        Some(Reconnect::Blocking(connect)) => connect(),
+                        Some(Reconnect::Async(connect)) => connect().await,
+                        None => unreachable!("I/O errors are only retried after reconnecting"),
+                    }
+                    .map(|con| {
+                        self.con = con;
+                        reconnect = false;
+                    })
+                } else {
+                    Ok(())
+                };
+                let result = match result {
+                    Ok(()) => self.con.req_packed_command(cmd).await,
+                    Err(err) => Err(err),
+                };
+                match result {
+                    Err(err) => {
+                        match self.retry_delay(attempts, &err, command.as_deref(), can_reconnect) {
+                            Some(delay) => {
+                                reconnect |= needs_reconnect(&err);
+                                crate::aio::Runtime::locate().sleep(delay).await
+                            }
+                            None => return Err(err),
+                        }
+                    }
+                    result => return result,
+                }
+            }
+        })
+    }
+
+    fn req_packed_commands<'a>(
+        &'a mut self,
+        cmd: &'a crate::Pipeline,
+        offset: usize,
+        count: usize,
+    ) -> crate::RedisFuture<'a, Vec<Value>> {
+        self.con.req_packed_commands(cmd, offset, count)
+    }
+
+    fn get_db(&self) -> i64 {
+        self.con.get_db()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+    use std::io;
+
+    // Fails with the given errors before it answers `OK`.
+    struct Flaky {
+        errors: Vec<RedisError>,
+        requests: usize,
+    }
+
+    impl Flaky {
+        fn new(errors: Vec<RedisError>) -> Self {
+            Flaky {
+                errors,
+                requests: 0,
+            }
+        }
+    }
+
+    impl ConnectionLike for Flaky {
+        fn req_packed_command(&mut self, _cmd: &[u8]) -> RedisResult<Value> {
+            self.requests += 1;
+            match self.errors.pop() {
+                Some(err) => Err(err),
+                None => Ok(Value::Okay),
+            }
+        }
+
+        fn req_packed_commands(
+            &mut self,
+            _cmd: &[u8],
+            _offset: usize,
+            _count: usize,
+        ) -> RedisResult<Vec<Value>> {
+            unimplemented!()
+        }
+
+        fn get_db(&self) -> i64 {
+            0
+        }
+
+        fn check_connection(&mut self) -> bool {
+            true
+        }
+
+        fn is_open(&self) -> bool {
+            true
+        }
+    }
+
+    fn policy() -> ExponentialBackoff {
+        ExponentialBackoff::default().initial_delay(Duration::from_millis(1))
+    }
+
+    fn loading() -> RedisError {
+        RedisError::from((ErrorKind::BusyLoadingError, "Loading"))
+    }
+
+    fn reset() -> RedisError {
+        RedisError::from(io::Error::from(io::ErrorKind::ConnectionReset))
+    }
+
+    #[test]
+    fn test_retries_transient_errors() {
+        let mut con = RetryConnection::new(Flaky::new(vec![loading(), loading()]), policy());
+        assert_eq!(crate::cmd("SET").arg("k").arg(1).query(&mut con), Ok(()));
+        assert_eq!(con.get_ref().requests, 3);
+
+        let errors = vec![loading(), loading(), loading()];
+        let mut con = RetryConnection::new(Flaky::new(errors), policy());
+        let err = crate::cmd("SET").arg("k").arg(1).query::<()>(&mut con);
+        assert_eq!(err.unwrap_err().kind(), ErrorKind::BusyLoadingError);
+        assert_eq!(con.get_ref().requests, 3);
+
+        let errors = vec![RedisError::from((ErrorKind::ResponseError, "Error"))];
+        let mut con = RetryConnection::new(Flaky::new(errors), policy());
+        assert!(crate::cmd("GET").arg("k").query::<()>(&mut con).is_err());
+        assert_eq!(con.get_ref().requests, 1);
+    }
+
+    fn reconnecting(errors: Vec<RedisError>) -> RetryConnection<Flaky> {
+        RetryConnection::new(Flaky::new(errors), policy())
+            .reconnect_with(|| Ok(Flaky::new(Vec::new())))
+    }
+
+    #[test]
+    fn test_io_errors_need_idempotent_commands() {
+        let mut con = reconnecting(vec![reset()]);
+        assert_eq!(crate::cmd("get").arg("k").query(&mut con), Ok(()));
+        // the command was sent again on a new connection
+        assert_eq!(con.get_ref().requests, 1);
+
+        let mut con = reconnecting(vec![reset()]);
+        assert!(crate::cmd("INCR").arg("k").query::<()>(&mut con).is_err());
+        assert_eq!(con.get_ref().requests, 1);
+
+        // packed commands are checked as well
+        let mut con = reconnecting(vec![reset()]);
+        let packed = crate::cmd("MGET").arg("a").arg("b").get_packed_command();
+        assert_eq!(con.req_packed_command(&packed), Ok(Value::Okay));
+        assert_eq!(con.get_ref().requests, 1);
+
+        // without a new connection I/O errors are not retried
+        let mut con = RetryConnection::new(Flaky::new(vec![reset()]), policy());
+        assert!(crate::cmd("GET").arg("k").query::<()>(&mut con).is_err());
+        assert_eq!(con.get_ref().requests, 1);
+    }
+
+    // Answers every command with its last argument, but the first `timeouts`
+    // reads time out and leave their reply unread, like a slow server.
+    struct Lagging {
+        replies: VecDeque<Value>,
+        timeouts: usize,
+    }
+
+    impl ConnectionLike for Lagging {
+        fn req_packed_command(&mut self, cmd: &[u8]) -> RedisResult<Value> {
+            let reply = match parse_redis_value(cmd) {
+                Ok(Value::Bulk(mut args)) => args.pop().unwrap(),
+                _ => unreachable!(),
+            };
+            self.replies.push_back(reply);
+            if self.timeouts > 0 {
+                self.timeouts -= 1;
+                return Err(io::Error::from(io::ErrorKind::WouldBlock).into());
+            }
+            Ok(self.replies.pop_front().unwrap())
+        }
+
+        fn req_packed_commands(
+            &mut self,
+            _cmd: &[u8],
+            _offset: usize,
+            _count: usize,
+        ) -> RedisResult<Vec<Value>> {
+            unimplemented!()
+        }
+
+        fn get_db(&self) -> i64 {
+            0
+        }
+
+        fn check_connection(&mut self) -> bool {
+            true
+        }
+
+        fn is_open(&self) -> bool {
+            true
+        }
+    }
+
+    fn lagging(timeouts: usize) -> Lagging {
+        Lagging {
+            replies: VecDeque::new(),
+            timeouts,
+        }
+    }
+
+    #[test]
+    fn test_timeouts_keep_replies_aligned() {
+        // retrying on the same connection would read the stale reply
+        let mut con = RetryConnection::new(lagging(1), policy());
+        let err = crate::cmd("GET").arg("a").query::<String>(&mut con);
+        assert!(err.unwrap_err().is_timeout());
+        assert_eq!(con.get_ref().replies.len(), 1);
+
+        let mut con = RetryConnection::new(lagging(1), policy()).reconnect_with(|| Ok(lagging(0)));
+        assert_eq!(
+            crate::cmd("GET").arg("a").query(&mut con),
+            Ok("a".to_string())
+        );
+        assert_eq!(
+            crate::cmd("GET").arg("b").query(&mut con),
+            Ok("b".to_string())
+        );
+        assert!(con.get_ref().replies.is_empty());
+    }
+
+    #[test]
+    fn test_exponential_backoff() {
+        let policy = ExponentialBackoff::default()
+            .initial_delay(Duration::from_millis(10))
+            .max_delay(Duration::from_millis(50));
+        assert_eq!(policy.delay(1), Duration::from_millis(10));
+        assert_eq!(policy.delay(3), Duration::from_millis(40));
+        assert_eq!(policy.delay(4), Duration::from_millis(50));
+        assert_eq!(policy.delay(100), Duration::from_millis(50));
+    }
+}
@@ -0,0 +1,830 @@
+//! Defines a client which finds the nodes of a service through redis sentinels.
+//!
+//! The sentinels are asked for the current master of the service every
+//! time a connection is opened, so a new connection always goes to the
+//! master elected by the last failover.  [`SentinelConnection`] does this
+//! transparently by reconnecting when its master goes away, and it listens
+//! to the `+switch-master` events of the sentinels to move to the new master
+//! as soon as a failover completed.
+//!
+//! ```rust,no_run
+//! use redis::Commands;
+//! use redis::sentinel::SentinelClient;
+//!
+//! # fn do_something() -> redis::RedisResult<()> {
+//! let client = SentinelClient::open(
+//!     vec!["redis://127.0.0.1:26379/", "redis://127.0.0.1:26380/"],
+//!     "mymaster",
+//! )?;
+//! let mut con = client.get_connection()?;
+//! let _: () = con.set("key", 42)?;
+//! # Ok(()) }
+//! ```
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Weak};
+use std::thread;
+use std::time::Duration;
+
+use crate::client::Client;
+use crate::cmd::{cmd, Cmd};
+use crate::connection::{
+    Connection, ConnectionAddr, ConnectionInfo, ConnectionLike, IntoConnectionInfo, Msg,
+    RedisConnectionInfo,
+};
+use crate::tls::TlsCertificates;
+use crate::types::{from_redis_value, ErrorKind, RedisError, RedisResult, Value};
+
+#[cfg(feature = "aio")]
+use crate::aio::MultiplexedConnection;
+#[cfg(feature = "aio")]
+use crate::types::RedisFuture;
+#[cfg(feature = "aio")]
+use futures_util::future::FutureExt;
+
+// The channel on which the sentinels announce a new master.
+const SWITCH_MASTER: &str = "+switch-master";
+
+// How often the failover watchers check whether their connection was dropped.
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Used to configure and build a [SentinelClient](SentinelClient).
+pub struct SentinelClientBuilder {
+    sentinels: RedisResult<Vec<ConnectionInfo>>,
+    service_name: String,
+    node_info: RedisConnectionInfo,
+    certificates: Option<TlsCertificates>,
+}
+
+impl SentinelClientBuilder {
+    /// Generate the base configuration for a new client of the service
+    /// monitored under `service_name`.
+    pub fn new<T: IntoConnectionInfo, S: Into<String>>(
+        sentinels: Vec<T>,
+        service_name: S,
+    ) -> SentinelClientBuilder {
+        SentinelClientBuilder {
+            sentinels: sentinels
+                .into_iter()
+                .map(|x| x.into_connection_info())
+                .collect(),
+            service_name: service_name.into(),
+            node_info: RedisConnectionInfo::default(),
+            certificates: None,
+        }
+    }
+
+    /// Set the database, credentials and protocol used for the connections
+    /// to the master and replicas.  The sentinels use the ones of their own
+    /// connection info.
+    pub fn node_connection_info(mut self, info: RedisConnectionInfo) -> SentinelClientBuilder {
+        self.node_info = info;
+        self
+    }
+
+    /// Set the TLS certificates used for the connections to the sentinels
+    /// and, through them, to the master and replicas.  The sentinels must be
+    /// given as `rediss://` addresses.
+    pub fn tls_certificates(mut self, certificates: TlsCertificates) -> SentinelClientBuilder {
+        self.certificates = Some(certificates);
+        self
+    }
+
+    /// Builds a [SentinelClient](SentinelClient).  This does not connect to
+    /// the sentinels yet.
+    ///
+    /// # Errors
+    ///
+    /// Upon failure to parse the sentinels or if there are none, an error is
+    /// returned.
+    pub fn open(self) -> RedisResult<SentinelClient> {
+        let mut sentinels = self.sentinels?;
+        if sentinels.is_empty() {
+            fail!((ErrorKind::InvalidClientConfig, "No sentinel was given"));
+        }
+        if let Some(certificates) = self.certificates {
+            for sentinel in sentinels.iter_mut() {
+                certificates.apply(sentinel)?;
+            }
+        }
+        Ok(SentinelClient {
+            sentinels: Arc::new(sentinels),
+            service_name: self.service_name,
+            node_info: self.node_info,
+            next_replica: Arc::new(AtomicUsize::new(0)),
+        })
+    }
+}
+
+/// This is a client of a service monitored by redis sentinels.
+#[derive(Clone)]
+pub struct SentinelClient {
+    sentinels: Arc<Vec<ConnectionInfo>>,
+    service_name: String,
+    node_info: RedisConnectionInfo,
+    next_replica: Arc<AtomicUsize>,
+}
+
+impl SentinelClient {
+    /// Create a [SentinelClient](SentinelClient) with the default
+    /// configuration.  This does not connect to the sentinels yet.
+    pub fn open<T: IntoConnectionInfo, S: Into<String>>(
+        sentinels: Vec<T>,
+        service_name: S,
+    ) -> RedisResult<SentinelClient> {
+        SentinelClientBuilder::new(sentinels, service_name).open()
+    }
+
+    /// Returns the name of the service.
+    pub fn service_name(&self) -> &str {
+        &self.service_name
+    }
+
+    /// Asks the sentinels in order for the address of the current master.
+    pub fn master_info(&self) -> RedisResult<ConnectionInfo> {
+        self.try_sentinels(|sentinel| {
+            let mut con = Client::open(sentinel.clone())?.get_connection()?;
+            let reply = master_addr_cmd(&self.service_name).query(&mut con)?;
+            self.master_from_reply(sentinel, reply)
+        })
+    }
+
+    /// Asks the sentinels in order for the addresses of the replicas of
+    /// the master which are not known to be down.
+    pub fn replica_infos(&self) -> RedisResult<Vec<ConnectionInfo>> {
+        self.try_sentinels(|sentinel| {
+            let mut con = Client::open(sentinel.clone())?.get_connection()?;
+            let reply = replicas_cmd(&self.service_name).query(&mut con)?;
+            self.replicas_from_reply(sentinel, reply)
+        })
+    }
+
+    /// Opens a connection to the current master.
+    ///
+    /// The master reported by each sentinel is checked with `ROLE`, so a
+    /// sentinel which has not noticed a failover yet is skipped.
+    pub fn get_connection(&self) -> RedisResult<Connection> {
+        self.try_sentinels(|sentinel| {
+            let mut con = Client::open(sentinel.clone())?.get_connection()?;
+            let reply = master_addr_cmd(&self.service_name).query(&mut con)?;
+            let master = self.master_from_reply(sentinel, reply)?;
+            let mut con = Client::open(master)?.get_connection()?;
+            check_master(cmd("ROLE").query(&mut con)?)?;
+            Ok(con)
+        })
+    }
+
+    /// Opens a connection to one of the replicas, using them in turn, or to
+    /// the master if no replica can be reached.
+    pub fn get_replica_connection(&self) -> RedisResult<Connection> {
+        let replicas = self.replica_infos()?;
+        for info in self.rotate(replicas) {
+            if let Ok(con) = Client::open(info)?.get_connection() {
+                return Ok(con);
+            }
+        }
+        self.get_connection()
+    }
+
+    /// Returns a connection to the master which reconnects to the new
+    /// master after a failover.
+    pub fn get_sentinel_connection(&self) -> RedisResult<SentinelConnection> {
+        let con = self.get_connection()?;
+        let switched = Arc::new(AtomicBool::new(false));
+        let client = self.clone();
+        let watched = Arc::downgrade(&switched);
+        thread::spawn(move || watch_failovers(client, watched));
+        Ok(SentinelConnection {
+            con: Some(con),
+            client: self.clone(),
+            switched,
+        })
+    }
+
+    /// Asks the sentinels in order for the address of the current master.
+    #[cfg(feature = "aio")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "aio")))]
+    pub async fn master_info_async(&self) -> RedisResult<ConnectionInfo> {
+        let mut last_err = None;
+        for sentinel in self.sentinels.iter() {
+            let result = async {
+                let mut con = Client::open(sentinel.clone())?
+                    .get_async_connection()
+                    .await?;
+                let reply = master_addr_cmd(&self.service_name)
+                    .query_async(&mut con)
+                    .await?;
+                self.master_from_reply(sentinel, reply)
+            }
+            .await;
+            match result {
+                Ok(info) => return Ok(info),
+                Err(err) => last_err = Some(err),
+            }
+        }
+        Err(last_err.unwrap())
+    }
+
+    /// Asks the sentinels in order for the addresses of the replicas of
+    /// the master which are not known to be down.
+    #[cfg(feature = "aio")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "aio")))]
+    pub async fn replica_infos_async(&self) -> RedisResult<Vec<ConnectionInfo>> {
+        let mut last_err = None;
+        for sentinel in self.sentinels.iter() {
+            let result = async {
+                let mut con = Client::open(sentinel.clone())?
+                    .get_async_connection()
+                    .await?;
+                let reply = replicas_cmd(&self.service_name)
+                    .query_async(&mut con)
+                    .await?;
+                self.replicas_from_reply(sentinel, reply)
+            }
+            .await;
+            match result {
+                Ok(infos) => return Ok(infos),
+                Err(err) => last_err = Some(err),
+            }
+        }
+        Err(last_err.unwrap())
+    }
+
+    /// Opens a multiplexed connection to the current master, see
+    /// `get_connection`.
+    #[cfg(feature = "aio")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "aio")))]
+    pub async fn get_multiplexed_async_connection(&self) -> RedisResult<MultiplexedConnection> {
+        let mut last_err = None;
+        for sentinel in self.sentinels.iter() {
+            let result = async {
+                let mut con = Client::open(sentinel.clone())?
+                    .get_async_connection()
+                    .await?;
+                let reply = master_addr_cmd(&self.service_name)
+                    .query_async(&mut con)
+                    .await?;
+                let master = self.master_from_reply(sentinel, reply)?;
+                let mut con = Client::open(master)?
+                    .get_multiplexed_async_connection()
+                    .await?;
+                check_master(cmd("ROLE").query_async(&mut con).await?)?;
+                Ok(con)
+            }
+            .await;
+            match result {
+                Ok(con) => return Ok(con),
+                Err(err) => last_err = Some(err),
+            }
+        }
+        Err(last_err.unwrap())
+    }
+
+    /// Opens a multiplexed connection to one of the replicas, see
+    /// `get_replica_connection`.
+    #[cfg(feature = "aio")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "aio")))]
+    pub async fn get_multiplexed_async_replica_connection(
+        &self,
+    ) -> RedisResult<MultiplexedConnection> {
+        let replicas = self.replica_infos_async().await?;
+        for info in self.rotate(replicas) {
+            if let Ok(con) = Client::open(info)?.get_multiplexed_async_connection().await {
+                return Ok(con);
+            }
+        }
+        self.get_multiplexed_async_connection().await
+    }
+
+    /// Returns a multiplexed connection to the master which reconnects to
+    /// the new master after a failover.
+    #[cfg(feature = "aio")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "aio")))]
+    pub async fn get_async_sentinel_connection(&self) -> RedisResult<AsyncSentinelConnection> {
+        let con = self.get_multiplexed_async_connection().await?;
+        let switched = Arc::new(AtomicBool::new(false));
+        crate::aio::Runtime::locate().spawn(watch_failovers_async(
+            self.clone(),
+            Arc::downgrade(&switched),
+        ));
+        Ok(AsyncSentinelConnection {
+            con: Some(con),
+            client: self.clone(),
+            switched,
+        })
+    }
+
+    fn try_sentinels<T, F>(&self, mut f: F) -> RedisResult<T>
+    where
+        F: FnMut(&ConnectionInfo) -> RedisResult<T>,
+    {
+        let mut last_err = None;
+        for sentinel in self.sentinels.iter() {
+            match f(sentinel) {
+                Ok(rv) => return Ok(rv),
+                Err(err) => last_err = Some(err),
+            }
+        }
+        Err(last_err.unwrap())
+    }
+
+    // Starts with a different replica on every call to spread the reads.
+    fn rotate(&self, mut replicas: Vec<ConnectionInfo>) -> Vec<ConnectionInfo> {
+        if !replicas.is_empty() {
+            let start = self.next_replica.fetch_add(1, Ordering::Relaxed) % replicas.len();
+            replicas.rotate_left(start);
+        }
+        replicas
+    }
+
+    fn master_from_reply(
+        &self,
+        sentinel: &ConnectionInfo,
+        reply: Value,
+    ) -> RedisResult<ConnectionInfo> {
+        if reply == Value::Nil {
+            fail!((
+                ErrorKind::InvalidClientConfig,
+                "Service is not monitored by the sentinel",
+                self.service_name.clone()
+            ));
+        }
+        let (host, port) = reply.into_sentinel_master_addr()?;
+        Ok(self.node_info(sentinel, host, port))
+    }
+
+    fn replicas_from_reply(
+        &self,
+        sentinel: &ConnectionInfo,
+        reply: Value,
+    ) -> RedisResult<Vec<ConnectionInfo>> {
+        let replicas: Vec<std::collections::HashMap<String, String>> = from_redis_value(&reply)?;
+        let mut infos = Vec::new();
+        for replica in replicas {
+            let is_down = replica.get("flags").is_some_and(|flags| {
+                flags
+                    .split(',')
+                    .any(|flag| matches!(flag, "s_down" | "o_down" | "disconnected"))
+            });
+            if is_down {
+                continue;
+            }
+            match (replica.get("ip"), replica.get("port")) {
+                (Some(host), Some(port)) => {
+                    let port = port.parse().map_err(|_| {
+                        RedisError::from((
+                            ErrorKind::TypeError,
+                            "Invalid replica port",
+                            port.clone(),
+                        ))
+                    })?;
+                    infos.push(self.node_info(sentinel, host.clone(), port));
+                }
+                _ => fail!((ErrorKind::TypeError, "Replica entry without an address")),
+            }
+        }
+        Ok(infos)
+    }
+
+    // The nodes are reached over TLS, with the same certificates, if the
+    // sentinel is, and through its proxy with its TCP settings.
+    fn node_info(&self, sentinel: &ConnectionInfo, host: String, port: u16) -> ConnectionInfo {
+        let addr = match sentinel.addr {
+            ConnectionAddr::TcpTls {
+                insecure,
+                ref certificates,
+                ..
+            } => ConnectionAddr::TcpTls {
+                host,
+                port,
+                insecure,
+                certificates: certificates.clone(),
+            },
+            _ => ConnectionAddr::Tcp(host, port),
+        };
+        ConnectionInfo {
+            addr,
+            redis: self.node_info.clone(),
+            proxy: sentinel.proxy.clone(),
+            tcp_settings: sentinel.tcp_settings,
+        }
+    }
+}
+
+fn master_addr_cmd(service_name: &str) -> Cmd {
+    let mut cmd = cmd("SENTINEL");
+    cmd.arg("GET-MASTER-ADDR-BY-NAME").arg(service_name);
+    cmd
+}
+
+fn replicas_cmd(service_name: &str) -> Cmd {
+    let mut cmd = cmd("SENTINEL");
+    cmd.arg("REPLICAS").arg(service_name);
+    cmd
+}
+
+// Checks the reply of `ROLE`, whose first element is the role of the node.
+fn check_master(role: Value) -> RedisResult<()> {
+    match role.as_sequence() {
+        Some([role, ..]) if from_redis_value::<String>(role)? == "master" => Ok(()),
+        _ => fail!((
+            ErrorKind::ReadOnly,
+            "The node reported by the sentinel is not a master"
+        )),
+    }
+}
+
+// Whether the message is a `+switch-master <name> <old ip> <old port> <new ip>
+// <new port>` event of the service.
+fn is_switch_of(msg: &Msg, service_name: &str) -> bool {
+    msg.get_payload::<String>()
+        .is_ok_and(|payload| payload.split(' ').next() == Some(service_name))
+}
+
+// Marks the connection for a reconnect, returns false if it was dropped.
+fn notify_switch(switched: &Weak<AtomicBool>) -> bool {
+    match switched.upgrade() {
+        Some(switched) => {
+            switched.store(true, Ordering::Relaxed);
+            true
+        }
+        None => false,
+    }
+}
+
+// Listens to the failovers announced by the sentinels, on a thread of its
+// own, until the connection is dropped.  When the subscription had to be
+// made again a failover may have been missed, so the connection reconnects
+// then as well.
+fn watch_failovers(client: SentinelClient, switched: Weak<AtomicBool>) {
+    let mut watched = false;
+    while switched.strong_count() > 0 {
+        for sentinel in client.sentinels.iter() {
+            let mut con = match Client::open(sentinel.clone()).and_then(|c| c.get_connection()) {
+                Ok(con) => con,
+                Err(_) => continue,
+            };
+            let mut pubsub = con.as_pubsub();
+            let subscribed = pubsub
+                .subscribe(SWITCH_MASTER)
+                .and_then(|_| pubsub.set_read_timeout(Some(WATCH_INTERVAL)));
+            if subscribed.is_err() {
+                continue;
+            }
+            if watched && !notify_switch(&switched) {
+                return;
+            }
+            watched = true;
+            loop {
+                match pubsub.get_message() {
+                    Ok(msg) => {
+                        if is_switch_of(&msg, &client.service_name) && !notify_switch(&switched) {
+                            return;
+                        }
+                    }
+                    Err(err) if err.is_timeout() => {
+                        if switched.strong_count() == 0 {
+                            return;
+                        }
+                    }
+                    Err(_) => break,
+                }
+            }
+        }
+        thread::sleep(WATCH_INTERVAL);
+    }
+}
+
+// Async version of `watch_failovers`, run as a task.
+#[cfg(feature = "aio")]
+async fn watch_failovers_async(client: SentinelClient, switched: Weak<AtomicBool>) {
+    use futures_util::StreamExt;
+
+    let runtime = crate::aio::Runtime::locate();
+    let mut watched = false;
+    while switched.strong_count() > 0 {
+        for sentinel in client.sentinels.iter() {
+            let subscribed = async {
+                let mut pubsub = Client::open(sentinel.clone())?
+                    .get_async_connection()
+                    .await?
+                    .into_pubsub();
+                pubsub.subscribe(SWITCH_MASTER).await?;
+                Ok::<_, RedisError>(pubsub.into_on_message())
+            }
+            .await;
+            let mut messages = match subscribed {
+                Ok(messages) => Box::pin(messages),
+                Err(_) => continue,
+            };
+            if watched && !notify_switch(&switched) {
+                return;
+            }
+            watched = true;
+            loop {
+                match runtime.timeout(WATCH_INTERVAL, messages.next()).await {
+                    Ok(Some(msg)) => {
+                        if is_switch_of(&msg, &client.service_name) && !notify_switch(&switched) {
+                            return;
+                        }
+                    }
+                    Ok(None) => break,
+                    Err(_) => {
+                        if switched.strong_count() == 0 {
+                            return;
+                        }
+                    }
+                }
+            }
+        }
+        runtime.sleep(WATCH_INTERVAL).await;
+    }
+}
+
+// Errors after which the connection is dropped and the master resolved again.
+fn needs_reconnect(err: &RedisError) -> bool {
+    err.kind() == ErrorKind::ReadOnly || err.is_connection_dropped() || err.is_io_error()
+}
+
+/// A connection to the master of a sentinel monitored service.
+///
+/// When a sentinel announces a new master with `+switch-master`, or the
+/// connection breaks or the node turns out to have become a replica, the
+/// master is resolved again on the next command.  A command rejected with
+/// a `READONLY` error was not executed and is retried once on the new
+/// master.
+///
+/// The announcements are listened to on a thread, which exits about a
+/// second after the connection was dropped.
+pub struct SentinelConnection {
+    client: SentinelClient,
+    con: Option<Connection>,
+    // raised by the failover watcher
+    switched: Arc<AtomicBool>,
+}
+
+impl SentinelConnection {
+    fn connection(&mut self) -> RedisResult<&mut Connection> {
+        if self.switched.swap(false, Ordering::Relaxed) {
+            self.con = None;
+        }
+        if !self.con.as_ref().is_some_and(|con| con.is_open()) {
+            self.con = Some(self.client.get_connection()?);
+        }
+        Ok(self.con.as_mut().unwrap())
+    }
+}
+
+impl ConnectionLike for SentinelConnection {
+    fn req_packed_command(&mut self, cmd: &[u8]) -> RedisResult<Value> {
+        match self.connection()?.req_packed_command(cmd) {
+            Err(err) if needs_reconnect(&err) => {
+                self.con = None;
+                if err.kind() == ErrorKind::ReadOnly {
+                    return self.connection()?.req_packed_command(cmd);
+                }
+                Err(err)
+            }
+            result => result,
+        }
+    }
+
+    fn req_packed_commands(
+        &mut self,
+        cmd: &[u8],
+        offset: usize,
+        count: usize,
+    ) -> RedisResult<Vec<Value>> {
+        let result = self.connection()?.req_packed_commands(cmd, offset, count);
+        if let Err(ref err) = result {
+            if needs_reconnect(err) {
+                self.con = None;
+            }
+        }
+        result
+    }
+
+    fn get_db(&self) -> i64 {
+        self.client.node_info.db
+    }
+
+    fn check_connection(&mut self) -> bool {
+        match self.connection() {
+            Ok(con) => con.check_connection(),
+            Err(_) => false,
+        }
+    }
+
+    fn is_open(&self) -> bool {
+        self.con.as_ref().is_some_and(|con| con.is_open())
+    }
+}
+
+/// A multiplexed connection to the master of a sentinel monitored service,
+/// which behaves like [`SentinelConnection`].  The announcements of the
+/// sentinels are listened to on a task instead of a thread.
+#[cfg(feature = "aio")]
+#[cfg_attr(docsrs, doc(cfg(feature = "aio")))]
+pub struct AsyncSentinelConnection {
+    client: SentinelClient,
+    con: Option<MultiplexedConnection>,
+    switched: Arc<AtomicBool>,
+}
+
+#[cfg(feature = "aio")]
+impl AsyncSentinelConnection {
+    async fn connection(&mut self) -> RedisResult<&mut MultiplexedConnection> {
+        if self.switched.swap(false, Ordering::Relaxed) {
+            self.con = None;
+        }
+        if self.con.is_none() {
+            self.con = Some(self.client.get_multiplexed_async_connection().await?);
+        }
+        Ok(self.con.as_mut().unwrap())
+    }
+}
+
+#[cfg(feature = "aio")]
+impl crate::aio::ConnectionLike for AsyncSentinelConnection {
+    fn req_packed_command<'a>(&'a mut self, cmd: &'a Cmd) -> RedisFuture<'a, Value> {
+        (async move {
+            match self.connection().await?.req_packed_command(cmd).await {
+                Err(err) if needs_reconnect(&err) => {
+                    self.con = None;
+                    if err.kind() == ErrorKind::ReadOnly {
+                        return self.connection().await?.req_packed_command(cmd).await;
+                    }
+                    Err(err)
+                }
+                result => result,
+            }
+        })
+        .boxed()
+    }
+
+    fn req_packed_commands<'a>(
+        &'a mut self,
+        cmd: &'a crate::Pipeline,
+        offset: usize,
+        count: usize,
+    ) -> RedisFuture<'a, Vec<Value>> {
+        (async move {
+            let result = self
+                .connection()
+                .await?
+                .req_packed_commands(cmd, offset, count)
+                .await;
+            if let Err(ref err) = result {
+                if needs_reconnect(err) {
+                    self.con = None;
+                }
+            }
+            result
+        })
+        .boxed()
+    }
+
+    fn get_db(&self) -> i64 {
+        self.client.node_info.db
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_redis_value;
+
+    fn client() -> SentinelClient {
+        SentinelClientBuilder::new(vec!["redis://127.0.0.1:26379"], "mymaster")
+            .node_connection_info(RedisConnectionInfo {
+                db: 2,
+                password: Some("secret".into()),
+                ..Default::default()
+            })
+            .open()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_open_without_sentinels() {
+        let err = SentinelClient::open(Vec::<String>::new(), "mymaster")
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), ErrorKind::InvalidClientConfig);
+    }
+
+    #[test]
+    fn test_master_from_reply() {
+        let client = client();
+        let sentinel = client.sentinels[0].clone();
+        let reply = parse_redis_value(b"*2\r\n$8\r\n10.0.0.1\r\n$4\r\n6380\r\n").unwrap();
+        let info = client.master_from_reply(&sentinel, reply).unwrap();
+        assert_eq!(info.addr, ConnectionAddr::Tcp("10.0.0.1".into(), 6380));
+        assert_eq!(info.redis.db, 2);
+        assert_eq!(info.redis.password.as_deref(), Some("secret"));
+
+        let err = client.master_from_reply(&sentinel, Value::Nil).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidClientConfig);
+
+        let tls = ConnectionInfo {
+            addr: ConnectionAddr::TcpTls {
+                host: "sentinel".into(),
+                port: 26379,
+                insecure: true,
+                certificates: Some(TlsCertificates::new().root_cert("ca")),
+            },
+            redis: Default::default(),
+            proxy: None,
+            tcp_settings: Default::default(),
+        };
+        let reply = parse_redis_value(b"*2\r\n$8\r\n10.0.0.1\r\n$4\r\n6380\r\n").unwrap();
+        assert_eq!(
+            client.master_from_reply(&tls, reply).unwrap().addr,
+            ConnectionAddr::TcpTls {
+                host: "10.0.0.1".into(),
+                port: 6380,
+                insecure: true,
+                certificates: Some(TlsCertificates::new().root_cert("ca")),
+            }
+        );
+    }
+
+    #[test]
+    fn test_replicas_from_reply() {
+        let client = client();
+        let sentinel = client.sentinels[0].clone();
+        let reply = Value::Bulk(
+            [("10.0.0.2", "slave"), ("10.0.0.3", "s_down,slave")]
+                .iter()
+                .map(|(ip, flags)| {
+                    Value::Bulk(
+                        ["ip", ip, "port", "6379", "flags", flags]
+                            .iter()
+                            .map(|s| Value::Data(s.as_bytes().to_vec()))
+                            .collect(),
+                    )
+                })
+                .collect(),
+        );
+        let infos = client.replicas_from_reply(&sentinel, reply).unwrap();
+        assert_eq!(infos.len(), 1);
+        assert_eq!(infos[0].addr, ConnectionAddr::Tcp("10.0.0.2".into(), 6379));
+    }
+
+    #[test]
+    fn test_rotate() {
+        let client = client();
+        let replicas = vec![client.sentinels[0].clone(); 3]
+            .into_iter()
+            .enumerate()
+            .map(|(i, mut info)| {
+                info.redis.db = i as i64;
+                info
+            })
+            .collect::<Vec<_>>();
+        let firsts: Vec<i64> = (0..4)
+            .map(|_| client.rotate(replicas.clone())[0].redis.db)
+            .collect();
+        assert_eq!(firsts, [0, 1, 2, 0]);
+    }
+
+    #[test]
+    fn test_check_master() {
+        let master = parse_redis_value(b"*3\r\n$6\r\nmaster\r\n:0\r\n*0\r\n").unwrap();
+        assert!(check_master(master).is_ok());
+        let replica = parse_redis_value(
+            b"*5\r\n$5\r\nslave\r\n$9\r\n127.0.0.1\r\n:6379\r\n$9\r\nconnected\r\n:0\r\n",
+        )
+        .unwrap();
+        assert_eq!(
+            check_master(replica).unwrap_err().kind(),
+            ErrorKind::ReadOnly
+        );
+    }
+
+    #[test]
+    fn test_switch_master() {
+        let event = |payload: &str| {
+            Msg::from_value(&Value::Bulk(vec![
+                Value::Data(b"message".to_vec()),
+                Value::Data(SWITCH_MASTER.as_bytes().to_vec()),
+                Value::Data(payload.as_bytes().to_vec()),
+            ]))
+            .unwrap()
+        };
+        assert!(is_switch_of(
+            &event("mymaster 127.0.0.1 6379 127.0.0.1 6380"),
+            "mymaster"
+        ));
+        assert!(!is_switch_of(
+            &event("mymaster2 127.0.0.1 6379 127.0.0.1 6380"),
+            "mymaster"
+        ));
+
+        let switched = Arc::new(AtomicBool::new(false));
+        let watched = Arc::downgrade(&switched);
+        assert!(notify_switch(&watched));
+        assert!(switched.load(Ordering::Relaxed));
+        drop(switched);
+        assert!(!notify_switch(&watched));
+    }
+}
@@ -0,0 +1,758 @@
+//! Stores serde serializable types as command arguments.
+//!
+//! Values can be stored in two encodings, selected with a wrapper type:
+//!
+//! * [`Json`] stores the value as a single JSON document, for `SET`/`GET`
+//!   and the other commands which take a single value.
+//! * [`HashFields`] stores the fields of a struct or map as field/value
+//!   pairs, for `HSET`/`HGETALL`.  Strings, numbers and booleans are stored
+//!   as their text, nested values as JSON and `None` fields are skipped.
+//!
+//! Both wrappers convert the replies back into the value.  Other replies,
+//! like the entries of `XRANGE`, can be deserialized with
+//! [`from_redis_value_serde`].
+//!
+//! ```rust,no_run
+//! # fn do_something() -> redis::RedisResult<()> {
+//! use redis::serde::{HashFields, Json};
+//! use redis::Commands;
+//! use serde::{Deserialize, Serialize};
+//!
+//! #[derive(Serialize, Deserialize)]
+//! struct User {
+//!     name: String,
+//!     age: u32,
+//! }
+//!
+//! let client = redis::Client::open("redis://127.0.0.1/")?;
+//! let mut con = client.get_connection()?;
+//! let user = User { name: "ada".into(), age: 36 };
+//! let _: () = con.set("user:1", Json(&user))?;
+//! let Json(user): Json<User> = con.get("user:1")?;
+//! redis::cmd("HSET")
+//!     .arg("user:2")
+//!     .arg(HashFields(&user))
+//!     .query::<()>(&mut con)?;
+//! let HashFields(user): HashFields<User> = con.hgetall("user:2")?;
+//! # Ok(()) }
+//! ```
+use std::fmt;
+use std::str;
+
+use ::serde::de::{self, Deserialize, DeserializeOwned, IntoDeserializer, Visitor};
+use ::serde::Serialize;
+
+use crate::types::{
+    ErrorKind, FromRedisValue, RedisError, RedisResult, RedisWrite, ToRedisArgs, Value,
+};
+
+/// Stores a value as a JSON document.
+///
+/// Writing the value panics if it can not be serialized to JSON, for
+/// instance because a map has keys which are not strings.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Json<T>(pub T);
+
+impl<T: Serialize> ToRedisArgs for Json<T> {
+    fn write_redis_args<W>(&self, out: &mut W)
+    where
+        W: ?Sized + RedisWrite,
+    {
+        let json = serde_json::to_vec(&self.0).expect("value can not be serialized to JSON");
+        out.write_arg(&json);
+    }
+}
+
+impl<T: DeserializeOwned> FromRedisValue for Json<T> {
+    fn from_redis_value(v: &Value) -> RedisResult<Json<T>> {
+        let json = match *v {
+            Value::Data(ref bytes) => &bytes[..],
+            Value::Status(ref status) => status.as_bytes(),
+            _ => fail!(incompatible(v, "Response type not JSON compatible")),
+        };
+        serde_json::from_slice(json)
+            .map(Json)
+            .map_err(|err| deserialize_error(err.to_string()))
+    }
+}
+
+/// Stores the fields of a struct or map as field/value pairs of a hash.
+///
+/// Writing the value panics if it is not a struct or a map with string
+/// keys.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct HashFields<T>(pub T);
+
+impl<T: Serialize> ToRedisArgs for HashFields<T> {
+    fn write_redis_args<W>(&self, out: &mut W)
+    where
+        W: ?Sized + RedisWrite,
+    {
+        let fields = match serde_json::to_value(&self.0) {
+            Ok(serde_json::Value::Object(fields)) => fields,
+            _ => panic!("value can not be serialized to hash fields"),
+        };
+        for (field, value) in fields {
+            let value = match value {
+                serde_json::Value::Null => continue,
+                serde_json::Value::String(s) => s,
+                serde_json::Value::Bool(b) => b.to_string(),
+                serde_json::Value::Number(n) => n.to_string(),
+                nested => nested.to_string(),
+            };
+            out.write_arg(field.as_bytes());
+            out.write_arg(value.as_bytes());
+        }
+    }
+
+    fn is_single_arg(&self) -> bool {
+        false
+    }
+}
+
+impl<T: DeserializeOwned> FromRedisValue for HashFields<T> {
+    fn from_redis_value(v: &Value) -> RedisResult<HashFields<T>> {
+        if v.as_map_iter().is_none() {
+            fail!(incompatible(v, "Response type not hash compatible"));
+        }
+        from_redis_value_serde(v).map(HashFields)
+    }
+}
+
+/// Deserializes a reply into any type implementing `Deserialize`.
+///
+/// Bulk and set replies are read as sequences, map replies and bulks of
+/// alternating keys and values as maps and structs.  Strings are parsed
+/// into numbers and booleans where those are expected, and strings holding
+/// JSON into nested sequences and maps, the way `HashFields` stores them.
+///
+/// ```rust
+/// use redis::Value;
+/// use std::collections::HashMap;
+///
+/// // the reply of `XRANGE`
+/// let reply = Value::Bulk(vec![Value::Bulk(vec![
+///     Value::Data(b"1-0".to_vec()),
+///     Value::Bulk(vec![
+///         Value::Data(b"temperature".to_vec()),
+///         Value::Data(b"21.5".to_vec()),
+///     ]),
+/// ])]);
+/// let entries: Vec<(String, HashMap<String, f64>)> =
+///     redis::from_redis_value_serde(&reply).unwrap();
+/// assert_eq!(entries[0].1["temperature"], 21.5);
+/// ```
+pub fn from_redis_value_serde<'de, T: Deserialize<'de>>(v: &'de Value) -> RedisResult<T> {
+    T::deserialize(v)
+}
+
+fn text(v: &Value) -> Option<&[u8]> {
+    match *v {
+        Value::Data(ref bytes) => Some(bytes),
+        Value::Status(ref s)
+        | Value::BigNumber(ref s)
+        | Value::VerbatimString { text: ref s, .. } => Some(s.as_bytes()),
+        Value::Okay => Some(b"OK"),
+        _ => None,
+    }
+}
+
+fn incompatible(v: &Value, details: &str) -> RedisError {
+    RedisError::from((
+        ErrorKind::TypeError,
+        "Response was of incompatible type",
+        format!("{:?} (response was {:?})", details, v),
+    ))
+}
+
+fn deserialize_error(details: String) -> RedisError {
+    RedisError::from((
+        ErrorKind::TypeError,
+        "Response could not be deserialized",
+        details,
+    ))
+}
+
+impl de::Error for RedisError {
+    fn custom<T: fmt::Display>(msg: T) -> RedisError {
+        deserialize_error(msg.to_string())
+    }
+}
+
+// Deserializes a value from its text the way `HashFields` stores it:
+// scalars are parsed from the text, sequences and maps from JSON.
+struct TextDeserializer<'de>(&'de [u8]);
+
+impl<'de> TextDeserializer<'de> {
+    fn str(&self) -> RedisResult<&'de str> {
+        str::from_utf8(self.0).map_err(|err| deserialize_error(err.to_string()))
+    }
+
+    fn parse<T>(&self) -> RedisResult<T>
+    where
+        T: str::FromStr,
+        T::Err: fmt::Display,
+    {
+        let s = self.str()?;
+        s.parse()
+            .map_err(|err| deserialize_error(format!("invalid value {:?}: {}", s, err)))
+    }
+
+    fn json(&self) -> serde_json::Deserializer<serde_json::de::SliceRead<'de>> {
+        serde_json::Deserializer::from_slice(self.0)
+    }
+}
+
+fn json_error(err: serde_json::Error) -> RedisError {
+    deserialize_error(err.to_string())
+}
+
+macro_rules! deserialize_parsed {
+    ($($method:ident => $visit:ident,)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> RedisResult<V::Value> {
+                visitor.$visit(self.parse()?)
+            }
+        )*
+    };
+}
+
+impl<'de> de::Deserializer<'de> for TextDeserializer<'de> {
+    type Error = RedisError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> RedisResult<V::Value> {
+        match self.0.first() {
+            Some(b'[') | Some(b'{') => self.json().deserialize_any(visitor).map_err(json_error),
+            _ => match str::from_utf8(self.0) {
+                Ok(s) => visitor.visit_borrowed_str(s),
+                Err(_) => visitor.visit_borrowed_bytes(self.0),
+            },
+        }
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> RedisResult<V::Value> {
+        match self.0 {
+            b"1" | b"true" => visitor.visit_bool(true),
+            b"0" | b"false" => visitor.visit_bool(false),
+            _ => Err(deserialize_error(format!(
+                "invalid boolean {:?}",
+                String::from_utf8_lossy(self.0)
+            ))),
+        }
+    }
+
+    deserialize_parsed! {
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+        deserialize_i128 => visit_i128,
+        deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32,
+        deserialize_u64 => visit_u64,
+        deserialize_u128 => visit_u128,
+        deserialize_f32 => visit_f32,
+        deserialize_f64 => visit_f64,
+        deserialize_char => visit_char,
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> RedisResult<V::Value> {
+        visitor.visit_borrowed_str(self.str()?)
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> RedisResult<V::Value> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> RedisResult<V::Value> {
+        visitor.visit_borrowed_bytes(self.0)
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> RedisResult<V::Value> {
+        self.deserialize_bytes(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> RedisResult<V::Value> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> RedisResult<V::Value> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> RedisResult<V::Value> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> RedisResult<V::Value> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> RedisResult<V::Value> {
+        self.json().deserialize_seq(visitor).map_err(json_error)
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, len: usize, visitor: V) -> RedisResult<V::Value> {
+        self.json()
+            .deserialize_tuple(len, visitor)
+            .map_err(json_error)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> RedisResult<V::Value> {
+        self.json()
+            .deserialize_tuple_struct(name, len, visitor)
+            .map_err(json_error)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> RedisResult<V::Value> {
+        self.json().deserialize_map(visitor).map_err(json_error)
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> RedisResult<V::Value> {
+        self.json()
+            .deserialize_struct(name, fields, visitor)
+            .map_err(json_error)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> RedisResult<V::Value> {
+        match self.0.first() {
+            // variants with data are stored as JSON
+            Some(b'{') | Some(b'"') => self
+                .json()
+                .deserialize_enum(name, variants, visitor)
+                .map_err(json_error),
+            _ => visitor.visit_enum(self.str()?.into_deserializer()),
+        }
+    }
+
+    fn deserialize_identifier<V: Visitor<'de>>(self, visitor: V) -> RedisResult<V::Value> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> RedisResult<V::Value> {
+        visitor.visit_unit()
+    }
+}
+
+impl<'de> IntoDeserializer<'de, RedisError> for TextDeserializer<'de> {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+fn visit_seq<'de, V: Visitor<'de>>(items: &'de [Value], visitor: V) -> RedisResult<V::Value> {
+    let mut seq = de::value::SeqDeserializer::new(items.iter());
+    let value = visitor.visit_seq(&mut seq)?;
+    seq.end()?;
+    Ok(value)
+}
+
+fn visit_map<'de, I, V>(items: I, visitor: V) -> RedisResult<V::Value>
+where
+    I: Iterator<Item = (&'de Value, &'de Value)>,
+    V: Visitor<'de>,
+{
+    let mut map = de::value::MapDeserializer::new(items);
+    let value = visitor.visit_map(&mut map)?;
+    map.end()?;
+    Ok(value)
+}
+
+// Scalars are parsed from string replies, other replies are passed on as
+// they are.
+macro_rules! deserialize_text {
+    ($($method:ident,)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> RedisResult<V::Value> {
+                match text(self) {
+                    Some(text) => TextDeserializer(text).$method(visitor),
+                    None => self.deserialize_any(visitor),
+                }
+            }
+        )*
+    };
+}
+
+impl<'de> de::Deserializer<'de> for &'de Value {
+    type Error = RedisError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> RedisResult<V::Value> {
+        match *self {
+            Value::Nil => visitor.visit_unit(),
+            Value::Int(i) => visitor.visit_i64(i),
+            Value::Double(d) => visitor.visit_f64(d),
+            Value::Boolean(b) => visitor.visit_bool(b),
+            Value::Bulk(ref items)
+            | Value::Set(ref items)
+            | Value::Push {
+                data: ref items, ..
+            } => visit_seq(items, visitor),
+            Value::Map(ref items) => visit_map(items.iter().map(|(k, v)| (k, v)), visitor),
+            Value::Attribute { ref data, .. } => data.deserialize_any(visitor),
+            Value::Data(ref bytes) => TextDeserializer(bytes).deserialize_any(visitor),
+            Value::Status(ref s)
+            | Value::BigNumber(ref s)
+            | Value::VerbatimString { text: ref s, .. } => visitor.visit_borrowed_str(s),
+            Value::Okay => visitor.visit_borrowed_str("OK"),
+            Value::ServerError(ref line) => Err(crate::parser::server_error(line)),
+        }
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> RedisResult<V::Value> {
+        match *self {
+            Value::Int(i) => visitor.visit_bool(i != 0),
+            _ => match text(self) {
+                Some(text) => TextDeserializer(text).deserialize_bool(visitor),
+                None => self.deserialize_any(visitor),
+            },
+        }
+    }
+
+    deserialize_text! {
+        deserialize_i8,
+        deserialize_i16,
+        deserialize_i32,
+        deserialize_i64,
+        deserialize_i128,
+        deserialize_u8,
+        deserialize_u16,
+        deserialize_u32,
+        deserialize_u64,
+        deserialize_u128,
+        deserialize_f32,
+        deserialize_f64,
+        deserialize_char,
+        deserialize_bytes,
+        deserialize_byte_buf,
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> RedisResult<V::Value> {
+        match *self {
+            Value::Int(i) => visitor.visit_string(i.to_string()),
+            Value::Double(d) => visitor.visit_string(d.to_string()),
+            _ => match text(self) {
+                Some(text) => TextDeserializer(text).deserialize_str(visitor),
+                None => self.deserialize_any(visitor),
+            },
+        }
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> RedisResult<V::Value> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> RedisResult<V::Value> {
+        match *self {
+            Value::Nil => visitor.visit_none(),
+            Value::Attribute { ref data, .. } => data.deserialize_option(visitor),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> RedisResult<V::Value> {
+        match *self {
+            Value::Nil | Value::Okay => visitor.visit_unit(),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> RedisResult<V::Value> {
+        self.deserialize_unit(visitor)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> RedisResult<V::Value> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> RedisResult<V::Value> {
+        match *self {
+            Value::Nil => visit_seq(&[], visitor),
+            _ => match text(self) {
+                Some(text) => TextDeserializer(text).deserialize_seq(visitor),
+                None => self.deserialize_any(visitor),
+            },
+        }
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, _len: usize, visitor: V) -> RedisResult<V::Value> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> RedisResult<V::Value> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> RedisResult<V::Value> {
+        match *self {
+            Value::Nil => visit_map(std::iter::empty(), visitor),
+            Value::Bulk(ref items) if items.len() % 2 == 0 => {
+                visit_map(items.chunks(2).map(|pair| (&pair[0], &pair[1])), visitor)
+            }
+            Value::Bulk(_) => Err(deserialize_error(
+                "a bulk of keys and values must have an even length".to_string(),
+            )),
+            _ => match text(self) {
+                Some(text) => TextDeserializer(text).deserialize_map(visitor),
+                None => self.deserialize_any(visitor),
+            },
+        }
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> RedisResult<V::Value> {
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> RedisResult<V::Value> {
+        match text(self) {
+            Some(text) => TextDeserializer(text).deserialize_enum(name, variants, visitor),
+            None => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_identifier<V: Visitor<'de>>(self, visitor: V) -> RedisResult<V::Value> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> RedisResult<V::Value> {
+        visitor.visit_unit()
+    }
+}
+
+impl<'de> IntoDeserializer<'de, RedisError> for &'de Value {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::serde::Deserialize;
+    use std::collections::HashMap;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum Role {
+        Admin,
+        Guest { until: u64 },
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct User {
+        name: String,
+        age: u32,
+        admin: bool,
+        email: Option<String>,
+        tags: Vec<String>,
+        role: Role,
+        score: f64,
+    }
+
+    fn user() -> User {
+        User {
+            name: "123".into(),
+            age: 36,
+            admin: true,
+            email: None,
+            tags: vec!["a".into(), "b".into()],
+            role: Role::Admin,
+            score: 1.5,
+        }
+    }
+
+    // Turns the arguments into the reply of `HGETALL`.
+    fn reply(args: Vec<Vec<u8>>) -> Value {
+        Value::Bulk(args.into_iter().map(Value::Data).collect())
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let args = Json(&user()).to_redis_args();
+        assert_eq!(args.len(), 1);
+        let Json(decoded): Json<User> =
+            FromRedisValue::from_redis_value(&Value::Data(args.into_iter().next().unwrap()))
+                .unwrap();
+        assert_eq!(decoded, user());
+
+        let err = Json::<User>::from_redis_value(&Value::Data(b"{".to_vec())).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::TypeError);
+        assert!(Json::<User>::from_redis_value(&Value::Nil).is_err());
+    }
+
+    #[test]
+    fn hash_fields_round_trip() {
+        let args = HashFields(&user()).to_redis_args();
+        let fields: HashMap<_, _> = args
+            .chunks(2)
+            .map(|pair| {
+                (
+                    String::from_utf8(pair[0].clone()).unwrap(),
+                    String::from_utf8(pair[1].clone()).unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(fields["name"], "123");
+        assert_eq!(fields["age"], "36");
+        assert_eq!(fields["tags"], r#"["a","b"]"#);
+        assert_eq!(fields["role"], "Admin");
+        assert!(!fields.contains_key("email"));
+
+        let HashFields(decoded): HashFields<User> =
+            FromRedisValue::from_redis_value(&reply(args)).unwrap();
+        assert_eq!(decoded, user());
+
+        let mut guest = user();
+        guest.email = Some("x@example.com".into());
+        guest.role = Role::Guest { until: 10 };
+        let args = HashFields(&guest).to_redis_args();
+        let HashFields(decoded): HashFields<User> =
+            FromRedisValue::from_redis_value(&reply(args)).unwrap();
+        assert_eq!(decoded, guest);
+    }
+
+    #[test]
+    fn deserialize_values() {
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Entry<'a> {
+            id: &'a str,
+            fields: HashMap<String, String>,
+        }
+
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Info {
+            count: u64,
+            ready: bool,
+            ratio: Option<f32>,
+            name: String,
+            entries: Vec<(String, Vec<i64>)>,
+            first: Option<Box<Info>>,
+        }
+
+        let data = |s: &str| Value::Data(s.as_bytes().to_vec());
+        let entry = Value::Map(vec![
+            (Value::Status("id".into()), data("1-0")),
+            (
+                Value::Status("fields".into()),
+                Value::Bulk(vec![data("a"), data("1")]),
+            ),
+        ]);
+        let entry: Entry = from_redis_value_serde(&entry).unwrap();
+        assert_eq!(entry.id, "1-0");
+        assert_eq!(entry.fields["a"], "1");
+
+        let info = Value::Bulk(vec![
+            data("count"),
+            Value::Int(3),
+            data("ready"),
+            data("1"),
+            data("ratio"),
+            Value::Nil,
+            data("name"),
+            Value::Int(42),
+            data("entries"),
+            Value::Bulk(vec![Value::Bulk(vec![
+                data("x"),
+                Value::Set(vec![Value::Int(1), data("-2")]),
+            ])]),
+            data("first"),
+            Value::Attribute {
+                data: Box::new(Value::Map(vec![
+                    (data("count"), data("7")),
+                    (data("ready"), Value::Boolean(false)),
+                    (data("ratio"), Value::Double(0.5)),
+                    (data("name"), Value::Okay),
+                    (data("entries"), Value::Nil),
+                ])),
+                attributes: vec![],
+            },
+        ]);
+        let info: Info = from_redis_value_serde(&info).unwrap();
+        assert_eq!(
+            info,
+            Info {
+                count: 3,
+                ready: true,
+                ratio: None,
+                name: "42".into(),
+                entries: vec![("x".into(), vec![1, -2])],
+                first: Some(Box::new(Info {
+                    count: 7,
+                    ready: false,
+                    ratio: Some(0.5),
+                    name: "OK".into(),
+                    entries: vec![],
+                    first: None,
+                })),
+            }
+        );
+
+        let odd = Value::Bulk(vec![data("count")]);
+        assert!(from_redis_value_serde::<HashMap<String, u64>>(&odd).is_err());
+        let err = from_redis_value_serde::<Vec<u8>>(&Value::Int(1)).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::TypeError);
+    }
+
+    #[test]
+    fn hash_fields_errors() {
+        let mut args = HashFields(&user()).to_redis_args();
+        let age = args.iter().position(|arg| arg == b"age").unwrap();
+        args[age + 1] = b"old".to_vec();
+        let err = HashFields::<User>::from_redis_value(&reply(args)).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::TypeError);
+        assert!(err.to_string().contains("old"), "{}", err);
+
+        let err = HashFields::<User>::from_redis_value(&reply(vec![])).unwrap_err();
+        assert!(err.to_string().contains("missing field"), "{}", err);
+        assert!(HashFields::<User>::from_redis_value(&Value::Int(1)).is_err());
+    }
+}
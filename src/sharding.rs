@@ -0,0 +1,468 @@
+//! Client side sharding over independent redis servers.
+//!
+//! [`ShardedClient`] spreads the keys over a fixed list of servers which do
+//! not form a cluster, as twemproxy does.  Every command is sent to the
+//! server its key hashes to, by default with the ketama consistent hashing,
+//! so adding or removing a server only moves the keys of a fraction of the
+//! ring.  Like in a cluster, the part of a key in braces is hashed instead
+//! of the whole key if there is one, which keeps `user:{42}:name` and
+//! `user:{42}:email` on the same server.
+//!
+//! A command is routed by its first key, so the keys of multi key commands
+//! and pipelines need to hash to the same server, otherwise they fail.
+//! Commands without keys fail as well, they can be sent to a single server
+//! through [`ShardedConnection::shard_connection`].
+//!
+//! ```rust,no_run
+//! # fn do_something() -> redis::RedisResult<()> {
+//! use redis::sharding::ShardedClient;
+//! use redis::Commands;
+//!
+//! let client = ShardedClient::open(vec![
+//!     "redis://10.0.0.1/",
+//!     "redis://10.0.0.2/",
+//!     "redis://10.0.0.3/",
+//! ])?;
+//! let mut con = client.get_connection()?;
+//! let _: () = con.set("key", 42)?;
+//! # Ok(()) }
+//! ```
+use std::fmt;
+use std::sync::Arc;
+
+use crate::client::Client;
+use crate::connection::{Connection, ConnectionLike, IntoConnectionInfo};
+use crate::parser::unpack_commands;
+use crate::types::{ErrorKind, RedisResult, Value};
+
+/// Decides which shard holds a key.
+pub trait ShardHasher: Send + Sync {
+    /// Returns the index of the shard of the key, which is less than the
+    /// number of shards the hasher was made for.
+    fn shard(&self, key: &[u8]) -> usize;
+}
+
+/// The ketama consistent hashing of libmemcached and twemproxy, with 160
+/// points per shard on the ring.
+#[derive(Debug, Clone)]
+pub struct Ketama {
+    ring: Vec<(u32, usize)>,
+}
+
+const POINTS_PER_SHARD: usize = 160;
+
+// The first four bytes of the MD5 digest, as ketama places them.
+fn ketama_hash(digest: &[u8]) -> u32 {
+    u32::from_le_bytes([digest[0], digest[1], digest[2], digest[3]])
+}
+
+impl Ketama {
+    /// Builds the ring of the shards with the names, usually their
+    /// `host:port`.  The index of a shard is its position in `names`.
+    pub fn new<I, T>(names: I) -> Ketama
+    where
+        I: IntoIterator<Item = T>,
+        T: AsRef<str>,
+    {
+        let mut ring = Vec::new();
+        for (shard, name) in names.into_iter().enumerate() {
+            for i in 0..POINTS_PER_SHARD / 4 {
+                let digest = md5::compute(format!("{}-{}", name.as_ref(), i));
+                for point in digest.chunks(4) {
+                    ring.push((ketama_hash(point), shard));
+                }
+            }
+        }
+        ring.sort_unstable();
+        Ketama { ring }
+    }
+}
+
+impl ShardHasher for Ketama {
+    fn shard(&self, key: &[u8]) -> usize {
+        let hash = ketama_hash(&md5::compute(key)[..]);
+        let i = self.ring.partition_point(|&(point, _)| point < hash);
+        match self.ring.get(i).or_else(|| self.ring.first()) {
+            Some(&(_, shard)) => shard,
+            None => 0,
+        }
+    }
+}
+
+// The part of the key in the first pair of braces, if it is not empty.
+fn hash_tag(key: &[u8]) -> Option<&[u8]> {
+    let open = key.iter().position(|&b| b == b'{')?;
+    let len = key[open + 1..].iter().position(|&b| b == b'}')?;
+    match &key[open + 1..open + 1 + len] {
+        b"" => None,
+        tag => Some(tag),
+    }
+}
+
+// Returns the key which decides the shard of the command, if it has one.
+fn command_key<'a>(args: &[&'a [u8]]) -> Option<&'a [u8]> {
+    let position = match &args.first()?.to_ascii_uppercase()[..] {
+        b"EVAL" | b"EVALSHA" | b"FCALL" | b"FCALL_RO" => {
+            let keys = std::str::from_utf8(args.get(2)?).ok()?;
+            if keys.parse::<u64>().ok()? == 0 {
+                return None;
+            }
+            3
+        }
+        b"XREAD" | b"XREADGROUP" => {
+            args.iter()
+                .position(|arg| arg.eq_ignore_ascii_case(b"STREAMS"))?
+                + 1
+        }
+        b"XGROUP" | b"XINFO" | b"OBJECT" | b"MEMORY" => 2,
+        _ => 1,
+    };
+    args.get(position).copied()
+}
+
+/// A client of servers which hold a share of the keys each, see the
+/// [module docs](self).
+#[derive(Clone)]
+pub struct ShardedClient {
+    shards: Vec<Client>,
+    hasher: Arc<dyn ShardHasher>,
+}
+
+impl fmt::Debug for ShardedClient {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ShardedClient")
+            .field("shards", &self.shards)
+            .finish()
+    }
+}
+
+impl ShardedClient {
+    /// Creates a client of the servers, which are hashed with [`Ketama`] by
+    /// their addresses.  This does not connect to the servers yet.
+    pub fn open<T: IntoConnectionInfo>(nodes: Vec<T>) -> RedisResult<ShardedClient> {
+        if nodes.is_empty() {
+            fail!((
+                ErrorKind::InvalidClientConfig,
+                "At least one shard is required"
+            ));
+        }
+        let shards = nodes
+            .into_iter()
+            .map(Client::open)
+            .collect::<RedisResult<Vec<_>>>()?;
+        let hasher = Ketama::new(
+            shards
+                .iter()
+                .map(|shard| shard.get_connection_info().addr.to_string()),
+        );
+        Ok(ShardedClient {
+            shards,
+            hasher: Arc::new(hasher),
+        })
+    }
+
+    /// Sets how the keys are spread over the shards.  The hasher has to
+    /// return the index of a shard in the order they were passed to
+    /// `open`.
+    pub fn with_hasher<H: ShardHasher + 'static>(mut self, hasher: H) -> ShardedClient {
+        self.hasher = Arc::new(hasher);
+        self
+    }
+
+    /// Returns the clients of the shards.
+    pub fn shards(&self) -> &[Client] {
+        &self.shards
+    }
+
+    /// Returns the index of the shard which holds the key.
+    pub fn shard_of(&self, key: &[u8]) -> usize {
+        self.hasher.shard(hash_tag(key).unwrap_or(key))
+    }
+
+    // Returns the shard of all the keyed commands among the arguments.
+    fn route<'a, I>(&self, commands: I) -> RedisResult<usize>
+    where
+        I: IntoIterator<Item = Vec<&'a [u8]>>,
+    {
+        let mut shard = None;
+        for args in commands {
+            let key = match command_key(&args) {
+                Some(key) => key,
+                None => continue,
+            };
+            match (shard, self.shard_of(key)) {
+                (Some(shard), other) if shard != other => fail!((
+                    ErrorKind::CrossSlot,
+                    "Keys of the request belong to different shards"
+                )),
+                (_, other) => shard = Some(other),
+            }
+        }
+        match shard {
+            Some(shard) if shard < self.shards.len() => Ok(shard),
+            Some(_) => fail!((
+                ErrorKind::ClientError,
+                "The shard hasher returned an unknown shard"
+            )),
+            None => fail!((ErrorKind::ClientError, "The request has no key to shard by")),
+        }
+    }
+
+    /// Returns a connection which opens the connections to the shards when
+    /// they are first needed.
+    pub fn get_connection(&self) -> RedisResult<ShardedConnection> {
+        Ok(ShardedConnection {
+            client: self.clone(),
+            cons: self.shards.iter().map(|_| None).collect(),
+        })
+    }
+
+    /// Returns an async connection which opens multiplexed connections to
+    /// the shards when they are first needed.
+    #[cfg(feature = "aio")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "aio")))]
+    pub fn get_async_connection(&self) -> ShardedAsyncConnection {
+        ShardedAsyncConnection {
+            client: self.clone(),
+            cons: self.shards.iter().map(|_| None).collect(),
+        }
+    }
+}
+
+/// A connection to the shards of a [`ShardedClient`], which connects to
+/// each shard on first use and reconnects when its connection broke.
+pub struct ShardedConnection {
+    client: ShardedClient,
+    cons: Vec<Option<Connection>>,
+}
+
+impl ShardedConnection {
+    /// Returns the connection to the shard with the index, to send the
+    /// commands which are not about keys.
+    pub fn shard_connection(&mut self, shard: usize) -> RedisResult<&mut Connection> {
+        let client = match self.client.shards.get(shard) {
+            Some(client) => client,
+            None => fail!((ErrorKind::ClientError, "There is no shard with the index")),
+        };
+        let con = &mut self.cons[shard];
+        if !con.as_ref().is_some_and(Connection::is_open) {
+            *con = Some(client.get_connection()?);
+        }
+        Ok(con.as_mut().unwrap())
+    }
+}
+
+impl ConnectionLike for ShardedConnection {
+    fn req_packed_command(&mut self, cmd: &[u8]) -> RedisResult<Value> {
+        let shard = self.client.route(unpack_commands(cmd)?)?;
+        self.shard_connection(shard)?.req_packed_command(cmd)
+    }
+
+    fn req_packed_commands(
+        &mut self,
+        cmd: &[u8],
+        offset: usize,
+        count: usize,
+    ) -> RedisResult<Vec<Value>> {
+        let shard = self.client.route(unpack_commands(cmd)?)?;
+        self.shard_connection(shard)?
+            .req_packed_commands(cmd, offset, count)
+    }
+
+    fn get_db(&self) -> i64 {
+        self.client.shards[0].get_connection_info().redis.db
+    }
+
+    fn check_connection(&mut self) -> bool {
+        (0..self.cons.len()).all(|shard| {
+            self.shard_connection(shard)
+                .is_ok_and(|con| con.check_connection())
+        })
+    }
+
+    fn is_open(&self) -> bool {
+        self.cons.iter().flatten().all(Connection::is_open)
+    }
+}
+
+/// An async connection to the shards of a [`ShardedClient`], which opens a
+/// multiplexed connection to each shard on first use and reopens it when
+/// it broke.
+#[cfg(feature = "aio")]
+#[cfg_attr(docsrs, doc(cfg(feature = "aio")))]
+pub struct ShardedAsyncConnection {
+    client: ShardedClient,
+    cons: Vec<Option<crate::aio::MultiplexedConnection>>,
+}
+
+#[cfg(feature = "aio")]
+impl ShardedAsyncConnection {
+    /// Returns the connection to the shard with the index, to send the
+    /// commands which are not about keys.
+    pub async fn shard_connection(
+        &mut self,
+        shard: usize,
+    ) -> RedisResult<&mut crate::aio::MultiplexedConnection> {
+        let client = match self.client.shards.get(shard) {
+            Some(client) => client,
+            None => fail!((ErrorKind::ClientError, "There is no shard with the index")),
+        };
+        let con = &mut self.cons[shard];
+        if con.is_none() {
+            *con = Some(client.get_multiplexed_async_connection().await?);
+        }
+        Ok(con.as_mut().unwrap())
+    }
+
+    // Forgets the connection to the shard if the request failed because it
+    // broke, so the next request reconnects.
+    fn check_result<T>(&mut self, shard: usize, result: RedisResult<T>) -> RedisResult<T> {
+        if let Err(ref err) = result {
+            if err.is_io_error() || err.is_connection_dropped() {
+                self.cons[shard] = None;
+            }
+        }
+        result
+    }
+}
+
+// The arguments of the command, with cursor arguments left out.
+#[cfg(feature = "aio")]
+fn cmd_args(cmd: &crate::cmd::Cmd) -> Vec<&[u8]> {
+    use crate::cmd::Arg;
+
+    cmd.args_iter()
+        .filter_map(|arg| match arg {
+            Arg::Simple(arg) => Some(arg),
+            Arg::Cursor => None,
+        })
+        .collect()
+}
+
+#[cfg(feature = "aio")]
+impl crate::aio::ConnectionLike for ShardedAsyncConnection {
+    fn req_packed_command<'a>(
+        &'a mut self,
+        cmd: &'a crate::cmd::Cmd,
+    ) -> crate::RedisFuture<'a, Value> {
+        Box::pin(async move {
+            let shard = self.client.route(Some(cmd_args(cmd)))?;
+            let result = self
+                .shard_connection(shard)
+                .await?
+                .req_packed_command(cmd)
+                .await;
+            self.check_result(shard, result)
+        })
+    }
+
+    fn req_packed_commands<'a>(
+        &'a mut self,
+        cmd: &'a crate::Pipeline,
+        offset: usize,
+        count: usize,
+    ) -> crate::RedisFuture<'a, Vec<Value>> {
+        Box::pin(async move {
+            let shard = self.client.route(cmd.cmd_iter().map(cmd_args))?;
+            let result = self
+                .shard_connection(shard)
+                .await?
+                .req_packed_commands(cmd, offset, count)
+                .await;
+            self.check_result(shard, result)
+        })
+    }
+
+    fn get_db(&self) -> i64 {
+        self.client.shards[0].get_connection_info().redis.db
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cmd::cmd;
+
+    #[test]
+    fn test_ketama_spreads_keys() {
+        let ketama = Ketama::new(["10.0.0.1:6379", "10.0.0.2:6379", "10.0.0.3:6379"]);
+        let mut counts = [0; 3];
+        for i in 0..3000 {
+            counts[ketama.shard(format!("key{}", i).as_bytes())] += 1;
+        }
+        assert!(counts.iter().all(|&count| count > 600), "{:?}", counts);
+    }
+
+    #[test]
+    fn test_ketama_keeps_keys_of_remaining_shards() {
+        let three = Ketama::new(["a:1", "b:1", "c:1"]);
+        let two = Ketama::new(["a:1", "b:1"]);
+        for i in 0..1000 {
+            let key = format!("key{}", i);
+            let shard = three.shard(key.as_bytes());
+            if shard < 2 {
+                assert_eq!(two.shard(key.as_bytes()), shard);
+            }
+        }
+    }
+
+    #[test]
+    fn test_command_key() {
+        let key = |cmd: &crate::cmd::Cmd| {
+            let packed = cmd.get_packed_command();
+            let commands = unpack_commands(&packed).unwrap();
+            command_key(&commands[0]).map(<[u8]>::to_vec)
+        };
+        assert_eq!(key(cmd("GET").arg("a")), Some(b"a".to_vec()));
+        assert_eq!(key(&cmd("PING")), None);
+        assert_eq!(
+            key(cmd("EVAL").arg("return 1").arg(1).arg("b")),
+            Some(b"b".to_vec())
+        );
+        assert_eq!(key(cmd("EVAL").arg("return 1").arg(0)), None);
+        assert_eq!(
+            key(cmd("XREAD")
+                .arg("COUNT")
+                .arg(2)
+                .arg("STREAMS")
+                .arg("s")
+                .arg(0)),
+            Some(b"s".to_vec())
+        );
+    }
+
+    #[test]
+    fn test_route() {
+        let client = ShardedClient::open(vec![
+            "redis://10.0.0.1/",
+            "redis://10.0.0.2/",
+            "redis://10.0.0.3/",
+        ])
+        .unwrap();
+        assert_eq!(client.shard_of(b"user:{42}:name"), client.shard_of(b"42"));
+
+        let pipeline = crate::pipe()
+            .atomic()
+            .set("{user}:a", 1)
+            .set("{user}:b", 2)
+            .clone();
+        let packed = pipeline.get_packed_pipeline();
+        let commands = unpack_commands(&packed).unwrap();
+        assert_eq!(client.route(commands).unwrap(), client.shard_of(b"user"));
+
+        let mismatched = (0..100)
+            .map(|i| format!("key{}", i))
+            .find(|key| client.shard_of(key.as_bytes()) != client.shard_of(b"key0"))
+            .unwrap();
+        let pipeline = crate::pipe().get("key0").get(mismatched).clone();
+        let packed = pipeline.get_packed_pipeline();
+        let commands = unpack_commands(&packed).unwrap();
+        assert_eq!(
+            client.route(commands).unwrap_err().kind(),
+            ErrorKind::CrossSlot
+        );
+        let packed = cmd("PING").get_packed_command();
+        let commands = unpack_commands(&packed).unwrap();
+        assert!(client.route(commands).is_err());
+    }
+}
@@ -0,0 +1,176 @@
+use std::io;
+use std::time::Duration;
+
+#[cfg(not(target_os = "wasi"))]
+use socket2::{SockRef, TcpKeepalive};
+
+/// The options of the TCP sockets of the connections, set in
+/// `ConnectionInfo::tcp_settings`.
+///
+/// By default the options of the operating system are kept, so a dead peer
+/// is noticed only after hours of keepalive probes, if at all.  Enabling
+/// keepalive with a short time, interval and number of retries, or setting a
+/// user timeout, detects it in seconds instead.
+///
+/// The options are ignored on WASI, which does not support setting them.
+///
+/// ```rust,no_run
+/// # fn do_something() -> redis::RedisResult<()> {
+/// use std::time::Duration;
+///
+/// let mut info: redis::ConnectionInfo = "redis://127.0.0.1/".parse()?;
+/// info.tcp_settings = redis::TcpSettings::default()
+///     .nodelay(true)
+///     .keepalive_time(Duration::from_secs(10))
+///     .keepalive_interval(Duration::from_secs(5))
+///     .keepalive_retries(3);
+/// let client = redis::Client::open(info)?;
+/// # Ok(()) }
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TcpSettings {
+    nodelay: bool,
+    keepalive_time: Option<Duration>,
+    keepalive_interval: Option<Duration>,
+    keepalive_retries: Option<u32>,
+    user_timeout: Option<Duration>,
+}
+
+impl TcpSettings {
+    /// Sets `TCP_NODELAY`, which sends small writes without waiting to
+    /// coalesce them.
+    pub fn nodelay(mut self, nodelay: bool) -> Self {
+        self.nodelay = nodelay;
+        self
+    }
+
+    /// Enables keepalive, sending the first probe after the connection was
+    /// idle for `time`.
+    pub fn keepalive_time(mut self, time: Duration) -> Self {
+        self.keepalive_time = Some(time);
+        self
+    }
+
+    /// Enables keepalive, sending probes every `interval` once the
+    /// connection is idle.
+    ///
+    /// This is ignored on platforms which do not support it.
+    pub fn keepalive_interval(mut self, interval: Duration) -> Self {
+        self.keepalive_interval = Some(interval);
+        self
+    }
+
+    /// Enables keepalive, closing the connection after `retries` probes
+    /// went unanswered.
+    ///
+    /// This is ignored on platforms which do not support it.
+    pub fn keepalive_retries(mut self, retries: u32) -> Self {
+        self.keepalive_retries = Some(retries);
+        self
+    }
+
+    /// Sets `TCP_USER_TIMEOUT`, closing the connection when written data is
+    /// not acknowledged within `timeout`.
+    ///
+    /// This is only supported on Linux, Android and Fuchsia and is ignored
+    /// elsewhere.
+    pub fn user_timeout(mut self, timeout: Duration) -> Self {
+        self.user_timeout = Some(timeout);
+        self
+    }
+
+    #[cfg(not(target_os = "wasi"))]
+    fn keepalive(&self) -> Option<TcpKeepalive> {
+        if self.keepalive_time.is_none()
+            && self.keepalive_interval.is_none()
+            && self.keepalive_retries.is_none()
+        {
+            return None;
+        }
+        let mut keepalive = TcpKeepalive::new();
+        if let Some(time) = self.keepalive_time {
+            keepalive = keepalive.with_time(time);
+        }
+        #[cfg(any(
+            target_os = "android",
+            target_os = "freebsd",
+            target_os = "fuchsia",
+            target_os = "illumos",
+            target_os = "ios",
+            target_os = "linux",
+            target_os = "macos",
+            target_os = "netbsd",
+            target_os = "windows",
+        ))]
+        {
+            if let Some(interval) = self.keepalive_interval {
+                keepalive = keepalive.with_interval(interval);
+            }
+            if let Some(retries) = self.keepalive_retries {
+                keepalive = keepalive.with_retries(retries);
+            }
+        }
+        Some(keepalive)
+    }
+
+    // Applies the options to a connected socket.
+    #[cfg(not(target_os = "wasi"))]
+    pub(crate) fn apply<'s, S>(&self, socket: &'s S) -> io::Result<()>
+    where
+        SockRef<'s>: From<&'s S>,
+    {
+        if *self == TcpSettings::default() {
+            return Ok(());
+        }
+        let socket = SockRef::from(socket);
+        if self.nodelay {
+            socket.set_tcp_nodelay(true)?;
+        }
+        if let Some(keepalive) = self.keepalive() {
+            socket.set_tcp_keepalive(&keepalive)?;
+        }
+        #[cfg(any(target_os = "android", target_os = "fuchsia", target_os = "linux"))]
+        socket.set_tcp_user_timeout(self.user_timeout)?;
+        Ok(())
+    }
+
+    #[cfg(target_os = "wasi")]
+    pub(crate) fn apply<S>(&self, _socket: &S) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(all(test, not(target_os = "wasi")))]
+mod tests {
+    use super::*;
+    use std::net::{TcpListener, TcpStream};
+
+    #[test]
+    fn test_apply_tcp_settings() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let tcp = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let settings = TcpSettings::default()
+            .nodelay(true)
+            .keepalive_time(Duration::from_secs(10))
+            .keepalive_interval(Duration::from_secs(5))
+            .keepalive_retries(3)
+            .user_timeout(Duration::from_secs(20));
+        settings.apply(&tcp).unwrap();
+
+        let socket = SockRef::from(&tcp);
+        assert!(socket.tcp_nodelay().unwrap());
+        assert!(socket.keepalive().unwrap());
+        #[cfg(target_os = "linux")]
+        {
+            assert_eq!(
+                socket.tcp_keepalive_time().unwrap(),
+                Duration::from_secs(10)
+            );
+            assert_eq!(socket.tcp_keepalive_retries().unwrap(), 3);
+            assert_eq!(
+                socket.tcp_user_timeout().unwrap(),
+                Some(Duration::from_secs(20))
+            );
+        }
+    }
+}
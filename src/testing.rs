@@ -0,0 +1,634 @@
+//! Connections to unit test code which talks to redis without running a
+//! server.
+//!
+//! [`MockConnection`] is set up with the commands the code under test is
+//! expected to send and the replies to them.  Each command sent is matched
+//! against the expected commands which were not sent yet, in the order they
+//! were added, and answered with the reply of the first one it is equal
+//! to.  Commands which match none of them are recorded and fail with an
+//! `ErrorKind::ClientError`.  When the connection is dropped it panics if
+//! an expected command was not sent or an unexpected one was.
+//!
+//! ```rust
+//! use redis::testing::MockConnection;
+//! use redis::{cmd, Commands, Value};
+//!
+//! let mut con = MockConnection::new()
+//!     .expect(cmd("SET").arg("key").arg(42), Ok(Value::Okay))
+//!     .expect(cmd("GET").arg("key"), Ok(Value::Data(b"42".to_vec())));
+//! let _: () = con.set("key", 42).unwrap();
+//! let value: i64 = con.get("key").unwrap();
+//! assert_eq!(value, 42);
+//! ```
+//!
+//! The commands of a pipeline are matched one by one.  An atomic pipeline
+//! is sent wrapped in `MULTI` and `EXEC`, so its commands are expected to
+//! be replied with `QUEUED` and `EXEC` with the replies of all of them, as
+//! the server does.
+//!
+//! [`Recorder`] wraps a connection to a server and writes the requests sent
+//! through it and the responses to them in the wire format to a file.
+//! [`Replay`] serves the responses of such a recording back, failing when a
+//! request differs from the recorded one, which makes for golden tests of
+//! the traffic of an application and allows debugging protocol issues
+//! without the server.
+//!
+//! ```rust,no_run
+//! # fn do_something() -> redis::RedisResult<()> {
+//! use redis::testing::{Recorder, Replay};
+//! use redis::Commands;
+//!
+//! let client = redis::Client::open("redis://127.0.0.1/")?;
+//! let mut con = Recorder::create(client.get_connection()?, "traffic.resp")?;
+//! let _: () = con.set("key", 42)?;
+//! drop(con);
+//!
+//! let mut con = Replay::open("traffic.resp")?;
+//! let _: () = con.set("key", 42)?;
+//! # Ok(()) }
+//! ```
+//!
+//! With the `test-support` feature, [`TestServer`] and [`TestCluster`]
+//! start throwaway servers for the tests which need a real one.
+use std::borrow::Borrow;
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::thread;
+
+use crate::cmd::Cmd;
+use crate::connection::ConnectionLike;
+use crate::parser::{unpack_commands, Parser, Token, Tokens};
+use crate::types::{ErrorKind, RedisError, RedisResult, Value};
+
+#[cfg(feature = "test-support")]
+mod server;
+#[cfg(feature = "test-support")]
+#[cfg_attr(docsrs, doc(cfg(feature = "test-support")))]
+pub use self::server::{TestCluster, TestServer, TestServerConfig};
+
+// A command split into its arguments, including the name.
+#[derive(PartialEq, Eq)]
+struct Command(Vec<Vec<u8>>);
+
+impl Command {
+    // Splits packed commands into the commands.
+    fn unpack(packed: &[u8]) -> RedisResult<Vec<Command>> {
+        Ok(unpack_commands(packed)?
+            .into_iter()
+            .map(|args| Command(args.into_iter().map(<[u8]>::to_vec).collect()))
+            .collect())
+    }
+}
+
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, arg) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(" ")?;
+            }
+            write!(f, "{}", String::from_utf8_lossy(arg))?;
+        }
+        Ok(())
+    }
+}
+
+struct Expectation {
+    command: Command,
+    reply: RedisResult<Value>,
+}
+
+/// A connection which replies to the commands with scripted replies, see
+/// the [module docs](self).
+#[derive(Default)]
+pub struct MockConnection {
+    expected: Vec<Expectation>,
+    unexpected: Vec<Command>,
+    db: i64,
+}
+
+impl MockConnection {
+    /// Creates a connection which expects no commands.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Expects the command to be sent, replying with `reply`.
+    pub fn expect<C: Borrow<Cmd>>(mut self, cmd: C, reply: RedisResult<Value>) -> Self {
+        let command = Command::unpack(&cmd.borrow().get_packed_command())
+            .expect("commands are packed correctly")
+            .pop()
+            .expect("a command is packed");
+        self.expected.push(Expectation { command, reply });
+        self
+    }
+
+    /// Sets the database the connection claims to be bound to.
+    pub fn db(mut self, db: i64) -> Self {
+        self.db = db;
+        self
+    }
+
+    /// Returns the expected commands which were not sent yet.
+    pub fn pending_commands(&self) -> Vec<String> {
+        self.expected
+            .iter()
+            .map(|expectation| expectation.command.to_string())
+            .collect()
+    }
+
+    /// Returns the commands which were sent but not expected.
+    pub fn unexpected_commands(&self) -> Vec<String> {
+        self.unexpected.iter().map(Command::to_string).collect()
+    }
+
+    fn reply(&mut self, command: Command) -> RedisResult<Value> {
+        match self
+            .expected
+            .iter()
+            .position(|expectation| expectation.command == command)
+        {
+            Some(i) => self.expected.remove(i).reply,
+            None => {
+                let err = RedisError::from((
+                    ErrorKind::ClientError,
+                    "Unexpected command",
+                    command.to_string(),
+                ));
+                self.unexpected.push(command);
+                Err(err)
+            }
+        }
+    }
+
+    fn reply_all(&mut self, packed: &[u8], offset: usize, count: usize) -> RedisResult<Vec<Value>> {
+        let mut first_err = None;
+        let mut replies = Vec::with_capacity(count);
+        for (i, command) in Command::unpack(packed)?.into_iter().enumerate() {
+            match self.reply(command) {
+                Ok(reply) if i >= offset && replies.len() < count => replies.push(reply),
+                Ok(_) => {}
+                Err(err) => {
+                    first_err.get_or_insert(err);
+                }
+            }
+        }
+        match first_err {
+            Some(err) => Err(err),
+            None => Ok(replies),
+        }
+    }
+}
+
+impl fmt::Debug for MockConnection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MockConnection")
+            .field("pending", &self.pending_commands())
+            .field("unexpected", &self.unexpected_commands())
+            .field("db", &self.db)
+            .finish()
+    }
+}
+
+impl Drop for MockConnection {
+    fn drop(&mut self) {
+        if thread::panicking() || (self.expected.is_empty() && self.unexpected.is_empty()) {
+            return;
+        }
+        panic!(
+            "MockConnection dropped with commands not sent: {:?}, commands not expected: {:?}",
+            self.pending_commands(),
+            self.unexpected_commands()
+        );
+    }
+}
+
+impl ConnectionLike for MockConnection {
+    fn req_packed_command(&mut self, cmd: &[u8]) -> RedisResult<Value> {
+        let mut replies = self.reply_all(cmd, 0, 1)?;
+        match replies.pop() {
+            Some(reply) => Ok(reply),
+            None => fail!((ErrorKind::ClientError, "Invalid packed command")),
+        }
+    }
+
+    fn req_packed_commands(
+        &mut self,
+        cmd: &[u8],
+        offset: usize,
+        count: usize,
+    ) -> RedisResult<Vec<Value>> {
+        self.reply_all(cmd, offset, count)
+    }
+
+    fn get_db(&self) -> i64 {
+        self.db
+    }
+
+    fn check_connection(&mut self) -> bool {
+        true
+    }
+
+    fn is_open(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(feature = "aio")]
+impl crate::aio::ConnectionLike for MockConnection {
+    fn req_packed_command<'a>(&'a mut self, cmd: &'a Cmd) -> crate::RedisFuture<'a, Value> {
+        let reply = ConnectionLike::req_packed_command(self, &cmd.get_packed_command());
+        Box::pin(async move { reply })
+    }
+
+    fn req_packed_commands<'a>(
+        &'a mut self,
+        cmd: &'a crate::Pipeline,
+        offset: usize,
+        count: usize,
+    ) -> crate::RedisFuture<'a, Vec<Value>> {
+        let replies = self.reply_all(&cmd.get_packed_pipeline(), offset, count);
+        Box::pin(async move { replies })
+    }
+
+    fn get_db(&self) -> i64 {
+        self.db
+    }
+}
+
+// Encodes the response to a request, which is a single error for failed
+// requests.  Errors which do not come from the server are not recorded.
+fn encode_response(response: &RedisResult<Vec<Value>>) -> Option<Vec<u8>> {
+    match response {
+        Ok(values) => {
+            let mut buf = Vec::new();
+            for value in values {
+                buf.extend(value.to_resp_bytes().ok()?);
+            }
+            Some(buf)
+        }
+        Err(err) => {
+            let code = err.code()?;
+            let line = match err.detail() {
+                Some(detail) => format!("-{} {}\r\n", code, detail),
+                None => format!("-{}\r\n", code),
+            };
+            Some(line.into_bytes())
+        }
+    }
+}
+
+/// A connection which records the requests sent through it and the
+/// responses to them, see the [module docs](self).
+///
+/// Every request is written together with its response, as an array of the
+/// request and the response bytes.  Requests which fail without a response
+/// from the server, like on IO errors, are not recorded.
+pub struct Recorder<C, W = File> {
+    con: C,
+    out: W,
+}
+
+impl<C> Recorder<C> {
+    /// Wraps the connection, recording to the file at `path`, which is
+    /// truncated if it exists.
+    pub fn create<P: AsRef<Path>>(con: C, path: P) -> RedisResult<Self> {
+        Ok(Recorder::new(con, File::create(path)?))
+    }
+}
+
+impl<C, W: Write> Recorder<C, W> {
+    /// Wraps the connection, recording to `out`.
+    pub fn new(con: C, out: W) -> Self {
+        Recorder { con, out }
+    }
+
+    /// Returns a reference to the wrapped connection.
+    pub fn get_ref(&self) -> &C {
+        &self.con
+    }
+
+    /// Returns a mutable reference to the wrapped connection.
+    pub fn get_mut(&mut self) -> &mut C {
+        &mut self.con
+    }
+
+    /// Returns the wrapped connection and the recording.
+    pub fn into_inner(self) -> (C, W) {
+        (self.con, self.out)
+    }
+
+    fn record(&mut self, request: &[u8], response: &RedisResult<Vec<Value>>) -> RedisResult<()> {
+        if let Some(response) = encode_response(response) {
+            let exchange = Value::Bulk(vec![Value::Data(request.to_vec()), Value::Data(response)]);
+            self.out.write_all(&exchange.to_resp_bytes()?)?;
+            self.out.flush()?;
+        }
+        Ok(())
+    }
+}
+
+impl<C: ConnectionLike, W: Write> ConnectionLike for Recorder<C, W> {
+    fn req_packed_command(&mut self, cmd: &[u8]) -> RedisResult<Value> {
+        let response = self.con.req_packed_command(cmd).map(|value| vec![value]);
+        self.record(cmd, &response)?;
+        response.map(|mut values| values.remove(0))
+    }
+
+    fn req_packed_commands(
+        &mut self,
+        cmd: &[u8],
+        offset: usize,
+        count: usize,
+    ) -> RedisResult<Vec<Value>> {
+        let response = self.con.req_packed_commands(cmd, offset, count);
+        self.record(cmd, &response)?;
+        response
+    }
+
+    fn get_db(&self) -> i64 {
+        self.con.get_db()
+    }
+
+    fn supports_pipelining(&self) -> bool {
+        self.con.supports_pipelining()
+    }
+
+    fn check_connection(&mut self) -> bool {
+        self.con.check_connection()
+    }
+
+    fn is_open(&self) -> bool {
+        self.con.is_open()
+    }
+}
+
+#[cfg(feature = "aio")]
+impl<C, W> crate::aio::ConnectionLike for Recorder<C, W>
+where
+    C: crate::aio::ConnectionLike + Send,
+    W: Write + Send,
+{
+    fn req_packed_command<'a>(&'a mut self, cmd: &'a Cmd) -> crate::RedisFuture<'a, Value> {
+        Box::pin(async move {
+            let response = self
+                .con
+                .req_packed_command(cmd)
+                .await
+                .map(|value| vec![value]);
+            self.record(&cmd.get_packed_command(), &response)?;
+            response.map(|mut values| values.remove(0))
+        })
+    }
+
+    fn req_packed_commands<'a>(
+        &'a mut self,
+        cmd: &'a crate::Pipeline,
+        offset: usize,
+        count: usize,
+    ) -> crate::RedisFuture<'a, Vec<Value>> {
+        Box::pin(async move {
+            let response = self.con.req_packed_commands(cmd, offset, count).await;
+            self.record(&cmd.get_packed_pipeline(), &response)?;
+            response
+        })
+    }
+
+    fn get_db(&self) -> i64 {
+        self.con.get_db()
+    }
+}
+
+// Returns the data of the next bulk string of a recording.
+fn next_data(tokens: &mut Tokens) -> RedisResult<Vec<u8>> {
+    loop {
+        match tokens.next().transpose()? {
+            Some(Token::BulkHeader(_)) => {}
+            Some(Token::BulkData(data)) => return Ok(data.to_vec()),
+            _ => fail!((ErrorKind::ClientError, "Invalid recording")),
+        }
+    }
+}
+
+/// A connection which serves the responses of a recording made with
+/// [`Recorder`], see the [module docs](self).
+#[derive(Debug, Default)]
+pub struct Replay {
+    exchanges: VecDeque<(Vec<u8>, Vec<u8>)>,
+    db: i64,
+}
+
+impl Replay {
+    /// Reads the recording in the file at `path`.
+    pub fn open<P: AsRef<Path>>(path: P) -> RedisResult<Self> {
+        Replay::from_bytes(&std::fs::read(path)?)
+    }
+
+    /// Reads the recording from its bytes.
+    pub fn from_bytes(recording: &[u8]) -> RedisResult<Self> {
+        let mut exchanges = VecDeque::new();
+        let mut tokens = Tokens::new(recording);
+        while let Some(token) = tokens.next() {
+            match token? {
+                Token::ArrayHeader(2) => {
+                    exchanges.push_back((next_data(&mut tokens)?, next_data(&mut tokens)?))
+                }
+                _ => fail!((ErrorKind::ClientError, "Invalid recording")),
+            }
+        }
+        Ok(Replay { exchanges, db: 0 })
+    }
+
+    /// Sets the database the connection claims to be bound to.
+    pub fn db(mut self, db: i64) -> Self {
+        self.db = db;
+        self
+    }
+
+    /// Returns the number of recorded requests which were not sent yet.
+    pub fn remaining(&self) -> usize {
+        self.exchanges.len()
+    }
+
+    fn replay(&mut self, request: &[u8], count: usize) -> RedisResult<Vec<Value>> {
+        let (recorded, response) = match self.exchanges.pop_front() {
+            Some(exchange) => exchange,
+            None => fail!((ErrorKind::ClientError, "The recording is exhausted")),
+        };
+        if recorded != request {
+            fail!((
+                ErrorKind::ClientError,
+                "Request does not match the recording",
+                format!(
+                    "sent {:?}, recorded {:?}",
+                    String::from_utf8_lossy(request),
+                    String::from_utf8_lossy(&recorded)
+                )
+            ));
+        }
+        let mut parser = Parser::new();
+        let mut response = &response[..];
+        (0..count)
+            .map(|_| parser.parse_value(&mut response))
+            .collect()
+    }
+}
+
+impl ConnectionLike for Replay {
+    fn req_packed_command(&mut self, cmd: &[u8]) -> RedisResult<Value> {
+        Ok(self.replay(cmd, 1)?.remove(0))
+    }
+
+    fn req_packed_commands(
+        &mut self,
+        cmd: &[u8],
+        _offset: usize,
+        count: usize,
+    ) -> RedisResult<Vec<Value>> {
+        self.replay(cmd, count)
+    }
+
+    fn get_db(&self) -> i64 {
+        self.db
+    }
+
+    fn check_connection(&mut self) -> bool {
+        true
+    }
+
+    fn is_open(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(feature = "aio")]
+impl crate::aio::ConnectionLike for Replay {
+    fn req_packed_command<'a>(&'a mut self, cmd: &'a Cmd) -> crate::RedisFuture<'a, Value> {
+        let response = ConnectionLike::req_packed_command(self, &cmd.get_packed_command());
+        Box::pin(async move { response })
+    }
+
+    fn req_packed_commands<'a>(
+        &'a mut self,
+        cmd: &'a crate::Pipeline,
+        _offset: usize,
+        count: usize,
+    ) -> crate::RedisFuture<'a, Vec<Value>> {
+        let response = self.replay(&cmd.get_packed_pipeline(), count);
+        Box::pin(async move { response })
+    }
+
+    fn get_db(&self) -> i64 {
+        self.db
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cmd::{cmd, pipe};
+    use crate::commands::Commands;
+    use crate::types::make_extension_error;
+
+    #[test]
+    fn test_replies_in_any_order() {
+        let mut con = MockConnection::new()
+            .expect(cmd("GET").arg("a"), Ok(Value::Int(1)))
+            .expect(cmd("GET").arg("b"), Ok(Value::Int(2)));
+        assert_eq!(con.get("b"), Ok(2));
+        assert_eq!(con.get("a"), Ok(1));
+    }
+
+    #[test]
+    fn test_pipeline() {
+        let mut con = MockConnection::new()
+            .expect(cmd("MULTI"), Ok(Value::Okay))
+            .expect(
+                cmd("INCRBY").arg("a").arg(1),
+                Ok(Value::Status("QUEUED".into())),
+            )
+            .expect(
+                cmd("INCRBY").arg("b").arg(1),
+                Ok(Value::Status("QUEUED".into())),
+            )
+            .expect(
+                cmd("EXEC"),
+                Ok(Value::Bulk(vec![Value::Int(1), Value::Int(2)])),
+            );
+        let (a, b): (i64, i64) = pipe()
+            .atomic()
+            .incr("a", 1)
+            .incr("b", 1)
+            .query(&mut con)
+            .unwrap();
+        assert_eq!((a, b), (1, 2));
+    }
+
+    #[test]
+    fn test_records_unexpected_commands() {
+        let mut con = MockConnection::new().expect(cmd("PING"), Ok(Value::Status("PONG".into())));
+        let err = con.get::<_, i64>("a").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ClientError);
+        assert_eq!(con.unexpected_commands(), vec!["GET a".to_string()]);
+        assert_eq!(con.pending_commands(), vec!["PING".to_string()]);
+        // forget the failures, so the drop does not panic
+        con.expected.clear();
+        con.unexpected.clear();
+    }
+
+    #[test]
+    #[should_panic(expected = "commands not sent")]
+    fn test_panics_on_drop_with_pending_commands() {
+        let _con = MockConnection::new().expect(cmd("PING"), Ok(Value::Okay));
+    }
+
+    #[cfg(feature = "aio")]
+    #[test]
+    fn test_async() {
+        let mut con = MockConnection::new().expect(cmd("GET").arg("a"), Ok(Value::Int(1)));
+        let get = cmd("GET").arg("a").clone();
+        let reply = crate::aio::ConnectionLike::req_packed_command(&mut con, &get);
+        assert_eq!(
+            futures_util::FutureExt::now_or_never(reply),
+            Some(Ok(Value::Int(1)))
+        );
+    }
+
+    #[test]
+    fn test_record_and_replay() {
+        let con = MockConnection::new()
+            .expect(cmd("SET").arg("a").arg(1), Ok(Value::Okay))
+            .expect(cmd("GET").arg("a"), Ok(Value::Data(b"1".to_vec())))
+            .expect(
+                cmd("LPUSH").arg("a").arg(2),
+                Err(make_extension_error(
+                    "WRONGTYPE",
+                    Some("Operation against a key holding the wrong kind of value"),
+                )),
+            )
+            .expect(cmd("INCRBY").arg("b").arg(1), Ok(Value::Int(1)))
+            .expect(cmd("INCRBY").arg("c").arg(1), Ok(Value::Int(1)));
+        let mut recorder = Recorder::new(con, Vec::new());
+        let _: () = recorder.set("a", 1).unwrap();
+        assert_eq!(recorder.get("a"), Ok(1));
+        assert!(recorder.lpush::<_, _, i64>("a", 2).is_err());
+        let pipeline = pipe().incr("b", 1).incr("c", 1).clone();
+        let _: (i64, i64) = pipeline.query(&mut recorder).unwrap();
+        let (_, recording) = recorder.into_inner();
+
+        let mut replay = Replay::from_bytes(&recording).unwrap();
+        assert_eq!(replay.remaining(), 4);
+        let _: () = replay.set("a", 1).unwrap();
+        assert_eq!(replay.get("a"), Ok(1));
+        let err = replay.lpush::<_, _, i64>("a", 2).unwrap_err();
+        assert_eq!(err.code(), Some("WRONGTYPE"));
+        assert_eq!(pipeline.query(&mut replay), Ok((1, 1)));
+        assert_eq!(replay.remaining(), 0);
+
+        let mut replay = Replay::from_bytes(&recording).unwrap();
+        let err = replay.get::<_, i64>("b").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ClientError);
+    }
+}
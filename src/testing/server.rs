@@ -0,0 +1,338 @@
+use std::fs;
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use crate::client::Client;
+use crate::cmd::cmd;
+use crate::connection::{ConnectionAddr, ConnectionInfo};
+use crate::tcp::TcpSettings;
+use crate::types::{ErrorKind, RedisError, RedisResult, Value};
+
+// The number of hash slots of a cluster.
+const SLOTS: usize = 16384;
+
+// How long to wait between the checks whether a server is ready.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+static NEXT_DIR: AtomicUsize = AtomicUsize::new(0);
+
+// Creates an empty directory for the files of a server.
+fn server_dir() -> RedisResult<PathBuf> {
+    let dir = std::env::temp_dir().join(format!(
+        "redis-rs-{}-{}",
+        std::process::id(),
+        NEXT_DIR.fetch_add(1, Ordering::Relaxed)
+    ));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+// Finds a free port of localhost.  This is racy, another process may bind
+// the port before the server does.  Cluster nodes also listen on the port
+// plus 10000, so the ports above 55535 are skipped.
+fn free_port() -> RedisResult<u16> {
+    for _ in 0..100 {
+        let port = TcpListener::bind("127.0.0.1:0")?.local_addr()?.port();
+        if port <= 55535 {
+            return Ok(port);
+        }
+    }
+    fail!((ErrorKind::IoError, "Could not find a free port"))
+}
+
+/// The configuration of a [`TestServer`].
+///
+/// By default the `redis-server` found in the `PATH` is started on a random
+/// TCP port of localhost, without persistence, and is given up to 10
+/// seconds to accept connections.
+#[derive(Clone, Debug)]
+pub struct TestServerConfig {
+    program: PathBuf,
+    unix_socket: bool,
+    args: Vec<String>,
+    ready_timeout: Duration,
+}
+
+impl Default for TestServerConfig {
+    fn default() -> Self {
+        TestServerConfig {
+            program: PathBuf::from("redis-server"),
+            unix_socket: false,
+            args: Vec::new(),
+            ready_timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+impl TestServerConfig {
+    /// Sets the server executable to run.
+    pub fn program<P: Into<PathBuf>>(mut self, program: P) -> Self {
+        self.program = program.into();
+        self
+    }
+
+    /// Makes the server listen on a unix socket instead of TCP.
+    #[cfg(unix)]
+    pub fn unix_socket(mut self, unix_socket: bool) -> Self {
+        self.unix_socket = unix_socket;
+        self
+    }
+
+    /// Adds a command line argument of the server, for instance
+    /// `--maxmemory`.
+    pub fn arg<T: Into<String>>(mut self, arg: T) -> Self {
+        self.args.push(arg.into());
+        self
+    }
+
+    /// Sets how long to wait for the server to accept connections.
+    pub fn ready_timeout(mut self, timeout: Duration) -> Self {
+        self.ready_timeout = timeout;
+        self
+    }
+}
+
+/// A throwaway `redis-server` process for tests, which is killed and whose
+/// files are removed when it is dropped.
+///
+/// ```rust,no_run
+/// # fn do_something() -> redis::RedisResult<()> {
+/// use redis::testing::TestServer;
+/// use redis::Commands;
+///
+/// let server = TestServer::start()?;
+/// let mut con = server.client()?.get_connection()?;
+/// let _: () = con.set("key", 42)?;
+/// # Ok(()) }
+/// ```
+pub struct TestServer {
+    process: Child,
+    addr: ConnectionAddr,
+    dir: PathBuf,
+}
+
+impl TestServer {
+    /// Starts a server with the default configuration and waits until it
+    /// accepts connections.
+    pub fn start() -> RedisResult<TestServer> {
+        TestServer::start_with(TestServerConfig::default())
+    }
+
+    /// Starts a server with the configuration and waits until it accepts
+    /// connections.
+    pub fn start_with(config: TestServerConfig) -> RedisResult<TestServer> {
+        let dir = server_dir()?;
+        let mut command = Command::new(&config.program);
+        command
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .arg("--dir")
+            .arg(&dir)
+            .arg("--save")
+            .arg("")
+            .arg("--appendonly")
+            .arg("no");
+        let addr = if config.unix_socket {
+            let path = dir.join("redis.sock");
+            command
+                .arg("--port")
+                .arg("0")
+                .arg("--unixsocket")
+                .arg(&path);
+            ConnectionAddr::Unix(path)
+        } else {
+            let port = free_port()?;
+            command
+                .arg("--bind")
+                .arg("127.0.0.1")
+                .arg("--port")
+                .arg(port.to_string());
+            ConnectionAddr::Tcp("127.0.0.1".to_string(), port)
+        };
+        command.args(&config.args);
+        let process = match command.spawn() {
+            Ok(process) => process,
+            Err(err) => {
+                let _ = fs::remove_dir_all(&dir);
+                fail!((
+                    ErrorKind::IoError,
+                    "Failed to start the server",
+                    format!("{}: {}", config.program.display(), err)
+                ));
+            }
+        };
+        let mut server = TestServer { process, addr, dir };
+        server.wait_ready(config.ready_timeout)?;
+        Ok(server)
+    }
+
+    fn wait_ready(&mut self, timeout: Duration) -> RedisResult<()> {
+        let client = self.client()?;
+        let start = Instant::now();
+        loop {
+            if let Some(status) = self.process.try_wait()? {
+                fail!((ErrorKind::IoError, "The server exited", status.to_string()));
+            }
+            let ping = client
+                .get_connection_with_timeout(timeout)
+                .and_then(|mut con| cmd("PING").query::<Value>(&mut con));
+            match ping {
+                Ok(_) => return Ok(()),
+                Err(err) if start.elapsed() >= timeout => return Err(err),
+                Err(_) => sleep(POLL_INTERVAL),
+            }
+        }
+    }
+
+    /// Returns the address the server listens on.
+    pub fn addr(&self) -> &ConnectionAddr {
+        &self.addr
+    }
+
+    /// Returns the connection info of the server.
+    pub fn connection_info(&self) -> ConnectionInfo {
+        ConnectionInfo {
+            addr: self.addr.clone(),
+            redis: Default::default(),
+            proxy: None,
+            tcp_settings: TcpSettings::default(),
+        }
+    }
+
+    /// Returns a client of the server.
+    pub fn client(&self) -> RedisResult<Client> {
+        Client::open(self.connection_info())
+    }
+
+    /// Kills the server and removes its files.
+    pub fn stop(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// A throwaway cluster of `redis-server` processes for tests, which are
+/// killed when it is dropped.
+///
+/// The nodes are masters without replicas which split the hash slots
+/// evenly.  They always listen on TCP.
+///
+/// ```rust,no_run
+/// # fn do_something() -> redis::RedisResult<()> {
+/// use redis::testing::TestCluster;
+///
+/// let cluster = TestCluster::start(3)?;
+/// let client = redis::Client::open(cluster.nodes()[0].clone())?;
+/// # Ok(()) }
+/// ```
+pub struct TestCluster {
+    servers: Vec<TestServer>,
+}
+
+impl TestCluster {
+    /// Starts a cluster of `nodes` servers with the default configuration
+    /// and waits until its state is ok.
+    pub fn start(nodes: usize) -> RedisResult<TestCluster> {
+        TestCluster::start_with(nodes, TestServerConfig::default())
+    }
+
+    /// Starts a cluster of `nodes` servers with the configuration and waits
+    /// until its state is ok.
+    pub fn start_with(nodes: usize, mut config: TestServerConfig) -> RedisResult<TestCluster> {
+        if nodes == 0 {
+            fail!((
+                ErrorKind::InvalidClientConfig,
+                "A cluster needs at least one node"
+            ));
+        }
+        config.unix_socket = false;
+        let config = config
+            .arg("--cluster-enabled")
+            .arg("yes")
+            .arg("--cluster-node-timeout")
+            .arg("5000");
+        let servers = (0..nodes)
+            .map(|_| TestServer::start_with(config.clone()))
+            .collect::<RedisResult<Vec<_>>>()?;
+
+        let mut first = servers[0].client()?.get_connection()?;
+        for (i, server) in servers.iter().enumerate() {
+            let mut con = server.client()?.get_connection()?;
+            let slots: Vec<usize> = (i * SLOTS / nodes..(i + 1) * SLOTS / nodes).collect();
+            cmd("CLUSTER")
+                .arg("ADDSLOTS")
+                .arg(slots)
+                .query::<()>(&mut con)?;
+            if let ConnectionAddr::Tcp(ref host, port) = server.addr {
+                if i > 0 {
+                    cmd("CLUSTER")
+                        .arg("MEET")
+                        .arg(host)
+                        .arg(port)
+                        .query::<()>(&mut first)?;
+                }
+            }
+        }
+
+        let cluster = TestCluster { servers };
+        cluster.wait_ready(config.ready_timeout)?;
+        Ok(cluster)
+    }
+
+    // Waits until every node knows all the others and that all slots are
+    // served.
+    fn wait_ready(&self, timeout: Duration) -> RedisResult<()> {
+        let start = Instant::now();
+        let ready = format!("cluster_known_nodes:{}", self.servers.len());
+        for server in &self.servers {
+            let mut con = server.client()?.get_connection()?;
+            loop {
+                let info: String = cmd("CLUSTER").arg("INFO").query(&mut con)?;
+                if info.contains("cluster_state:ok") && info.contains(&ready) {
+                    break;
+                }
+                if start.elapsed() >= timeout {
+                    return Err(RedisError::from((
+                        ErrorKind::ClusterDown,
+                        "The cluster did not become ready",
+                        info,
+                    )));
+                }
+                sleep(POLL_INTERVAL);
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the servers of the cluster.
+    pub fn servers(&self) -> &[TestServer] {
+        &self.servers
+    }
+
+    /// Returns the connection infos of the nodes.
+    pub fn nodes(&self) -> Vec<ConnectionInfo> {
+        self.servers
+            .iter()
+            .map(TestServer::connection_info)
+            .collect()
+    }
+
+    /// Returns a cluster client of the cluster.
+    #[cfg(feature = "cluster")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "cluster")))]
+    pub fn client(&self) -> RedisResult<crate::cluster::ClusterClient> {
+        crate::cluster::ClusterClient::open(self.nodes())
+    }
+}
@@ -0,0 +1,339 @@
+use std::fmt;
+
+use crate::connection::{ConnectionAddr, ConnectionInfo};
+use crate::types::{ErrorKind, RedisResult};
+
+#[cfg(feature = "tls-rustls")]
+use std::{
+    convert::TryFrom,
+    io::{BufReader, Cursor},
+    sync::Arc,
+};
+
+#[cfg(feature = "tls-rustls")]
+use rustls::{
+    client::{ServerCertVerified, ServerCertVerifier},
+    Certificate, ClientConfig, OwnedTrustAnchor, PrivateKey, RootCertStore, ServerName,
+};
+
+/// The certificate and private key a client presents to the server for
+/// mutual TLS.
+///
+/// Both are PEM encoded.  The native-tls backend only accepts PKCS#8 keys,
+/// rustls also accepts PKCS#1 and SEC1 keys.
+#[derive(Clone, PartialEq, Eq)]
+pub struct ClientTlsConfig {
+    /// PEM encoded certificate chain of the client.
+    pub client_cert: Vec<u8>,
+    /// PEM encoded private key of the client.
+    pub client_key: Vec<u8>,
+}
+
+impl fmt::Debug for ClientTlsConfig {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ClientTlsConfig")
+            .field("client_cert", &String::from_utf8_lossy(&self.client_cert))
+            .field("client_key", &"<redacted>")
+            .finish()
+    }
+}
+
+/// Certificates used to establish `rediss://` connections.
+///
+/// By default the server certificate is verified against the system (native-tls)
+/// or webpki (rustls) root certificates and no client certificate is sent.
+///
+/// ```rust,no_run
+/// # fn do_something() -> redis::RedisResult<()> {
+/// let ca = std::fs::read("ca.crt")?;
+/// let cert = std::fs::read("client.crt")?;
+/// let key = std::fs::read("client.key")?;
+/// let certificates = redis::TlsCertificates::new()
+///     .root_cert(ca)
+///     .client_cert(cert, key);
+/// let client = redis::Client::build_with_tls("rediss://127.0.0.1/", certificates)?;
+/// # Ok(()) }
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TlsCertificates {
+    /// Certificate and key presented to the server, if any.
+    pub client_tls: Option<ClientTlsConfig>,
+    /// PEM encoded bundle of certificate authorities trusted instead of the
+    /// default roots.
+    pub root_cert: Option<Vec<u8>>,
+    /// Accepts any server certificate, see `ConnectionAddr::TcpTls::insecure`.
+    pub insecure: bool,
+}
+
+impl TlsCertificates {
+    /// Creates certificates which keep the default verification.
+    pub fn new() -> TlsCertificates {
+        TlsCertificates::default()
+    }
+
+    /// Presents the given certificate and private key to the server.
+    pub fn client_cert<C: Into<Vec<u8>>, K: Into<Vec<u8>>>(
+        mut self,
+        cert: C,
+        key: K,
+    ) -> TlsCertificates {
+        self.client_tls = Some(ClientTlsConfig {
+            client_cert: cert.into(),
+            client_key: key.into(),
+        });
+        self
+    }
+
+    /// Trusts the certificate authorities of the PEM bundle instead of the
+    /// default roots.
+    pub fn root_cert<C: Into<Vec<u8>>>(mut self, bundle: C) -> TlsCertificates {
+        self.root_cert = Some(bundle.into());
+        self
+    }
+
+    /// Disables the verification of the server certificate.  Only meant for
+    /// development setups with self signed certificates.
+    pub fn insecure(mut self, insecure: bool) -> TlsCertificates {
+        self.insecure = insecure;
+        self
+    }
+
+    /// Attaches the certificates to a `rediss://` connection info after
+    /// checking that they can be loaded.
+    pub(crate) fn apply(&self, info: &mut ConnectionInfo) -> RedisResult<()> {
+        self.validate()?;
+        match info.addr {
+            ConnectionAddr::TcpTls {
+                ref mut insecure,
+                ref mut certificates,
+                ..
+            } => {
+                *insecure |= self.insecure;
+                *certificates = Some(self.clone());
+                Ok(())
+            }
+            _ => fail!((
+                ErrorKind::InvalidClientConfig,
+                "TLS certificates require a rediss:// address"
+            )),
+        }
+    }
+
+    #[cfg(feature = "tls-rustls")]
+    fn validate(&self) -> RedisResult<()> {
+        create_rustls_config(false, Some(self)).map(drop)
+    }
+
+    #[cfg(all(feature = "tls", not(feature = "tls-rustls")))]
+    fn validate(&self) -> RedisResult<()> {
+        create_native_tls_connector(false, Some(self)).map(drop)
+    }
+
+    #[cfg(not(any(feature = "tls", feature = "tls-rustls")))]
+    fn validate(&self) -> RedisResult<()> {
+        fail!((
+            ErrorKind::InvalidClientConfig,
+            "can't use TLS certificates, the feature is not enabled"
+        ))
+    }
+}
+
+/// Builds the native-tls connector used for `rediss://` connections.
+#[cfg(all(feature = "tls", not(feature = "tls-rustls")))]
+pub(crate) fn create_native_tls_connector(
+    insecure: bool,
+    certificates: Option<&TlsCertificates>,
+) -> RedisResult<native_tls::TlsConnector> {
+    let mut builder = native_tls::TlsConnector::builder();
+    if let Some(certificates) = certificates {
+        if let Some(ref client_tls) = certificates.client_tls {
+            builder.identity(native_tls::Identity::from_pkcs8(
+                &client_tls.client_cert,
+                &client_tls.client_key,
+            )?);
+        }
+        if let Some(ref root_cert) = certificates.root_cert {
+            for cert in pem_certificates(root_cert) {
+                builder.add_root_certificate(native_tls::Certificate::from_pem(cert)?);
+            }
+            builder.disable_built_in_roots(true);
+        }
+    }
+    if insecure {
+        builder
+            .danger_accept_invalid_certs(true)
+            .danger_accept_invalid_hostnames(true)
+            .use_sni(false);
+    }
+    Ok(builder.build()?)
+}
+
+/// Splits a PEM bundle into its certificates since native-tls only reads
+/// the first one.
+#[cfg(all(feature = "tls", not(feature = "tls-rustls")))]
+pub(crate) fn pem_certificates(bundle: &[u8]) -> Vec<&[u8]> {
+    const END: &[u8] = b"-----END CERTIFICATE-----";
+    let mut certs = vec![];
+    let mut rest = bundle;
+    while let Some(pos) = rest.windows(END.len()).position(|w| w == END) {
+        let (cert, tail) = rest.split_at(pos + END.len());
+        certs.push(cert);
+        rest = tail;
+    }
+    certs
+}
+
+/// Builds the rustls configuration used for `rediss://` connections.
+///
+/// The server certificate is verified against the webpki root
+/// certificates, or the custom bundle of the certificates, unless
+/// `insecure` is set, in which case any certificate is accepted and no SNI
+/// extension is sent.
+#[cfg(feature = "tls-rustls")]
+pub(crate) fn create_rustls_config(
+    insecure: bool,
+    certificates: Option<&TlsCertificates>,
+) -> RedisResult<ClientConfig> {
+    let mut root_store = RootCertStore::empty();
+    match certificates.and_then(|c| c.root_cert.as_ref()) {
+        Some(root_cert) => {
+            for cert in load_certs(root_cert)? {
+                root_store.add(&cert)?;
+            }
+        }
+        None => root_store.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|ta| {
+            OwnedTrustAnchor::from_subject_spki_name_constraints(
+                ta.subject,
+                ta.spki,
+                ta.name_constraints,
+            )
+        })),
+    }
+    let builder = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(root_store);
+    let mut config = match certificates.and_then(|c| c.client_tls.as_ref()) {
+        Some(client_tls) => builder.with_client_auth_cert(
+            load_certs(&client_tls.client_cert)?,
+            load_key(&client_tls.client_key)?,
+        )?,
+        None => builder.with_no_client_auth(),
+    };
+    if insecure {
+        config
+            .dangerous()
+            .set_certificate_verifier(Arc::new(NoCertificateVerification));
+        config.enable_sni = false;
+    }
+    Ok(config)
+}
+
+#[cfg(feature = "tls-rustls")]
+fn load_certs(pem: &[u8]) -> RedisResult<Vec<Certificate>> {
+    let certs = rustls_pemfile::certs(&mut BufReader::new(Cursor::new(pem)))?;
+    if certs.is_empty() {
+        fail!((
+            ErrorKind::InvalidClientConfig,
+            "No certificate found in PEM data"
+        ));
+    }
+    Ok(certs.into_iter().map(Certificate).collect())
+}
+
+#[cfg(feature = "tls-rustls")]
+fn load_key(pem: &[u8]) -> RedisResult<PrivateKey> {
+    let mut reader = BufReader::new(Cursor::new(pem));
+    loop {
+        match rustls_pemfile::read_one(&mut reader)? {
+            Some(rustls_pemfile::Item::RSAKey(key))
+            | Some(rustls_pemfile::Item::PKCS8Key(key))
+            | Some(rustls_pemfile::Item::ECKey(key)) => return Ok(PrivateKey(key)),
+            Some(_) => {}
+            None => fail!((
+                ErrorKind::InvalidClientConfig,
+                "No private key found in PEM data"
+            )),
+        }
+    }
+}
+
+/// Converts the host of a `rediss://` address into the name the server
+/// certificate is checked against.
+#[cfg(feature = "tls-rustls")]
+pub(crate) fn server_name(host: &str) -> RedisResult<ServerName> {
+    Ok(ServerName::try_from(host)?)
+}
+
+#[cfg(feature = "tls-rustls")]
+struct NoCertificateVerification;
+
+#[cfg(feature = "tls-rustls")]
+impl ServerCertVerifier for NoCertificateVerification {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &Certificate,
+        _intermediates: &[Certificate],
+        _server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: std::time::SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::IntoConnectionInfo;
+
+    #[test]
+    fn test_debug_redacts_client_key() {
+        let certificates = TlsCertificates::new().client_cert("CERT", "SECRET KEY");
+        let debug = format!("{:?}", certificates);
+        assert!(debug.contains("CERT"));
+        assert!(!debug.contains("SECRET"));
+    }
+
+    #[test]
+    fn test_apply_requires_tls_address() {
+        let mut info = "redis://127.0.0.1/".into_connection_info().unwrap();
+        let err = TlsCertificates::new().apply(&mut info).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidClientConfig);
+    }
+
+    #[cfg(any(feature = "tls", feature = "tls-rustls"))]
+    #[test]
+    fn test_apply_sets_certificates() {
+        let mut info = "rediss://127.0.0.1/".into_connection_info().unwrap();
+        let certificates = TlsCertificates::new().insecure(true);
+        certificates.apply(&mut info).unwrap();
+        assert_eq!(
+            info.addr,
+            ConnectionAddr::TcpTls {
+                host: "127.0.0.1".into(),
+                port: 6379,
+                insecure: true,
+                certificates: Some(certificates),
+            }
+        );
+    }
+
+    #[cfg(feature = "tls-rustls")]
+    #[test]
+    fn test_invalid_pem_is_rejected() {
+        let certificates = TlsCertificates::new().root_cert("not a certificate");
+        let err = create_rustls_config(false, Some(&certificates)).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidClientConfig);
+    }
+
+    #[cfg(all(feature = "tls", not(feature = "tls-rustls")))]
+    #[test]
+    fn test_pem_certificates() {
+        let bundle = b"-----BEGIN CERTIFICATE-----\nA\n-----END CERTIFICATE-----\n\
+                       -----BEGIN CERTIFICATE-----\nB\n-----END CERTIFICATE-----\n";
+        let certs = pem_certificates(bundle);
+        assert_eq!(certs.len(), 2);
+        assert!(certs[1].ends_with(b"B\n-----END CERTIFICATE-----"));
+    }
+}
@@ -1,6 +1,6 @@
 use std::collections::{BTreeMap, BTreeSet};
 use std::collections::{HashMap, HashSet};
-use std::convert::From;
+use std::convert::{From, TryFrom};
 use std::default::Default;
 use std::error;
 use std::fmt;
@@ -8,6 +8,7 @@ use std::hash::{BuildHasher, Hash};
 use std::io;
 use std::str::{from_utf8, Utf8Error};
 use std::string::FromUtf8Error;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 macro_rules! invalid_type_error {
     ($v:expr, $det:expr) => {{
@@ -37,6 +38,31 @@ pub enum NumericBehavior {
     NumberIsFloat,
 }
 
+/// Describes how text received from the server is decoded into strings.
+///
+/// The bytes stored in `Value::Data` are never touched; the policy only
+/// applies when a string is produced from them, and when status and error
+/// lines are parsed.
+#[derive(PartialEq, Eq, Clone, Debug, Copy, Default)]
+pub enum StringDecoding {
+    /// The text must be valid UTF-8.  This is the default.
+    #[default]
+    Utf8Strict,
+    /// Every byte is interpreted as a latin-1 (ISO 8859-1) character.
+    /// Decoding never fails.
+    Latin1,
+}
+
+impl StringDecoding {
+    /// Decodes the bytes into a string according to this policy.
+    pub fn decode(self, bytes: &[u8]) -> Result<String, Utf8Error> {
+        match self {
+            StringDecoding::Utf8Strict => from_utf8(bytes).map(|s| s.to_string()),
+            StringDecoding::Latin1 => Ok(bytes.iter().map(|&b| char::from(b)).collect()),
+        }
+    }
+}
+
 /// An enum of all error kinds.
 #[derive(PartialEq, Eq, Copy, Clone, Debug)]
 #[non_exhaustive]
@@ -79,10 +105,15 @@ pub enum ErrorKind {
     ExtensionError,
     /// Attempt to write to a read-only server
     ReadOnly,
+    /// A reply exceeded one of the limits configured with `ParseLimits`.
+    ParseLimitExceeded,
+    /// An async operation did not complete within its timeout, like a
+    /// request waiting for its reply or a checkout from a connection pool.
+    Timeout,
 }
 
 /// Internal low-level redis value enum.
-#[derive(PartialEq, Eq, Clone)]
+#[derive(Clone)]
 pub enum Value {
     /// A nil response from the server.
     Nil,
@@ -100,20 +131,272 @@ pub enum Value {
     Status(String),
     /// A status response which represents the string "OK".
     Okay,
+    /// A RESP3 map of key/value pairs, in the order sent by the server.
+    Map(Vec<(Value, Value)>),
+    /// A RESP3 set of unordered values.
+    Set(Vec<Value>),
+    /// A RESP3 double.
+    Double(f64),
+    /// A RESP3 boolean.
+    Boolean(bool),
+    /// A RESP3 big number, kept as its decimal text.
+    BigNumber(String),
+    /// A RESP3 verbatim string.  `format` is the three letter type of the
+    /// text, usually `txt` or `mkd`.
+    VerbatimString {
+        /// The format of the text.
+        format: String,
+        /// The text itself.
+        text: String,
+    },
+    /// A RESP3 push frame sent out of band by the server.
+    Push {
+        /// The kind of the push, such as `invalidate` or `message`.
+        kind: String,
+        /// The elements following the kind.
+        data: Vec<Value>,
+    },
+    /// A RESP3 value sent with attributes, auxiliary key/value pairs such
+    /// as key popularity hints which are not part of the reply itself.
+    Attribute {
+        /// The value the attributes were sent with.
+        data: Box<Value>,
+        /// The attributes, in the order sent by the server.
+        attributes: Vec<(Value, Value)>,
+    },
+    /// A server error nested in an aggregate, such as the reply of a
+    /// failed command in the reply of `EXEC`, kept as its line like
+    /// `ERR unknown command`.  These are only parsed by a `Parser` with
+    /// `set_error_values` enabled, otherwise such an error fails the whole
+    /// reply.
+    ServerError(String),
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Nil, Value::Nil) => true,
+            (Value::Int(a), Value::Int(b)) => a == b,
+            (Value::Data(a), Value::Data(b)) => a == b,
+            (Value::Bulk(a), Value::Bulk(b)) => a == b,
+            (Value::Status(a), Value::Status(b)) => a == b,
+            (Value::Okay, Value::Okay) => true,
+            (Value::Map(a), Value::Map(b)) => a == b,
+            (Value::Set(a), Value::Set(b)) => a == b,
+            // doubles are compared by their bits so that `Value` stays `Eq`
+            (Value::Double(a), Value::Double(b)) => a.to_bits() == b.to_bits(),
+            (Value::Boolean(a), Value::Boolean(b)) => a == b,
+            (Value::BigNumber(a), Value::BigNumber(b)) => a == b,
+            (
+                Value::VerbatimString { format, text },
+                Value::VerbatimString {
+                    format: other_format,
+                    text: other_text,
+                },
+            ) => format == other_format && text == other_text,
+            (
+                Value::Push { kind, data },
+                Value::Push {
+                    kind: other_kind,
+                    data: other_data,
+                },
+            ) => kind == other_kind && data == other_data,
+            (
+                Value::Attribute { data, attributes },
+                Value::Attribute {
+                    data: other_data,
+                    attributes: other_attributes,
+                },
+            ) => data == other_data && attributes == other_attributes,
+            (Value::ServerError(a), Value::ServerError(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Value {}
+
+/// A redis value which borrows its binary data and statuses from the
+/// buffer it was parsed from, as returned by `parse_redis_value_ref`.
+///
+/// The variants have the same meaning as the ones of `Value`.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum ValueRef<'a> {
+    /// A nil response from the server.
+    Nil,
+    /// An integer response.
+    Int(i64),
+    /// An arbitary binary data borrowed from the parsed buffer.
+    Data(&'a [u8]),
+    /// A bulk response of more data.
+    Bulk(Vec<ValueRef<'a>>),
+    /// A status response borrowed from the parsed buffer.
+    Status(&'a str),
+    /// A status response which represents the string "OK".
+    Okay,
+}
+
+impl<'a> ValueRef<'a> {
+    /// Copies the borrowed data into an owned `Value`.
+    pub fn to_value(&self) -> Value {
+        match *self {
+            ValueRef::Nil => Value::Nil,
+            ValueRef::Int(value) => Value::Int(value),
+            ValueRef::Data(data) => Value::Data(data.to_vec()),
+            ValueRef::Bulk(ref items) => {
+                Value::Bulk(items.iter().map(ValueRef::to_value).collect())
+            }
+            ValueRef::Status(status) => Value::Status(status.to_string()),
+            ValueRef::Okay => Value::Okay,
+        }
+    }
+}
+
+/// A redis value whose binary data are `Bytes` slices of the buffer it was
+/// parsed from, as returned by `parse_redis_value_bytes`.
+///
+/// The variants have the same meaning as the ones of `Value`.
+#[cfg(feature = "bytes")]
+#[cfg_attr(docsrs, doc(cfg(feature = "bytes")))]
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum BytesValue {
+    /// A nil response from the server.
+    Nil,
+    /// An integer response.
+    Int(i64),
+    /// An arbitary binary data sharing the parsed buffer.
+    Data(bytes::Bytes),
+    /// A bulk response of more data.
+    Bulk(Vec<BytesValue>),
+    /// A status response.
+    Status(String),
+    /// A status response which represents the string "OK".
+    Okay,
+}
+
+#[cfg(feature = "bytes")]
+impl From<BytesValue> for Value {
+    fn from(value: BytesValue) -> Value {
+        match value {
+            BytesValue::Nil => Value::Nil,
+            BytesValue::Int(value) => Value::Int(value),
+            BytesValue::Data(data) => Value::Data(data.to_vec()),
+            BytesValue::Bulk(items) => Value::Bulk(items.into_iter().map(Value::from).collect()),
+            BytesValue::Status(status) => Value::Status(status),
+            BytesValue::Okay => Value::Okay,
+        }
+    }
+}
+
+/// The binary data of a `LazyValue`.
+#[cfg(feature = "bytes")]
+#[cfg_attr(docsrs, doc(cfg(feature = "bytes")))]
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum LazyData {
+    /// Data which was copied while parsing.
+    Owned(Vec<u8>),
+    /// Data which still references the parsed buffer and is copied on
+    /// demand.
+    Shared(bytes::Bytes),
+}
+
+#[cfg(feature = "bytes")]
+impl LazyData {
+    /// Returns the data without copying it.
+    pub fn as_bytes(&self) -> &[u8] {
+        match *self {
+            LazyData::Owned(ref data) => data,
+            LazyData::Shared(ref data) => data,
+        }
+    }
+
+    /// Returns whether the data still references the parsed buffer.
+    pub fn is_shared(&self) -> bool {
+        matches!(*self, LazyData::Shared(_))
+    }
+
+    /// Returns the data as an owned vector, copying it out of the parsed
+    /// buffer the first time it is needed.
+    pub fn to_mut(&mut self) -> &mut Vec<u8> {
+        if let LazyData::Shared(ref data) = *self {
+            *self = LazyData::Owned(data.to_vec());
+        }
+        match *self {
+            LazyData::Owned(ref mut data) => data,
+            LazyData::Shared(_) => unreachable!(),
+        }
+    }
+
+    /// Converts the data into an owned vector, copying it if needed.
+    pub fn into_vec(self) -> Vec<u8> {
+        match self {
+            LazyData::Owned(data) => data,
+            LazyData::Shared(data) => data.to_vec(),
+        }
+    }
+}
+
+/// A redis value whose large binary data reference the buffer it was
+/// parsed from, as returned by `parse_redis_value_lazy`.
+///
+/// The variants have the same meaning as the ones of `Value`.
+#[cfg(feature = "bytes")]
+#[cfg_attr(docsrs, doc(cfg(feature = "bytes")))]
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum LazyValue {
+    /// A nil response from the server.
+    Nil,
+    /// An integer response.
+    Int(i64),
+    /// An arbitary binary data which is only copied when needed.
+    Data(LazyData),
+    /// A bulk response of more data.
+    Bulk(Vec<LazyValue>),
+    /// A status response.
+    Status(String),
+    /// A status response which represents the string "OK".
+    Okay,
+}
+
+#[cfg(feature = "bytes")]
+impl From<LazyValue> for Value {
+    fn from(value: LazyValue) -> Value {
+        match value {
+            LazyValue::Nil => Value::Nil,
+            LazyValue::Int(value) => Value::Int(value),
+            LazyValue::Data(data) => Value::Data(data.into_vec()),
+            LazyValue::Bulk(items) => Value::Bulk(items.into_iter().map(Value::from).collect()),
+            LazyValue::Status(status) => Value::Status(status),
+            LazyValue::Okay => Value::Okay,
+        }
+    }
 }
 
-pub struct MapIter<'a>(std::slice::Iter<'a, Value>);
+pub struct MapIter<'a>(MapIterInner<'a>);
+
+enum MapIterInner<'a> {
+    Bulk(std::slice::Iter<'a, Value>),
+    Map(std::slice::Iter<'a, (Value, Value)>),
+}
 
 impl<'a> Iterator for MapIter<'a> {
     type Item = (&'a Value, &'a Value);
 
     fn next(&mut self) -> Option<Self::Item> {
-        Some((self.0.next()?, self.0.next()?))
+        match self.0 {
+            MapIterInner::Bulk(ref mut iter) => Some((iter.next()?, iter.next()?)),
+            MapIterInner::Map(ref mut iter) => iter.next().map(|(k, v)| (k, v)),
+        }
     }
 
     fn size_hint(&self) -> (usize, Option<usize>) {
-        let (low, high) = self.0.size_hint();
-        (low / 2, high.map(|h| h / 2))
+        match self.0 {
+            MapIterInner::Bulk(ref iter) => {
+                let (low, high) = iter.size_hint();
+                (low / 2, high.map(|h| h / 2))
+            }
+            MapIterInner::Map(ref iter) => iter.size_hint(),
+        }
     }
 }
 
@@ -153,10 +436,159 @@ impl Value {
         }
     }
 
+    /// Shortens every data and status value longer than `max` bytes,
+    /// recursing into bulks.
+    ///
+    /// A marker like `...[+N bytes]` is appended to shortened values, which
+    /// makes the result suitable for logging large replies.  Statuses are
+    /// only cut at character boundaries.
+    pub fn truncate_data(&mut self, max: usize) {
+        match *self {
+            Value::Data(ref mut data) if data.len() > max => {
+                let dropped = data.len() - max;
+                data.truncate(max);
+                data.extend_from_slice(format!("...[+{} bytes]", dropped).as_bytes());
+            }
+            Value::Status(ref mut status) if status.len() > max => {
+                let mut end = max;
+                while !status.is_char_boundary(end) {
+                    end -= 1;
+                }
+                let dropped = status.len() - end;
+                status.truncate(end);
+                status.push_str(&format!("...[+{} bytes]", dropped));
+            }
+            Value::Bulk(ref mut items)
+            | Value::Set(ref mut items)
+            | Value::Push {
+                data: ref mut items,
+                ..
+            } => {
+                for item in items {
+                    item.truncate_data(max);
+                }
+            }
+            Value::Map(ref mut items) => {
+                for (key, value) in items {
+                    key.truncate_data(max);
+                    value.truncate_data(max);
+                }
+            }
+            Value::Attribute {
+                ref mut data,
+                ref mut attributes,
+            } => {
+                data.truncate_data(max);
+                for (key, value) in attributes {
+                    key.truncate_data(max);
+                    value.truncate_data(max);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Returns the RESP3 attributes the value was sent with, if any.
+    pub fn attributes(&self) -> Option<&[(Value, Value)]> {
+        match *self {
+            Value::Attribute { ref attributes, .. } => Some(attributes),
+            _ => None,
+        }
+    }
+
+    /// Returns the value without the RESP3 attributes it was sent with.
+    pub fn without_attributes(&self) -> &Value {
+        match *self {
+            Value::Attribute { ref data, .. } => data.without_attributes(),
+            _ => self,
+        }
+    }
+
+    /// Replaces every RESP3 verbatim string with a data value of its text,
+    /// recursing into aggregates, for code which does not care about the
+    /// format of the text.
+    pub fn strip_verbatim_formats(self) -> Value {
+        fn strip_pairs(pairs: Vec<(Value, Value)>) -> Vec<(Value, Value)> {
+            pairs
+                .into_iter()
+                .map(|(key, value)| (key.strip_verbatim_formats(), value.strip_verbatim_formats()))
+                .collect()
+        }
+
+        match self {
+            Value::VerbatimString { text, .. } => Value::Data(text.into_bytes()),
+            Value::Bulk(items) => Value::Bulk(
+                items
+                    .into_iter()
+                    .map(Value::strip_verbatim_formats)
+                    .collect(),
+            ),
+            Value::Set(items) => Value::Set(
+                items
+                    .into_iter()
+                    .map(Value::strip_verbatim_formats)
+                    .collect(),
+            ),
+            Value::Push { kind, data } => Value::Push {
+                kind,
+                data: data
+                    .into_iter()
+                    .map(Value::strip_verbatim_formats)
+                    .collect(),
+            },
+            Value::Map(items) => Value::Map(strip_pairs(items)),
+            Value::Attribute { data, attributes } => Value::Attribute {
+                data: Box::new(data.strip_verbatim_formats()),
+                attributes: strip_pairs(attributes),
+            },
+            value => value,
+        }
+    }
+
+    /// Returns the error of a `Value::ServerError`, or converts any other
+    /// value with `FromRedisValue`.
+    ///
+    /// ```rust
+    /// # use redis::{ErrorKind, Value};
+    /// assert_eq!(Value::Int(1).into_result::<i64>().unwrap(), 1);
+    /// let err = Value::ServerError("ERR no such key".into())
+    ///     .into_result::<i64>()
+    ///     .unwrap_err();
+    /// assert_eq!(err.kind(), ErrorKind::ResponseError);
+    /// ```
+    pub fn into_result<T: FromRedisValue>(self) -> RedisResult<T> {
+        match self {
+            Value::ServerError(line) => Err(crate::parser::server_error(&line)),
+            value => from_redis_value(&value),
+        }
+    }
+
+    /// Returns a copy of the value shortened with `truncate_data`.
+    pub fn truncated(&self, max: usize) -> Value {
+        let mut value = self.clone();
+        value.truncate_data(max);
+        value
+    }
+
+    /// Returns the boolean meaning of an integer reply of `1` or `0`, as
+    /// sent by commands like `EXPIRE`, `SETNX` or `SISMEMBER`, or of a
+    /// RESP3 boolean.
+    ///
+    /// Any other value, including other integers, returns `None`.
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self {
+            Value::Int(1) => Some(true),
+            Value::Int(0) => Some(false),
+            Value::Boolean(val) => Some(val),
+            _ => None,
+        }
+    }
+
     /// Returns an `&[Value]` if `self` is compatible with a sequence type
     pub fn as_sequence(&self) -> Option<&[Value]> {
         match self {
             Value::Bulk(items) => Some(&items[..]),
+            Value::Set(items) => Some(&items[..]),
             Value::Nil => Some(&[]),
             _ => None,
         }
@@ -165,10 +597,206 @@ impl Value {
     /// Returns an iterator of `(&Value, &Value)` if `self` is compatible with a map type
     pub fn as_map_iter(&self) -> Option<MapIter<'_>> {
         match self {
-            Value::Bulk(items) => Some(MapIter(items.iter())),
+            Value::Bulk(items) => Some(MapIter(MapIterInner::Bulk(items.iter()))),
+            Value::Map(items) => Some(MapIter(MapIterInner::Map(items.iter()))),
             _ => None,
         }
     }
+
+    /// Decodes the reply of `LATENCY LATEST` into one entry per event.
+    pub fn into_latency_latest(self) -> RedisResult<Vec<LatencyLatest>> {
+        let items = match self {
+            Value::Bulk(items) => items,
+            Value::Nil => return Ok(vec![]),
+            v => invalid_type_error!(v, "LATENCY LATEST response is not a bulk"),
+        };
+        items
+            .iter()
+            .map(|item| match item.as_sequence() {
+                Some([event, timestamp, latest, max]) => Ok(LatencyLatest {
+                    event: from_redis_value(event)?,
+                    timestamp: reply_int(timestamp, "Invalid LATENCY LATEST timestamp")?,
+                    latest_ms: reply_int(latest, "Invalid LATENCY LATEST latency")?,
+                    max_ms: reply_int(max, "Invalid LATENCY LATEST max latency")?,
+                }),
+                _ => invalid_type_error!(item, "LATENCY LATEST entry must have four elements"),
+            })
+            .collect()
+    }
+
+    /// Checks that the value is the `OK` status reply.
+    ///
+    /// Admin commands like `FAILOVER` reply with `OK` once they have been
+    /// accepted and with an error if they were rejected, so
+    /// `query::<Value>(..)?.expect_ok()` tells both outcomes apart while the
+    /// rejection reason stays available through `RedisError::code` and
+    /// `RedisError::detail`.
+    pub fn expect_ok(&self) -> RedisResult<()> {
+        match *self {
+            Value::Okay => Ok(()),
+            _ => invalid_type_error!(self, "Expected an OK reply"),
+        }
+    }
+
+    /// Serializes the value in the wire format, the inverse of
+    /// `parse_redis_value`.  See `write_reply` for the values which cannot
+    /// be encoded.
+    ///
+    /// ```rust
+    /// let value = redis::Value::Bulk(vec![redis::Value::Int(1), redis::Value::Okay]);
+    /// let bytes = value.to_resp_bytes().unwrap();
+    /// assert_eq!(bytes, b"*2\r\n:1\r\n+OK\r\n");
+    /// assert_eq!(redis::parse_redis_value(&bytes).unwrap(), value);
+    /// ```
+    pub fn to_resp_bytes(&self) -> RedisResult<Vec<u8>> {
+        let mut buf = Vec::new();
+        crate::parser::write_reply(self, &mut buf)?;
+        Ok(buf)
+    }
+
+    /// Converts a string compatible value into a `String`, decoding binary
+    /// data with the given policy instead of assuming UTF-8.
+    pub fn decode_string(&self, decoding: StringDecoding) -> RedisResult<String> {
+        match *self {
+            Value::Data(ref bytes) => Ok(decoding.decode(bytes)?),
+            _ => from_redis_value(self),
+        }
+    }
+
+    /// Decodes the reply of `CLIENT TRACKINGINFO`.
+    ///
+    /// Fields may appear in any order and unknown fields are ignored.
+    pub fn into_tracking_info(self) -> RedisResult<TrackingInfo> {
+        let pairs = match self.as_map_iter() {
+            Some(pairs) => pairs,
+            None => invalid_type_error!(self, "CLIENT TRACKINGINFO response is not a map"),
+        };
+        let mut info = TrackingInfo::default();
+        for (key, value) in pairs {
+            let key: String = from_redis_value(key)?;
+            match key.as_str() {
+                "flags" => info.flags = from_redis_value(value)?,
+                "redirect" => info.redirect = from_redis_value(value)?,
+                "prefixes" => info.prefixes = from_redis_value(value)?,
+                _ => {}
+            }
+        }
+        Ok(info)
+    }
+
+    /// Decodes the reply of `LATENCY HISTORY` into its samples.
+    pub fn into_latency_history(self) -> RedisResult<Vec<LatencySample>> {
+        let items = match self {
+            Value::Bulk(items) => items,
+            Value::Nil => return Ok(vec![]),
+            v => invalid_type_error!(v, "LATENCY HISTORY response is not a bulk"),
+        };
+        items
+            .iter()
+            .map(|item| match item.as_sequence() {
+                Some([timestamp, latency]) => Ok(LatencySample {
+                    timestamp: reply_int(timestamp, "Invalid LATENCY HISTORY timestamp")?,
+                    latency_ms: reply_int(latency, "Invalid LATENCY HISTORY latency")?,
+                }),
+                _ => invalid_type_error!(item, "LATENCY HISTORY sample must have two elements"),
+            })
+            .collect()
+    }
+
+    /// Decodes a client side caching invalidation message.
+    ///
+    /// Both the `invalidate` push frame (`[invalidate, keys]`) and the
+    /// message published on the `__redis__:invalidate` channel when
+    /// invalidations are redirected (`[message, channel, keys]`) are
+    /// recognized, either as RESP3 push frames or as arrays.  A nil payload
+    /// means that all keys were flushed.
+    pub fn into_invalidation(self) -> RedisResult<Invalidation> {
+        if let Value::Push { ref kind, ref data } = self {
+            match (kind.as_str(), &data[..]) {
+                ("invalidate", [payload]) => return invalidation_payload(payload),
+                ("message", [channel, payload])
+                    if *channel == Value::Data(b"__redis__:invalidate".to_vec()) =>
+                {
+                    return invalidation_payload(payload)
+                }
+                _ => invalid_type_error!(self, "Value is not an invalidation message"),
+            }
+        }
+        match self.as_sequence() {
+            Some([kind, payload]) if *kind == Value::Data(b"invalidate".to_vec()) => {
+                invalidation_payload(payload)
+            }
+            Some([kind, channel, payload])
+                if *kind == Value::Data(b"message".to_vec())
+                    && *channel == Value::Data(b"__redis__:invalidate".to_vec()) =>
+            {
+                invalidation_payload(payload)
+            }
+            _ => invalid_type_error!(self, "Value is not an invalidation message"),
+        }
+    }
+
+    /// Decodes the `[ip, port]` reply of `SENTINEL GET-MASTER-ADDR-BY-NAME`.
+    ///
+    /// Fails if the sentinel does not know the master or if the port is
+    /// outside of the `u16` range.
+    pub fn into_sentinel_master_addr(self) -> RedisResult<(String, u16)> {
+        match self.as_sequence() {
+            Some([ip, port]) => Ok((from_redis_value(ip)?, sentinel_port(port)?)),
+            _ => invalid_type_error!(self, "Sentinel master address must be an [ip, port] pair"),
+        }
+    }
+
+    /// Decodes the reply of `SENTINEL MASTERS` into one field map per
+    /// monitored master.
+    pub fn into_sentinel_masters(self) -> RedisResult<Vec<HashMap<String, String>>> {
+        let items = match self {
+            Value::Bulk(items) => items,
+            v => invalid_type_error!(v, "SENTINEL MASTERS response is not a bulk"),
+        };
+        items
+            .iter()
+            .map(|item| match item.as_map_iter() {
+                Some(pairs) => pairs
+                    .map(|(k, v)| Ok((from_redis_value(k)?, from_redis_value(v)?)))
+                    .collect(),
+                None => invalid_type_error!(item, "Sentinel master entry is not a map"),
+            })
+            .collect()
+    }
+}
+
+fn invalidation_payload(payload: &Value) -> RedisResult<Invalidation> {
+    match *payload {
+        Value::Nil => Ok(Invalidation::FlushAll),
+        Value::Bulk(_) => Ok(Invalidation::Keys(from_redis_value(payload)?)),
+        _ => invalid_type_error!(
+            payload,
+            "Invalidation payload must be a list of keys or nil"
+        ),
+    }
+}
+
+fn sentinel_port(v: &Value) -> RedisResult<u16> {
+    let port = reply_int(v, "Invalid sentinel port")?;
+    match u16::try_from(port) {
+        Ok(port) => Ok(port),
+        Err(_) => fail!((
+            ErrorKind::TypeError,
+            "Sentinel port out of range",
+            port.to_string()
+        )),
+    }
+}
+
+fn reply_int(v: &Value, what: &'static str) -> RedisResult<i64> {
+    match *v {
+        Value::Int(val) => Ok(val),
+        Value::Data(_) | Value::Status(_) => {
+            from_redis_value(v).map_err(|_| invalid_type_error_inner!(v, what))
+        }
+        _ => invalid_type_error!(v, what),
+    }
 }
 
 impl fmt::Debug for Value {
@@ -194,6 +822,55 @@ impl fmt::Debug for Value {
             }
             Value::Okay => write!(fmt, "ok"),
             Value::Status(ref s) => write!(fmt, "status({:?})", s),
+            Value::Map(ref values) => {
+                write!(fmt, "map(")?;
+                let mut is_first = true;
+                for (key, val) in values.iter() {
+                    if !is_first {
+                        write!(fmt, ", ")?;
+                    }
+                    write!(fmt, "{:?}: {:?}", key, val)?;
+                    is_first = false;
+                }
+                write!(fmt, ")")
+            }
+            Value::Set(ref values) => {
+                write!(fmt, "set(")?;
+                let mut is_first = true;
+                for val in values.iter() {
+                    if !is_first {
+                        write!(fmt, ", ")?;
+                    }
+                    write!(fmt, "{:?}", val)?;
+                    is_first = false;
+                }
+                write!(fmt, ")")
+            }
+            Value::Double(val) => write!(fmt, "double({:?})", val),
+            Value::Boolean(val) => write!(fmt, "boolean({:?})", val),
+            Value::BigNumber(ref val) => write!(fmt, "big-number({})", val),
+            Value::VerbatimString {
+                ref format,
+                ref text,
+            } => write!(fmt, "verbatim-string({}, {:?})", format, text),
+            Value::Push { ref kind, ref data } => {
+                write!(fmt, "push({:?}", kind)?;
+                for val in data.iter() {
+                    write!(fmt, ", {:?}", val)?;
+                }
+                write!(fmt, ")")
+            }
+            Value::Attribute {
+                ref data,
+                ref attributes,
+            } => {
+                write!(fmt, "attribute({:?}", data)?;
+                for (key, val) in attributes.iter() {
+                    write!(fmt, ", {:?}: {:?}", key, val)?;
+                }
+                write!(fmt, ")")
+            }
+            Value::ServerError(ref line) => write!(fmt, "server-error({:?})", line),
         }
     }
 }
@@ -260,6 +937,32 @@ impl From<native_tls::Error> for RedisError {
     }
 }
 
+#[cfg(feature = "tls-rustls")]
+impl From<rustls::Error> for RedisError {
+    fn from(err: rustls::Error) -> RedisError {
+        RedisError {
+            repr: ErrorRepr::WithDescriptionAndDetail(
+                ErrorKind::IoError,
+                "TLS error",
+                err.to_string(),
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "tls-rustls")]
+impl From<rustls::client::InvalidDnsNameError> for RedisError {
+    fn from(err: rustls::client::InvalidDnsNameError) -> RedisError {
+        RedisError {
+            repr: ErrorRepr::WithDescriptionAndDetail(
+                ErrorKind::InvalidClientConfig,
+                "Invalid TLS server name",
+                err.to_string(),
+            ),
+        }
+    }
+}
+
 impl From<FromUtf8Error> for RedisError {
     fn from(_: FromUtf8Error) -> RedisError {
         RedisError {
@@ -390,6 +1093,8 @@ impl RedisError {
             ErrorKind::ExtensionError => "extension error",
             ErrorKind::ClientError => "client error",
             ErrorKind::ReadOnly => "read-only",
+            ErrorKind::ParseLimitExceeded => "parse limit exceeded",
+            ErrorKind::Timeout => "timeout",
         }
     }
 
@@ -442,7 +1147,7 @@ impl RedisError {
                 err.kind(),
                 io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock
             ),
-            _ => false,
+            _ => self.kind() == ErrorKind::Timeout,
         }
     }
 
@@ -459,7 +1164,8 @@ impl RedisError {
 
     /// Returns the node the error refers to.
     ///
-    /// This returns `(addr, slot_id)`.
+    /// This returns `(addr, slot_id)`, or `None` if the error is not a
+    /// redirect or if its detail is missing or malformed.
     pub fn redirect_node(&self) -> Option<(&str, u16)> {
         match self.kind() {
             ErrorKind::Ask | ErrorKind::Moved => (),
@@ -491,7 +1197,6 @@ impl RedisError {
     ///
     /// The `ioerror_description` parameter will be prepended to the message in
     /// case an `IoError` is found.
-    #[cfg(feature = "connection-manager")] // Used to avoid "unused method" warning
     pub(crate) fn clone_mostly(&self, ioerror_description: &'static str) -> Self {
         let repr = match self.repr {
             ErrorRepr::WithDescription(kind, desc) => ErrorRepr::WithDescription(kind, desc),
@@ -600,6 +1305,93 @@ impl InfoDict {
     }
 }
 
+/// A single entry of the reply to the [`LATENCY LATEST`][1] command.
+///
+/// [1]: https://redis.io/commands/latency-latest
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LatencyLatest {
+    /// The name of the monitored event.
+    pub event: String,
+    /// Unix timestamp of the latest latency spike for the event.
+    pub timestamp: i64,
+    /// The latest event latency in milliseconds.
+    pub latest_ms: i64,
+    /// The all-time maximum latency for the event in milliseconds.
+    pub max_ms: i64,
+}
+
+/// A single sample of the reply to the [`LATENCY HISTORY`][1] command.
+///
+/// [1]: https://redis.io/commands/latency-history
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LatencySample {
+    /// Unix timestamp of the sample.
+    pub timestamp: i64,
+    /// The latency of the event in milliseconds.
+    pub latency_ms: i64,
+}
+
+/// The tracking state of a connection as reported by the
+/// [`CLIENT TRACKINGINFO`][1] command.
+///
+/// [1]: https://redis.io/commands/client-trackinginfo
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TrackingInfo {
+    /// The tracking flags of the connection, such as `on`, `bcast` or `optin`.
+    pub flags: Vec<String>,
+    /// The client id invalidation messages are redirected to.  `0` means
+    /// they are not redirected and `-1` that tracking is not enabled.
+    pub redirect: i64,
+    /// The key prefixes tracked in broadcasting mode.
+    pub prefixes: Vec<String>,
+}
+
+/// A command processed by the server, as reported by the [`MONITOR`][1]
+/// command.
+///
+/// [1]: https://redis.io/commands/monitor
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MonitorEntry {
+    /// When the server processed the command.
+    pub timestamp: SystemTime,
+    /// The database the command was run in.
+    pub db: i64,
+    /// The address of the client which sent the command, `lua` for the
+    /// commands of scripts, or `unix:<path>` for a unix socket.
+    pub client: String,
+    /// The name of the command, as sent by the client.
+    pub command: String,
+    /// The arguments of the command.
+    pub args: Vec<Vec<u8>>,
+}
+
+/// An out of band message pushed by the server over a RESP3 connection.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PushMessage {
+    /// The kind of the message, such as `invalidate` or `message`.
+    pub kind: String,
+    /// The elements following the kind.
+    pub data: Vec<Value>,
+}
+
+impl From<PushMessage> for Value {
+    fn from(msg: PushMessage) -> Value {
+        Value::Push {
+            kind: msg.kind,
+            data: msg.data,
+        }
+    }
+}
+
+/// A client side caching invalidation sent by the server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Invalidation {
+    /// The given keys were modified.
+    Keys(Vec<Vec<u8>>),
+    /// The whole keyspace was flushed.
+    FlushAll,
+}
+
 /// Abstraction trait for redis command abstractions.
 pub trait RedisWrite {
     /// Accepts a serialized redis command.
@@ -1016,7 +1808,7 @@ pub trait FromRedisValue: Sized {
     fn from_redis_values(items: &[Value]) -> RedisResult<Vec<Self>> {
         Ok(items
             .iter()
-            .filter_map(|item| FromRedisValue::from_redis_value(item).ok())
+            .filter_map(|item| from_redis_value(item).ok())
             .collect())
     }
 
@@ -1028,6 +1820,45 @@ pub trait FromRedisValue: Sized {
     }
 }
 
+// Converts the doubles of RESP3 replies.  Integers are only converted from
+// doubles without a fraction which are in their range.
+trait FromDouble: Sized {
+    fn from_double(val: f64) -> Option<Self>;
+}
+
+macro_rules! from_double_for_int {
+    ($($t:ty),*) => {
+        $(
+            impl FromDouble for $t {
+                fn from_double(val: f64) -> Option<$t> {
+                    // `MAX as f64` rounds up to the next power of two for
+                    // wide types, adding one gives that power for all
+                    if val.fract() == 0.0 && val >= <$t>::MIN as f64 && val < <$t>::MAX as f64 + 1.0
+                    {
+                        Some(val as $t)
+                    } else {
+                        None
+                    }
+                }
+            }
+        )*
+    };
+}
+
+from_double_for_int!(i8, u8, i16, u16, i32, u32, i64, u64, i128, u128, isize, usize);
+
+impl FromDouble for f32 {
+    fn from_double(val: f64) -> Option<f32> {
+        Some(val as f32)
+    }
+}
+
+impl FromDouble for f64 {
+    fn from_double(val: f64) -> Option<f64> {
+        Some(val)
+    }
+}
+
 macro_rules! from_redis_value_for_num_internal {
     ($t:ty, $v:expr) => {{
         let v = $v;
@@ -1041,6 +1872,14 @@ macro_rules! from_redis_value_for_num_internal {
                 Ok(rv) => Ok(rv),
                 Err(_) => invalid_type_error!(v, "Could not convert from string."),
             },
+            Value::Double(val) => match <$t as FromDouble>::from_double(val) {
+                Some(rv) => Ok(rv),
+                None => invalid_type_error!(v, "Double not convertible without loss."),
+            },
+            Value::BigNumber(ref s) => match s.parse::<$t>() {
+                Ok(rv) => Ok(rv),
+                Err(_) => invalid_type_error!(v, "Big number out of range."),
+            },
             _ => invalid_type_error!(v, "Response type not convertible to numeric."),
         }
     }};
@@ -1104,6 +1943,7 @@ impl FromRedisValue for bool {
                 }
             }
             Value::Okay => Ok(true),
+            Value::Boolean(val) => Ok(val),
             _ => invalid_type_error!(v, "Response type not bool compatible."),
         }
     }
@@ -1115,6 +1955,9 @@ impl FromRedisValue for String {
             Value::Data(ref bytes) => Ok(from_utf8(bytes)?.to_string()),
             Value::Okay => Ok("OK".to_string()),
             Value::Status(ref val) => Ok(val.to_string()),
+            Value::Double(val) => Ok(val.to_string()),
+            Value::BigNumber(ref val) => Ok(val.to_string()),
+            Value::VerbatimString { ref text, .. } => Ok(text.to_string()),
             _ => invalid_type_error!(v, "Response type not string compatible."),
         }
     }
@@ -1129,7 +1972,9 @@ impl<T: FromRedisValue> FromRedisValue for Vec<T> {
                 Some(x) => Ok(x),
                 None => invalid_type_error!(v, "Response type not vector compatible."),
             },
-            Value::Bulk(ref items) => FromRedisValue::from_redis_values(items),
+            Value::Bulk(ref items) | Value::Set(ref items) => {
+                FromRedisValue::from_redis_values(items)
+            }
             Value::Nil => Ok(vec![]),
             _ => invalid_type_error!(v, "Response type not vector compatible."),
         }
@@ -1264,6 +2109,40 @@ impl FromRedisValue for InfoDict {
     }
 }
 
+impl FromRedisValue for MonitorEntry {
+    fn from_redis_value(v: &Value) -> RedisResult<MonitorEntry> {
+        let line: String = from_redis_value(v)?;
+        match parse_monitor_line(&line) {
+            Some(entry) => Ok(entry),
+            None => invalid_type_error!(v, "Invalid MONITOR line"),
+        }
+    }
+}
+
+// Parses `<seconds>.<micros> [<db> <client>] "<command>" "<arg>" ...`, the
+// arguments being quoted like the ones of inline commands.
+fn parse_monitor_line(line: &str) -> Option<MonitorEntry> {
+    let (time, rest) = line.split_once(" [")?;
+    let (secs, micros) = time.split_once('.')?;
+    let timestamp = UNIX_EPOCH
+        + Duration::from_secs(secs.parse().ok()?)
+        + Duration::from_micros(micros.parse().ok()?);
+    let (source, rest) = rest.split_once("] ")?;
+    let (db, client) = source.split_once(' ')?;
+    let mut args = crate::parser::split_inline_args(rest.as_bytes()).ok()?;
+    if args.is_empty() {
+        return None;
+    }
+    let command = String::from_utf8_lossy(&args.remove(0)).into_owned();
+    Some(MonitorEntry {
+        timestamp,
+        db: db.parse().ok()?,
+        client: client.to_string(),
+        command,
+        args,
+    })
+}
+
 impl<T: FromRedisValue> FromRedisValue for Option<T> {
     fn from_redis_value(v: &Value) -> RedisResult<Option<T>> {
         if let Value::Nil = *v {
@@ -1283,8 +2162,235 @@ impl FromRedisValue for bytes::Bytes {
     }
 }
 
+/// Durations are written and read as a number of milliseconds, the unit of
+/// `PEXPIRE`, `PTTL` and the `PX` option of `SET`.
+impl ToRedisArgs for Duration {
+    fn write_redis_args<W>(&self, out: &mut W)
+    where
+        W: ?Sized + RedisWrite,
+    {
+        let mut buf = ::itoa::Buffer::new();
+        let s = buf.format(self.as_millis());
+        out.write_arg(s.as_bytes())
+    }
+
+    fn describe_numeric_behavior(&self) -> NumericBehavior {
+        NumericBehavior::NumberIsInteger
+    }
+}
+
+impl FromRedisValue for Duration {
+    fn from_redis_value(v: &Value) -> RedisResult<Duration> {
+        Ok(Duration::from_millis(non_negative_millis(v)?))
+    }
+}
+
+/// Points in time are written and read as UNIX timestamps in milliseconds,
+/// the unit of `PEXPIREAT` and `PEXPIRETIME`.
+impl ToRedisArgs for SystemTime {
+    fn write_redis_args<W>(&self, out: &mut W)
+    where
+        W: ?Sized + RedisWrite,
+    {
+        let millis = match self.duration_since(UNIX_EPOCH) {
+            Ok(since) => since.as_millis() as i128,
+            Err(err) => -(err.duration().as_millis() as i128),
+        };
+        let mut buf = ::itoa::Buffer::new();
+        let s = buf.format(millis);
+        out.write_arg(s.as_bytes())
+    }
+
+    fn describe_numeric_behavior(&self) -> NumericBehavior {
+        NumericBehavior::NumberIsInteger
+    }
+}
+
+/// Negative replies, like the `-1` and `-2` of `PEXPIRETIME` for keys
+/// without expiration, are rejected.
+impl FromRedisValue for SystemTime {
+    fn from_redis_value(v: &Value) -> RedisResult<SystemTime> {
+        Ok(UNIX_EPOCH + Duration::from_millis(non_negative_millis(v)?))
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl ToRedisArgs for chrono::DateTime<chrono::Utc> {
+    fn write_redis_args<W>(&self, out: &mut W)
+    where
+        W: ?Sized + RedisWrite,
+    {
+        self.timestamp_millis().write_redis_args(out)
+    }
+
+    fn describe_numeric_behavior(&self) -> NumericBehavior {
+        NumericBehavior::NumberIsInteger
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl FromRedisValue for chrono::DateTime<chrono::Utc> {
+    fn from_redis_value(v: &Value) -> RedisResult<Self> {
+        use chrono::TimeZone;
+
+        match chrono::Utc
+            .timestamp_millis_opt(non_negative_millis(v)? as i64)
+            .single()
+        {
+            Some(time) => Ok(time),
+            None => invalid_type_error!(v, "Timestamp out of range."),
+        }
+    }
+}
+
+#[cfg(feature = "time")]
+impl ToRedisArgs for time::OffsetDateTime {
+    fn write_redis_args<W>(&self, out: &mut W)
+    where
+        W: ?Sized + RedisWrite,
+    {
+        let mut buf = ::itoa::Buffer::new();
+        let s = buf.format(self.unix_timestamp_nanos() / 1_000_000);
+        out.write_arg(s.as_bytes())
+    }
+
+    fn describe_numeric_behavior(&self) -> NumericBehavior {
+        NumericBehavior::NumberIsInteger
+    }
+}
+
+#[cfg(feature = "time")]
+impl FromRedisValue for time::OffsetDateTime {
+    fn from_redis_value(v: &Value) -> RedisResult<Self> {
+        let nanos = i128::from(non_negative_millis(v)?) * 1_000_000;
+        match time::OffsetDateTime::from_unix_timestamp_nanos(nanos) {
+            Ok(time) => Ok(time),
+            Err(_) => invalid_type_error!(v, "Timestamp out of range."),
+        }
+    }
+}
+
+// Implements the conversions of a type through its `Display` and `FromStr`
+// implementations.
+macro_rules! string_based_conversions {
+    ($t:ty, $det:expr) => {
+        impl ToRedisArgs for $t {
+            fn write_redis_args<W>(&self, out: &mut W)
+            where
+                W: ?Sized + RedisWrite,
+            {
+                out.write_arg(self.to_string().as_bytes())
+            }
+        }
+
+        impl FromRedisValue for $t {
+            fn from_redis_value(v: &Value) -> RedisResult<$t> {
+                let s = match *v {
+                    Value::Data(ref bytes) => from_utf8(bytes)?,
+                    Value::Status(ref s) => s,
+                    _ => invalid_type_error!(v, $det),
+                };
+                match s.parse() {
+                    Ok(rv) => Ok(rv),
+                    Err(_) => invalid_type_error!(v, $det),
+                }
+            }
+        }
+    };
+}
+
+string_based_conversions!(std::net::IpAddr, "Response is not an IP address.");
+string_based_conversions!(std::net::Ipv4Addr, "Response is not an IPv4 address.");
+string_based_conversions!(std::net::Ipv6Addr, "Response is not an IPv6 address.");
+
+#[cfg(feature = "ipnet")]
+string_based_conversions!(ipnet::IpNet, "Response is not an IP network.");
+#[cfg(feature = "ipnet")]
+string_based_conversions!(ipnet::Ipv4Net, "Response is not an IPv4 network.");
+#[cfg(feature = "ipnet")]
+string_based_conversions!(ipnet::Ipv6Net, "Response is not an IPv6 network.");
+
+/// UUIDs are written in their hyphenated form and read from any text form
+/// or from their 16 bytes.
+#[cfg(feature = "uuid")]
+impl ToRedisArgs for uuid::Uuid {
+    fn write_redis_args<W>(&self, out: &mut W)
+    where
+        W: ?Sized + RedisWrite,
+    {
+        let mut buf = uuid::Uuid::encode_buffer();
+        out.write_arg(self.hyphenated().encode_lower(&mut buf).as_bytes())
+    }
+}
+
+#[cfg(feature = "uuid")]
+impl FromRedisValue for uuid::Uuid {
+    fn from_redis_value(v: &Value) -> RedisResult<uuid::Uuid> {
+        let rv = match *v {
+            Value::Data(ref bytes) if bytes.len() == 16 => uuid::Uuid::from_slice(bytes),
+            Value::Data(ref bytes) => uuid::Uuid::try_parse_ascii(bytes),
+            Value::Status(ref s) => uuid::Uuid::parse_str(s),
+            _ => invalid_type_error!(v, "Response type not UUID compatible."),
+        };
+        match rv {
+            Ok(rv) => Ok(rv),
+            Err(_) => invalid_type_error!(v, "Response is not a UUID."),
+        }
+    }
+}
+
+/// Decimals are written as their exact decimal text.
+#[cfg(feature = "rust_decimal")]
+impl ToRedisArgs for rust_decimal::Decimal {
+    fn write_redis_args<W>(&self, out: &mut W)
+    where
+        W: ?Sized + RedisWrite,
+    {
+        out.write_arg(self.to_string().as_bytes())
+    }
+
+    fn describe_numeric_behavior(&self) -> NumericBehavior {
+        NumericBehavior::NumberIsFloat
+    }
+}
+
+#[cfg(feature = "rust_decimal")]
+impl FromRedisValue for rust_decimal::Decimal {
+    fn from_redis_value(v: &Value) -> RedisResult<rust_decimal::Decimal> {
+        let rv = match *v {
+            Value::Int(val) => return Ok(val.into()),
+            Value::Data(ref bytes) => from_utf8(bytes)?.parse(),
+            Value::Status(ref s) | Value::BigNumber(ref s) => s.parse(),
+            Value::Double(val) => val.to_string().parse(),
+            _ => invalid_type_error!(v, "Response type not decimal compatible."),
+        };
+        match rv {
+            Ok(rv) => Ok(rv),
+            Err(_) => invalid_type_error!(v, "Could not convert to decimal."),
+        }
+    }
+}
+
+fn non_negative_millis(v: &Value) -> RedisResult<u64> {
+    let millis: i64 = from_redis_value(v)?;
+    if millis < 0 {
+        invalid_type_error!(v, "Negative number of milliseconds.");
+    }
+    Ok(millis as u64)
+}
+
 /// A shortcut function to invoke `FromRedisValue::from_redis_value`
 /// to make the API slightly nicer.
+///
+/// Types which cannot represent the RESP3 attributes of a value, which is
+/// all of them but `Value`, are converted from the value without them.  The
+/// conversions of aggregates convert their elements with this function, so
+/// the attributes of nested values are stripped as well.
 pub fn from_redis_value<T: FromRedisValue>(v: &Value) -> RedisResult<T> {
-    FromRedisValue::from_redis_value(v)
+    match *v {
+        Value::Attribute { ref data, .. } => {
+            FromRedisValue::from_redis_value(v).or_else(|_| from_redis_value(data))
+        }
+        _ => FromRedisValue::from_redis_value(v),
+    }
 }
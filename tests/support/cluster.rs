@@ -38,11 +38,7 @@ impl ClusterType {
     fn build_addr(port: u16) -> redis::ConnectionAddr {
         match ClusterType::get_intended() {
             ClusterType::Tcp => redis::ConnectionAddr::Tcp("127.0.0.1".into(), port),
-            ClusterType::TcpTls => redis::ConnectionAddr::TcpTls {
-                host: "127.0.0.1".into(),
-                port,
-                insecure: true,
-            },
+            ClusterType::TcpTls => redis::ConnectionAddr::tcp_tls("127.0.0.1", port, true),
         }
     }
 }
@@ -136,6 +132,8 @@ impl RedisCluster {
             let conn_info = redis::ConnectionInfo {
                 addr: server.get_client_addr().clone(),
                 redis: Default::default(),
+                proxy: None,
+                tcp_settings: Default::default(),
             };
             eprintln!(
                 "waiting until {:?} knows required number of replicas",
@@ -204,6 +202,8 @@ impl TestClusterContext {
                 .map(|server| redis::ConnectionInfo {
                     addr: server.get_client_addr().clone(),
                     redis: Default::default(),
+                    proxy: None,
+                    tcp_settings: Default::default(),
                 })
                 .collect(),
         );
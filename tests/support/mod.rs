@@ -13,6 +13,9 @@ pub fn current_thread_runtime() -> tokio::runtime::Runtime {
     #[cfg(feature = "aio")]
     builder.enable_io();
 
+    // the timeouts of requests need the timer
+    builder.enable_time();
+
     builder.build().unwrap()
 }
 
@@ -73,18 +76,14 @@ impl RedisServer {
                 // this is technically a race but we can't do better with
                 // the tools that redis gives us :(
                 let addr = &"127.0.0.1:0".parse::<SocketAddr>().unwrap().into();
-                let socket = Socket::new(Domain::ipv4(), Type::stream(), None).unwrap();
+                let socket = Socket::new(Domain::IPV4, Type::STREAM, None).unwrap();
                 socket.set_reuse_address(true).unwrap();
                 socket.bind(addr).unwrap();
                 socket.listen(1).unwrap();
-                let listener = socket.into_tcp_listener();
+                let listener = std::net::TcpListener::from(socket);
                 let redis_port = listener.local_addr().unwrap().port();
                 if tls {
-                    redis::ConnectionAddr::TcpTls {
-                        host: "127.0.0.1".to_string(),
-                        port: redis_port,
-                        insecure: true,
-                    }
+                    redis::ConnectionAddr::tcp_tls("127.0.0.1", redis_port, true)
                 } else {
                     redis::ConnectionAddr::Tcp("127.0.0.1".to_string(), redis_port)
                 }
@@ -148,11 +147,7 @@ impl RedisServer {
                     .arg("--bind")
                     .arg(host);
 
-                let addr = redis::ConnectionAddr::TcpTls {
-                    host: host.clone(),
-                    port,
-                    insecure: true,
-                };
+                let addr = redis::ConnectionAddr::tcp_tls(host.clone(), port, true);
 
                 RedisServer {
                     process: spawner(&mut redis_cmd),
@@ -206,6 +201,8 @@ impl TestContext {
         let client = redis::Client::open(redis::ConnectionInfo {
             addr: server.get_client_addr().clone(),
             redis: Default::default(),
+            proxy: None,
+            tcp_settings: Default::default(),
         })
         .unwrap();
         let mut con;
@@ -299,6 +296,59 @@ where
         }
         Value::Okay => write!(writer, "+OK\r\n"),
         Value::Status(ref s) => write!(writer, "+{}\r\n", s),
+        Value::ServerError(ref line) => write!(writer, "-{}\r\n", line),
+        Value::Map(ref pairs) => {
+            write!(writer, "%{}\r\n", pairs.len())?;
+            for (key, val) in pairs.iter() {
+                encode_value(key, writer)?;
+                encode_value(val, writer)?;
+            }
+            Ok(())
+        }
+        Value::Set(ref values) => {
+            write!(writer, "~{}\r\n", values.len())?;
+            for val in values.iter() {
+                encode_value(val, writer)?;
+            }
+            Ok(())
+        }
+        Value::Double(val) => write!(writer, ",{}\r\n", val),
+        Value::Boolean(val) => write!(writer, "#{}\r\n", if val { "t" } else { "f" }),
+        Value::BigNumber(ref val) => write!(writer, "({}\r\n", val),
+        Value::VerbatimString {
+            ref format,
+            ref text,
+        } => write!(
+            writer,
+            "={}\r\n{}:{}\r\n",
+            format.len() + 1 + text.len(),
+            format,
+            text
+        ),
+        Value::Push { ref kind, ref data } => {
+            write!(
+                writer,
+                ">{}\r\n${}\r\n{}\r\n",
+                data.len() + 1,
+                kind.len(),
+                kind
+            )?;
+            for val in data.iter() {
+                encode_value(val, writer)?;
+            }
+            Ok(())
+        }
+        Value::Attribute {
+            ref data,
+            ref attributes,
+        } => {
+            write!(writer, "|{}\r\n", attributes.len())?;
+            for (key, val) in attributes.iter() {
+                encode_value(key, writer)?;
+                encode_value(val, writer)?;
+            }
+            encode_value(data, writer)
+        }
     }
 }
 
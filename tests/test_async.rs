@@ -30,6 +30,50 @@ fn test_args() {
     .unwrap();
 }
 
+#[test]
+fn test_one_shot_async() {
+    let ctx = TestContext::new();
+    let info = ctx.client.get_connection_info().clone();
+    block_on_all(async move {
+        let _: () =
+            redis::one_shot_async(info.clone(), redis::cmd("SET").arg("my_key").arg(42)).await?;
+        let value: i64 = redis::one_shot_async(info, redis::cmd("GET").arg("my_key")).await?;
+        assert_eq!(value, 42);
+        Ok(())
+    })
+    .map_err(|err: redis::RedisError| err)
+    .unwrap();
+}
+
+#[test]
+fn test_delete_matching_async() {
+    let ctx = TestContext::new();
+    block_on_all(async move {
+        let mut con = ctx.multiplexed_async_connection().await?;
+        let mut pipe = redis::pipe();
+        for i in 0..50_000 {
+            pipe.cmd("SET")
+                .arg(format!("session:{}", i))
+                .arg(i)
+                .ignore();
+            pipe.cmd("SET").arg(format!("other:{}", i)).arg(i).ignore();
+        }
+        pipe.query_async(&mut con).await?;
+
+        let opts = redis::DeleteMatchingOptions::default().count(1000);
+        let matched =
+            redis::delete_matching_async(&mut con, "session:*", opts.clone().dry_run(true)).await?;
+        assert_eq!(matched, 50_000);
+        let removed = redis::delete_matching_async(&mut con, "session:*", opts).await?;
+        assert_eq!(removed, 50_000);
+        let size: u64 = redis::cmd("DBSIZE").query_async(&mut con).await?;
+        assert_eq!(size, 50_000);
+        Ok(())
+    })
+    .map_err(|err: redis::RedisError| err)
+    .unwrap();
+}
+
 #[test]
 fn dont_panic_on_closed_multiplexed_connection() {
     let ctx = TestContext::new();
@@ -221,6 +265,275 @@ fn test_args_with_errors_multiplexed_connection() {
     .unwrap();
 }
 
+#[test]
+fn test_response_timeout_multiplexed_connection() {
+    use std::time::Duration;
+
+    let ctx = TestContext::new();
+    block_on_all(async move {
+        let mut con = ctx.multiplexed_async_connection().await?;
+        con.set("timeout-key", 1).await?;
+
+        con.set_response_timeout(Some(Duration::from_millis(100)));
+        let err = con
+            .blpop::<_, Option<(String, String)>>("timeout-list", 1)
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), redis::ErrorKind::Timeout, "{}", err);
+
+        // the connection was closed
+        let err = con.get::<_, i64>("timeout-key").await.unwrap_err();
+        assert!(err.is_connection_dropped(), "{}", err);
+
+        let mut con = ctx.multiplexed_async_connection().await?;
+        let value: i64 = con.get("timeout-key").await?;
+        assert_eq!(value, 1);
+        Ok::<_, redis::RedisError>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_response_timeout_closes_connection() {
+    use std::time::Duration;
+
+    block_on_all(async move {
+        // a server which never replies
+        let (stream, _server) = tokio::io::duplex(1024);
+        let info = redis::RedisConnectionInfo {
+            library_info: redis::LibraryInfo::Disabled,
+            ..Default::default()
+        };
+        let mut con = MultiplexedConnection::new_from_stream(&info, stream).await?;
+        con.set_response_timeout(Some(Duration::from_millis(50)));
+
+        let mut pending = con.clone();
+        pending.set_response_timeout(None);
+        let ping = redis::cmd("PING");
+        let in_flight = ping.query_async::<_, String>(&mut pending);
+        let timed_out = ping.query_async::<_, String>(&mut con);
+        let (in_flight, timed_out) = future::join(in_flight, timed_out).await;
+        assert_eq!(timed_out.unwrap_err().kind(), ErrorKind::Timeout);
+        // the requests in flight are failed along with the next ones
+        assert!(in_flight.unwrap_err().is_connection_dropped());
+        let err = redis::cmd("PING")
+            .query_async::<_, String>(&mut con)
+            .await
+            .unwrap_err();
+        assert!(err.is_connection_dropped());
+        Ok::<_, redis::RedisError>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_command_timeout() {
+    use std::time::Duration;
+
+    let ctx = TestContext::new();
+    block_on_all(async move {
+        let mut con = ctx.async_connection().await?;
+        con.set("timeout-key", 1).await?;
+
+        let err = redis::cmd("BLPOP")
+            .arg("timeout-list")
+            .arg(1)
+            .timeout(Duration::from_millis(100))
+            .query_async::<_, Option<(String, String)>>(&mut con)
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), redis::ErrorKind::Timeout);
+        assert!(err.is_timeout());
+
+        // the reply of the cancelled BLPOP is skipped
+        let value: i64 = redis::cmd("GET")
+            .arg("timeout-key")
+            .query_async_with_timeout(&mut con, Duration::from_secs(5))
+            .await?;
+        assert_eq!(value, 1);
+        Ok::<_, redis::RedisError>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_cancelled_write_breaks_connection() {
+    use std::time::Duration;
+
+    block_on_all(async move {
+        // nothing reads the other end, so the request does not fit in the pipe
+        let (stream, _server) = tokio::io::duplex(16);
+        let info = redis::RedisConnectionInfo {
+            library_info: redis::LibraryInfo::Disabled,
+            ..Default::default()
+        };
+        let mut con = redis::aio::Connection::new(&info, stream).await?;
+
+        let err = redis::cmd("SET")
+            .arg("key")
+            .arg("x".repeat(64))
+            .timeout(Duration::from_millis(100))
+            .query_async::<_, ()>(&mut con)
+            .await
+            .unwrap_err();
+        assert!(err.is_timeout());
+
+        // the rest of the SET would be taken for the start of the next request
+        let err = redis::cmd("PING")
+            .query_async::<_, String>(&mut con)
+            .await
+            .unwrap_err();
+        assert!(err.is_connection_dropped());
+        Ok::<_, redis::RedisError>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_connect_with_stream() {
+    let ctx = TestContext::new();
+    block_on_all(async move {
+        let mut con = match ctx.server.get_client_addr() {
+            redis::ConnectionAddr::Tcp(host, port) => {
+                let stream = tokio::net::TcpStream::connect((host.as_str(), *port)).await?;
+                ctx.client.connect_with_stream(stream).await?
+            }
+            #[cfg(unix)]
+            redis::ConnectionAddr::Unix(path) => {
+                let stream = tokio::net::UnixStream::connect(path).await?;
+                ctx.client.connect_with_stream(stream).await?
+            }
+            // the TLS handshake is up to the caller
+            _ => return Ok(()),
+        };
+        con.set("stream-key", 7).await?;
+        let value: i64 = con.get("stream-key").await?;
+        assert_eq!(value, 7);
+        Ok::<_, redis::RedisError>(())
+    })
+    .unwrap();
+}
+
+#[cfg(feature = "tower")]
+#[test]
+fn test_tower_service() {
+    use tower_service::Service;
+
+    let ctx = TestContext::new();
+    block_on_all(async move {
+        let mut con = ctx.multiplexed_async_connection().await?;
+        future::poll_fn(|cx| Service::<redis::Cmd>::poll_ready(&mut con, cx)).await?;
+        let reply = con
+            .call(cmd("SET").arg("tower-key").arg(42).clone())
+            .await?;
+        assert_eq!(reply, redis::Value::Okay);
+
+        let mut pipeline = redis::pipe();
+        pipeline.get("tower-key").incr("tower-key", 1).ignore();
+        let reply = con.call(pipeline).await?;
+        assert_eq!(
+            reply,
+            redis::Value::Bulk(vec![redis::Value::Data(b"42".to_vec())])
+        );
+        Ok::<_, redis::RedisError>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_credentials_provider() {
+    use redis::aio::{Credentials, CredentialsProvider};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    struct CountingProvider(Arc<AtomicUsize>);
+
+    #[async_trait::async_trait]
+    impl CredentialsProvider for CountingProvider {
+        async fn credentials(&self) -> RedisResult<Credentials> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            // the default user of the test server accepts any password
+            Ok(Credentials {
+                username: Some("default".to_string()),
+                password: "token".to_string(),
+                valid_for: Some(Duration::from_millis(40)),
+            })
+        }
+    }
+
+    let ctx = TestContext::new();
+    block_on_all(async move {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let client = ctx
+            .client
+            .clone()
+            .with_credentials_provider(CountingProvider(calls.clone()));
+        let mut con = client.get_async_connection().await?;
+        con.set("credentials-key", 1).await?;
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // the multiplexed connection authenticates again before the
+        // credentials expire
+        let mut con = client.get_multiplexed_async_connection().await?;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(calls.load(Ordering::SeqCst) >= 4);
+        let value: i64 = con.get("credentials-key").await?;
+        assert_eq!(value, 1);
+        Ok::<_, redis::RedisError>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_resolver_srv_discovery() {
+    use redis::aio::Resolver;
+    use std::net::{SocketAddr, ToSocketAddrs};
+
+    // Knows the test server as `redis.test`, found through its SRV record.
+    struct TestResolver(SocketAddr);
+
+    #[async_trait::async_trait]
+    impl Resolver for TestResolver {
+        async fn resolve(&self, host: &str, port: u16) -> RedisResult<Vec<SocketAddr>> {
+            match host {
+                "redis.test" => Ok(vec![SocketAddr::new(self.0.ip(), port)]),
+                _ => Ok(vec![]),
+            }
+        }
+
+        async fn resolve_srv(&self, name: &str) -> RedisResult<Vec<(String, u16)>> {
+            assert_eq!(name, "_redis._tcp.test");
+            Ok(vec![
+                ("missing.test".to_string(), 6379),
+                ("redis.test".to_string(), self.0.port()),
+            ])
+        }
+    }
+
+    let ctx = TestContext::new();
+    let addr = match ctx.server.get_client_addr() {
+        redis::ConnectionAddr::Tcp(host, port) => (host.as_str(), *port)
+            .to_socket_addrs()
+            .unwrap()
+            .next()
+            .unwrap(),
+        // TLS would verify the hostname, unix sockets have none
+        _ => return,
+    };
+    block_on_all(async move {
+        let client = redis::Client::open("redis://unresolvable.test/")?
+            .with_resolver(TestResolver(addr))
+            .with_srv_discovery("_redis._tcp.test");
+        let mut con = client.get_multiplexed_async_connection().await?;
+        con.set("srv-key", 3).await?;
+        let value: i64 = con.get("srv-key").await?;
+        assert_eq!(value, 3);
+        Ok::<_, redis::RedisError>(())
+    })
+    .unwrap();
+}
+
 #[test]
 fn test_transaction_multiplexed_connection() {
     let ctx = TestContext::new();
@@ -316,6 +629,29 @@ fn test_async_scanning_small_batch() {
     test_async_scanning(2)
 }
 
+#[test]
+fn test_async_scan_stream() {
+    let ctx = TestContext::new();
+    block_on_all(async move {
+        let mut con = ctx.multiplexed_async_connection().await?;
+        for x in 0..100usize {
+            con.sadd("foo", x).await?;
+        }
+
+        let mut seen: Vec<usize> = con.sscan_stream::<_, usize>("foo").try_collect().await?;
+        seen.sort_unstable();
+        assert_eq!(seen, (0..100).collect::<Vec<_>>());
+
+        let sum = con
+            .sscan_stream::<_, usize>("foo")
+            .try_fold(0, |sum, x| future::ready(Ok(sum + x)))
+            .await?;
+        assert_eq!(sum, 4950);
+        Ok::<_, redis::RedisError>(())
+    })
+    .unwrap();
+}
+
 #[test]
 #[cfg(feature = "script")]
 fn test_script() {
@@ -431,7 +767,11 @@ async fn invalid_password_issue_343() {
             db: 0,
             username: None,
             password: Some("asdcasc".to_string()),
+            protocol: redis::ProtocolVersion::RESP2,
+            library_info: redis::LibraryInfo::default(),
         },
+        proxy: None,
+        tcp_settings: Default::default(),
     };
     let client = redis::Client::open(coninfo).unwrap();
     let err = client
@@ -563,3 +903,467 @@ mod pub_sub {
         .unwrap();
     }
 }
+
+#[test]
+fn test_resp3_handshake_multiplexed() {
+    let ctx = TestContext::new();
+    let client = redis::Client::open(redis::ConnectionInfo {
+        addr: ctx.server.get_client_addr().clone(),
+        redis: redis::RedisConnectionInfo {
+            protocol: redis::ProtocolVersion::RESP3,
+            ..Default::default()
+        },
+        proxy: None,
+        tcp_settings: Default::default(),
+    })
+    .unwrap();
+    block_on_all(async move {
+        let mut con = client.get_multiplexed_tokio_connection().await?;
+        assert_eq!(con.protocol(), redis::ProtocolVersion::RESP3);
+        let pong: String = redis::cmd("PING").query_async(&mut con).await?;
+        assert_eq!(pong, "PONG");
+        Ok::<_, redis::RedisError>(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_push_messages_multiplexed() {
+    let ctx = TestContext::new();
+    let client = redis::Client::open(redis::ConnectionInfo {
+        addr: ctx.server.get_client_addr().clone(),
+        redis: redis::RedisConnectionInfo {
+            protocol: redis::ProtocolVersion::RESP3,
+            ..Default::default()
+        },
+        proxy: None,
+        tcp_settings: Default::default(),
+    })
+    .unwrap();
+    block_on_all(async move {
+        let mut con = client.get_multiplexed_tokio_connection().await?;
+        let mut pushes = con.push_messages();
+        cmd("CLIENT")
+            .arg("TRACKING")
+            .arg("ON")
+            .query_async(&mut con)
+            .await?;
+        con.set("tracked", 1).await?;
+        let value: i64 = con.get("tracked").await?;
+        assert_eq!(value, 1);
+
+        let mut other = ctx.multiplexed_async_connection().await?;
+        other.set("tracked", 2).await?;
+
+        let msg = pushes.next().await.unwrap();
+        assert_eq!(msg.kind, "invalidate");
+        assert_eq!(
+            redis::Value::from(msg).into_invalidation()?,
+            redis::Invalidation::Keys(vec![b"tracked".to_vec()])
+        );
+
+        // replies are still matched to their commands
+        let value: i64 = con.get("tracked").await?;
+        assert_eq!(value, 2);
+        Ok::<_, redis::RedisError>(())
+    })
+    .unwrap();
+}
+
+#[test]
+#[cfg(feature = "caching")]
+fn test_cached_connection() {
+    use redis::caching::{CachedConnection, TrackingOptions};
+
+    let ctx = TestContext::new();
+    let client = redis::Client::open(redis::ConnectionInfo {
+        addr: ctx.server.get_client_addr().clone(),
+        redis: redis::RedisConnectionInfo {
+            protocol: redis::ProtocolVersion::RESP3,
+            ..Default::default()
+        },
+        proxy: None,
+        tcp_settings: Default::default(),
+    })
+    .unwrap();
+    block_on_all(async move {
+        let mut other = ctx.multiplexed_async_connection().await?;
+        other.set("cached", "a").await?;
+
+        let con = client.get_multiplexed_tokio_connection().await?;
+        let mut invalidations = redis::caching::invalidations(&con);
+        let mut cached = CachedConnection::new(con, TrackingOptions::default()).await?;
+        let value: String = cached.get("cached").await?;
+        assert_eq!(value, "a");
+        assert_eq!(cached.cached_keys(), 1);
+
+        other.set("cached", "b").await?;
+        assert_eq!(
+            invalidations.next().await,
+            Some(redis::Invalidation::Keys(vec![b"cached".to_vec()]))
+        );
+        let value: String = cached.get("cached").await?;
+        assert_eq!(value, "b");
+
+        // tracking needs the push messages of RESP3
+        let resp2 = ctx.multiplexed_async_connection().await?;
+        let err = CachedConnection::new(resp2, TrackingOptions::default())
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), ErrorKind::InvalidClientConfig);
+        Ok::<_, redis::RedisError>(())
+    })
+    .unwrap();
+}
+
+#[test]
+#[cfg(feature = "connection-pool")]
+fn test_connection_pool() {
+    use redis::aio::{ConnectionPool, PoolConfig, PoolState};
+    use std::time::Duration;
+
+    let ctx = TestContext::new();
+    block_on_all(async move {
+        let config = PoolConfig::default()
+            .min_size(1)
+            .max_size(2)
+            .checkout_timeout(Some(Duration::from_millis(100)));
+        let pool = ConnectionPool::new(ctx.client.clone(), config).await?;
+        assert_eq!(
+            pool.state(),
+            PoolState {
+                connections: 1,
+                idle_connections: 1
+            }
+        );
+
+        let mut first = pool.get().await?;
+        let mut second = pool.get().await?;
+        first.set("pooled", 1).await?;
+        let value: i64 = second.get("pooled").await?;
+        assert_eq!(value, 1);
+        assert_eq!(pool.state().connections, 2);
+
+        let err = pool.get().await.err().unwrap();
+        assert_eq!(err.kind(), redis::ErrorKind::Timeout);
+
+        drop(first);
+        assert_eq!(pool.state().idle_connections, 1);
+        let _third = pool.get().await?;
+        assert_eq!(pool.state().idle_connections, 0);
+
+        // a connection left in a transaction is closed instead of reused
+        redis::cmd("MULTI")
+            .query_async::<_, ()>(&mut second)
+            .await?;
+        drop(second);
+        assert_eq!(
+            pool.state(),
+            PoolState {
+                connections: 1,
+                idle_connections: 0
+            }
+        );
+
+        let err = ConnectionPool::new(ctx.client.clone(), PoolConfig::default().max_size(0))
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), ErrorKind::InvalidClientConfig);
+        Ok::<_, redis::RedisError>(())
+    })
+    .unwrap();
+}
+
+#[test]
+#[cfg(feature = "connection-pool")]
+fn test_connection_pool_discards_changed_sessions() {
+    use redis::aio::{ConnectionPool, PoolConfig};
+
+    let ctx = TestContext::new();
+    block_on_all(async move {
+        let pool =
+            ConnectionPool::new(ctx.client.clone(), PoolConfig::default().max_size(1)).await?;
+        let mut con = pool.get().await?;
+        con.set("pooled", 1).await?;
+        let id: i64 = redis::cmd("CLIENT").arg("ID").query_async(&mut con).await?;
+        redis::cmd("SELECT")
+            .arg(1)
+            .query_async::<_, ()>(&mut con)
+            .await?;
+        drop(con);
+        assert_eq!(pool.state().connections, 0);
+
+        // the next connection is a new one, bound to the configured database
+        let mut con = pool.get().await?;
+        let new_id: i64 = redis::cmd("CLIENT").arg("ID").query_async(&mut con).await?;
+        assert_ne!(new_id, id);
+        let value: i64 = con.get("pooled").await?;
+        assert_eq!(value, 1);
+        Ok::<_, redis::RedisError>(())
+    })
+    .unwrap();
+}
+
+#[test]
+#[cfg(feature = "connection-pool")]
+fn test_connection_pool_stays_within_max_size() {
+    use redis::aio::{ConnectionPool, PoolConfig};
+    use std::time::Duration;
+
+    let ctx = TestContext::new();
+    block_on_all(async move {
+        let config = PoolConfig::default().min_size(2).max_size(2);
+        let pool = ConnectionPool::new(ctx.client.clone(), config).await?;
+        let mut first = pool.get().await?;
+        let _second = pool.get().await?;
+
+        // the refill opening a replacement races with the next checkout
+        redis::cmd("MULTI").query_async::<_, ()>(&mut first).await?;
+        drop(first);
+        let _third = pool.get().await?;
+        for _ in 0..10 {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            assert!(pool.state().connections <= 2);
+        }
+        Ok::<_, redis::RedisError>(())
+    })
+    .unwrap();
+}
+
+#[test]
+#[cfg(feature = "connection-manager")]
+fn test_connection_manager() {
+    let ctx = TestContext::new();
+    block_on_all(async move {
+        let mut manager = ctx.client.get_tokio_connection_manager().await?;
+        manager.set("managed", 42).await?;
+        let mut clone = manager.clone();
+        let value: i64 = clone.get("managed").await?;
+        assert_eq!(value, 42);
+        Ok::<_, redis::RedisError>(())
+    })
+    .unwrap();
+}
+
+#[test]
+#[cfg(feature = "connection-manager")]
+fn test_connection_manager_with_config() {
+    use std::time::Duration;
+
+    let ctx = TestContext::new();
+    block_on_all(async move {
+        let config = redis::aio::ConnectionManagerConfig::default()
+            .initial_delay(Duration::from_millis(10))
+            .multiplier(3)
+            .max_delay(Duration::from_millis(500))
+            .jitter(Duration::from_millis(5))
+            .max_attempts(Some(5))
+            .command_retries(2);
+        let mut manager = ctx
+            .client
+            .get_tokio_connection_manager_with_config(config)
+            .await?;
+        manager.set("managed", 42).await?;
+
+        // the connection is dropped, the command is retried on a new one
+        let _: redis::RedisResult<()> = redis::cmd("CLIENT")
+            .arg("KILL")
+            .arg("TYPE")
+            .arg("normal")
+            .query_async(&mut manager)
+            .await;
+        let value: i64 = manager.get("managed").await?;
+        assert_eq!(value, 42);
+        Ok::<_, redis::RedisError>(())
+    })
+    .unwrap();
+}
+
+#[test]
+#[cfg(feature = "connection-manager")]
+fn test_connection_manager_response_timeout() {
+    use std::time::Duration;
+
+    let ctx = TestContext::new();
+    block_on_all(async move {
+        let config = redis::aio::ConnectionManagerConfig::default()
+            .response_timeout(Some(Duration::from_millis(100)));
+        let mut manager = ctx
+            .client
+            .get_tokio_connection_manager_with_config(config)
+            .await?;
+        manager.set("managed", 42).await?;
+
+        let err = manager
+            .blpop::<_, Option<(String, String)>>("timeout-list", 1)
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Timeout);
+
+        // the closed connection was replaced
+        let value: i64 = manager.get("managed").await?;
+        assert_eq!(value, 42);
+        Ok::<_, redis::RedisError>(())
+    })
+    .unwrap();
+}
+
+#[test]
+#[cfg(feature = "connection-manager")]
+fn test_connection_manager_events() {
+    use redis::ConnectionEvent;
+    use std::sync::{Arc, Mutex};
+
+    let ctx = TestContext::new();
+    block_on_all(async move {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let recorded = events.clone();
+        let config = redis::aio::ConnectionManagerConfig::default()
+            .command_retries(1)
+            .on_event(move |event| {
+                let name = match event {
+                    ConnectionEvent::Connected { .. } => "connected",
+                    ConnectionEvent::Authenticated { .. } => "authenticated",
+                    ConnectionEvent::Reconnecting { .. } => "reconnecting",
+                    ConnectionEvent::Reconnected { .. } => "reconnected",
+                    ConnectionEvent::GaveUp { .. } => "gave up",
+                    _ => "other",
+                };
+                recorded.lock().unwrap().push(name);
+            });
+        let mut manager = ctx
+            .client
+            .get_tokio_connection_manager_with_config(config)
+            .await?;
+        assert_eq!(*events.lock().unwrap(), ["connected"]);
+
+        let _: redis::RedisResult<()> = redis::cmd("CLIENT")
+            .arg("KILL")
+            .arg("TYPE")
+            .arg("normal")
+            .query_async(&mut manager)
+            .await;
+        redis::cmd("PING").query_async(&mut manager).await?;
+        assert_eq!(
+            *events.lock().unwrap(),
+            ["connected", "reconnecting", "connected", "reconnected"]
+        );
+        Ok::<_, redis::RedisError>(())
+    })
+    .unwrap();
+}
+
+#[test]
+#[cfg(feature = "connection-manager")]
+fn test_connection_manager_pubsub_slow_consumer() {
+    use redis::aio::{BufferConfig, ConnectionManagerConfig, OverflowPolicy, PubSubEvent};
+    use std::time::Duration;
+
+    let ctx = TestContext::new();
+    block_on_all(async move {
+        let policies = vec![
+            (OverflowPolicy::Block, vec!["1", "2", "3", "4", "5"], 0),
+            (OverflowPolicy::DropOldest, vec!["4", "5"], 3),
+            (OverflowPolicy::DropNewest, vec!["1", "2"], 3),
+            (OverflowPolicy::CloseWithError, vec!["1", "2"], 1),
+        ];
+        for (policy, expected, dropped) in policies {
+            let config = ConnectionManagerConfig::default()
+                .pubsub_buffer(BufferConfig::default().capacity(2).overflow(policy));
+            let mut manager = ctx
+                .client
+                .get_tokio_connection_manager_with_config(config)
+                .await?;
+            let mut pubsub = manager.pubsub().await?;
+            pubsub.subscribe("slow-channel").await?;
+            while manager.publish::<_, _, usize>("slow-channel", "0").await? == 0 {}
+            assert!(matches!(
+                pubsub.on_message().next().await,
+                Some(PubSubEvent::Message(_))
+            ));
+
+            for payload in 1..=5 {
+                manager
+                    .publish::<_, _, usize>("slow-channel", payload)
+                    .await?;
+            }
+            // the consumer only starts reading once every message arrived
+            tokio::time::sleep(Duration::from_millis(100)).await;
+
+            let stats = pubsub.stats();
+            let mut messages = pubsub.into_on_message();
+            for payload in expected {
+                match messages.next().await {
+                    Some(PubSubEvent::Message(msg)) => {
+                        assert_eq!(msg.get_payload::<String>()?, payload)
+                    }
+                    event => panic!("unexpected event {:?}", event),
+                }
+            }
+            assert_eq!(stats.dropped_messages(), dropped);
+            assert_eq!(stats.overflowed(), policy == OverflowPolicy::CloseWithError);
+            if policy == OverflowPolicy::CloseWithError {
+                assert!(messages.next().await.is_none());
+            }
+        }
+        Ok::<_, redis::RedisError>(())
+    })
+    .unwrap();
+}
+
+#[test]
+#[cfg(feature = "connection-manager")]
+fn test_connection_manager_pubsub() {
+    use redis::aio::PubSubEvent;
+
+    let ctx = TestContext::new();
+    block_on_all(async move {
+        let mut manager = ctx.client.get_tokio_connection_manager().await?;
+        let mut pubsub = manager.pubsub().await?;
+        pubsub.subscribe("managed-channel").await?;
+        pubsub.psubscribe("managed-*").await?;
+        pubsub.unsubscribe("managed-channel").await?;
+        let mut messages = pubsub.into_on_message();
+
+        // wait for the subscriptions to be processed
+        while manager
+            .publish::<_, _, usize>("managed-pattern", "first")
+            .await?
+            == 0
+        {}
+        match messages.next().await {
+            Some(PubSubEvent::Message(msg)) => {
+                assert_eq!(msg.get_channel_name(), "managed-pattern");
+                assert_eq!(msg.get_payload::<String>()?, "first");
+            }
+            event => panic!("unexpected event {:?}", event),
+        }
+
+        let _: () = redis::cmd("CLIENT")
+            .arg("KILL")
+            .arg("TYPE")
+            .arg("pubsub")
+            .query_async(&mut manager)
+            .await?;
+        assert!(matches!(
+            messages.next().await,
+            Some(PubSubEvent::Reconnected)
+        ));
+
+        while manager
+            .publish::<_, _, usize>("managed-pattern", "second")
+            .await?
+            == 0
+        {}
+        match messages.next().await {
+            Some(PubSubEvent::Message(msg)) => {
+                assert_eq!(msg.get_payload::<String>()?, "second");
+            }
+            event => panic!("unexpected event {:?}", event),
+        }
+        Ok::<_, redis::RedisError>(())
+    })
+    .unwrap();
+}
@@ -171,6 +171,62 @@ fn test_scan() {
     assert_eq!(&s, &[1, 2, 3]);
 }
 
+fn seed_delete_matching_keys(con: &mut redis::Connection) {
+    let mut pipe = redis::pipe();
+    for i in 0..50_000 {
+        pipe.cmd("SET")
+            .arg(format!("session:{}", i))
+            .arg(i)
+            .ignore();
+        if i % 100 == 0 {
+            pipe.cmd("SET").arg(format!("user:{}", i)).arg(i).ignore();
+        }
+    }
+    pipe.cmd("SET").arg("session").arg(0).ignore();
+    let _: () = pipe.query(con).unwrap();
+}
+
+#[test]
+fn test_delete_matching() {
+    let ctx = TestContext::new();
+    let mut con = ctx.connection();
+    seed_delete_matching_keys(&mut con);
+
+    let opts = redis::DeleteMatchingOptions::default().count(1000);
+    assert_eq!(
+        redis::delete_matching(&mut con, "session:*", opts.clone().dry_run(true)),
+        Ok(50_000)
+    );
+    assert_eq!(redis::cmd("DBSIZE").query(&mut con), Ok(50_501));
+
+    assert_eq!(
+        redis::delete_matching(&mut con, "session:*", opts),
+        Ok(50_000)
+    );
+    assert_eq!(redis::cmd("DBSIZE").query(&mut con), Ok(501));
+    assert_eq!(con.exists("session"), Ok(true));
+    assert_eq!(con.exists("user:0"), Ok(true));
+}
+
+#[test]
+fn test_delete_matching_max_keys() {
+    let ctx = TestContext::new();
+    let mut con = ctx.connection();
+    seed_delete_matching_keys(&mut con);
+
+    let opts = redis::DeleteMatchingOptions::default()
+        .max_keys(100)
+        .dry_run(true);
+    let err = redis::delete_matching(&mut con, "session:*", opts).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::ClientError);
+    assert_eq!(redis::cmd("DBSIZE").query(&mut con), Ok(50_501));
+
+    let opts = redis::DeleteMatchingOptions::default()
+        .max_keys(1_000)
+        .batch_delay(Duration::from_millis(1));
+    assert_eq!(redis::delete_matching(&mut con, "user:*", opts), Ok(500));
+}
+
 #[test]
 fn test_optionals() {
     let ctx = TestContext::new();
@@ -220,6 +276,37 @@ fn test_scanning() {
     assert_eq!(unseen.len(), 0);
 }
 
+#[test]
+fn test_iter_reply() {
+    let ctx = TestContext::new();
+    let mut con = ctx.connection();
+
+    for x in 0..1000 {
+        redis::cmd("RPUSH").arg("foo").arg(x).execute(&mut con);
+    }
+
+    let lrange = redis::cmd("LRANGE").arg("foo").arg(0).arg(-1).clone();
+    let iter = con.iter_reply::<usize>(&lrange).unwrap();
+    assert_eq!(iter.size_hint(), (1000, Some(1000)));
+    let items: Vec<usize> = iter.map(Result::unwrap).collect();
+    assert_eq!(items, (0..1000).collect::<Vec<_>>());
+
+    // the elements left over are read when the iterator is dropped
+    let mut iter = con.iter_reply::<usize>(&lrange).unwrap();
+    assert_eq!(iter.next().unwrap(), Ok(0));
+    drop(iter);
+    assert_eq!(redis::cmd("LLEN").arg("foo").query(&mut con), Ok(1000));
+
+    assert!(con
+        .iter_reply::<usize>(redis::cmd("LRANGE").arg("bar").arg(0).arg(-1))
+        .unwrap()
+        .next()
+        .is_none());
+    assert!(con
+        .iter_reply::<usize>(redis::cmd("LLEN").arg("foo"))
+        .is_err());
+}
+
 #[test]
 fn test_filtered_scanning() {
     let ctx = TestContext::new();
@@ -246,6 +333,61 @@ fn test_filtered_scanning() {
     assert_eq!(unseen.len(), 0);
 }
 
+#[test]
+fn test_scan_options() {
+    let ctx = TestContext::new();
+    let mut con = ctx.connection();
+
+    for x in 0..100 {
+        let _: () = con.hset("hash", format!("field_{}", x), x).unwrap();
+        let _: () = con.zadd("zset", format!("member_{}", x), x).unwrap();
+        let _: () = con.set(format!("key_{}", x), x).unwrap();
+    }
+
+    let opts = redis::ScanOptions::default().pattern("field_1*").count(10);
+    let mut fields: Vec<(String, usize)> = con.hscan_options("hash", opts).unwrap().collect();
+    fields.sort_unstable();
+    assert_eq!(fields.len(), 11);
+    assert_eq!(fields[0], ("field_1".to_string(), 1));
+
+    let opts = redis::ScanOptions::default().pattern("member_9*");
+    let members: Vec<(String, f64)> = con.zscan_options("zset", opts).unwrap().collect();
+    assert_eq!(members.len(), 11);
+    assert!(members.contains(&("member_99".to_string(), 99.0)));
+
+    let opts = redis::ScanOptions::default().scan_type("hash");
+    let keys: Vec<String> = con.scan_options(opts).unwrap().collect();
+    assert_eq!(keys, vec!["hash".to_string()]);
+
+    let opts = redis::ScanOptions::default()
+        .scan_type("string")
+        .count(1000);
+    assert_eq!(con.scan_options::<String>(opts).unwrap().count(), 100);
+}
+
+#[test]
+fn test_scan_options_args() {
+    use redis::ToRedisArgs;
+
+    let opts = redis::ScanOptions::default()
+        .pattern("key:*")
+        .count(100)
+        .scan_type("set");
+    assert!(!opts.is_single_arg());
+    assert_eq!(
+        opts.to_redis_args(),
+        vec![
+            b"MATCH".to_vec(),
+            b"key:*".to_vec(),
+            b"COUNT".to_vec(),
+            b"100".to_vec(),
+            b"TYPE".to_vec(),
+            b"set".to_vec(),
+        ]
+    );
+    assert!(redis::ScanOptions::default().to_redis_args().is_empty());
+}
+
 #[test]
 fn test_pipeline() {
     let ctx = TestContext::new();
@@ -538,6 +680,46 @@ fn test_pubsub() {
     thread.join().expect("Something went wrong");
 }
 
+#[test]
+fn test_resp3_pubsub() {
+    let ctx = TestContext::new();
+    let mut con = ctx.connection();
+    let client = redis::Client::open(redis::ConnectionInfo {
+        addr: ctx.server.get_client_addr().clone(),
+        redis: redis::RedisConnectionInfo {
+            protocol: redis::ProtocolVersion::RESP3,
+            ..Default::default()
+        },
+        proxy: None,
+        tcp_settings: Default::default(),
+    })
+    .unwrap();
+    let mut pubsub_con = client.get_connection().unwrap();
+    assert_eq!(pubsub_con.protocol(), redis::ProtocolVersion::RESP3);
+
+    let mut pubsub = pubsub_con.as_pubsub();
+    pubsub.subscribe("foo").unwrap();
+    assert_eq!(con.publish("foo", 42), Ok(1));
+    // the message pushed before the reply is kept
+    pubsub.psubscribe("ba*").unwrap();
+    assert_eq!(con.publish("bar", 23), Ok(1));
+
+    let msg = pubsub.get_message().unwrap();
+    assert_eq!(msg.get_channel(), Ok("foo".to_string()));
+    assert_eq!(msg.get_payload(), Ok(42));
+    let msg = pubsub.get_message().unwrap();
+    assert_eq!(msg.get_channel(), Ok("bar".to_string()));
+    assert_eq!(msg.get_pattern(), Ok("ba*".to_string()));
+    assert_eq!(msg.get_payload(), Ok(23));
+    drop(pubsub);
+
+    // dropping the PubSub unsubscribed from both
+    assert_eq!(
+        redis::cmd("PING").query(&mut pubsub_con),
+        Ok("PONG".to_string())
+    );
+}
+
 #[test]
 fn test_pubsub_unsubscribe() {
     let ctx = TestContext::new();
@@ -677,6 +859,49 @@ fn test_script() {
     assert_eq!(response, Ok(("foo".to_string(), 42)));
 }
 
+#[test]
+#[cfg(feature = "script")]
+fn test_eval_once() {
+    let ctx = TestContext::new();
+    let mut con = ctx.connection();
+
+    let _: () = redis::cmd("SET")
+        .arg("my_key")
+        .arg("foo")
+        .query(&mut con)
+        .unwrap();
+    let response =
+        ctx.client
+            .eval_once("return {redis.call('GET', KEYS[1]), ARGV[1]}", "my_key", 42);
+
+    assert_eq!(response, Ok(("foo".to_string(), 42)));
+}
+
+#[test]
+fn test_one_shot() {
+    let ctx = TestContext::new();
+    let info = ctx.client.get_connection_info().clone();
+
+    let _: () = redis::one_shot(info.clone(), redis::cmd("SET").arg("my_key").arg(42)).unwrap();
+    assert_eq!(
+        redis::one_shot(info, redis::cmd("GET").arg("my_key")),
+        Ok(42)
+    );
+}
+
+#[test]
+fn test_one_shot_unreachable_address_times_out() {
+    let start = std::time::Instant::now();
+    let result: RedisResult<()> = redis::one_shot_with_timeout(
+        "redis://10.255.255.1:6379/",
+        &redis::cmd("PING"),
+        Duration::from_millis(100),
+    );
+
+    assert!(result.is_err());
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
+
 #[test]
 fn test_tuple_args() {
     let ctx = TestContext::new();
@@ -938,3 +1163,113 @@ fn test_zrandmember() {
     let results: Vec<String> = con.zrandmember_withscores(setname, -5).unwrap();
     assert_eq!(results.len(), 10);
 }
+
+#[test]
+fn test_client_setinfo() {
+    let ctx = TestContext::new();
+    let client = redis::Client::open(redis::ConnectionInfo {
+        addr: ctx.server.get_client_addr().clone(),
+        redis: redis::RedisConnectionInfo {
+            library_info: redis::LibraryInfo::WithSuffix("test_v1".to_string()),
+            ..Default::default()
+        },
+        proxy: None,
+        tcp_settings: Default::default(),
+    })
+    .unwrap();
+    let mut con = client.get_connection().unwrap();
+    let info: String = redis::cmd("CLIENT").arg("INFO").query(&mut con).unwrap();
+    // servers older than 7.2 neither know the command nor show the fields
+    if info.contains("lib-name=") {
+        assert!(info.contains("lib-name=redis-rs(test_v1)"), "{}", info);
+        assert!(
+            info.contains(&format!("lib-ver={}", env!("CARGO_PKG_VERSION"))),
+            "{}",
+            info
+        );
+    }
+}
+
+#[test]
+fn test_metrics_observer() {
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Clone, Default)]
+    struct Recorder(Arc<Mutex<Vec<String>>>, Arc<Mutex<(usize, usize)>>);
+
+    impl redis::instrumentation::MetricsObserver for Recorder {
+        fn command_finished(&self, command: &str, _: Duration, error: Option<&redis::RedisError>) {
+            let outcome = if error.is_some() { "err" } else { "ok" };
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("{} {}", command, outcome));
+        }
+
+        fn bytes_sent(&self, bytes: usize) {
+            self.1.lock().unwrap().0 += bytes;
+        }
+
+        fn bytes_received(&self, bytes: usize) {
+            self.1.lock().unwrap().1 += bytes;
+        }
+    }
+
+    let ctx = TestContext::new();
+    let recorder = Recorder::default();
+    let client = ctx.client.clone().with_metrics_observer(recorder.clone());
+    let mut con = client.get_connection().unwrap();
+
+    let _: () = con.set("key", "value").unwrap();
+    let _: (String,) = redis::pipe().get("key").query(&mut con).unwrap();
+    assert!(con.incr::<_, _, i64>("key", 1).is_err());
+
+    assert_eq!(
+        *recorder.0.lock().unwrap(),
+        vec!["SET ok", "PIPELINE ok", "INCRBY err"]
+    );
+    let (sent, received) = *recorder.1.lock().unwrap();
+    assert!(sent > 0 && received > 0);
+}
+
+#[test]
+fn test_resp3_handshake() {
+    let ctx = TestContext::new();
+    let client = redis::Client::open(redis::ConnectionInfo {
+        addr: ctx.server.get_client_addr().clone(),
+        redis: redis::RedisConnectionInfo {
+            protocol: redis::ProtocolVersion::RESP3,
+            ..Default::default()
+        },
+        proxy: None,
+        tcp_settings: Default::default(),
+    })
+    .unwrap();
+    let mut con = client.get_connection().unwrap();
+    assert_eq!(con.protocol(), redis::ProtocolVersion::RESP3);
+
+    redis::cmd("HSET")
+        .arg("hash")
+        .arg("field")
+        .arg(1)
+        .execute(&mut con);
+    let hash: HashMap<String, i64> = redis::cmd("HGETALL").arg("hash").query(&mut con).unwrap();
+    assert_eq!(hash.get("field"), Some(&1));
+
+    assert_eq!(ctx.connection().protocol(), redis::ProtocolVersion::RESP2);
+}
+
+#[test]
+fn test_get_connection_with_stream() {
+    let ctx = TestContext::new();
+    let stream = match ctx.server.get_client_addr() {
+        redis::ConnectionAddr::Tcp(host, port) => {
+            std::net::TcpStream::connect((host.as_str(), *port)).unwrap()
+        }
+        // The stream is plain TCP
+        _ => return,
+    };
+    let mut con = ctx.client.get_connection_with_stream(stream).unwrap();
+    let _: () = con.set("stream-key", 5).unwrap();
+    assert_eq!(con.get("stream-key"), Ok(5));
+}
@@ -1,7 +1,7 @@
 #![cfg(feature = "cluster")]
 mod support;
 use crate::support::*;
-use redis::cluster::cluster_pipe;
+use redis::cluster::{cluster_pipe, ReadRouting};
 
 #[test]
 fn test_cluster_basics() {
@@ -28,8 +28,7 @@ fn test_cluster_readonly() {
         TestClusterContext::new_with_cluster_client_builder(6, 1, |builder| builder.readonly(true));
     let mut con = cluster.connection();
 
-    // con is a READONLY replica, so we'll get the MOVED response and will be redirected
-    // to the master
+    // writes go to the masters, reads to the READONLY replicas
     redis::cmd("SET")
         .arg("{x}key1")
         .arg(b"foo")
@@ -44,6 +43,182 @@ fn test_cluster_readonly() {
     );
 }
 
+#[test]
+fn test_cluster_read_routing() {
+    for read_routing in [
+        ReadRouting::PreferReplica,
+        ReadRouting::RoundRobinReplicas,
+        ReadRouting::LowestLatency,
+    ] {
+        let cluster = TestClusterContext::new_with_cluster_client_builder(6, 1, |builder| {
+            builder.read_routing(read_routing)
+        });
+        let mut con = cluster.connection();
+
+        // the write goes to the master even though the reads go to replicas
+        redis::cmd("SET")
+            .arg("{x}key1")
+            .arg(b"foo")
+            .execute(&mut con);
+
+        // the replicas may lag behind a little
+        let mut value = None;
+        for _ in 0..100 {
+            value = redis::cmd("GET").arg("{x}key1").query(&mut con).unwrap();
+            if value.is_some() {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        assert_eq!(value, Some("foo".to_string()));
+    }
+}
+
+#[test]
+fn test_cluster_ask_redirect() {
+    let cluster = TestClusterContext::new(3, 0);
+    cluster.wait_for_cluster_up();
+    let mut con = cluster.connection();
+
+    let mut nodes: Vec<redis::Connection> = cluster
+        .cluster
+        .iter_servers()
+        .map(|server| {
+            redis::Client::open(redis::ConnectionInfo {
+                addr: server.get_client_addr().clone(),
+                redis: Default::default(),
+                proxy: None,
+                tcp_settings: Default::default(),
+            })
+            .unwrap()
+            .get_connection()
+            .unwrap()
+        })
+        .collect();
+
+    // Start migrating the slot of "foo" (12182) away from the node serving it,
+    // so the node answers with ASK for keys it does not have.
+    let source = nodes
+        .iter_mut()
+        .position(|node| {
+            redis::cmd("GET")
+                .arg("foo")
+                .query::<redis::Value>(node)
+                .is_ok()
+        })
+        .unwrap();
+    let target = (source + 1) % nodes.len();
+    let source_id: String = redis::cmd("CLUSTER")
+        .arg("MYID")
+        .query(&mut nodes[source])
+        .unwrap();
+    let target_id: String = redis::cmd("CLUSTER")
+        .arg("MYID")
+        .query(&mut nodes[target])
+        .unwrap();
+    redis::cmd("CLUSTER")
+        .arg(&["SETSLOT", "12182", "IMPORTING", &source_id])
+        .execute(&mut nodes[target]);
+    redis::cmd("CLUSTER")
+        .arg(&["SETSLOT", "12182", "MIGRATING", &target_id])
+        .execute(&mut nodes[source]);
+
+    redis::cmd("SET").arg("foo").arg("bar").execute(&mut con);
+    assert_eq!(
+        redis::cmd("GET").arg("foo").query(&mut con),
+        Ok("bar".to_string())
+    );
+    let resp = cluster_pipe()
+        .cmd("GET")
+        .arg("foo")
+        .query::<Vec<String>>(&mut con);
+    assert_eq!(resp, Ok(vec!["bar".to_string()]));
+}
+
+#[test]
+fn test_cluster_pubsub() {
+    let cluster = TestClusterContext::new(3, 0);
+    cluster.wait_for_cluster_up();
+    let mut con = cluster.connection();
+    let mut pubsub = cluster.client.get_pubsub().unwrap();
+    pubsub.subscribe("news").unwrap();
+    pubsub.psubscribe("weather:*").unwrap();
+    pubsub.ssubscribe(&["orders:{a}", "orders:{b}"]).unwrap();
+    pubsub.set_read_timeout(Some(std::time::Duration::from_secs(5)));
+
+    redis::cmd("PUBLISH").arg("news").arg(1).execute(&mut con);
+    redis::cmd("PUBLISH")
+        .arg("weather:rain")
+        .arg(2)
+        .execute(&mut con);
+    redis::cmd("SPUBLISH")
+        .arg("orders:{a}")
+        .arg(3)
+        .execute(&mut con);
+    redis::cmd("SPUBLISH")
+        .arg("orders:{b}")
+        .arg(4)
+        .execute(&mut con);
+
+    let mut received = Vec::new();
+    for _ in 0..4 {
+        let msg = pubsub.get_message().unwrap();
+        received.push((
+            msg.get_channel_name().to_string(),
+            msg.get_payload::<i64>().unwrap(),
+        ));
+    }
+    received.sort();
+    assert_eq!(
+        received,
+        vec![
+            ("news".to_string(), 1),
+            ("orders:{a}".to_string(), 3),
+            ("orders:{b}".to_string(), 4),
+            ("weather:rain".to_string(), 2),
+        ]
+    );
+
+    pubsub.sunsubscribe("orders:{a}").unwrap();
+    redis::cmd("SPUBLISH")
+        .arg("orders:{a}")
+        .arg(5)
+        .execute(&mut con);
+    redis::cmd("SPUBLISH")
+        .arg("orders:{b}")
+        .arg(6)
+        .execute(&mut con);
+    assert_eq!(pubsub.get_message().unwrap().get_payload(), Ok(6));
+}
+
+#[test]
+fn test_cluster_multi_key_fan_out() {
+    let cluster = TestClusterContext::new(3, 0);
+    cluster.wait_for_cluster_up();
+    let mut con = cluster.connection();
+
+    // the keys belong to different slots
+    redis::cmd("MSET")
+        .arg(&["a", "1", "b", "2", "c", "3"])
+        .execute(&mut con);
+    assert_eq!(
+        redis::cmd("MGET")
+            .arg(&["c", "missing", "a", "b"])
+            .query(&mut con),
+        Ok((Some(3), None::<i64>, Some(1), Some(2)))
+    );
+    assert_eq!(
+        redis::cmd("EXISTS")
+            .arg(&["a", "b", "missing"])
+            .query(&mut con),
+        Ok(2)
+    );
+    assert_eq!(
+        redis::cmd("DEL").arg(&["a", "b", "c"]).query(&mut con),
+        Ok(3)
+    );
+}
+
 #[test]
 fn test_cluster_eval() {
     let cluster = TestClusterContext::new(3, 0);
@@ -99,6 +274,34 @@ fn test_cluster_pipeline() {
     assert_eq!(resp, vec!["OK".to_string()]);
 }
 
+#[test]
+fn test_cluster_regular_pipeline() {
+    let cluster = TestClusterContext::new(3, 0);
+    cluster.wait_for_cluster_up();
+    let mut con = cluster.connection();
+
+    // the keys are served by different nodes
+    let resp = redis::pipe()
+        .set("foo", 1)
+        .ignore()
+        .set("bar", 2)
+        .ignore()
+        .get("foo")
+        .get("bar")
+        .get("baz")
+        .query::<(i64, i64, Option<i64>)>(&mut con)
+        .unwrap();
+    assert_eq!(resp, (1, 2, None));
+
+    let resp = redis::pipe()
+        .atomic()
+        .incr("{x}a", 1)
+        .incr("{x}b", 2)
+        .query::<(i64, i64)>(&mut con)
+        .unwrap();
+    assert_eq!(resp, (1, 2));
+}
+
 #[test]
 fn test_cluster_pipeline_multiple_keys() {
     use redis::FromRedisValue;
@@ -222,3 +425,98 @@ fn test_cluster_pipeline_ordering_with_improper_command() {
     let got = pipe.query::<Vec<String>>(&mut con).unwrap();
     assert_eq!(got, expected);
 }
+
+#[test]
+fn test_cluster_node_address_mapper() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let mapped = Arc::new(AtomicUsize::new(0));
+    let counter = mapped.clone();
+    let cluster = TestClusterContext::new_with_cluster_client_builder(3, 0, move |builder| {
+        // `localhost` may resolve to both loopback addresses, which are
+        // tried in turn
+        builder.node_address_mapper(move |host, port| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            (host.replace("127.0.0.1", "localhost"), port)
+        })
+    });
+    let mut con = cluster.connection();
+    assert!(mapped.load(Ordering::SeqCst) > 0);
+
+    redis::cmd("SET")
+        .arg("{x}key1")
+        .arg(b"foo")
+        .execute(&mut con);
+    redis::cmd("SET").arg(&["{x}key2", "bar"]).execute(&mut con);
+
+    assert_eq!(
+        redis::cmd("MGET")
+            .arg(&["{x}key1", "{x}key2"])
+            .query(&mut con),
+        Ok(("foo".to_string(), b"bar".to_vec()))
+    );
+}
+
+#[test]
+fn test_cluster_scan() {
+    let cluster = TestClusterContext::new(3, 0);
+    let mut con = cluster.connection();
+
+    // keys without a hash tag are spread over all the nodes
+    for i in 0..100 {
+        redis::cmd("SET")
+            .arg(format!("key{}", i))
+            .arg(i)
+            .execute(&mut con);
+    }
+    redis::cmd("SET").arg("other").arg(0).execute(&mut con);
+
+    let options = redis::ScanOptions::default().pattern("key*").count(10);
+    let mut keys: Vec<String> = con
+        .scan_cluster(options)
+        .unwrap()
+        .collect::<redis::RedisResult<_>>()
+        .unwrap();
+    keys.sort();
+    keys.dedup();
+
+    let mut expected: Vec<String> = (0..100).map(|i| format!("key{}", i)).collect();
+    expected.sort();
+    assert_eq!(keys, expected);
+}
+
+#[test]
+fn test_cluster_delete_matching() {
+    let cluster = TestClusterContext::new(3, 0);
+    let mut con = cluster.connection();
+
+    for i in 0..1000 {
+        redis::cmd("SET")
+            .arg(format!("session:{}", i))
+            .arg(i)
+            .execute(&mut con);
+    }
+    redis::cmd("SET").arg("user:1").arg(0).execute(&mut con);
+
+    let opts = redis::DeleteMatchingOptions::default()
+        .count(100)
+        .batch_size(64);
+    assert_eq!(
+        redis::delete_matching_cluster(&mut con, "session:*", opts.clone().dry_run(true)),
+        Ok(1000)
+    );
+    let err = redis::delete_matching_cluster(&mut con, "session:*", opts.clone().max_keys(999))
+        .unwrap_err();
+    assert_eq!(err.kind(), redis::ErrorKind::ClientError);
+    assert_eq!(
+        redis::delete_matching_cluster(&mut con, "session:*", opts),
+        Ok(1000)
+    );
+    let left: Vec<String> = con
+        .scan_cluster(redis::ScanOptions::default())
+        .unwrap()
+        .collect::<redis::RedisResult<_>>()
+        .unwrap();
+    assert_eq!(left, vec!["user:1".to_string()]);
+}
@@ -0,0 +1,60 @@
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use redis::aio::{AsyncRuntime, BoxFuture, BoxStream};
+use redis::{AsyncCommands, ErrorKind, RedisResult};
+
+use crate::support::*;
+
+mod support;
+
+// Runs the connections on tokio, as an outside runtime would.
+struct Delegating;
+
+impl AsyncRuntime for Delegating {
+    fn spawn(&self, future: BoxFuture<'static, ()>) {
+        tokio::spawn(future);
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        Box::pin(tokio::time::sleep(duration))
+    }
+
+    fn connect_tcp(&self, addr: SocketAddr) -> BoxFuture<'static, io::Result<BoxStream>> {
+        Box::pin(async move {
+            let stream = tokio::net::TcpStream::connect(addr).await?;
+            Ok(Box::pin(stream) as BoxStream)
+        })
+    }
+
+    #[cfg(unix)]
+    fn connect_unix(&self, path: &std::path::Path) -> BoxFuture<'static, io::Result<BoxStream>> {
+        let path = path.to_owned();
+        Box::pin(async move {
+            let stream = tokio::net::UnixStream::connect(path).await?;
+            Ok(Box::pin(stream) as BoxStream)
+        })
+    }
+}
+
+#[test]
+fn test_custom_runtime() {
+    redis::aio::set_runtime(Delegating).unwrap();
+    let err = redis::aio::set_runtime(Delegating).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::ClientError);
+
+    let ctx = TestContext::new();
+    block_on_all(async move {
+        let mut con = ctx.client.get_multiplexed_async_connection().await?;
+        con.set("key", 42).await?;
+        let value: i64 = con.get("key").await?;
+        assert_eq!(value, 42);
+
+        let mut con = ctx.client.get_async_connection().await?;
+        let value: i64 = con.get("key").await?;
+        assert_eq!(value, 42);
+        RedisResult::Ok(())
+    })
+    .unwrap();
+}
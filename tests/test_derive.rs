@@ -0,0 +1,125 @@
+#![cfg(feature = "derive")]
+
+use redis::{FromRedisValue, ToRedisArgs, Value};
+
+#[derive(Debug, PartialEq, ToRedisArgs, FromRedisValue)]
+struct User {
+    name: String,
+    #[redis(rename = "user_age")]
+    age: u32,
+    email: Option<String>,
+    role: Role,
+}
+
+#[derive(Debug, PartialEq, ToRedisArgs, FromRedisValue)]
+enum Role {
+    Admin,
+    #[redis(rename = "member")]
+    Member,
+}
+
+#[derive(Debug, PartialEq, ToRedisArgs, FromRedisValue)]
+struct Wrapper<T> {
+    value: T,
+}
+
+fn data(s: &str) -> Value {
+    Value::Data(s.as_bytes().to_vec())
+}
+
+#[test]
+fn test_struct_to_redis_args() {
+    let user = User {
+        name: "ada".into(),
+        age: 36,
+        email: None,
+        role: Role::Member,
+    };
+    assert!(!user.is_single_arg());
+    assert_eq!(
+        user.to_redis_args(),
+        vec![
+            b"name".to_vec(),
+            b"ada".to_vec(),
+            b"user_age".to_vec(),
+            b"36".to_vec(),
+            b"role".to_vec(),
+            b"member".to_vec(),
+        ]
+    );
+    assert_eq!(
+        Wrapper { value: 1.5 }.to_redis_args(),
+        vec![b"value".to_vec(), b"1.5".to_vec()]
+    );
+}
+
+#[test]
+fn test_struct_from_redis_value() {
+    let expected = User {
+        name: "ada".into(),
+        age: 36,
+        email: Some("ada@example.com".into()),
+        role: Role::Admin,
+    };
+    let hgetall = Value::Bulk(vec![
+        data("name"),
+        data("ada"),
+        data("user_age"),
+        data("36"),
+        data("unknown"),
+        data("ignored"),
+        data("email"),
+        data("ada@example.com"),
+        data("role"),
+        data("Admin"),
+    ]);
+    assert_eq!(User::from_redis_value(&hgetall), Ok(expected));
+
+    let map = Value::Map(vec![
+        (Value::Status("name".into()), data("bob")),
+        (Value::Status("user_age".into()), Value::Int(7)),
+        (Value::Status("role".into()), Value::Status("member".into())),
+    ]);
+    assert_eq!(
+        User::from_redis_value(&map),
+        Ok(User {
+            name: "bob".into(),
+            age: 7,
+            email: None,
+            role: Role::Member,
+        })
+    );
+
+    let missing = Value::Bulk(vec![data("name"), data("ada")]);
+    let err = User::from_redis_value(&missing).unwrap_err();
+    assert_eq!(err.kind(), redis::ErrorKind::TypeError);
+    assert!(err.to_string().contains("user_age"), "{}", err);
+
+    assert!(User::from_redis_value(&Value::Int(1)).is_err());
+    assert_eq!(
+        Wrapper::<i64>::from_redis_value(&Value::Bulk(vec![data("value"), data("-3")])),
+        Ok(Wrapper { value: -3 })
+    );
+}
+
+#[test]
+#[should_panic(expected = "field `value` of Wrapper converts to more than one argument")]
+fn test_struct_rejects_fields_of_several_args() {
+    Wrapper {
+        value: vec!["a", "b"],
+    }
+    .to_redis_args();
+}
+
+#[test]
+fn test_enum_conversions() {
+    assert!(Role::Admin.is_single_arg());
+    assert_eq!(Role::Admin.to_redis_args(), vec![b"Admin".to_vec()]);
+    assert_eq!(Role::from_redis_value(&data("member")), Ok(Role::Member));
+    assert_eq!(
+        Role::from_redis_value(&Value::Status("Admin".into())),
+        Ok(Role::Admin)
+    );
+    assert!(Role::from_redis_value(&data("Member")).is_err());
+    assert!(Role::from_redis_value(&Value::Nil).is_err());
+}
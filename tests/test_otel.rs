@@ -0,0 +1,254 @@
+#![cfg(feature = "otel")]
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::net::TcpListener;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use redis::{Commands, Parser, Value};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Metadata, Subscriber};
+use tracing_core::span::Current;
+
+#[derive(Clone, Debug)]
+struct RecordedSpan {
+    id: u64,
+    name: &'static str,
+    metadata: &'static Metadata<'static>,
+    parent: Option<u64>,
+    fields: HashMap<String, String>,
+}
+
+impl RecordedSpan {
+    fn field(&self, name: &str) -> Option<&str> {
+        self.fields.get(name).map(String::as_str)
+    }
+}
+
+// Keeps every span in memory, with the values recorded in its fields.
+#[derive(Clone, Default)]
+struct Recorder {
+    next_id: Arc<AtomicU64>,
+    spans: Arc<Mutex<Vec<RecordedSpan>>>,
+    entered: Arc<Mutex<Vec<u64>>>,
+}
+
+struct FieldVisitor<'a>(&'a mut HashMap<String, String>);
+
+impl Visit for FieldVisitor<'_> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), value.to_string());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.0
+            .insert(field.name().to_string(), format!("{:?}", value));
+    }
+}
+
+impl Subscriber for Recorder {
+    fn enabled(&self, _: &Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, attrs: &Attributes<'_>) -> Id {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let parent = match attrs.parent() {
+            Some(parent) => Some(parent.into_u64()),
+            None if attrs.is_contextual() => self.entered.lock().unwrap().last().copied(),
+            None => None,
+        };
+        let mut fields = HashMap::new();
+        attrs.record(&mut FieldVisitor(&mut fields));
+        self.spans.lock().unwrap().push(RecordedSpan {
+            id,
+            name: attrs.metadata().name(),
+            metadata: attrs.metadata(),
+            parent,
+            fields,
+        });
+        Id::from_u64(id)
+    }
+
+    fn record(&self, span: &Id, values: &Record<'_>) {
+        let mut spans = self.spans.lock().unwrap();
+        let span = spans
+            .iter_mut()
+            .find(|recorded| recorded.id == span.into_u64())
+            .unwrap();
+        values.record(&mut FieldVisitor(&mut span.fields));
+    }
+
+    fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+    fn event(&self, _: &Event<'_>) {}
+
+    fn enter(&self, span: &Id) {
+        self.entered.lock().unwrap().push(span.into_u64());
+    }
+
+    fn exit(&self, _: &Id) {
+        self.entered.lock().unwrap().pop();
+    }
+
+    fn current_span(&self) -> Current {
+        let id = match self.entered.lock().unwrap().last() {
+            Some(&id) => id,
+            None => return Current::none(),
+        };
+        let spans = self.spans.lock().unwrap();
+        let span = spans.iter().find(|span| span.id == id).unwrap();
+        Current::new(Id::from_u64(id), span.metadata)
+    }
+}
+
+// Answers the commands of one connection like a server would.
+fn fake_server() -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut parser = Parser::new();
+        while let Ok(Value::Bulk(args)) = parser.parse_value(&mut stream) {
+            let reply: &[u8] = match args.first() {
+                Some(Value::Data(name)) => match &name.to_ascii_uppercase()[..] {
+                    b"GET" => b"$5\r\nalice\r\n",
+                    b"INCRBY" => b":1\r\n",
+                    b"SELECT" | b"SET" | b"CLIENT" => b"+OK\r\n",
+                    _ => b"-ERR unknown command\r\n",
+                },
+                _ => b"-ERR unknown command\r\n",
+            };
+            stream.write_all(reply).unwrap();
+        }
+    });
+    port
+}
+
+fn connect() -> (redis::Connection, u16) {
+    let port = fake_server();
+    let client = redis::Client::open(format!("redis://127.0.0.1:{}/3", port)).unwrap();
+    (client.get_connection().unwrap(), port)
+}
+
+fn recorded<F: FnOnce()>(f: F) -> Vec<RecordedSpan> {
+    let recorder = Recorder::default();
+    tracing::subscriber::with_default(recorder.clone(), f);
+    let spans = recorder.spans.lock().unwrap().clone();
+    spans
+}
+
+fn find<'a>(spans: &'a [RecordedSpan], operation: &str) -> &'a RecordedSpan {
+    spans
+        .iter()
+        .find(|span| span.field("db.operation") == Some(operation))
+        .unwrap()
+}
+
+fn fields(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+    pairs
+        .iter()
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect()
+}
+
+#[test]
+fn test_command_span() {
+    let mut port = 0;
+    let spans = recorded(|| {
+        let (mut con, server_port) = connect();
+        port = server_port;
+        let name: String = con.get("user:1").unwrap();
+        assert_eq!(name, "alice");
+    });
+    let mut span = find(&spans, "GET").clone();
+    assert_eq!(span.name, "redis.command");
+    assert!(span.fields.remove("connection_id").is_some());
+    assert!(span.fields.remove("duration_us").is_some());
+    assert_eq!(
+        span.fields,
+        fields(&[
+            ("command", "GET"),
+            ("key", "user:1"),
+            ("outcome", "ok"),
+            ("otel.name", "GET"),
+            ("otel.kind", "client"),
+            ("db.system", "redis"),
+            ("db.operation", "GET"),
+            ("db.redis.database_index", "3"),
+            ("net.peer.name", "127.0.0.1"),
+            ("net.peer.port", &port.to_string()),
+        ])
+    );
+}
+
+#[test]
+fn test_pipeline_span() {
+    let mut port = 0;
+    let spans = recorded(|| {
+        let (mut con, server_port) = connect();
+        port = server_port;
+        let (count,): (i64,) = redis::pipe()
+            .set("user:1", "alice")
+            .ignore()
+            .incr("visits", 1)
+            .query(&mut con)
+            .unwrap();
+        assert_eq!(count, 1);
+    });
+    let mut span = spans
+        .iter()
+        .rev()
+        .find(|span| span.name == "redis.pipeline")
+        .unwrap()
+        .clone();
+    assert!(span.fields.remove("connection_id").is_some());
+    assert!(span.fields.remove("duration_us").is_some());
+    assert_eq!(
+        span.fields,
+        fields(&[
+            ("command", "PIPELINE"),
+            ("commands", "2"),
+            ("outcome", "ok"),
+            ("otel.name", "PIPELINE"),
+            ("otel.kind", "client"),
+            ("db.system", "redis"),
+            ("db.operation", "PIPELINE"),
+            ("db.redis.database_index", "3"),
+            ("db.redis.pipeline_length", "2"),
+            ("net.peer.name", "127.0.0.1"),
+            ("net.peer.port", &port.to_string()),
+        ])
+    );
+}
+
+#[test]
+fn test_error_status() {
+    let spans = recorded(|| {
+        let (mut con, _) = connect();
+        assert!(redis::cmd("BOGUS").query::<()>(&mut con).is_err());
+    });
+    let span = find(&spans, "BOGUS");
+    assert_eq!(span.field("otel.status_code"), Some("ERROR"));
+    assert_eq!(span.field("otel.status_message"), Some("ResponseError"));
+    assert_eq!(span.field("outcome"), Some("response error"));
+}
+
+#[test]
+fn test_attach_current_span() {
+    let spans = recorded(|| {
+        let (mut con, _) = connect();
+        let request = tracing::info_span!("request");
+        let cmd = request.in_scope(|| {
+            let mut cmd = redis::cmd("GET");
+            cmd.arg("user:1").attach_current_span();
+            cmd
+        });
+        cmd.query::<String>(&mut con).unwrap();
+    });
+    let request = spans.iter().find(|span| span.name == "request").unwrap();
+    assert_eq!(find(&spans, "GET").parent, Some(request.id));
+}
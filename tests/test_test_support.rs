@@ -0,0 +1,55 @@
+use redis::testing::{TestCluster, TestServer, TestServerConfig};
+use redis::{Commands, ConnectionAddr};
+
+#[test]
+fn test_server() {
+    let server = TestServer::start().unwrap();
+    let mut con = server.client().unwrap().get_connection().unwrap();
+    let _: () = con.set("key", 42).unwrap();
+    assert_eq!(con.get("key"), Ok(42));
+}
+
+#[test]
+fn test_server_stops_on_drop() {
+    let server = TestServer::start().unwrap();
+    let client = server.client().unwrap();
+    drop(server);
+    assert!(client.get_connection().is_err());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_server_unix_socket() {
+    let server = TestServer::start_with(TestServerConfig::default().unix_socket(true)).unwrap();
+    assert!(matches!(server.addr(), ConnectionAddr::Unix(_)));
+    let mut con = server.client().unwrap().get_connection().unwrap();
+    assert_eq!(redis::cmd("PING").query(&mut con), Ok("PONG".to_string()));
+}
+
+#[test]
+fn test_server_fails_to_start() {
+    let config = TestServerConfig::default().program("redis-rs-no-such-server");
+    assert!(TestServer::start_with(config).is_err());
+}
+
+#[test]
+fn test_cluster() {
+    let cluster = TestCluster::start(3).unwrap();
+    assert_eq!(cluster.nodes().len(), 3);
+    for server in cluster.servers() {
+        let mut con = server.client().unwrap().get_connection().unwrap();
+        let info: String = redis::cmd("CLUSTER").arg("INFO").query(&mut con).unwrap();
+        assert!(info.contains("cluster_state:ok"));
+    }
+}
+
+#[cfg(feature = "cluster")]
+#[test]
+fn test_cluster_client() {
+    let cluster = TestCluster::start(3).unwrap();
+    let mut con = cluster.client().unwrap().get_connection().unwrap();
+    for i in 0..10 {
+        let _: () = con.set(format!("key{}", i), i).unwrap();
+        assert_eq!(con.get(format!("key{}", i)), Ok(i));
+    }
+}
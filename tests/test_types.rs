@@ -193,6 +193,142 @@ fn test_bytes() {
     assert_eq!(v.unwrap_err().kind(), ErrorKind::TypeError);
 }
 
+#[test]
+fn test_durations_and_times() {
+    use redis::{ErrorKind, FromRedisValue, ToRedisArgs, Value};
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    assert_eq!(
+        Duration::from_secs(90).to_redis_args(),
+        vec![b"90000".to_vec()]
+    );
+    assert_eq!(
+        Duration::from_redis_value(&Value::Int(1500)),
+        Ok(Duration::from_millis(1500))
+    );
+    let time = UNIX_EPOCH + Duration::from_millis(1_700_000_000_123);
+    assert_eq!(time.to_redis_args(), vec![b"1700000000123".to_vec()]);
+    assert_eq!(
+        SystemTime::from_redis_value(&Value::Data(b"1700000000123".to_vec())),
+        Ok(time)
+    );
+    // PEXPIRETIME of a key without expiration
+    let err = SystemTime::from_redis_value(&Value::Int(-1)).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::TypeError);
+    assert!(Duration::from_redis_value(&Value::Int(-2)).is_err());
+}
+
+#[cfg(feature = "chrono")]
+#[test]
+fn test_chrono() {
+    use chrono::{DateTime, TimeZone, Utc};
+    use redis::{FromRedisValue, ToRedisArgs, Value};
+
+    let time = Utc.timestamp_millis_opt(1_700_000_000_123).unwrap();
+    assert_eq!(time.to_redis_args(), vec![b"1700000000123".to_vec()]);
+    assert_eq!(
+        DateTime::<Utc>::from_redis_value(&Value::Int(1_700_000_000_123)),
+        Ok(time)
+    );
+    assert!(DateTime::<Utc>::from_redis_value(&Value::Int(-1)).is_err());
+}
+
+#[cfg(feature = "time")]
+#[test]
+fn test_time() {
+    use redis::{FromRedisValue, ToRedisArgs, Value};
+    use time::OffsetDateTime;
+
+    let time = OffsetDateTime::from_unix_timestamp_nanos(1_700_000_000_123_000_000).unwrap();
+    assert_eq!(time.to_redis_args(), vec![b"1700000000123".to_vec()]);
+    assert_eq!(
+        OffsetDateTime::from_redis_value(&Value::Data(b"1700000000123".to_vec())),
+        Ok(time)
+    );
+    assert!(OffsetDateTime::from_redis_value(&Value::Nil).is_err());
+}
+
+#[test]
+fn test_ip_addresses() {
+    use redis::{FromRedisValue, ToRedisArgs, Value};
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+    let v4 = Ipv4Addr::new(10, 0, 0, 1);
+    assert_eq!(v4.to_redis_args(), vec![b"10.0.0.1".to_vec()]);
+    assert_eq!(
+        IpAddr::from_redis_value(&Value::Data(b"10.0.0.1".to_vec())),
+        Ok(IpAddr::V4(v4))
+    );
+    assert_eq!(
+        Ipv6Addr::from_redis_value(&Value::Status("::1".into())),
+        Ok(Ipv6Addr::LOCALHOST)
+    );
+    assert!(Ipv4Addr::from_redis_value(&Value::Data(b"::1".to_vec())).is_err());
+    assert!(IpAddr::from_redis_value(&Value::Int(1)).is_err());
+}
+
+#[cfg(feature = "ipnet")]
+#[test]
+fn test_ipnet() {
+    use ipnet::{IpNet, Ipv4Net};
+    use redis::{FromRedisValue, ToRedisArgs, Value};
+
+    let net: Ipv4Net = "10.0.0.0/8".parse().unwrap();
+    assert_eq!(net.to_redis_args(), vec![b"10.0.0.0/8".to_vec()]);
+    assert_eq!(
+        IpNet::from_redis_value(&Value::Data(b"10.0.0.0/8".to_vec())),
+        Ok(IpNet::V4(net))
+    );
+    assert!(Ipv4Net::from_redis_value(&Value::Data(b"10.0.0.0".to_vec())).is_err());
+}
+
+#[cfg(feature = "uuid")]
+#[test]
+fn test_uuid() {
+    use redis::{FromRedisValue, ToRedisArgs, Value};
+    use uuid::Uuid;
+
+    let text = "67e55044-10b1-426f-9247-bb680e5fe0c8";
+    let uuid = Uuid::parse_str(text).unwrap();
+    assert_eq!(uuid.to_redis_args(), vec![text.as_bytes().to_vec()]);
+    assert_eq!(
+        Uuid::from_redis_value(&Value::Data(text.as_bytes().to_vec())),
+        Ok(uuid)
+    );
+    assert_eq!(
+        Uuid::from_redis_value(&Value::Data(uuid.as_bytes().to_vec())),
+        Ok(uuid)
+    );
+    assert_eq!(
+        Uuid::from_redis_value(&Value::Status(uuid.simple().to_string())),
+        Ok(uuid)
+    );
+    assert!(Uuid::from_redis_value(&Value::Data(b"not a uuid".to_vec())).is_err());
+}
+
+#[cfg(feature = "rust_decimal")]
+#[test]
+fn test_decimal() {
+    use redis::{FromRedisValue, ToRedisArgs, Value};
+    use rust_decimal::Decimal;
+
+    let decimal = Decimal::new(12345, 2);
+    assert_eq!(decimal.to_redis_args(), vec![b"123.45".to_vec()]);
+    assert_eq!(
+        Decimal::from_redis_value(&Value::Data(b"123.45".to_vec())),
+        Ok(decimal)
+    );
+    assert_eq!(
+        Decimal::from_redis_value(&Value::Int(7)),
+        Ok(Decimal::new(7, 0))
+    );
+    assert_eq!(
+        Decimal::from_redis_value(&Value::Double(123.45)),
+        Ok(decimal)
+    );
+    assert!(Decimal::from_redis_value(&Value::Data(b"1.2.3".to_vec())).is_err());
+}
+
 #[test]
 fn test_types_to_redis_args() {
     use redis::ToRedisArgs;
@@ -227,3 +363,553 @@ fn test_types_to_redis_args() {
         .to_redis_args()
         .is_empty());
 }
+
+#[test]
+fn test_latency_latest() {
+    use redis::{ErrorKind, LatencyLatest, Value};
+
+    let reply = Value::Bulk(vec![
+        Value::Bulk(vec![
+            Value::Data(b"command".to_vec()),
+            Value::Int(1405067976),
+            Value::Int(251),
+            Value::Int(1001),
+        ]),
+        Value::Bulk(vec![
+            Value::Data(b"fast-command".to_vec()),
+            Value::Data(b"1405067822".to_vec()),
+            Value::Int(3),
+            Value::Int(7),
+        ]),
+    ]);
+    assert_eq!(
+        reply.into_latency_latest().unwrap(),
+        vec![
+            LatencyLatest {
+                event: "command".into(),
+                timestamp: 1405067976,
+                latest_ms: 251,
+                max_ms: 1001,
+            },
+            LatencyLatest {
+                event: "fast-command".into(),
+                timestamp: 1405067822,
+                latest_ms: 3,
+                max_ms: 7,
+            },
+        ]
+    );
+
+    assert_eq!(Value::Bulk(vec![]).into_latency_latest(), Ok(vec![]));
+
+    let malformed = Value::Bulk(vec![Value::Bulk(vec![
+        Value::Data(b"command".to_vec()),
+        Value::Int(1405067976),
+    ])]);
+    assert_eq!(
+        malformed.into_latency_latest().unwrap_err().kind(),
+        ErrorKind::TypeError
+    );
+
+    let garbage = Value::Bulk(vec![Value::Bulk(vec![
+        Value::Data(b"command".to_vec()),
+        Value::Data(b"yesterday".to_vec()),
+        Value::Int(1),
+        Value::Int(1),
+    ])]);
+    let err = garbage.into_latency_latest().unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::TypeError);
+    assert!(err.detail().unwrap().contains("timestamp"));
+}
+
+#[test]
+fn test_latency_history() {
+    use redis::{ErrorKind, LatencySample, Value};
+
+    let reply = Value::Bulk(vec![
+        Value::Bulk(vec![Value::Int(1405067822), Value::Int(251)]),
+        Value::Bulk(vec![Value::Int(1405067941), Value::Int(1001)]),
+    ]);
+    assert_eq!(
+        reply.into_latency_history().unwrap(),
+        vec![
+            LatencySample {
+                timestamp: 1405067822,
+                latency_ms: 251,
+            },
+            LatencySample {
+                timestamp: 1405067941,
+                latency_ms: 1001,
+            },
+        ]
+    );
+
+    assert_eq!(Value::Bulk(vec![]).into_latency_history(), Ok(vec![]));
+
+    assert_eq!(
+        Value::Int(3).into_latency_history().unwrap_err().kind(),
+        ErrorKind::TypeError
+    );
+    assert_eq!(
+        Value::Bulk(vec![Value::Bulk(vec![Value::Int(1)])])
+            .into_latency_history()
+            .unwrap_err()
+            .kind(),
+        ErrorKind::TypeError
+    );
+}
+
+#[test]
+fn test_tracking_info() {
+    use redis::{ErrorKind, TrackingInfo, Value};
+
+    let reply = Value::Bulk(vec![
+        Value::Data(b"prefixes".to_vec()),
+        Value::Bulk(vec![
+            Value::Data(b"user:".to_vec()),
+            Value::Data(b"session:".to_vec()),
+        ]),
+        Value::Data(b"flags".to_vec()),
+        Value::Bulk(vec![
+            Value::Status("on".into()),
+            Value::Status("bcast".into()),
+        ]),
+        Value::Data(b"redirect".to_vec()),
+        Value::Int(0),
+        Value::Data(b"unknown-field".to_vec()),
+        Value::Bulk(vec![]),
+    ]);
+    assert_eq!(
+        reply.into_tracking_info().unwrap(),
+        TrackingInfo {
+            flags: vec!["on".into(), "bcast".into()],
+            redirect: 0,
+            prefixes: vec!["user:".into(), "session:".into()],
+        }
+    );
+
+    let off = Value::Bulk(vec![
+        Value::Data(b"flags".to_vec()),
+        Value::Bulk(vec![Value::Status("off".into())]),
+        Value::Data(b"redirect".to_vec()),
+        Value::Int(-1),
+        Value::Data(b"prefixes".to_vec()),
+        Value::Bulk(vec![]),
+    ]);
+    assert_eq!(
+        off.into_tracking_info().unwrap(),
+        TrackingInfo {
+            flags: vec!["off".into()],
+            redirect: -1,
+            prefixes: vec![],
+        }
+    );
+
+    assert_eq!(
+        Value::Int(1).into_tracking_info().unwrap_err().kind(),
+        ErrorKind::TypeError
+    );
+}
+
+#[test]
+fn test_expect_ok() {
+    use redis::{parse_redis_value, ErrorKind, Value};
+
+    // FAILOVER accepted
+    assert_eq!(parse_redis_value(b"+OK\r\n").unwrap().expect_ok(), Ok(()));
+
+    // WAIT replies with the number of acknowledging replicas
+    let wait = parse_redis_value(b":2\r\n").unwrap();
+    assert_eq!(wait, Value::Int(2));
+    assert_eq!(wait.expect_ok().unwrap_err().kind(), ErrorKind::TypeError);
+
+    // FAILOVER rejected
+    let err = parse_redis_value(b"-ERR FAILOVER requires connected replicas.\r\n").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::ResponseError);
+    assert_eq!(err.code(), Some("ERR"));
+    assert_eq!(err.detail(), Some("FAILOVER requires connected replicas."));
+
+    let err = parse_redis_value(b"-NOREPLICAS Not enough good replicas to write.\r\n").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::ExtensionError);
+    assert_eq!(err.code(), Some("NOREPLICAS"));
+    assert_eq!(err.detail(), Some("Not enough good replicas to write."));
+}
+
+#[test]
+fn test_sentinel_master_addr() {
+    use redis::{parse_redis_value, ErrorKind, Value};
+
+    let reply = parse_redis_value(b"*2\r\n$9\r\n127.0.0.1\r\n$4\r\n6379\r\n").unwrap();
+    assert_eq!(
+        reply.into_sentinel_master_addr().unwrap(),
+        ("127.0.0.1".to_string(), 6379)
+    );
+
+    let out_of_range = parse_redis_value(b"*2\r\n$9\r\n127.0.0.1\r\n$5\r\n65536\r\n").unwrap();
+    let err = out_of_range.into_sentinel_master_addr().unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::TypeError);
+    assert_eq!(err.detail(), Some("65536"));
+
+    let negative = Value::Bulk(vec![Value::Data(b"10.0.0.1".to_vec()), Value::Int(-1)]);
+    assert_eq!(
+        negative.into_sentinel_master_addr().unwrap_err().kind(),
+        ErrorKind::TypeError
+    );
+
+    // unknown master
+    assert_eq!(
+        Value::Nil.into_sentinel_master_addr().unwrap_err().kind(),
+        ErrorKind::TypeError
+    );
+}
+
+#[test]
+fn test_sentinel_masters() {
+    use redis::{parse_redis_value, ErrorKind, Value};
+
+    let reply = parse_redis_value(
+        b"*2\r\n\
+          *6\r\n$4\r\nname\r\n$8\r\nmymaster\r\n$2\r\nip\r\n$9\r\n127.0.0.1\r\n$4\r\nport\r\n$4\r\n6379\r\n\
+          *4\r\n$4\r\nname\r\n$5\r\ncache\r\n$5\r\nflags\r\n$13\r\nmaster,s_down\r\n",
+    )
+    .unwrap();
+    let masters = reply.into_sentinel_masters().unwrap();
+    assert_eq!(masters.len(), 2);
+    assert_eq!(masters[0]["name"], "mymaster");
+    assert_eq!(masters[0]["ip"], "127.0.0.1");
+    assert_eq!(masters[0]["port"], "6379");
+    assert_eq!(masters[1]["name"], "cache");
+    assert_eq!(masters[1]["flags"], "master,s_down");
+
+    assert_eq!(Value::Bulk(vec![]).into_sentinel_masters(), Ok(vec![]));
+    assert_eq!(
+        Value::Bulk(vec![Value::Int(1)])
+            .into_sentinel_masters()
+            .unwrap_err()
+            .kind(),
+        ErrorKind::TypeError
+    );
+}
+
+#[test]
+fn test_invalidation() {
+    use redis::{parse_redis_value, ErrorKind, Invalidation, Value};
+
+    let push =
+        parse_redis_value(b"*2\r\n$10\r\ninvalidate\r\n*2\r\n$3\r\nkey\r\n$4\r\nkey2\r\n").unwrap();
+    assert_eq!(
+        push.into_invalidation().unwrap(),
+        Invalidation::Keys(vec![b"key".to_vec(), b"key2".to_vec()])
+    );
+
+    let flush = parse_redis_value(b"*2\r\n$10\r\ninvalidate\r\n$-1\r\n").unwrap();
+    assert_eq!(flush.into_invalidation().unwrap(), Invalidation::FlushAll);
+
+    let resp3 = parse_redis_value(b">2\r\n$10\r\ninvalidate\r\n*1\r\n$3\r\nkey\r\n").unwrap();
+    assert_eq!(
+        resp3.into_invalidation().unwrap(),
+        Invalidation::Keys(vec![b"key".to_vec()])
+    );
+    let resp3_flush = parse_redis_value(b">2\r\n$10\r\ninvalidate\r\n_\r\n").unwrap();
+    assert_eq!(
+        resp3_flush.into_invalidation().unwrap(),
+        Invalidation::FlushAll
+    );
+
+    let redirected = parse_redis_value(
+        b"*3\r\n$7\r\nmessage\r\n$20\r\n__redis__:invalidate\r\n*1\r\n$3\r\nkey\r\n",
+    )
+    .unwrap();
+    assert_eq!(
+        redirected.into_invalidation().unwrap(),
+        Invalidation::Keys(vec![b"key".to_vec()])
+    );
+
+    let other = parse_redis_value(b"*3\r\n$7\r\nmessage\r\n$4\r\nnews\r\n$2\r\nhi\r\n").unwrap();
+    assert_eq!(
+        other.into_invalidation().unwrap_err().kind(),
+        ErrorKind::TypeError
+    );
+    assert_eq!(
+        Value::Bulk(vec![Value::Data(b"invalidate".to_vec()), Value::Int(1)])
+            .into_invalidation()
+            .unwrap_err()
+            .kind(),
+        ErrorKind::TypeError
+    );
+}
+
+#[test]
+fn test_malformed_redirects() {
+    use redis::{parse_redis_value, ErrorKind};
+
+    let err = parse_redis_value(b"-MOVED\r\n").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Moved);
+    assert_eq!(err.detail(), None);
+    assert_eq!(err.redirect_node(), None);
+
+    let err = parse_redis_value(b"-ASK\r\n").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Ask);
+    assert_eq!(err.redirect_node(), None);
+
+    for malformed in &[
+        &b"-MOVED 3999\r\n"[..],
+        b"-MOVED x 127.0.0.1:6381\r\n",
+        b"-ASK 70000 127.0.0.1:6381\r\n",
+    ] {
+        let err = parse_redis_value(malformed).unwrap_err();
+        assert!(err.is_cluster_error());
+        assert_eq!(err.redirect_node(), None);
+    }
+
+    let err = parse_redis_value(b"-MOVED 3999 127.0.0.1:6381\r\n").unwrap_err();
+    assert_eq!(err.redirect_node(), Some(("127.0.0.1:6381", 3999)));
+}
+
+#[test]
+fn test_as_bool() {
+    use redis::Value;
+
+    assert_eq!(Value::Int(1).as_bool(), Some(true));
+    assert_eq!(Value::Int(0).as_bool(), Some(false));
+    assert_eq!(Value::Int(2).as_bool(), None);
+    assert_eq!(Value::Int(-1).as_bool(), None);
+    assert_eq!(Value::Data(b"1".to_vec()).as_bool(), None);
+    assert_eq!(Value::Okay.as_bool(), None);
+    assert_eq!(Value::Nil.as_bool(), None);
+    assert_eq!(Value::Boolean(true).as_bool(), Some(true));
+    assert_eq!(Value::Boolean(false).as_bool(), Some(false));
+}
+
+#[test]
+fn test_truncated() {
+    use redis::Value;
+
+    let value = Value::Bulk(vec![
+        Value::Data(vec![b'a'; 100]),
+        Value::Data(b"short".to_vec()),
+        Value::Bulk(vec![
+            Value::Status("héllo wörld".to_string()),
+            Value::Int(7),
+        ]),
+        Value::Okay,
+    ]);
+    assert_eq!(
+        value.truncated(8),
+        Value::Bulk(vec![
+            Value::Data(b"aaaaaaaa...[+92 bytes]".to_vec()),
+            Value::Data(b"short".to_vec()),
+            Value::Bulk(vec![
+                Value::Status("héllo w...[+5 bytes]".to_string()),
+                Value::Int(7)
+            ]),
+            Value::Okay,
+        ])
+    );
+
+    // cut in the middle of the two bytes of "é"
+    assert_eq!(
+        Value::Status("hé".to_string()).truncated(2),
+        Value::Status("h...[+2 bytes]".to_string())
+    );
+
+    let mut untouched = Value::Data(b"12345678".to_vec());
+    untouched.truncate_data(8);
+    assert_eq!(untouched, Value::Data(b"12345678".to_vec()));
+}
+
+#[test]
+fn test_resp3_types() {
+    use redis::{FromRedisValue, Value};
+    use std::collections::{BTreeMap, HashMap, HashSet};
+
+    let map = Value::Map(vec![
+        (Value::Data(b"a".to_vec()), Value::Int(1)),
+        (Value::Status("b".into()), Value::Double(2.0)),
+    ]);
+    let hm: HashMap<String, i64> = FromRedisValue::from_redis_value(&map).unwrap();
+    assert_eq!(hm.len(), 2);
+    assert_eq!(hm["a"], 1);
+    assert_eq!(hm["b"], 2);
+    let bm: BTreeMap<String, f64> = FromRedisValue::from_redis_value(&map).unwrap();
+    assert_eq!(
+        bm.into_iter().collect::<Vec<_>>(),
+        [("a".into(), 1.0), ("b".into(), 2.0)]
+    );
+
+    let set = Value::Set(vec![Value::Data(b"x".to_vec()), Value::Data(b"y".to_vec())]);
+    let hs: HashSet<String> = FromRedisValue::from_redis_value(&set).unwrap();
+    assert_eq!(hs, ["x", "y"].iter().map(|s| s.to_string()).collect());
+    let v: Vec<String> = FromRedisValue::from_redis_value(&set).unwrap();
+    assert_eq!(v, ["x", "y"]);
+
+    assert_eq!(f64::from_redis_value(&Value::Double(1.5)), Ok(1.5));
+    assert_eq!(i64::from_redis_value(&Value::Double(-3.0)), Ok(-3));
+    assert_eq!(u8::from_redis_value(&Value::Double(255.0)), Ok(255));
+    // integers are not truncated or saturated
+    assert!(i64::from_redis_value(&Value::Double(1.5)).is_err());
+    assert!(i64::from_redis_value(&Value::Double(f64::NAN)).is_err());
+    assert!(i64::from_redis_value(&Value::Double(f64::INFINITY)).is_err());
+    assert!(i64::from_redis_value(&Value::Double(1e300)).is_err());
+    assert!(i64::from_redis_value(&Value::Double(9223372036854775808.0)).is_err());
+    assert!(u8::from_redis_value(&Value::Double(256.0)).is_err());
+    assert!(u32::from_redis_value(&Value::Double(-1.0)).is_err());
+    assert!(f64::from_redis_value(&Value::Double(f64::NAN))
+        .unwrap()
+        .is_nan());
+    assert_eq!(
+        String::from_redis_value(&Value::Double(1.5)),
+        Ok("1.5".into())
+    );
+    assert_eq!(bool::from_redis_value(&Value::Boolean(true)), Ok(true));
+    assert_eq!(bool::from_redis_value(&Value::Boolean(false)), Ok(false));
+
+    let big = Value::BigNumber("3492890328409238509324850943850943825024385".into());
+    assert_eq!(
+        String::from_redis_value(&big),
+        Ok("3492890328409238509324850943850943825024385".into())
+    );
+    assert!(i64::from_redis_value(&big).is_err());
+    assert_eq!(
+        i64::from_redis_value(&Value::BigNumber("-7".into())),
+        Ok(-7)
+    );
+
+    let verbatim = Value::VerbatimString {
+        format: "txt".into(),
+        text: "Some string".into(),
+    };
+    assert_eq!(
+        String::from_redis_value(&verbatim),
+        Ok("Some string".into())
+    );
+
+    assert!(Value::Double(f64::NAN) == Value::Double(f64::NAN));
+    assert!(Value::Double(0.0) != Value::Double(-0.0));
+}
+
+#[test]
+fn test_monitor_entry() {
+    use redis::{FromRedisValue, MonitorEntry, Value};
+    use std::time::{Duration, UNIX_EPOCH};
+
+    let line = r#"1339518083.107412 [0 127.0.0.1:60866] "set" "key" "a \"b\"\x00""#;
+    let entry = MonitorEntry::from_redis_value(&Value::Status(line.into())).unwrap();
+    assert_eq!(
+        entry,
+        MonitorEntry {
+            timestamp: UNIX_EPOCH + Duration::from_micros(1339518083107412),
+            db: 0,
+            client: "127.0.0.1:60866".into(),
+            command: "set".into(),
+            args: vec![b"key".to_vec(), b"a \"b\"\x00".to_vec()],
+        }
+    );
+
+    let line = r#"1339518087.877697 [3 lua] "keys" "*""#;
+    let entry = MonitorEntry::from_redis_value(&Value::Data(line.into())).unwrap();
+    assert_eq!(entry.db, 3);
+    assert_eq!(entry.client, "lua");
+    assert_eq!(entry.command, "keys");
+    assert_eq!(entry.args, vec![b"*".to_vec()]);
+
+    assert!(MonitorEntry::from_redis_value(&Value::Status("OK".into())).is_err());
+    assert!(MonitorEntry::from_redis_value(&Value::Int(1)).is_err());
+}
+
+#[test]
+fn test_strip_verbatim_formats() {
+    use redis::Value;
+
+    fn verbatim(text: &str) -> Value {
+        Value::VerbatimString {
+            format: "txt".into(),
+            text: text.into(),
+        }
+    }
+
+    let value = Value::Bulk(vec![
+        verbatim("a"),
+        Value::Bulk(vec![Value::Int(1), verbatim("b")]),
+        Value::Map(vec![(verbatim("k"), verbatim("v"))]),
+    ]);
+    assert_eq!(
+        value.strip_verbatim_formats(),
+        Value::Bulk(vec![
+            Value::Data(b"a".to_vec()),
+            Value::Bulk(vec![Value::Int(1), Value::Data(b"b".to_vec())]),
+            Value::Map(vec![(
+                Value::Data(b"k".to_vec()),
+                Value::Data(b"v".to_vec())
+            )]),
+        ])
+    );
+    assert_eq!(Value::Okay.strip_verbatim_formats(), Value::Okay);
+}
+
+#[test]
+fn test_into_result() {
+    use redis::{ErrorKind, Parser, Value};
+
+    let mut parser = Parser::new();
+    parser.set_error_values(true);
+    let reply = parser
+        .parse_value(&b"*2\r\n-WRONGTYPE Operation against a key\r\n:7\r\n"[..])
+        .unwrap();
+    let mut items = match reply {
+        Value::Bulk(items) => items.into_iter(),
+        value => panic!("unexpected reply {:?}", value),
+    };
+
+    let err = items.next().unwrap().into_result::<i64>().unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::ExtensionError);
+    assert_eq!(err.code(), Some("WRONGTYPE"));
+    assert_eq!(err.detail(), Some("Operation against a key"));
+    assert_eq!(items.next().unwrap().into_result::<i64>(), Ok(7));
+}
+
+#[test]
+fn test_nested_attributes() {
+    use redis::{FromRedisValue, Value};
+    use std::collections::HashMap;
+
+    fn attributed(data: Value) -> Value {
+        Value::Attribute {
+            data: Box::new(data),
+            attributes: vec![(Value::Status("ttl".into()), Value::Int(3600))],
+        }
+    }
+
+    let bulk = Value::Bulk(vec![
+        attributed(Value::Int(1)),
+        Value::Int(2),
+        attributed(Value::Data(b"3".to_vec())),
+    ]);
+    assert_eq!(Vec::<i64>::from_redis_value(&bulk), Ok(vec![1, 2, 3]));
+    assert_eq!(
+        <(i64, i64, String)>::from_redis_value(&bulk),
+        Ok((1, 2, "3".into()))
+    );
+
+    let nested = Value::Bulk(vec![attributed(bulk.clone()), Value::Bulk(vec![])]);
+    assert_eq!(
+        Vec::<Vec<i64>>::from_redis_value(&nested),
+        Ok(vec![vec![1, 2, 3], vec![]])
+    );
+
+    let map = Value::Map(vec![
+        (attributed(Value::Data(b"a".to_vec())), Value::Int(1)),
+        (Value::Data(b"b".to_vec()), attributed(Value::Int(2))),
+    ]);
+    let map: HashMap<String, i64> = FromRedisValue::from_redis_value(&map).unwrap();
+    assert_eq!(
+        map,
+        vec![("a".into(), 1), ("b".into(), 2)].into_iter().collect()
+    );
+
+    let pairs = Value::Bulk(vec![Value::Data(b"a".to_vec()), attributed(Value::Int(1))]);
+    assert_eq!(
+        Vec::<(String, i64)>::from_redis_value(&pairs),
+        Ok(vec![("a".into(), 1)])
+    );
+}